@@ -11,9 +11,10 @@
 #include <signal.h>
 #include <stdatomic.h>
 #include <stdint.h>
+#include <link.h>
 
 #define POE_RT_MAGIC 0x504F4552
-#define POE_RT_VERSION 1
+#define POE_RT_VERSION 2
 #define POE_RT_DEFAULT_ENTRIES (1 << 16)
 #define POE_RT_ENTRY_SIZE 32
 
@@ -36,7 +37,8 @@ typedef struct {
     uint32_t _pad;
     atomic_uint_fast64_t write_pos;
     uint64_t start_ns;
-    char _reserved[32];
+    uint64_t load_base;
+    char _reserved[24];
 } poe_header_t;
 
 static poe_header_t *g_header = NULL;
@@ -45,6 +47,9 @@ static int g_fd = -1;
 static int g_initialized = 0;
 static __thread uint8_t t_depth = 0;
 static __thread int t_in_hook = 0;
+static __thread uintptr_t t_stack_lo = 0;
+static __thread uintptr_t t_stack_hi = 0;
+static __thread int t_base_depth = -1;
 
 static uint64_t clock_ns(void) {
     struct timespec ts;
@@ -52,6 +57,12 @@ static uint64_t clock_ns(void) {
     return (uint64_t)ts.tv_sec * 1000000000ULL + (uint64_t)ts.tv_nsec;
 }
 
+static int first_object_base(struct dl_phdr_info *info, size_t size, void *data) {
+    (void)size;
+    *(uint64_t *)data = (uint64_t)info->dlpi_addr;
+    return 1;
+}
+
 static void poe_rt_init(void) {
     if (g_initialized) return;
     g_initialized = 1;
@@ -97,9 +108,13 @@ static void poe_rt_init(void) {
     g_header->capacity = capacity;
     atomic_store(&g_header->write_pos, 0);
     g_header->start_ns = clock_ns();
+
+    uint64_t base = 0;
+    dl_iterate_phdr(first_object_base, &base);
+    g_header->load_base = base;
 }
 
-static inline void poe_rt_record(void *func, void *call_site, uint8_t event_type) {
+static inline void poe_rt_record(void *func, void *call_site, uint8_t event_type, uint8_t depth) {
     if (!g_header || t_in_hook) return;
     t_in_hook = 1;
 
@@ -112,7 +127,7 @@ static inline void poe_rt_record(void *func, void *call_site, uint8_t event_type
     e->call_site = (uint64_t)call_site;
     e->tid = (uint32_t)gettid();
     e->event_type = event_type;
-    e->depth = t_depth;
+    e->depth = depth;
 
     t_in_hook = 0;
 }
@@ -120,14 +135,59 @@ static inline void poe_rt_record(void *func, void *call_site, uint8_t event_type
 void __attribute__((no_instrument_function))
 __cyg_profile_func_enter(void *func, void *call_site) {
     if (!g_initialized) poe_rt_init();
-    poe_rt_record(func, call_site, 0);
+    poe_rt_record(func, call_site, 0, t_depth);
     if (t_depth < 255) t_depth++;
 }
 
 void __attribute__((no_instrument_function))
 __cyg_profile_func_exit(void *func, void *call_site) {
     if (t_depth > 0) t_depth--;
-    poe_rt_record(func, call_site, 1);
+    poe_rt_record(func, call_site, 1, t_depth);
+}
+
+/* Frames above the instrumented function, found by walking the frame-pointer
+ * chain. Frames from code built without frame pointers end the walk early,
+ * which is why the result is only kept relative to the first one seen. */
+static uint8_t __attribute__((no_instrument_function))
+poe_rt_fp_depth(uintptr_t *fp) {
+    if (!t_stack_hi) {
+        pthread_attr_t attr;
+        void *addr;
+        size_t size;
+        if (pthread_getattr_np(pthread_self(), &attr) != 0) return 0;
+        pthread_attr_getstack(&attr, &addr, &size);
+        pthread_attr_destroy(&attr);
+        t_stack_lo = (uintptr_t)addr;
+        t_stack_hi = t_stack_lo + size;
+    }
+
+    int depth = 0;
+    while (depth < 512) {
+        uintptr_t cur = (uintptr_t)fp;
+        if (cur < t_stack_lo || cur + 16 > t_stack_hi || (cur & 7)) break;
+        uintptr_t next = fp[0];
+        if (next <= cur) break;
+        fp = (uintptr_t *)next;
+        depth++;
+    }
+
+    if (t_base_depth < 0 || depth < t_base_depth) t_base_depth = depth;
+    int rel = depth - t_base_depth;
+    return rel > 255 ? 255 : (uint8_t)rel;
+}
+
+/* Entry hook for -pg style instrumentation, which is what rustc emits for
+ * -Zinstrument-mcount. There is no matching exit hook, so only entries are
+ * recorded; func is the return address into the instrumented function. */
+void __attribute__((no_instrument_function, noinline))
+mcount(void) {
+    if (t_in_hook) return;
+    if (!g_initialized) poe_rt_init();
+    if (!g_header) return;
+    t_in_hook = 1;
+    uint8_t depth = poe_rt_fp_depth(__builtin_frame_address(1));
+    t_in_hook = 0;
+    poe_rt_record(__builtin_return_address(0), __builtin_return_address(1), 2, depth);
 }
 
 static void __attribute__((destructor, no_instrument_function))
@@ -4,11 +4,59 @@ use std::process::Command;
 
 use anyhow::{bail, Context, Result};
 
+use crate::logging;
+use crate::util;
+
 const POE_RT_C: &str = include_str!("../../runtime/poe_rt.c");
 
 pub struct InstrumentConfig {
     pub build_command: Vec<String>,
-    pub _output_dir: PathBuf,
+    pub output_dir: PathBuf,
+    /// Write a toolchain file for this build system into `output_dir`.
+    pub emit_toolchain: Option<Toolchain>,
+}
+
+/// Build systems that record absolute compiler paths and so bypass the
+/// PATH wrappers unless pointed at them through a toolchain file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Toolchain {
+    Cmake,
+    Meson,
+}
+
+impl Toolchain {
+    pub fn parse(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "cmake" => Ok(Self::Cmake),
+            "meson" => Ok(Self::Meson),
+            _ => bail!("unknown toolchain kind '{}' (expected cmake or meson)", s),
+        }
+    }
+
+    fn file_name(&self) -> &'static str {
+        match self {
+            Self::Cmake => "poe-toolchain.cmake",
+            Self::Meson => "poe-native.ini",
+        }
+    }
+
+    /// Recognizes configure steps: `cmake <src>` (not `--build`/`--install`)
+    /// and `meson setup`.
+    fn detect(command: &[String]) -> Option<Self> {
+        let program = Path::new(command.first()?).file_name()?.to_str()?;
+        let args = &command[1..];
+        match program {
+            "cmake"
+                if !args
+                    .iter()
+                    .any(|a| matches!(a.as_str(), "--build" | "--install" | "-E" | "-P")) =>
+            {
+                Some(Self::Cmake)
+            }
+            "meson" if args.first().is_some_and(|a| a == "setup") => Some(Self::Meson),
+            _ => None,
+        }
+    }
 }
 
 pub fn execute_instrumented_build(config: InstrumentConfig) -> Result<PathBuf> {
@@ -32,18 +80,61 @@ pub fn execute_instrumented_build(config: InstrumentConfig) -> Result<PathBuf> {
     let current_path = std::env::var("PATH").unwrap_or_default();
     let new_path = format!("{}:{}", wrapper_dir.display(), current_path);
 
+    if let Some(kind) = config.emit_toolchain {
+        let path = config.output_dir.join(kind.file_name());
+        write_toolchain_file(kind, &path, &wrapper_dir, &rt_path)?;
+        log::info!("wrote {}", path.display());
+    }
+
     if config.build_command.is_empty() {
-        bail!("no build command specified");
+        if config.emit_toolchain.is_none() {
+            bail!("no build command specified");
+        }
+        return Ok(rt_path);
     }
 
-    eprintln!("poe build: compiling runtime library...");
-    eprintln!("poe build: injecting -finstrument-functions via compiler wrappers");
-    eprintln!("poe build: running: {}", config.build_command.join(" "));
+    log::info!("injecting -finstrument-functions via compiler wrappers");
+
+    let mut args = config.build_command[1..].to_vec();
+    if let Some(kind) = Toolchain::detect(&config.build_command) {
+        let path = work_dir.join(kind.file_name());
+        write_toolchain_file(kind, &path, &wrapper_dir, &rt_path)?;
+        log::info!("using generated toolchain file {}", path.display());
+        match kind {
+            Toolchain::Cmake => {
+                args.insert(0, format!("-DCMAKE_TOOLCHAIN_FILE={}", path.display()));
+            }
+            Toolchain::Meson => {
+                args.insert(1, "--native-file".into());
+                args.insert(2, path.display().to_string());
+            }
+        }
+    }
 
-    let status = Command::new(&config.build_command[0])
-        .args(&config.build_command[1..])
+    let mut command = Command::new(&config.build_command[0]);
+    command
+        .args(&args)
         .env("PATH", &new_path)
-        .env("POE_RT_LIB", rt_path.to_string_lossy().as_ref())
+        .env("POE_RT_LIB", rt_path.to_string_lossy().as_ref());
+
+    if is_cargo(&config.build_command[0]) {
+        check_nightly(&config.build_command)?;
+        let user_wrapper = std::env::var("RUSTC_WRAPPER")
+            .ok()
+            .filter(|w| !w.is_empty());
+        let wrapper = wrapper_dir.join("poe-rustc");
+        fs::write(
+            &wrapper,
+            rustc_wrapper_script(&rt_path, user_wrapper.as_deref()),
+        )?;
+        set_executable(&wrapper)?;
+        log::info!("injecting -Zinstrument-mcount into crates built by cargo");
+        command.env("RUSTC_WRAPPER", &wrapper);
+    }
+
+    log::info!("running: {} {}", config.build_command[0], args.join(" "));
+
+    let status = command
         .status()
         .with_context(|| format!("failed to run: {}", config.build_command[0]))?;
 
@@ -51,12 +142,159 @@ pub fn execute_instrumented_build(config: InstrumentConfig) -> Result<PathBuf> {
         bail!("build command failed with exit code: {:?}", status.code());
     }
 
-    eprintln!("poe build: done. Run the instrumented binary under 'poe run' to capture traces.");
-    eprintln!("poe build: runtime library: {}", rt_path.display());
+    if !logging::is_quiet() {
+        eprintln!(
+            "poe build: done. Run the instrumented binary under 'poe run' to capture traces."
+        );
+        eprintln!("poe build: runtime library: {}", rt_path.display());
+    }
 
     Ok(rt_path)
 }
 
+fn write_toolchain_file(
+    kind: Toolchain,
+    path: &Path,
+    wrapper_dir: &Path,
+    rt_lib: &Path,
+) -> Result<()> {
+    let pick = |names: &[&str]| {
+        names
+            .iter()
+            .map(|n| wrapper_dir.join(n))
+            .find(|p| p.exists())
+    };
+    let Some(cc) = pick(&["cc", "gcc", "clang"]) else {
+        bail!("no C compiler found to wrap");
+    };
+    let cxx = pick(&["c++", "g++", "clang++"]);
+
+    let content = match kind {
+        Toolchain::Cmake => {
+            let mut out = format!(
+                "# Generated by `poe build`. Routes compilation through poe's wrappers,\n\
+                 # which add -finstrument-functions and link {rt}.\n\
+                 # Use on a fresh build dir: cmake -DCMAKE_TOOLCHAIN_FILE=<this file> <src>\n\
+                 set(CMAKE_C_COMPILER \"{cc}\")\n",
+                rt = rt_lib.display(),
+                cc = cc.display()
+            );
+            if let Some(cxx) = cxx {
+                out.push_str(&format!("set(CMAKE_CXX_COMPILER \"{}\")\n", cxx.display()));
+            }
+            out
+        }
+        Toolchain::Meson => {
+            let mut out = format!(
+                "# Generated by `poe build`. Routes compilation through poe's wrappers,\n\
+                 # which add -finstrument-functions and link {rt}.\n\
+                 # Use on a fresh build dir: meson setup --native-file <this file> <builddir>\n\
+                 [binaries]\n\
+                 c = '{cc}'\n",
+                rt = rt_lib.display(),
+                cc = cc.display()
+            );
+            if let Some(cxx) = cxx {
+                out.push_str(&format!("cpp = '{}'\n", cxx.display()));
+            }
+            out
+        }
+    };
+
+    fs::write(path, content).with_context(|| format!("failed to write {}", path.display()))
+}
+
+fn is_cargo(program: &str) -> bool {
+    Path::new(program)
+        .file_name()
+        .is_some_and(|name| name == "cargo")
+}
+
+fn is_nightly(version: &str) -> bool {
+    version.contains("-nightly") || version.contains("-dev")
+}
+
+/// rustc has no stable -finstrument-functions, and -Zinstrument-mcount is
+/// nightly-only. Asks the rustc cargo will use, honoring a `+toolchain`
+/// argument and `$RUSTC`. A `RUSTC_BOOTSTRAP` the user set is left to them.
+fn check_nightly(command: &[String]) -> Result<()> {
+    if std::env::var_os("RUSTC_BOOTSTRAP").is_some() {
+        return Ok(());
+    }
+    let rustc = std::env::var("RUSTC").unwrap_or_else(|_| "rustc".into());
+    let mut probe = Command::new(&rustc);
+    if let Some(toolchain) = command.get(1).filter(|a| a.starts_with('+')) {
+        probe.arg(toolchain);
+    }
+    let output = probe
+        .arg("-V")
+        .output()
+        .with_context(|| format!("failed to run {} -V", rustc))?;
+    let version = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    if !output.status.success() || !is_nightly(&version) {
+        bail!(
+            "instrumenting Rust needs -Zinstrument-mcount, which only a nightly toolchain accepts \
+             (found {}); run `poe build -- cargo +nightly build`",
+            if version.is_empty() {
+                "no rustc"
+            } else {
+                &version
+            }
+        );
+    }
+    Ok(())
+}
+
+/// A RUSTC_WRAPPER for cargo, which runs it as `<wrapper> rustc <args>`.
+/// -Zinstrument-mcount inserts an mcount() call into every function, which
+/// the runtime records as an entry; frame pointers let it find the caller
+/// and the depth. Build scripts and proc macros run during the build and
+/// are left alone, and only crates that link an executable or shared
+/// library get the runtime, so it never ends up in a build script.
+fn rustc_wrapper_script(rt_lib: &Path, user_wrapper: Option<&str>) -> String {
+    format!(
+        r#"#!/bin/sh
+instrument=0
+link=0
+prev=
+for arg in "$@"; do
+    case "$prev" in
+        --crate-name)
+            case "$arg" in
+                build_script_*) instrument=2 ;;
+                *) [ "$instrument" = 0 ] && instrument=1 ;;
+            esac ;;
+        --crate-type)
+            case "$arg" in
+                proc-macro) instrument=2 ;;
+                bin|dylib|cdylib) link=1 ;;
+            esac ;;
+    esac
+    [ "$arg" = "--test" ] && link=1
+    prev=$arg
+done
+
+if [ "$instrument" = 1 ]; then
+    set -- "$@" -Zinstrument-mcount -Cforce-frame-pointers=yes
+    if [ "$link" = 1 ]; then
+        set -- "$@" -L"{rt_dir}" -lpoe_rt -Clink-arg=-Wl,-rpath,"{rt_dir}"
+    fi
+fi
+exec {chain}"$@"
+"#,
+        rt_dir = rt_lib.parent().unwrap().display(),
+        chain = user_wrapper
+            .map(|w| format!("{} ", util::shell_quote(w)))
+            .unwrap_or_default(),
+    )
+}
+
+fn set_executable(path: &Path) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(path, fs::Permissions::from_mode(0o755))?;
+    Ok(())
+}
+
 fn compile_runtime(work_dir: &Path) -> Result<PathBuf> {
     let src_path = work_dir.join("poe_rt.c");
     fs::write(&src_path, POE_RT_C)?;
@@ -64,12 +302,14 @@ fn compile_runtime(work_dir: &Path) -> Result<PathBuf> {
     let lib_path = work_dir.join("libpoe_rt.so");
 
     let cc = std::env::var("CC").unwrap_or_else(|_| "cc".into());
+    log::info!("compiling the runtime library with {}", cc);
 
     let status = Command::new(&cc)
         .args([
             "-shared",
             "-fPIC",
             "-O2",
+            "-fno-omit-frame-pointer",
             "-o",
             lib_path.to_str().unwrap(),
             src_path.to_str().unwrap(),
@@ -119,14 +359,7 @@ fi
     );
 
     fs::write(&wrapper_path, script)?;
-
-    #[cfg(unix)]
-    {
-        use std::os::unix::fs::PermissionsExt;
-        fs::set_permissions(&wrapper_path, fs::Permissions::from_mode(0o755))?;
-    }
-
-    Ok(())
+    set_executable(&wrapper_path)
 }
 
 fn find_real_compiler(name: &str) -> Result<String> {
@@ -194,12 +427,23 @@ pub fn read_runtime_trace(path: &Path) -> Result<(Vec<RuntimeEntry>, u64)> {
     Ok((entries, start_ns))
 }
 
+/// Where the traced executable was mapped, so PIE addresses can be matched
+/// against its symbol table. Only version 2+ runtimes record it.
+pub fn read_runtime_load_base(path: &Path) -> Option<u64> {
+    let data = fs::read(path).ok()?;
+    if data.len() < 64 || u32::from_le_bytes(data[4..8].try_into().ok()?) < 2 {
+        return None;
+    }
+    Some(u64::from_le_bytes(data[32..40].try_into().ok()?))
+}
+
 #[derive(Debug, Clone)]
 pub struct RuntimeEntry {
     pub ts_ns: u64,
     pub func_addr: u64,
     pub call_site: u64,
     pub tid: u32,
+    /// 0 = enter, 1 = exit, 2 = mcount entry (func_addr is inside the function).
     pub event_type: u8,
     pub depth: u8,
 }
@@ -337,4 +581,65 @@ mod tests {
         assert_eq!(entries[0].tid, 1);
         assert_eq!(entries[1].tid, 2);
     }
+
+    #[test]
+    fn load_base_needs_version_two() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("trace.bin");
+        let mut data = make_test_trace(4, &[]);
+        data[32..40].copy_from_slice(&0x5555_0000u64.to_le_bytes());
+        std::fs::write(&path, &data).unwrap();
+        assert_eq!(read_runtime_load_base(&path), None);
+
+        data[4..8].copy_from_slice(&2u32.to_le_bytes());
+        std::fs::write(&path, &data).unwrap();
+        assert_eq!(read_runtime_load_base(&path), Some(0x5555_0000));
+    }
+
+    #[test]
+    fn detects_configure_steps() {
+        let cmd = |s: &str| s.split(' ').map(String::from).collect::<Vec<_>>();
+        assert_eq!(
+            Toolchain::detect(&cmd("cmake -S . -B build")),
+            Some(Toolchain::Cmake)
+        );
+        assert_eq!(Toolchain::detect(&cmd("cmake --build build")), None);
+        assert_eq!(
+            Toolchain::detect(&cmd("meson setup build")),
+            Some(Toolchain::Meson)
+        );
+        assert_eq!(Toolchain::detect(&cmd("meson compile -C build")), None);
+        assert_eq!(Toolchain::detect(&cmd("make -j8")), None);
+    }
+
+    #[test]
+    fn rustc_wrapper_instruments_only_target_crates() {
+        assert!(is_cargo("/usr/bin/cargo"));
+        assert!(!is_cargo("make"));
+        assert!(is_nightly("rustc 1.84.0-nightly (3f1be1ec7 2024-10-28)"));
+        assert!(!is_nightly("rustc 1.84.0 (9fc6b4312 2025-01-07)"));
+
+        let dir = tempfile::tempdir().unwrap();
+        let wrapper = dir.path().join("poe-rustc");
+        let script = rustc_wrapper_script(Path::new("/tmp/rt/libpoe_rt.so"), None);
+        fs::write(&wrapper, script).unwrap();
+        set_executable(&wrapper).unwrap();
+        let flags = |args: &str| {
+            let out = Command::new(&wrapper)
+                .arg("echo")
+                .args(args.split(' '))
+                .output()
+                .unwrap();
+            String::from_utf8(out.stdout).unwrap()
+        };
+        let bin = flags("--crate-name app --crate-type bin src/main.rs");
+        assert!(bin.contains("-Zinstrument-mcount"), "{}", bin);
+        assert!(bin.contains("-L/tmp/rt -lpoe_rt"), "{}", bin);
+        let lib = flags("--crate-name dep --crate-type lib src/lib.rs");
+        assert!(lib.contains("-Zinstrument-mcount") && !lib.contains("-lpoe_rt"));
+        let build = flags("--crate-name build_script_build --crate-type bin build.rs");
+        assert!(!build.contains("-Z"), "{}", build);
+        let proc_macro = flags("--crate-type proc-macro --crate-name derive src/lib.rs");
+        assert!(!proc_macro.contains("-Z"), "{}", proc_macro);
+    }
 }
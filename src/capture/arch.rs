@@ -0,0 +1,343 @@
+//! Register access for the architectures poe traces: where a stopped
+//! thread's syscall number, arguments and return value live, and the
+//! registers worth recording at a crash.
+
+use nix::sys::ptrace;
+use nix::unistd::Pid;
+
+#[cfg(target_arch = "x86_64")]
+use crate::capture::syscalls;
+
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+compile_error!("poe traces x86_64 and aarch64 processes only");
+
+/// `AUDIT_ARCH_*` of the native syscall ABI, as seccomp filters see it.
+#[cfg(target_arch = "x86_64")]
+pub const AUDIT_ARCH: u32 = 0xc000_003e;
+#[cfg(target_arch = "aarch64")]
+pub const AUDIT_ARCH: u32 = 0xc000_00b7;
+
+/// Oldest kernel with everything the tracer relies on; aarch64 needs
+/// PTRACE_GET_SYSCALL_INFO.
+#[cfg(target_arch = "x86_64")]
+pub const MIN_KERNEL: (u32, u32) = (4, 8);
+#[cfg(target_arch = "aarch64")]
+pub const MIN_KERNEL: (u32, u32) = (5, 3);
+
+/// The syscall ABI a stopped thread entered the kernel with. A 64-bit
+/// process can exec a 32-bit helper, whose syscall numbers mean something
+/// else entirely.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Abi {
+    Native,
+    /// 32-bit code: i386 on x86_64, AArch32 on aarch64.
+    Compat,
+    /// x86_64 registers with 32-bit pointers.
+    #[cfg_attr(not(target_arch = "x86_64"), allow(dead_code))]
+    X32,
+}
+
+impl Abi {
+    pub fn name(self) -> &'static str {
+        match self {
+            Abi::Native => std::env::consts::ARCH,
+            Abi::Compat if cfg!(target_arch = "x86_64") => "i386",
+            Abi::Compat => "arm",
+            Abi::X32 => "x32",
+        }
+    }
+}
+
+/// Where a thread in a syscall-stop is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyscallStop {
+    /// `nr` is in the native table; None when the ABI's syscall has no
+    /// native counterpart poe can decode from its registers.
+    Entry {
+        abi: Abi,
+        nr: Option<u64>,
+        args: [u64; 6],
+    },
+    Exit {
+        ret: i64,
+    },
+}
+
+/// Code segment selector of 32-bit user code.
+#[cfg(target_arch = "x86_64")]
+const USER32_CS: u64 = 0x23;
+#[cfg(target_arch = "x86_64")]
+const X32_SYSCALL_BIT: u64 = 0x4000_0000;
+
+/// At syscall entry the kernel sets rax to -ENOSYS; at exit it holds the
+/// return value, so entry and exit tell apart without toggling. i386 code
+/// runs with the 32-bit code segment and passes arguments in ebx, ecx,
+/// edx, esi, edi and ebp; x32 sets a bit in the syscall number.
+#[cfg(target_arch = "x86_64")]
+pub fn syscall_stop(pid: Pid) -> nix::Result<SyscallStop> {
+    Ok(decode_regs(&ptrace::getregs(pid)?))
+}
+
+#[cfg(target_arch = "x86_64")]
+fn decode_regs(regs: &libc::user_regs_struct) -> SyscallStop {
+    let compat = regs.cs == USER32_CS;
+    if regs.rax as i64 != -(libc::ENOSYS as i64) {
+        let ret = if compat {
+            regs.rax as u32 as i32 as i64
+        } else {
+            regs.rax as i64
+        };
+        return SyscallStop::Exit { ret };
+    }
+    if compat {
+        let args = [regs.rbx, regs.rcx, regs.rdx, regs.rsi, regs.rdi, regs.rbp];
+        return SyscallStop::Entry {
+            abi: Abi::Compat,
+            nr: syscalls::i386_to_native(regs.orig_rax as u32 as u64),
+            args: args.map(|a| a & 0xffff_ffff),
+        };
+    }
+    let args = [regs.rdi, regs.rsi, regs.rdx, regs.r10, regs.r8, regs.r9];
+    if regs.orig_rax & X32_SYSCALL_BIT != 0 {
+        // x32 shares the native numbers below 512; the calls taking
+        // structs with pointers in them are renumbered above.
+        let nr = regs.orig_rax & !X32_SYSCALL_BIT;
+        return SyscallStop::Entry {
+            abi: Abi::X32,
+            nr: (nr < 512).then_some(nr),
+            args,
+        };
+    }
+    SyscallStop::Entry {
+        abi: Abi::Native,
+        nr: Some(regs.orig_rax),
+        args,
+    }
+}
+
+/// `struct ptrace_syscall_info`, skipping the instruction and stack
+/// pointer; `data` is the entry, exit or seccomp union.
+#[cfg(target_arch = "aarch64")]
+#[repr(C)]
+#[derive(Default)]
+struct PtraceSyscallInfo {
+    op: u8,
+    _pad: [u8; 3],
+    arch: u32,
+    _pointers: [u64; 2],
+    data: [u64; 8],
+}
+
+/// x0 is both the first argument and the return value and nothing marks
+/// entry from exit, so this asks the kernel (5.3+) with
+/// PTRACE_GET_SYSCALL_INFO, which also reports the ABI. AArch32 tasks'
+/// table isn't decoded.
+#[cfg(target_arch = "aarch64")]
+pub fn syscall_stop(pid: Pid) -> nix::Result<SyscallStop> {
+    const PTRACE_GET_SYSCALL_INFO: libc::c_uint = 0x420e;
+
+    let mut info = PtraceSyscallInfo::default();
+    let res = unsafe {
+        libc::ptrace(
+            PTRACE_GET_SYSCALL_INFO as _,
+            pid.as_raw(),
+            std::mem::size_of::<PtraceSyscallInfo>(),
+            &mut info as *mut PtraceSyscallInfo,
+        )
+    };
+    nix::errno::Errno::result(res)?;
+    decode_info(&info)
+}
+
+#[cfg(target_arch = "aarch64")]
+const PTRACE_SYSCALL_INFO_ENTRY: u8 = 1;
+#[cfg(target_arch = "aarch64")]
+const PTRACE_SYSCALL_INFO_EXIT: u8 = 2;
+
+#[cfg(target_arch = "aarch64")]
+fn decode_info(info: &PtraceSyscallInfo) -> nix::Result<SyscallStop> {
+    match info.op {
+        PTRACE_SYSCALL_INFO_ENTRY => {
+            let mut args = [0u64; 6];
+            args.copy_from_slice(&info.data[1..7]);
+            let native = info.arch == AUDIT_ARCH;
+            Ok(SyscallStop::Entry {
+                abi: if native { Abi::Native } else { Abi::Compat },
+                nr: native.then_some(info.data[0]),
+                args,
+            })
+        }
+        PTRACE_SYSCALL_INFO_EXIT => Ok(SyscallStop::Exit {
+            ret: info.data[0] as i64,
+        }),
+        _ => Err(nix::errno::Errno::EINVAL),
+    }
+}
+
+/// Program counter and stack pointer of a stopped 64-bit thread.
+pub fn pc_sp(pid: Pid) -> Option<(u64, u64)> {
+    let regs = ptrace::getregs(pid).ok()?;
+    #[cfg(target_arch = "x86_64")]
+    return (regs.cs != USER32_CS).then_some((regs.rip, regs.rsp));
+    #[cfg(target_arch = "aarch64")]
+    return Some((regs.pc, regs.sp));
+}
+
+/// Registers appended to a crash signal's event detail.
+pub fn crash_registers(pid: Pid) -> Option<String> {
+    let regs = ptrace::getregs(pid).ok()?;
+    #[cfg(target_arch = "x86_64")]
+    return Some(format!(
+        " rip={:#x} rsp={:#x} rbp={:#x} rax={:#x} rdi={:#x} rsi={:#x}",
+        regs.rip, regs.rsp, regs.rbp, regs.rax, regs.rdi, regs.rsi,
+    ));
+    #[cfg(target_arch = "aarch64")]
+    return Some(format!(
+        " pc={:#x} sp={:#x} fp={:#x} lr={:#x} x0={:#x} x1={:#x}",
+        regs.pc, regs.sp, regs.regs[29], regs.regs[30], regs.regs[0], regs.regs[1],
+    ));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(target_arch = "x86_64")]
+    fn regs_at_entry(orig_rax: u64, cs: u64) -> libc::user_regs_struct {
+        // SAFETY: user_regs_struct is plain integers.
+        let mut regs: libc::user_regs_struct = unsafe { std::mem::zeroed() };
+        regs.rax = -(libc::ENOSYS as i64) as u64;
+        regs.orig_rax = orig_rax;
+        regs.cs = cs;
+        regs
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    #[test]
+    fn native_entry_reads_the_syscall_argument_registers() {
+        let mut regs = regs_at_entry(syscalls::SYS_OPENAT, 0x33);
+        (regs.rdi, regs.rsi, regs.rdx) = (1, 2, 3);
+        (regs.r10, regs.r8, regs.r9) = (4, 5, 6);
+        // rcx is clobbered by the syscall instruction and never an argument.
+        regs.rcx = 99;
+        assert_eq!(
+            decode_regs(&regs),
+            SyscallStop::Entry {
+                abi: Abi::Native,
+                nr: Some(syscalls::SYS_OPENAT),
+                args: [1, 2, 3, 4, 5, 6],
+            }
+        );
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    #[test]
+    fn i386_entry_maps_numbers_and_truncates_arguments() {
+        // i386 open is 5, with arguments in ebx, ecx, edx, esi, edi, ebp.
+        let mut regs = regs_at_entry(5, USER32_CS);
+        (regs.rbx, regs.rcx, regs.rdx) = (0xdead_0000_0804_a000, 0o102, 0o644);
+        (regs.rsi, regs.rdi, regs.rbp) = (4, 5, 6);
+        assert_eq!(
+            decode_regs(&regs),
+            SyscallStop::Entry {
+                abi: Abi::Compat,
+                nr: Some(syscalls::SYS_OPEN),
+                args: [0x0804_a000, 0o102, 0o644, 4, 5, 6],
+            }
+        );
+
+        // i386 socketcall has no native counterpart.
+        let regs = regs_at_entry(102, USER32_CS);
+        assert!(matches!(
+            decode_regs(&regs),
+            SyscallStop::Entry {
+                abi: Abi::Compat,
+                nr: None,
+                ..
+            }
+        ));
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    #[test]
+    fn x32_entry_strips_the_abi_bit() {
+        let regs = regs_at_entry(X32_SYSCALL_BIT | syscalls::SYS_READ, 0x33);
+        assert!(matches!(
+            decode_regs(&regs),
+            SyscallStop::Entry {
+                abi: Abi::X32,
+                nr: Some(syscalls::SYS_READ),
+                ..
+            }
+        ));
+        // 512 and up are x32's own numbers for calls with pointer structs.
+        let regs = regs_at_entry(X32_SYSCALL_BIT | 519, 0x33);
+        assert!(matches!(
+            decode_regs(&regs),
+            SyscallStop::Entry {
+                abi: Abi::X32,
+                nr: None,
+                ..
+            }
+        ));
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    #[test]
+    fn exit_return_values_are_sign_extended_per_abi() {
+        let mut regs = regs_at_entry(syscalls::SYS_READ, 0x33);
+        regs.rax = -(libc::EBADF as i64) as u64;
+        assert_eq!(
+            decode_regs(&regs),
+            SyscallStop::Exit {
+                ret: -libc::EBADF as i64
+            }
+        );
+        // A 32-bit task's eax; the upper half of rax is not its to set.
+        regs.cs = USER32_CS;
+        regs.rax = 0xffff_ffe0;
+        assert_eq!(decode_regs(&regs), SyscallStop::Exit { ret: -32 });
+    }
+
+    #[cfg(target_arch = "aarch64")]
+    #[test]
+    fn syscall_info_entry_and_exit_are_decoded() {
+        let mut info = PtraceSyscallInfo {
+            op: PTRACE_SYSCALL_INFO_ENTRY,
+            arch: AUDIT_ARCH,
+            data: [56, 1, 2, 3, 4, 5, 6, 0],
+            ..Default::default()
+        };
+        assert_eq!(
+            decode_info(&info),
+            Ok(SyscallStop::Entry {
+                abi: Abi::Native,
+                nr: Some(56),
+                args: [1, 2, 3, 4, 5, 6],
+            })
+        );
+
+        // AUDIT_ARCH_ARM: AArch32 numbers aren't decoded.
+        info.arch = 0x4000_0028;
+        assert!(matches!(
+            decode_info(&info),
+            Ok(SyscallStop::Entry {
+                abi: Abi::Compat,
+                nr: None,
+                ..
+            })
+        ));
+
+        info.op = PTRACE_SYSCALL_INFO_EXIT;
+        info.data[0] = -(libc::ENOENT as i64) as u64;
+        assert_eq!(
+            decode_info(&info),
+            Ok(SyscallStop::Exit {
+                ret: -libc::ENOENT as i64
+            })
+        );
+
+        info.op = 0;
+        assert_eq!(decode_info(&info), Err(nix::errno::Errno::EINVAL));
+    }
+}
@@ -0,0 +1,93 @@
+use std::sync::mpsc;
+
+use anyhow::{bail, Result};
+
+use crate::capture::tracer::{Tracer, TracerConfig};
+use crate::capture::unotify::{self, UnotifyTracer};
+use crate::events::types::TraceEvent;
+
+/// How syscalls of the traced command are intercepted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Backend {
+    /// A ptrace stop at every syscall entry and exit. Sees results and
+    /// durations, signals and register state.
+    #[default]
+    Ptrace,
+    /// seccomp user notification for the interesting syscalls only. Much
+    /// cheaper, but records arguments without results.
+    Seccomp,
+}
+
+impl Backend {
+    pub fn parse(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "ptrace" => Ok(Self::Ptrace),
+            "seccomp" | "seccomp-unotify" | "unotify" => Ok(Self::Seccomp),
+            _ => bail!(
+                "unknown capture backend '{}' (expected ptrace or seccomp)",
+                s
+            ),
+        }
+    }
+}
+
+pub enum ActiveTracer {
+    Ptrace(Box<Tracer>),
+    Seccomp(Box<UnotifyTracer>),
+}
+
+impl ActiveTracer {
+    /// Spawns `argv` under the requested backend, falling back to ptrace
+    /// when seccomp notification is unavailable.
+    pub fn spawn(
+        backend: Backend,
+        config: TracerConfig,
+        event_tx: mpsc::Sender<TraceEvent>,
+        argv: &[String],
+    ) -> Result<(Self, i32)> {
+        if backend == Backend::Seccomp {
+            match unotify::unsupported_reason() {
+                Some(reason) => {
+                    log::warn!("seccomp backend unavailable ({}), using ptrace", reason)
+                }
+                None => {
+                    let mut tracer = UnotifyTracer::new(config.clone(), event_tx.clone());
+                    match tracer.spawn_and_trace(argv) {
+                        Ok(pid) => {
+                            if config.hash_writes.is_some()
+                                || !config.watch_files.is_empty()
+                                || config.capture_sql
+                                || config.capture_cache
+                                || config.capture_http2
+                            {
+                                log::warn!(
+                                    "--hash-writes, --watch-file and --capture-sql/cache/http2 need syscall results and are ignored by the seccomp backend"
+                                );
+                            }
+                            return Ok((Self::Seccomp(Box::new(tracer)), pid));
+                        }
+                        Err(e) => log::warn!("{:#}, using ptrace", e),
+                    }
+                }
+            }
+        }
+
+        let mut tracer = Tracer::new(config, event_tx);
+        let pid = tracer.spawn_and_trace(argv)?;
+        Ok((Self::Ptrace(Box::new(tracer)), pid))
+    }
+
+    pub fn base_ts(&self) -> u64 {
+        match self {
+            Self::Ptrace(t) => t.base_ts(),
+            Self::Seccomp(t) => t.base_ts(),
+        }
+    }
+
+    pub fn run_event_loop(&mut self) -> Result<(Option<i32>, Option<i32>)> {
+        match self {
+            Self::Ptrace(t) => t.run_event_loop(),
+            Self::Seccomp(t) => t.run_event_loop(),
+        }
+    }
+}
@@ -0,0 +1,539 @@
+use std::collections::VecDeque;
+
+use crate::capture::dbwire::{Stream, WireEvent};
+
+/// Bytes kept of each command argument; values are only sized.
+const MAX_ARG_LEN: usize = 256;
+
+/// Redis commands whose first argument is not a key, or whose arguments
+/// may hold credentials; none of their arguments are recorded.
+const KEYLESS: [&str; 28] = [
+    "ACL", "AUTH", "BGSAVE", "CLIENT", "CLUSTER", "COMMAND", "CONFIG", "DBSIZE", "DEBUG",
+    "DISCARD", "ECHO", "EVAL", "EVALSHA", "EXEC", "FCALL", "FLUSHALL", "FLUSHDB", "HELLO", "INFO",
+    "MIGRATE", "MULTI", "PING", "QUIT", "RESET", "SAVE", "SCAN", "SCRIPT", "SELECT",
+];
+
+const MEMCACHED_COMMANDS: [&str; 23] = [
+    "get",
+    "gets",
+    "gat",
+    "gats",
+    "set",
+    "add",
+    "replace",
+    "append",
+    "prepend",
+    "cas",
+    "delete",
+    "incr",
+    "decr",
+    "touch",
+    "stats",
+    "version",
+    "flush_all",
+    "verbosity",
+    "mg",
+    "ms",
+    "md",
+    "ma",
+    "mn",
+];
+
+/// One cache command and the server's reply.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CacheOp {
+    pub ts: u64,
+    pub proc_id: i32,
+    pub proto: &'static str,
+    pub command: String,
+    pub key: Option<String>,
+    /// Bytes of the values sent with the command.
+    pub value_bytes: Option<u64>,
+    /// None when no reply was seen before the connection closed.
+    pub end_ts: Option<u64>,
+    /// Short form of the reply: status line, integer, size or item count.
+    pub reply: Option<String>,
+    /// Bytes of the values returned.
+    pub reply_bytes: Option<u64>,
+    /// Whether a lookup found its key; None when the reply doesn't say.
+    pub hit: Option<bool>,
+    pub error: Option<String>,
+}
+
+impl CacheOp {
+    fn new(ts: u64, proc_id: i32, proto: &'static str, command: String) -> Self {
+        Self {
+            ts,
+            proc_id,
+            proto,
+            command,
+            key: None,
+            value_bytes: None,
+            end_ts: None,
+            reply: None,
+            reply_bytes: None,
+            hit: None,
+            error: None,
+        }
+    }
+
+    pub fn status(&self) -> &'static str {
+        match (&self.error, self.end_ts, self.hit) {
+            (Some(_), _, _) => "error",
+            (None, None, _) => "no_response",
+            (None, Some(_), Some(false)) => "miss",
+            _ => "ok",
+        }
+    }
+
+    pub fn to_json(&self, peer: Option<&str>) -> serde_json::Value {
+        serde_json::json!({
+            "proto": self.proto,
+            "peer": peer,
+            "command": self.command,
+            "key": self.key,
+            "value_bytes": self.value_bytes,
+            "status": self.status(),
+            "duration_ns": self.end_ts.map(|e| e.saturating_sub(self.ts)),
+            "reply": self.reply,
+            "reply_bytes": self.reply_bytes,
+            "hit": self.hit,
+            "error": self.error,
+        })
+    }
+}
+
+fn text(b: &[u8]) -> String {
+    String::from_utf8_lossy(b).into_owned()
+}
+
+/// Client commands are RESP arrays: `*<n>\r\n$<len>\r\n...`.
+pub fn looks_like_resp(data: &[u8]) -> bool {
+    data.len() >= 4 && data[0] == b'*' && data[1].is_ascii_digit()
+}
+
+pub fn looks_like_memcached(data: &[u8]) -> bool {
+    let Some(end) = data.windows(2).position(|w| w == b"\r\n") else {
+        return false;
+    };
+    let line = text(&data[..end]);
+    line.split_whitespace()
+        .next()
+        .is_some_and(|cmd| MEMCACHED_COMMANDS.contains(&cmd))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    /// Simple strings, errors, integers, doubles, booleans, big numbers.
+    Line(u8, String),
+    /// Bulk, verbatim and bulk-error strings: kept head and full length;
+    /// None for nil.
+    Bulk(u8, Option<Vec<u8>>, usize),
+    /// Arrays, maps, sets, pushes and attributes with their element count;
+    /// None for a nil array.
+    Aggregate(u8, Option<usize>),
+    Null,
+    /// An inline command: a plain line of words.
+    Inline(String),
+}
+
+/// Incremental RESP2/RESP3 tokenizer.
+#[derive(Default)]
+struct RespReader {
+    /// Elements still expected by each open aggregate, outermost first.
+    open: Vec<usize>,
+    /// A bulk string whose header was read but not its body.
+    bulk: Option<(u8, usize)>,
+}
+
+impl RespReader {
+    /// Reads one token and how many aggregates enclose it.
+    fn next(&mut self, stream: &mut Stream) -> Option<(Token, usize)> {
+        let depth = self.open.len();
+        if let Some((kind, len)) = self.bulk {
+            let data = stream.block(len, MAX_ARG_LEN)?;
+            self.bulk = None;
+            return Some((Token::Bulk(kind, Some(data), len), depth));
+        }
+        let line = stream.line()?;
+        let Some((&kind, rest)) = line.split_first() else {
+            return Some((Token::Inline(String::new()), depth));
+        };
+        let count = text(rest).parse::<i64>().ok().filter(|n| *n >= 0);
+        let token = match kind {
+            b'$' | b'=' | b'!' => match count {
+                Some(len) => {
+                    self.bulk = Some((kind, len as usize));
+                    return self.next(stream);
+                }
+                None => Token::Bulk(kind, None, 0),
+            },
+            // Maps and attributes count key/value pairs.
+            b'%' | b'|' => Token::Aggregate(kind, count.map(|n| n as usize * 2)),
+            b'*' | b'~' | b'>' => Token::Aggregate(kind, count.map(|n| n as usize)),
+            b'_' => Token::Null,
+            b'+' | b'-' | b':' | b',' | b'#' | b'(' => Token::Line(kind, text(rest)),
+            _ => Token::Inline(text(&line)),
+        };
+        Some((token, depth))
+    }
+
+    /// Accounts for a token just read; true when it ended a top-level value.
+    fn complete(&mut self, token: &Token) -> bool {
+        if let Token::Aggregate(_, Some(n)) = token {
+            if *n > 0 {
+                self.open.push(*n);
+                return false;
+            }
+        }
+        while let Some(left) = self.open.last_mut() {
+            *left -= 1;
+            if *left > 0 {
+                return false;
+            }
+            self.open.pop();
+        }
+        true
+    }
+}
+
+#[derive(Default)]
+struct RespReply {
+    kind: u8,
+    text: Option<String>,
+    bytes: u64,
+    hit: Option<bool>,
+    error: Option<String>,
+}
+
+#[derive(Default)]
+pub(crate) struct Redis {
+    client: RespReader,
+    server: RespReader,
+    /// Arguments of the command being read: kept head and full length.
+    args: Vec<(Vec<u8>, usize)>,
+    reply: Option<RespReply>,
+    /// Replies come back in command order, so pipelined commands queue.
+    pub(crate) pending: VecDeque<CacheOp>,
+}
+
+impl Redis {
+    pub(crate) fn client(&mut self, stream: &mut Stream, ts: u64, proc_id: i32) {
+        while let Some((token, depth)) = self.client.next(stream) {
+            match (&token, depth) {
+                (Token::Inline(line), 0) => {
+                    self.args = line
+                        .split_whitespace()
+                        .map(|w| (w.as_bytes().to_vec(), w.len()))
+                        .collect();
+                }
+                (Token::Bulk(_, Some(data), len), 1) => self.args.push((data.clone(), *len)),
+                _ => {}
+            }
+            if self.client.complete(&token) && !self.args.is_empty() {
+                let args = std::mem::take(&mut self.args);
+                self.pending.push_back(redis_command(&args, ts, proc_id));
+            }
+        }
+    }
+
+    pub(crate) fn server(&mut self, stream: &mut Stream, ts: u64, out: &mut Vec<WireEvent>) {
+        while let Some((token, depth)) = self.server.next(stream) {
+            let reply = self.reply.get_or_insert_with(RespReply::default);
+            if depth == 0 {
+                match &token {
+                    Token::Line(b'-', msg) => reply.error = Some(msg.clone()),
+                    Token::Bulk(b'!', Some(msg), _) => reply.error = Some(text(msg)),
+                    Token::Line(kind, value) => {
+                        reply.kind = *kind;
+                        reply.text = Some(value.clone());
+                    }
+                    Token::Bulk(_, Some(_), len) => {
+                        reply.text = Some(format!("{} bytes", len));
+                        reply.hit = Some(true);
+                    }
+                    Token::Bulk(_, None, _) | Token::Null | Token::Aggregate(_, None) => {
+                        reply.text = Some("(nil)".into());
+                        reply.hit = Some(false);
+                    }
+                    Token::Aggregate(kind, Some(n)) => {
+                        reply.kind = *kind;
+                        let items = if matches!(kind, b'%' | b'|') {
+                            n / 2
+                        } else {
+                            *n
+                        };
+                        reply.text = Some(format!("{} items", items));
+                    }
+                    Token::Inline(line) => reply.text = Some(line.clone()),
+                }
+            }
+            if let Token::Bulk(_, Some(_), len) = token {
+                reply.bytes += len as u64;
+            }
+            if !self.server.complete(&token) {
+                continue;
+            }
+            let Some(reply) = self.reply.take() else {
+                continue;
+            };
+            // Pushes (pub/sub) and attributes are not replies to a command.
+            if matches!(reply.kind, b'>' | b'|') {
+                continue;
+            }
+            if let Some(mut op) = self.pending.pop_front() {
+                op.end_ts = Some(ts);
+                op.reply = reply.text;
+                op.reply_bytes = (reply.bytes > 0).then_some(reply.bytes);
+                op.hit = reply.hit;
+                op.error = reply.error;
+                out.push(WireEvent::Cache(op));
+            }
+        }
+    }
+}
+
+fn redis_command(args: &[(Vec<u8>, usize)], ts: u64, proc_id: i32) -> CacheOp {
+    let command = text(&args[0].0).to_uppercase();
+    let mut op = CacheOp::new(ts, proc_id, "redis", command);
+    if !KEYLESS.contains(&op.command.as_str()) {
+        op.key = args.get(1).map(|(k, _)| text(k));
+        if args.len() > 2 {
+            op.value_bytes = Some(args[2..].iter().map(|(_, len)| *len as u64).sum());
+        }
+    }
+    op
+}
+
+/// How the server frames its answer to a memcached command.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum McReply {
+    /// `VALUE`/`STAT` lines up to `END`.
+    Values,
+    /// One meta status line, with a data block after `VA`.
+    Meta,
+    /// One line.
+    Line,
+}
+
+#[derive(Default)]
+pub(crate) struct Memcached {
+    /// Data block still to come after a storage command line.
+    client_block: Option<usize>,
+    /// Data block still to come after a `VALUE` or `VA` line.
+    server_block: Option<usize>,
+    values: u64,
+    bytes: u64,
+    pub(crate) pending: VecDeque<(CacheOp, McReply)>,
+}
+
+impl Memcached {
+    pub(crate) fn client(
+        &mut self,
+        stream: &mut Stream,
+        ts: u64,
+        proc_id: i32,
+        out: &mut Vec<WireEvent>,
+    ) {
+        loop {
+            if let Some(len) = self.client_block {
+                if stream.block(len, 0).is_none() {
+                    return;
+                }
+                self.client_block = None;
+            }
+            let Some(line) = stream.line() else {
+                return;
+            };
+            let line = text(&line);
+            let words: Vec<&str> = line.split_whitespace().collect();
+            let Some(&cmd) = words.first() else {
+                continue;
+            };
+            let mut op = CacheOp::new(ts, proc_id, "memcached", cmd.to_string());
+            let word = |i: usize| words.get(i).map(|w| w.to_string());
+            let reply = match cmd {
+                "get" | "gets" => {
+                    op.key = Some(words[1..].join(" ")).filter(|k| !k.is_empty());
+                    McReply::Values
+                }
+                "gat" | "gats" => {
+                    op.key = words
+                        .get(2..)
+                        .map(|k| k.join(" "))
+                        .filter(|k| !k.is_empty());
+                    McReply::Values
+                }
+                "stats" => McReply::Values,
+                "set" | "add" | "replace" | "append" | "prepend" | "cas" => {
+                    op.key = word(1);
+                    op.value_bytes = words.get(4).and_then(|b| b.parse().ok());
+                    self.client_block = op.value_bytes.map(|b| b as usize);
+                    McReply::Line
+                }
+                "ms" => {
+                    op.key = word(1);
+                    op.value_bytes = words.get(2).and_then(|b| b.parse().ok());
+                    self.client_block = op.value_bytes.map(|b| b as usize);
+                    McReply::Meta
+                }
+                "mg" | "md" | "ma" => {
+                    op.key = word(1);
+                    McReply::Meta
+                }
+                "mn" => McReply::Meta,
+                "delete" | "incr" | "decr" | "touch" => {
+                    op.key = word(1);
+                    McReply::Line
+                }
+                _ => McReply::Line,
+            };
+            if words.last() == Some(&"noreply") {
+                op.end_ts = Some(ts);
+                op.reply = Some("noreply".into());
+                out.push(WireEvent::Cache(op));
+            } else {
+                self.pending.push_back((op, reply));
+            }
+        }
+    }
+
+    pub(crate) fn server(&mut self, stream: &mut Stream, ts: u64, out: &mut Vec<WireEvent>) {
+        loop {
+            if let Some(len) = self.server_block {
+                if stream.block(len, 0).is_none() {
+                    return;
+                }
+                self.server_block = None;
+                self.bytes += len as u64;
+                if matches!(self.pending.front(), Some((_, McReply::Meta))) {
+                    self.finish(ts, "VA".into(), Some(true), None, out);
+                }
+                continue;
+            }
+            let Some(line) = stream.line() else {
+                return;
+            };
+            let line = text(&line);
+            let mut words = line.split_whitespace();
+            let first = words.next().unwrap_or("");
+            let Some(&(ref op, reply)) = self.pending.front() else {
+                continue;
+            };
+            let lookup = op.command != "stats";
+            match (reply, first) {
+                (_, "ERROR" | "CLIENT_ERROR" | "SERVER_ERROR") => {
+                    self.finish(ts, first.into(), None, Some(line.clone()), out)
+                }
+                (McReply::Values, "VALUE") => {
+                    self.values += 1;
+                    self.server_block = words.nth(2).and_then(|b| b.parse().ok());
+                }
+                (McReply::Values, "END") => {
+                    let hit = lookup.then_some(self.values > 0);
+                    let summary = if lookup {
+                        format!("{} values", self.values)
+                    } else {
+                        "END".into()
+                    };
+                    self.finish(ts, summary, hit, None, out);
+                }
+                (McReply::Values, _) => {}
+                (McReply::Meta, "VA") => {
+                    self.server_block = words.next().and_then(|b| b.parse().ok());
+                }
+                (McReply::Meta, "EN" | "NF") => {
+                    self.finish(ts, first.into(), Some(false), None, out)
+                }
+                (McReply::Meta, "HD") if op.command == "mg" => {
+                    self.finish(ts, first.into(), Some(true), None, out)
+                }
+                (_, "NOT_FOUND") => self.finish(ts, first.into(), Some(false), None, out),
+                _ => self.finish(ts, line.clone(), None, None, out),
+            }
+        }
+    }
+
+    fn finish(
+        &mut self,
+        ts: u64,
+        reply: String,
+        hit: Option<bool>,
+        error: Option<String>,
+        out: &mut Vec<WireEvent>,
+    ) {
+        let bytes = std::mem::take(&mut self.bytes);
+        self.values = 0;
+        if let Some((mut op, _)) = self.pending.pop_front() {
+            op.end_ts = Some(ts);
+            op.reply = Some(reply);
+            op.reply_bytes = (bytes > 0).then_some(bytes);
+            op.hit = hit;
+            op.error = error;
+            out.push(WireEvent::Cache(op));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::capture::dbwire::DbConn;
+
+    use super::*;
+
+    fn ops(events: Vec<WireEvent>) -> Vec<CacheOp> {
+        events
+            .into_iter()
+            .filter_map(|e| match e {
+                WireEvent::Cache(op) => Some(op),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn redis_pipelined_commands() {
+        let mut conn = DbConn::new(None, false, true, false);
+        let pipeline = b"*3\r\n$3\r\nSET\r\n$6\r\nuser:1\r\n$5\r\nalice\r\n\
+                         *2\r\n$3\r\nGET\r\n$6\r\nuser:1\r\n\
+                         *2\r\n$3\r\nGET\r\n$6\r\nuser:2\r\n\
+                         *2\r\n$4\r\nAUTH\r\n$6\r\nsecret\r\n\
+                         *2\r\n$4\r\nINCR\r\n$6\r\nuser:1\r\n";
+        assert!(conn.on_send(0, 1, pipeline, pipeline.len()).is_empty());
+        let replies = b"+OK\r\n$5\r\nalice\r\n$-1\r\n-WRONGPASS invalid password\r\n";
+        let done = ops(conn.on_recv(5, 1, replies, replies.len()));
+        assert_eq!(done.len(), 4);
+        assert_eq!(done[0].value_bytes, Some(5));
+        assert_eq!(done[1].reply_bytes, Some(5));
+        assert_eq!(done[1].status(), "ok");
+        assert_eq!(done[2].key.as_deref(), Some("user:2"));
+        assert_eq!(done[2].status(), "miss");
+        assert_eq!(done[3].key, None);
+        assert_eq!(done[3].status(), "error");
+
+        // Split reply, then the connection closes with nothing pending.
+        let done = ops(conn.on_recv(6, 1, b"-WRONGTYPE Operation", 20));
+        assert!(done.is_empty());
+        let done = ops(conn.on_recv(7, 1, b" against a key\r\n", 16));
+        assert_eq!(done[0].command, "INCR");
+        assert!(done[0].error.as_deref().unwrap().starts_with("WRONGTYPE"));
+        assert!(conn.close().is_empty());
+    }
+
+    #[test]
+    fn memcached_text_protocol() {
+        let mut conn = DbConn::new(None, false, true, false);
+        let reqs = b"set k 0 0 5\r\nhello\r\nget k\r\nget missing\r\ndelete gone noreply\r\n";
+        let done = ops(conn.on_send(0, 1, reqs, reqs.len()));
+        assert_eq!(done.len(), 1);
+        assert_eq!(done[0].reply.as_deref(), Some("noreply"));
+        let replies = b"STORED\r\nVALUE k 0 5\r\nhello\r\nEND\r\nEND\r\n";
+        let done = ops(conn.on_recv(3, 1, replies, replies.len()));
+        assert_eq!(done.len(), 3);
+        assert_eq!(done[0].value_bytes, Some(5));
+        assert_eq!(done[0].reply.as_deref(), Some("STORED"));
+        assert_eq!(done[1].hit, Some(true));
+        assert_eq!(done[1].reply_bytes, Some(5));
+        assert_eq!(done[2].status(), "miss");
+    }
+}
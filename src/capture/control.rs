@@ -0,0 +1,251 @@
+use std::collections::{HashMap, HashSet};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::events::types::{EventKind, TraceEvent};
+use crate::trace::db::TraceDb;
+use crate::util::procfs;
+
+/// Names the fd the traced program writes capture markers to.
+pub const CONTROL_FD_ENV: &str = "POE_CONTROL_FD";
+
+const MARKER: &str = "POE:CAPTURE ";
+
+/// Detail of a `capture_control` event.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CaptureControl {
+    /// `off` or `on`.
+    pub capture: String,
+}
+
+/// `Some(false)` for `POE:CAPTURE OFF`, `Some(true)` for `POE:CAPTURE ON`,
+/// from the start of a write to the control fd.
+pub fn parse_marker(buf: &[u8]) -> Option<bool> {
+    let text = String::from_utf8_lossy(buf);
+    let rest = text.trim_start().strip_prefix(MARKER)?;
+    match rest.lines().next()?.trim().to_ascii_uppercase().as_str() {
+        "OFF" => Some(false),
+        "ON" => Some(true),
+        _ => None,
+    }
+}
+
+/// Whether `fd` of `pid` is the control fd named in the process's own
+/// environment, or a dup of it: shells redirect with dup2, so
+/// `echo ... >&$POE_CONTROL_FD` writes to fd 1.
+pub fn is_control_fd(pid: i32, fd: i32) -> bool {
+    let Some(control) = procfs::read_environ(pid)
+        .ok()
+        .and_then(|env| env.get(CONTROL_FD_ENV)?.parse::<i32>().ok())
+    else {
+        return false;
+    };
+    fd == control || same_file(pid, fd, control)
+}
+
+/// kcmp(KCMP_FILE): whether two of a process's fds share one open file.
+fn same_file(pid: i32, a: i32, b: i32) -> bool {
+    const KCMP_FILE: libc::c_int = 0;
+    let res = unsafe {
+        libc::syscall(
+            libc::SYS_kcmp,
+            pid,
+            pid,
+            KCMP_FILE,
+            a as libc::c_ulong,
+            b as libc::c_ulong,
+        )
+    };
+    res == 0
+}
+
+/// Drops the events a process records while it has capture turned off.
+/// A marker applies to the writer's whole thread group, and processes it
+/// starts while paused start out paused. Process starts, execs and exits
+/// and stdio still go through, so the process tree and output stay whole.
+#[derive(Default)]
+pub struct CaptureGate {
+    /// Thread group of each task that isn't its own leader.
+    tgids: HashMap<i32, i32>,
+    paused: HashSet<i32>,
+    dropped: u64,
+}
+
+impl CaptureGate {
+    pub fn admit(&mut self, event: &TraceEvent) -> bool {
+        match event {
+            TraceEvent::Process(p) => {
+                let tgid = p.tgid.unwrap_or(p.proc_id);
+                if tgid != p.proc_id {
+                    self.tgids.insert(p.proc_id, tgid);
+                } else {
+                    // A recycled pid doesn't inherit the old process's state.
+                    self.tgids.remove(&tgid);
+                    let parent_paused = p.parent_proc_id.is_some_and(|pp| self.is_paused(pp));
+                    if parent_paused {
+                        self.paused.insert(tgid);
+                    } else {
+                        self.paused.remove(&tgid);
+                    }
+                }
+                return true;
+            }
+            TraceEvent::Generic(e) if e.kind == EventKind::CaptureControl => {
+                if let Ok(control) = serde_json::from_str::<CaptureControl>(&e.detail) {
+                    let tgid = self.tgid(e.proc_id);
+                    if control.capture == "off" {
+                        self.paused.insert(tgid);
+                    } else {
+                        self.paused.remove(&tgid);
+                    }
+                }
+                return true;
+            }
+            _ => {}
+        }
+        let structural = match event {
+            TraceEvent::Process(_)
+            | TraceEvent::ProcessExit(_)
+            | TraceEvent::ProcessIo(_)
+            | TraceEvent::Stdio(_) => true,
+            TraceEvent::Generic(e) => matches!(
+                e.kind,
+                EventKind::ProcessStart | EventKind::ProcessExit | EventKind::ProcessExec
+            ),
+            _ => false,
+        };
+        if !structural && self.is_paused(event.proc_id()) {
+            self.dropped += 1;
+            return false;
+        }
+        true
+    }
+
+    /// Whether the task's thread group has capture turned off.
+    pub fn is_paused(&self, proc_id: i32) -> bool {
+        self.paused.contains(&self.tgid(proc_id))
+    }
+
+    fn tgid(&self, proc_id: i32) -> i32 {
+        self.tgids.get(&proc_id).copied().unwrap_or(proc_id)
+    }
+
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+}
+
+/// `[start, end)` spans of a run during which capture was off; a pause
+/// never resumed runs to `i64::MAX`.
+pub fn paused_windows(db: &TraceDb) -> Result<Vec<(i64, i64)>> {
+    let mut windows = Vec::new();
+    let mut start: Option<i64> = None;
+    for e in db.query_events_by_kind("capture_control")? {
+        let Some(control) = e
+            .detail
+            .as_deref()
+            .and_then(|d| serde_json::from_str::<CaptureControl>(d).ok())
+        else {
+            continue;
+        };
+        match (control.capture.as_str(), start) {
+            ("off", None) => start = Some(e.ts),
+            ("on", Some(s)) => {
+                windows.push((s, e.ts));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(s) = start {
+        windows.push((s, i64::MAX));
+    }
+    Ok(windows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::types::{Event, FileEvent, FileOpKind, ProcessInfo};
+
+    fn start(proc_id: i32, parent: Option<i32>, tgid: i32) -> TraceEvent {
+        TraceEvent::Process(ProcessInfo {
+            proc_id,
+            parent_proc_id: parent,
+            argv: Vec::new(),
+            cwd: "/".into(),
+            start_ts: 0,
+            tgid: Some(tgid),
+        })
+    }
+
+    fn marker(proc_id: i32, capture: &str) -> TraceEvent {
+        TraceEvent::Generic(Event {
+            ts: 0,
+            proc_id,
+            kind: EventKind::CaptureControl,
+            detail: format!("{{\"capture\":\"{}\"}}", capture),
+        })
+    }
+
+    fn read(proc_id: i32) -> TraceEvent {
+        TraceEvent::File(FileEvent {
+            ts: 0,
+            proc_id,
+            op: FileOpKind::Read,
+            path: None,
+            fd: Some(3),
+            bytes: Some(1),
+            flags: None,
+            result: Some(1),
+            duration_ns: None,
+        })
+    }
+
+    #[test]
+    fn only_dups_of_the_control_fd_share_its_file() {
+        use std::os::fd::AsRawFd;
+
+        let control = std::fs::File::create("/dev/null").unwrap();
+        let dup = control.try_clone().unwrap();
+        let other = std::fs::File::create("/dev/null").unwrap();
+        let pid = std::process::id() as i32;
+        assert!(same_file(pid, dup.as_raw_fd(), control.as_raw_fd()));
+        assert!(!same_file(pid, other.as_raw_fd(), control.as_raw_fd()));
+    }
+
+    #[test]
+    fn pauses_only_the_writers_thread_group_and_its_new_children() {
+        let mut gate = CaptureGate::default();
+        for event in [
+            start(10, None, 10),
+            start(11, Some(10), 11),
+            start(12, Some(10), 10),
+        ] {
+            assert!(gate.admit(&event));
+        }
+        // Thread 12 of process 10 turns capture off for all of 10.
+        assert!(gate.admit(&marker(12, "off")));
+        assert!(!gate.admit(&read(10)));
+        assert!(!gate.admit(&read(12)));
+        // 11 was already running and keeps recording.
+        assert!(gate.admit(&read(11)));
+
+        assert!(gate.admit(&start(13, Some(12), 13)));
+        assert!(!gate.admit(&read(13)));
+        assert!(gate.admit(&marker(10, "on")));
+        assert!(gate.admit(&read(12)));
+        assert!(!gate.admit(&read(13)));
+        assert_eq!(gate.dropped(), 4);
+    }
+
+    #[test]
+    fn parses_capture_markers() {
+        assert_eq!(parse_marker(b"POE:CAPTURE OFF\n"), Some(false));
+        assert_eq!(parse_marker(b"POE:CAPTURE on"), Some(true));
+        assert_eq!(parse_marker(b"POE:CAPTURE ON\nmore"), Some(true));
+        assert_eq!(parse_marker(b"POE:CAPTURE MAYBE"), None);
+        assert_eq!(parse_marker(b"hello"), None);
+    }
+}
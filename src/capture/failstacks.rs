@@ -0,0 +1,217 @@
+use std::collections::HashMap;
+
+use nix::unistd::Pid;
+use serde::{Deserialize, Serialize};
+
+use crate::capture::arch;
+use crate::capture::tracer::read_bytes_from_process;
+use crate::events::types::{Event, EventKind, TraceEvent};
+use crate::symbols::resolver::{ResolvedSymbol, SymbolResolver};
+use crate::util::procfs::{self, MemoryMapping};
+
+/// Stacks per run; tools probing search paths fail hundreds of opens.
+const MAX_STACKS: usize = 1000;
+const MAX_FRAMES: usize = 24;
+/// How far above the stack pointer to look for return addresses.
+const SCAN_BYTES: u64 = 16 * 1024;
+/// Remembered call-site checks; the cache starts over once it holds this
+/// many, as a long run can scan millions of distinct stack words.
+const MAX_CALL_SITES: usize = 64 * 1024;
+
+/// Errors that are part of normal nonblocking or interrupted I/O rather
+/// than failures worth a stack.
+const EXPECTED_ERRNOS: [i32; 3] = [libc::EAGAIN, libc::EINPROGRESS, libc::EINTR];
+
+/// Detail of a `failure_stack` event.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FailureStack {
+    pub op: String,
+    pub errno: i64,
+    /// Innermost first, e.g. `open64+0x5b (libc.so.6)`.
+    pub frames: Vec<String>,
+}
+
+/// Walks the user stack of a thread stopped at a failing syscall exit.
+/// There are no frame pointers to rely on, so the walk scans the stack for
+/// words that point into executable mappings just past a call instruction:
+/// return addresses, with the odd stale one from a frame already popped.
+#[derive(Default)]
+pub struct FailureStacks {
+    maps: HashMap<i32, Vec<MemoryMapping>>,
+    resolver: SymbolResolver,
+    loaded: Option<i32>,
+    after_call: HashMap<(i32, u64), bool>,
+    recorded: usize,
+}
+
+impl FailureStacks {
+    pub fn capture(
+        &mut self,
+        pid: Pid,
+        tgid: i32,
+        ts: u64,
+        proc_id: i32,
+        op: &str,
+        ret: i64,
+    ) -> Option<TraceEvent> {
+        if ret >= 0 || EXPECTED_ERRNOS.contains(&(-ret as i32)) || self.recorded >= MAX_STACKS {
+            return None;
+        }
+        let (pc, sp) = arch::pc_sp(pid)?;
+        let addrs = self.walk(pid, tgid, pc, sp);
+        if addrs.is_empty() {
+            return None;
+        }
+        if self.loaded != Some(tgid) {
+            self.resolver.load_maps(self.maps.get(&tgid)?.clone());
+            self.loaded = Some(tgid);
+        }
+        let frames = addrs
+            .iter()
+            .map(|&addr| frame_label(&self.resolver.resolve(addr), addr))
+            .collect();
+        self.recorded += 1;
+        let stack = FailureStack {
+            op: op.to_string(),
+            errno: -ret,
+            frames,
+        };
+        Some(TraceEvent::Generic(Event {
+            ts,
+            proc_id,
+            kind: EventKind::FailureStack,
+            detail: serde_json::to_string(&stack).ok()?,
+        }))
+    }
+
+    /// Drops what was learned about a process's address space, which an
+    /// exec replaces.
+    pub fn forget_process(&mut self, tgid: i32) {
+        self.maps.remove(&tgid);
+        self.after_call.retain(|(t, _), _| *t != tgid);
+        if self.loaded == Some(tgid) {
+            self.loaded = None;
+        }
+    }
+
+    fn walk(&mut self, pid: Pid, tgid: i32, rip: u64, rsp: u64) -> Vec<u64> {
+        if self.mapping(tgid, rip).is_none() {
+            self.maps.remove(&tgid);
+            self.loaded = None;
+        }
+        let Some(stack_end) = self.mapping(tgid, rsp).map(|m| m.end) else {
+            return Vec::new();
+        };
+        let Some(stack) = read_bytes_from_process(
+            pid,
+            rsp,
+            stack_end.saturating_sub(rsp).min(SCAN_BYTES) as usize,
+        ) else {
+            return Vec::new();
+        };
+
+        let mut addrs = vec![rip];
+        for word in stack.chunks_exact(8) {
+            if addrs.len() >= MAX_FRAMES {
+                break;
+            }
+            let addr = u64::from_ne_bytes(word.try_into().unwrap_or_default());
+            let executable = self
+                .mapping(tgid, addr)
+                .is_some_and(|m| m.permissions.contains('x'));
+            if executable && self.follows_call(pid, tgid, addr) && addrs.last() != Some(&addr) {
+                addrs.push(addr);
+            }
+        }
+        addrs
+    }
+
+    /// The mapping holding `addr`, reading the process's maps the first
+    /// time it is asked about.
+    fn mapping(&mut self, tgid: i32, addr: u64) -> Option<&MemoryMapping> {
+        let maps = self
+            .maps
+            .entry(tgid)
+            .or_insert_with(|| procfs::read_maps(tgid).unwrap_or_default());
+        maps.iter().find(|m| addr >= m.start && addr < m.end)
+    }
+
+    fn follows_call(&mut self, pid: Pid, tgid: i32, addr: u64) -> bool {
+        if let Some(&known) = self.after_call.get(&(tgid, addr)) {
+            return known;
+        }
+        let found = addr
+            .checked_sub(CALL_BYTES as u64)
+            .and_then(|start| read_bytes_from_process(pid, start, CALL_BYTES))
+            .is_some_and(|code| is_call_site(&code));
+        if self.after_call.len() >= MAX_CALL_SITES {
+            self.after_call.clear();
+        }
+        self.after_call.insert((tgid, addr), found);
+        found
+    }
+}
+
+/// Bytes before a return address `is_call_site` looks at.
+#[cfg(target_arch = "x86_64")]
+const CALL_BYTES: usize = 7;
+#[cfg(target_arch = "aarch64")]
+const CALL_BYTES: usize = 4;
+
+/// Whether the 7 bytes before a return address end in an x86-64 call:
+/// `call rel32`, or `call` through a register, `[reg+disp8]`,
+/// `[reg+disp32]` or `[rip+disp32]`.
+#[cfg(target_arch = "x86_64")]
+fn is_call_site(code: &[u8]) -> bool {
+    let indirect =
+        |op: u8, modrm: u8, mode: u8| op == 0xff && modrm & 0x38 == 0x10 && modrm & 0xc0 == mode;
+    code.len() == 7
+        && (code[2] == 0xe8
+            || indirect(code[5], code[6], 0xc0)
+            || indirect(code[4], code[5], 0x40)
+            || indirect(code[1], code[2], 0x80)
+            || (code[1] == 0xff && code[2] == 0x15))
+}
+
+/// Whether the instruction before a return address is an aarch64 `bl` or
+/// `blr`.
+#[cfg(target_arch = "aarch64")]
+fn is_call_site(code: &[u8]) -> bool {
+    let Ok(insn) = code.try_into().map(u32::from_le_bytes) else {
+        return false;
+    };
+    insn & 0xfc00_0000 == 0x9400_0000 || insn & 0xffff_fc1f == 0xd63f_0000
+}
+
+fn frame_label(sym: &Option<ResolvedSymbol>, addr: u64) -> String {
+    match sym {
+        Some(s) if s.function.starts_with("0x") => {
+            let module = s.module.rsplit('/').next().unwrap_or(&s.module);
+            format!("{:#x} ({})", addr, module)
+        }
+        Some(s) if s.offset > 0 => format!("{}+{:#x} ({})", s.function, s.offset, s.module),
+        Some(s) => format!("{} ({})", s.function, s.module),
+        None => format!("{:#x}", addr),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(target_arch = "x86_64")]
+    fn recognizes_call_instructions_before_return_addresses() {
+        // call rel32
+        assert!(is_call_site(&[0x90, 0x90, 0xe8, 0x10, 0x20, 0x30, 0x40]));
+        // call *%rax
+        assert!(is_call_site(&[0x90, 0x90, 0x90, 0x90, 0x90, 0xff, 0xd0]));
+        // call *0x18(%rbx)
+        assert!(is_call_site(&[0x90, 0x90, 0x90, 0x90, 0xff, 0x53, 0x18]));
+        // call *0x1234(%rip)
+        assert!(is_call_site(&[0x90, 0xff, 0x15, 0x34, 0x12, 0x00, 0x00]));
+        // jmp *%rax is not a call
+        assert!(!is_call_site(&[0x90, 0x90, 0x90, 0x90, 0x90, 0xff, 0xe0]));
+        assert!(!is_call_site(&[0x90; 7]));
+    }
+}
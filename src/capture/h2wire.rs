@@ -0,0 +1,1058 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::OnceLock;
+
+use crate::capture::dbwire::{Stream, WireEvent, MAX_MESSAGE_LEN};
+
+const PREFACE: &[u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+const FRAME_HEADER: usize = 9;
+/// Header value bytes kept; paths and gRPC messages can be long.
+const MAX_VALUE_LEN: usize = 1024;
+
+const DATA: u8 = 0x0;
+const HEADERS: u8 = 0x1;
+const RST_STREAM: u8 = 0x3;
+const PUSH_PROMISE: u8 = 0x5;
+const GOAWAY: u8 = 0x7;
+const CONTINUATION: u8 = 0x9;
+
+const END_STREAM: u8 = 0x1;
+const END_HEADERS: u8 = 0x4;
+const PADDED: u8 = 0x8;
+const PRIORITY: u8 = 0x20;
+
+const REFUSED_STREAM: u32 = 0x7;
+
+/// One HTTP/2 request stream and its response.
+#[derive(Debug, Clone, PartialEq)]
+pub struct H2Call {
+    pub ts: u64,
+    pub proc_id: i32,
+    pub stream_id: u32,
+    pub method: Option<String>,
+    pub path: Option<String>,
+    pub authority: Option<String>,
+    /// The request's content-type is `application/grpc*`.
+    pub grpc: bool,
+    /// Set once the response ended or the stream was reset.
+    pub end_ts: Option<u64>,
+    pub http_status: Option<u16>,
+    pub grpc_status: Option<u32>,
+    pub grpc_message: Option<String>,
+    /// RST_STREAM error code, from either side. Streams a GOAWAY said the
+    /// server never processed count as refused.
+    pub reset: Option<u32>,
+    pub request_bytes: u64,
+    pub response_bytes: u64,
+}
+
+impl H2Call {
+    fn new(ts: u64, proc_id: i32, stream_id: u32) -> Self {
+        Self {
+            ts,
+            proc_id,
+            stream_id,
+            method: None,
+            path: None,
+            authority: None,
+            grpc: false,
+            end_ts: None,
+            http_status: None,
+            grpc_status: None,
+            grpc_message: None,
+            reset: None,
+            request_bytes: 0,
+            response_bytes: 0,
+        }
+    }
+
+    pub fn status(&self) -> &'static str {
+        if self.reset.is_some() {
+            "reset"
+        } else if self.grpc_status.is_some_and(|s| s != 0)
+            || self.http_status.is_some_and(|s| s >= 400)
+        {
+            "error"
+        } else if self.end_ts.is_none() {
+            "no_response"
+        } else {
+            "ok"
+        }
+    }
+
+    pub fn to_json(&self, peer: Option<&str>) -> serde_json::Value {
+        serde_json::json!({
+            "peer": peer,
+            "stream_id": self.stream_id,
+            "method": self.method,
+            "path": self.path,
+            "authority": self.authority,
+            "grpc": self.grpc,
+            "status": self.status(),
+            "duration_ns": self.end_ts.map(|e| e.saturating_sub(self.ts)),
+            "http_status": self.http_status,
+            "grpc_status": self.grpc_status,
+            "grpc_message": self.grpc_message,
+            "reset": self.reset,
+            "request_bytes": self.request_bytes,
+            "response_bytes": self.response_bytes,
+        })
+    }
+}
+
+pub fn grpc_code_name(code: u32) -> &'static str {
+    match code {
+        0 => "OK",
+        1 => "CANCELLED",
+        2 => "UNKNOWN",
+        3 => "INVALID_ARGUMENT",
+        4 => "DEADLINE_EXCEEDED",
+        5 => "NOT_FOUND",
+        6 => "ALREADY_EXISTS",
+        7 => "PERMISSION_DENIED",
+        8 => "RESOURCE_EXHAUSTED",
+        9 => "FAILED_PRECONDITION",
+        10 => "ABORTED",
+        11 => "OUT_OF_RANGE",
+        12 => "UNIMPLEMENTED",
+        13 => "INTERNAL",
+        14 => "UNAVAILABLE",
+        15 => "DATA_LOSS",
+        16 => "UNAUTHENTICATED",
+        _ => "?",
+    }
+}
+
+pub fn h2_error_name(code: u32) -> &'static str {
+    match code {
+        0x0 => "NO_ERROR",
+        0x1 => "PROTOCOL_ERROR",
+        0x2 => "INTERNAL_ERROR",
+        0x3 => "FLOW_CONTROL_ERROR",
+        0x4 => "SETTINGS_TIMEOUT",
+        0x5 => "STREAM_CLOSED",
+        0x6 => "FRAME_SIZE_ERROR",
+        0x7 => "REFUSED_STREAM",
+        0x8 => "CANCEL",
+        0x9 => "COMPRESSION_ERROR",
+        0xa => "CONNECT_ERROR",
+        0xb => "ENHANCE_YOUR_CALM",
+        0xc => "INADEQUATE_SECURITY",
+        0xd => "HTTP_1_1_REQUIRED",
+        _ => "?",
+    }
+}
+
+/// Cleartext HTTP/2 opens with the client connection preface; h2 over TLS
+/// never shows it and is left alone.
+pub fn looks_like_h2(data: &[u8]) -> bool {
+    data.starts_with(PREFACE)
+}
+
+const STATIC_TABLE: [(&str, &str); 61] = [
+    (":authority", ""),
+    (":method", "GET"),
+    (":method", "POST"),
+    (":path", "/"),
+    (":path", "/index.html"),
+    (":scheme", "http"),
+    (":scheme", "https"),
+    (":status", "200"),
+    (":status", "204"),
+    (":status", "206"),
+    (":status", "304"),
+    (":status", "400"),
+    (":status", "404"),
+    (":status", "500"),
+    ("accept-charset", ""),
+    ("accept-encoding", "gzip, deflate"),
+    ("accept-language", ""),
+    ("accept-ranges", ""),
+    ("accept", ""),
+    ("access-control-allow-origin", ""),
+    ("age", ""),
+    ("allow", ""),
+    ("authorization", ""),
+    ("cache-control", ""),
+    ("content-disposition", ""),
+    ("content-encoding", ""),
+    ("content-language", ""),
+    ("content-length", ""),
+    ("content-location", ""),
+    ("content-range", ""),
+    ("content-type", ""),
+    ("cookie", ""),
+    ("date", ""),
+    ("etag", ""),
+    ("expect", ""),
+    ("expires", ""),
+    ("from", ""),
+    ("host", ""),
+    ("if-match", ""),
+    ("if-modified-since", ""),
+    ("if-none-match", ""),
+    ("if-range", ""),
+    ("if-unmodified-since", ""),
+    ("last-modified", ""),
+    ("link", ""),
+    ("location", ""),
+    ("max-forwards", ""),
+    ("proxy-authenticate", ""),
+    ("proxy-authorization", ""),
+    ("range", ""),
+    ("referer", ""),
+    ("refresh", ""),
+    ("retry-after", ""),
+    ("server", ""),
+    ("set-cookie", ""),
+    ("strict-transport-security", ""),
+    ("transfer-encoding", ""),
+    ("user-agent", ""),
+    ("vary", ""),
+    ("via", ""),
+    ("www-authenticate", ""),
+];
+
+/// (code, bit length) per symbol; 256 is EOS.
+const HUFFMAN: [(u32, u8); 257] = [
+    (0x1ff8, 13),
+    (0x7fffd8, 23),
+    (0xfffffe2, 28),
+    (0xfffffe3, 28),
+    (0xfffffe4, 28),
+    (0xfffffe5, 28),
+    (0xfffffe6, 28),
+    (0xfffffe7, 28),
+    (0xfffffe8, 28),
+    (0xffffea, 24),
+    (0x3ffffffc, 30),
+    (0xfffffe9, 28),
+    (0xfffffea, 28),
+    (0x3ffffffd, 30),
+    (0xfffffeb, 28),
+    (0xfffffec, 28),
+    (0xfffffed, 28),
+    (0xfffffee, 28),
+    (0xfffffef, 28),
+    (0xffffff0, 28),
+    (0xffffff1, 28),
+    (0xffffff2, 28),
+    (0x3ffffffe, 30),
+    (0xffffff3, 28),
+    (0xffffff4, 28),
+    (0xffffff5, 28),
+    (0xffffff6, 28),
+    (0xffffff7, 28),
+    (0xffffff8, 28),
+    (0xffffff9, 28),
+    (0xffffffa, 28),
+    (0xffffffb, 28),
+    (0x14, 6),
+    (0x3f8, 10),
+    (0x3f9, 10),
+    (0xffa, 12),
+    (0x1ff9, 13),
+    (0x15, 6),
+    (0xf8, 8),
+    (0x7fa, 11),
+    (0x3fa, 10),
+    (0x3fb, 10),
+    (0xf9, 8),
+    (0x7fb, 11),
+    (0xfa, 8),
+    (0x16, 6),
+    (0x17, 6),
+    (0x18, 6),
+    (0x0, 5),
+    (0x1, 5),
+    (0x2, 5),
+    (0x19, 6),
+    (0x1a, 6),
+    (0x1b, 6),
+    (0x1c, 6),
+    (0x1d, 6),
+    (0x1e, 6),
+    (0x1f, 6),
+    (0x5c, 7),
+    (0xfb, 8),
+    (0x7ffc, 15),
+    (0x20, 6),
+    (0xffb, 12),
+    (0x3fc, 10),
+    (0x1ffa, 13),
+    (0x21, 6),
+    (0x5d, 7),
+    (0x5e, 7),
+    (0x5f, 7),
+    (0x60, 7),
+    (0x61, 7),
+    (0x62, 7),
+    (0x63, 7),
+    (0x64, 7),
+    (0x65, 7),
+    (0x66, 7),
+    (0x67, 7),
+    (0x68, 7),
+    (0x69, 7),
+    (0x6a, 7),
+    (0x6b, 7),
+    (0x6c, 7),
+    (0x6d, 7),
+    (0x6e, 7),
+    (0x6f, 7),
+    (0x70, 7),
+    (0x71, 7),
+    (0x72, 7),
+    (0xfc, 8),
+    (0x73, 7),
+    (0xfd, 8),
+    (0x1ffb, 13),
+    (0x7fff0, 19),
+    (0x1ffc, 13),
+    (0x3ffc, 14),
+    (0x22, 6),
+    (0x7ffd, 15),
+    (0x3, 5),
+    (0x23, 6),
+    (0x4, 5),
+    (0x24, 6),
+    (0x5, 5),
+    (0x25, 6),
+    (0x26, 6),
+    (0x27, 6),
+    (0x6, 5),
+    (0x74, 7),
+    (0x75, 7),
+    (0x28, 6),
+    (0x29, 6),
+    (0x2a, 6),
+    (0x7, 5),
+    (0x2b, 6),
+    (0x76, 7),
+    (0x2c, 6),
+    (0x8, 5),
+    (0x9, 5),
+    (0x2d, 6),
+    (0x77, 7),
+    (0x78, 7),
+    (0x79, 7),
+    (0x7a, 7),
+    (0x7b, 7),
+    (0x7ffe, 15),
+    (0x7fc, 11),
+    (0x3ffd, 14),
+    (0x1ffd, 13),
+    (0xffffffc, 28),
+    (0xfffe6, 20),
+    (0x3fffd2, 22),
+    (0xfffe7, 20),
+    (0xfffe8, 20),
+    (0x3fffd3, 22),
+    (0x3fffd4, 22),
+    (0x3fffd5, 22),
+    (0x7fffd9, 23),
+    (0x3fffd6, 22),
+    (0x7fffda, 23),
+    (0x7fffdb, 23),
+    (0x7fffdc, 23),
+    (0x7fffdd, 23),
+    (0x7fffde, 23),
+    (0xffffeb, 24),
+    (0x7fffdf, 23),
+    (0xffffec, 24),
+    (0xffffed, 24),
+    (0x3fffd7, 22),
+    (0x7fffe0, 23),
+    (0xffffee, 24),
+    (0x7fffe1, 23),
+    (0x7fffe2, 23),
+    (0x7fffe3, 23),
+    (0x7fffe4, 23),
+    (0x1fffdc, 21),
+    (0x3fffd8, 22),
+    (0x7fffe5, 23),
+    (0x3fffd9, 22),
+    (0x7fffe6, 23),
+    (0x7fffe7, 23),
+    (0xffffef, 24),
+    (0x3fffda, 22),
+    (0x1fffdd, 21),
+    (0xfffe9, 20),
+    (0x3fffdb, 22),
+    (0x3fffdc, 22),
+    (0x7fffe8, 23),
+    (0x7fffe9, 23),
+    (0x1fffde, 21),
+    (0x7fffea, 23),
+    (0x3fffdd, 22),
+    (0x3fffde, 22),
+    (0xfffff0, 24),
+    (0x1fffdf, 21),
+    (0x3fffdf, 22),
+    (0x7fffeb, 23),
+    (0x7fffec, 23),
+    (0x1fffe0, 21),
+    (0x1fffe1, 21),
+    (0x3fffe0, 22),
+    (0x1fffe2, 21),
+    (0x7fffed, 23),
+    (0x3fffe1, 22),
+    (0x7fffee, 23),
+    (0x7fffef, 23),
+    (0xfffea, 20),
+    (0x3fffe2, 22),
+    (0x3fffe3, 22),
+    (0x3fffe4, 22),
+    (0x7ffff0, 23),
+    (0x3fffe5, 22),
+    (0x3fffe6, 22),
+    (0x7ffff1, 23),
+    (0x3ffffe0, 26),
+    (0x3ffffe1, 26),
+    (0xfffeb, 20),
+    (0x7fff1, 19),
+    (0x3fffe7, 22),
+    (0x7ffff2, 23),
+    (0x3fffe8, 22),
+    (0x1ffffec, 25),
+    (0x3ffffe2, 26),
+    (0x3ffffe3, 26),
+    (0x3ffffe4, 26),
+    (0x7ffffde, 27),
+    (0x7ffffdf, 27),
+    (0x3ffffe5, 26),
+    (0xfffff1, 24),
+    (0x1ffffed, 25),
+    (0x7fff2, 19),
+    (0x1fffe3, 21),
+    (0x3ffffe6, 26),
+    (0x7ffffe0, 27),
+    (0x7ffffe1, 27),
+    (0x3ffffe7, 26),
+    (0x7ffffe2, 27),
+    (0xfffff2, 24),
+    (0x1fffe4, 21),
+    (0x1fffe5, 21),
+    (0x3ffffe8, 26),
+    (0x3ffffe9, 26),
+    (0xffffffd, 28),
+    (0x7ffffe3, 27),
+    (0x7ffffe4, 27),
+    (0x7ffffe5, 27),
+    (0xfffec, 20),
+    (0xfffff3, 24),
+    (0xfffed, 20),
+    (0x1fffe6, 21),
+    (0x3fffe9, 22),
+    (0x1fffe7, 21),
+    (0x1fffe8, 21),
+    (0x7ffff3, 23),
+    (0x3fffea, 22),
+    (0x3fffeb, 22),
+    (0x1ffffee, 25),
+    (0x1ffffef, 25),
+    (0xfffff4, 24),
+    (0xfffff5, 24),
+    (0x3ffffea, 26),
+    (0x7ffff4, 23),
+    (0x3ffffeb, 26),
+    (0x7ffffe6, 27),
+    (0x3ffffec, 26),
+    (0x3ffffed, 26),
+    (0x7ffffe7, 27),
+    (0x7ffffe8, 27),
+    (0x7ffffe9, 27),
+    (0x7ffffea, 27),
+    (0x7ffffeb, 27),
+    (0xffffffe, 28),
+    (0x7ffffec, 27),
+    (0x7ffffed, 27),
+    (0x7ffffee, 27),
+    (0x7ffffef, 27),
+    (0x7fffff0, 27),
+    (0x3ffffee, 26),
+    (0x3fffffff, 30),
+];
+
+fn huffman_codes() -> &'static HashMap<(u8, u32), u16> {
+    static CODES: OnceLock<HashMap<(u8, u32), u16>> = OnceLock::new();
+    CODES.get_or_init(|| {
+        HUFFMAN
+            .iter()
+            .enumerate()
+            .map(|(sym, &(code, len))| ((len, code), sym as u16))
+            .collect()
+    })
+}
+
+fn huffman_decode(data: &[u8]) -> Option<Vec<u8>> {
+    let codes = huffman_codes();
+    let mut out = Vec::new();
+    let (mut code, mut len) = (0u32, 0u8);
+    for byte in data {
+        for bit in (0..8).rev() {
+            code = (code << 1) | ((byte >> bit) & 1) as u32;
+            len += 1;
+            if len < 5 {
+                continue;
+            }
+            match codes.get(&(len, code)) {
+                Some(256) => return None,
+                Some(&sym) => {
+                    out.push(sym as u8);
+                    code = 0;
+                    len = 0;
+                }
+                None if len >= 30 => return None,
+                None => {}
+            }
+        }
+    }
+    // Padding is the most significant bits of EOS: up to seven 1 bits.
+    (len < 8 && code == (1 << len) - 1).then_some(out)
+}
+
+/// HPACK decoder for one direction of a connection.
+struct Hpack {
+    dynamic: VecDeque<(String, String)>,
+    size: usize,
+    max_size: usize,
+}
+
+impl Default for Hpack {
+    fn default() -> Self {
+        Self {
+            dynamic: VecDeque::new(),
+            size: 0,
+            max_size: 4096,
+        }
+    }
+}
+
+fn integer(data: &mut &[u8], prefix: u8) -> Option<usize> {
+    let (&first, rest) = data.split_first()?;
+    *data = rest;
+    let mask = (1u16 << prefix) as usize - 1;
+    let mut value = first as usize & mask;
+    if value < mask {
+        return Some(value);
+    }
+    let mut shift = 0;
+    loop {
+        let (&b, rest) = data.split_first()?;
+        *data = rest;
+        value = value.checked_add(((b & 0x7f) as usize).checked_shl(shift)?)?;
+        if b & 0x80 == 0 {
+            return Some(value);
+        }
+        shift += 7;
+        if shift > 28 {
+            return None;
+        }
+    }
+}
+
+fn string(data: &mut &[u8]) -> Option<String> {
+    let huffman = *data.first()? & 0x80 != 0;
+    let len = integer(data, 7)?;
+    if len > data.len() {
+        return None;
+    }
+    let (raw, rest) = data.split_at(len);
+    *data = rest;
+    let bytes = if huffman {
+        huffman_decode(raw)?
+    } else {
+        raw.to_vec()
+    };
+    Some(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+impl Hpack {
+    fn entry(&self, index: usize) -> Option<(String, String)> {
+        match index {
+            0 => None,
+            1..=61 => {
+                let (name, value) = STATIC_TABLE[index - 1];
+                Some((name.to_string(), value.to_string()))
+            }
+            _ => self.dynamic.get(index - 62).cloned(),
+        }
+    }
+
+    fn insert(&mut self, name: String, value: String) {
+        self.size += name.len() + value.len() + 32;
+        self.dynamic.push_front((name, value));
+        self.evict();
+    }
+
+    fn evict(&mut self) {
+        while self.size > self.max_size {
+            let Some((name, value)) = self.dynamic.pop_back() else {
+                break;
+            };
+            self.size -= name.len() + value.len() + 32;
+        }
+    }
+
+    /// Decodes a complete header block. None means the block was malformed
+    /// and the table can no longer be trusted.
+    fn decode(&mut self, mut data: &[u8]) -> Option<Vec<(String, String)>> {
+        let mut headers = Vec::new();
+        while let Some(&b) = data.first() {
+            if b & 0x80 != 0 {
+                let index = integer(&mut data, 7)?;
+                headers.push(self.entry(index)?);
+            } else if b & 0xe0 == 0x20 {
+                self.max_size = integer(&mut data, 5)?;
+                self.evict();
+            } else {
+                let (prefix, indexed) = if b & 0xc0 == 0x40 {
+                    (6, true)
+                } else {
+                    (4, false)
+                };
+                let name = match integer(&mut data, prefix)? {
+                    0 => string(&mut data)?,
+                    index => self.entry(index)?.0,
+                };
+                let value = string(&mut data)?;
+                if indexed {
+                    self.insert(name.clone(), value.clone());
+                }
+                headers.push((name, value));
+            }
+        }
+        Some(headers)
+    }
+}
+
+/// A header block being collected across CONTINUATION frames.
+struct PendingBlock {
+    stream_id: u32,
+    end_stream: bool,
+    data: Vec<u8>,
+}
+
+struct HeaderBlock {
+    stream_id: u32,
+    end_stream: bool,
+    fields: Vec<(String, String)>,
+}
+
+/// HPACK state and frame decoding for one direction.
+#[derive(Default)]
+struct Side {
+    hpack: Hpack,
+    block: Option<PendingBlock>,
+}
+
+impl Side {
+    /// Returns a completed header block, decoded. `ok` is cleared when the
+    /// block could not be decoded.
+    fn frame(
+        &mut self,
+        kind: u8,
+        flags: u8,
+        stream_id: u32,
+        payload: &[u8],
+        ok: &mut bool,
+    ) -> Option<HeaderBlock> {
+        let fragment = match kind {
+            HEADERS | PUSH_PROMISE => {
+                let Some(fragment) = block_fragment(kind, flags, payload) else {
+                    *ok = false;
+                    return None;
+                };
+                self.block = Some(PendingBlock {
+                    // Promised responses aren't tracked; only keep the
+                    // table in step.
+                    stream_id: if kind == HEADERS { stream_id } else { 0 },
+                    end_stream: kind == HEADERS && flags & END_STREAM != 0,
+                    data: Vec::new(),
+                });
+                fragment
+            }
+            CONTINUATION => payload,
+            _ => return None,
+        };
+        let Some(block) = self.block.as_mut() else {
+            *ok = false;
+            return None;
+        };
+        block.data.extend_from_slice(fragment);
+        if block.data.len() > MAX_MESSAGE_LEN {
+            *ok = false;
+            return None;
+        }
+        if flags & END_HEADERS == 0 {
+            return None;
+        }
+        let block = self.block.take()?;
+        match self.hpack.decode(&block.data) {
+            Some(fields) => Some(HeaderBlock {
+                stream_id: block.stream_id,
+                end_stream: block.end_stream,
+                fields,
+            }),
+            None => {
+                *ok = false;
+                None
+            }
+        }
+    }
+}
+
+/// The header block fragment of a HEADERS or PUSH_PROMISE payload, without
+/// padding, priority and promised stream fields.
+fn block_fragment(kind: u8, flags: u8, payload: &[u8]) -> Option<&[u8]> {
+    let mut p = payload;
+    let mut pad = 0;
+    if flags & PADDED != 0 {
+        pad = *p.first()? as usize;
+        p = &p[1..];
+    }
+    if kind == HEADERS && flags & PRIORITY != 0 {
+        p = p.get(5..)?;
+    }
+    if kind == PUSH_PROMISE {
+        p = p.get(4..)?;
+    }
+    p.get(..p.len().checked_sub(pad)?)
+}
+
+struct Frame {
+    kind: u8,
+    flags: u8,
+    stream_id: u32,
+    /// Full payload length; `payload` may hold only its head.
+    len: usize,
+    payload: Vec<u8>,
+    truncated: bool,
+}
+
+fn next_frame(stream: &mut Stream) -> Option<Frame> {
+    let msg = stream.next(FRAME_HEADER, |h| {
+        FRAME_HEADER + ((h[0] as usize) << 16 | (h[1] as usize) << 8 | h[2] as usize)
+    })?;
+    let h = &msg.data;
+    let len = (h[0] as usize) << 16 | (h[1] as usize) << 8 | h[2] as usize;
+    Some(Frame {
+        kind: h[3],
+        flags: h[4],
+        stream_id: u32::from_be_bytes([h[5], h[6], h[7], h[8]]) & 0x7fff_ffff,
+        len,
+        payload: h[FRAME_HEADER..].to_vec(),
+        truncated: msg.truncated,
+    })
+}
+
+fn be32(b: &[u8]) -> Option<u32> {
+    Some(u32::from_be_bytes(b.get(..4)?.try_into().ok()?))
+}
+
+/// Percent-decodes a `grpc-message` value.
+fn grpc_message(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            if let Some(b) = value
+                .get(i + 1..i + 3)
+                .and_then(|h| u8::from_str_radix(h, 16).ok())
+            {
+                out.push(b);
+                i += 3;
+                continue;
+            }
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+fn clip(value: &str) -> String {
+    if value.len() <= MAX_VALUE_LEN {
+        return value.to_string();
+    }
+    let mut end = MAX_VALUE_LEN;
+    while !value.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}...", &value[..end])
+}
+
+/// Client side of a cleartext HTTP/2 connection.
+#[derive(Default)]
+pub(crate) struct Http2 {
+    preface: bool,
+    client: Side,
+    server: Side,
+    pub(crate) streams: HashMap<u32, H2Call>,
+    /// Set when a header block was lost; the HPACK tables are then out
+    /// of step for good.
+    pub(crate) broken: bool,
+}
+
+impl Http2 {
+    pub(crate) fn client(
+        &mut self,
+        stream: &mut Stream,
+        ts: u64,
+        proc_id: i32,
+        out: &mut Vec<WireEvent>,
+    ) {
+        if !self.preface {
+            if stream.next(PREFACE.len(), |_| PREFACE.len()).is_none() {
+                return;
+            }
+            self.preface = true;
+        }
+        while let Some(frame) = next_frame(stream) {
+            if frame.truncated && frame.kind != DATA {
+                self.broken = true;
+                return;
+            }
+            let mut ok = true;
+            let block = self.client.frame(
+                frame.kind,
+                frame.flags,
+                frame.stream_id,
+                &frame.payload,
+                &mut ok,
+            );
+            if !ok {
+                self.broken = true;
+                return;
+            }
+            if let Some(HeaderBlock {
+                stream_id: id,
+                fields,
+                ..
+            }) = block
+            {
+                if id == 0 || self.streams.contains_key(&id) {
+                    // Request trailers.
+                    continue;
+                }
+                let mut call = H2Call::new(ts, proc_id, id);
+                for (name, value) in fields {
+                    match name.as_str() {
+                        ":method" => call.method = Some(value),
+                        ":path" => call.path = Some(clip(&value)),
+                        ":authority" => call.authority = Some(clip(&value)),
+                        "host" if call.authority.is_none() => call.authority = Some(clip(&value)),
+                        "content-type" => call.grpc = value.starts_with("application/grpc"),
+                        _ => {}
+                    }
+                }
+                self.streams.insert(id, call);
+                continue;
+            }
+            match frame.kind {
+                DATA => {
+                    if let Some(call) = self.streams.get_mut(&frame.stream_id) {
+                        call.request_bytes += frame.len as u64;
+                    }
+                }
+                RST_STREAM => {
+                    if let Some(mut call) = self.streams.remove(&frame.stream_id) {
+                        call.reset = be32(&frame.payload);
+                        call.end_ts = Some(ts);
+                        out.push(WireEvent::Rpc(call));
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    pub(crate) fn server(&mut self, stream: &mut Stream, ts: u64, out: &mut Vec<WireEvent>) {
+        while let Some(frame) = next_frame(stream) {
+            if frame.truncated && frame.kind != DATA {
+                self.broken = true;
+                return;
+            }
+            let mut ok = true;
+            let block = self.server.frame(
+                frame.kind,
+                frame.flags,
+                frame.stream_id,
+                &frame.payload,
+                &mut ok,
+            );
+            if !ok {
+                self.broken = true;
+                return;
+            }
+            let ended = match (frame.kind, block) {
+                (_, Some(block)) => {
+                    let id = block.stream_id;
+                    if let Some(call) = self.streams.get_mut(&id) {
+                        for (name, value) in block.fields {
+                            match name.as_str() {
+                                ":status" => call.http_status = value.parse().ok(),
+                                "grpc-status" => call.grpc_status = value.parse().ok(),
+                                "grpc-message" => {
+                                    call.grpc_message = Some(clip(&grpc_message(&value)))
+                                }
+                                _ => {}
+                            }
+                        }
+                    }
+                    block.end_stream.then_some(id)
+                }
+                (DATA, None) => {
+                    if let Some(call) = self.streams.get_mut(&frame.stream_id) {
+                        call.response_bytes += frame.len as u64;
+                    }
+                    (frame.flags & END_STREAM != 0).then_some(frame.stream_id)
+                }
+                (RST_STREAM, None) => {
+                    if let Some(call) = self.streams.get_mut(&frame.stream_id) {
+                        call.reset = be32(&frame.payload);
+                    }
+                    Some(frame.stream_id)
+                }
+                (GOAWAY, None) => {
+                    // Streams above the last one the server processed were
+                    // dropped unanswered.
+                    let last = be32(&frame.payload).unwrap_or(0) & 0x7fff_ffff;
+                    let mut refused: Vec<u32> = self
+                        .streams
+                        .keys()
+                        .copied()
+                        .filter(|id| *id > last)
+                        .collect();
+                    refused.sort();
+                    for id in refused {
+                        if let Some(mut call) = self.streams.remove(&id) {
+                            call.reset = Some(REFUSED_STREAM);
+                            call.end_ts = Some(ts);
+                            out.push(WireEvent::Rpc(call));
+                        }
+                    }
+                    None
+                }
+                _ => None,
+            };
+            if let Some(mut call) = ended.and_then(|id| self.streams.remove(&id)) {
+                call.end_ts = Some(ts);
+                out.push(WireEvent::Rpc(call));
+            }
+        }
+    }
+
+    /// Streams still open, in the order they were started.
+    pub(crate) fn close(self) -> Vec<H2Call> {
+        let mut calls: Vec<H2Call> = self.streams.into_values().collect();
+        calls.sort_by_key(|c| c.stream_id);
+        calls
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::capture::dbwire::DbConn;
+
+    fn hex(s: &str) -> Vec<u8> {
+        let s: String = s.split_whitespace().collect();
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    fn frame(kind: u8, flags: u8, id: u32, payload: &[u8]) -> Vec<u8> {
+        let len = payload.len();
+        let mut f = vec![(len >> 16) as u8, (len >> 8) as u8, len as u8, kind, flags];
+        f.extend_from_slice(&id.to_be_bytes());
+        f.extend_from_slice(payload);
+        f
+    }
+
+    /// Literal header field without indexing, new name.
+    fn literal(name: &str, value: &str) -> Vec<u8> {
+        let mut b = vec![0, name.len() as u8];
+        b.extend_from_slice(name.as_bytes());
+        b.push(value.len() as u8);
+        b.extend_from_slice(value.as_bytes());
+        b
+    }
+
+    fn calls(events: Vec<WireEvent>) -> Vec<H2Call> {
+        events
+            .into_iter()
+            .map(|e| match e {
+                WireEvent::Rpc(c) => c,
+                other => panic!("unexpected {:?}", other),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn hpack_rfc_examples() {
+        // RFC 7541 C.4.1 and C.4.2: Huffman strings and the dynamic table.
+        let mut hpack = Hpack::default();
+        let first = hpack
+            .decode(&hex("8286 8441 8cf1 e3c2 e5f2 3a6b a0ab 90f4 ff"))
+            .unwrap();
+        assert_eq!(first[3], (":authority".into(), "www.example.com".into()));
+        let second = hpack.decode(&hex("8286 84be 5886 a8eb 1064 9cbf")).unwrap();
+        assert_eq!(second[3], (":authority".into(), "www.example.com".into()));
+        assert_eq!(second[4], ("cache-control".into(), "no-cache".into()));
+        assert_eq!(hpack.size, 110);
+        assert!(hpack.decode(&hex("c0")).is_none());
+    }
+
+    #[test]
+    fn grpc_calls_statuses_and_resets() {
+        let mut conn = DbConn::new(None, false, false, true);
+        let request = |id: u32, path: &str| {
+            let mut block = hex("83 86");
+            block.extend(literal(":path", path));
+            block.extend(literal("content-type", "application/grpc"));
+            frame(HEADERS, END_HEADERS, id, &block)
+        };
+        let mut sent = PREFACE.to_vec();
+        sent.extend(frame(0x4, 0, 0, &[]));
+        sent.extend(request(1, "/pkg.Users/Get"));
+        sent.extend(frame(DATA, END_STREAM, 1, &[0; 12]));
+        sent.extend(request(3, "/pkg.Users/List"));
+        sent.extend(request(5, "/pkg.Users/Delete"));
+        sent.extend(request(7, "/pkg.Users/Watch"));
+        assert!(conn.on_send(10, 1, &sent, sent.len()).is_empty());
+
+        let mut recv = frame(HEADERS, END_HEADERS, 1, &hex("88"));
+        recv.extend(frame(DATA, 0, 1, &[0; 30]));
+        let mut trailers = literal("grpc-status", "14");
+        trailers.extend(literal("grpc-message", "backend%20unavailable"));
+        recv.extend(frame(HEADERS, END_HEADERS | END_STREAM, 1, &trailers));
+        recv.extend(frame(RST_STREAM, 0, 3, &8u32.to_be_bytes()));
+        let mut goaway = 5u32.to_be_bytes().to_vec();
+        goaway.extend(0u32.to_be_bytes());
+        recv.extend(frame(GOAWAY, 0, 0, &goaway));
+        let done = calls(conn.on_recv(20, 1, &recv, recv.len()));
+        assert_eq!(done.len(), 3);
+        assert_eq!(done[0].path.as_deref(), Some("/pkg.Users/Get"));
+        assert_eq!(done[0].method.as_deref(), Some("POST"));
+        assert!(done[0].grpc);
+        assert_eq!(done[0].http_status, Some(200));
+        assert_eq!(done[0].grpc_status, Some(14));
+        assert_eq!(done[0].grpc_message.as_deref(), Some("backend unavailable"));
+        assert_eq!((done[0].request_bytes, done[0].response_bytes), (12, 30));
+        assert_eq!(done[0].status(), "error");
+        assert_eq!(done[1].stream_id, 3);
+        assert_eq!((done[1].reset, done[1].status()), (Some(8), "reset"));
+        assert_eq!(
+            (done[2].stream_id, done[2].reset),
+            (7, Some(REFUSED_STREAM))
+        );
+
+        let open = calls(conn.close());
+        assert_eq!(open.len(), 1);
+        assert_eq!((open[0].stream_id, open[0].status()), (5, "no_response"));
+    }
+}
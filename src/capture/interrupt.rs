@@ -0,0 +1,141 @@
+use std::sync::atomic::{AtomicBool, AtomicI32, AtomicU32, Ordering};
+use std::thread;
+use std::time::Duration;
+
+use anyhow::Result;
+
+/// First SIGINT/SIGTERM poe received during the run, 0 for none.
+static RECEIVED: AtomicI32 = AtomicI32::new(0);
+static COUNT: AtomicU32 = AtomicU32::new(0);
+/// A signal sent to poe itself that still has to be passed on, 0 for none.
+static PENDING_FORWARD: AtomicI32 = AtomicI32::new(0);
+static DONE: AtomicBool = AtomicBool::new(false);
+
+/// After this many interrupts the command is killed outright, for programs
+/// that ignore SIGINT/SIGTERM.
+const FORCE_KILL_AFTER: u32 = 3;
+
+const SIGNALS: [libc::c_int; 2] = [libc::SIGINT, libc::SIGTERM];
+
+extern "C" fn on_interrupt(sig: libc::c_int, info: *mut libc::siginfo_t, _: *mut libc::c_void) {
+    let _ = RECEIVED.compare_exchange(0, sig, Ordering::SeqCst, Ordering::SeqCst);
+    COUNT.fetch_add(1, Ordering::SeqCst);
+    // ^C on a terminal (si_code SI_KERNEL) already went to the whole
+    // foreground group, the command included; only signals sent to poe
+    // itself, like a CI timeout's SIGTERM, need passing on.
+    let from_user = info.is_null() || unsafe { (*info).si_code } <= 0;
+    if from_user {
+        PENDING_FORWARD.store(sig, Ordering::SeqCst);
+    }
+}
+
+/// Keeps SIGINT/SIGTERM from killing poe mid-run: they are passed on to the
+/// traced command instead, so poe sees it exit and can still write a pack.
+pub struct InterruptGuard {
+    previous: Vec<(libc::c_int, libc::sigaction)>,
+}
+
+impl InterruptGuard {
+    /// `own_group` is whether the command leads its own process group
+    /// (`--pty`), in which case signals go to the whole group.
+    pub fn install(root_pid: i32, own_group: bool) -> Result<Self> {
+        RECEIVED.store(0, Ordering::SeqCst);
+        COUNT.store(0, Ordering::SeqCst);
+        PENDING_FORWARD.store(0, Ordering::SeqCst);
+        DONE.store(false, Ordering::SeqCst);
+
+        let mut previous = Vec::new();
+        for sig in SIGNALS {
+            unsafe {
+                let mut action: libc::sigaction = std::mem::zeroed();
+                action.sa_sigaction = on_interrupt
+                    as extern "C" fn(libc::c_int, *mut libc::siginfo_t, *mut libc::c_void)
+                    as libc::sighandler_t;
+                // SA_RESTART keeps the tracer's waitpid from failing with EINTR.
+                action.sa_flags = libc::SA_SIGINFO | libc::SA_RESTART;
+                libc::sigemptyset(&mut action.sa_mask);
+                let mut old: libc::sigaction = std::mem::zeroed();
+                if libc::sigaction(sig, &action, &mut old) == 0 {
+                    previous.push((sig, old));
+                }
+            }
+        }
+
+        let target = if own_group { -root_pid } else { root_pid };
+        thread::Builder::new()
+            .name("poe-interrupt".into())
+            .spawn(move || {
+                let mut killed = false;
+                while !DONE.load(Ordering::SeqCst) {
+                    let sig = PENDING_FORWARD.swap(0, Ordering::SeqCst);
+                    if sig != 0 {
+                        unsafe { libc::kill(target, sig) };
+                    }
+                    if !killed && COUNT.load(Ordering::SeqCst) >= FORCE_KILL_AFTER {
+                        killed = true;
+                        log::warn!("killing the command and its children");
+                        for pid in descendants(root_pid) {
+                            unsafe { libc::kill(pid, libc::SIGKILL) };
+                        }
+                        unsafe { libc::kill(root_pid, libc::SIGKILL) };
+                    }
+                    thread::sleep(Duration::from_millis(50));
+                }
+            })?;
+
+        Ok(Self { previous })
+    }
+
+    /// The first interrupt received since `install`, if any.
+    pub fn received(&self) -> Option<i32> {
+        match RECEIVED.load(Ordering::SeqCst) {
+            0 => None,
+            sig => Some(sig),
+        }
+    }
+}
+
+impl Drop for InterruptGuard {
+    fn drop(&mut self) {
+        DONE.store(true, Ordering::SeqCst);
+        for (sig, old) in &self.previous {
+            unsafe { libc::sigaction(*sig, old, std::ptr::null_mut()) };
+        }
+    }
+}
+
+/// Sends SIGTERM to the command and every process under it, for stopping
+/// a run on poe's own initiative.
+pub fn terminate(root_pid: i32) {
+    for pid in descendants(root_pid) {
+        unsafe { libc::kill(pid, libc::SIGTERM) };
+    }
+    unsafe { libc::kill(root_pid, libc::SIGTERM) };
+}
+
+/// All live descendants of `root`, from each process's parent pid.
+fn descendants(root: i32) -> Vec<i32> {
+    let parents: Vec<(i32, i32)> = std::fs::read_dir("/proc")
+        .into_iter()
+        .flatten()
+        .filter_map(|e| e.ok()?.file_name().to_str()?.parse::<i32>().ok())
+        .filter_map(|pid| {
+            let ppid = crate::util::procfs::read_status_field(pid, "PPid").ok()?;
+            Some((pid, ppid.trim().parse().ok()?))
+        })
+        .collect();
+    let mut found = vec![root];
+    let mut i = 0;
+    while i < found.len() {
+        let parent = found[i];
+        found.extend(
+            parents
+                .iter()
+                .filter(|(_, ppid)| *ppid == parent)
+                .map(|(pid, _)| *pid),
+        );
+        i += 1;
+    }
+    found.remove(0);
+    found
+}
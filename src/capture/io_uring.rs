@@ -0,0 +1,538 @@
+use std::collections::HashMap;
+
+use crate::capture::syscalls::{decode_sockaddr, SyscallEntryInfo};
+use crate::events::types::*;
+
+pub const IORING_OFF_SQ_RING: u64 = 0;
+pub const IORING_OFF_CQ_RING: u64 = 0x8000000;
+pub const IORING_OFF_SQES: u64 = 0x10000000;
+
+const IORING_SETUP_SQPOLL: u32 = 1 << 1;
+const IORING_SETUP_SQE128: u32 = 1 << 10;
+const IORING_SETUP_CQE32: u32 = 1 << 11;
+const IORING_SETUP_NO_SQARRAY: u32 = 1 << 16;
+const IORING_FEAT_SINGLE_MMAP: u32 = 1 << 0;
+
+const IOSQE_FIXED_FILE: u8 = 1 << 0;
+
+const IORING_OP_READV: u8 = 1;
+const IORING_OP_WRITEV: u8 = 2;
+const IORING_OP_READ_FIXED: u8 = 4;
+const IORING_OP_WRITE_FIXED: u8 = 5;
+const IORING_OP_SENDMSG: u8 = 9;
+const IORING_OP_RECVMSG: u8 = 10;
+const IORING_OP_ACCEPT: u8 = 13;
+const IORING_OP_CONNECT: u8 = 16;
+const IORING_OP_OPENAT: u8 = 18;
+const IORING_OP_CLOSE: u8 = 19;
+const IORING_OP_STATX: u8 = 21;
+const IORING_OP_READ: u8 = 22;
+const IORING_OP_WRITE: u8 = 23;
+const IORING_OP_SEND: u8 = 26;
+const IORING_OP_RECV: u8 = 27;
+const IORING_OP_OPENAT2: u8 = 28;
+const IORING_OP_SHUTDOWN: u8 = 34;
+const IORING_OP_RENAMEAT: u8 = 35;
+const IORING_OP_UNLINKAT: u8 = 36;
+const IORING_OP_MKDIRAT: u8 = 37;
+const IORING_OP_SYMLINKAT: u8 = 38;
+const IORING_OP_LINKAT: u8 = 39;
+const IORING_OP_SOCKET: u8 = 45;
+
+const IO_URING_PARAMS_SIZE: usize = 120;
+const MAX_ENTRIES_PER_ENTER: u32 = 4096;
+
+/// Ring geometry reported by io_uring_setup in `struct io_uring_params`.
+#[derive(Debug, Clone, Default)]
+pub struct RingParams {
+    pub sq_entries: u32,
+    pub cq_entries: u32,
+    pub flags: u32,
+    pub features: u32,
+    pub sq_head: u32,
+    pub sq_tail: u32,
+    pub sq_mask: u32,
+    pub sq_array: u32,
+    pub cq_head: u32,
+    pub cq_tail: u32,
+    pub cq_mask: u32,
+    pub cqes: u32,
+}
+
+impl RingParams {
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        if data.len() < IO_URING_PARAMS_SIZE {
+            return None;
+        }
+        let u32_at = |off: usize| u32::from_ne_bytes(data[off..off + 4].try_into().unwrap());
+
+        Some(Self {
+            sq_entries: u32_at(0),
+            cq_entries: u32_at(4),
+            flags: u32_at(8),
+            features: u32_at(20),
+            sq_head: u32_at(40),
+            sq_tail: u32_at(44),
+            sq_mask: u32_at(48),
+            sq_array: u32_at(64),
+            cq_head: u32_at(80),
+            cq_tail: u32_at(84),
+            cq_mask: u32_at(88),
+            cqes: u32_at(100),
+        })
+    }
+
+    fn sqe_size(&self) -> usize {
+        if self.flags & IORING_SETUP_SQE128 != 0 {
+            128
+        } else {
+            64
+        }
+    }
+
+    fn cqe_size(&self) -> usize {
+        if self.flags & IORING_SETUP_CQE32 != 0 {
+            32
+        } else {
+            16
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Sqe {
+    pub opcode: u8,
+    pub flags: u8,
+    pub fd: i32,
+    pub off: u64,
+    pub addr: u64,
+    pub len: u32,
+    pub op_flags: u32,
+    pub user_data: u64,
+}
+
+impl Sqe {
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        if data.len() < 64 {
+            return None;
+        }
+        let u32_at = |off: usize| u32::from_ne_bytes(data[off..off + 4].try_into().unwrap());
+        let u64_at = |off: usize| u64::from_ne_bytes(data[off..off + 8].try_into().unwrap());
+
+        Some(Self {
+            opcode: data[0],
+            flags: data[1],
+            fd: u32_at(4) as i32,
+            off: u64_at(8),
+            addr: u64_at(16),
+            len: u32_at(24),
+            op_flags: u32_at(28),
+            user_data: u64_at(32),
+        })
+    }
+}
+
+struct Ring {
+    params: RingParams,
+    sq_ring: Option<u64>,
+    cq_ring: Option<u64>,
+    sqes: Option<u64>,
+    cq_seen: Option<u32>,
+    inflight: HashMap<u64, (Sqe, SyscallEntryInfo)>,
+}
+
+/// Tracks io_uring instances per thread group so submissions and completions
+/// read from the tracee's shared rings can be turned into file/net events.
+#[derive(Default)]
+pub struct IoUringTracker {
+    rings: HashMap<(i32, i32), Ring>,
+}
+
+impl IoUringTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_ring_fd(&self, tgid: i32, fd: i32) -> bool {
+        self.rings.contains_key(&(tgid, fd))
+    }
+
+    pub fn on_setup(&mut self, tgid: i32, fd: i32, params: RingParams) {
+        if params.flags & IORING_SETUP_SQPOLL != 0 {
+            log::warn!(
+                "io_uring SQPOLL ring on fd {} -- submissions will not be decoded",
+                fd
+            );
+        }
+        self.rings.insert(
+            (tgid, fd),
+            Ring {
+                params,
+                sq_ring: None,
+                cq_ring: None,
+                sqes: None,
+                cq_seen: None,
+                inflight: HashMap::new(),
+            },
+        );
+    }
+
+    pub fn on_mmap(&mut self, tgid: i32, fd: i32, offset: u64, addr: u64) {
+        let Some(ring) = self.rings.get_mut(&(tgid, fd)) else {
+            return;
+        };
+        match offset {
+            IORING_OFF_SQ_RING => {
+                ring.sq_ring = Some(addr);
+                if ring.params.features & IORING_FEAT_SINGLE_MMAP != 0 {
+                    ring.cq_ring = Some(addr);
+                }
+            }
+            IORING_OFF_CQ_RING => ring.cq_ring = Some(addr),
+            IORING_OFF_SQES => ring.sqes = Some(addr),
+            _ => {}
+        }
+    }
+
+    pub fn on_close(&mut self, tgid: i32, fd: i32) {
+        self.rings.remove(&(tgid, fd));
+    }
+
+    pub fn forget_process(&mut self, tgid: i32) {
+        self.rings.retain(|(t, _), _| *t != tgid);
+    }
+
+    /// Called at io_uring_enter entry: decodes the SQEs the kernel is about to consume.
+    pub fn on_submit(
+        &mut self,
+        tgid: i32,
+        fd: i32,
+        ts: u64,
+        path_reader: &dyn Fn(u64) -> Option<String>,
+        addr_reader: &dyn Fn(u64, usize) -> Option<Vec<u8>>,
+    ) {
+        let Some(ring) = self.rings.get_mut(&(tgid, fd)) else {
+            return;
+        };
+        let (Some(sq_ring), Some(sqes)) = (ring.sq_ring, ring.sqes) else {
+            return;
+        };
+        let p = &ring.params;
+
+        let Some(head) = read_u32(addr_reader, sq_ring + p.sq_head as u64) else {
+            return;
+        };
+        let Some(tail) = read_u32(addr_reader, sq_ring + p.sq_tail as u64) else {
+            return;
+        };
+        let pending = tail
+            .wrapping_sub(head)
+            .min(p.sq_entries)
+            .min(MAX_ENTRIES_PER_ENTER);
+
+        for i in 0..pending {
+            let pos = head.wrapping_add(i) & p.sq_mask;
+            let idx = if p.flags & IORING_SETUP_NO_SQARRAY != 0 {
+                pos
+            } else {
+                match read_u32(addr_reader, sq_ring + p.sq_array as u64 + pos as u64 * 4) {
+                    Some(idx) => idx & p.sq_mask,
+                    None => continue,
+                }
+            };
+
+            let sqe_addr = sqes + idx as u64 * p.sqe_size() as u64;
+            let Some(sqe) = addr_reader(sqe_addr, 64).and_then(|d| Sqe::parse(&d)) else {
+                continue;
+            };
+            let info = decode_sqe(&sqe, ts, path_reader, addr_reader);
+            if !matches!(info, SyscallEntryInfo::Ignored) {
+                ring.inflight.insert(sqe.user_data, (sqe, info));
+            }
+        }
+    }
+
+    /// Called at io_uring_enter exit: drains newly posted CQEs and finalizes
+    /// the matching submissions.
+    pub fn on_complete(
+        &mut self,
+        pid: i32,
+        tgid: i32,
+        fd: i32,
+        addr_reader: &dyn Fn(u64, usize) -> Option<Vec<u8>>,
+    ) -> Vec<TraceEvent> {
+        let mut events = Vec::new();
+        let Some(ring) = self.rings.get_mut(&(tgid, fd)) else {
+            return events;
+        };
+        let Some(cq_ring) = ring.cq_ring else {
+            return events;
+        };
+        let p = &ring.params;
+
+        let Some(head) = read_u32(addr_reader, cq_ring + p.cq_head as u64) else {
+            return events;
+        };
+        let Some(tail) = read_u32(addr_reader, cq_ring + p.cq_tail as u64) else {
+            return events;
+        };
+
+        // Entries the application already reaped are still in the ring until
+        // overwritten, so read from the last tail we saw rather than from head.
+        let start = match ring.cq_seen {
+            Some(seen) if tail.wrapping_sub(seen) <= p.cq_entries => seen,
+            _ => head,
+        };
+        let count = tail.wrapping_sub(start).min(p.cq_entries);
+
+        for i in 0..count {
+            let pos = start.wrapping_add(i) & p.cq_mask;
+            let cqe_addr = cq_ring + p.cqes as u64 + pos as u64 * p.cqe_size() as u64;
+            let Some(data) = addr_reader(cqe_addr, 16) else {
+                continue;
+            };
+            if data.len() < 16 {
+                continue;
+            }
+            let user_data = u64::from_ne_bytes(data[0..8].try_into().unwrap());
+            let res = i32::from_ne_bytes(data[8..12].try_into().unwrap()) as i64;
+
+            if let Some((sqe, info)) = ring.inflight.remove(&user_data) {
+                if let Some(event) = finalize(pid, &sqe, &info, res) {
+                    events.push(event);
+                }
+            }
+        }
+        ring.cq_seen = Some(tail);
+
+        events
+    }
+}
+
+fn read_u32(reader: &dyn Fn(u64, usize) -> Option<Vec<u8>>, addr: u64) -> Option<u32> {
+    let data = reader(addr, 4)?;
+    Some(u32::from_ne_bytes(data.get(..4)?.try_into().ok()?))
+}
+
+pub fn decode_sqe(
+    sqe: &Sqe,
+    ts: u64,
+    path_reader: &dyn Fn(u64) -> Option<String>,
+    addr_reader: &dyn Fn(u64, usize) -> Option<Vec<u8>>,
+) -> SyscallEntryInfo {
+    let fd = if sqe.flags & IOSQE_FIXED_FILE != 0 {
+        None
+    } else {
+        Some(sqe.fd)
+    };
+
+    let file = |op: FileOpKind, path: Option<String>, fd: Option<i32>, flags: Option<i32>| {
+        SyscallEntryInfo::File {
+            op,
+            path,
+            fd,
+            flags,
+            ts,
+        }
+    };
+    let net = |op: NetOpKind, addr: Option<String>| SyscallEntryInfo::Net {
+        op,
+        proto: None,
+        addr,
+        ts,
+    };
+
+    match sqe.opcode {
+        IORING_OP_OPENAT => file(
+            FileOpKind::Open,
+            path_reader(sqe.addr),
+            fd,
+            Some(sqe.op_flags as i32),
+        ),
+        IORING_OP_OPENAT2 => {
+            // struct open_how { u64 flags; u64 mode; u64 resolve; }
+            let flags = addr_reader(sqe.off, 8)
+                .and_then(|d| {
+                    d.get(..8)
+                        .map(|b| u64::from_ne_bytes(b.try_into().unwrap()))
+                })
+                .map(|f| f as i32);
+            file(FileOpKind::Open, path_reader(sqe.addr), fd, flags)
+        }
+        IORING_OP_CLOSE => file(FileOpKind::Close, None, fd, None),
+        IORING_OP_READ | IORING_OP_READV | IORING_OP_READ_FIXED => {
+            file(FileOpKind::Read, None, fd, None)
+        }
+        IORING_OP_WRITE | IORING_OP_WRITEV | IORING_OP_WRITE_FIXED => {
+            file(FileOpKind::Write, None, fd, None)
+        }
+        IORING_OP_STATX => file(FileOpKind::Stat, path_reader(sqe.addr), fd, None),
+        IORING_OP_UNLINKAT => file(
+            FileOpKind::Unlink,
+            path_reader(sqe.addr),
+            fd,
+            Some(sqe.op_flags as i32),
+        ),
+        IORING_OP_MKDIRAT => file(FileOpKind::Mkdir, path_reader(sqe.addr), fd, None),
+        IORING_OP_RENAMEAT => {
+            let path = match (path_reader(sqe.addr), path_reader(sqe.off)) {
+                (Some(o), Some(n)) => Some(format!("{} -> {}", o, n)),
+                (Some(o), None) => Some(o),
+                _ => None,
+            };
+            file(FileOpKind::Rename, path, fd, None)
+        }
+        IORING_OP_SYMLINKAT => {
+            let path = match (path_reader(sqe.addr), path_reader(sqe.off)) {
+                (Some(t), Some(l)) => Some(format!("{} -> {}", l, t)),
+                (_, Some(l)) => Some(l),
+                _ => None,
+            };
+            file(FileOpKind::Symlink, path, fd, None)
+        }
+        IORING_OP_LINKAT => {
+            let path = match (path_reader(sqe.addr), path_reader(sqe.off)) {
+                (Some(o), Some(n)) => Some(format!("{} -> {}", o, n)),
+                (Some(o), None) => Some(o),
+                _ => None,
+            };
+            file(FileOpKind::Link, path, fd, None)
+        }
+
+        IORING_OP_CONNECT => net(
+            NetOpKind::Connect,
+            decode_sockaddr(sqe.addr, sqe.off as usize, addr_reader),
+        ),
+        IORING_OP_ACCEPT => net(NetOpKind::Accept, None),
+        IORING_OP_SEND | IORING_OP_SENDMSG => net(NetOpKind::Send, None),
+        IORING_OP_RECV | IORING_OP_RECVMSG => net(NetOpKind::Recv, None),
+        IORING_OP_SHUTDOWN => net(NetOpKind::Shutdown, None),
+        IORING_OP_SOCKET => SyscallEntryInfo::Net {
+            op: NetOpKind::Socket,
+            proto: Some(crate::capture::syscalls::classify_socket(
+                sqe.fd,
+                sqe.off as i32,
+            )),
+            addr: None,
+            ts,
+        },
+
+        _ => SyscallEntryInfo::Ignored,
+    }
+}
+
+pub fn finalize(pid: i32, sqe: &Sqe, info: &SyscallEntryInfo, res: i64) -> Option<TraceEvent> {
+    match info {
+        SyscallEntryInfo::File {
+            op,
+            path,
+            fd,
+            flags,
+            ts,
+        } => {
+            let bytes = match op {
+                FileOpKind::Read | FileOpKind::Write if res >= 0 => Some(res as u64),
+                _ => None,
+            };
+            let fd = match op {
+                FileOpKind::Open if res >= 0 => Some(res as i32),
+                _ => *fd,
+            };
+            Some(TraceEvent::File(FileEvent {
+                ts: *ts,
+                proc_id: pid,
+                op: *op,
+                path: path.clone(),
+                fd,
+                bytes,
+                flags: *flags,
+                result: Some(res),
+                duration_ns: None,
+            }))
+        }
+        SyscallEntryInfo::Net {
+            op,
+            proto,
+            addr,
+            ts,
+        } => {
+            let bytes = match op {
+                NetOpKind::Send | NetOpKind::Recv if res >= 0 => Some(res as u64),
+                _ => None,
+            };
+            let sqe_fd = (sqe.flags & IOSQE_FIXED_FILE == 0).then_some(sqe.fd);
+            let fd = match op {
+                NetOpKind::Accept | NetOpKind::Socket => (res >= 0).then_some(res as i32),
+                _ => sqe_fd,
+            };
+            let listen_fd = sqe_fd.filter(|_| *op == NetOpKind::Accept);
+            Some(TraceEvent::Net(NetEvent {
+                ts: *ts,
+                proc_id: pid,
+                op: *op,
+                proto: proto.clone(),
+                src: None,
+                dst: addr.clone(),
+                bytes,
+                fd,
+                result: Some(res),
+                duration_ns: None,
+                listen_fd,
+            }))
+        }
+        SyscallEntryInfo::Ignored => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sqe_bytes(opcode: u8, fd: i32, addr: u64, op_flags: u32, user_data: u64) -> Vec<u8> {
+        let mut data = vec![0u8; 64];
+        data[0] = opcode;
+        data[4..8].copy_from_slice(&fd.to_ne_bytes());
+        data[16..24].copy_from_slice(&addr.to_ne_bytes());
+        data[28..32].copy_from_slice(&op_flags.to_ne_bytes());
+        data[32..40].copy_from_slice(&user_data.to_ne_bytes());
+        data
+    }
+
+    #[test]
+    fn openat_sqe_becomes_file_open() {
+        let sqe = Sqe::parse(&sqe_bytes(IORING_OP_OPENAT, -100, 0x1000, 0o2, 7)).unwrap();
+        assert_eq!(sqe.user_data, 7);
+
+        let path_reader = |addr: u64| (addr == 0x1000).then(|| "/etc/hosts".to_string());
+        let addr_reader = |_: u64, _: usize| None;
+        let info = decode_sqe(&sqe, 42, &path_reader, &addr_reader);
+
+        let Some(TraceEvent::File(event)) = finalize(1, &sqe, &info, 5) else {
+            panic!("expected file event");
+        };
+        assert_eq!(event.op, FileOpKind::Open);
+        assert_eq!(event.path.as_deref(), Some("/etc/hosts"));
+        assert_eq!(event.fd, Some(5));
+        assert_eq!(event.ts, 42);
+    }
+
+    #[test]
+    fn recv_sqe_reports_bytes() {
+        let sqe = Sqe::parse(&sqe_bytes(IORING_OP_RECV, 9, 0, 0, 1)).unwrap();
+        let info = decode_sqe(&sqe, 0, &|_| None, &|_, _| None);
+
+        let Some(TraceEvent::Net(event)) = finalize(1, &sqe, &info, 128) else {
+            panic!("expected net event");
+        };
+        assert_eq!(event.op, NetOpKind::Recv);
+        assert_eq!(event.fd, Some(9));
+        assert_eq!(event.bytes, Some(128));
+    }
+
+    #[test]
+    fn unknown_opcode_ignored() {
+        let sqe = Sqe::parse(&sqe_bytes(0, 0, 0, 0, 0)).unwrap();
+        let info = decode_sqe(&sqe, 0, &|_| None, &|_, _| None);
+        assert!(matches!(info, SyscallEntryInfo::Ignored));
+    }
+}
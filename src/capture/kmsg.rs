@@ -0,0 +1,176 @@
+use std::fs::File;
+use std::io::{ErrorKind, Read, Seek, SeekFrom};
+use std::os::unix::fs::OpenOptionsExt;
+use std::sync::OnceLock;
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::events::types::{Event, EventKind};
+
+/// Kernel messages kept per run; a flood of them says nothing the first
+/// ones don't.
+const MAX_RECORDS: usize = 200;
+/// debug (7) lines are left out.
+const MAX_LEVEL: u8 = 6;
+
+const LEVELS: [&str; 8] = [
+    "emerg", "alert", "crit", "err", "warning", "notice", "info", "debug",
+];
+
+/// Detail of a `kernel_log` event: one `/dev/kmsg` record logged while the
+/// command ran.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct KernelRecord {
+    pub level: String,
+    /// segfault, oom_kill, io_error or syn_flood for the messages explain
+    /// knows about.
+    pub category: Option<String>,
+    /// The process the message is about, where it names one.
+    pub pid: Option<i32>,
+    pub message: String,
+}
+
+fn rules() -> &'static [(&'static str, Regex)] {
+    static RULES: OnceLock<Vec<(&str, Regex)>> = OnceLock::new();
+    RULES.get_or_init(|| {
+        [
+            (
+                "segfault",
+                r"\[(\d+)\]:? (?:segfault at|general protection|trap )",
+            ),
+            ("oom_kill", r"Killed process (\d+)"),
+            (
+                "io_error",
+                r"(?i)\bI/O error\b|EXT4-fs error|XFS \(\S+\): .*error|critical medium error",
+            ),
+            ("syn_flood", r"Possible SYN flooding on port"),
+        ]
+        .into_iter()
+        .map(|(category, re)| (category, Regex::new(re).unwrap()))
+        .collect()
+    })
+}
+
+/// Parses a record, `<prio>,<seq>,<usec>,<flags>;<message>`, into its
+/// monotonic timestamp in ns and what it says. Continuation lines with
+/// device properties are dropped.
+pub fn parse_record(record: &str) -> Option<(u64, KernelRecord)> {
+    let (prefix, rest) = record.split_once(';')?;
+    let mut fields = prefix.split(',');
+    let prio: u32 = fields.next()?.parse().ok()?;
+    let _seq = fields.next()?;
+    let usec: u64 = fields.next()?.parse().ok()?;
+    let level = (prio & 7) as u8;
+    if level > MAX_LEVEL {
+        return None;
+    }
+    let message = rest.lines().next().unwrap_or("").trim_end().to_string();
+    let (category, pid) = rules()
+        .iter()
+        .find_map(|(category, re)| {
+            let caps = re.captures(&message)?;
+            let pid = caps.get(1).and_then(|p| p.as_str().parse().ok());
+            Some((Some(category.to_string()), pid))
+        })
+        .unwrap_or((None, None));
+    Some((
+        usec * 1000,
+        KernelRecord {
+            level: LEVELS[level as usize].to_string(),
+            category,
+            pid,
+            message,
+        },
+    ))
+}
+
+/// The kernel log from the point it was opened. Opened before the command
+/// starts and drained once it ends, so segfault, OOM and I/O error lines
+/// about the run land in the pack. Needs read access to `/dev/kmsg`, which
+/// `kernel.dmesg_restrict` can take away from non-root users.
+pub struct KernelLog {
+    file: File,
+}
+
+impl KernelLog {
+    pub fn open() -> Option<Self> {
+        let mut file = std::fs::OpenOptions::new()
+            .read(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open("/dev/kmsg")
+            .map_err(|e| log::debug!("kernel log not captured: /dev/kmsg: {}", e))
+            .ok()?;
+        // Only what is logged from now on.
+        file.seek(SeekFrom::End(0)).ok()?;
+        Some(Self { file })
+    }
+
+    /// Events for the records logged since `open`, stamped relative to
+    /// `base_ts` like the rest of the trace.
+    pub fn drain(&mut self, base_ts: u64, root_pid: i32) -> Vec<Event> {
+        let mut events = Vec::new();
+        let mut buf = vec![0u8; 16 * 1024];
+        while events.len() < MAX_RECORDS {
+            // Each read returns one record.
+            let n = match self.file.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => n,
+                // Records were overwritten before they were read.
+                Err(e) if e.raw_os_error() == Some(libc::EPIPE) => continue,
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    log::debug!("reading /dev/kmsg: {}", e);
+                    break;
+                }
+            };
+            let Some((ts, record)) = parse_record(&String::from_utf8_lossy(&buf[..n])) else {
+                continue;
+            };
+            events.push(Event {
+                ts: ts.saturating_sub(base_ts),
+                proc_id: record.pid.unwrap_or(root_pid),
+                kind: EventKind::KernelLog,
+                detail: serde_json::to_string(&record).unwrap_or_default(),
+            });
+        }
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_and_classifies_records() {
+        let (ts, segv) = parse_record(
+            "6,1234,5000123,-;python3[4321]: segfault at 0 ip 00007f2a sp 00007ffd error 4 in libfoo.so[7f2a+1000]\n SUBSYSTEM=x\n",
+        )
+        .unwrap();
+        assert_eq!(ts, 5_000_123_000);
+        assert_eq!(segv.level, "info");
+        assert_eq!(segv.category.as_deref(), Some("segfault"));
+        assert_eq!(segv.pid, Some(4321));
+        assert!(segv.message.ends_with("in libfoo.so[7f2a+1000]"));
+
+        let (_, oom) = parse_record(
+            "3,9,1,-;Memory cgroup out of memory: Killed process 77 (node) total-vm:1024kB",
+        )
+        .unwrap();
+        assert_eq!((oom.level.as_str(), oom.pid), ("err", Some(77)));
+        assert_eq!(oom.category.as_deref(), Some("oom_kill"));
+
+        let (_, flood) = parse_record(
+            "6,10,2,-;TCP: request_sock_TCP: Possible SYN flooding on port 8080. Sending cookies.",
+        )
+        .unwrap();
+        assert_eq!(flood.category.as_deref(), Some("syn_flood"));
+        let (_, io) =
+            parse_record("3,11,3,-;blk_update_request: I/O error, dev sda, sector 2048").unwrap();
+        assert_eq!(io.category.as_deref(), Some("io_error"));
+
+        assert!(parse_record("7,12,4,-;debug chatter").is_none());
+        assert!(parse_record("garbage").is_none());
+    }
+}
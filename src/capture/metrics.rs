@@ -0,0 +1,154 @@
+use std::time::Duration;
+
+use crate::pack::summary::CaptureMetrics;
+
+/// Counters kept by the db writer thread.
+#[derive(Debug, Default, Clone)]
+pub struct WriterStats {
+    pub events: u64,
+    /// Most events found already queued when the writer woke up.
+    pub backlog_peak: u64,
+    pub batches: u64,
+    pub write_time: Duration,
+    pub write_max: Duration,
+    /// Events in batches the db failed to store.
+    pub dropped_events: u64,
+    /// Events left out while capture was turned off.
+    pub paused_events: u64,
+}
+
+impl WriterStats {
+    pub fn record_backlog(&mut self, queued: u64) {
+        self.backlog_peak = self.backlog_peak.max(queued);
+    }
+
+    pub fn record_batch(&mut self, events: usize, elapsed: Duration, stored: bool) {
+        self.events += events as u64;
+        self.batches += 1;
+        self.write_time += elapsed;
+        self.write_max = self.write_max.max(elapsed);
+        if !stored {
+            self.dropped_events += events as u64;
+        }
+    }
+}
+
+/// CPU time in ms for `RUSAGE_SELF`, `RUSAGE_CHILDREN` or `RUSAGE_THREAD`.
+pub fn cpu_time_ms(who: libc::c_int) -> u64 {
+    let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+    if unsafe { libc::getrusage(who, &mut usage) } != 0 {
+        return 0;
+    }
+    let ms = |tv: libc::timeval| tv.tv_sec as u64 * 1000 + tv.tv_usec as u64 / 1000;
+    ms(usage.ru_utime) + ms(usage.ru_stime)
+}
+
+/// CPU clocks read when the run starts, to take the run's share out of
+/// the process totals.
+pub struct CpuClocks {
+    poe_ms: u64,
+    children_ms: u64,
+}
+
+impl CpuClocks {
+    pub fn start() -> Self {
+        Self {
+            poe_ms: cpu_time_ms(libc::RUSAGE_SELF),
+            children_ms: cpu_time_ms(libc::RUSAGE_CHILDREN),
+        }
+    }
+
+    pub fn finish(
+        &self,
+        writer: &WriterStats,
+        tracer_cpu_ms: u64,
+        duration_ms: u64,
+    ) -> CaptureMetrics {
+        let secs = duration_ms.max(1) as f64 / 1000.0;
+        CaptureMetrics {
+            events: writer.events,
+            events_per_sec: (writer.events as f64 / secs).round(),
+            backlog_peak: writer.backlog_peak,
+            db_batches: writer.batches,
+            db_write_ms: writer.write_time.as_millis() as u64,
+            db_write_max_ms: writer.write_max.as_millis() as u64,
+            dropped_events: writer.dropped_events,
+            paused_events: writer.paused_events,
+            tracer_cpu_ms,
+            poe_cpu_ms: cpu_time_ms(libc::RUSAGE_SELF).saturating_sub(self.poe_ms),
+            workload_cpu_ms: cpu_time_ms(libc::RUSAGE_CHILDREN).saturating_sub(self.children_ms),
+        }
+    }
+}
+
+/// poe using more than this share of one CPU, on average over the run,
+/// likely slowed the workload down...
+const POE_CPU_WARN_RATIO: f64 = 0.5;
+/// ...unless the run was too short for the ratio to mean much.
+const MIN_RUN_MS: u64 = 200;
+/// Queued events hold memory and delay the realtime diff.
+const BACKLOG_WARN: u64 = 100_000;
+
+/// Why the captured run may not behave like an untraced one, for explain.
+pub fn overhead_warnings(metrics: &CaptureMetrics, duration_ms: u64) -> Vec<String> {
+    let mut warnings = Vec::new();
+    if metrics.dropped_events > 0 {
+        warnings.push(format!(
+            "{} events were dropped; the trace is incomplete",
+            metrics.dropped_events
+        ));
+    }
+    if metrics.paused_events > 0 {
+        warnings.push(format!(
+            "{} events were left out while the program had capture turned off",
+            metrics.paused_events
+        ));
+    }
+    if duration_ms >= MIN_RUN_MS {
+        let ratio = metrics.poe_cpu_ms as f64 / duration_ms as f64;
+        if ratio > POE_CPU_WARN_RATIO {
+            warnings.push(format!(
+                "poe used {:.0}% of a CPU while tracing ({} events/s); timings are likely inflated",
+                ratio * 100.0,
+                metrics.events_per_sec
+            ));
+        }
+    }
+    if metrics.backlog_peak >= BACKLOG_WARN {
+        warnings.push(format!(
+            "up to {} events were queued for the trace db; the capture fell behind the workload",
+            metrics.backlog_peak
+        ));
+    }
+    warnings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn warns_on_drops_cpu_and_backlog() {
+        let quiet = CaptureMetrics {
+            events: 1000,
+            events_per_sec: 1000.0,
+            poe_cpu_ms: 100,
+            ..Default::default()
+        };
+        assert!(overhead_warnings(&quiet, 1000).is_empty());
+
+        let busy = CaptureMetrics {
+            poe_cpu_ms: 900,
+            dropped_events: 12,
+            backlog_peak: 250_000,
+            ..quiet.clone()
+        };
+        let warnings = overhead_warnings(&busy, 1000);
+        assert_eq!(warnings.len(), 3);
+        assert!(warnings[0].starts_with("12 events were dropped"));
+        assert!(warnings[1].starts_with("poe used 90% of a CPU"));
+
+        // Too short a run to judge CPU share.
+        assert_eq!(overhead_warnings(&busy, 50).len(), 2);
+    }
+}
@@ -1,5 +1,19 @@
+pub mod arch;
+pub mod backend;
+pub mod cachewire;
+pub mod control;
+pub mod dbwire;
+pub mod failstacks;
+pub mod h2wire;
+pub mod interrupt;
+pub mod io_uring;
+pub mod kmsg;
+pub mod metrics;
+pub mod ports;
+pub mod pty;
 pub mod runner;
 pub mod stacks;
 pub mod stdio;
 pub mod syscalls;
 pub mod tracer;
+pub mod unotify;
@@ -0,0 +1,205 @@
+use std::collections::{HashMap, HashSet};
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use serde::{Deserialize, Serialize};
+
+use crate::events::types::{Event, EventKind, NetOpKind, TraceEvent};
+use crate::util::procfs;
+
+/// Snapshots per run; a server retrying the same bind in a loop only needs
+/// the first.
+const MAX_SNAPSHOTS: usize = 5;
+
+const PROTOS: [&str; 4] = ["tcp", "tcp6", "udp", "udp6"];
+
+/// A socket on the contested port, from `/proc/net`, with the process that
+/// has it open when that can be found.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PortHolder {
+    pub proto: String,
+    pub local: String,
+    /// Kernel socket state, e.g. `LISTEN` or `TIME_WAIT`.
+    pub state: String,
+    pub pid: Option<i32>,
+    pub command: Option<String>,
+}
+
+/// Detail of a `port_holders` event.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PortSnapshot {
+    pub addr: String,
+    pub port: u16,
+    pub holders: Vec<PortHolder>,
+}
+
+/// Watches the event stream for binds failing with EADDRINUSE and records
+/// who holds the port while the conflict is fresh, since the holder may be
+/// outside the traced tree or gone by the time the run ends.
+#[derive(Default)]
+pub struct PortWatcher {
+    seen: HashSet<u16>,
+}
+
+impl PortWatcher {
+    pub fn on_event(&mut self, event: &TraceEvent) -> Option<TraceEvent> {
+        let TraceEvent::Net(net) = event else {
+            return None;
+        };
+        if net.op != NetOpKind::Bind || net.result != Some(-(libc::EADDRINUSE as i64)) {
+            return None;
+        }
+        let addr = net.dst.clone()?;
+        let port: u16 = addr.rsplit(':').next()?.parse().ok()?;
+        if self.seen.len() >= MAX_SNAPSHOTS || !self.seen.insert(port) {
+            return None;
+        }
+        let snapshot = PortSnapshot {
+            addr,
+            port,
+            holders: holders(port),
+        };
+        Some(TraceEvent::Generic(Event {
+            ts: net.ts,
+            proc_id: net.proc_id,
+            kind: EventKind::PortHolders,
+            detail: serde_json::to_string(&snapshot).ok()?,
+        }))
+    }
+}
+
+/// Sockets bound to `port` on this host and their owning processes.
+pub fn holders(port: u16) -> Vec<PortHolder> {
+    let mut found: Vec<(PortHolder, u64)> = Vec::new();
+    for proto in PROTOS {
+        let Ok(table) = std::fs::read_to_string(format!("/proc/net/{}", proto)) else {
+            continue;
+        };
+        found.extend(
+            table
+                .lines()
+                .skip(1)
+                .filter_map(|line| parse_socket_line(proto, line))
+                .filter(|(_, p, _, _)| *p == port)
+                .map(|(local, _, state, inode)| {
+                    let holder = PortHolder {
+                        proto: proto.trim_end_matches('6').into(),
+                        local,
+                        state,
+                        pid: None,
+                        command: None,
+                    };
+                    (holder, inode)
+                }),
+        );
+    }
+
+    let inodes: HashSet<u64> = found.iter().map(|(_, i)| *i).filter(|i| *i != 0).collect();
+    let owners = socket_owners(&inodes);
+    found
+        .into_iter()
+        .map(|(mut holder, inode)| {
+            if let Some(&pid) = owners.get(&inode) {
+                holder.pid = Some(pid);
+                holder.command = procfs::read_cmdline(pid).ok().map(|argv| argv.join(" "));
+            }
+            holder
+        })
+        .collect()
+}
+
+/// `(local address, port, state, inode)` from a `/proc/net/{tcp,udp}[6]` row.
+fn parse_socket_line(proto: &str, line: &str) -> Option<(String, u16, String, u64)> {
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    let (ip_hex, port_hex) = fields.get(1)?.split_once(':')?;
+    let port = u16::from_str_radix(port_hex, 16).ok()?;
+    let state = u8::from_str_radix(fields.get(3)?, 16).ok()?;
+    let inode = fields.get(9)?.parse().ok()?;
+    let ip = decode_ip(ip_hex)?;
+    let local = if proto.ends_with('6') {
+        format!("[{}]:{}", ip, port)
+    } else {
+        format!("{}:{}", ip, port)
+    };
+    // UDP reuses TCP_CLOSE (7) for bound sockets.
+    let state = match (proto.starts_with("udp"), state) {
+        (true, 7) => "BOUND",
+        (_, 1) => "ESTABLISHED",
+        (_, 2) => "SYN_SENT",
+        (_, 3) => "SYN_RECV",
+        (_, 4) => "FIN_WAIT1",
+        (_, 5) => "FIN_WAIT2",
+        (_, 6) => "TIME_WAIT",
+        (_, 8) => "CLOSE_WAIT",
+        (_, 9) => "LAST_ACK",
+        (_, 10) => "LISTEN",
+        (_, 11) => "CLOSING",
+        _ => "CLOSE",
+    };
+    Some((local, port, state.into(), inode))
+}
+
+/// The kernel prints addresses as native-endian 32-bit words.
+fn decode_ip(hex: &str) -> Option<String> {
+    let words: Option<Vec<u32>> = (0..hex.len() / 8)
+        .map(|i| u32::from_str_radix(&hex[i * 8..i * 8 + 8], 16).ok())
+        .collect();
+    let bytes: Vec<u8> = words?.iter().flat_map(|w| w.to_ne_bytes()).collect();
+    match bytes.len() {
+        4 => Some(Ipv4Addr::new(bytes[0], bytes[1], bytes[2], bytes[3]).to_string()),
+        16 => {
+            let octets: [u8; 16] = bytes.try_into().ok()?;
+            Some(Ipv6Addr::from(octets).to_string())
+        }
+        _ => None,
+    }
+}
+
+/// Socket inode -> a pid with it open, from every readable `/proc/*/fd`.
+fn socket_owners(inodes: &HashSet<u64>) -> HashMap<u64, i32> {
+    let mut owners = HashMap::new();
+    if inodes.is_empty() {
+        return owners;
+    }
+    let pids = std::fs::read_dir("/proc")
+        .into_iter()
+        .flatten()
+        .filter_map(|e| e.ok()?.file_name().to_str()?.parse::<i32>().ok());
+    for pid in pids {
+        let Ok(fds) = std::fs::read_dir(format!("/proc/{}/fd", pid)) else {
+            continue;
+        };
+        for fd in fds.flatten() {
+            let Ok(target) = std::fs::read_link(fd.path()) else {
+                continue;
+            };
+            let inode = target
+                .to_str()
+                .and_then(|t| t.strip_prefix("socket:["))
+                .and_then(|t| t.strip_suffix(']'))
+                .and_then(|t| t.parse::<u64>().ok());
+            if let Some(inode) = inode.filter(|i| inodes.contains(i)) {
+                owners.entry(inode).or_insert(pid);
+            }
+        }
+    }
+    owners
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_proc_net_rows() {
+        let v4 = "   1: 0100007F:1F90 00000000:0000 0A 00000000:00000000 00:00000000 00000000  1000        0 4242 1 0000000000000000 100 0 0 10 0";
+        assert_eq!(
+            parse_socket_line("tcp", v4),
+            Some(("127.0.0.1:8080".into(), 8080, "LISTEN".into(), 4242))
+        );
+        let v6 = "   0: 00000000000000000000000001000000:1F90 00000000000000000000000000000000:0000 06 00000000:00000000 00:00000000 00000000     0        0 0 1 0000000000000000";
+        assert_eq!(
+            parse_socket_line("tcp6", v6),
+            Some(("[::1]:8080".into(), 8080, "TIME_WAIT".into(), 0))
+        );
+    }
+}
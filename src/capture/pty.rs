@@ -0,0 +1,261 @@
+use std::os::unix::io::RawFd;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use anyhow::Result;
+
+use crate::capture::stdio::{StdinPipe, StdioPipes};
+
+/// Set by the SIGWINCH handler, which can only reach statics.
+static WINCH_PENDING: AtomicBool = AtomicBool::new(false);
+
+struct Pty {
+    master: RawFd,
+    slave: RawFd,
+}
+
+fn open_pty(winsize: Option<&libc::winsize>) -> Result<Pty> {
+    let (mut master, mut slave) = (0, 0);
+    let ws_ptr = winsize.map_or(std::ptr::null(), |w| w as *const libc::winsize);
+    let rc = unsafe {
+        libc::openpty(
+            &mut master,
+            &mut slave,
+            std::ptr::null_mut(),
+            std::ptr::null(),
+            ws_ptr,
+        )
+    };
+    if rc != 0 {
+        anyhow::bail!("openpty failed: {}", std::io::Error::last_os_error());
+    }
+    for fd in [master, slave] {
+        set_cloexec(fd);
+    }
+    Ok(Pty { master, slave })
+}
+
+fn set_cloexec(fd: RawFd) {
+    unsafe {
+        let flags = libc::fcntl(fd, libc::F_GETFD);
+        if flags >= 0 {
+            libc::fcntl(fd, libc::F_SETFD, flags | libc::FD_CLOEXEC);
+        }
+    }
+}
+
+fn dup_cloexec(fd: RawFd) -> Result<RawFd> {
+    let new_fd = unsafe { libc::fcntl(fd, libc::F_DUPFD_CLOEXEC, 0) };
+    if new_fd < 0 {
+        anyhow::bail!("dup failed: {}", std::io::Error::last_os_error());
+    }
+    Ok(new_fd)
+}
+
+/// Window size of whichever of poe's own stdio streams is a terminal.
+fn terminal_winsize() -> Option<libc::winsize> {
+    for fd in [libc::STDOUT_FILENO, libc::STDERR_FILENO, libc::STDIN_FILENO] {
+        let mut ws: libc::winsize = unsafe { std::mem::zeroed() };
+        if unsafe { libc::ioctl(fd, libc::TIOCGWINSZ, &mut ws) } == 0 && ws.ws_col > 0 {
+            return Some(ws);
+        }
+    }
+    None
+}
+
+/// Pseudo terminals for the child: stdin and stdout share one pty, stderr
+/// gets its own so the two output streams stay separately captured.
+pub struct PtyPipes {
+    pub stdio: StdioPipes,
+    pub stdin: StdinPipe,
+    masters: [RawFd; 2],
+}
+
+pub fn create_pty_pipes() -> Result<PtyPipes> {
+    let winsize = terminal_winsize();
+    let out = open_pty(winsize.as_ref())?;
+    let err = open_pty(winsize.as_ref())?;
+
+    Ok(PtyPipes {
+        stdin: StdinPipe {
+            child_stdin_read: dup_cloexec(out.slave)?,
+            parent_stdin_write: dup_cloexec(out.master)?,
+        },
+        stdio: StdioPipes {
+            parent_stdout_read: out.master,
+            child_stdout_write: out.slave,
+            parent_stderr_read: err.master,
+            child_stderr_write: err.slave,
+        },
+        masters: [out.master, err.master],
+    })
+}
+
+extern "C" fn on_sigwinch(_: libc::c_int) {
+    WINCH_PENDING.store(true, Ordering::Relaxed);
+}
+
+impl PtyPipes {
+    /// Copies poe's terminal size onto the child's ptys whenever poe gets
+    /// SIGWINCH; the kernel then delivers SIGWINCH to the child's foreground group.
+    pub fn forward_winsize(&self) -> Result<WinsizeForwarder> {
+        // The relays close the masters when the child is gone; resize
+        // through our own duplicates so a reused fd number is never hit.
+        let masters = [dup_cloexec(self.masters[0])?, dup_cloexec(self.masters[1])?];
+        let done = Arc::new(AtomicBool::new(false));
+        unsafe {
+            libc::signal(
+                libc::SIGWINCH,
+                on_sigwinch as extern "C" fn(libc::c_int) as libc::sighandler_t,
+            );
+        }
+
+        let thread_done = done.clone();
+        let handle = thread::Builder::new()
+            .name("poe-winch".into())
+            .spawn(move || {
+                while !thread_done.load(Ordering::Relaxed) {
+                    if WINCH_PENDING.swap(false, Ordering::Relaxed) {
+                        if let Some(ws) = terminal_winsize() {
+                            for fd in masters {
+                                unsafe { libc::ioctl(fd, libc::TIOCSWINSZ, &ws) };
+                            }
+                        }
+                    }
+                    thread::sleep(Duration::from_millis(50));
+                }
+            })?;
+        Ok(WinsizeForwarder {
+            done,
+            handle: Some(handle),
+            masters,
+        })
+    }
+}
+
+/// The running SIGWINCH forwarder. Dropping it stops and joins the thread,
+/// then closes its duplicates of the masters.
+pub struct WinsizeForwarder {
+    done: Arc<AtomicBool>,
+    handle: Option<thread::JoinHandle<()>>,
+    masters: [RawFd; 2],
+}
+
+impl Drop for WinsizeForwarder {
+    fn drop(&mut self) {
+        unsafe { libc::signal(libc::SIGWINCH, libc::SIG_DFL) };
+        self.done.store(true, Ordering::Relaxed);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+        for fd in self.masters {
+            nix::unistd::close(fd).ok();
+        }
+    }
+}
+
+/// Puts poe's own terminal into raw mode so keystrokes (including ^C) reach
+/// the child's pty untouched. Restores the previous settings on drop.
+pub struct RawModeGuard {
+    fd: RawFd,
+    original: Option<libc::termios>,
+}
+
+impl RawModeGuard {
+    pub fn enable() -> Self {
+        Self::enable_on(libc::STDIN_FILENO)
+    }
+
+    fn enable_on(fd: RawFd) -> Self {
+        if unsafe { libc::isatty(fd) } != 1 {
+            return Self { fd, original: None };
+        }
+        let mut original: libc::termios = unsafe { std::mem::zeroed() };
+        if unsafe { libc::tcgetattr(fd, &mut original) } != 0 {
+            return Self { fd, original: None };
+        }
+        let mut raw = original;
+        unsafe {
+            libc::cfmakeraw(&mut raw);
+            libc::tcsetattr(fd, libc::TCSANOW, &raw);
+        }
+        Self {
+            fd,
+            original: Some(original),
+        }
+    }
+}
+
+impl Drop for RawModeGuard {
+    fn drop(&mut self) {
+        if let Some(ref original) = self.original {
+            unsafe { libc::tcsetattr(self.fd, libc::TCSANOW, original) };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::capture::stdio::{StdinCapture, StdinMode};
+    use std::fs::File;
+    use std::io::Read;
+    use std::os::unix::io::FromRawFd;
+    use std::sync::mpsc;
+
+    fn lflag(fd: RawFd) -> libc::tcflag_t {
+        let mut termios: libc::termios = unsafe { std::mem::zeroed() };
+        assert_eq!(unsafe { libc::tcgetattr(fd, &mut termios) }, 0);
+        termios.c_lflag
+    }
+
+    #[test]
+    fn raw_mode_is_restored_on_drop() {
+        let pty = open_pty(None).unwrap();
+        let before = lflag(pty.slave);
+        assert_ne!(before & libc::ICANON, 0);
+
+        let guard = RawModeGuard::enable_on(pty.slave);
+        assert_eq!(lflag(pty.slave) & (libc::ICANON | libc::ECHO), 0);
+        drop(guard);
+        assert_eq!(lflag(pty.slave), before);
+
+        for fd in [pty.master, pty.slave] {
+            nix::unistd::close(fd).ok();
+        }
+    }
+
+    #[test]
+    fn replayed_stdin_ends_in_eof_on_the_pty() {
+        let pipes = create_pty_pipes().unwrap();
+        let (tx, _rx) = mpsc::channel();
+        let capture = StdinCapture::start(
+            &pipes.stdin,
+            StdinMode::Replay(b"hello\n".to_vec()),
+            0,
+            tx,
+            0,
+            1024,
+            None,
+        )
+        .unwrap();
+
+        let mut child_stdin = unsafe { File::from_raw_fd(pipes.stdio.child_stdout_write) };
+        let mut buf = [0u8; 64];
+        let n = child_stdin.read(&mut buf).unwrap();
+        assert_eq!(&buf[..n], b"hello\n");
+        // The relay's VEOF at the start of a line reads as end of file.
+        assert_eq!(child_stdin.read(&mut buf).unwrap(), 0);
+        assert_eq!(capture.finish(), b"hello\n");
+
+        for fd in [
+            pipes.stdio.parent_stdout_read,
+            pipes.stdio.parent_stderr_read,
+            pipes.stdio.child_stderr_write,
+        ] {
+            nix::unistd::close(fd).ok();
+        }
+    }
+}
@@ -1,19 +1,31 @@
+use std::collections::BTreeMap;
+use std::os::unix::io::AsRawFd;
 use std::path::{Path, PathBuf};
-use std::sync::{mpsc, Arc};
+use std::sync::{mpsc, Arc, Mutex};
 use std::thread;
 use std::time::Duration;
 
 use anyhow::Result;
 
 use crate::build::instrument;
+use crate::capture::backend::{ActiveTracer, Backend};
+use crate::capture::control::{CaptureGate, CONTROL_FD_ENV};
+use crate::capture::interrupt::InterruptGuard;
+use crate::capture::kmsg::KernelLog;
+use crate::capture::metrics::{self, CpuClocks, WriterStats};
+use crate::capture::ports::PortWatcher;
+use crate::capture::pty::{self, RawModeGuard};
 use crate::capture::stacks::StackSampler;
-use crate::capture::stdio::{self, StdioCapture};
-use crate::capture::tracer::{Tracer, TracerConfig};
+use crate::capture::stdio::{self, StdinCapture, StdinMode, StdioCapture};
+use crate::capture::tracer::TracerConfig;
+use crate::distributed::live::LiveReporter;
 use crate::distributed::trace_context::TraceContext;
 use crate::events::types::*;
-use crate::explain::realtime_diff::RealtimeDiffMonitor;
-use crate::hooks::adapter::AdapterManager;
+use crate::explain::profile::BaselineProfile;
+use crate::explain::realtime_diff::{DiffPolicy, RealtimeDiffMonitor};
+use crate::hooks::adapter::{AdapterManager, LoadContext, ShellAdapter, StartContext};
 use crate::hooks::rust as rust_hooks;
+use crate::redact::Redactor;
 use crate::trace::TraceDb;
 use crate::util;
 
@@ -25,7 +37,63 @@ pub struct RunConfig {
     pub ring_buffer_size: usize,
     pub sample_freq: u64,
     pub batch_size: usize,
-    pub diff_baseline: Option<std::path::PathBuf>,
+    /// Baselines for realtime diff; several reduce noise from
+    /// nondeterministic runs.
+    pub diff_baselines: Vec<std::path::PathBuf>,
+    /// Learned by `poe learn`; diffed against like more baselines.
+    pub diff_profile: Option<BaselineProfile>,
+    pub diff_policy: DiffPolicy,
+    pub max_pack_size: Option<u64>,
+    pub stdin_mode: StdinMode,
+    pub pty: bool,
+    pub hash_writes: Option<u64>,
+    pub watch_files: Vec<String>,
+    pub backend: Backend,
+    pub trace_shell: bool,
+    pub capture_sql: bool,
+    pub capture_cache: bool,
+    pub capture_http2: bool,
+    pub tags: BTreeMap<String, String>,
+    /// Processes besides the root whose failure also emits a pack.
+    pub on_failure_of: Vec<FailureWatch>,
+    /// Program name patterns (`cc1*`) of children not to trace past exec.
+    pub exclude_children: Vec<String>,
+    /// Trace children started with vfork or posix_spawn.
+    pub follow_vfork: bool,
+    /// A poe serve URL to report the run's span to while it runs.
+    pub stream: Option<String>,
+    /// Redacts secrets from stdio and watched-file payloads as they are
+    /// captured, and the environment stored in the pack.
+    pub redact: Option<Redactor>,
+}
+
+/// An `--on-failure-of` target.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FailureWatch {
+    Any,
+    Pid(i32),
+    /// Matches the basename of argv[0], or of argv[1] for interpreters
+    /// (`python app.py` is `app.py`).
+    Name(String),
+}
+
+impl FailureWatch {
+    pub fn parse(s: &str) -> Self {
+        match s.parse::<i32>() {
+            Ok(pid) => Self::Pid(pid),
+            Err(_) if s == "any" => Self::Any,
+            Err(_) => Self::Name(s.to_string()),
+        }
+    }
+
+    fn matches(&self, pid: i32, argv: &[String]) -> bool {
+        let basename = |arg: &String| arg.rsplit('/').next().unwrap_or(arg).to_string();
+        match self {
+            Self::Any => true,
+            Self::Pid(p) => *p == pid,
+            Self::Name(name) => argv.iter().take(2).any(|a| basename(a) == *name),
+        }
+    }
 }
 
 impl Default for RunConfig {
@@ -38,7 +106,25 @@ impl Default for RunConfig {
             ring_buffer_size: 1024 * 1024,
             sample_freq: 99,
             batch_size: 1024,
-            diff_baseline: None,
+            diff_baselines: Vec::new(),
+            diff_profile: None,
+            diff_policy: DiffPolicy::default(),
+            max_pack_size: None,
+            stdin_mode: StdinMode::Inherit,
+            pty: false,
+            hash_writes: None,
+            watch_files: Vec::new(),
+            backend: Backend::Ptrace,
+            trace_shell: false,
+            capture_sql: false,
+            capture_cache: false,
+            capture_http2: false,
+            tags: BTreeMap::new(),
+            on_failure_of: Vec::new(),
+            exclude_children: Vec::new(),
+            follow_vfork: true,
+            stream: None,
+            redact: None,
         }
     }
 }
@@ -51,15 +137,34 @@ pub struct RunResult {
     pub run_id: String,
     pub duration_ms: u64,
     pub realtime_divergences: Vec<crate::explain::realtime_diff::Divergence>,
+    /// The divergence `--diff-halt` stopped the command at.
+    pub diff_halt: Option<crate::explain::realtime_diff::Divergence>,
 }
 
 pub fn execute_run(config: RunConfig) -> Result<RunResult> {
+    let mut adapters = AdapterManager::load_default();
+    if config.trace_shell {
+        adapters.register(Box::new(ShellAdapter::default()));
+    }
+    execute_run_with_adapters(config, adapters)
+}
+
+/// Like [`execute_run`], with a caller-built adapter set (e.g. one with
+/// extra [`crate::hooks::adapter::Adapter`]s registered).
+pub fn execute_run_with_adapters(
+    config: RunConfig,
+    mut adapter_manager: AdapterManager,
+) -> Result<RunResult> {
     let run_id = uuid::Uuid::new_v4().to_string();
     let start_time = chrono::Utc::now();
     let start_mono = util::timestamp_ns();
+    let cpu_clocks = CpuClocks::start();
 
     let work_dir = std::env::temp_dir().join(format!("poe-{}", &run_id[..8]));
     std::fs::create_dir_all(&work_dir)?;
+    if let Err(e) = crate::logging::start_file(&work_dir.join("poe.log")) {
+        log::warn!("failed to create run log: {}", e);
+    }
 
     let db_path = work_dir.join("trace.sqlite");
     {
@@ -87,105 +192,197 @@ pub fn execute_run(config: RunConfig) -> Result<RunResult> {
         start_time,
         git_sha,
         hostname,
+        tags: config.tags.clone(),
+        trace_context: Some(TraceContext::from_env_or_new()),
     };
+    let live = config
+        .stream
+        .as_deref()
+        .and_then(|url| LiveReporter::start(url, &run_info));
 
     {
         let db = TraceDb::open(&db_path)?;
         db.insert_run(&run_info)?;
     }
 
-    let pipes = stdio::create_pipes()?;
+    let pty_pipes = if config.pty {
+        Some(pty::create_pty_pipes()?)
+    } else {
+        None
+    };
+    let pipes = match pty_pipes {
+        Some(ref p) => p.stdio.clone(),
+        None => stdio::create_pipes()?,
+    };
+    // A pty child can't read poe's stdin directly, so plain inheritance
+    // becomes a forwarding relay into the pty.
+    let stdin_mode = match (&config.stdin_mode, &pty_pipes) {
+        (StdinMode::Inherit, Some(_)) => StdinMode::Forward,
+        (mode, _) => mode.clone(),
+    };
+    let stdin_pipe = match (&stdin_mode, &pty_pipes) {
+        (StdinMode::Inherit, _) => None,
+        (_, Some(p)) => Some(p.stdin.clone()),
+        (_, None) => Some(stdio::create_stdin_pipe()?),
+    };
 
-    let mut adapter_manager = AdapterManager::new();
-    adapter_manager.detect_and_register(&config.command);
+    adapter_manager.activate(&config.command);
+    let adapter_manager = Arc::new(Mutex::new(adapter_manager));
+    let event_adapters = adapter_manager
+        .lock()
+        .unwrap()
+        .has_adapters()
+        .then(|| adapter_manager.clone());
 
     let (event_tx, event_rx) = mpsc::channel::<TraceEvent>();
 
     let diff_monitor: Option<Arc<RealtimeDiffMonitor>> =
-        config
-            .diff_baseline
-            .as_ref()
-            .and_then(|path| match RealtimeDiffMonitor::new(path) {
+        if config.diff_baselines.is_empty() && config.diff_profile.is_none() {
+            None
+        } else {
+            match RealtimeDiffMonitor::new(
+                &config.diff_baselines,
+                config.diff_profile.as_ref(),
+                config.diff_policy.clone(),
+            ) {
                 Ok(m) => {
-                    eprintln!("poe: realtime diff monitor active against baseline");
+                    log::info!(
+                        "realtime diff monitor active against {} baseline(s){}",
+                        config.diff_baselines.len(),
+                        match &config.diff_profile {
+                            Some(p) => format!(" and a profile of {} run(s)", p.runs),
+                            None => String::new(),
+                        }
+                    );
                     Some(Arc::new(m))
                 }
                 Err(e) => {
-                    eprintln!("poe: failed to load diff baseline: {:#}", e);
+                    log::warn!("failed to load diff baseline: {:#}", e);
                     None
                 }
-            });
+            }
+        };
 
     let batch_size = config.batch_size;
     let db_writer_handle = {
         let db_path = db_path.clone();
         let diff_mon = diff_monitor.clone();
-        thread::Builder::new()
-            .name("poe-db-writer".into())
-            .spawn(move || -> Result<()> {
+        let adapters = event_adapters;
+        thread::Builder::new().name("poe-db-writer".into()).spawn(
+            move || -> Result<WriterStats> {
                 let db = TraceDb::open(&db_path)?;
                 let mut batch = Vec::with_capacity(batch_size);
+                let mut stats = WriterStats::default();
+                let mut ports = PortWatcher::default();
+                let mut gate = CaptureGate::default();
+                let mut take = |event: TraceEvent, batch: &mut Vec<TraceEvent>| {
+                    if !gate.admit(&event) {
+                        return;
+                    }
+                    if let Some(ref mon) = diff_mon {
+                        if !gate.is_paused(event.proc_id()) {
+                            mon.check(&event);
+                        }
+                    }
+                    if let Some(ref adapters) = adapters {
+                        adapters.lock().unwrap().on_event(&event);
+                    }
+                    batch.extend(ports.on_event(&event));
+                    batch.push(event);
+                };
 
                 loop {
                     match event_rx.recv_timeout(Duration::from_millis(100)) {
                         Ok(event) => {
-                            if let Some(ref mon) = diff_mon {
-                                mon.check(&event);
-                            }
-                            batch.push(event);
+                            take(event, &mut batch);
+                            // Everything already queued is backlog; drain it
+                            // all so the peak is measured, in batch_size chunks.
+                            let mut queued = 0;
                             while let Ok(event) = event_rx.try_recv() {
-                                if let Some(ref mon) = diff_mon {
-                                    mon.check(&event);
-                                }
-                                batch.push(event);
+                                queued += 1;
+                                take(event, &mut batch);
                                 if batch.len() >= batch_size {
-                                    break;
+                                    flush_batch(&db, &mut batch, &mut stats);
                                 }
                             }
+                            stats.record_backlog(queued);
                             if batch.len() >= batch_size {
-                                db.batch_insert_events(&batch)?;
-                                batch.clear();
+                                flush_batch(&db, &mut batch, &mut stats);
                             }
                         }
                         Err(mpsc::RecvTimeoutError::Timeout) => {
-                            if !batch.is_empty() {
-                                db.batch_insert_events(&batch)?;
-                                batch.clear();
-                            }
+                            flush_batch(&db, &mut batch, &mut stats);
                         }
                         Err(mpsc::RecvTimeoutError::Disconnected) => {
-                            if !batch.is_empty() {
-                                db.batch_insert_events(&batch)?;
-                            }
+                            flush_batch(&db, &mut batch, &mut stats);
                             break;
                         }
                     }
                 }
-                Ok(())
-            })?
+                stats.paused_events = gate.dropped();
+                if let Err(e) = db.finish_stdio() {
+                    log::error!("db writer: failed to store the last stdio lines: {:#}", e);
+                }
+                Ok(stats)
+            },
+        )?
     };
 
     let mut env_overrides = std::collections::HashMap::new();
     let mut clear_cloexec_fds = Vec::new();
 
-    adapter_manager.on_load(&mut env_overrides, &mut clear_cloexec_fds)?;
+    // Writes of capture markers land here; /dev/null so nothing has to
+    // drain it. Held until the command exits.
+    let control = std::fs::OpenOptions::new().write(true).open("/dev/null")?;
+    clear_cloexec_fds.push(control.as_raw_fd());
+    env_overrides.insert(CONTROL_FD_ENV.to_string(), control.as_raw_fd().to_string());
+
+    adapter_manager.lock().unwrap().on_load(&mut LoadContext {
+        argv: &config.command,
+        mode: config.capture_mode,
+        env: &mut env_overrides,
+        clear_cloexec_fds: &mut clear_cloexec_fds,
+    })?;
 
     rust_hooks::apply_rust_env(&mut env_overrides);
 
-    let trace_ctx = TraceContext::from_env_or_new();
-    trace_ctx.inject_env(&mut env_overrides);
+    if let Some(ref trace_ctx) = run_info.trace_context {
+        trace_ctx.inject_env(&mut env_overrides);
+    }
 
     let tracer_config = TracerConfig {
         capture_mode: config.capture_mode,
+        stdin_fd: stdin_pipe.as_ref().map(|p| p.child_stdin_read),
         stdout_fd: Some(pipes.child_stdout_write),
         stderr_fd: Some(pipes.child_stderr_write),
+        controlling_tty: pty_pipes.is_some(),
         env_overrides,
         clear_cloexec_fds,
+        hash_writes: config.hash_writes,
+        watch_files: config.watch_files.clone(),
+        capture_sql: config.capture_sql,
+        capture_cache: config.capture_cache,
+        capture_http2: config.capture_http2,
+        control_fd: Some(control.as_raw_fd()),
+        exclude_children: config.exclude_children.clone(),
+        follow_vfork: config.follow_vfork,
+        redact: config.redact.clone(),
     };
 
-    let mut tracer = Tracer::new(tracer_config, event_tx.clone());
-    let root_pid = tracer.spawn_and_trace(&config.command)?;
+    let mut kernel_log = KernelLog::open();
+    let (mut tracer, root_pid) = ActiveTracer::spawn(
+        config.backend,
+        tracer_config,
+        event_tx.clone(),
+        &config.command,
+    )?;
     let base_ts = tracer.base_ts();
+    if let Some(ref mon) = diff_monitor {
+        mon.arm(root_pid);
+    }
+    // Held until the pack is written, so a second ^C during the flush
+    // doesn't lose it either.
+    let interrupts = InterruptGuard::install(root_pid, pty_pipes.is_some())?;
 
     let stdio_capture = StdioCapture::start(
         &pipes,
@@ -193,56 +390,124 @@ pub fn execute_run(config: RunConfig) -> Result<RunResult> {
         event_tx.clone(),
         base_ts,
         config.ring_buffer_size,
+        config.redact.clone(),
     )?;
 
-    adapter_manager.on_start(event_tx.clone(), root_pid)?;
+    let stdin_capture = match stdin_pipe {
+        Some(ref pipe) => Some(StdinCapture::start(
+            pipe,
+            stdin_mode.clone(),
+            root_pid,
+            event_tx.clone(),
+            base_ts,
+            config.ring_buffer_size,
+            config.redact.clone(),
+        )?),
+        None => None,
+    };
+
+    let pty_terminal = match pty_pipes {
+        Some(ref p) => Some((p.forward_winsize()?, RawModeGuard::enable())),
+        None => None,
+    };
+
+    adapter_manager.lock().unwrap().on_start(&StartContext {
+        event_tx: event_tx.clone(),
+        root_pid,
+        base_ts,
+    })?;
 
     let mut stack_sampler = StackSampler::new(base_ts, config.sample_freq);
     stack_sampler.add_process(root_pid)?;
 
+    let tracer_cpu_start = metrics::cpu_time_ms(libc::RUSAGE_THREAD);
     let (exit_code, signal) = tracer.run_event_loop()?;
+    let tracer_cpu_ms = metrics::cpu_time_ms(libc::RUSAGE_THREAD).saturating_sub(tracer_cpu_start);
+    let interrupted = interrupts.received();
+    if let Some(sig) = interrupted {
+        log::warn!(
+            "interrupted by {}; writing the pack",
+            util::signal_name(sig)
+        );
+    }
+
+    drop(pty_terminal);
 
     stack_sampler.drain_samples(&event_tx);
     stack_sampler.stop();
+    if let Some(ref mut kernel_log) = kernel_log {
+        for event in kernel_log.drain(base_ts, root_pid) {
+            let _ = event_tx.send(TraceEvent::Generic(event));
+        }
+    }
 
     drop(event_tx);
     drop(tracer);
 
-    adapter_manager.on_exit()?;
+    adapter_manager.lock().unwrap().on_exit()?;
 
     let rt_trace_path = std::path::PathBuf::from(format!("/tmp/poe-rt-{}.bin", root_pid));
-    let (native_trace_entries, rt_start_ns) = if rt_trace_path.exists() {
+    let (native_trace_entries, rt_start_ns, rt_load_base) = if rt_trace_path.exists() {
         match instrument::read_runtime_trace(&rt_trace_path) {
             Ok((entries, start_ns)) => {
-                eprintln!(
-                    "poe: captured {} native function trace entries",
-                    entries.len()
-                );
+                log::info!("captured {} native function trace entries", entries.len());
+                let load_base = instrument::read_runtime_load_base(&rt_trace_path);
                 let _ = std::fs::remove_file(&rt_trace_path);
-                (entries, start_ns)
+                (entries, start_ns, load_base)
             }
             Err(e) => {
-                eprintln!("poe: failed to read runtime trace: {:#}", e);
-                (Vec::new(), base_ts)
+                log::warn!("failed to read runtime trace: {:#}", e);
+                (Vec::new(), base_ts, None)
             }
         }
     } else {
-        (Vec::new(), base_ts)
+        (Vec::new(), base_ts, None)
     };
 
     let (stdout_ring, stderr_ring) = stdio_capture.finish();
+    let stdin_data = stdin_capture.map(|c| c.finish());
+
+    let writer_stats = match db_writer_handle.join() {
+        Ok(Ok(stats)) => stats,
+        Ok(Err(e)) => {
+            log::error!("db writer error: {:#}", e);
+            WriterStats::default()
+        }
+        Err(e) => {
+            log::error!("db writer thread panicked: {:?}", e);
+            WriterStats::default()
+        }
+    };
 
-    match db_writer_handle.join() {
-        Ok(Ok(())) => {}
-        Ok(Err(e)) => eprintln!("poe: db writer error: {:#}", e),
-        Err(e) => eprintln!("poe: db writer thread panicked: {:?}", e),
+    {
+        let db = TraceDb::open(&db_path)?;
+        if let Err(e) = crate::trace::logs::extract_log_events(&db) {
+            log::warn!("failed to parse structured logs: {:#}", e);
+        }
     }
 
     let end_time = chrono::Utc::now();
     let duration_ns = util::timestamp_ns().saturating_sub(start_mono);
     let duration_ms = duration_ns / 1_000_000;
+    let capture_metrics = cpu_clocks.finish(&writer_stats, tracer_cpu_ms, duration_ms);
+    if let Some(live) = live {
+        live.finish(exit_code, signal, duration_ms);
+    }
+    if capture_metrics.dropped_events > 0 {
+        log::warn!(
+            "{} events could not be stored; the trace is incomplete",
+            capture_metrics.dropped_events
+        );
+    }
 
-    let trigger = determine_trigger(exit_code, signal, config.always_emit);
+    let mut trigger =
+        determine_trigger(exit_code, signal, config.always_emit, interrupted.is_some());
+    if matches!(trigger, None | Some(TriggerReason::Always)) && !config.on_failure_of.is_empty() {
+        let db = TraceDb::open(&db_path)?;
+        if let Some(pid) = failed_child(&db, root_pid, &config.on_failure_of)? {
+            trigger = Some(TriggerReason::ChildFailure(pid));
+        }
+    }
 
     if !native_trace_entries.is_empty() {
         let db = TraceDb::open(&db_path)?;
@@ -251,14 +516,21 @@ pub fn execute_run(config: RunConfig) -> Result<RunResult> {
             binary_path,
             &native_trace_entries
                 .iter()
-                .map(|e| e.func_addr)
+                .flat_map(|e| [e.func_addr, e.call_site])
                 .collect::<Vec<_>>(),
+            rt_load_base,
         );
         for entry in &native_trace_entries {
-            let symbol = resolved_addrs
+            let mut symbol = resolved_addrs
                 .get(&entry.func_addr)
                 .cloned()
                 .unwrap_or_else(|| format!("0x{:x}", entry.func_addr));
+            // mcount entries point just past the call inside the function.
+            if entry.event_type == 2 {
+                if let Some((name, _)) = symbol.rsplit_once("+0x") {
+                    symbol = name.to_string();
+                }
+            }
             let call_site = resolved_addrs
                 .get(&entry.call_site)
                 .cloned()
@@ -270,10 +542,10 @@ pub fn execute_run(config: RunConfig) -> Result<RunResult> {
                 "tid": entry.tid,
                 "func_addr": format!("0x{:x}", entry.func_addr),
             });
-            let kind = if entry.event_type == 0 {
-                crate::events::types::EventKind::NativeTraceEnter
-            } else {
+            let kind = if entry.event_type == 1 {
                 crate::events::types::EventKind::NativeTraceExit
+            } else {
+                crate::events::types::EventKind::NativeTraceEnter
             };
             let event = crate::events::types::Event {
                 ts: rt_start_ns.saturating_sub(base_ts) + entry.ts_ns,
@@ -290,11 +562,15 @@ pub fn execute_run(config: RunConfig) -> Result<RunResult> {
         db.update_run_end(&run_id, &end_time, exit_code, signal, trigger)?;
     }
 
+    let poe_log = crate::logging::finish_file();
     let pack_path = if trigger.is_some() {
         let pack_name = format!("poe-{}.poepack", &run_id[..8]);
         let pack_path = config.output_dir.join(&pack_name);
 
         let db = TraceDb::open(&db_path)?;
+        if let Err(e) = crate::pack::filestat::record(&db) {
+            log::warn!("failed to record file states: {:#}", e);
+        }
         db.checkpoint()?;
         crate::pack::writer::write_pack(
             &pack_path,
@@ -306,6 +582,11 @@ pub fn execute_run(config: RunConfig) -> Result<RunResult> {
             duration_ms,
             &stdout_ring,
             &stderr_ring,
+            stdin_data.as_deref(),
+            poe_log.as_deref(),
+            Some(&capture_metrics),
+            config.max_pack_size,
+            &config.redact.clone().unwrap_or_default(),
         )?;
 
         Some(pack_path)
@@ -313,14 +594,17 @@ pub fn execute_run(config: RunConfig) -> Result<RunResult> {
         None
     };
 
+    drop(interrupts);
+
     if let Err(e) = std::fs::remove_dir_all(&work_dir) {
-        eprintln!("poe: failed to clean up work dir: {}", e);
+        log::warn!("failed to clean up work dir: {}", e);
     }
 
     let realtime_divergences = diff_monitor
         .as_ref()
         .map(|m| m.take_divergences())
         .unwrap_or_default();
+    let diff_halt = diff_monitor.as_ref().and_then(|m| m.halted());
 
     Ok(RunResult {
         exit_code,
@@ -330,14 +614,64 @@ pub fn execute_run(config: RunConfig) -> Result<RunResult> {
         run_id,
         duration_ms,
         realtime_divergences,
+        diff_halt,
     })
 }
 
+/// Writes and times a batch. A batch the db rejects is counted as dropped
+/// rather than stopping the writer, which would lose everything after it.
+fn flush_batch(db: &TraceDb, batch: &mut Vec<TraceEvent>, stats: &mut WriterStats) {
+    if batch.is_empty() {
+        return;
+    }
+    let start = std::time::Instant::now();
+    let result = db.batch_insert_events(batch);
+    stats.record_batch(batch.len(), start.elapsed(), result.is_ok());
+    if let Err(e) = result {
+        log::error!("db writer: failed to store {} events: {:#}", batch.len(), e);
+    }
+    batch.clear();
+}
+
+/// Signals supervisors and shells use to stop processes on purpose; a
+/// child ending this way is not a failure.
+const STOP_SIGNALS: [i32; 4] = [libc::SIGTERM, libc::SIGINT, libc::SIGHUP, libc::SIGPIPE];
+
+/// The first process other than the root, matching one of `watches`, that
+/// exited non-zero or was killed by something other than a stop signal.
+fn failed_child(db: &TraceDb, root_pid: i32, watches: &[FailureWatch]) -> Result<Option<i32>> {
+    let mut failed: Vec<_> = db
+        .query_processes()?
+        .into_iter()
+        .filter(|p| p.proc_id != root_pid)
+        .filter(|p| match p.signal {
+            Some(sig) => !STOP_SIGNALS.contains(&sig),
+            None => p.exit_code.is_some_and(|c| c != 0),
+        })
+        .filter(|p| {
+            let argv: Vec<String> = p
+                .argv
+                .as_deref()
+                .and_then(|a| serde_json::from_str(a).ok())
+                .unwrap_or_default();
+            watches.iter().any(|w| w.matches(p.proc_id, &argv))
+        })
+        .collect();
+    failed.sort_by_key(|p| p.end_ts.unwrap_or(i64::MAX));
+    Ok(failed.first().map(|p| p.proc_id))
+}
+
 fn determine_trigger(
     exit_code: Option<i32>,
     signal: Option<i32>,
     always: bool,
+    interrupted: bool,
 ) -> Option<TriggerReason> {
+    // The user stopped the run to look at it.
+    if interrupted {
+        return Some(TriggerReason::Explicit);
+    }
+
     if always {
         return Some(TriggerReason::Always);
     }
@@ -360,7 +694,11 @@ fn determine_trigger(
     None
 }
 
-fn resolve_native_addrs(binary: &str, addrs: &[u64]) -> std::collections::HashMap<u64, String> {
+fn resolve_native_addrs(
+    binary: &str,
+    addrs: &[u64],
+    load_base: Option<u64>,
+) -> std::collections::HashMap<u64, String> {
     use std::collections::HashMap;
 
     let mut result = HashMap::new();
@@ -396,7 +734,9 @@ fn resolve_native_addrs(binary: &str, addrs: &[u64]) -> std::collections::HashMa
         .find(|(_, name)| name == "main")
         .map(|&(a, _)| a);
 
-    let load_offset = if let Some(elf_m) = elf_main {
+    let load_offset = if let Some(base) = load_base {
+        base
+    } else if let Some(elf_m) = elf_main {
         let page_offset = elf_m & 0xFFF;
         addrs
             .iter()
@@ -425,3 +765,22 @@ fn resolve_native_addrs(binary: &str, addrs: &[u64]) -> std::collections::HashMa
 
     result
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn failure_watches_match_pid_and_program_name() {
+        let argv = |args: &[&str]| args.iter().map(|a| a.to_string()).collect::<Vec<_>>();
+        assert_eq!(FailureWatch::parse("any"), FailureWatch::Any);
+        assert_eq!(FailureWatch::parse("1234"), FailureWatch::Pid(1234));
+
+        let web = FailureWatch::parse("web");
+        assert!(web.matches(7, &argv(&["/usr/local/bin/web", "--port", "80"])));
+        assert!(!web.matches(7, &argv(&["worker", "--queue", "web"])));
+        let script = FailureWatch::parse("app.py");
+        assert!(script.matches(7, &argv(&["python3", "/srv/app.py"])));
+        assert!(FailureWatch::Pid(7).matches(7, &[]));
+    }
+}
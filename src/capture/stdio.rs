@@ -1,14 +1,16 @@
 use std::io::{Read, Write};
 use std::os::unix::io::{FromRawFd, RawFd};
-use std::sync::mpsc;
+use std::sync::{mpsc, Arc, Mutex};
 use std::thread;
 
 use anyhow::Result;
 
 use crate::events::types::*;
+use crate::redact::{Redactor, StreamRedactor};
 use crate::util;
 use crate::util::ringbuf::ByteRing;
 
+#[derive(Clone)]
 pub struct StdioPipes {
     pub child_stdout_write: RawFd,
     pub child_stderr_write: RawFd,
@@ -16,21 +18,17 @@ pub struct StdioPipes {
     pub parent_stderr_read: RawFd,
 }
 
+fn pipe_cloexec() -> Result<[i32; 2]> {
+    let mut fds = [0i32; 2];
+    if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } != 0 {
+        anyhow::bail!("pipe2 failed: {}", std::io::Error::last_os_error());
+    }
+    Ok(fds)
+}
+
 pub fn create_pipes() -> Result<StdioPipes> {
-    let stdout_pipe = unsafe {
-        let mut fds = [0i32; 2];
-        if libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) != 0 {
-            anyhow::bail!("pipe2 failed: {}", std::io::Error::last_os_error());
-        }
-        fds
-    };
-    let stderr_pipe = unsafe {
-        let mut fds = [0i32; 2];
-        if libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) != 0 {
-            anyhow::bail!("pipe2 failed: {}", std::io::Error::last_os_error());
-        }
-        fds
-    };
+    let stdout_pipe = pipe_cloexec()?;
+    let stderr_pipe = pipe_cloexec()?;
 
     Ok(StdioPipes {
         parent_stdout_read: stdout_pipe[0],
@@ -52,6 +50,7 @@ impl StdioCapture {
         event_tx: mpsc::Sender<TraceEvent>,
         base_ts: u64,
         ring_capacity: usize,
+        redact: Option<Redactor>,
     ) -> Result<Self> {
         nix::unistd::close(pipes.child_stdout_write).ok();
         nix::unistd::close(pipes.child_stderr_write).ok();
@@ -61,6 +60,7 @@ impl StdioCapture {
 
         let stdout_tx = event_tx.clone();
         let stderr_tx = event_tx;
+        let stdout_redact = redact.clone();
 
         let stdout_handle = thread::Builder::new()
             .name("poe-stdout-relay".into())
@@ -73,6 +73,7 @@ impl StdioCapture {
                     stdout_tx,
                     base_ts,
                     ring_capacity,
+                    stdout_redact,
                 )
             })?;
 
@@ -87,6 +88,7 @@ impl StdioCapture {
                     stderr_tx,
                     base_ts,
                     ring_capacity,
+                    redact,
                 )
             })?;
 
@@ -113,6 +115,7 @@ impl StdioCapture {
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn relay_stream<W: Write>(
     read_fd: RawFd,
     mut output: W,
@@ -121,27 +124,40 @@ fn relay_stream<W: Write>(
     event_tx: mpsc::Sender<TraceEvent>,
     base_ts: u64,
     ring_capacity: usize,
+    redact: Option<Redactor>,
 ) -> ByteRing {
     let mut ring = ByteRing::new(ring_capacity);
     let mut file = unsafe { std::fs::File::from_raw_fd(read_fd) };
     let mut buf = [0u8; 8192];
+    // The terminal gets the output as written; only what is stored is
+    // redacted.
+    let mut redactor = redact.map(StreamRedactor::new);
+    let mut record = |data: &[u8]| {
+        if data.is_empty() {
+            return;
+        }
+        ring.write(data);
+        let ts = util::timestamp_ns().saturating_sub(base_ts);
+        let _ = event_tx.send(TraceEvent::Stdio(StdioChunk {
+            ts,
+            proc_id: root_pid,
+            stream,
+            data: data.to_vec(),
+        }));
+    };
 
     loop {
         match file.read(&mut buf) {
             Ok(0) => break,
             Ok(n) => {
                 let chunk = &buf[..n];
-                ring.write(chunk);
                 let _ = output.write_all(chunk);
                 let _ = output.flush();
 
-                let ts = util::timestamp_ns().saturating_sub(base_ts);
-                let _ = event_tx.send(TraceEvent::Stdio(StdioChunk {
-                    ts,
-                    proc_id: root_pid,
-                    stream,
-                    data: chunk.to_vec(),
-                }));
+                match redactor.as_mut() {
+                    Some(r) => record(&r.push(chunk)),
+                    None => record(chunk),
+                }
             }
             Err(e) => {
                 if e.kind() == std::io::ErrorKind::Interrupted {
@@ -151,6 +167,179 @@ fn relay_stream<W: Write>(
             }
         }
     }
+    if let Some(mut r) = redactor {
+        record(&r.finish());
+    }
 
     ring
 }
+
+/// How the child's stdin is wired up.
+#[derive(Debug, Clone, Default)]
+pub enum StdinMode {
+    /// The child shares poe's stdin directly; nothing is recorded.
+    #[default]
+    Inherit,
+    /// poe's stdin is relayed to the child without being recorded (used
+    /// when the child's stdin is a pty rather than poe's own stdin).
+    Forward,
+    /// poe's stdin is teed into the child through a pipe and recorded.
+    Capture,
+    /// Previously recorded bytes are fed to the child, then stdin is closed.
+    Replay(Vec<u8>),
+}
+
+#[derive(Clone)]
+pub struct StdinPipe {
+    pub child_stdin_read: RawFd,
+    pub parent_stdin_write: RawFd,
+}
+
+pub fn create_stdin_pipe() -> Result<StdinPipe> {
+    let fds = pipe_cloexec()?;
+    Ok(StdinPipe {
+        child_stdin_read: fds[0],
+        parent_stdin_write: fds[1],
+    })
+}
+
+/// Recorded stdin: the first `capacity` bytes plus the total seen. The
+/// sender lives here so finish() can drop it while the relay is still blocked.
+#[derive(Default)]
+struct StdinRecord {
+    data: Vec<u8>,
+    total: u64,
+    capacity: usize,
+    root_pid: i32,
+    base_ts: u64,
+    event_tx: Option<mpsc::Sender<TraceEvent>>,
+    redactor: Option<StreamRedactor>,
+}
+
+impl StdinRecord {
+    fn record(&mut self, chunk: &[u8]) {
+        if self.event_tx.is_none() {
+            return;
+        }
+        self.total += chunk.len() as u64;
+        match self.redactor.as_mut() {
+            Some(r) => {
+                let redacted = r.push(chunk);
+                self.store(redacted);
+            }
+            None => self.store(chunk.to_vec()),
+        }
+    }
+
+    fn store(&mut self, data: Vec<u8>) {
+        let Some(ref tx) = self.event_tx else {
+            return;
+        };
+        if data.is_empty() {
+            return;
+        }
+        let room = self.capacity.saturating_sub(self.data.len());
+        self.data.extend_from_slice(&data[..data.len().min(room)]);
+        let ts = util::timestamp_ns().saturating_sub(self.base_ts);
+        let _ = tx.send(TraceEvent::Stdio(StdioChunk {
+            ts,
+            proc_id: self.root_pid,
+            stream: StdioStream::Stdin,
+            data,
+        }));
+    }
+}
+
+pub struct StdinCapture {
+    record: Arc<Mutex<StdinRecord>>,
+    capacity: usize,
+}
+
+impl StdinCapture {
+    pub fn start(
+        pipe: &StdinPipe,
+        mode: StdinMode,
+        root_pid: i32,
+        event_tx: mpsc::Sender<TraceEvent>,
+        base_ts: u64,
+        capacity: usize,
+        redact: Option<Redactor>,
+    ) -> Result<Self> {
+        nix::unistd::close(pipe.child_stdin_read).ok();
+
+        let record = Arc::new(Mutex::new(StdinRecord {
+            event_tx: (!matches!(mode, StdinMode::Forward)).then_some(event_tx),
+            redactor: redact.map(StreamRedactor::new),
+            capacity,
+            root_pid,
+            base_ts,
+            ..Default::default()
+        }));
+        let write_fd = pipe.parent_stdin_write;
+        let thread_record = record.clone();
+
+        // Not joined in finish(): a capture relay may stay blocked on poe's
+        // own stdin (e.g. a terminal) long after the child has exited.
+        thread::Builder::new()
+            .name("poe-stdin-relay".into())
+            .spawn(move || {
+                let mut child_stdin = unsafe { std::fs::File::from_raw_fd(write_fd) };
+                let mut feed = |chunk: &[u8]| -> bool {
+                    thread_record.lock().unwrap().record(chunk);
+                    child_stdin.write_all(chunk).is_ok()
+                };
+
+                match mode {
+                    StdinMode::Inherit => {}
+                    StdinMode::Replay(data) => {
+                        for chunk in data.chunks(8192) {
+                            if !feed(chunk) {
+                                break;
+                            }
+                        }
+                    }
+                    StdinMode::Forward | StdinMode::Capture => {
+                        let mut input = std::io::stdin().lock();
+                        let mut buf = [0u8; 8192];
+                        loop {
+                            match input.read(&mut buf) {
+                                Ok(0) => break,
+                                Ok(n) => {
+                                    if !feed(&buf[..n]) {
+                                        break;
+                                    }
+                                }
+                                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+                                Err(_) => break,
+                            }
+                        }
+                    }
+                }
+
+                // Dropping our end of a pty doesn't give the child EOF (the
+                // stdout relay still holds the master), so send VEOF instead.
+                if unsafe { libc::isatty(write_fd) } == 1 {
+                    let _ = child_stdin.write_all(&[0x04]);
+                }
+            })?;
+
+        Ok(Self { record, capacity })
+    }
+
+    /// Returns the recorded stdin bytes seen so far.
+    pub fn finish(self) -> Vec<u8> {
+        let mut rec = self.record.lock().unwrap();
+        if let Some(mut redactor) = rec.redactor.take() {
+            rec.store(redactor.finish());
+        }
+        rec.event_tx = None;
+        if rec.total > self.capacity as u64 {
+            log::warn!(
+                "stdin was {} bytes, only the first {} were recorded",
+                rec.total,
+                self.capacity
+            );
+        }
+        rec.data.clone()
+    }
+}
@@ -1,63 +1,225 @@
 use crate::events::types::*;
+use crate::util;
 
-pub const SYS_READ: u64 = 0;
-pub const SYS_WRITE: u64 = 1;
-pub const SYS_OPEN: u64 = 2;
-pub const SYS_CLOSE: u64 = 3;
-pub const SYS_STAT: u64 = 4;
-pub const SYS_FSTAT: u64 = 5;
-pub const SYS_LSTAT: u64 = 6;
-pub const SYS_PREAD64: u64 = 17;
-pub const SYS_PWRITE64: u64 = 18;
-pub const SYS_READV: u64 = 19;
-pub const SYS_WRITEV: u64 = 20;
-pub const SYS_PIPE: u64 = 22;
-pub const SYS_DUP: u64 = 32;
-pub const SYS_DUP2: u64 = 33;
-pub const SYS_SOCKET: u64 = 41;
-pub const SYS_CONNECT: u64 = 42;
-pub const SYS_ACCEPT: u64 = 43;
-pub const SYS_SENDTO: u64 = 44;
-pub const SYS_RECVFROM: u64 = 45;
-pub const SYS_SENDMSG: u64 = 46;
-pub const SYS_RECVMSG: u64 = 47;
-pub const SYS_SHUTDOWN: u64 = 48;
-pub const SYS_BIND: u64 = 49;
-pub const SYS_LISTEN: u64 = 50;
-pub const SYS_GETSOCKNAME: u64 = 51;
-pub const SYS_GETPEERNAME: u64 = 52;
-pub const SYS_SOCKETPAIR: u64 = 53;
-pub const SYS_CLONE: u64 = 56;
-pub const SYS_FORK: u64 = 57;
-pub const SYS_VFORK: u64 = 58;
-pub const SYS_EXECVE: u64 = 59;
-pub const SYS_EXIT: u64 = 60;
-pub const SYS_FCNTL: u64 = 72;
-pub const SYS_TRUNCATE: u64 = 76;
-pub const SYS_FTRUNCATE: u64 = 77;
-pub const SYS_CHDIR: u64 = 80;
-pub const SYS_RENAME: u64 = 82;
-pub const SYS_MKDIR: u64 = 83;
-pub const SYS_RMDIR: u64 = 84;
-pub const SYS_CREAT: u64 = 85;
-pub const SYS_LINK: u64 = 86;
-pub const SYS_UNLINK: u64 = 87;
-pub const SYS_SYMLINK: u64 = 88;
-pub const SYS_READLINK: u64 = 89;
-pub const SYS_CHMOD: u64 = 90;
-pub const SYS_CHOWN: u64 = 92;
-pub const SYS_OPENAT: u64 = 257;
-pub const SYS_MKDIRAT: u64 = 258;
-pub const SYS_UNLINKAT: u64 = 263;
-pub const SYS_RENAMEAT: u64 = 264;
-pub const SYS_FCHMODAT: u64 = 268;
-pub const SYS_FACCESSAT: u64 = 269;
-pub const SYS_ACCEPT4: u64 = 288;
-pub const SYS_RENAMEAT2: u64 = 316;
-pub const SYS_EXECVEAT: u64 = 322;
-pub const SYS_EXIT_GROUP: u64 = 231;
-pub const SYS_NEWFSTATAT: u64 = 262;
-pub const SYS_PIPE2: u64 = 293;
+pub use self::nr::*;
+
+#[cfg(target_arch = "x86_64")]
+mod nr {
+    pub const SYS_READ: u64 = 0;
+    pub const SYS_WRITE: u64 = 1;
+    pub const SYS_OPEN: u64 = 2;
+    pub const SYS_CLOSE: u64 = 3;
+    pub const SYS_STAT: u64 = 4;
+    pub const SYS_FSTAT: u64 = 5;
+    pub const SYS_LSTAT: u64 = 6;
+    pub const SYS_MMAP: u64 = 9;
+    pub const SYS_MUNMAP: u64 = 11;
+    pub const SYS_RT_SIGACTION: u64 = 13;
+    pub const SYS_PREAD64: u64 = 17;
+    pub const SYS_PWRITE64: u64 = 18;
+    pub const SYS_READV: u64 = 19;
+    pub const SYS_WRITEV: u64 = 20;
+    pub const SYS_PIPE: u64 = 22;
+    pub const SYS_DUP: u64 = 32;
+    pub const SYS_DUP2: u64 = 33;
+    pub const SYS_SENDFILE: u64 = 40;
+    pub const SYS_SOCKET: u64 = 41;
+    pub const SYS_CONNECT: u64 = 42;
+    pub const SYS_ACCEPT: u64 = 43;
+    pub const SYS_SENDTO: u64 = 44;
+    pub const SYS_RECVFROM: u64 = 45;
+    pub const SYS_SENDMSG: u64 = 46;
+    pub const SYS_RECVMSG: u64 = 47;
+    pub const SYS_SHUTDOWN: u64 = 48;
+    pub const SYS_BIND: u64 = 49;
+    pub const SYS_LISTEN: u64 = 50;
+    pub const SYS_GETSOCKNAME: u64 = 51;
+    pub const SYS_GETPEERNAME: u64 = 52;
+    pub const SYS_SOCKETPAIR: u64 = 53;
+    pub const SYS_CLONE: u64 = 56;
+    pub const SYS_FORK: u64 = 57;
+    pub const SYS_VFORK: u64 = 58;
+    pub const SYS_EXECVE: u64 = 59;
+    pub const SYS_EXIT: u64 = 60;
+    pub const SYS_FCNTL: u64 = 72;
+    pub const SYS_TRUNCATE: u64 = 76;
+    pub const SYS_FTRUNCATE: u64 = 77;
+    pub const SYS_CHDIR: u64 = 80;
+    pub const SYS_RENAME: u64 = 82;
+    pub const SYS_MKDIR: u64 = 83;
+    pub const SYS_RMDIR: u64 = 84;
+    pub const SYS_CREAT: u64 = 85;
+    pub const SYS_LINK: u64 = 86;
+    pub const SYS_UNLINK: u64 = 87;
+    pub const SYS_SYMLINK: u64 = 88;
+    pub const SYS_READLINK: u64 = 89;
+    pub const SYS_CHMOD: u64 = 90;
+    pub const SYS_CHOWN: u64 = 92;
+    pub const SYS_EXIT_GROUP: u64 = 231;
+    pub const SYS_OPENAT: u64 = 257;
+    pub const SYS_MKDIRAT: u64 = 258;
+    pub const SYS_FCHOWNAT: u64 = 260;
+    pub const SYS_NEWFSTATAT: u64 = 262;
+    pub const SYS_UNLINKAT: u64 = 263;
+    pub const SYS_RENAMEAT: u64 = 264;
+    pub const SYS_LINKAT: u64 = 265;
+    pub const SYS_SYMLINKAT: u64 = 266;
+    pub const SYS_READLINKAT: u64 = 267;
+    pub const SYS_FCHMODAT: u64 = 268;
+    pub const SYS_FACCESSAT: u64 = 269;
+    pub const SYS_SPLICE: u64 = 275;
+    pub const SYS_ACCEPT4: u64 = 288;
+    pub const SYS_PIPE2: u64 = 293;
+    pub const SYS_RENAMEAT2: u64 = 316;
+    pub const SYS_EXECVEAT: u64 = 322;
+    pub const SYS_COPY_FILE_RANGE: u64 = 326;
+    pub const SYS_STATX: u64 = 332;
+    pub const SYS_IO_URING_SETUP: u64 = 425;
+    pub const SYS_IO_URING_ENTER: u64 = 426;
+}
+
+/// The generic table aarch64 uses has no open, stat, fork or the other
+/// path calls that predate the `*at` forms. They get numbers past any
+/// syscall's, so the decoding below matches on them without cfgs.
+#[cfg(target_arch = "aarch64")]
+mod nr {
+    const NONE: u64 = 1 << 32;
+
+    pub const SYS_DUP: u64 = 23;
+    pub const SYS_FCNTL: u64 = 25;
+    pub const SYS_MKDIRAT: u64 = 34;
+    pub const SYS_UNLINKAT: u64 = 35;
+    pub const SYS_SYMLINKAT: u64 = 36;
+    pub const SYS_LINKAT: u64 = 37;
+    pub const SYS_RENAMEAT: u64 = 38;
+    pub const SYS_TRUNCATE: u64 = 45;
+    pub const SYS_FTRUNCATE: u64 = 46;
+    pub const SYS_FACCESSAT: u64 = 48;
+    pub const SYS_CHDIR: u64 = 49;
+    pub const SYS_FCHMODAT: u64 = 53;
+    pub const SYS_FCHOWNAT: u64 = 54;
+    pub const SYS_OPENAT: u64 = 56;
+    pub const SYS_CLOSE: u64 = 57;
+    pub const SYS_PIPE2: u64 = 59;
+    pub const SYS_READ: u64 = 63;
+    pub const SYS_WRITE: u64 = 64;
+    pub const SYS_READV: u64 = 65;
+    pub const SYS_WRITEV: u64 = 66;
+    pub const SYS_PREAD64: u64 = 67;
+    pub const SYS_PWRITE64: u64 = 68;
+    pub const SYS_SENDFILE: u64 = 71;
+    pub const SYS_SPLICE: u64 = 76;
+    pub const SYS_READLINKAT: u64 = 78;
+    pub const SYS_NEWFSTATAT: u64 = 79;
+    pub const SYS_FSTAT: u64 = 80;
+    pub const SYS_EXIT: u64 = 93;
+    pub const SYS_EXIT_GROUP: u64 = 94;
+    pub const SYS_RT_SIGACTION: u64 = 134;
+    pub const SYS_SOCKET: u64 = 198;
+    pub const SYS_SOCKETPAIR: u64 = 199;
+    pub const SYS_BIND: u64 = 200;
+    pub const SYS_LISTEN: u64 = 201;
+    pub const SYS_ACCEPT: u64 = 202;
+    pub const SYS_CONNECT: u64 = 203;
+    pub const SYS_GETSOCKNAME: u64 = 204;
+    pub const SYS_GETPEERNAME: u64 = 205;
+    pub const SYS_SENDTO: u64 = 206;
+    pub const SYS_RECVFROM: u64 = 207;
+    pub const SYS_SHUTDOWN: u64 = 210;
+    pub const SYS_SENDMSG: u64 = 211;
+    pub const SYS_RECVMSG: u64 = 212;
+    pub const SYS_MUNMAP: u64 = 215;
+    pub const SYS_CLONE: u64 = 220;
+    pub const SYS_EXECVE: u64 = 221;
+    pub const SYS_MMAP: u64 = 222;
+    pub const SYS_ACCEPT4: u64 = 242;
+    pub const SYS_RENAMEAT2: u64 = 276;
+    pub const SYS_EXECVEAT: u64 = 281;
+    pub const SYS_COPY_FILE_RANGE: u64 = 285;
+    pub const SYS_STATX: u64 = 291;
+    pub const SYS_IO_URING_SETUP: u64 = 425;
+    pub const SYS_IO_URING_ENTER: u64 = 426;
+
+    pub const SYS_OPEN: u64 = NONE + 0;
+    pub const SYS_STAT: u64 = NONE + 1;
+    pub const SYS_LSTAT: u64 = NONE + 2;
+    pub const SYS_PIPE: u64 = NONE + 3;
+    pub const SYS_DUP2: u64 = NONE + 4;
+    pub const SYS_FORK: u64 = NONE + 5;
+    pub const SYS_VFORK: u64 = NONE + 6;
+    pub const SYS_RENAME: u64 = NONE + 7;
+    pub const SYS_MKDIR: u64 = NONE + 8;
+    pub const SYS_RMDIR: u64 = NONE + 9;
+    pub const SYS_CREAT: u64 = NONE + 10;
+    pub const SYS_LINK: u64 = NONE + 11;
+    pub const SYS_UNLINK: u64 = NONE + 12;
+    pub const SYS_SYMLINK: u64 = NONE + 13;
+    pub const SYS_READLINK: u64 = NONE + 14;
+    pub const SYS_CHMOD: u64 = NONE + 15;
+    pub const SYS_CHOWN: u64 = NONE + 16;
+}
+
+/// The native syscall an i386 one decodes as: same arguments in the same
+/// order, with no structs whose layout differs between the two. Vectored
+/// I/O, msghdr calls, socketcall, sigaction and io_uring are left out.
+#[cfg(target_arch = "x86_64")]
+pub fn i386_to_native(nr: u64) -> Option<u64> {
+    Some(match nr {
+        1 => SYS_EXIT,
+        3 => SYS_READ,
+        4 => SYS_WRITE,
+        5 => SYS_OPEN,
+        6 => SYS_CLOSE,
+        8 => SYS_CREAT,
+        9 => SYS_LINK,
+        10 => SYS_UNLINK,
+        15 => SYS_CHMOD,
+        38 => SYS_RENAME,
+        39 => SYS_MKDIR,
+        40 => SYS_RMDIR,
+        83 => SYS_SYMLINK,
+        85 => SYS_READLINK,
+        91 => SYS_MUNMAP,
+        92 => SYS_TRUNCATE,
+        93 => SYS_FTRUNCATE,
+        180 => SYS_PREAD64,
+        181 => SYS_PWRITE64,
+        187 => SYS_SENDFILE,
+        192 => SYS_MMAP,
+        195 => SYS_STAT,
+        196 => SYS_LSTAT,
+        197 => SYS_FSTAT,
+        212 => SYS_CHOWN,
+        252 => SYS_EXIT_GROUP,
+        295 => SYS_OPENAT,
+        296 => SYS_MKDIRAT,
+        298 => SYS_FCHOWNAT,
+        300 => SYS_NEWFSTATAT,
+        301 => SYS_UNLINKAT,
+        302 => SYS_RENAMEAT,
+        303 => SYS_LINKAT,
+        304 => SYS_SYMLINKAT,
+        305 => SYS_READLINKAT,
+        306 => SYS_FCHMODAT,
+        307 => SYS_FACCESSAT,
+        313 => SYS_SPLICE,
+        353 => SYS_RENAMEAT2,
+        359 => SYS_SOCKET,
+        360 => SYS_SOCKETPAIR,
+        361 => SYS_BIND,
+        362 => SYS_CONNECT,
+        363 => SYS_LISTEN,
+        364 => SYS_ACCEPT4,
+        367 => SYS_GETSOCKNAME,
+        368 => SYS_GETPEERNAME,
+        369 => SYS_SENDTO,
+        371 => SYS_RECVFROM,
+        373 => SYS_SHUTDOWN,
+        377 => SYS_COPY_FILE_RANGE,
+        383 => SYS_STATX,
+        _ => return None,
+    })
+}
 
 pub fn syscall_name(nr: u64) -> &'static str {
     match nr {
@@ -68,6 +230,13 @@ pub fn syscall_name(nr: u64) -> &'static str {
         SYS_STAT => "stat",
         SYS_FSTAT => "fstat",
         SYS_LSTAT => "lstat",
+        SYS_MMAP => "mmap",
+        SYS_MUNMAP => "munmap",
+        SYS_RT_SIGACTION => "rt_sigaction",
+        SYS_SENDFILE => "sendfile",
+        SYS_SPLICE => "splice",
+        SYS_COPY_FILE_RANGE => "copy_file_range",
+        SYS_STATX => "statx",
         SYS_PREAD64 => "pread64",
         SYS_PWRITE64 => "pwrite64",
         SYS_READV => "readv",
@@ -107,6 +276,10 @@ pub fn syscall_name(nr: u64) -> &'static str {
         SYS_READLINK => "readlink",
         SYS_CHMOD => "chmod",
         SYS_CHOWN => "chown",
+        SYS_FCHOWNAT => "fchownat",
+        SYS_LINKAT => "linkat",
+        SYS_SYMLINKAT => "symlinkat",
+        SYS_READLINKAT => "readlinkat",
         SYS_OPENAT => "openat",
         SYS_MKDIRAT => "mkdirat",
         SYS_UNLINKAT => "unlinkat",
@@ -119,6 +292,8 @@ pub fn syscall_name(nr: u64) -> &'static str {
         SYS_EXIT_GROUP => "exit_group",
         SYS_NEWFSTATAT => "newfstatat",
         SYS_PIPE2 => "pipe2",
+        SYS_IO_URING_SETUP => "io_uring_setup",
+        SYS_IO_URING_ENTER => "io_uring_enter",
         _ => "unknown",
     }
 }
@@ -149,6 +324,10 @@ pub fn is_file_syscall(nr: u64) -> bool {
             | SYS_READLINK
             | SYS_CHMOD
             | SYS_CHOWN
+            | SYS_FCHOWNAT
+            | SYS_LINKAT
+            | SYS_SYMLINKAT
+            | SYS_READLINKAT
             | SYS_OPENAT
             | SYS_MKDIRAT
             | SYS_UNLINKAT
@@ -157,6 +336,12 @@ pub fn is_file_syscall(nr: u64) -> bool {
             | SYS_FACCESSAT
             | SYS_RENAMEAT2
             | SYS_NEWFSTATAT
+            | SYS_STATX
+            | SYS_SENDFILE
+            | SYS_SPLICE
+            | SYS_COPY_FILE_RANGE
+            | SYS_MMAP
+            | SYS_MUNMAP
     )
 }
 
@@ -181,13 +366,24 @@ pub fn is_net_syscall(nr: u64) -> bool {
 }
 
 pub fn is_interesting_syscall(nr: u64) -> bool {
-    is_file_syscall(nr) || is_net_syscall(nr) || is_process_syscall(nr)
+    is_file_syscall(nr) || is_net_syscall(nr) || is_process_syscall(nr) || is_io_uring_syscall(nr)
+}
+
+pub fn is_io_uring_syscall(nr: u64) -> bool {
+    matches!(nr, SYS_IO_URING_SETUP | SYS_IO_URING_ENTER | SYS_MMAP)
 }
 
 pub fn is_process_syscall(nr: u64) -> bool {
     matches!(
         nr,
-        SYS_CLONE | SYS_FORK | SYS_VFORK | SYS_EXECVE | SYS_EXIT | SYS_EXIT_GROUP | SYS_EXECVEAT
+        SYS_CLONE
+            | SYS_FORK
+            | SYS_VFORK
+            | SYS_EXECVE
+            | SYS_EXIT
+            | SYS_EXIT_GROUP
+            | SYS_EXECVEAT
+            | SYS_RT_SIGACTION
     )
 }
 
@@ -221,7 +417,7 @@ impl SyscallDecoder {
 
     pub fn decode_entry(
         &self,
-        _pid: i32,
+        pid: i32,
         ts: u64,
         nr: u64,
         args: [u64; 6],
@@ -366,6 +562,61 @@ impl SyscallDecoder {
                     ts: rel_ts,
                 }
             }
+            SYS_STATX => {
+                let path = path_reader(args[1]);
+                SyscallEntryInfo::File {
+                    op: FileOpKind::Stat,
+                    path,
+                    fd: Some(args[0] as i32),
+                    flags: Some(args[2] as i32),
+                    ts: rel_ts,
+                }
+            }
+            SYS_COPY_FILE_RANGE | SYS_SPLICE => {
+                let (fd_in, fd_out) = (args[0] as i32, args[2] as i32);
+                SyscallEntryInfo::File {
+                    op: if nr == SYS_SPLICE {
+                        FileOpKind::Splice
+                    } else {
+                        FileOpKind::CopyFileRange
+                    },
+                    path: transfer_path(pid, fd_in, fd_out),
+                    fd: Some(fd_out),
+                    flags: None,
+                    ts: rel_ts,
+                }
+            }
+            SYS_SENDFILE => {
+                let (fd_out, fd_in) = (args[0] as i32, args[1] as i32);
+                SyscallEntryInfo::File {
+                    op: FileOpKind::Sendfile,
+                    path: transfer_path(pid, fd_in, fd_out),
+                    fd: Some(fd_out),
+                    flags: None,
+                    ts: rel_ts,
+                }
+            }
+            SYS_MMAP => {
+                let fd = args[4] as i32;
+                if fd < 0 || args[3] & libc::MAP_ANONYMOUS as u64 != 0 {
+                    SyscallEntryInfo::Ignored
+                } else {
+                    SyscallEntryInfo::File {
+                        op: FileOpKind::Mmap,
+                        path: util::procfs::read_fd_path(pid, fd),
+                        fd: Some(fd),
+                        flags: Some(args[2] as i32),
+                        ts: rel_ts,
+                    }
+                }
+            }
+            SYS_MUNMAP => SyscallEntryInfo::File {
+                op: FileOpKind::Munmap,
+                path: None,
+                fd: None,
+                flags: None,
+                ts: rel_ts,
+            },
             SYS_FSTAT => SyscallEntryInfo::File {
                 op: FileOpKind::Stat,
                 path: None,
@@ -387,8 +638,12 @@ impl SyscallDecoder {
                     ts: rel_ts,
                 }
             }
-            SYS_CHOWN => {
-                let path = path_reader(args[0]);
+            SYS_CHOWN | SYS_FCHOWNAT => {
+                let path = if nr == SYS_FCHOWNAT {
+                    path_reader(args[1])
+                } else {
+                    path_reader(args[0])
+                };
                 SyscallEntryInfo::File {
                     op: FileOpKind::Chown,
                     path,
@@ -397,9 +652,12 @@ impl SyscallDecoder {
                     ts: rel_ts,
                 }
             }
-            SYS_LINK => {
-                let old = path_reader(args[0]);
-                let new = path_reader(args[1]);
+            SYS_LINK | SYS_LINKAT => {
+                let (old, new) = if nr == SYS_LINKAT {
+                    (path_reader(args[1]), path_reader(args[3]))
+                } else {
+                    (path_reader(args[0]), path_reader(args[1]))
+                };
                 let path = match (old, new) {
                     (Some(o), Some(n)) => Some(format!("{} -> {}", o, n)),
                     (Some(o), None) => Some(o),
@@ -413,9 +671,9 @@ impl SyscallDecoder {
                     ts: rel_ts,
                 }
             }
-            SYS_SYMLINK => {
+            SYS_SYMLINK | SYS_SYMLINKAT => {
                 let target = path_reader(args[0]);
-                let linkpath = path_reader(args[1]);
+                let linkpath = path_reader(args[if nr == SYS_SYMLINKAT { 2 } else { 1 }]);
                 let path = match (target, linkpath) {
                     (Some(t), Some(l)) => Some(format!("{} -> {}", l, t)),
                     (_, Some(l)) => Some(l),
@@ -429,8 +687,12 @@ impl SyscallDecoder {
                     ts: rel_ts,
                 }
             }
-            SYS_READLINK => {
-                let path = path_reader(args[0]);
+            SYS_READLINK | SYS_READLINKAT => {
+                let path = if nr == SYS_READLINKAT {
+                    path_reader(args[1])
+                } else {
+                    path_reader(args[0])
+                };
                 SyscallEntryInfo::File {
                     op: FileOpKind::Readlink,
                     path,
@@ -467,8 +729,8 @@ impl SyscallDecoder {
                 }
             }
 
-            SYS_SOCKET => {
-                let proto = decode_socket_domain(args[0] as i32);
+            SYS_SOCKET | SYS_SOCKETPAIR => {
+                let proto = classify_socket(args[0] as i32, args[1] as i32);
                 SyscallEntryInfo::Net {
                     op: NetOpKind::Socket,
                     proto: Some(proto),
@@ -547,6 +809,7 @@ impl SyscallDecoder {
         entry: &SyscallEntryInfo,
         ret: i64,
         nr: u64,
+        args: [u64; 6],
     ) -> Option<FileEvent> {
         if let SyscallEntryInfo::File {
             op,
@@ -557,13 +820,15 @@ impl SyscallDecoder {
         } = entry
         {
             let bytes = match nr {
-                SYS_READ | SYS_PREAD64 | SYS_READV | SYS_WRITE | SYS_PWRITE64 | SYS_WRITEV => {
+                SYS_READ | SYS_PREAD64 | SYS_READV | SYS_WRITE | SYS_PWRITE64 | SYS_WRITEV
+                | SYS_SENDFILE | SYS_SPLICE | SYS_COPY_FILE_RANGE => {
                     if ret >= 0 {
                         Some(ret as u64)
                     } else {
                         None
                     }
                 }
+                SYS_MMAP | SYS_MUNMAP => Some(args[1]),
                 _ => None,
             };
 
@@ -585,6 +850,7 @@ impl SyscallDecoder {
                 bytes,
                 flags: *flags,
                 result: Some(ret),
+                duration_ns: None,
             })
         } else {
             None
@@ -643,6 +909,7 @@ impl SyscallDecoder {
                 | SYS_SENDMSG | SYS_RECVMSG => Some(args[0] as i32),
                 _ => None,
             };
+            let listen_fd = matches!(nr, SYS_ACCEPT | SYS_ACCEPT4).then_some(args[0] as i32);
 
             Some(NetEvent {
                 ts: *ts,
@@ -654,6 +921,8 @@ impl SyscallDecoder {
                 bytes,
                 fd,
                 result: Some(ret),
+                duration_ns: None,
+                listen_fd,
             })
         } else {
             None
@@ -679,7 +948,29 @@ pub enum SyscallEntryInfo {
     Ignored,
 }
 
-fn decode_socket_domain(domain: i32) -> String {
+fn transfer_path(pid: i32, fd_in: i32, fd_out: i32) -> Option<String> {
+    let src = util::procfs::read_fd_path(pid, fd_in);
+    let dst = util::procfs::read_fd_path(pid, fd_out);
+    match (src, dst) {
+        (Some(s), Some(d)) => Some(format!("{} -> {}", s, d)),
+        (Some(s), None) => Some(s),
+        (None, Some(d)) => Some(d),
+        _ => None,
+    }
+}
+
+pub(crate) fn classify_socket(domain: i32, sock_type: i32) -> String {
+    let sock_type = sock_type & 0xf;
+    match domain {
+        libc::AF_INET | libc::AF_INET6 if sock_type == libc::SOCK_STREAM => "tcp".into(),
+        libc::AF_INET | libc::AF_INET6 if sock_type == libc::SOCK_DGRAM => "udp".into(),
+        libc::AF_UNIX if sock_type == libc::SOCK_DGRAM => "unix-dgram".into(),
+        libc::AF_UNIX => "unix".into(),
+        _ => decode_socket_domain(domain),
+    }
+}
+
+pub(crate) fn decode_socket_domain(domain: i32) -> String {
     match domain {
         libc::AF_UNIX => "unix".into(),
         libc::AF_INET => "ipv4".into(),
@@ -689,7 +980,7 @@ fn decode_socket_domain(domain: i32) -> String {
     }
 }
 
-fn decode_sockaddr(
+pub(crate) fn decode_sockaddr(
     addr_ptr: u64,
     addr_len: usize,
     reader: &dyn Fn(u64, usize) -> Option<Vec<u8>>,
@@ -743,3 +1034,49 @@ fn decode_sockaddr(
         _ => Some(format!("family={}", family)),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn at_forms_decode_like_their_legacy_calls() {
+        let decoder = SyscallDecoder::new();
+        let paths = |addr: u64| Some(format!("/p{}", addr));
+        let no_addr = |_: u64, _: usize| None;
+        let decode = |nr: u64, args: [u64; 6]| match decoder
+            .decode_entry(1, 0, nr, args, &paths, &no_addr)
+        {
+            SyscallEntryInfo::File { op, path, .. } => (op, path),
+            other => panic!("{:?}", other),
+        };
+
+        let link = decode(SYS_LINKAT, [0, 1, 0, 2, 0, 0]);
+        assert_eq!(link, (FileOpKind::Link, Some("/p1 -> /p2".into())));
+        assert_eq!(
+            decode(SYS_SYMLINKAT, [1, 0, 2, 0, 0, 0]),
+            (FileOpKind::Symlink, Some("/p2 -> /p1".into()))
+        );
+        assert_eq!(
+            decode(SYS_READLINKAT, [0, 3, 0, 0, 0, 0]).1.as_deref(),
+            Some("/p3")
+        );
+        assert_eq!(
+            decode(SYS_FCHOWNAT, [0, 4, 0, 0, 0, 0]).0,
+            FileOpKind::Chown
+        );
+        assert_eq!(syscall_name(SYS_LINKAT), "linkat");
+        assert!(is_file_syscall(SYS_READLINKAT));
+    }
+
+    #[test]
+    #[cfg(target_arch = "x86_64")]
+    fn i386_calls_map_onto_native_ones() {
+        assert_eq!(i386_to_native(5), Some(SYS_OPEN));
+        assert_eq!(i386_to_native(295), Some(SYS_OPENAT));
+        assert_eq!(i386_to_native(362), Some(SYS_CONNECT));
+        // socketcall and writev pass structs laid out for 32 bits.
+        assert_eq!(i386_to_native(102), None);
+        assert_eq!(i386_to_native(146), None);
+    }
+}
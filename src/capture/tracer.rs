@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::ffi::CString;
 use std::os::unix::io::RawFd;
 use std::sync::mpsc;
@@ -9,28 +9,88 @@ use nix::sys::signal::Signal;
 use nix::sys::wait::{waitpid, WaitPidFlag, WaitStatus};
 use nix::unistd::Pid;
 
+use crate::capture::arch::{self, Abi, SyscallStop};
+use crate::capture::control::{self, CaptureControl};
+use crate::capture::dbwire::{self, DbConn, WireEvent};
+use crate::capture::failstacks::FailureStacks;
+use crate::capture::io_uring::{IoUringTracker, RingParams};
 use crate::capture::syscalls::*;
 use crate::events::types::*;
+use crate::redact::{Redactor, StreamRedactor};
 use crate::util;
 
+/// Per-syscall cap on bytes kept for a `--watch-file` payload.
+const MAX_PAYLOAD_BYTES: usize = 1024 * 1024;
+const MAX_IOVECS: usize = 1024;
+/// Longest write checked for a `POE:CAPTURE` marker.
+const MAX_MARKER_BYTES: u64 = 64;
+
 struct TracedProcess {
     pid: Pid,
+    tgid: i32,
     pending_syscall: Option<PendingSyscall>,
     alive: bool,
+    /// Matched `--exclude-children` (or descends from one that did): runs
+    /// without syscall stops, only its signals and exit are seen.
+    excluded: bool,
+    /// Network namespace rx/tx bytes when the task started.
+    net_dev_start: Option<(u64, u64)>,
+    /// Set once the task makes a syscall in a non-native ABI, e.g. after
+    /// exec'ing a 32-bit helper.
+    foreign_abi: Option<ForeignAbi>,
+}
+
+struct ForeignAbi {
+    abi: Abi,
+    decoded: u64,
+    /// Syscalls with no native counterpart, left out of the trace.
+    skipped: u64,
 }
 
 struct PendingSyscall {
+    abi: Abi,
     nr: u64,
     args: [u64; 6],
     entry_info: SyscallEntryInfo,
 }
 
+#[derive(Clone)]
 pub struct TracerConfig {
     pub capture_mode: CaptureMode,
+    pub stdin_fd: Option<RawFd>,
     pub stdout_fd: Option<RawFd>,
     pub stderr_fd: Option<RawFd>,
+    /// Make the child a session leader with its stdin as controlling terminal.
+    pub controlling_tty: bool,
     pub env_overrides: HashMap<String, String>,
     pub clear_cloexec_fds: Vec<RawFd>,
+    /// Hash regular files written by the child when their fd is closed,
+    /// skipping files larger than this many bytes.
+    pub hash_writes: Option<u64>,
+    /// Absolute paths whose read/write buffers are recorded in full.
+    pub watch_files: Vec<String>,
+    /// Decode Postgres and MySQL client traffic into `db_query` events.
+    pub capture_sql: bool,
+    /// Decode Redis and memcached client traffic into `cache_op` events.
+    pub capture_cache: bool,
+    /// Decode cleartext HTTP/2 client traffic into `h2_stream` events.
+    pub capture_http2: bool,
+    /// Fd the child writes `POE:CAPTURE OFF/ON` markers to.
+    pub control_fd: Option<RawFd>,
+    /// Program name patterns whose processes stop being traced once exec'd.
+    pub exclude_children: Vec<String>,
+    /// Redact secrets from watched-file payloads before they are sent on.
+    pub redact: Option<Redactor>,
+    /// Trace children created with vfork or clone(CLONE_VFORK), which is
+    /// how glibc's posix_spawn starts them.
+    pub follow_vfork: bool,
+}
+
+/// A watched fd's redactor and the last payload sent for it, whose path
+/// and op the held-back bytes are flushed under.
+struct PayloadStream {
+    redactor: StreamRedactor,
+    last: FilePayload,
 }
 
 pub struct Tracer {
@@ -39,18 +99,45 @@ pub struct Tracer {
     root_pid: Option<Pid>,
     event_tx: mpsc::Sender<TraceEvent>,
     decoder: SyscallDecoder,
+    io_uring: IoUringTracker,
+    file_mappings: HashMap<(i32, u64), Option<String>>,
+    socket_protos: HashMap<(i32, i32), String>,
+    written_fds: HashMap<(i32, i32), String>,
+    watched_fds: HashMap<(i32, i32), Option<String>>,
+    /// Under `--redact`, each watched fd's redactor, holding back an
+    /// incomplete line until the next read or write completes it.
+    payload_streams: HashMap<(i32, i32), PayloadStream>,
+    db_conns: HashMap<(i32, i32), DbConn>,
+    early_stopped: HashSet<i32>,
+    /// vfork children and the parents held in their event stop until the
+    /// child execs or exits; until then the two share an address space.
+    vfork_parents: HashMap<i32, Pid>,
+    /// Stacks of failing file and net syscalls, in full mode.
+    failure_stacks: Option<FailureStacks>,
     base_ts: u64,
 }
 
 impl Tracer {
     pub fn new(config: TracerConfig, event_tx: mpsc::Sender<TraceEvent>) -> Self {
         let base_ts = util::timestamp_ns();
+        let failure_stacks =
+            (config.capture_mode == CaptureMode::Full).then(FailureStacks::default);
         Self {
             config,
             processes: HashMap::new(),
             root_pid: None,
             event_tx,
             decoder: SyscallDecoder::new(),
+            io_uring: IoUringTracker::new(),
+            file_mappings: HashMap::new(),
+            socket_protos: HashMap::new(),
+            written_fds: HashMap::new(),
+            watched_fds: HashMap::new(),
+            payload_streams: HashMap::new(),
+            db_conns: HashMap::new(),
+            early_stopped: HashSet::new(),
+            vfork_parents: HashMap::new(),
+            failure_stacks,
             base_ts,
         }
     }
@@ -68,36 +155,11 @@ impl Tracer {
             .map(|a| CString::new(a.as_str()).unwrap())
             .collect();
 
-        let stdout_fd = self.config.stdout_fd;
-        let stderr_fd = self.config.stderr_fd;
-        let env_overrides = self.config.env_overrides.clone();
-        let clear_cloexec_fds = self.config.clear_cloexec_fds.clone();
-
         let fork_result = unsafe { nix::unistd::fork() }?;
 
         match fork_result {
             nix::unistd::ForkResult::Child => {
-                if let Some(fd) = stdout_fd {
-                    nix::unistd::dup2(fd, 1).ok();
-                    nix::unistd::close(fd).ok();
-                }
-                if let Some(fd) = stderr_fd {
-                    nix::unistd::dup2(fd, 2).ok();
-                    nix::unistd::close(fd).ok();
-                }
-
-                for fd in &clear_cloexec_fds {
-                    unsafe {
-                        let flags = libc::fcntl(*fd, libc::F_GETFD);
-                        if flags >= 0 {
-                            libc::fcntl(*fd, libc::F_SETFD, flags & !libc::FD_CLOEXEC);
-                        }
-                    }
-                }
-
-                for (key, val) in &env_overrides {
-                    std::env::set_var(key, val);
-                }
+                prepare_child(&self.config);
 
                 ptrace::traceme().expect("PTRACE_TRACEME failed");
 
@@ -117,13 +179,7 @@ impl Tracer {
                     other => bail!("unexpected initial wait status: {:?}", other),
                 }
 
-                let opts = ptrace::Options::PTRACE_O_TRACESYSGOOD
-                    | ptrace::Options::PTRACE_O_TRACEFORK
-                    | ptrace::Options::PTRACE_O_TRACEVFORK
-                    | ptrace::Options::PTRACE_O_TRACECLONE
-                    | ptrace::Options::PTRACE_O_TRACEEXEC
-                    | ptrace::Options::PTRACE_O_TRACEEXIT;
-                ptrace::setoptions(child, opts)?;
+                ptrace::setoptions(child, self.ptrace_options())?;
 
                 let cwd = util::procfs::read_cwd(raw_pid).unwrap_or_default();
 
@@ -133,14 +189,19 @@ impl Tracer {
                     argv: argv.to_vec(),
                     cwd,
                     start_ts: 0,
+                    tgid: Some(raw_pid),
                 };
 
                 self.processes.insert(
                     raw_pid,
                     TracedProcess {
                         pid: child,
+                        tgid: raw_pid,
                         pending_syscall: None,
                         alive: true,
+                        excluded: false,
+                        net_dev_start: util::procfs::read_net_dev(raw_pid).ok(),
+                        foreign_abi: None,
                     },
                 );
 
@@ -170,14 +231,14 @@ impl Tracer {
             match status {
                 WaitStatus::PtraceSyscall(pid) => {
                     self.handle_syscall(pid)?;
-                    if ptrace::syscall(pid, None).is_err() {
+                    if self.resume(pid, None).is_err() {
                         self.mark_dead(pid.as_raw());
                     }
                 }
 
                 WaitStatus::PtraceEvent(pid, _sig, event) => {
-                    self.handle_ptrace_event(pid, event)?;
-                    if ptrace::syscall(pid, None).is_err() {
+                    let resume = self.handle_ptrace_event(pid, event)?;
+                    if resume && self.resume(pid, None).is_err() {
                         self.mark_dead(pid.as_raw());
                     }
                 }
@@ -191,6 +252,7 @@ impl Tracer {
                         signal: None,
                     }));
                     self.mark_dead(pid.as_raw());
+                    self.forget_process(pid.as_raw());
 
                     if pid == root_pid {
                         root_exit_code = Some(code);
@@ -219,6 +281,7 @@ impl Tracer {
                     }));
 
                     self.mark_dead(pid.as_raw());
+                    self.forget_process(pid.as_raw());
 
                     if pid == root_pid {
                         root_signal = Some(sig_num);
@@ -228,6 +291,14 @@ impl Tracer {
                     }
                 }
 
+                // A new child's initial stop can be reported before the parent's
+                // fork event; hold it until the event handler picks it up.
+                WaitStatus::Stopped(pid, Signal::SIGSTOP)
+                    if !self.processes.contains_key(&pid.as_raw()) =>
+                {
+                    self.early_stopped.insert(pid.as_raw());
+                }
+
                 WaitStatus::Stopped(pid, sig) => {
                     let deliver = match sig {
                         Signal::SIGSTOP | Signal::SIGTRAP => None,
@@ -247,11 +318,8 @@ impl Tracer {
                                 format!("received {} ({})", util::signal_name(sig_num), sig_num);
 
                             if is_crash {
-                                if let Ok(regs) = ptrace::getregs(pid) {
-                                    detail.push_str(&format!(
-                                        " rip={:#x} rsp={:#x} rbp={:#x} rax={:#x} rdi={:#x} rsi={:#x}",
-                                        regs.rip, regs.rsp, regs.rbp, regs.rax, regs.rdi, regs.rsi,
-                                    ));
+                                if let Some(regs) = arch::crash_registers(pid) {
+                                    detail.push_str(&regs);
                                 }
 
                                 if let Ok(siginfo) = ptrace::getsiginfo(pid) {
@@ -276,7 +344,7 @@ impl Tracer {
                             Some(sig)
                         }
                     };
-                    if ptrace::syscall(pid, deliver).is_err() {
+                    if self.resume(pid, deliver).is_err() {
                         self.mark_dead(pid.as_raw());
                     }
                 }
@@ -287,28 +355,49 @@ impl Tracer {
             }
         }
 
+        self.flush_written_files(None);
+        self.flush_db_conns(None);
+        self.flush_payloads(None);
         Ok((root_exit_code, root_signal))
     }
 
+    /// Counts a syscall made in a non-native ABI; the task's totals are
+    /// reported as it exits.
+    fn note_foreign_abi(&mut self, pid: i32, abi: Abi, decoded: bool) {
+        let Some(proc) = self.processes.get_mut(&pid) else {
+            return;
+        };
+        let foreign = proc.foreign_abi.get_or_insert_with(|| {
+            log::debug!("pid {} made a {} syscall", pid, abi.name());
+            ForeignAbi {
+                abi,
+                decoded: 0,
+                skipped: 0,
+            }
+        });
+        foreign.abi = abi;
+        if decoded {
+            foreign.decoded += 1;
+        } else {
+            foreign.skipped += 1;
+        }
+    }
+
     fn handle_syscall(&mut self, pid: Pid) -> Result<()> {
         let raw = pid.as_raw();
 
-        let regs = match ptrace::getregs(pid) {
-            Ok(r) => r,
+        let stop = match arch::syscall_stop(pid) {
+            Ok(stop) => stop,
             Err(_) => return Ok(()),
         };
 
-        let nr = regs.orig_rax;
-        let rax = regs.rax as i64;
-
-        // On x86_64, at syscall entry the kernel sets rax = -ENOSYS (-38).
-        // At syscall exit, rax contains the return value.
-        // We use this to determine entry vs exit without fragile toggling.
-        let is_entry = rax == -38;
-
-        if is_entry {
-            let args = [regs.rdi, regs.rsi, regs.rdx, regs.r10, regs.r8, regs.r9];
-
+        if let SyscallStop::Entry { abi, nr, args } = stop {
+            if abi != Abi::Native {
+                self.note_foreign_abi(raw, abi, nr.is_some());
+            }
+            let Some(nr) = nr else {
+                return Ok(());
+            };
             if is_interesting_syscall(nr) {
                 let path_reader =
                     |addr: u64| -> Option<String> { read_string_from_process(pid, addr, 4096) };
@@ -321,43 +410,146 @@ impl Tracer {
                     self.decoder
                         .decode_entry(raw, ts, nr, args, &path_reader, &addr_reader);
 
+                if nr == SYS_IO_URING_ENTER {
+                    let tgid = self.processes.get(&raw).map(|p| p.tgid).unwrap_or(raw);
+                    self.io_uring
+                        .on_submit(tgid, args[0] as i32, ts, &path_reader, &addr_reader);
+                }
+
                 if let Some(proc) = self.processes.get_mut(&raw) {
                     proc.pending_syscall = Some(PendingSyscall {
+                        abi,
                         nr,
                         args,
                         entry_info,
                     });
                 }
             }
-        } else {
-            let ret = rax;
-
-            if let Some(proc) = self.processes.get_mut(&raw) {
-                if let Some(pending) = proc.pending_syscall.take() {
-                    match &pending.entry_info {
-                        SyscallEntryInfo::File { .. } => {
-                            if let Some(file_event) = self.decoder.finalize_file_event(
-                                raw,
-                                &pending.entry_info,
-                                ret,
-                                pending.nr,
-                            ) {
-                                let _ = self.event_tx.send(TraceEvent::File(file_event));
+        } else if let SyscallStop::Exit { ret } = stop {
+            let exit_ts = self.relative_ts();
+
+            let pending = self.processes.get_mut(&raw).and_then(|proc| {
+                proc.pending_syscall
+                    .take()
+                    .map(|pending| (proc.tgid, pending))
+            });
+
+            if let Some((tgid, pending)) = pending {
+                if pending.nr == SYS_RT_SIGACTION && ret == 0 && pending.args[1] != 0 {
+                    self.record_signal_handler(pid, &pending);
+                }
+
+                if is_io_uring_syscall(pending.nr) {
+                    self.handle_io_uring_exit(pid, tgid, &pending, ret);
+                } else if pending.nr == SYS_CLOSE && ret == 0 {
+                    let fd = pending.args[0] as i32;
+                    self.socket_protos.remove(&(tgid, fd));
+                    self.watched_fds.remove(&(tgid, fd));
+                    self.flush_payload((tgid, fd));
+                    if let Some(conn) = self.db_conns.remove(&(tgid, fd)) {
+                        self.send_wire_events(conn.peer.clone(), conn.close());
+                    }
+                    if let Some(path) = self.written_fds.remove(&(tgid, fd)) {
+                        self.hash_written_file(tgid, path);
+                    }
+                    if self.io_uring.is_ring_fd(tgid, fd) {
+                        self.io_uring.on_close(tgid, fd);
+                    }
+                }
+
+                match &pending.entry_info {
+                    SyscallEntryInfo::File { .. } => {
+                        if let Some(mut file_event) = self.decoder.finalize_file_event(
+                            raw,
+                            &pending.entry_info,
+                            ret,
+                            pending.nr,
+                            pending.args,
+                        ) {
+                            file_event.duration_ns = Some(exit_ts.saturating_sub(file_event.ts));
+                            if pending.nr == SYS_WRITE
+                                && ret > 0
+                                && pending.args[2] <= MAX_MARKER_BYTES
+                                && self.config.control_fd.is_some()
+                            {
+                                if let Some(event) = self.capture_marker(pid, &pending, &file_event)
+                                {
+                                    let _ = self.event_tx.send(event);
+                                    return Ok(());
+                                }
+                            }
+                            match file_event.op {
+                                FileOpKind::Mmap if ret >= 0 => {
+                                    self.file_mappings
+                                        .insert((tgid, ret as u64), file_event.path.clone());
+                                }
+                                // Only unmaps of file-backed regions we saw mapped are reported.
+                                FileOpKind::Munmap => {
+                                    match self.file_mappings.remove(&(tgid, pending.args[0])) {
+                                        Some(path) => file_event.path = path,
+                                        None => return Ok(()),
+                                    }
+                                }
+                                FileOpKind::Open if ret >= 0 => {
+                                    self.watched_fds.remove(&(tgid, ret as i32));
+                                    self.flush_payload((tgid, ret as i32));
+                                }
+                                FileOpKind::Write if ret > 0 => {
+                                    if let Some(fd) = file_event.fd {
+                                        self.track_written_fd(tgid, fd);
+                                    }
+                                    self.record_payload(pid, tgid, &pending, &file_event, ret);
+                                    self.record_db_traffic(pid, tgid, &pending, file_event.fd, ret);
+                                }
+                                // A full or read-only filesystem needs the path to
+                                // say which mount, and the fd may have come from a
+                                // dup2 poe doesn't follow.
+                                FileOpKind::Write
+                                    if [libc::ENOSPC, libc::EDQUOT, libc::EROFS]
+                                        .contains(&(-ret as i32)) =>
+                                {
+                                    file_event.path = file_event
+                                        .fd
+                                        .and_then(|fd| util::procfs::read_fd_path(tgid, fd));
+                                }
+                                FileOpKind::Read if ret > 0 => {
+                                    self.record_payload(pid, tgid, &pending, &file_event, ret);
+                                    self.record_db_traffic(pid, tgid, &pending, file_event.fd, ret);
+                                }
+                                _ => {}
                             }
+                            self.record_failure_stack(
+                                pid,
+                                tgid,
+                                file_event.ts,
+                                file_event.op.as_str(),
+                                ret,
+                            );
+                            let _ = self.event_tx.send(TraceEvent::File(file_event));
                         }
-                        SyscallEntryInfo::Net { .. } => {
-                            if let Some(net_event) = self.decoder.finalize_net_event(
-                                raw,
-                                &pending.entry_info,
+                    }
+                    SyscallEntryInfo::Net { .. } => {
+                        if let Some(mut net_event) = self.decoder.finalize_net_event(
+                            raw,
+                            &pending.entry_info,
+                            ret,
+                            pending.nr,
+                            pending.args,
+                        ) {
+                            net_event.duration_ns = Some(exit_ts.saturating_sub(net_event.ts));
+                            self.track_socket_proto(pid, tgid, &pending, ret, &mut net_event);
+                            self.track_db_conn(pid, tgid, &pending, ret, &net_event);
+                            self.record_failure_stack(
+                                pid,
+                                tgid,
+                                net_event.ts,
+                                net_event.op.as_str(),
                                 ret,
-                                pending.nr,
-                                pending.args,
-                            ) {
-                                let _ = self.event_tx.send(TraceEvent::Net(net_event));
-                            }
+                            );
+                            let _ = self.event_tx.send(TraceEvent::Net(net_event));
                         }
-                        SyscallEntryInfo::Ignored => {}
                     }
+                    SyscallEntryInfo::Ignored => {}
                 }
             }
         }
@@ -365,7 +557,218 @@ impl Tracer {
         Ok(())
     }
 
-    fn handle_ptrace_event(&mut self, pid: Pid, event: i32) -> Result<()> {
+    /// A `capture_control` event for a marker written to the control fd.
+    /// Other writes cost one short memory read when they are marker sized.
+    fn capture_marker(
+        &self,
+        pid: Pid,
+        pending: &PendingSyscall,
+        file_event: &FileEvent,
+    ) -> Option<TraceEvent> {
+        let buf = read_bytes_from_process(pid, pending.args[1], pending.args[2] as usize)?;
+        let on = control::parse_marker(&buf)?;
+        if !control::is_control_fd(pid.as_raw(), file_event.fd?) {
+            return None;
+        }
+        let detail = CaptureControl {
+            capture: if on { "on" } else { "off" }.into(),
+        };
+        Some(TraceEvent::Generic(Event {
+            ts: file_event.ts,
+            proc_id: file_event.proc_id,
+            kind: EventKind::CaptureControl,
+            detail: serde_json::to_string(&detail).ok()?,
+        }))
+    }
+
+    fn record_failure_stack(&mut self, pid: Pid, tgid: i32, ts: u64, op: &str, ret: i64) {
+        let Some(stacks) = self.failure_stacks.as_mut() else {
+            return;
+        };
+        if let Some(event) = stacks.capture(pid, tgid, ts, pid.as_raw(), op, ret) {
+            let _ = self.event_tx.send(event);
+        }
+    }
+
+    fn handle_io_uring_exit(&mut self, pid: Pid, tgid: i32, pending: &PendingSyscall, ret: i64) {
+        let addr_reader =
+            |addr: u64, len: usize| -> Option<Vec<u8>> { read_bytes_from_process(pid, addr, len) };
+
+        match pending.nr {
+            SYS_IO_URING_SETUP if ret >= 0 => {
+                if let Some(params) = addr_reader(pending.args[1], 120)
+                    .as_deref()
+                    .and_then(RingParams::parse)
+                {
+                    self.io_uring.on_setup(tgid, ret as i32, params);
+                }
+            }
+            SYS_MMAP if ret >= 0 => {
+                self.io_uring
+                    .on_mmap(tgid, pending.args[4] as i32, pending.args[5], ret as u64);
+            }
+            SYS_IO_URING_ENTER => {
+                for event in self.io_uring.on_complete(
+                    pid.as_raw(),
+                    tgid,
+                    pending.args[0] as i32,
+                    &addr_reader,
+                ) {
+                    // Completions are reaped here, so the duration spans
+                    // submission to reap rather than the kernel's own work.
+                    let now = self.relative_ts();
+                    let event = match event {
+                        TraceEvent::Net(mut n) => {
+                            self.annotate_proto(tgid, &mut n);
+                            n.duration_ns = Some(now.saturating_sub(n.ts));
+                            TraceEvent::Net(n)
+                        }
+                        TraceEvent::File(mut f) => {
+                            f.duration_ns = Some(now.saturating_sub(f.ts));
+                            TraceEvent::File(f)
+                        }
+                        other => other,
+                    };
+                    let _ = self.event_tx.send(event);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn record_signal_handler(&mut self, pid: Pid, pending: &PendingSyscall) {
+        let sig = pending.args[0] as i32;
+        let Some(action) =
+            read_bytes_from_process(pid, pending.args[1], SigAction::size(pending.abi))
+                .and_then(|d| SigAction::parse(&d, pending.abi))
+        else {
+            return;
+        };
+
+        let _ = self.event_tx.send(TraceEvent::Generic(Event {
+            ts: self.relative_ts(),
+            proc_id: pid.as_raw(),
+            kind: EventKind::SignalHandler,
+            detail: format!("{} ({}) {}", util::signal_name(sig), sig, action.describe()),
+        }));
+    }
+
+    fn track_socket_proto(
+        &mut self,
+        pid: Pid,
+        tgid: i32,
+        pending: &PendingSyscall,
+        ret: i64,
+        event: &mut NetEvent,
+    ) {
+        match pending.nr {
+            SYS_SOCKETPAIR if ret == 0 => {
+                if let Some(data) = read_bytes_from_process(pid, pending.args[3], 8) {
+                    if data.len() == 8 {
+                        let fds = [
+                            i32::from_ne_bytes(data[0..4].try_into().unwrap()),
+                            i32::from_ne_bytes(data[4..8].try_into().unwrap()),
+                        ];
+                        if let Some(ref proto) = event.proto {
+                            for fd in fds {
+                                self.socket_protos.insert((tgid, fd), proto.clone());
+                            }
+                        }
+                        event.fd = Some(fds[0]);
+                    }
+                }
+            }
+            // Accepted sockets share the protocol of the listening socket.
+            SYS_ACCEPT | SYS_ACCEPT4 if ret >= 0 => {
+                if pending.args[1] != 0 && pending.args[2] != 0 {
+                    let addr_reader = |addr: u64, len: usize| -> Option<Vec<u8>> {
+                        read_bytes_from_process(pid, addr, len)
+                    };
+                    let addr_len = addr_reader(pending.args[2], 4)
+                        .filter(|d| d.len() == 4)
+                        .map(|d| u32::from_ne_bytes(d[..4].try_into().unwrap()) as usize);
+                    if let Some(len) = addr_len {
+                        event.src = decode_sockaddr(pending.args[1], len, &addr_reader);
+                    }
+                }
+                if let Some(proto) = self
+                    .socket_protos
+                    .get(&(tgid, pending.args[0] as i32))
+                    .cloned()
+                {
+                    self.socket_protos.insert((tgid, ret as i32), proto);
+                }
+            }
+            _ => {}
+        }
+
+        self.annotate_proto(tgid, event);
+    }
+
+    fn annotate_proto(&mut self, tgid: i32, event: &mut NetEvent) {
+        let Some(fd) = event.fd else {
+            return;
+        };
+        match event.proto {
+            Some(ref proto) if event.op == NetOpKind::Socket => {
+                self.socket_protos.insert((tgid, fd), proto.clone());
+            }
+            None => event.proto = self.socket_protos.get(&(tgid, fd)).cloned(),
+            _ => {}
+        }
+    }
+
+    /// Resumes a stopped thread until its next syscall or, when excluded,
+    /// until its next signal or exit.
+    fn resume(&self, pid: Pid, sig: Option<Signal>) -> nix::Result<()> {
+        if self
+            .processes
+            .get(&pid.as_raw())
+            .is_some_and(|p| p.excluded)
+        {
+            ptrace::cont(pid, sig)
+        } else {
+            ptrace::syscall(pid, sig)
+        }
+    }
+
+    /// The `--exclude-children` pattern an exec'd program matches. Patterns
+    /// with a `/` are matched against the full path, others the basename.
+    fn exclusion(&self, argv: &[String]) -> Option<String> {
+        let program = argv.first()?;
+        let name = program.rsplit('/').next().unwrap_or(program);
+        self.config
+            .exclude_children
+            .iter()
+            .find(|p| util::glob_match(p, if p.contains('/') { program } else { name }))
+            .cloned()
+    }
+
+    fn ptrace_options(&self) -> ptrace::Options {
+        let mut opts = ptrace::Options::PTRACE_O_TRACESYSGOOD
+            | ptrace::Options::PTRACE_O_TRACEFORK
+            | ptrace::Options::PTRACE_O_TRACECLONE
+            | ptrace::Options::PTRACE_O_TRACEEXEC
+            | ptrace::Options::PTRACE_O_TRACEEXIT;
+        if self.config.follow_vfork {
+            opts |= ptrace::Options::PTRACE_O_TRACEVFORK;
+        }
+        opts
+    }
+
+    /// Lets the parent of a vfork child run again once the child has its
+    /// own address space or is gone.
+    fn release_vfork_parent(&mut self, child: i32) {
+        if let Some(parent) = self.vfork_parents.remove(&child) {
+            if self.resume(parent, None).is_err() {
+                self.mark_dead(parent.as_raw());
+            }
+        }
+    }
+
+    /// Handles a ptrace event stop; false when the stopped task is to stay
+    /// stopped for now.
+    fn handle_ptrace_event(&mut self, pid: Pid, event: i32) -> Result<bool> {
         let ts = self.relative_ts();
 
         match event {
@@ -373,26 +776,57 @@ impl Tracer {
                 let new_pid_raw = ptrace::getevent(pid)? as i32;
                 let new_pid = Pid::from_raw(new_pid_raw);
 
-                let _ = waitpid(new_pid, Some(WaitPidFlag::__WALL));
-
-                let opts = ptrace::Options::PTRACE_O_TRACESYSGOOD
-                    | ptrace::Options::PTRACE_O_TRACEFORK
-                    | ptrace::Options::PTRACE_O_TRACEVFORK
-                    | ptrace::Options::PTRACE_O_TRACECLONE
-                    | ptrace::Options::PTRACE_O_TRACEEXEC
-                    | ptrace::Options::PTRACE_O_TRACEEXIT;
+                if !self.early_stopped.remove(&new_pid_raw) {
+                    match waitpid(new_pid, Some(WaitPidFlag::__WALL)) {
+                        // Killed before its first stop: nothing to trace.
+                        Ok(WaitStatus::Exited(..) | WaitStatus::Signaled(..)) | Err(_) => {
+                            return Ok(true);
+                        }
+                        Ok(_) => {}
+                    }
+                }
 
-                let _ = ptrace::setoptions(new_pid, opts);
+                let _ = ptrace::setoptions(new_pid, self.ptrace_options());
 
                 let cwd = util::procfs::read_cwd(new_pid_raw).unwrap_or_default();
                 let cmdline = util::procfs::read_cmdline(new_pid_raw).unwrap_or_default();
+                let tgid = util::procfs::read_status_field(new_pid_raw, "Tgid")
+                    .ok()
+                    .and_then(|t| t.parse().ok())
+                    .unwrap_or(new_pid_raw);
+
+                // A forked child inherits its parent's descriptor table.
+                let parent_tgid = self
+                    .processes
+                    .get(&pid.as_raw())
+                    .map(|p| p.tgid)
+                    .unwrap_or(pid.as_raw());
+                if tgid != parent_tgid {
+                    let inherited: Vec<(i32, String)> = self
+                        .socket_protos
+                        .iter()
+                        .filter(|((t, _), _)| *t == parent_tgid)
+                        .map(|((_, fd), proto)| (*fd, proto.clone()))
+                        .collect();
+                    for (fd, proto) in inherited {
+                        self.socket_protos.insert((tgid, fd), proto);
+                    }
+                }
 
+                let excluded = self
+                    .processes
+                    .get(&pid.as_raw())
+                    .is_some_and(|p| p.excluded);
                 self.processes.insert(
                     new_pid_raw,
                     TracedProcess {
                         pid: new_pid,
+                        tgid,
                         pending_syscall: None,
                         alive: true,
+                        excluded,
+                        net_dev_start: util::procfs::read_net_dev(new_pid_raw).ok(),
+                        foreign_abi: None,
                     },
                 );
 
@@ -402,13 +836,24 @@ impl Tracer {
                     argv: cmdline,
                     cwd,
                     start_ts: ts,
+                    tgid: Some(tgid),
                 }));
 
-                let _ = ptrace::syscall(new_pid, None);
+                let _ = self.resume(new_pid, None);
+
+                // The kernel keeps a vfork parent from returning until the
+                // child execs or exits anyway; holding it in this stop as
+                // well means it makes no syscalls, and takes no stops, on
+                // the stack and memory the child is still using.
+                if event == libc::PTRACE_EVENT_VFORK {
+                    self.vfork_parents.insert(new_pid_raw, pid);
+                    return Ok(false);
+                }
             }
 
             libc::PTRACE_EVENT_EXEC => {
                 let raw = pid.as_raw();
+                self.release_vfork_parent(raw);
                 let cmdline = util::procfs::read_cmdline(raw).unwrap_or_default();
 
                 let _ = self.event_tx.send(TraceEvent::Generic(Event {
@@ -418,12 +863,30 @@ impl Tracer {
                     detail: serde_json::to_string(&cmdline).unwrap_or_default(),
                 }));
 
+                let pattern = (Some(pid) != self.root_pid)
+                    .then(|| self.exclusion(&cmdline))
+                    .flatten();
                 if let Some(proc) = self.processes.get_mut(&raw) {
                     proc.pending_syscall = None;
+                    if let Some(pattern) = pattern.filter(|_| !proc.excluded) {
+                        proc.excluded = true;
+                        let _ = self.event_tx.send(TraceEvent::Generic(Event {
+                            ts,
+                            proc_id: raw,
+                            kind: EventKind::ProcessExcluded,
+                            detail: serde_json::json!({ "pattern": pattern }).to_string(),
+                        }));
+                    }
+                    let tgid = proc.tgid;
+                    self.io_uring.forget_process(tgid);
+                    if let Some(stacks) = self.failure_stacks.as_mut() {
+                        stacks.forget_process(tgid);
+                    }
                 }
             }
 
             libc::PTRACE_EVENT_EXIT => {
+                self.release_vfork_parent(pid.as_raw());
                 let exit_status = ptrace::getevent(pid)? as i32;
                 let code = if libc::WIFEXITED(exit_status) {
                     Some(libc::WEXITSTATUS(exit_status))
@@ -442,18 +905,360 @@ impl Tracer {
                     kind: EventKind::ProcessExit,
                     detail: format!("exit_code={:?} signal={:?}", code, sig),
                 }));
+
+                if let Some(foreign) = self
+                    .processes
+                    .get_mut(&pid.as_raw())
+                    .and_then(|p| p.foreign_abi.take())
+                {
+                    let _ = self.event_tx.send(TraceEvent::Generic(Event {
+                        ts,
+                        proc_id: pid.as_raw(),
+                        kind: EventKind::ForeignAbi,
+                        detail: serde_json::json!({
+                            "abi": foreign.abi.name(),
+                            "decoded_syscalls": foreign.decoded,
+                            "skipped_syscalls": foreign.skipped,
+                        })
+                        .to_string(),
+                    }));
+                }
+
+                // The task is still in /proc until it is reaped.
+                self.record_process_io(pid);
+                if self.config.capture_mode == CaptureMode::Full {
+                    if let Ok(stat) = util::procfs::read_schedstat(pid.as_raw()) {
+                        let _ = self.event_tx.send(TraceEvent::Generic(Event {
+                            ts,
+                            proc_id: pid.as_raw(),
+                            kind: EventKind::SchedStats,
+                            detail: serde_json::json!({
+                                "run_ns": stat.run_ns,
+                                "wait_ns": stat.wait_ns,
+                                "timeslices": stat.timeslices,
+                                "voluntary_switches": stat.voluntary_switches,
+                                "involuntary_switches": stat.involuntary_switches,
+                            })
+                            .to_string(),
+                        }));
+                    }
+                }
             }
 
             _ => {}
         }
 
-        Ok(())
+        Ok(true)
+    }
+
+    /// Sends the exiting task's I/O counters, so I/O the syscall trace
+    /// doesn't see (mapped files, io_uring) still shows in the totals.
+    fn record_process_io(&self, pid: Pid) {
+        let raw = pid.as_raw();
+        let Some(proc) = self.processes.get(&raw) else {
+            return;
+        };
+        let Ok(io) = util::procfs::read_io(proc.tgid, raw) else {
+            return;
+        };
+        let net = proc
+            .net_dev_start
+            .zip(util::procfs::read_net_dev(raw).ok())
+            .map(|((rx0, tx0), (rx, tx))| (rx.saturating_sub(rx0), tx.saturating_sub(tx0)));
+        let _ = self.event_tx.send(TraceEvent::ProcessIo(ProcessIo {
+            proc_id: raw,
+            rchar: io.rchar,
+            wchar: io.wchar,
+            read_bytes: io.read_bytes,
+            write_bytes: io.write_bytes,
+            cancelled_write_bytes: io.cancelled_write_bytes,
+            net_rx_bytes: net.map(|n| n.0),
+            net_tx_bytes: net.map(|n| n.1),
+        }));
+    }
+
+    fn track_written_fd(&mut self, tgid: i32, fd: i32) {
+        if self.config.hash_writes.is_none() || self.written_fds.contains_key(&(tgid, fd)) {
+            return;
+        }
+        let Some(path) = util::procfs::read_fd_path(tgid, fd) else {
+            return;
+        };
+        if path.starts_with('/') && !path.starts_with("/dev/") && !path.starts_with("/proc/") {
+            self.written_fds.insert((tgid, fd), path);
+        }
+    }
+
+    fn hash_written_file(&mut self, tgid: i32, path: String) {
+        let Some(cap) = self.config.hash_writes else {
+            return;
+        };
+        let Ok(meta) = std::fs::metadata(&path) else {
+            return;
+        };
+        if !meta.is_file() {
+            return;
+        }
+        let hash = if meta.len() <= cap {
+            std::fs::read(&path)
+                .ok()
+                .map(|data| util::hash_bytes(&data))
+        } else {
+            None
+        };
+        let _ = self.event_tx.send(TraceEvent::WrittenFile(WrittenFile {
+            ts: self.relative_ts(),
+            proc_id: tgid,
+            path,
+            size: meta.len(),
+            hash,
+        }));
+    }
+
+    /// Resolves an fd to a `--watch-file` path, caching misses too so
+    /// unwatched fds cost one readlink each.
+    fn watched_path(&mut self, tgid: i32, fd: i32) -> Option<String> {
+        if self.config.watch_files.is_empty() {
+            return None;
+        }
+        let watch_files = &self.config.watch_files;
+        self.watched_fds
+            .entry((tgid, fd))
+            .or_insert_with(|| {
+                util::procfs::read_fd_path(tgid, fd).filter(|p| watch_files.contains(p))
+            })
+            .clone()
+    }
+
+    fn record_payload(
+        &mut self,
+        pid: Pid,
+        tgid: i32,
+        pending: &PendingSyscall,
+        event: &FileEvent,
+        ret: i64,
+    ) {
+        let Some(fd) = event.fd else {
+            return;
+        };
+        let Some(path) = self.watched_path(tgid, fd) else {
+            return;
+        };
+        let len = (ret as usize).min(MAX_PAYLOAD_BYTES);
+        let data = match pending.nr {
+            SYS_READV | SYS_WRITEV => {
+                read_iovecs(pid, pending.args[1], pending.args[2] as usize, len)
+            }
+            _ => read_bytes_from_process(pid, pending.args[1], len),
+        };
+        let Some(data) = data else {
+            return;
+        };
+        let mut payload = FilePayload {
+            ts: event.ts,
+            proc_id: event.proc_id,
+            op: event.op,
+            path,
+            fd,
+            bytes: ret as u64,
+            data: Vec::new(),
+        };
+        payload.data = match self.config.redact {
+            Some(ref redactor) => {
+                let stream =
+                    self.payload_streams
+                        .entry((tgid, fd))
+                        .or_insert_with(|| PayloadStream {
+                            redactor: StreamRedactor::new(redactor.clone()),
+                            last: payload.clone(),
+                        });
+                stream.last = payload.clone();
+                stream.redactor.push(&data)
+            }
+            None => data,
+        };
+        let _ = self.event_tx.send(TraceEvent::FilePayload(payload));
+    }
+
+    /// Sends what a watched fd's redactor still holds once the fd is closed,
+    /// as a payload of no bytes of its own.
+    fn flush_payload(&mut self, key: (i32, i32)) {
+        let Some(mut stream) = self.payload_streams.remove(&key) else {
+            return;
+        };
+        let data = stream.redactor.finish();
+        if data.is_empty() {
+            return;
+        }
+        let _ = self.event_tx.send(TraceEvent::FilePayload(FilePayload {
+            ts: self.relative_ts(),
+            bytes: 0,
+            data,
+            ..stream.last
+        }));
+    }
+
+    fn flush_payloads(&mut self, tgid: Option<i32>) {
+        let keys: Vec<(i32, i32)> = self
+            .payload_streams
+            .keys()
+            .filter(|(t, _)| tgid.is_none_or(|g| *t == g))
+            .copied()
+            .collect();
+        for key in keys {
+            self.flush_payload(key);
+        }
+    }
+
+    fn track_db_conn(
+        &mut self,
+        pid: Pid,
+        tgid: i32,
+        pending: &PendingSyscall,
+        ret: i64,
+        event: &NetEvent,
+    ) {
+        if !self.config.capture_sql && !self.config.capture_cache && !self.config.capture_http2 {
+            return;
+        }
+        match event.op {
+            // Non-blocking clients see EINPROGRESS and carry on.
+            NetOpKind::Connect if ret == 0 || ret == -(libc::EINPROGRESS as i64) => {
+                if matches!(event.proto.as_deref(), Some("tcp" | "unix") | None) {
+                    if let Some(fd) = event.fd {
+                        let conn = DbConn::new(
+                            event.dst.clone(),
+                            self.config.capture_sql,
+                            self.config.capture_cache,
+                            self.config.capture_http2,
+                        );
+                        self.db_conns.insert((tgid, fd), conn);
+                    }
+                }
+            }
+            NetOpKind::Send | NetOpKind::Recv if ret > 0 => {
+                self.record_db_traffic(pid, tgid, pending, event.fd, ret);
+            }
+            _ => {}
+        }
+    }
+
+    /// Feeds the bytes a send/recv/read/write moved on a connected socket
+    /// to its wire decoder.
+    fn record_db_traffic(
+        &mut self,
+        pid: Pid,
+        tgid: i32,
+        pending: &PendingSyscall,
+        fd: Option<i32>,
+        ret: i64,
+    ) {
+        let Some(fd) = fd else {
+            return;
+        };
+        if !self.db_conns.contains_key(&(tgid, fd)) {
+            return;
+        }
+        let len = (ret as usize).min(dbwire::MAX_MESSAGE_LEN);
+        let data = match pending.nr {
+            SYS_READV | SYS_WRITEV => {
+                read_iovecs(pid, pending.args[1], pending.args[2] as usize, len)
+            }
+            SYS_SENDMSG | SYS_RECVMSG => read_bytes_from_process(pid, pending.args[1], 32)
+                .filter(|h| h.len() == 32)
+                .and_then(|h| {
+                    let iov = u64::from_ne_bytes(h[16..24].try_into().unwrap());
+                    let iovlen = u64::from_ne_bytes(h[24..32].try_into().unwrap());
+                    read_iovecs(pid, iov, iovlen as usize, len)
+                }),
+            SYS_READ | SYS_WRITE | SYS_SENDTO | SYS_RECVFROM => {
+                read_bytes_from_process(pid, pending.args[1], len)
+            }
+            _ => None,
+        };
+        let Some(data) = data else {
+            return;
+        };
+        let sending = matches!(
+            pending.nr,
+            SYS_WRITE | SYS_WRITEV | SYS_SENDTO | SYS_SENDMSG
+        );
+        let ts = self.relative_ts();
+        let Some(conn) = self.db_conns.get_mut(&(tgid, fd)) else {
+            return;
+        };
+        let events = if sending {
+            conn.on_send(ts, pid.as_raw(), &data, ret as usize)
+        } else {
+            conn.on_recv(ts, pid.as_raw(), &data, ret as usize)
+        };
+        let peer = conn.peer.clone();
+        if conn.is_off() {
+            self.db_conns.remove(&(tgid, fd));
+        }
+        self.send_wire_events(peer, events);
+    }
+
+    fn send_wire_events(&self, peer: Option<String>, events: Vec<WireEvent>) {
+        for mut e in events {
+            if let Some(ref redactor) = self.config.redact {
+                e.redact(redactor);
+            }
+            let _ = self.event_tx.send(TraceEvent::Generic(Event {
+                ts: e.ts(),
+                proc_id: e.proc_id(),
+                kind: e.kind(),
+                detail: e.to_json(peer.as_deref()).to_string(),
+            }));
+        }
+    }
+
+    /// Requests still unanswered when their connection goes away.
+    fn flush_db_conns(&mut self, tgid: Option<i32>) {
+        let keys: Vec<(i32, i32)> = self
+            .db_conns
+            .keys()
+            .filter(|(t, _)| tgid.is_none_or(|g| *t == g))
+            .copied()
+            .collect();
+        for key in keys {
+            if let Some(conn) = self.db_conns.remove(&key) {
+                self.send_wire_events(conn.peer.clone(), conn.close());
+            }
+        }
+    }
+
+    /// Files still open for writing when their process exits are closed by
+    /// the kernel, so hash them now.
+    fn flush_written_files(&mut self, tgid: Option<i32>) {
+        let keys: Vec<(i32, i32)> = self
+            .written_fds
+            .keys()
+            .filter(|(t, _)| tgid.is_none_or(|g| *t == g))
+            .copied()
+            .collect();
+        for key in keys {
+            if let Some(path) = self.written_fds.remove(&key) {
+                self.hash_written_file(key.0, path);
+            }
+        }
+    }
+
+    fn forget_process(&mut self, tgid: i32) {
+        self.flush_written_files(Some(tgid));
+        self.flush_db_conns(Some(tgid));
+        self.flush_payloads(Some(tgid));
+        self.watched_fds.retain(|(t, _), _| *t != tgid);
+        self.io_uring.forget_process(tgid);
+        self.socket_protos.retain(|(t, _), _| *t != tgid);
+        self.file_mappings.retain(|(t, _), _| *t != tgid);
     }
 
     fn mark_dead(&mut self, raw_pid: i32) {
         if let Some(proc) = self.processes.get_mut(&raw_pid) {
             proc.alive = false;
         }
+        self.release_vfork_parent(raw_pid);
     }
 
     fn all_dead(&self) -> bool {
@@ -473,7 +1278,41 @@ impl Tracer {
     }
 }
 
-fn read_string_from_process(pid: Pid, addr: u64, max_len: usize) -> Option<String> {
+/// Wires up stdio, the controlling terminal, inherited fds and environment
+/// in a freshly forked child, before it execs the target.
+pub(crate) fn prepare_child(config: &TracerConfig) {
+    if let Some(fd) = config.stdin_fd {
+        nix::unistd::dup2(fd, 0).ok();
+        nix::unistd::close(fd).ok();
+    }
+    if let Some(fd) = config.stdout_fd {
+        nix::unistd::dup2(fd, 1).ok();
+        nix::unistd::close(fd).ok();
+    }
+    if let Some(fd) = config.stderr_fd {
+        nix::unistd::dup2(fd, 2).ok();
+        nix::unistd::close(fd).ok();
+    }
+    if config.controlling_tty {
+        nix::unistd::setsid().ok();
+        unsafe { libc::ioctl(0, libc::TIOCSCTTY, 0) };
+    }
+
+    for fd in &config.clear_cloexec_fds {
+        unsafe {
+            let flags = libc::fcntl(*fd, libc::F_GETFD);
+            if flags >= 0 {
+                libc::fcntl(*fd, libc::F_SETFD, flags & !libc::FD_CLOEXEC);
+            }
+        }
+    }
+
+    for (key, val) in &config.env_overrides {
+        std::env::set_var(key, val);
+    }
+}
+
+pub(crate) fn read_string_from_process(pid: Pid, addr: u64, max_len: usize) -> Option<String> {
     if addr == 0 {
         return None;
     }
@@ -524,7 +1363,89 @@ fn read_string_ptrace(pid: Pid, addr: u64, max_len: usize) -> Option<String> {
     Some(String::from_utf8_lossy(&result).into_owned())
 }
 
-fn read_bytes_from_process(pid: Pid, addr: u64, len: usize) -> Option<Vec<u8>> {
+/// `struct sigaction` as rt_sigaction takes it: handler, flags, restorer
+/// and mask. Native tasks use 64-bit words; i386, AArch32 and x32 tasks
+/// the compat layout with 32-bit words and a 64-bit mask.
+#[derive(Debug, PartialEq)]
+struct SigAction {
+    handler: u64,
+    flags: u64,
+    mask: u64,
+}
+
+/// `SA_*` flags worth showing; SA_RESTORER is set by every libc.
+const SA_FLAGS: [(u64, &str); 7] = [
+    (0x1, "SA_NOCLDSTOP"),
+    (0x2, "SA_NOCLDWAIT"),
+    (0x4, "SA_SIGINFO"),
+    (0x0800_0000, "SA_ONSTACK"),
+    (0x1000_0000, "SA_RESTART"),
+    (0x4000_0000, "SA_NODEFER"),
+    (0x8000_0000, "SA_RESETHAND"),
+];
+
+impl SigAction {
+    fn size(abi: Abi) -> usize {
+        match abi {
+            Abi::Native => 32,
+            Abi::Compat | Abi::X32 => 20,
+        }
+    }
+
+    fn parse(data: &[u8], abi: Abi) -> Option<Self> {
+        let word = |i: usize| -> Option<u64> {
+            match abi {
+                Abi::Native => Some(u64::from_ne_bytes(
+                    data.get(i * 8..i * 8 + 8)?.try_into().ok()?,
+                )),
+                Abi::Compat | Abi::X32 => {
+                    Some(u32::from_ne_bytes(data.get(i * 4..i * 4 + 4)?.try_into().ok()?) as u64)
+                }
+            }
+        };
+        let mask_at = Self::size(abi) - 8;
+        Some(Self {
+            handler: word(0)?,
+            flags: word(1)?,
+            mask: u64::from_ne_bytes(data.get(mask_at..mask_at + 8)?.try_into().ok()?),
+        })
+    }
+
+    /// `default`, `ignore` or `handler=0x...`, then any `flags=` and `mask=`.
+    fn describe(&self) -> String {
+        let mut out = match self.handler {
+            0 => "default".to_string(),
+            1 => "ignore".to_string(),
+            addr => format!("handler={:#x}", addr),
+        };
+        let flags: Vec<&str> = SA_FLAGS
+            .iter()
+            .filter(|(bit, _)| self.flags & bit != 0)
+            .map(|(_, name)| *name)
+            .collect();
+        if !flags.is_empty() {
+            out.push_str(&format!(" flags={}", flags.join("|")));
+        }
+        // Shells block everything while a handler runs.
+        if self.mask == u64::MAX {
+            out.push_str(" mask=all");
+            return out;
+        }
+        let mask: Vec<String> = (1..=64)
+            .filter(|sig| self.mask & (1 << (sig - 1)) != 0)
+            .map(|sig| match util::signal_name(sig) {
+                "UNKNOWN" => sig.to_string(),
+                name => name.to_string(),
+            })
+            .collect();
+        if !mask.is_empty() {
+            out.push_str(&format!(" mask={}", mask.join("|")));
+        }
+        out
+    }
+}
+
+pub(crate) fn read_bytes_from_process(pid: Pid, addr: u64, len: usize) -> Option<Vec<u8>> {
     if addr == 0 || len == 0 {
         return None;
     }
@@ -549,6 +1470,26 @@ fn read_bytes_from_process(pid: Pid, addr: u64, len: usize) -> Option<Vec<u8>> {
     Some(buf)
 }
 
+/// Gathers up to `len` bytes from the buffers of a remote iovec array.
+fn read_iovecs(pid: Pid, iov_addr: u64, iovcnt: usize, len: usize) -> Option<Vec<u8>> {
+    let iov_size = std::mem::size_of::<libc::iovec>();
+    let raw = read_bytes_from_process(pid, iov_addr, iovcnt.min(MAX_IOVECS) * iov_size)?;
+    let mut data = Vec::with_capacity(len);
+    for iov in raw.chunks_exact(iov_size) {
+        let remaining = len - data.len();
+        if remaining == 0 {
+            break;
+        }
+        let base = u64::from_ne_bytes(iov[..8].try_into().ok()?);
+        let iov_len = u64::from_ne_bytes(iov[8..16].try_into().ok()?) as usize;
+        let want = iov_len.min(remaining);
+        if want > 0 {
+            data.extend(read_bytes_from_process(pid, base, want)?);
+        }
+    }
+    Some(data)
+}
+
 fn read_bytes_ptrace(pid: Pid, addr: u64, len: usize) -> Option<Vec<u8>> {
     let word_size = std::mem::size_of::<libc::c_long>();
     let mut result = Vec::with_capacity(len);
@@ -565,3 +1506,48 @@ fn read_bytes_ptrace(pid: Pid, addr: u64, len: usize) -> Option<Vec<u8>> {
 
     Some(result)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sigaction_is_read_in_the_tasks_layout() {
+        let mut native = Vec::new();
+        for word in [0x401000u64, 0x1000_0004 | 0x0400_0000, 0x7f00_0000, 1 << 14] {
+            native.extend_from_slice(&word.to_ne_bytes());
+        }
+        let action = SigAction::parse(&native, Abi::Native).unwrap();
+        assert_eq!(
+            action.describe(),
+            "handler=0x401000 flags=SA_SIGINFO|SA_RESTART mask=SIGTERM"
+        );
+
+        // i386: 32-bit handler, flags and restorer, then the 64-bit mask.
+        let mut compat = Vec::new();
+        for word in [0x8049000u32, 0x1000_0000, 0x8048000] {
+            compat.extend_from_slice(&word.to_ne_bytes());
+        }
+        compat.extend_from_slice(&(1u64 << 33).to_ne_bytes());
+        assert_eq!(compat.len(), SigAction::size(Abi::Compat));
+        let action = SigAction::parse(&compat, Abi::Compat).unwrap();
+        assert_eq!(
+            action.describe(),
+            "handler=0x8049000 flags=SA_RESTART mask=34"
+        );
+
+        assert_eq!(SigAction::parse(&[0; 8], Abi::Native), None);
+        let ignore = SigAction {
+            handler: 1,
+            flags: 0,
+            mask: 0,
+        };
+        assert_eq!(ignore.describe(), "ignore");
+        let blocking = SigAction {
+            handler: 0x1000,
+            flags: 0,
+            mask: u64::MAX,
+        };
+        assert_eq!(blocking.describe(), "handler=0x1000 mask=all");
+    }
+}
@@ -0,0 +1,599 @@
+//! Capture backend built on seccomp user-space notification.
+//!
+//! The child installs a filter that routes only the interesting syscalls to
+//! a listener fd held by poe; everything else runs at native speed. poe reads
+//! the arguments while the caller is blocked and lets the syscall continue.
+//! The kernel never reports back, so events carry no result or duration.
+
+use std::collections::{HashMap, HashSet};
+use std::ffi::CString;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::sync::mpsc;
+
+use anyhow::{bail, Context, Result};
+use nix::sys::wait::{waitpid, WaitPidFlag, WaitStatus};
+use nix::unistd::Pid;
+
+use crate::capture::arch;
+use crate::capture::syscalls::*;
+use crate::capture::tracer::{
+    prepare_child, read_bytes_from_process, read_string_from_process, TracerConfig,
+};
+use crate::events::types::*;
+use crate::util;
+
+const SECCOMP_IOCTL_NOTIF_RECV: libc::c_ulong = 0xc050_2100;
+const SECCOMP_IOCTL_NOTIF_SEND: libc::c_ulong = 0xc018_2101;
+const SECCOMP_IOCTL_NOTIF_ID_VALID: libc::c_ulong = 0x4008_2102;
+
+/// `SECCOMP_USER_NOTIF_FLAG_CONTINUE` landed in 5.5; without it every
+/// intercepted syscall would have to be emulated.
+const MIN_KERNEL: (u32, u32) = (5, 5);
+
+const POLL_INTERVAL_MS: i32 = 100;
+
+/// Returns why the backend can't run here, or None if it can.
+pub fn unsupported_reason() -> Option<String> {
+    let actions = std::fs::read_to_string("/proc/sys/kernel/seccomp/actions_avail");
+    match actions {
+        Err(_) => return Some("kernel built without SECCOMP_FILTER".into()),
+        Ok(a) if !a.split_whitespace().any(|x| x == "user_notif") => {
+            return Some("kernel lacks SECCOMP_RET_USER_NOTIF".into())
+        }
+        Ok(_) => {}
+    }
+    match kernel_version() {
+        Some(v) if v >= MIN_KERNEL => None,
+        Some((major, minor)) => Some(format!(
+            "kernel {}.{} too old (>= {}.{} required)",
+            major, minor, MIN_KERNEL.0, MIN_KERNEL.1
+        )),
+        None => Some("unable to determine kernel version".into()),
+    }
+}
+
+fn kernel_version() -> Option<(u32, u32)> {
+    let release = std::fs::read_to_string("/proc/sys/kernel/osrelease").ok()?;
+    let mut parts = release.trim().split(['.', '-']);
+    let major = parts.next()?.parse().ok()?;
+    let minor = parts.next()?.parse().ok()?;
+    Some((major, minor))
+}
+
+/// Syscalls routed to the supervisor. mmap/munmap and io_uring need the
+/// return value to mean anything, so they are left alone.
+fn filtered_syscalls() -> Vec<u64> {
+    (0..512)
+        .filter(|&nr| {
+            (is_file_syscall(nr) && !matches!(nr, SYS_MMAP | SYS_MUNMAP))
+                || is_net_syscall(nr)
+                || matches!(nr, SYS_EXECVE | SYS_EXECVEAT | SYS_EXIT_GROUP)
+        })
+        .collect()
+}
+
+fn bpf_stmt(code: u32, k: u32) -> libc::sock_filter {
+    libc::sock_filter {
+        code: code as u16,
+        jt: 0,
+        jf: 0,
+        k,
+    }
+}
+
+fn bpf_jeq(k: u32, jt: u8, jf: u8) -> libc::sock_filter {
+    libc::sock_filter {
+        code: (libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K) as u16,
+        jt,
+        jf,
+        k,
+    }
+}
+
+/// Builds the filter program. `sendmsg` on `exempt_fd` is always allowed so
+/// the child can hand the listener fd back without waiting on itself; the
+/// target can reuse that fd number, so such sends may go unrecorded.
+fn build_filter(nrs: &[u64], exempt_fd: RawFd) -> Vec<libc::sock_filter> {
+    let load = |offset: u32| bpf_stmt(libc::BPF_LD | libc::BPF_W | libc::BPF_ABS, offset);
+    let ret = |action: u32| bpf_stmt(libc::BPF_RET | libc::BPF_K, action);
+    let n = nrs.len();
+    assert!(n < 255, "too many filtered syscalls for one jump table");
+
+    let mut prog = vec![
+        load(4),
+        bpf_jeq(arch::AUDIT_ARCH, 1, 0),
+        ret(libc::SECCOMP_RET_ALLOW),
+        load(0),
+        bpf_jeq(SYS_SENDMSG as u32, 0, 3),
+        load(16),
+        bpf_jeq(exempt_fd as u32, 0, 1),
+        ret(libc::SECCOMP_RET_ALLOW),
+        load(0),
+    ];
+    for (i, &nr) in nrs.iter().enumerate() {
+        prog.push(bpf_jeq(nr as u32, (n - i) as u8, 0));
+    }
+    prog.push(ret(libc::SECCOMP_RET_ALLOW));
+    prog.push(ret(libc::SECCOMP_RET_USER_NOTIF));
+    prog
+}
+
+pub struct UnotifyTracer {
+    config: TracerConfig,
+    event_tx: mpsc::Sender<TraceEvent>,
+    decoder: SyscallDecoder,
+    listener: Option<OwnedFd>,
+    root_pid: Option<Pid>,
+    /// Thread id -> thread group id for every task seen so far.
+    tgids: HashMap<i32, i32>,
+    exited: HashSet<i32>,
+    base_ts: u64,
+}
+
+impl UnotifyTracer {
+    pub fn new(config: TracerConfig, event_tx: mpsc::Sender<TraceEvent>) -> Self {
+        Self {
+            config,
+            event_tx,
+            decoder: SyscallDecoder::new(),
+            listener: None,
+            root_pid: None,
+            tgids: HashMap::new(),
+            exited: HashSet::new(),
+            base_ts: util::timestamp_ns(),
+        }
+    }
+
+    /// Forks the child and installs the filter. Fails without having run
+    /// the target if the kernel refuses the filter, so the caller can fall
+    /// back to ptrace.
+    pub fn spawn_and_trace(&mut self, argv: &[String]) -> Result<i32> {
+        if argv.is_empty() {
+            bail!("empty command");
+        }
+
+        let program = CString::new(argv[0].as_str())
+            .with_context(|| format!("invalid program name: {}", argv[0]))?;
+        let c_args: Vec<CString> = argv
+            .iter()
+            .map(|a| CString::new(a.as_str()).unwrap())
+            .collect();
+
+        let mut sv = [0; 2];
+        if unsafe {
+            libc::socketpair(
+                libc::AF_UNIX,
+                libc::SOCK_SEQPACKET | libc::SOCK_CLOEXEC,
+                0,
+                sv.as_mut_ptr(),
+            )
+        } < 0
+        {
+            return Err(std::io::Error::last_os_error()).context("socketpair failed");
+        }
+        let (parent_sock, child_sock) = unsafe { (OwnedFd::from_raw_fd(sv[0]), sv[1]) };
+
+        let prog = build_filter(&filtered_syscalls(), child_sock);
+
+        match unsafe { nix::unistd::fork() }? {
+            nix::unistd::ForkResult::Child => {
+                drop(parent_sock);
+                prepare_child(&self.config);
+
+                let fprog = libc::sock_fprog {
+                    len: prog.len() as u16,
+                    filter: prog.as_ptr() as *mut libc::sock_filter,
+                };
+                let listener = unsafe {
+                    if libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) < 0 {
+                        -1
+                    } else {
+                        libc::syscall(
+                            libc::SYS_seccomp,
+                            libc::SECCOMP_SET_MODE_FILTER,
+                            libc::SECCOMP_FILTER_FLAG_NEW_LISTENER,
+                            &fprog as *const libc::sock_fprog,
+                        ) as RawFd
+                    }
+                };
+                if listener < 0 {
+                    let errno = std::io::Error::last_os_error().raw_os_error().unwrap_or(0);
+                    send_status(child_sock, errno, None);
+                    unsafe { libc::_exit(127) };
+                }
+                send_status(child_sock, 0, Some(listener));
+                // Both fds are close-on-exec; closing them here would only
+                // produce notifications nobody is reading yet.
+
+                let err = nix::unistd::execvp(&program, &c_args).unwrap_err();
+                eprintln!("poe: execvp failed: {}", err);
+                std::process::exit(127);
+            }
+            nix::unistd::ForkResult::Parent { child } => {
+                unsafe { libc::close(child_sock) };
+                let (status, listener) = recv_status(parent_sock.as_raw_fd());
+                let Some(listener) = listener else {
+                    let _ = waitpid(child, None);
+                    bail!(
+                        "failed to install seccomp filter: {}",
+                        std::io::Error::from_raw_os_error(status)
+                    );
+                };
+
+                let raw_pid = child.as_raw();
+                self.root_pid = Some(child);
+                self.listener = Some(listener);
+                self.tgids.insert(raw_pid, raw_pid);
+
+                let _ = self.event_tx.send(TraceEvent::Process(ProcessInfo {
+                    proc_id: raw_pid,
+                    parent_proc_id: None,
+                    argv: argv.to_vec(),
+                    cwd: util::procfs::read_cwd(raw_pid).unwrap_or_default(),
+                    start_ts: 0,
+                    tgid: Some(raw_pid),
+                }));
+
+                Ok(raw_pid)
+            }
+        }
+    }
+
+    pub fn run_event_loop(&mut self) -> Result<(Option<i32>, Option<i32>)> {
+        let root_pid = self
+            .root_pid
+            .ok_or_else(|| anyhow::anyhow!("no root process"))?;
+        let listener = self
+            .listener
+            .take()
+            .ok_or_else(|| anyhow::anyhow!("no seccomp listener"))?;
+        let mut root_status: Option<(Option<i32>, Option<i32>)> = None;
+
+        loop {
+            let mut pfd = libc::pollfd {
+                fd: listener.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            let n = unsafe { libc::poll(&mut pfd, 1, POLL_INTERVAL_MS) };
+            if n < 0 {
+                let err = std::io::Error::last_os_error();
+                if err.kind() == std::io::ErrorKind::Interrupted {
+                    continue;
+                }
+                return Err(err).context("poll on seccomp listener failed");
+            }
+
+            if pfd.revents & libc::POLLIN != 0 {
+                self.handle_notification(listener.as_raw_fd());
+            }
+
+            if root_status.is_none() {
+                root_status = self.reap_root(root_pid, WaitPidFlag::WNOHANG)?;
+            }
+
+            // POLLHUP arrives once every task using the filter is gone; on
+            // kernels that never send it, stop when the root is done and
+            // the listener has gone quiet.
+            let hung_up = pfd.revents & (libc::POLLHUP | libc::POLLERR) != 0;
+            if hung_up || (n == 0 && root_status.is_some()) {
+                break;
+            }
+        }
+
+        let (exit_code, signal) = match root_status {
+            Some(status) => status,
+            None => self
+                .reap_root(root_pid, WaitPidFlag::empty())?
+                .unwrap_or((None, None)),
+        };
+        Ok((exit_code, signal))
+    }
+
+    fn reap_root(
+        &mut self,
+        root_pid: Pid,
+        flags: WaitPidFlag,
+    ) -> Result<Option<(Option<i32>, Option<i32>)>> {
+        let status = match waitpid(root_pid, Some(flags)) {
+            Ok(s) => s,
+            Err(nix::errno::Errno::ECHILD) => return Ok(Some((None, None))),
+            Err(e) => return Err(e.into()),
+        };
+        let ts = self.relative_ts();
+        let raw = root_pid.as_raw();
+        let (code, sig) = match status {
+            WaitStatus::Exited(_, code) => (Some(code), None),
+            WaitStatus::Signaled(_, sig, _) => {
+                let sig_num = sig as i32;
+                let _ = self.event_tx.send(TraceEvent::Generic(Event {
+                    ts,
+                    proc_id: raw,
+                    kind: EventKind::Signal,
+                    detail: format!("killed by {} ({})", util::signal_name(sig_num), sig_num),
+                }));
+                (None, Some(sig_num))
+            }
+            _ => return Ok(None),
+        };
+        let _ = self.event_tx.send(TraceEvent::ProcessExit(ProcessExit {
+            proc_id: raw,
+            end_ts: ts,
+            exit_code: code,
+            signal: sig,
+        }));
+        Ok(Some((code, sig)))
+    }
+
+    fn handle_notification(&mut self, listener: RawFd) {
+        let mut req: libc::seccomp_notif = unsafe { std::mem::zeroed() };
+        if unsafe { libc::ioctl(listener, SECCOMP_IOCTL_NOTIF_RECV, &mut req) } < 0 {
+            // ENOENT: the caller died before we picked it up.
+            return;
+        }
+
+        let tid = req.pid as i32;
+        let nr = req.data.nr as u64;
+        let args = req.data.args;
+        let ts = self.relative_ts();
+        let pid = Pid::from_raw(tid);
+
+        let tgid = self.observe_task(tid, ts);
+        let path_reader = |addr: u64| read_string_from_process(pid, addr, 4096);
+        let addr_reader = |addr: u64, len: usize| read_bytes_from_process(pid, addr, len);
+
+        let event = match nr {
+            SYS_EXIT_GROUP => self.exit_event(tgid, ts, args[0] as i32),
+            SYS_EXECVE | SYS_EXECVEAT => self.exec_event(tid, tgid, ts, nr, args),
+            _ => {
+                let entry =
+                    self.decoder
+                        .decode_entry(tgid, ts, nr, args, &path_reader, &addr_reader);
+                entry_event(tgid, &entry, nr, args)
+            }
+        };
+
+        // The task may have died and its tid been reused while we were
+        // reading its memory; drop what we read in that case.
+        let valid = unsafe { libc::ioctl(listener, SECCOMP_IOCTL_NOTIF_ID_VALID, &req.id) } == 0;
+
+        let resp = libc::seccomp_notif_resp {
+            id: req.id,
+            val: 0,
+            error: 0,
+            flags: libc::SECCOMP_USER_NOTIF_FLAG_CONTINUE as u32,
+        };
+        unsafe { libc::ioctl(listener, SECCOMP_IOCTL_NOTIF_SEND, &resp) };
+
+        if let (true, Some(event)) = (valid, event) {
+            let _ = self.event_tx.send(event);
+        }
+    }
+
+    /// Maps a task to its process, announcing processes the first time one
+    /// of their tasks makes a filtered syscall.
+    fn observe_task(&mut self, tid: i32, ts: u64) -> i32 {
+        if let Some(&tgid) = self.tgids.get(&tid) {
+            return tgid;
+        }
+        let status_field = |pid: i32, field: &str| -> Option<i32> {
+            util::procfs::read_status_field(pid, field)
+                .ok()
+                .and_then(|v| v.parse().ok())
+        };
+        let tgid = status_field(tid, "Tgid").unwrap_or(tid);
+        self.tgids.insert(tid, tgid);
+
+        if tgid != tid && self.tgids.contains_key(&tgid) {
+            return tgid;
+        }
+        self.tgids.insert(tgid, tgid);
+
+        let parent = status_field(tgid, "PPid").filter(|pp| self.tgids.contains_key(pp));
+        let _ = self.event_tx.send(TraceEvent::Process(ProcessInfo {
+            proc_id: tgid,
+            parent_proc_id: parent,
+            argv: util::procfs::read_cmdline(tgid).unwrap_or_default(),
+            cwd: util::procfs::read_cwd(tgid).unwrap_or_default(),
+            start_ts: ts,
+            tgid: Some(tgid),
+        }));
+        tgid
+    }
+
+    fn exit_event(&mut self, tgid: i32, ts: u64, code: i32) -> Option<TraceEvent> {
+        // The root's status comes from waitpid, which also sees signals.
+        if Some(tgid) == self.root_pid.map(|p| p.as_raw()) || !self.exited.insert(tgid) {
+            return None;
+        }
+        Some(TraceEvent::ProcessExit(ProcessExit {
+            proc_id: tgid,
+            end_ts: ts,
+            exit_code: Some(code & 0xff),
+            signal: None,
+        }))
+    }
+
+    /// Reports an exec at entry. execvp probes each PATH entry, so attempts
+    /// on files that don't exist are skipped.
+    fn exec_event(
+        &mut self,
+        tid: i32,
+        tgid: i32,
+        ts: u64,
+        nr: u64,
+        args: [u64; 6],
+    ) -> Option<TraceEvent> {
+        let pid = Pid::from_raw(tid);
+        let (path_arg, argv_arg) = if nr == SYS_EXECVEAT {
+            (args[1], args[2])
+        } else {
+            (args[0], args[1])
+        };
+        let path = read_string_from_process(pid, path_arg, 4096)?;
+        let resolved = if path.starts_with('/') {
+            path.clone()
+        } else {
+            format!("/proc/{}/cwd/{}", tid, path)
+        };
+        if nr == SYS_EXECVE && !std::path::Path::new(&resolved).is_file() {
+            return None;
+        }
+
+        let mut argv = Vec::new();
+        for i in 0..256u64 {
+            let ptr = read_bytes_from_process(pid, argv_arg + i * 8, 8)?;
+            let ptr = u64::from_ne_bytes(ptr.try_into().ok()?);
+            if ptr == 0 {
+                break;
+            }
+            argv.push(read_string_from_process(pid, ptr, 4096).unwrap_or_default());
+        }
+        if argv.is_empty() {
+            argv.push(path);
+        }
+
+        Some(TraceEvent::Generic(Event {
+            ts,
+            proc_id: tgid,
+            kind: EventKind::ProcessExec,
+            detail: serde_json::to_string(&argv).unwrap_or_default(),
+        }))
+    }
+
+    fn relative_ts(&self) -> u64 {
+        util::timestamp_ns().saturating_sub(self.base_ts)
+    }
+
+    pub fn base_ts(&self) -> u64 {
+        self.base_ts
+    }
+}
+
+/// Turns a decoded syscall entry into an event. Only the arguments are
+/// known, so results and byte counts are left empty.
+fn entry_event(pid: i32, entry: &SyscallEntryInfo, nr: u64, args: [u64; 6]) -> Option<TraceEvent> {
+    match entry {
+        SyscallEntryInfo::File {
+            op,
+            path,
+            fd,
+            flags,
+            ts,
+        } => Some(TraceEvent::File(FileEvent {
+            ts: *ts,
+            proc_id: pid,
+            op: *op,
+            path: path.clone(),
+            // For openat the entry fd is the directory, not the result.
+            fd: if *op == FileOpKind::Open { None } else { *fd },
+            bytes: None,
+            flags: *flags,
+            result: None,
+            duration_ns: None,
+        })),
+        SyscallEntryInfo::Net {
+            op,
+            proto,
+            addr,
+            ts,
+        } => Some(TraceEvent::Net(NetEvent {
+            ts: *ts,
+            proc_id: pid,
+            op: *op,
+            proto: proto.clone(),
+            src: None,
+            dst: addr.clone(),
+            bytes: None,
+            // At entry an accept has no new fd yet, only the listener.
+            fd: (*op != NetOpKind::Accept && nr != SYS_SOCKET && nr != SYS_SOCKETPAIR)
+                .then_some(args[0] as i32),
+            result: None,
+            duration_ns: None,
+            listen_fd: (*op == NetOpKind::Accept).then_some(args[0] as i32),
+        })),
+        SyscallEntryInfo::Ignored => None,
+    }
+}
+
+/// Sends a status word, plus the listener fd on success, to the parent.
+fn send_status(sock: RawFd, status: i32, fd: Option<RawFd>) {
+    let mut payload = status.to_ne_bytes();
+    let mut iov = libc::iovec {
+        iov_base: payload.as_mut_ptr() as *mut libc::c_void,
+        iov_len: payload.len(),
+    };
+    let mut cmsg_buf = [0u8; 64];
+    let mut msg: libc::msghdr = unsafe { std::mem::zeroed() };
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    if let Some(fd) = fd {
+        unsafe {
+            let space = libc::CMSG_SPACE(std::mem::size_of::<RawFd>() as u32) as usize;
+            msg.msg_control = cmsg_buf.as_mut_ptr() as *mut libc::c_void;
+            msg.msg_controllen = space;
+            let cmsg = libc::CMSG_FIRSTHDR(&msg);
+            (*cmsg).cmsg_level = libc::SOL_SOCKET;
+            (*cmsg).cmsg_type = libc::SCM_RIGHTS;
+            (*cmsg).cmsg_len = libc::CMSG_LEN(std::mem::size_of::<RawFd>() as u32) as usize;
+            std::ptr::write_unaligned(libc::CMSG_DATA(cmsg) as *mut RawFd, fd);
+        }
+    }
+    unsafe { libc::sendmsg(sock, &msg, 0) };
+}
+
+fn recv_status(sock: RawFd) -> (i32, Option<OwnedFd>) {
+    let mut payload = [0u8; 4];
+    let mut iov = libc::iovec {
+        iov_base: payload.as_mut_ptr() as *mut libc::c_void,
+        iov_len: payload.len(),
+    };
+    let mut cmsg_buf = [0u8; 64];
+    let mut msg: libc::msghdr = unsafe { std::mem::zeroed() };
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = cmsg_buf.as_mut_ptr() as *mut libc::c_void;
+    msg.msg_controllen = cmsg_buf.len();
+
+    let n = unsafe { libc::recvmsg(sock, &mut msg, libc::MSG_CMSG_CLOEXEC) };
+    if n < payload.len() as isize {
+        return (libc::EPIPE, None);
+    }
+    let status = i32::from_ne_bytes(payload);
+    let fd = unsafe {
+        let cmsg = libc::CMSG_FIRSTHDR(&msg);
+        if cmsg.is_null()
+            || (*cmsg).cmsg_level != libc::SOL_SOCKET
+            || (*cmsg).cmsg_type != libc::SCM_RIGHTS
+        {
+            None
+        } else {
+            let fd = std::ptr::read_unaligned(libc::CMSG_DATA(cmsg) as *const RawFd);
+            Some(OwnedFd::from_raw_fd(fd))
+        }
+    };
+    (status, fd.filter(|_| status == 0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filter_jumps_land_on_notify() {
+        let nrs = filtered_syscalls();
+        assert!(nrs.contains(&SYS_OPENAT) && nrs.contains(&SYS_CONNECT));
+        assert!(!nrs.contains(&SYS_MMAP));
+
+        let prog = build_filter(&nrs, 7);
+        let notify = prog.len() - 1;
+        assert_eq!(prog[notify].k, libc::SECCOMP_RET_USER_NOTIF);
+        assert_eq!(prog[notify - 1].k, libc::SECCOMP_RET_ALLOW);
+        let table = notify - 1 - nrs.len();
+        for (i, &nr) in nrs.iter().enumerate() {
+            let insn = &prog[table + i];
+            assert_eq!(insn.k as u64, nr);
+            assert_eq!(table + i + 1 + insn.jt as usize, notify);
+        }
+    }
+}
@@ -0,0 +1,156 @@
+use std::path::Path;
+use std::process::Command;
+
+use anyhow::{bail, Context, Result};
+use colored::Colorize;
+
+use crate::logging;
+use crate::pack::baseline::{self, BaselineStore};
+use crate::pack::reader::PackReader;
+
+pub fn set(pack: &Path, name: &str, server: Option<String>, allow_secrets: bool) -> Result<()> {
+    baseline::validate_name(name)?;
+    if let Some(server) = server {
+        if !allow_secrets {
+            check_secrets(pack)?;
+        }
+        return publish(pack, name, &server);
+    }
+    let store = BaselineStore::open_default()?;
+    let info = store.set(name, pack, &pack.display().to_string())?;
+    if !logging::is_quiet() {
+        eprintln!(
+            "poe: baseline {} -> {} ({})",
+            format!("@{}", name).cyan(),
+            &info.run_id[..8],
+            info.command.join(" ")
+        );
+    }
+    Ok(())
+}
+
+/// Refuses to upload a pack that was written with likely secrets in it.
+fn check_secrets(pack: &Path) -> Result<()> {
+    let reader = PackReader::open(pack)?;
+    let secrets = &reader.summary().secrets;
+    if secrets.is_empty() {
+        return Ok(());
+    }
+    let found: Vec<String> = secrets
+        .iter()
+        .map(|s| format!("{} in {}", s.kind, s.source))
+        .collect();
+    bail!(
+        "{} has {} likely secret(s) ({}); capture it again with `poe run --redact` or pass --allow-secrets to upload anyway",
+        pack.display(),
+        secrets.len(),
+        found.join(", ")
+    );
+}
+
+/// Uploads the pack to a poe server and points the server's baseline at it.
+fn publish(pack: &Path, name: &str, server: &str) -> Result<()> {
+    let server = server.trim_end_matches('/');
+    let upload = curl(&[
+        "-X".into(),
+        "POST".into(),
+        "--data-binary".into(),
+        format!("@{}", pack.display()),
+        format!("{}/api/packs", server),
+    ])?;
+    let Some(id) = upload["id"].as_str() else {
+        bail!("upload failed: {}", upload);
+    };
+    let body = serde_json::json!({ "id": id }).to_string();
+    let set = curl(&[
+        "-X".into(),
+        "PUT".into(),
+        "--data".into(),
+        body,
+        format!("{}/api/baselines/{}", server, name),
+    ])?;
+    if set["status"] != "ok" {
+        bail!("failed to set baseline: {}", set);
+    }
+    if !logging::is_quiet() {
+        eprintln!(
+            "poe: baseline {} -> {} on {}",
+            format!("@{}", name).cyan(),
+            &id[..8.min(id.len())],
+            server
+        );
+    }
+    Ok(())
+}
+
+pub fn list(server: Option<String>, json: bool) -> Result<()> {
+    let baselines: Vec<serde_json::Value> = match server {
+        Some(server) => {
+            let url = format!("{}/api/baselines", server.trim_end_matches('/'));
+            serde_json::from_value(curl(&[url])?)?
+        }
+        None => BaselineStore::open_default()?
+            .list()?
+            .iter()
+            .map(|b| {
+                serde_json::json!({
+                    "name": b.name,
+                    "id": b.run_id,
+                    "command": b.command,
+                    "timestamp": b.timestamp,
+                    "set_at": b.set_at,
+                    "source": b.source,
+                })
+            })
+            .collect(),
+    };
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&baselines)?);
+        return Ok(());
+    }
+    if baselines.is_empty() {
+        println!("no baselines (set one with `poe baseline set <pack> --name <name>`)");
+        return Ok(());
+    }
+    for b in &baselines {
+        let command: Vec<&str> = b["command"]
+            .as_array()
+            .map(|a| a.iter().filter_map(|c| c.as_str()).collect())
+            .unwrap_or_default();
+        let id = b["id"].as_str().unwrap_or("");
+        println!(
+            "{:<20} {}  {}  {}",
+            format!("@{}", b["name"].as_str().unwrap_or("")).cyan(),
+            &id[..8.min(id.len())],
+            b["timestamp"].as_str().unwrap_or("-").dimmed(),
+            command.join(" ")
+        );
+    }
+    Ok(())
+}
+
+pub fn remove(name: &str) -> Result<()> {
+    BaselineStore::open_default()?.remove(name)?;
+    if !logging::is_quiet() {
+        eprintln!("poe: removed baseline @{}", name);
+    }
+    Ok(())
+}
+
+fn curl(args: &[String]) -> Result<serde_json::Value> {
+    let output = Command::new("curl")
+        .arg("-sS")
+        .args(args)
+        .output()
+        .context("failed to run curl")?;
+    if !output.status.success() {
+        bail!("{}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+    serde_json::from_slice(&output.stdout).with_context(|| {
+        format!(
+            "unexpected response: {}",
+            String::from_utf8_lossy(&output.stdout).trim()
+        )
+    })
+}
@@ -0,0 +1,289 @@
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Context, Result};
+use colored::Colorize;
+use serde::Serialize;
+
+use crate::capture::backend::Backend;
+use crate::capture::unotify;
+use crate::pack::reader::PackReader;
+
+const DEFAULT_RUNS: usize = 5;
+
+/// Timings of the command run one way: bare, or under a capture backend.
+#[derive(Debug, Serialize)]
+struct BenchMode {
+    mode: String,
+    runs: usize,
+    median_ms: f64,
+    min_ms: f64,
+    max_ms: f64,
+    /// Median wall time over the untraced median, in percent.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    overhead_pct: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    events: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pack_bytes: Option<u64>,
+    /// Exit codes that differed from the untraced runs'.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    exit_codes: Vec<i32>,
+}
+
+#[derive(Debug, Serialize)]
+struct BenchReport {
+    command: Vec<String>,
+    modes: Vec<BenchMode>,
+}
+
+pub fn execute(
+    command: Vec<String>,
+    runs: Option<usize>,
+    backends: Vec<String>,
+    mode: Option<String>,
+    max_overhead: Option<f64>,
+    json: bool,
+) -> Result<()> {
+    if command.is_empty() {
+        bail!("no command given (usage: poe bench [OPTIONS] -- <command>)");
+    }
+    let runs = runs.unwrap_or(DEFAULT_RUNS).max(1);
+    let backends: Vec<&str> = if backends.is_empty() {
+        let mut all = vec!["ptrace"];
+        if unotify::unsupported_reason().is_none() {
+            all.push("seccomp");
+        }
+        all
+    } else {
+        for b in &backends {
+            Backend::parse(b)?;
+        }
+        backends.iter().map(String::as_str).collect()
+    };
+
+    // One untimed run first, so the first timed one doesn't pay for a
+    // cold page cache.
+    run_once(plain(&command))?;
+    let mut samples = Vec::with_capacity(runs);
+    let mut exit_codes = Vec::new();
+    for _ in 0..runs {
+        let (elapsed, code) = run_once(plain(&command))?;
+        samples.push(elapsed);
+        exit_codes.push(code);
+    }
+    let baseline = BenchMode::new("none".into(), &samples, None);
+    let expected_code = exit_codes.first().copied().unwrap_or(0);
+
+    let mut modes = vec![baseline];
+    for backend in backends {
+        let dir =
+            std::env::temp_dir().join(format!("poe-bench-{}-{}", std::process::id(), backend));
+        let result = bench_backend(
+            &command,
+            backend,
+            mode.as_deref(),
+            runs,
+            &dir,
+            modes[0].median_ms,
+            expected_code,
+        );
+        let _ = std::fs::remove_dir_all(&dir);
+        modes.push(result?);
+    }
+
+    let report = BenchReport { command, modes };
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        print_report(&report, runs);
+    }
+
+    if let Some(limit) = max_overhead {
+        if let Some(worst) = report
+            .modes
+            .iter()
+            .filter(|m| m.overhead_pct.is_some_and(|o| o > limit))
+            .max_by(|a, b| a.overhead_pct.unwrap().total_cmp(&b.overhead_pct.unwrap()))
+        {
+            bail!(
+                "{} capture overhead {:.1}% is above --max-overhead {}%",
+                worst.mode,
+                worst.overhead_pct.unwrap(),
+                limit
+            );
+        }
+    }
+    Ok(())
+}
+
+fn bench_backend(
+    command: &[String],
+    backend: &str,
+    mode: Option<&str>,
+    runs: usize,
+    dir: &Path,
+    baseline_ms: f64,
+    expected_code: i32,
+) -> Result<BenchMode> {
+    std::fs::create_dir_all(dir)?;
+    let mut samples = Vec::with_capacity(runs);
+    let mut exit_codes = Vec::new();
+    let mut events = Vec::new();
+    let mut sizes = Vec::new();
+    for _ in 0..runs {
+        let mut cmd = Command::new(std::env::current_exe()?);
+        cmd.args(["run", "--always", "--backend", backend, "--output"])
+            .arg(dir);
+        if let Some(mode) = mode {
+            cmd.args(["--mode", mode]);
+        }
+        cmd.arg("--").args(command);
+        let (elapsed, code) = run_once(cmd)?;
+        samples.push(elapsed);
+        if code != expected_code {
+            exit_codes.push(code);
+        }
+
+        let pack = take_pack(dir)?
+            .with_context(|| format!("poe run --backend {} wrote no pack", backend))?;
+        sizes.push(std::fs::metadata(&pack)?.len());
+        events.push(PackReader::open(&pack)?.summary().stats.event_count);
+        std::fs::remove_file(&pack)?;
+    }
+    let mut result = BenchMode::new(backend.to_string(), &samples, Some(baseline_ms));
+    result.events = median(&mut events);
+    result.pack_bytes = median(&mut sizes);
+    result.exit_codes = exit_codes;
+    Ok(result)
+}
+
+impl BenchMode {
+    fn new(mode: String, samples: &[Duration], baseline_ms: Option<f64>) -> Self {
+        let mut ms: Vec<f64> = samples
+            .iter()
+            .map(|d| d.as_micros() as f64 / 1000.0)
+            .collect();
+        ms.sort_by(f64::total_cmp);
+        let median_ms = if ms.len().is_multiple_of(2) {
+            (ms[ms.len() / 2 - 1] + ms[ms.len() / 2]) / 2.0
+        } else {
+            ms[ms.len() / 2]
+        };
+        Self {
+            mode,
+            runs: ms.len(),
+            median_ms,
+            min_ms: ms[0],
+            max_ms: ms[ms.len() - 1],
+            overhead_pct: baseline_ms
+                .filter(|b| *b > 0.0)
+                .map(|b| (median_ms / b - 1.0) * 100.0),
+            events: None,
+            pack_bytes: None,
+            exit_codes: Vec::new(),
+        }
+    }
+}
+
+fn median<T: Ord + Copy>(values: &mut [T]) -> Option<T> {
+    values.sort();
+    values.get(values.len() / 2).copied()
+}
+
+fn plain(command: &[String]) -> Command {
+    let mut cmd = Command::new(&command[0]);
+    cmd.args(&command[1..]);
+    cmd
+}
+
+/// Wall time and exit code; the command's output is discarded so the
+/// terminal doesn't take part in the timing.
+fn run_once(mut cmd: Command) -> Result<(Duration, i32)> {
+    let start = Instant::now();
+    let status = cmd
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .with_context(|| format!("failed to run {}", cmd.get_program().to_string_lossy()))?;
+    Ok((start.elapsed(), status.code().unwrap_or(-1)))
+}
+
+pub(crate) fn take_pack(dir: &Path) -> Result<Option<PathBuf>> {
+    Ok(std::fs::read_dir(dir)?
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .find(|p| p.extension().is_some_and(|x| x == "poepack")))
+}
+
+fn print_report(report: &BenchReport, runs: usize) {
+    println!();
+    println!("{}", "=== poe bench ===".cyan().bold());
+    println!();
+    println!("  command: {}", report.command.join(" "));
+    println!("  runs:    {} per mode", runs);
+    println!();
+    println!(
+        "  {:<10} {:>10} {:>10} {:>10} {:>10} {:>10} {:>10}",
+        "backend", "median", "min", "max", "overhead", "events", "pack"
+    );
+    for m in &report.modes {
+        let overhead = match m.overhead_pct {
+            Some(pct) => format!("{:+.1}%", pct),
+            None => "-".into(),
+        };
+        let overhead = if m.overhead_pct.is_some_and(|p| p > 100.0) {
+            format!("{:>10}", overhead).red()
+        } else {
+            format!("{:>10}", overhead).normal()
+        };
+        println!(
+            "  {:<10} {:>8.1}ms {:>8.1}ms {:>8.1}ms {} {:>10} {:>10}",
+            m.mode,
+            m.median_ms,
+            m.min_ms,
+            m.max_ms,
+            overhead,
+            m.events.map_or("-".into(), |e| e.to_string()),
+            m.pack_bytes.map_or("-".into(), format_bytes),
+        );
+        if !m.exit_codes.is_empty() {
+            println!(
+                "  {}",
+                format!("  exited differently under capture: {:?}", m.exit_codes).yellow()
+            );
+        }
+    }
+    println!();
+}
+
+fn format_bytes(bytes: u64) -> String {
+    if bytes < 1024 {
+        format!("{} B", bytes)
+    } else if bytes < 1024 * 1024 {
+        format!("{:.1} KB", bytes as f64 / 1024.0)
+    } else {
+        format!("{:.1} MB", bytes as f64 / (1024.0 * 1024.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overhead_is_against_the_untraced_median() {
+        let ms =
+            |v: &[u64]| -> Vec<Duration> { v.iter().map(|&m| Duration::from_millis(m)).collect() };
+        let bare = BenchMode::new("none".into(), &ms(&[12, 10, 30, 9]), None);
+        assert_eq!(
+            (bare.median_ms, bare.min_ms, bare.max_ms),
+            (11.0, 9.0, 30.0)
+        );
+        assert_eq!(bare.overhead_pct, None);
+        let traced = BenchMode::new("ptrace".into(), &ms(&[33, 40, 30]), Some(bare.median_ms));
+        assert!((traced.overhead_pct.unwrap() - 200.0).abs() < 1e-9);
+    }
+}
@@ -2,12 +2,20 @@ use std::path::PathBuf;
 
 use anyhow::Result;
 
-use crate::build::instrument::{self, InstrumentConfig};
+use crate::build::instrument::{self, InstrumentConfig, Toolchain};
 
-pub fn execute(command: Vec<String>, output: Option<PathBuf>) -> Result<()> {
+pub fn execute(
+    command: Vec<String>,
+    output: Option<PathBuf>,
+    emit_toolchain: Option<String>,
+) -> Result<()> {
     let config = InstrumentConfig {
         build_command: command,
-        _output_dir: output.unwrap_or_else(|| PathBuf::from(".")),
+        output_dir: output.unwrap_or_else(|| PathBuf::from(".")),
+        emit_toolchain: emit_toolchain
+            .as_deref()
+            .map(Toolchain::parse)
+            .transpose()?,
     };
 
     instrument::execute_instrumented_build(config)?;
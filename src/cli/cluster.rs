@@ -0,0 +1,102 @@
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use colored::Colorize;
+
+use crate::explain::analyzer;
+use crate::explain::cluster::{self, ClusterMember, Fingerprint};
+use crate::explain::patterns::PatternRegistry;
+use crate::pack::reader::PackReader;
+
+pub fn execute(dir: PathBuf, json: bool, pattern_files: Vec<PathBuf>) -> Result<()> {
+    let mut registry = PatternRegistry::load_default();
+    for path in &pattern_files {
+        registry.load_rules_file(path)?;
+    }
+
+    let mut paths: Vec<PathBuf> = std::fs::read_dir(&dir)
+        .with_context(|| format!("failed to read {}", dir.display()))?
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.extension().map(|x| x == "poepack").unwrap_or(false))
+        .collect();
+    paths.sort();
+
+    let mut members = Vec::new();
+    for path in &paths {
+        let pack = match PackReader::open(path) {
+            Ok(pack) => pack,
+            Err(e) => {
+                log::warn!("skipping {}: {:#}", path.display(), e);
+                continue;
+            }
+        };
+        let output = analyzer::analyze_with(&pack, &registry)?;
+        let summary = pack.summary();
+        if let Some(fingerprint) = Fingerprint::compute(summary, &output) {
+            let member = ClusterMember {
+                run_id: summary.run_id.clone(),
+                pack: path.display().to_string(),
+                timestamp: summary.timestamp.clone(),
+                command: summary.command.clone(),
+                description: output
+                    .failure
+                    .as_ref()
+                    .map(|f| f.description.clone())
+                    .unwrap_or_default(),
+            };
+            members.push((fingerprint, member));
+        }
+    }
+    let failing = members.len();
+    let clusters = cluster::group(members);
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&clusters)?);
+        return Ok(());
+    }
+
+    println!();
+    println!("{}", "=== poe cluster ===".cyan().bold());
+    println!();
+    println!(
+        "  {} packs, {} failing, {} distinct failures",
+        paths.len(),
+        failing,
+        clusters.len()
+    );
+    println!();
+
+    for c in &clusters {
+        println!(
+            "  {} {:>4}x  {}",
+            c.id.dimmed(),
+            c.count,
+            c.fingerprint.label().red()
+        );
+        println!(
+            "      {} {}  {} {}",
+            "first:".dimmed(),
+            c.first_seen,
+            "last:".dimmed(),
+            c.last_seen
+        );
+        if !c.latest.description.is_empty() {
+            println!("      {}", c.latest.description);
+        }
+        println!(
+            "      {} {}",
+            "latest:".dimmed(),
+            c.latest.command.join(" ")
+        );
+        for pack in c.packs.iter().rev().take(5) {
+            println!("      {} {}", "pack:".dimmed(), pack);
+        }
+        if c.packs.len() > 5 {
+            println!("      ... and {} more", c.packs.len() - 5);
+        }
+        println!();
+    }
+
+    Ok(())
+}
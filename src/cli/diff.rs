@@ -1,22 +1,56 @@
 use std::path::PathBuf;
 
-use anyhow::Result;
+use anyhow::{bail, Result};
 use colored::Colorize;
 
-use crate::explain::diff;
+use crate::explain::{diff, unified};
+use crate::pack;
 
-pub fn execute(baseline: PathBuf, candidate: PathBuf, json: bool) -> Result<()> {
-    let output = diff::diff_packs(&baseline, &candidate)?;
+pub fn execute(
+    baseline: PathBuf,
+    candidate: PathBuf,
+    json: bool,
+    format: Option<String>,
+) -> Result<()> {
+    let format = match format.as_deref() {
+        Some(f) => f.to_ascii_lowercase(),
+        None if json => "json".to_string(),
+        None => "text".to_string(),
+    };
+    let baseline = pack::baseline::resolve(&baseline)?;
+    let candidate = pack::remote::resolve(&candidate)?;
 
-    if json {
-        println!("{}", serde_json::to_string_pretty(&output)?);
-        return Ok(());
+    match format.as_str() {
+        "text" => print_diff(&diff::diff_packs(&baseline, &candidate)?),
+        "json" => {
+            let output = diff::diff_packs(&baseline, &candidate)?;
+            println!("{}", serde_json::to_string_pretty(&output)?);
+        }
+        "unified" => print_unified(&unified::render(&baseline, &candidate)?),
+        other => bail!(
+            "unknown format '{}' (expected text, json, or unified)",
+            other
+        ),
     }
-
-    print_diff(&output);
     Ok(())
 }
 
+fn print_unified(diff: &str) {
+    for line in diff.lines() {
+        if line.starts_with("---") || line.starts_with("+++") {
+            println!("{}", line.bold());
+        } else if line.starts_with("@@") {
+            println!("{}", line.cyan());
+        } else if line.starts_with('-') {
+            println!("{}", line.red());
+        } else if line.starts_with('+') {
+            println!("{}", line.green());
+        } else {
+            println!("{}", line);
+        }
+    }
+}
+
 pub fn print_diff(output: &diff::DiffOutput) {
     println!();
     println!("{}", "=== poe diff ===".cyan().bold());
@@ -97,6 +131,7 @@ pub fn print_diff(output: &diff::DiffOutput) {
         let has_changes = !f.new_paths.is_empty()
             || !f.missing_paths.is_empty()
             || !f.new_errors.is_empty()
+            || !f.content_changes.is_empty()
             || f.baseline_ops != f.candidate_ops;
 
         if has_changes {
@@ -138,6 +173,20 @@ pub fn print_diff(output: &diff::DiffOutput) {
                     println!("    {} {} -> {}", err.op, err.path, err.result);
                 }
             }
+            if !f.content_changes.is_empty() {
+                println!("  {}", "written with different contents:".red());
+                for c in f.content_changes.iter().take(10) {
+                    println!(
+                        "    {} {} ({} -> {}) {} -> {}",
+                        "~".yellow(),
+                        c.path,
+                        format_bytes(c.baseline_size),
+                        format_bytes(c.candidate_size),
+                        short_hash(c.baseline_hash.as_deref()).dimmed(),
+                        short_hash(c.candidate_hash.as_deref()).dimmed(),
+                    );
+                }
+            }
             println!();
         }
     }
@@ -182,6 +231,63 @@ pub fn print_diff(output: &diff::DiffOutput) {
         }
     }
 
+    if let Some(ref c) = output.cache_diff {
+        let has_changes = !c.new_misses.is_empty()
+            || !c.new_errors.is_empty()
+            || !c.new_commands.is_empty()
+            || !c.missing_commands.is_empty()
+            || !c.size_changes.is_empty();
+
+        if has_changes {
+            println!("{}", "--- cache changes ---".yellow().bold());
+            println!(
+                "  {} -> {} ops, misses: {} -> {}",
+                c.baseline_ops, c.candidate_ops, c.baseline_misses, c.candidate_misses,
+            );
+            if !c.new_misses.is_empty() {
+                println!("  {}", "found in baseline, missed now:".red());
+                for key in c.new_misses.iter().take(10) {
+                    println!("    {} {}", "!".red(), key);
+                }
+            }
+            if !c.new_errors.is_empty() {
+                println!("  {}", "new cache errors:".red());
+                for err in c.new_errors.iter().take(10) {
+                    println!("    {}", err);
+                }
+            }
+            for (title, sign, list) in [
+                ("new commands:", "+".green(), &c.new_commands),
+                ("missing commands:", "-".red(), &c.missing_commands),
+            ] {
+                if list.is_empty() {
+                    continue;
+                }
+                println!("  {}", title.dimmed());
+                for cmd in list.iter().take(10) {
+                    println!("    {} {}", sign, cmd);
+                }
+                if list.len() > 10 {
+                    println!("    {} ...and {} more", sign, list.len() - 10);
+                }
+            }
+            if !c.size_changes.is_empty() {
+                println!("  {}", "value sizes changed:".dimmed());
+                for sc in c.size_changes.iter().take(10) {
+                    println!(
+                        "    {} {} {} {} -> {}",
+                        "~".yellow(),
+                        sc.command,
+                        sc.key,
+                        format_bytes(sc.baseline_bytes),
+                        format_bytes(sc.candidate_bytes),
+                    );
+                }
+            }
+            println!();
+        }
+    }
+
     if let Some(ref sd) = output.stderr_diff {
         if !sd.new_lines.is_empty() {
             println!("{}", "--- new stderr lines ---".yellow().bold());
@@ -192,12 +298,82 @@ pub fn print_diff(output: &diff::DiffOutput) {
         }
     }
 
+    if let Some(ref env) = output.env_diff {
+        println!("{}", "--- environment ---".yellow().bold());
+        for var in &env.added {
+            println!("  {} {}={}", "+".green(), var.name, var.value);
+        }
+        for var in &env.removed {
+            println!("  {} {}={}", "-".red(), var.name, var.value);
+        }
+        for change in &env.changed {
+            if change.added_entries.is_empty() && change.removed_entries.is_empty() {
+                println!(
+                    "  {} {}: {} -> {}",
+                    "~".yellow(),
+                    change.name,
+                    change.baseline.dimmed(),
+                    change.candidate
+                );
+                continue;
+            }
+            println!("  {} {}:", "~".yellow(), change.name);
+            for entry in &change.added_entries {
+                println!("      {} {}", "+".green(), entry);
+            }
+            for entry in &change.removed_entries {
+                println!("      {} {}", "-".red(), entry);
+            }
+        }
+        println!();
+    }
+
+    if !output.sandbox_diff.is_empty() {
+        println!("{}", "--- sandbox ---".yellow().bold());
+        for change in &output.sandbox_diff {
+            println!(
+                "  {} {}: {} -> {}",
+                "~".yellow(),
+                change.name,
+                change.baseline.dimmed(),
+                change.candidate
+            );
+        }
+        println!();
+    }
+
+    if !output.new_patterns.is_empty() || !output.resolved_patterns.is_empty() {
+        println!("{}", "--- diagnosis ---".yellow().bold());
+        for (sign, list) in [
+            ("+".red(), &output.new_patterns),
+            ("-".green(), &output.resolved_patterns),
+        ] {
+            for p in list {
+                println!(
+                    "  {} {} {} {}",
+                    sign,
+                    p.code.code().dimmed(),
+                    p.category.cyan(),
+                    p.description
+                );
+            }
+        }
+        println!();
+    }
+
     if output.exit_code_diff.is_none()
         && output.signal_diff.is_none()
+        && output.new_patterns.is_empty()
+        && output.sandbox_diff.is_empty()
         && output.process_diff.new_processes.is_empty()
         && output.process_diff.missing_processes.is_empty()
         && output.file_diff.new_errors.is_empty()
+        && output.file_diff.content_changes.is_empty()
         && output.net_diff.new_errors.is_empty()
+        && output
+            .cache_diff
+            .as_ref()
+            .is_none_or(|c| c.new_misses.is_empty() && c.new_errors.is_empty())
     {
         println!(
             "{}",
@@ -210,6 +386,13 @@ pub fn print_diff(output: &diff::DiffOutput) {
     println!();
 }
 
+fn short_hash(hash: Option<&str>) -> String {
+    match hash {
+        Some(h) => h.chars().take(12).collect(),
+        None => "unhashed".into(),
+    }
+}
+
 fn format_bytes(bytes: u64) -> String {
     if bytes == 0 {
         "0 B".into()
@@ -1,54 +1,143 @@
-use anyhow::Result;
+use std::io::{BufRead, IsTerminal, Write};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Context, Result};
 use colored::Colorize;
+use serde::Serialize;
+
+use crate::capture::arch;
+use crate::pack::reader::PackReader;
+use crate::util;
+use crate::util::sandbox::Sandbox;
 
+#[derive(Serialize)]
 struct Check {
     name: &'static str,
     status: CheckStatus,
     detail: String,
+    /// Shell commands, to be run as root, that would make the check pass.
+    /// Fixed strings: nothing from the environment is spliced into them.
+    fix: Vec<String>,
 }
 
+#[derive(Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
 enum CheckStatus {
     Ok,
     Warn,
     Fail,
 }
 
-pub fn execute() -> Result<()> {
-    println!();
-    println!("{}", "=== poe doctor ===".cyan().bold());
-    println!();
+/// The result of capturing a small syscall-bound command with one backend.
+#[derive(Serialize)]
+struct BackendProbe {
+    backend: &'static str,
+    status: CheckStatus,
+    /// File syscalls the pack recorded.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    captured_ops: Option<i64>,
+    /// Extra wall time per traced syscall, in microseconds.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    overhead_us: Option<f64>,
+    /// How many times slower the command ran traced.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    slowdown: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
 
-    let checks = vec![
+#[derive(Serialize)]
+struct DoctorReport {
+    checks: Vec<Check>,
+    backends: Vec<BackendProbe>,
+    sandbox: Sandbox,
+    ok: usize,
+    warnings: usize,
+    failures: usize,
+}
+
+fn run_checks() -> Vec<Check> {
+    vec![
         check_kernel(),
         check_ptrace(),
         check_perf(),
         check_proc_filesystem(),
         check_process_vm_readv(),
-    ];
+        check_seccomp_unotify(),
+    ]
+}
 
-    let mut ok_count = 0;
-    let mut warn_count = 0;
-    let mut fail_count = 0;
+pub fn execute(json: bool, fix: bool) -> Result<()> {
+    let checks = run_checks();
+    let backends = probe_backends();
+    let count = |status| {
+        checks.iter().filter(|c| c.status == status).count()
+            + backends.iter().filter(|b| b.status == status).count()
+    };
+    let (ok_count, warn_count, fail_count) = (
+        count(CheckStatus::Ok),
+        count(CheckStatus::Warn),
+        count(CheckStatus::Fail),
+    );
+
+    if json {
+        let report = DoctorReport {
+            checks,
+            backends,
+            sandbox: Sandbox::read(),
+            ok: ok_count,
+            warnings: warn_count,
+            failures: fail_count,
+        };
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        if fail_count > 0 {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    println!();
+    println!("{}", "=== poe doctor ===".cyan().bold());
+    println!();
 
     for check in &checks {
         let (icon, color_fn): (&str, fn(&str) -> colored::ColoredString) = match check.status {
-            CheckStatus::Ok => {
-                ok_count += 1;
-                ("OK", |s: &str| s.green())
-            }
-            CheckStatus::Warn => {
-                warn_count += 1;
-                ("WARN", |s: &str| s.yellow())
-            }
-            CheckStatus::Fail => {
-                fail_count += 1;
-                ("FAIL", |s: &str| s.red())
-            }
+            CheckStatus::Ok => ("OK", |s: &str| s.green()),
+            CheckStatus::Warn => ("WARN", |s: &str| s.yellow()),
+            CheckStatus::Fail => ("FAIL", |s: &str| s.red()),
         };
 
         println!("  [{}] {}: {}", color_fn(icon), check.name, check.detail);
     }
 
+    println!();
+    println!(
+        "  {}",
+        format!("backends (dd, {} one-byte copies):", PROBE_COPIES).bold()
+    );
+    for probe in &backends {
+        let (icon, color_fn): (&str, fn(&str) -> colored::ColoredString) = match probe.status {
+            CheckStatus::Ok => ("OK", |s: &str| s.green()),
+            CheckStatus::Warn => ("WARN", |s: &str| s.yellow()),
+            CheckStatus::Fail => ("FAIL", |s: &str| s.red()),
+        };
+        println!(
+            "    [{}] {}: {}",
+            color_fn(icon),
+            probe.backend,
+            probe.detail()
+        );
+    }
+
+    // Not pass/fail, but what a command traced here inherits; the same
+    // is recorded in every pack.
+    println!();
+    println!("  {}", "sandbox:".bold());
+    for (name, value) in Sandbox::read().summary() {
+        println!("    {}: {}", name, value);
+    }
+
     println!();
     println!(
         "  {} ok, {} warnings, {} failures",
@@ -79,6 +168,79 @@ pub fn execute() -> Result<()> {
 
     println!();
 
+    if fix {
+        apply_fixes(&checks)?;
+    }
+    if fail_count > 0 {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+/// Prints the fixes, and runs them through sudo once the user agrees.
+/// Without a terminal to ask on, they are only printed.
+fn apply_fixes(checks: &[Check]) -> Result<()> {
+    let fixable: Vec<&Check> = checks.iter().filter(|c| !c.fix.is_empty()).collect();
+    if fixable.is_empty() {
+        println!("  Nothing poe can fix from here.");
+        println!();
+        return Ok(());
+    }
+    // SAFETY: geteuid has no preconditions.
+    let root = unsafe { libc::geteuid() } == 0;
+    println!("  {}", "fixes:".bold());
+    for check in &fixable {
+        println!("    # {}", check.name);
+        for command in &check.fix {
+            if root {
+                println!("    {}", command);
+            } else {
+                println!("    sudo sh -c {}", util::shell_quote(command));
+            }
+        }
+    }
+    println!();
+
+    if !std::io::stdin().is_terminal() {
+        return Ok(());
+    }
+    print!(
+        "  Run these now{}? [y/N] ",
+        if root { "" } else { " with sudo" }
+    );
+    std::io::stdout().flush()?;
+    let mut answer = String::new();
+    std::io::stdin().lock().read_line(&mut answer)?;
+    if !matches!(answer.trim(), "y" | "Y" | "yes") {
+        return Ok(());
+    }
+
+    for command in fixable.iter().flat_map(|c| &c.fix) {
+        let mut cmd = if root {
+            Command::new("sh")
+        } else {
+            let mut sudo = Command::new("sudo");
+            sudo.arg("sh");
+            sudo
+        };
+        let status = cmd.arg("-c").arg(command).status()?;
+        if !status.success() {
+            bail!("`{}` failed ({})", command, status);
+        }
+    }
+
+    println!();
+    for check in run_checks() {
+        if fixable.iter().any(|f| f.name == check.name) {
+            let state = match check.status {
+                CheckStatus::Ok => "OK".green(),
+                CheckStatus::Warn => "WARN".yellow(),
+                CheckStatus::Fail => "FAIL".red(),
+            };
+            println!("  [{}] {}: {}", state, check.name, check.detail);
+        }
+    }
+    println!();
     Ok(())
 }
 
@@ -94,20 +256,26 @@ fn check_kernel() -> Check {
     let major: u32 = parts.first().and_then(|s| s.parse().ok()).unwrap_or(0);
     let minor: u32 = parts.get(1).and_then(|s| s.parse().ok()).unwrap_or(0);
 
-    if major >= 5 || (major == 4 && minor >= 8) {
+    let (min_major, min_minor) = arch::MIN_KERNEL;
+    if (major, minor) >= arch::MIN_KERNEL {
         Check {
             name: "kernel version",
             status: CheckStatus::Ok,
+            fix: Vec::new(),
             detail: format!(
-                "{} (>= 4.8 required for full ptrace support)",
-                version_short
+                "{} (>= {}.{} required for full ptrace support)",
+                version_short, min_major, min_minor
             ),
         }
     } else {
         Check {
             name: "kernel version",
             status: CheckStatus::Warn,
-            detail: format!("{} (4.8+ recommended)", version_short),
+            fix: Vec::new(),
+            detail: format!(
+                "{} ({}.{}+ recommended)",
+                version_short, min_major, min_minor
+            ),
         }
     }
 }
@@ -122,31 +290,37 @@ fn check_ptrace() -> Check {
         "0" => Check {
             name: "ptrace scope",
             status: CheckStatus::Ok,
+            fix: Vec::new(),
             detail: "0 (classic ptrace permissions - unrestricted for child processes)".into(),
         },
         "1" => Check {
             name: "ptrace scope",
             status: CheckStatus::Ok,
+            fix: Vec::new(),
             detail: "1 (restricted - child processes only, sufficient for poe)".into(),
         },
         "2" => Check {
             name: "ptrace scope",
             status: CheckStatus::Warn,
-            detail: "2 (admin only - may need CAP_SYS_PTRACE)".into(),
+            fix: lower_ptrace_scope(),
+            detail: "2 (admin only - poe needs 1 or lower, or to run as root)".into(),
         },
         "3" => Check {
             name: "ptrace scope",
             status: CheckStatus::Fail,
-            detail: "3 (no ptrace allowed - poe cannot function)".into(),
+            fix: Vec::new(),
+            detail: "3 (no ptrace allowed - poe cannot function; only a reboot lowers it)".into(),
         },
         "N/A" => Check {
             name: "ptrace scope",
             status: CheckStatus::Ok,
+            fix: Vec::new(),
             detail: "YAMA not present (ptrace unrestricted)".into(),
         },
         _ => Check {
             name: "ptrace scope",
             status: CheckStatus::Warn,
+            fix: Vec::new(),
             detail: format!("unknown scope: {}", scope),
         },
     }
@@ -162,47 +336,73 @@ fn check_perf() -> Check {
         "-1" => Check {
             name: "perf_event_paranoid",
             status: CheckStatus::Ok,
+            fix: Vec::new(),
             detail: "-1 (no restrictions on perf events)".into(),
         },
         "0" | "1" => Check {
             name: "perf_event_paranoid",
             status: CheckStatus::Ok,
+            fix: Vec::new(),
             detail: format!("{} (sufficient for user-space stack sampling)", paranoid),
         },
         "2" => Check {
             name: "perf_event_paranoid",
             status: CheckStatus::Warn,
+            fix: lower_perf_paranoid(),
             detail: "2 (stack sampling may require CAP_PERFMON)".into(),
         },
         "3" | "4" => Check {
             name: "perf_event_paranoid",
             status: CheckStatus::Warn,
+            fix: lower_perf_paranoid(),
             detail: format!("{} (stack sampling disabled without CAP_PERFMON)", paranoid),
         },
         "N/A" => Check {
             name: "perf_event_paranoid",
             status: CheckStatus::Warn,
+            fix: Vec::new(),
             detail: "unable to read perf_event_paranoid".into(),
         },
         _ => Check {
             name: "perf_event_paranoid",
             status: CheckStatus::Warn,
+            fix: Vec::new(),
             detail: format!("unknown value: {}", paranoid),
         },
     }
 }
 
+/// Sets it now and for later boots.
+fn lower_perf_paranoid() -> Vec<String> {
+    vec![
+        "sysctl -w kernel.perf_event_paranoid=1".into(),
+        "echo kernel.perf_event_paranoid=1 > /etc/sysctl.d/60-poe-perf.conf".into(),
+    ]
+}
+
+/// Back to Yama's default, which still lets poe trace its own children.
+/// Granting poe CAP_SYS_PTRACE instead would let traced setuid programs
+/// keep their privileges, exposing what they read to the pack.
+fn lower_ptrace_scope() -> Vec<String> {
+    vec![
+        "sysctl -w kernel.yama.ptrace_scope=1".into(),
+        "echo kernel.yama.ptrace_scope=1 > /etc/sysctl.d/60-poe-ptrace.conf".into(),
+    ]
+}
+
 fn check_proc_filesystem() -> Check {
     if std::path::Path::new("/proc/self/maps").exists() {
         Check {
             name: "/proc filesystem",
             status: CheckStatus::Ok,
+            fix: Vec::new(),
             detail: "available (required for memory map reading)".into(),
         }
     } else {
         Check {
             name: "/proc filesystem",
             status: CheckStatus::Fail,
+            fix: Vec::new(),
             detail: "/proc not mounted or not accessible".into(),
         }
     }
@@ -227,13 +427,166 @@ fn check_process_vm_readv() -> Check {
         Check {
             name: "process_vm_readv",
             status: CheckStatus::Ok,
+            fix: Vec::new(),
             detail: "available (efficient cross-process memory reading)".into(),
         }
     } else {
         Check {
             name: "process_vm_readv",
             status: CheckStatus::Warn,
+            fix: Vec::new(),
             detail: "unavailable (will fall back to ptrace PEEKDATA, slower)".into(),
         }
     }
 }
+
+/// Each copy is a read and a write, enough syscalls for the per-call cost
+/// to stand out from poe's startup and pack writing.
+const PROBE_COPIES: u64 = 5000;
+
+impl BackendProbe {
+    fn failed(backend: &'static str, status: CheckStatus, error: String) -> Self {
+        Self {
+            backend,
+            status,
+            captured_ops: None,
+            overhead_us: None,
+            slowdown: None,
+            error: Some(error),
+        }
+    }
+
+    fn detail(&self) -> String {
+        if let Some(ref error) = self.error {
+            return error.clone();
+        }
+        let mut text = format!("captured {} file ops", self.captured_ops.unwrap_or(0));
+        if let Some(us) = self.overhead_us {
+            text.push_str(&format!(", ~{:.1} us per traced syscall", us));
+        }
+        if let Some(slowdown) = self.slowdown {
+            text.push_str(&format!(" ({:.1}x slower)", slowdown));
+        }
+        text
+    }
+}
+
+/// Runs a syscall-bound dd under `poe run` with each backend, and once
+/// without poe. Timing the same command with no copies as well takes
+/// poe's startup and pack writing out of the per-syscall figure.
+fn probe_backends() -> Vec<BackendProbe> {
+    let dd = |copies: u64| {
+        let mut cmd = Command::new("dd");
+        cmd.args([
+            "if=/dev/zero",
+            "of=/dev/null",
+            "bs=1",
+            &format!("count={}", copies),
+        ]);
+        cmd
+    };
+    let plain = match (time(dd(PROBE_COPIES)), time(dd(0))) {
+        (Ok(full), Ok(empty)) => full.saturating_sub(empty),
+        (Err(e), _) | (_, Err(e)) => {
+            let error = format!("cannot run the probe command: {:#}", e);
+            return vec![BackendProbe::failed("ptrace", CheckStatus::Warn, error)];
+        }
+    };
+
+    let mut probes = vec![probe("ptrace", plain, CheckStatus::Fail, &dd)];
+    probes.push(match crate::capture::unotify::unsupported_reason() {
+        Some(reason) => BackendProbe::failed("seccomp", CheckStatus::Warn, reason),
+        None => probe("seccomp", plain, CheckStatus::Warn, &dd),
+    });
+    probes
+}
+
+fn probe(
+    backend: &'static str,
+    plain: Duration,
+    on_error: CheckStatus,
+    dd: &dyn Fn(u64) -> Command,
+) -> BackendProbe {
+    let dir = std::env::temp_dir().join(format!("poe-doctor-{}-{}", std::process::id(), backend));
+    let result = (|| -> Result<BackendProbe> {
+        std::fs::create_dir_all(&dir)?;
+        let traced = |copies: u64| -> Result<Duration> {
+            let inner = dd(copies);
+            let mut cmd = Command::new(std::env::current_exe()?);
+            cmd.args(["run", "--always", "--backend", backend, "--output"])
+                .arg(&dir)
+                .arg("--")
+                .arg(inner.get_program())
+                .args(inner.get_args());
+            time(cmd)
+        };
+        let empty = traced(0)?;
+        for entry in std::fs::read_dir(&dir)? {
+            std::fs::remove_file(entry?.path())?;
+        }
+        let full = traced(PROBE_COPIES)?;
+        let pack = std::fs::read_dir(&dir)?
+            .filter_map(|e| e.ok())
+            .map(|e| e.path())
+            .find(|p| p.extension().is_some_and(|x| x == "poepack"))
+            .context("poe run wrote no pack")?;
+        let captured = PackReader::open(&pack)?.summary().stats.file_ops;
+        if (captured as u64) < PROBE_COPIES {
+            bail!(
+                "captured only {} file ops of the {} dd made",
+                captured,
+                PROBE_COPIES * 2
+            );
+        }
+
+        let extra = full.saturating_sub(empty).saturating_sub(plain);
+        let syscalls = PROBE_COPIES * 2;
+        Ok(BackendProbe {
+            backend,
+            status: CheckStatus::Ok,
+            captured_ops: Some(captured),
+            overhead_us: Some(extra.as_secs_f64() * 1e6 / syscalls as f64),
+            slowdown: (!plain.is_zero())
+                .then(|| full.saturating_sub(empty).as_secs_f64() / plain.as_secs_f64()),
+            error: None,
+        })
+    })();
+    let _ = std::fs::remove_dir_all(&dir);
+    result.unwrap_or_else(|e| BackendProbe::failed(backend, on_error, format!("{:#}", e)))
+}
+
+/// Wall time of a command that must succeed, with its output discarded.
+fn time(mut cmd: Command) -> Result<Duration> {
+    let start = Instant::now();
+    let status = cmd
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .with_context(|| format!("failed to run {}", cmd.get_program().to_string_lossy()))?;
+    if !status.success() {
+        bail!(
+            "{} exited with {}",
+            cmd.get_program().to_string_lossy(),
+            status
+        );
+    }
+    Ok(start.elapsed())
+}
+
+fn check_seccomp_unotify() -> Check {
+    match crate::capture::unotify::unsupported_reason() {
+        None => Check {
+            name: "seccomp user notification",
+            status: CheckStatus::Ok,
+            fix: Vec::new(),
+            detail: "available (--backend seccomp for lower-overhead capture)".into(),
+        },
+        Some(reason) => Check {
+            name: "seccomp user notification",
+            status: CheckStatus::Warn,
+            fix: Vec::new(),
+            detail: format!("{} (--backend seccomp will fall back to ptrace)", reason),
+        },
+    }
+}
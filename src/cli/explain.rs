@@ -1,21 +1,128 @@
+use std::collections::BTreeSet;
 use std::path::PathBuf;
 
 use anyhow::Result;
 use colored::Colorize;
 
+use crate::events::types::FileStat;
 use crate::explain::analyzer;
+use crate::explain::anomaly::{self, HistoryAnomalies};
+use crate::explain::context;
+use crate::explain::focus::{self, Focus};
+use crate::explain::markdown;
+use crate::explain::patterns::PatternRegistry;
+use crate::explain::zoom::{self, TimelineZoom};
+use crate::pack::filestat;
 use crate::pack::reader::PackReader;
+use crate::pack::remote;
+use crate::trace::dbqueries::DbStatement;
+use crate::trace::sched::SchedSummary;
 use crate::util;
 
-pub fn execute(pack_path: PathBuf, json: bool) -> Result<()> {
-    let pack = PackReader::open(&pack_path)?;
-    let output = analyzer::analyze(&pack)?;
+/// Frames of each failure stack printed; the rest are in the JSON output.
+const STACK_FRAMES: usize = 8;
 
-    if json {
-        println!("{}", serde_json::to_string_pretty(&output)?);
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Text,
+    Json,
+    Markdown,
+}
+
+impl Format {
+    fn parse(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "text" => Ok(Format::Text),
+            "json" => Ok(Format::Json),
+            "markdown" | "md" => Ok(Format::Markdown),
+            other => anyhow::bail!(
+                "unknown format '{}' (expected text, json, or markdown)",
+                other
+            ),
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn execute(
+    pack_path: PathBuf,
+    json: bool,
+    format: Option<String>,
+    pattern_files: Vec<PathBuf>,
+    suggest: bool,
+    context: bool,
+    budget: Option<String>,
+    focus: Vec<String>,
+    timeline_window: Option<String>,
+    pid: Option<i32>,
+    history: Option<PathBuf>,
+) -> Result<()> {
+    let format = match format {
+        Some(f) => Format::parse(&f)?,
+        None if json => Format::Json,
+        None => Format::Text,
+    };
+    let context_budget = match (context, budget) {
+        (false, _) => None,
+        (true, Some(b)) => Some(context::parse_budget(&b)?),
+        (true, None) => Some(context::DEFAULT_BUDGET_TOKENS),
+    };
+    let focus = focus
+        .iter()
+        .map(|f| Focus::parse(f))
+        .collect::<Result<Vec<_>>>()?;
+    if !focus.is_empty() && format == Format::Markdown {
+        anyhow::bail!("--focus applies to text and JSON output");
+    }
+    let show = |aspect: Focus| focus.is_empty() || focus.contains(&aspect);
+
+    let pack = PackReader::open(&remote::resolve(&pack_path)?)?;
+    let mut registry = PatternRegistry::load_default();
+    for path in &pattern_files {
+        registry.load_rules_file(path)?;
+    }
+    let mut output = analyzer::analyze_with(&pack, &registry)?;
+    if timeline_window.is_some() || pid.is_some() {
+        let (start, end) = match timeline_window {
+            Some(ref w) => zoom::parse_window(w)?,
+            None => (None, None),
+        };
+        output.timeline_window = Some(zoom::build(pack.db(), start, end, pid, zoom::MAX_ENTRIES)?);
+    }
+    if let Some(ref dir) = history {
+        output.history_anomalies = Some(anomaly::score_against(&pack, &output, dir)?);
+    }
+
+    if let Some(budget) = context_budget {
+        let block = context::build_context(pack.summary(), &output, budget);
+        if format == Format::Json {
+            println!("{}", serde_json::to_string_pretty(&block)?);
+        } else {
+            print!("{}", context::render_markdown(&block));
+        }
         return Ok(());
     }
 
+    match format {
+        Format::Json => {
+            let mut value = serde_json::to_value(&output)?;
+            if !focus.is_empty() {
+                let names: Vec<&str> = focus
+                    .iter()
+                    .flat_map(|f| f.fields().iter().copied())
+                    .collect();
+                focus::select_fields(&mut value, &names)?;
+            }
+            println!("{}", serde_json::to_string_pretty(&value)?);
+            return Ok(());
+        }
+        Format::Markdown => {
+            print!("{}", markdown::render(pack.summary(), &output));
+            return Ok(());
+        }
+        Format::Text => {}
+    }
+
     let summary = pack.summary();
 
     println!();
@@ -29,95 +136,236 @@ pub fn execute(pack_path: PathBuf, json: bool) -> Result<()> {
     if let Some(ref sha) = summary.git_sha {
         println!("{} {}", "git:".dimmed(), sha);
     }
+    if !summary.tags.is_empty() {
+        let tags: Vec<String> = summary
+            .tags
+            .iter()
+            .map(|(k, v)| format!("{}={}", k, v))
+            .collect();
+        println!("{} {}", "tags:".dimmed(), tags.join(" "));
+    }
     println!();
 
-    if !output.error_patterns.is_empty() {
-        println!("{}", "--- diagnosis ---".red().bold());
-        for pattern in &output.error_patterns {
-            let severity_colored = match pattern.severity.as_str() {
-                "critical" => format!("[{}]", pattern.severity).red().bold().to_string(),
-                "error" => format!("[{}]", pattern.severity).red().to_string(),
-                "warning" => format!("[{}]", pattern.severity).yellow().to_string(),
-                _ => format!("[{}]", pattern.severity),
+    if let Some(ref truncation) = output.truncation {
+        println!("{}", "--- pack truncated ---".yellow().bold());
+        println!(
+            "  {} {} (original {})",
+            "budget:".dimmed(),
+            format_bytes(truncation.budget_bytes),
+            format_bytes(truncation.original_bytes)
+        );
+        for step in &truncation.steps {
+            println!(
+                "  dropped {}: {} rows, {}",
+                step.stage,
+                step.rows_dropped,
+                format_bytes(step.bytes_dropped)
+            );
+        }
+        if !truncation.within_budget {
+            println!("  {}", "pack still exceeds budget".red());
+        }
+        println!();
+    }
+
+    if !output.secrets.is_empty() {
+        println!("{}", "--- possible secrets ---".red().bold());
+        for secret in &output.secrets {
+            let times = if secret.count > 1 {
+                format!(" (x{})", secret.count)
+            } else {
+                String::new()
             };
             println!(
-                "  {} {} {}",
-                severity_colored,
-                pattern.category.cyan(),
-                pattern.description,
+                "  {:<15} {:<20} {}{}",
+                secret.kind,
+                secret.source.dimmed(),
+                secret.preview,
+                times
             );
-            for example in &pattern.examples {
-                println!("    {}", example.dimmed());
-            }
         }
+        println!("  {}", "redact or drop the pack before sharing it".dimmed());
         println!();
     }
 
-    if let Some(ref failure) = output.failure {
-        println!("{}", "--- failure ---".red().bold());
-        println!("  {} {}", "kind:".dimmed(), failure.kind.red());
-        println!("  {} {}", "description:".dimmed(), failure.description);
-        if let Some(code) = failure.exit_code {
-            println!("  {} {}", "exit_code:".dimmed(), code);
+    if !output.capture_warnings.is_empty() {
+        println!("{}", "--- capture overhead ---".yellow().bold());
+        for warning in &output.capture_warnings {
+            println!("  {}", warning.yellow());
         }
-        if let Some(ref sig) = failure.signal {
-            println!("  {} {}", "signal:".dimmed(), sig.as_str().red());
+        println!();
+    }
+
+    if show(Focus::Failure) {
+        if !output.error_patterns.is_empty() {
+            println!("{}", "--- diagnosis ---".red().bold());
+            for pattern in &output.error_patterns {
+                let severity_colored = match pattern.severity.as_str() {
+                    "critical" => format!("[{}]", pattern.severity).red().bold().to_string(),
+                    "error" => format!("[{}]", pattern.severity).red().to_string(),
+                    "warning" => format!("[{}]", pattern.severity).yellow().to_string(),
+                    _ => format!("[{}]", pattern.severity),
+                };
+                let code = pattern
+                    .code
+                    .map(|c| format!(" {}", c.code()))
+                    .unwrap_or_default();
+                println!(
+                    "  {} {}{} {}",
+                    severity_colored,
+                    pattern.category.cyan(),
+                    code.dimmed(),
+                    pattern.description,
+                );
+                if pattern.root_cause {
+                    println!("    {}", "probable root cause".red().bold());
+                } else if let Some(ref cause) = pattern.caused_by {
+                    println!(
+                        "    {} {}",
+                        "likely a consequence of".dimmed(),
+                        cause.cyan()
+                    );
+                }
+                for example in &pattern.examples {
+                    println!("    {}", example.dimmed());
+                }
+            }
+            println!();
+        }
+
+        if suggest && !output.suggestions.is_empty() {
+            println!("{}", "--- suggestions ---".green().bold());
+            for s in &output.suggestions {
+                println!("  {} {}", s.category.cyan(), s.message);
+                if let Some(ref action) = s.action {
+                    println!("    {} {}", "try:".dimmed(), action);
+                }
+            }
+            println!();
         }
-        if let Some(ref loc) = failure.primary_location {
-            if let Some(ref func) = loc.function {
-                print!("  {} {}", "location:".dimmed(), func);
-                if let Some(ref file) = loc.file {
-                    print!(" at {}", file);
-                    if let Some(line) = loc.line {
-                        print!(":{}", line);
+
+        if let Some(ref failure) = output.failure {
+            println!("{}", "--- failure ---".red().bold());
+            println!("  {} {}", "kind:".dimmed(), failure.kind.red());
+            println!("  {} {}", "description:".dimmed(), failure.description);
+            if let (Some(pid), Some(process)) = (failure.primary_pid, &failure.process) {
+                println!("  {} {} {}", "process:".dimmed(), pid, process);
+            }
+            if let Some(code) = failure.exit_code {
+                println!("  {} {}", "exit_code:".dimmed(), code);
+            }
+            if let Some(ref sig) = failure.signal {
+                println!("  {} {}", "signal:".dimmed(), sig.as_str().red());
+            }
+            if let Some(ref loc) = failure.primary_location {
+                if let Some(ref func) = loc.function {
+                    print!("  {} {}", "location:".dimmed(), func);
+                    if let Some(ref file) = loc.file {
+                        print!(" at {}", file);
+                        if let Some(line) = loc.line {
+                            print!(":{}", line);
+                        }
                     }
+                    println!();
                 }
-                println!();
             }
+            if let Some(ref step) = output.shell_failed_step {
+                println!("  {} {}", "shell step:".dimmed(), step.command.yellow());
+            }
+            if let Some(stmt) = output
+                .database
+                .as_ref()
+                .and_then(|d| d.failed_statement.as_ref())
+            {
+                println!("  {} {}", "sql error:".dimmed(), db_error_text(stmt).red());
+                if !stmt.statement.is_empty() {
+                    println!(
+                        "  {} {}",
+                        "statement:".dimmed(),
+                        short_statement(stmt).yellow()
+                    );
+                }
+            }
+            if let Some(exc) = output.python_exceptions.last() {
+                print_python_exception(exc);
+            }
+            println!();
+        } else {
+            println!("{}", "--- no failure detected ---".green().bold());
+            println!();
         }
-        println!();
-    } else {
-        println!("{}", "--- no failure detected ---".green().bold());
-        println!();
-    }
 
-    if !output.process_tree.is_empty() {
-        println!("{}", "--- process tree ---".yellow().bold());
-        for proc in &output.process_tree {
-            let status = if let Some(sig) = proc.signal {
-                format!("killed by {}", util::signal_name(sig))
-                    .red()
-                    .to_string()
-            } else if let Some(code) = proc.exit_code {
-                if code == 0 {
-                    "ok".green().to_string()
+        if let Some(ref history) = output.history_anomalies {
+            print_anomalies(history);
+        }
+
+        if let Some(ref build) = output.build {
+            print_build_targets(build);
+        }
+
+        if let Some(ref database) = output.database {
+            print_db_statements(database);
+        }
+
+        if !output.shell_steps.is_empty() {
+            println!("{}", "--- shell steps ---".cyan().bold());
+            for step in &output.shell_steps {
+                let file = step.file.rsplit('/').next().unwrap_or(&step.file);
+                let location = match step.func {
+                    Some(ref func) => format!("{}:{} {}()", file, step.line, func),
+                    None => format!("{}:{}", file, step.line),
+                };
+                let command = step.command.lines().next().unwrap_or("");
+                if output.shell_failed_step.as_ref() == Some(step) {
+                    println!("  {} {:<24} {}", ">".red().bold(), location, command.red());
                 } else {
-                    format!("exit {}", code).red().to_string()
+                    println!("    {:<24} {}", location.dimmed(), command);
                 }
-            } else {
-                "?".dimmed().to_string()
-            };
+            }
+            println!();
+        }
 
-            let duration = proc
-                .duration_ms
-                .map(|d| format!(" ({:.1}ms)", d))
-                .unwrap_or_default();
+        if !output.process_tree.is_empty() {
+            println!("{}", "--- process tree ---".yellow().bold());
+            for proc in &output.process_tree {
+                let status = if let Some(sig) = proc.signal {
+                    format!("killed by {}", util::signal_name(sig))
+                        .red()
+                        .to_string()
+                } else if let Some(code) = proc.exit_code {
+                    if code == 0 {
+                        "ok".green().to_string()
+                    } else {
+                        format!("exit {}", code).red().to_string()
+                    }
+                } else {
+                    "?".dimmed().to_string()
+                };
 
-            let indent = if proc.parent_pid.is_some() {
-                "    "
-            } else {
-                "  "
-            };
+                let mut duration = proc
+                    .duration_ms
+                    .map(|d| format!(" ({:.1}ms)", d))
+                    .unwrap_or_default();
+                if proc.excluded {
+                    duration.push_str(&" [not traced]".dimmed().to_string());
+                }
 
-            println!(
-                "{}[{}] {}{} -> {}",
-                indent, proc.pid, proc.command, duration, status
-            );
+                let indent = if proc.parent_pid.is_some() {
+                    "    "
+                } else {
+                    "  "
+                };
+
+                println!(
+                    "{}[{}] {}{} -> {}",
+                    indent, proc.pid, proc.command, duration, status
+                );
+            }
+            println!();
         }
-        println!();
     }
 
-    if !output.hotspots.is_empty() {
+    if show(Focus::Hotspots) && !output.hotspots.is_empty() {
         println!("{}", "--- stack hotspots ---".yellow().bold());
         for hs in &output.hotspots {
             println!("  {:5.1}% ({:>5}) {}", hs.percentage, hs.count, hs.location);
@@ -125,147 +373,464 @@ pub fn execute(pack_path: PathBuf, json: bool) -> Result<()> {
         println!();
     }
 
-    if let Some(ref panic) = output.rust_panic {
-        println!("{}", "--- rust panic ---".red().bold());
-        println!("  {} {}", "panic:".red().bold(), panic.message,);
-        if let Some(ref loc) = panic.location {
-            println!(
-                "  {} {}:{}{}",
-                "at:".dimmed(),
-                loc.file,
-                loc.line,
-                loc.column.map(|c| format!(":{}", c)).unwrap_or_default(),
-            );
+    if show(Focus::Failure) {
+        if let Some(ref panic) = output.rust_panic {
+            println!("{}", "--- rust panic ---".red().bold());
+            println!("  {} {}", "panic:".red().bold(), panic.message,);
+            if let Some(ref loc) = panic.location {
+                println!(
+                    "  {} {}:{}{}",
+                    "at:".dimmed(),
+                    loc.file,
+                    loc.line,
+                    loc.column.map(|c| format!(":{}", c)).unwrap_or_default(),
+                );
+            }
+            if let Some(ref thread) = panic.thread {
+                println!("  {} {}", "thread:".dimmed(), thread);
+            }
+            if !panic.backtrace.is_empty() {
+                println!("  {}", "backtrace:".dimmed());
+                for frame in &panic.backtrace {
+                    let sym = frame.symbol.as_deref().unwrap_or("???");
+                    let loc = match (&frame.file, frame.line) {
+                        (Some(f), Some(l)) => format!(" at {}:{}", f, l),
+                        _ => String::new(),
+                    };
+                    let is_user = !sym.contains("std::")
+                        && !sym.contains("core::")
+                        && !sym.contains("__rust_")
+                        && !sym.contains("backtrace::");
+                    if is_user {
+                        println!("    {} #{}: {}{}", ">".cyan(), frame.index, sym.cyan(), loc,);
+                    } else {
+                        println!(
+                            "    {} #{}: {}{}",
+                            " ".dimmed(),
+                            frame.index,
+                            Colorize::dimmed(sym),
+                            loc.dimmed(),
+                        );
+                    }
+                }
+            }
+            println!();
         }
-        if let Some(ref thread) = panic.thread {
-            println!("  {} {}", "thread:".dimmed(), thread);
+
+        // The exception that ended the run is shown under the failure section.
+        let shown_in_failure =
+            usize::from(output.failure.is_some() && !output.python_exceptions.is_empty());
+        let other_exceptions =
+            &output.python_exceptions[..output.python_exceptions.len() - shown_in_failure];
+        if !other_exceptions.is_empty() {
+            println!("{}", "--- python exceptions ---".red().bold());
+            for exc in other_exceptions {
+                print_python_exception(exc);
+                println!();
+            }
         }
-        if !panic.backtrace.is_empty() {
-            println!("  {}", "backtrace:".dimmed());
-            for frame in &panic.backtrace {
-                let sym = frame.symbol.as_deref().unwrap_or("???");
-                let loc = match (&frame.file, frame.line) {
-                    (Some(f), Some(l)) => format!(" at {}:{}", f, l),
-                    _ => String::new(),
-                };
-                let is_user = !sym.contains("std::")
-                    && !sym.contains("core::")
-                    && !sym.contains("__rust_")
-                    && !sym.contains("backtrace::");
-                if is_user {
-                    println!("    {} #{}: {}{}", ">".cyan(), frame.index, sym.cyan(), loc,);
-                } else {
-                    println!(
-                        "    {} #{}: {}{}",
-                        " ".dimmed(),
-                        frame.index,
-                        Colorize::dimmed(sym),
-                        loc.dimmed(),
-                    );
-                }
+
+        if let Some(ref exc) = output.python_exception_stack {
+            println!(
+                "{}",
+                format!("--- python call stack at {} ---", exc.exc_type)
+                    .red()
+                    .bold()
+            );
+            for frame in &exc.stack {
+                let file = frame.file.rsplit('/').next().unwrap_or(&frame.file);
+                println!(
+                    "  {:>10.2}ms  {}() at {}:{}",
+                    frame.elapsed_ms,
+                    frame.func.cyan(),
+                    file,
+                    frame.line
+                );
             }
+            println!();
         }
-        println!();
     }
 
-    if !output.python_exceptions.is_empty() {
-        println!("{}", "--- python exceptions ---".red().bold());
-        for exc in &output.python_exceptions {
+    if show(Focus::Hotspots) && !output.python_slow_functions.is_empty() {
+        println!("{}", "--- slowest python functions ---".yellow().bold());
+        for f in &output.python_slow_functions {
+            let file = f.file.rsplit('/').next().unwrap_or(&f.file);
             println!(
-                "  {} {}: {}",
-                ">>>".red().bold(),
-                exc.exc_type.red().bold(),
-                exc.exc_msg,
+                "  {:>10.2}ms  self {:>9.2}ms  {:>6}x  {}() at {}:{}",
+                f.total_ms, f.self_ms, f.calls, f.func, file, f.line
             );
+        }
+        println!();
+    }
 
-            if !exc.chain.is_empty() && exc.chain.len() > 1 {
-                println!("  {}", "exception chain:".dimmed());
-                for entry in &exc.chain {
-                    let cause = entry.cause.as_deref().unwrap_or("root");
-                    println!(
-                        "    [{}] {}: {}",
-                        cause,
-                        entry.exc_type,
-                        &entry.msg[..entry.msg.len().min(80)]
-                    );
-                }
+    if show(Focus::Files) {
+        println!("{}", "--- file activity ---".yellow().bold());
+        println!(
+            "  {} total ops, {} unique paths",
+            output.file_activity.total_ops, output.file_activity.unique_paths
+        );
+        println!(
+            "  {} read, {} written",
+            format_bytes(output.file_activity.total_bytes_read),
+            format_bytes(output.file_activity.total_bytes_written),
+        );
+        if let Some(ref kio) = output.kernel_io {
+            if kio.untraced_read_bytes + kio.untraced_write_bytes >= UNTRACED_IO_BYTES {
+                println!(
+                    "  {} {} read, {} written outside traced syscalls (mmap, io_uring)",
+                    "+".dimmed(),
+                    format_bytes(kio.untraced_read_bytes),
+                    format_bytes(kio.untraced_write_bytes),
+                );
             }
-
-            println!("  {}", "traceback:".dimmed());
-            for frame in &exc.traceback {
+        }
+        if !output.file_activity.most_accessed.is_empty() {
+            println!("  {}", "most accessed:".dimmed());
+            for (path, count) in &output.file_activity.most_accessed {
+                println!("    {:>5}x {}", count, path);
+            }
+        }
+        if !output.file_activity.directories.is_empty() {
+            println!("  {}", "by directory:".dimmed());
+            for d in &output.file_activity.directories {
                 println!(
-                    "    {} {}:{} in {}",
-                    ">".cyan(),
-                    frame.file,
-                    frame.line,
-                    frame.func.cyan(),
+                    "    {:>5} ops {:>5} files  {} read, {} written  {}",
+                    d.ops,
+                    d.files,
+                    format_bytes(d.bytes_read),
+                    format_bytes(d.bytes_written),
+                    d.path
                 );
-                if let Some(ref locals) = frame.locals {
-                    for (k, v) in locals.iter().take(8) {
-                        println!("      {} = {}", k.yellow(), v.dimmed());
+            }
+        }
+        if !output.file_activity.permission_errors.is_empty() {
+            println!("  {}", "permission denied:".red());
+            for f in output.file_activity.permission_errors.iter().take(5) {
+                println!("    {} {} ({})", f.op_label(), f.path, f.errno_name);
+            }
+        }
+        if !output.file_activity.exclusive_creates.is_empty() {
+            println!("  {}", "already exists (exclusive create):".yellow());
+            for f in output.file_activity.exclusive_creates.iter().take(5) {
+                println!("    pid {} {} {}", f.pid, f.op_label(), f.path);
+            }
+        }
+        let fa = &output.file_activity;
+        let failed_paths: Vec<&str> = fa
+            .permission_errors
+            .iter()
+            .chain(
+                fa.failed_opens
+                    .iter()
+                    .filter(|f| analyzer::is_significant_missing_file(&f.path)),
+            )
+            .chain(&fa.exclusive_creates)
+            .map(|f| f.path.as_str())
+            .collect();
+        let mut seen = std::collections::HashSet::new();
+        let mut stated: Vec<(&str, &FileStat)> = failed_paths
+            .into_iter()
+            .filter(|path| seen.insert(*path))
+            .filter_map(|path| Some((path, fa.stat_of(path)?)))
+            .collect();
+        // Relative paths are usually the program's own files rather than
+        // interpreter or toolchain probes; and files that are there, or whose
+        // directory isn't, say more than a plain "missing".
+        stated.sort_by_key(|(path, stat)| {
+            let parent_missing = std::path::Path::new(path)
+                .parent()
+                .and_then(|p| fa.stat_of(&p.to_string_lossy()))
+                .is_some_and(|p| p.file_type == "missing");
+            (
+                path.starts_with('/'),
+                stat.file_type == "missing" && !parent_missing,
+            )
+        });
+        let states: Vec<String> = stated
+            .into_iter()
+            .map(|(path, stat)| {
+                let mut line = format!("{}: {}", path, filestat::describe(stat));
+                if stat.file_type == "missing" {
+                    let parent = std::path::Path::new(path)
+                        .parent()
+                        .and_then(|p| fa.stat_of(&p.to_string_lossy()));
+                    if let Some(parent) = parent {
+                        line.push_str(&format!(
+                            "; {} is {}",
+                            parent.path,
+                            filestat::describe(parent)
+                        ));
                     }
                 }
+                line
+            })
+            .collect();
+        if !states.is_empty() {
+            println!("  {}", "state when packed:".dimmed());
+            for line in states.iter().take(5) {
+                println!("    {}", line);
+            }
+        }
+        let traced: Vec<_> = fa
+            .failed_opens
+            .iter()
+            .chain(&fa.permission_errors)
+            .chain(&fa.exclusive_creates)
+            .filter(|f| !f.stack.is_empty())
+            .collect();
+        if !traced.is_empty() {
+            println!("  {}", "failing call sites:".dimmed());
+            for f in traced.iter().take(3) {
+                println!(
+                    "    pid {} {} {} -> {}",
+                    f.pid,
+                    f.op_label(),
+                    f.path,
+                    f.errno_name
+                );
+                print_stack(&f.stack);
             }
+        }
+        println!();
+    }
 
-            if let Some(ref locals) = exc.locals_at_crash {
-                if !locals.is_empty() {
-                    println!("  {}", "locals at crash site:".dimmed());
-                    for (k, v) in locals.iter().take(12) {
-                        println!("    {} = {}", k.yellow(), v.dimmed());
-                    }
-                }
+    if show(Focus::Net) {
+        println!("{}", "--- network activity ---".yellow().bold());
+        println!("  {} total ops", output.net_activity.total_ops);
+        println!(
+            "  {} sent, {} received",
+            format_bytes(output.net_activity.total_bytes_sent),
+            format_bytes(output.net_activity.total_bytes_received),
+        );
+        if let Some(net) = output.kernel_io.as_ref().and_then(|k| k.network.as_ref()) {
+            let untraced = (net.rx_bytes + net.tx_bytes)
+                .saturating_sub(net.traced_rx_bytes + net.traced_tx_bytes);
+            if untraced >= UNTRACED_IO_BYTES {
+                println!(
+                    "  {} interfaces moved {} received, {} sent during the run (whole network namespace)",
+                    "+".dimmed(),
+                    format_bytes(net.rx_bytes),
+                    format_bytes(net.tx_bytes),
+                );
             }
+        }
+        for pb in &output.net_activity.bytes_by_proto {
+            println!(
+                "    {:<10} {} sent, {} received",
+                pb.proto,
+                format_bytes(pb.sent),
+                format_bytes(pb.received)
+            );
+        }
+        if !output.net_activity.connections.is_empty() {
+            println!("  {}", "connections:".dimmed());
+            for conn in &output.net_activity.connections {
+                println!("    {} ({})", conn.addr, conn.result);
+            }
+        }
+        if !output.net_activity.failed_connections.is_empty() {
+            println!("  {}", "failed connections:".red());
+            for fc in output.net_activity.failed_connections.iter().take(5) {
+                println!("    {} -> {}", fc.addr, fc.errno_name);
+                print_stack(&fc.stack);
+            }
+        }
+        if output.net_activity.http2_requests > 0 {
+            println!(
+                "  {} HTTP/2 requests, {} failed",
+                output.net_activity.http2_requests, output.net_activity.http2_failed
+            );
+        }
+        if !output.net_activity.failed_rpcs.is_empty() {
+            println!("  {}", "failed rpcs:".red());
+            for rpc in output.net_activity.failed_rpcs.iter().rev().take(5) {
+                println!(
+                    "    {} {} -> {}",
+                    rpc.authority.as_deref().unwrap_or("?"),
+                    rpc.path.as_deref().unwrap_or("?"),
+                    rpc.outcome()
+                );
+            }
+        }
+        println!();
+    }
 
-            println!();
+    if show(Focus::Hotspots) && !output.slow_ops.is_empty() {
+        println!("{}", "--- slowest operations ---".yellow().bold());
+        for op in &output.slow_ops {
+            let outcome = match op.errno_name {
+                Some(ref e) => format!(" -> {}", e).red().to_string(),
+                None => String::new(),
+            };
+            println!(
+                "  {:>10.1}ms  [{}] {} {}{}",
+                op.duration_ms, op.pid, op.op, op.target, outcome
+            );
         }
+        println!();
     }
 
-    println!("{}", "--- file activity ---".yellow().bold());
-    println!(
-        "  {} total ops, {} unique paths",
-        output.file_activity.total_ops, output.file_activity.unique_paths
-    );
-    println!(
-        "  {} read, {} written",
-        format_bytes(output.file_activity.total_bytes_read),
-        format_bytes(output.file_activity.total_bytes_written),
-    );
-    if !output.file_activity.most_accessed.is_empty() {
-        println!("  {}", "most accessed:".dimmed());
-        for (path, count) in &output.file_activity.most_accessed {
-            println!("    {:>5}x {}", count, path);
+    if show(Focus::Hotspots) && !output.slow_functions.is_empty() {
+        println!("{}", "--- slowest native functions ---".yellow().bold());
+        for f in &output.slow_functions {
+            println!(
+                "  {:>10.2}ms  self {:>9.2}ms  {:>6}x  {}",
+                f.inclusive_ms, f.exclusive_ms, f.calls, f.func
+            );
+        }
+        println!();
+    }
+
+    if let Some(sched) = output.scheduling.as_ref().filter(|_| show(Focus::Hotspots)) {
+        print_scheduling(sched);
+    }
+
+    if show(Focus::Timeline) && !output.signals.deliveries.is_empty() {
+        println!("{}", "--- signals ---".yellow().bold());
+        for d in output.signals.deliveries.iter().take(20) {
+            let name = if d.fatal {
+                format!("{} (fatal)", d.signal).red().to_string()
+            } else {
+                d.signal.clone()
+            };
+            println!("  {:>10.2}ms [{}] {}", d.ts_ms, d.pid, name);
+        }
+        if output.signals.deliveries.len() > 20 {
+            println!("  ... and {} more", output.signals.deliveries.len() - 20);
+        }
+        // A handler reinstalled later in the run is listed once.
+        let handled: BTreeSet<String> = output
+            .signals
+            .handlers
+            .iter()
+            .filter(|h| h.disposition != "default")
+            .map(|h| {
+                let mut line = format!("[{}] {} {}", h.pid, h.signal, h.disposition);
+                if !h.flags.is_empty() {
+                    line.push_str(&format!(" ({})", h.flags.join("|")));
+                }
+                line
+            })
+            .collect();
+        if !handled.is_empty() {
+            println!("  {}", "handlers installed:".dimmed());
+            for h in handled.iter().take(10) {
+                println!("    {}", h);
+            }
         }
+        println!();
     }
-    if !output.file_activity.permission_errors.is_empty() {
-        println!("  {}", "permission denied:".red());
-        for f in output.file_activity.permission_errors.iter().take(5) {
-            println!("    {} {} ({})", f.op, f.path, f.errno_name);
+
+    let logs = &output.logs;
+    if show(Focus::Timeline) && logs.total > 0 {
+        println!("{}", "--- structured logs ---".yellow().bold());
+        println!(
+            "  {} lines, {} errors, {} warnings",
+            logs.total,
+            logs.errors.to_string().red(),
+            logs.warnings.to_string().yellow()
+        );
+        for l in logs.by_logger.iter().take(10) {
+            println!(
+                "    {:<30} {:>5} errors {:>5} warnings {:>7} total",
+                l.logger, l.errors, l.warnings, l.total
+            );
         }
+        if let Some(ref first) = logs.first_error {
+            println!(
+                "  {} {:.2}ms [{}] {}{}",
+                "first error:".red().bold(),
+                first.ts_ms,
+                first.pid,
+                first
+                    .logger
+                    .as_ref()
+                    .map(|l| format!("{}: ", l))
+                    .unwrap_or_default(),
+                first.message
+            );
+        }
+        println!();
     }
-    println!();
 
-    println!("{}", "--- network activity ---".yellow().bold());
-    println!("  {} total ops", output.net_activity.total_ops);
-    println!(
-        "  {} sent, {} received",
-        format_bytes(output.net_activity.total_bytes_sent),
-        format_bytes(output.net_activity.total_bytes_received),
-    );
-    if !output.net_activity.connections.is_empty() {
-        println!("  {}", "connections:".dimmed());
-        for conn in &output.net_activity.connections {
-            println!("    {} ({})", conn.addr, conn.result);
+    if show(Focus::Timeline) && !output.kernel_log.is_empty() {
+        println!("{}", "--- kernel log ---".yellow().bold());
+        for e in &output.kernel_log {
+            println!(
+                "  {:>10.2}ms [{}] {} {}",
+                e.ts_ms,
+                e.pid,
+                e.level.red(),
+                e.message
+            );
         }
+        println!();
     }
-    if !output.net_activity.failed_connections.is_empty() {
-        println!("  {}", "failed connections:".red());
-        for fc in output.net_activity.failed_connections.iter().take(5) {
-            println!("    {} -> {}", fc.addr, fc.errno_name);
+
+    let server = &output.server_activity;
+    if show(Focus::Net) && (!server.listeners.is_empty() || !server.bind_errors.is_empty()) {
+        println!("{}", "--- server activity ---".yellow().bold());
+        for l in &server.listeners {
+            let state = if l.listening { "listening" } else { "bound" };
+            println!(
+                "  [{}] {} {} {} ({} accepted)",
+                l.pid,
+                l.proto.as_deref().unwrap_or("-"),
+                l.addr,
+                state,
+                l.accepts
+            );
         }
+        for err in &server.bind_errors {
+            let line = format!("bind {} failed: {}", err.addr, err.errno_name);
+            if err.errno == libc::EADDRINUSE as i64 {
+                println!("  {} {}", line.red(), "(port already in use)".red().bold());
+                if let Some(c) = output
+                    .port_conflicts
+                    .iter()
+                    .find(|c| c.pid == err.pid && c.addr == err.addr)
+                {
+                    let holder = match (c.holder_pid, c.holder_command.as_deref()) {
+                        (Some(pid), Some(cmd)) => format!("pid {} ({})", pid, cmd),
+                        (Some(pid), None) => format!("pid {}", pid),
+                        (None, _) => "unknown".into(),
+                    };
+                    let mut note = format!("held by {} [{}]", holder, c.holder_source);
+                    if let Some(ref state) = c.holder_state {
+                        note.push_str(&format!(" {}", state));
+                    }
+                    if c.restart_race {
+                        note.push_str(" -- restart race");
+                    }
+                    println!("    {}", note.dimmed());
+                }
+            } else {
+                println!("  {}", line.red());
+            }
+        }
+        if !server.peers.is_empty() {
+            println!("  {}", "peers:".dimmed());
+            for p in server.peers.iter().take(10) {
+                println!(
+                    "    {} -> {} x{}: {} sent, {} received",
+                    p.peer,
+                    p.local,
+                    p.connections,
+                    format_bytes(p.sent),
+                    format_bytes(p.received)
+                );
+            }
+        }
+        println!();
     }
-    println!();
 
-    if !output.timeline.merged.is_empty() {
+    if let Some(zoom) = output
+        .timeline_window
+        .as_ref()
+        .filter(|_| show(Focus::Timeline))
+    {
+        print_zoom(zoom);
+    } else if show(Focus::Timeline) && !output.timeline.merged.is_empty() {
         println!("{}", "--- timeline ---".yellow().bold());
         for entry in &output.timeline.merged {
             let kind_colored = match entry.kind.as_str() {
@@ -282,7 +847,7 @@ pub fn execute(pack_path: PathBuf, json: bool) -> Result<()> {
         println!();
     }
 
-    if let Some(ref stderr_tail) = output.stderr_tail {
+    if let Some(stderr_tail) = output.stderr_tail.as_ref().filter(|_| show(Focus::Stderr)) {
         println!("{}", "--- stderr (tail) ---".yellow().bold());
         for line in stderr_tail.lines().take(30) {
             println!("  {}", Colorize::dimmed(line));
@@ -290,7 +855,7 @@ pub fn execute(pack_path: PathBuf, json: bool) -> Result<()> {
         println!();
     }
 
-    if let Some(ref stdout_tail) = output.stdout_tail {
+    if let Some(stdout_tail) = output.stdout_tail.as_ref().filter(|_| show(Focus::Stderr)) {
         println!("{}", "--- stdout (tail) ---".yellow().bold());
         for line in stdout_tail.lines().take(10) {
             println!("  {}", Colorize::dimmed(line));
@@ -304,6 +869,164 @@ pub fn execute(pack_path: PathBuf, json: bool) -> Result<()> {
     Ok(())
 }
 
+fn print_anomalies(history: &HistoryAnomalies) {
+    println!(
+        "{}",
+        format!(
+            "--- anomalies vs. history ({} earlier runs) ---",
+            history.runs
+        )
+        .yellow()
+        .bold()
+    );
+    if history.runs == 0 {
+        println!(
+            "  no earlier runs of this command in {}",
+            history.history_dir
+        );
+        println!();
+        return;
+    }
+    if let Some(ref d) = history.duration {
+        let z = d.z.map(|z| format!(", z {:+.1}", z)).unwrap_or_default();
+        println!(
+            "  {} {}ms (mean {:.0}ms, stddev {:.0}ms{})",
+            "duration:".dimmed(),
+            d.duration_ms,
+            d.mean_ms,
+            d.stddev_ms,
+            z
+        );
+    }
+    if history.anomalies.is_empty() {
+        println!("  {}", "nothing unusual".green());
+    }
+    for a in &history.anomalies {
+        let score = format!("{:.2}", a.score);
+        let score = if a.score >= 0.9 {
+            score.red()
+        } else {
+            score.yellow()
+        };
+        println!("  {} {:<16} {}", score, a.kind.cyan(), a.description);
+    }
+    println!();
+}
+
+fn print_zoom(zoom: &TimelineZoom) {
+    let bound = |ms: Option<f64>| ms.map(|ms| format!("{}ms", ms)).unwrap_or_default();
+    let mut title = "--- timeline".to_string();
+    if zoom.start_ms.is_some() || zoom.end_ms.is_some() {
+        title.push_str(&format!(
+            " {}..{}",
+            bound(zoom.start_ms),
+            bound(zoom.end_ms)
+        ));
+    }
+    if let Some(pid) = zoom.pid {
+        title.push_str(&format!(" pid {}", pid));
+    }
+    println!("{}", format!("{} ---", title).yellow().bold());
+    if zoom.entries.is_empty() {
+        println!("  {}", "no activity".dimmed());
+    }
+    for entry in &zoom.entries {
+        let kind_colored = match entry.kind.as_str() {
+            "event" => entry.kind.cyan().to_string(),
+            "file" => entry.kind.blue().to_string(),
+            "net" => entry.kind.magenta().to_string(),
+            _ => entry.kind.clone(),
+        };
+        println!(
+            "  {:>10.2}ms [{}] {:>5} {}",
+            entry.ts_ms, entry.proc_id, kind_colored, entry.description
+        );
+    }
+    if zoom.entries.len() < zoom.total {
+        println!(
+            "  {}",
+            format!(
+                "{} operations in {} lines{}",
+                zoom.total,
+                zoom.entries.len(),
+                if zoom.omitted > 0 {
+                    format!(
+                        ", {} more lines past these: narrow the window",
+                        zoom.omitted
+                    )
+                } else {
+                    String::new()
+                }
+            )
+            .dimmed()
+        );
+    }
+    println!();
+}
+
+fn print_python_exception(exc: &analyzer::PythonExceptionInfo) {
+    println!(
+        "  {} {}: {}",
+        ">>>".red().bold(),
+        exc.exc_type.red().bold(),
+        exc.exc_msg,
+    );
+
+    if !exc.chain.is_empty() && exc.chain.len() > 1 {
+        println!("  {}", "exception chain:".dimmed());
+        for entry in &exc.chain {
+            let cause = entry.cause.as_deref().unwrap_or("root");
+            println!(
+                "    [{}] {}: {}",
+                cause,
+                entry.exc_type,
+                entry.msg.chars().take(80).collect::<String>()
+            );
+        }
+    }
+
+    println!("  {}", "traceback:".dimmed());
+    for frame in &exc.traceback {
+        println!(
+            "    {} {}:{} in {}",
+            ">".cyan(),
+            frame.file,
+            frame.line,
+            frame.func.cyan(),
+        );
+        if let Some(ref locals) = frame.locals {
+            for (k, v) in locals.iter().take(8) {
+                println!("      {} = {}", k.yellow(), v.dimmed());
+            }
+        }
+    }
+
+    if let Some(ref locals) = exc.locals_at_crash {
+        if !locals.is_empty() {
+            println!("  {}", "locals at crash site:".dimmed());
+            for (k, v) in locals.iter().take(12) {
+                println!("    {} = {}", k.yellow(), v.dimmed());
+            }
+        }
+    }
+}
+
+/// Frames of a failure stack, innermost first.
+fn print_stack(stack: &[String]) {
+    for frame in stack.iter().take(STACK_FRAMES) {
+        println!("      {} {}", "at".dimmed(), frame);
+    }
+    if stack.len() > STACK_FRAMES {
+        println!(
+            "      {}",
+            format!("... {} more", stack.len() - STACK_FRAMES).dimmed()
+        );
+    }
+}
+
+/// Kernel-counted I/O the trace missed that is worth a line.
+const UNTRACED_IO_BYTES: u64 = 1024 * 1024;
+
 fn format_bytes(bytes: u64) -> String {
     if bytes == 0 {
         "0 bytes".into()
@@ -317,3 +1040,161 @@ fn format_bytes(bytes: u64) -> String {
         format!("{:.2} GB", bytes as f64 / (1024.0 * 1024.0 * 1024.0))
     }
 }
+
+fn db_error_text(stmt: &DbStatement) -> String {
+    let mut text = String::new();
+    if let Some(ref severity) = stmt.error_severity {
+        text.push_str(severity);
+        text.push(' ');
+    }
+    if let Some(ref code) = stmt.error_code {
+        text.push_str(code);
+        text.push(' ');
+    }
+    if let Some(vendor) = stmt.vendor_code {
+        text.push_str(&format!("({}) ", vendor));
+    }
+    text.push_str(stmt.error_message.as_deref().unwrap_or(""));
+    text
+}
+
+fn short_statement(stmt: &DbStatement) -> String {
+    if stmt.statement.is_empty() {
+        return "(connection)".into();
+    }
+    let line = stmt
+        .statement
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ");
+    match line.char_indices().nth(100) {
+        Some((i, _)) => format!("{}...", &line[..i]),
+        None => line,
+    }
+}
+
+fn print_scheduling(sched: &SchedSummary) {
+    println!("{}", "--- scheduling ---".yellow().bold());
+    println!(
+        "  {} {:.1}ms running, {:.1}ms waiting for a CPU ({:.0}%) over {} task{}",
+        "cpu:".dimmed(),
+        sched.run_ms,
+        sched.wait_ms,
+        sched.wait_share * 100.0,
+        sched.tasks,
+        if sched.tasks == 1 { "" } else { "s" }
+    );
+    println!(
+        "  {} {} voluntary, {} involuntary",
+        "context switches:".dimmed(),
+        sched.voluntary_switches,
+        sched.involuntary_switches
+    );
+    if sched.oversubscribed {
+        println!(
+            "  {}",
+            "the machine was oversubscribed: time went to waiting for a CPU, not to the code"
+                .yellow()
+        );
+    }
+    for p in sched.processes.iter().filter(|p| p.wait_ms >= 1.0) {
+        println!(
+            "  {:>10.1}ms wait {:>10.1}ms run  [{}] {}",
+            p.wait_ms,
+            p.run_ms,
+            p.pid,
+            p.command.as_deref().unwrap_or("?")
+        );
+    }
+    println!();
+}
+
+fn print_db_statements(database: &analyzer::DbSummary) {
+    println!(
+        "{}",
+        format!(
+            "--- sql statements ({} sent, {} failed) ---",
+            database.statements, database.failed
+        )
+        .cyan()
+        .bold()
+    );
+    for stmt in &database.recent {
+        let duration = match stmt.duration_ms {
+            Some(ms) => format!("{:>9.1}ms", ms),
+            None => format!("{:>11}", "-"),
+        };
+        let text = short_statement(stmt);
+        match stmt.status.as_str() {
+            "error" => {
+                println!("  {} {}  {}", ">".red().bold(), duration, text.red());
+                println!("      {}", db_error_text(stmt).red());
+                if let Some(ref detail) = stmt.error_detail {
+                    println!("      {}", detail.dimmed());
+                }
+            }
+            "no_response" => {
+                println!("    {}  {} {}", duration, text, "(no response)".yellow())
+            }
+            _ => println!(
+                "    {}  {} {}",
+                duration.dimmed(),
+                text,
+                stmt.tag.as_deref().unwrap_or("").dimmed()
+            ),
+        }
+    }
+    println!();
+}
+
+fn print_build_targets(build: &analyzer::BuildSummary) {
+    println!(
+        "{}",
+        format!(
+            "--- build targets ({} recipes, {} failed) ---",
+            build.recipes, build.failed
+        )
+        .cyan()
+        .bold()
+    );
+    for t in &build.targets {
+        let name = t.target.as_deref().unwrap_or("?");
+        let status = match (t.exit_code, t.signal) {
+            (_, Some(sig)) => format!("signal {}", sig).red(),
+            (Some(code), _) if code != 0 => format!("exit {}", code).red(),
+            (Some(_), _) => "ok".green(),
+            (None, _) => "running".yellow(),
+        };
+        let location = match (&t.makefile, t.line) {
+            (Some(file), Some(line)) => format!(" ({}:{})", file, line),
+            _ => String::new(),
+        };
+        let command: String = t
+            .command
+            .lines()
+            .next()
+            .unwrap_or("")
+            .chars()
+            .take(80)
+            .collect();
+        println!(
+            "  {:<24} {:>10.1}ms  {}{}",
+            name,
+            t.duration_ms.unwrap_or(0.0),
+            status,
+            location.dimmed()
+        );
+        println!("      {}", command.dimmed());
+        if t.failed {
+            for op in &t.failed_ops {
+                println!(
+                    "      {} {} {}",
+                    op.op,
+                    op.path,
+                    analyzer::errno_name(op.errno).red()
+                );
+            }
+        }
+    }
+    println!();
+}
@@ -0,0 +1,93 @@
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::PathBuf;
+
+use anyhow::{bail, Context, Result};
+
+use crate::explain::analyzer;
+use crate::explain::patterns::PatternRegistry;
+use crate::explain::sarif;
+use crate::logging;
+use crate::pack::reader::PackReader;
+use crate::pack::remote;
+use crate::trace::tables::{self, Table};
+
+pub fn execute(
+    format: String,
+    pack_path: PathBuf,
+    output: Option<PathBuf>,
+    source_root: Option<PathBuf>,
+    pattern_files: Vec<PathBuf>,
+) -> Result<()> {
+    if format != "sarif" {
+        bail!("unknown export '{}' (expected sarif or table)", format);
+    }
+
+    let pack = PackReader::open(&remote::resolve(&pack_path)?)?;
+    let mut registry = PatternRegistry::load_default();
+    for path in &pattern_files {
+        registry.load_rules_file(path)?;
+    }
+    let explain = analyzer::analyze_with(&pack, &registry)?;
+    let summary = pack.summary();
+    let root = source_root.unwrap_or_else(|| PathBuf::from(&summary.working_dir));
+    let log = serde_json::to_string_pretty(&sarif::render(summary, &explain, &root))?;
+
+    match output {
+        Some(path) => {
+            std::fs::write(&path, log + "\n")
+                .with_context(|| format!("failed to write {}", path.display()))?;
+            if !logging::is_quiet() {
+                eprintln!("poe: sarif written to {}", path.display());
+            }
+        }
+        None => println!("{}", log),
+    }
+    Ok(())
+}
+
+/// `poe export table`: one trace table as CSV or Parquet.
+pub fn table(
+    pack_path: PathBuf,
+    table: &str,
+    format: Option<String>,
+    output: Option<PathBuf>,
+) -> Result<()> {
+    let table = Table::parse(table)?;
+    let parquet = match format.as_deref() {
+        Some("parquet") => true,
+        Some("csv") => false,
+        Some(other) => bail!("unknown table format '{}' (expected csv or parquet)", other),
+        None => output
+            .as_ref()
+            .and_then(|p| p.extension())
+            .map(|x| x == "parquet")
+            .unwrap_or(false),
+    };
+    let pack = PackReader::open(&remote::resolve(&pack_path)?)?;
+    let db = pack.db();
+
+    let Some(path) = output else {
+        if parquet {
+            bail!("parquet needs an output file (-o out.parquet)");
+        }
+        tables::write_csv(db, table, &mut BufWriter::new(std::io::stdout().lock()))?;
+        return Ok(());
+    };
+    let file =
+        File::create(&path).with_context(|| format!("failed to create {}", path.display()))?;
+    let rows = if parquet {
+        tables::write_parquet(db, table, file)?
+    } else {
+        tables::write_csv(db, table, &mut BufWriter::new(file))?
+    };
+    if !logging::is_quiet() {
+        eprintln!(
+            "poe: {} {} rows written to {}",
+            rows,
+            table.name(),
+            path.display()
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,91 @@
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+use anyhow::{bail, Context, Result};
+use colored::Colorize;
+
+use crate::cli::bench::take_pack;
+use crate::explain::profile::BaselineProfile;
+use crate::explain::realtime_diff::Observed;
+
+const DEFAULT_RUNS: usize = 3;
+const DEFAULT_OUTPUT: &str = "poe-profile.json";
+
+pub fn execute(
+    command: Vec<String>,
+    runs: Option<usize>,
+    output: Option<PathBuf>,
+    mode: Option<String>,
+    ignore_exit_code: bool,
+) -> Result<()> {
+    if command.is_empty() {
+        bail!("no command given (usage: poe learn [OPTIONS] -- <command>)");
+    }
+    let runs = runs.unwrap_or(DEFAULT_RUNS).max(1);
+    let output = output.unwrap_or_else(|| PathBuf::from(DEFAULT_OUTPUT));
+    let dir = std::env::temp_dir().join(format!("poe-learn-{}", std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+    let result = learn(&command, runs, &dir, mode.as_deref(), ignore_exit_code);
+    let _ = std::fs::remove_dir_all(&dir);
+    let profile = result?;
+
+    if profile.runs == 0 {
+        bail!(
+            "every run failed, so there is nothing to learn from \
+             (--ignore-exit-code learns from failing runs too)"
+        );
+    }
+    profile.save(&output)?;
+    println!(
+        "{} profile of {} run(s) written to {}: {} paths, {} connections, {} commands, {} stderr lines",
+        "poe:".cyan().bold(),
+        profile.runs,
+        output.display(),
+        profile.file_paths.len(),
+        profile.net_addrs.len(),
+        profile.processes.len(),
+        profile.stderr_lines.len(),
+    );
+    println!(
+        "  {} poe run --profile {} -- {}",
+        "use:".dimmed(),
+        output.display(),
+        command.join(" ")
+    );
+    Ok(())
+}
+
+fn learn(
+    command: &[String],
+    runs: usize,
+    dir: &std::path::Path,
+    mode: Option<&str>,
+    ignore_exit_code: bool,
+) -> Result<BaselineProfile> {
+    let mut profile = BaselineProfile::new(command.to_vec());
+    for run in 1..=runs {
+        let mut cmd = Command::new(std::env::current_exe()?);
+        cmd.args(["run", "--always", "--output"]).arg(dir);
+        if let Some(mode) = mode {
+            cmd.args(["--mode", mode]);
+        }
+        let status = cmd
+            .arg("--")
+            .args(command)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .context("failed to run poe run")?;
+        let pack = take_pack(dir)?.context("poe run wrote no pack")?;
+        let code = status.code().unwrap_or(-1);
+        if code == 0 || ignore_exit_code {
+            profile.add_run(Observed::from_pack(&pack)?);
+            log::info!("run {}/{}: exit {}", run, runs, code);
+        } else {
+            log::info!("run {}/{}: exit {}, left out", run, runs, code);
+        }
+        std::fs::remove_file(&pack)?;
+    }
+    Ok(profile)
+}
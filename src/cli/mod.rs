@@ -1,9 +1,22 @@
+pub mod baseline;
+pub mod bench;
 pub mod build;
+pub mod cluster;
 pub mod diff;
 pub mod doctor;
 pub mod explain;
+pub mod export;
+pub mod learn;
+pub mod pack;
 pub mod query;
+pub mod replay;
+pub mod report;
 pub mod run;
+pub mod schema;
+pub mod serve;
+pub mod stats;
+pub mod top;
 
 pub mod trace;
 pub mod update;
+pub mod view;
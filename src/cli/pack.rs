@@ -0,0 +1,50 @@
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+
+use crate::logging;
+use crate::pack::reader;
+use crate::pack::remote;
+
+/// `poe pack list`: the files inside a pack.
+pub fn list(pack_path: PathBuf, json: bool) -> Result<()> {
+    let members = reader::members(&remote::resolve(&pack_path)?)?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&members)?);
+        return Ok(());
+    }
+    for m in &members {
+        println!("{:>12}  {}", m.size, m.name);
+    }
+    Ok(())
+}
+
+/// `poe pack extract`: copies one member out of a pack, e.g. trace.sqlite
+/// to open in sqlite3 or duckdb. `-o -` writes it to stdout.
+pub fn extract(pack_path: PathBuf, member: &str, output: Option<PathBuf>) -> Result<()> {
+    let pack = remote::resolve(&pack_path)?;
+    let path = output
+        .unwrap_or_else(|| PathBuf::from(Path::new(member).file_name().unwrap_or(member.as_ref())));
+    if path == Path::new("-") {
+        reader::extract_member(&pack, member, &mut std::io::stdout().lock())?;
+        return Ok(());
+    }
+
+    let file =
+        File::create(&path).with_context(|| format!("failed to create {}", path.display()))?;
+    let size =
+        reader::extract_member(&pack, member, &mut BufWriter::new(file)).inspect_err(|_| {
+            let _ = std::fs::remove_file(&path);
+        })?;
+    if !logging::is_quiet() {
+        eprintln!(
+            "poe: {} ({} bytes) written to {}",
+            member,
+            size,
+            path.display()
+        );
+    }
+    Ok(())
+}
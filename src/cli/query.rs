@@ -1,22 +1,175 @@
+use std::io::{BufWriter, Write};
 use std::path::PathBuf;
 
 use anyhow::Result;
 
+use crate::explain::analyzer;
 use crate::pack::reader::PackReader;
+use crate::pack::remote;
+use crate::trace::db::{Page, TraceDb};
+use crate::trace::{
+    buildsteps, cacheops, calltree, dbqueries, filehistory, filetree, h2streams, iostats, kernelio,
+    proctree, pycalls, sched,
+};
+use crate::util;
 
-pub fn execute(pack_path: PathBuf, query: String) -> Result<()> {
-    let pack = PackReader::open(&pack_path)?;
+const DEFAULT_EVENT_LIMIT: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Json,
+    Ndjson,
+    Csv,
+}
+
+impl OutputFormat {
+    pub fn parse(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "json" => Ok(Self::Json),
+            "ndjson" | "jsonl" => Ok(Self::Ndjson),
+            "csv" => Ok(Self::Csv),
+            other => anyhow::bail!("unknown format: {} (expected json, ndjson or csv)", other),
+        }
+    }
+}
+
+/// Writes rows one at a time as they come off SQLite so large tables are
+/// never held in memory. `Json` output matches `serde_json::to_string_pretty`
+/// of the whole array; CSV takes its columns from the first row.
+struct RowWriter<W: Write> {
+    out: W,
+    format: OutputFormat,
+    count: usize,
+    columns: Vec<String>,
+}
+
+impl<W: Write> RowWriter<W> {
+    fn new(out: W, format: OutputFormat) -> Self {
+        Self {
+            out,
+            format,
+            count: 0,
+            columns: Vec::new(),
+        }
+    }
+
+    fn push(&mut self, value: &serde_json::Value) -> Result<()> {
+        match self.format {
+            OutputFormat::Json => {
+                self.out
+                    .write_all(if self.count == 0 { b"[\n" } else { b",\n" })?;
+                let pretty = serde_json::to_string_pretty(value)?;
+                for (i, line) in pretty.lines().enumerate() {
+                    if i > 0 {
+                        self.out.write_all(b"\n")?;
+                    }
+                    write!(self.out, "  {}", line)?;
+                }
+            }
+            OutputFormat::Ndjson => {
+                serde_json::to_writer(&mut self.out, value)?;
+                self.out.write_all(b"\n")?;
+            }
+            OutputFormat::Csv => {
+                if self.count == 0 {
+                    self.columns = match value.as_object() {
+                        Some(map) => map.keys().cloned().collect(),
+                        None => vec!["value".into()],
+                    };
+                    let header: Vec<String> =
+                        self.columns.iter().map(|c| util::csv_field(c)).collect();
+                    writeln!(self.out, "{}", header.join(","))?;
+                }
+                let fields: Vec<String> = match value.as_object() {
+                    Some(map) => self
+                        .columns
+                        .iter()
+                        .map(|c| csv_value(map.get(c).unwrap_or(&serde_json::Value::Null)))
+                        .collect(),
+                    None => vec![csv_value(value)],
+                };
+                writeln!(self.out, "{}", fields.join(","))?;
+            }
+        }
+        self.count += 1;
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<()> {
+        if self.format == OutputFormat::Json {
+            if self.count == 0 {
+                self.out.write_all(b"[]\n")?;
+            } else {
+                self.out.write_all(b"\n]\n")?;
+            }
+        }
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+fn csv_value(value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::Null => String::new(),
+        serde_json::Value::String(s) => util::csv_field(s),
+        other => util::csv_field(&other.to_string()),
+    }
+}
+
+fn stdout_writer(format: OutputFormat) -> RowWriter<BufWriter<std::io::StdoutLock<'static>>> {
+    RowWriter::new(BufWriter::new(std::io::stdout().lock()), format)
+}
+
+fn write_rows(format: OutputFormat, rows: &[serde_json::Value]) -> Result<()> {
+    let mut out = stdout_writer(format);
+    for row in rows {
+        out.push(row)?;
+    }
+    out.finish()
+}
+
+/// Single-object results stay a pretty object in JSON and become one row
+/// otherwise.
+fn write_object(format: OutputFormat, value: &serde_json::Value) -> Result<()> {
+    if format == OutputFormat::Json {
+        println!("{}", serde_json::to_string_pretty(value)?);
+        return Ok(());
+    }
+    write_rows(format, std::slice::from_ref(value))
+}
+
+pub fn execute(
+    pack_path: PathBuf,
+    query: String,
+    limit: Option<usize>,
+    offset: usize,
+    format: Option<String>,
+) -> Result<()> {
+    // Graphviz output is a whole graph, not rows, so only the process tree
+    // has it.
+    let dot = format
+        .as_deref()
+        .is_some_and(|f| f.eq_ignore_ascii_case("dot"));
+    if dot && !query.eq_ignore_ascii_case("proctree") {
+        anyhow::bail!("--format dot is only available for the proctree query");
+    }
+    let format = match format {
+        Some(f) if !dot => OutputFormat::parse(&f)?,
+        _ => OutputFormat::Json,
+    };
+    let pack = PackReader::open(&remote::resolve(&pack_path)?)?;
     let db = pack.db();
+    let page = Page::new(limit, offset);
 
     let query_lower = query.to_lowercase();
 
     match query_lower.as_str() {
         "summary" => {
-            println!("{}", serde_json::to_string_pretty(pack.summary())?);
+            write_object(format, &serde_json::to_value(pack.summary())?)?;
         }
 
         "processes" | "procs" => {
-            let procs = db.query_processes()?;
+            let procs = db.query_processes_page(page)?;
             let results: Vec<serde_json::Value> = procs
                 .iter()
                 .map(|p| {
@@ -31,11 +184,40 @@ pub fn execute(pack_path: PathBuf, query: String) -> Result<()> {
                     })
                 })
                 .collect();
-            println!("{}", serde_json::to_string_pretty(&results)?);
+            write_rows(format, &results)?;
+        }
+
+        "proctree" => {
+            let procs = db.query_processes()?;
+            if dot {
+                print!("{}", proctree::to_dot(&procs));
+                return Ok(());
+            }
+            let results: Vec<serde_json::Value> = proctree::walk(&procs)
+                .iter()
+                .skip(offset)
+                .take(limit.unwrap_or(usize::MAX))
+                .map(|t| {
+                    let p = t.process;
+                    serde_json::json!({
+                        "pid": p.proc_id,
+                        "parent_pid": p.parent_proc_id,
+                        "depth": t.depth,
+                        "command": proctree::command(p),
+                        "duration_ms": proctree::duration_ms(p),
+                        "exit_code": p.exit_code,
+                        "signal": p.signal.map(util::signal_name),
+                    })
+                })
+                .collect();
+            write_rows(format, &results)?;
         }
 
         "events" => {
-            let events = db.query_last_events(100)?;
+            let events = db.query_last_events_page(Page::new(
+                Some(limit.unwrap_or(DEFAULT_EVENT_LIMIT)),
+                offset,
+            ))?;
             let results: Vec<serde_json::Value> = events
                 .iter()
                 .rev()
@@ -48,63 +230,141 @@ pub fn execute(pack_path: PathBuf, query: String) -> Result<()> {
                     })
                 })
                 .collect();
-            println!("{}", serde_json::to_string_pretty(&results)?);
+            write_rows(format, &results)?;
         }
 
         "files" => {
-            let files = db.query_file_events()?;
-            let results: Vec<serde_json::Value> = files
-                .iter()
-                .map(|f| {
-                    serde_json::json!({
-                        "ts_ms": f.ts as f64 / 1_000_000.0,
-                        "pid": f.proc_id,
-                        "op": f.op,
-                        "path": f.path,
-                        "fd": f.fd,
-                        "bytes": f.bytes,
-                        "result": f.result,
-                    })
-                })
-                .collect();
-            println!("{}", serde_json::to_string_pretty(&results)?);
+            let mut out = stdout_writer(format);
+            db.each_file_event(page, |f| {
+                out.push(&serde_json::json!({
+                    "ts_ms": f.ts as f64 / 1_000_000.0,
+                    "pid": f.proc_id,
+                    "op": f.op,
+                    "path": f.path,
+                    "fd": f.fd,
+                    "bytes": f.bytes,
+                    "flags": f.flags.filter(|_| f.op == "open").map(util::open_flags),
+                    "result": f.result,
+                    "duration_ms": f.duration_ns.map(|d| d as f64 / 1_000_000.0),
+                }))
+            })?;
+            out.finish()?;
         }
 
         "net" | "network" => {
-            let net = db.query_net_events()?;
-            let results: Vec<serde_json::Value> = net
-                .iter()
-                .map(|n| {
-                    serde_json::json!({
-                        "ts_ms": n.ts as f64 / 1_000_000.0,
-                        "pid": n.proc_id,
-                        "op": n.op,
-                        "src": n.src,
-                        "dst": n.dst,
-                        "bytes": n.bytes,
-                        "fd": n.fd,
-                        "result": n.result,
-                    })
-                })
-                .collect();
-            println!("{}", serde_json::to_string_pretty(&results)?);
+            let mut out = stdout_writer(format);
+            db.each_net_event(page, |n| {
+                out.push(&serde_json::json!({
+                    "ts_ms": n.ts as f64 / 1_000_000.0,
+                    "pid": n.proc_id,
+                    "op": n.op,
+                    "src": n.src,
+                    "dst": n.dst,
+                    "bytes": n.bytes,
+                    "fd": n.fd,
+                    "result": n.result,
+                    "duration_ms": n.duration_ns.map(|d| d as f64 / 1_000_000.0),
+                }))
+            })?;
+            out.finish()?;
         }
 
         "stacks" => {
-            let stacks = db.query_stacks()?;
-            let results: Vec<serde_json::Value> = stacks
-                .iter()
-                .map(|s| {
-                    let frames: Vec<u64> = serde_json::from_str(&s.frames).unwrap_or_default();
-                    serde_json::json!({
-                        "ts_ms": s.ts as f64 / 1_000_000.0,
-                        "pid": s.proc_id,
-                        "frames": frames.iter().map(|f| format!("{:#x}", f)).collect::<Vec<_>>(),
-                        "weight": s.weight,
-                    })
-                })
-                .collect();
-            println!("{}", serde_json::to_string_pretty(&results)?);
+            let mut out = stdout_writer(format);
+            db.each_stack(page, |s| {
+                let frames: Vec<u64> = serde_json::from_str(&s.frames).unwrap_or_default();
+                out.push(&serde_json::json!({
+                    "ts_ms": s.ts as f64 / 1_000_000.0,
+                    "pid": s.proc_id,
+                    "frames": frames.iter().map(|f| format!("{:#x}", f)).collect::<Vec<_>>(),
+                    "weight": s.weight,
+                }))
+            })?;
+            out.finish()?;
+        }
+
+        "io-stats" | "iostats" => {
+            show_io_stats(db, page, format)?;
+        }
+
+        "files-tree" => {
+            show_files_tree(db, None, page, format)?;
+        }
+
+        "payloads" => {
+            show_payloads(db, None, page, format)?;
+        }
+
+        "pycalls" => {
+            show_py_calls(db, false, page, format)?;
+        }
+
+        "pycalls:all" => {
+            show_py_calls(db, true, page, format)?;
+        }
+
+        "calltree" | "call-tree" => {
+            show_call_tree(db, page, format)?;
+        }
+
+        "build" | "targets" => {
+            show_build_targets(&pack, page, format)?;
+        }
+
+        "cache" => {
+            let rows = cacheops::compute(db)?
+                .into_iter()
+                .enumerate()
+                .filter(|(i, _)| in_page(*i, page))
+                .map(|(_, c)| serde_json::to_value(c))
+                .collect::<serde_json::Result<Vec<_>>>()?;
+            write_rows(format, &rows)?;
+        }
+
+        "io-counters" => {
+            let report = kernelio::compute(db)?;
+            if format == OutputFormat::Json {
+                write_object(format, &serde_json::to_value(&report)?)?;
+            } else {
+                let rows = report
+                    .processes
+                    .iter()
+                    .enumerate()
+                    .filter(|(i, _)| in_page(*i, page))
+                    .map(|(_, p)| serde_json::to_value(p))
+                    .collect::<serde_json::Result<Vec<_>>>()?;
+                write_rows(format, &rows)?;
+            }
+        }
+
+        "sched" => {
+            let rows = sched::compute(db)?
+                .into_iter()
+                .enumerate()
+                .filter(|(i, _)| in_page(*i, page))
+                .map(|(_, s)| serde_json::to_value(s))
+                .collect::<serde_json::Result<Vec<_>>>()?;
+            write_rows(format, &rows)?;
+        }
+
+        "streams" | "rpcs" => {
+            let rows = h2streams::compute(db)?
+                .into_iter()
+                .enumerate()
+                .filter(|(i, _)| in_page(*i, page))
+                .map(|(_, s)| serde_json::to_value(s))
+                .collect::<serde_json::Result<Vec<_>>>()?;
+            write_rows(format, &rows)?;
+        }
+
+        "statements" | "db" => {
+            let rows = dbqueries::compute(db)?
+                .into_iter()
+                .enumerate()
+                .filter(|(i, _)| in_page(*i, page))
+                .map(|(_, s)| serde_json::to_value(s))
+                .collect::<serde_json::Result<Vec<_>>>()?;
+            write_rows(format, &rows)?;
         }
 
         "stdout" => match pack.stdout() {
@@ -125,37 +385,74 @@ pub fn execute(pack_path: PathBuf, query: String) -> Result<()> {
             }
         },
 
+        "stdin" => match pack.stdin() {
+            Some(data) => {
+                std::io::Write::write_all(&mut std::io::stdout(), &data)?;
+            }
+            None => {
+                eprintln!("no stdin captured (run with --capture-stdin)");
+            }
+        },
+
         "stats" => {
             let summary = pack.summary();
-            println!("{}", serde_json::to_string_pretty(&summary.stats)?);
+            write_object(format, &serde_json::to_value(&summary.stats)?)?;
         }
 
         _ => {
             if query_lower.starts_with("sql:") {
                 let sql = &query[4..].trim();
-                execute_raw_sql(db, sql)?;
+                execute_raw_sql(db, sql, format)?;
             } else if query_lower.starts_with("files:") {
                 let pattern = &query[6..].trim();
-                search_files(db, pattern)?;
+                search_files(db, pattern, page, format)?;
+            } else if query_lower.starts_with("payloads:") {
+                let pattern = &query[9..].trim();
+                show_payloads(db, Some(pattern), page, format)?;
+            } else if let Some(depth) = query_lower.strip_prefix("files-tree:") {
+                let depth = depth
+                    .trim()
+                    .parse()
+                    .map_err(|_| anyhow::anyhow!("invalid files-tree depth: {}", depth))?;
+                show_files_tree(db, Some(depth), page, format)?;
+            } else if query_lower.starts_with("file-history:") {
+                let path = &query["file-history:".len()..].trim();
+                show_file_history(db, path, page, format)?;
             } else if query_lower.starts_with("net:") {
                 let pattern = &query[4..].trim();
-                search_net(db, pattern)?;
+                search_net(db, pattern, page, format)?;
             } else {
                 eprintln!("Unknown query: {}", query);
                 eprintln!();
                 eprintln!("Available queries:");
                 eprintln!("  summary        - Full summary JSON");
                 eprintln!("  processes      - Process tree");
-                eprintln!("  events         - Last 100 events");
+                eprintln!("  proctree       - Processes in tree order with depth; --format dot for Graphviz");
+                eprintln!("  events         - Most recent events (default 100)");
                 eprintln!("  files          - All file operations");
                 eprintln!("  net            - All network operations");
                 eprintln!("  stacks         - Stack samples");
                 eprintln!("  stdout         - Captured stdout");
                 eprintln!("  stderr         - Captured stderr");
+                eprintln!("  stdin          - Recorded stdin (--capture-stdin)");
                 eprintln!("  stats          - Statistics");
+                eprintln!("  sched          - CPU time and run-queue wait per task (full mode)");
+                eprintln!("  io-stats       - File I/O per process and per path");
+                eprintln!("  io-counters    - Kernel I/O counters vs traced bytes per process");
+                eprintln!("  files-tree[:<depth>] - File I/O rolled up per directory");
+                eprintln!("  payloads       - Read/write buffers of --watch-file paths");
+                eprintln!("  calltree       - Native call trees and slowest functions (poe build)");
+                eprintln!("  pycalls        - Python calls with durations, slowest functions, raise stacks");
+                eprintln!("  pycalls:all    - Same, including stdlib and site-packages frames");
                 eprintln!("  files:<path>   - Search file ops by path pattern");
+                eprintln!(
+                    "  file-history:<path> - What created, wrote, renamed and deleted a file"
+                );
                 eprintln!("  net:<addr>     - Search net ops by address pattern");
+                eprintln!("  payloads:<path> - Watched-file buffers by path pattern");
                 eprintln!("  sql:<query>    - Raw SQL against trace.sqlite");
+                eprintln!();
+                eprintln!("Row queries accept --limit and --offset; --format json|ndjson|csv");
             }
         }
     }
@@ -163,56 +460,348 @@ pub fn execute(pack_path: PathBuf, query: String) -> Result<()> {
     Ok(())
 }
 
-fn execute_raw_sql(db: &crate::trace::db::TraceDb, sql: &str) -> Result<()> {
-    let results = db.raw_query(sql)?;
-    println!("{}", serde_json::to_string_pretty(&results)?);
-    Ok(())
+fn execute_raw_sql(db: &TraceDb, sql: &str, format: OutputFormat) -> Result<()> {
+    let mut out = stdout_writer(format);
+    db.each_raw_row(sql, |row| out.push(&row))?;
+    out.finish()
 }
 
-fn search_files(db: &crate::trace::db::TraceDb, pattern: &str) -> Result<()> {
-    let files = db.query_file_events()?;
-    let results: Vec<serde_json::Value> = files
-        .iter()
-        .filter(|f| {
-            f.path
-                .as_ref()
-                .map(|p| p.contains(pattern))
-                .unwrap_or(false)
-        })
-        .map(|f| {
-            serde_json::json!({
-                "ts_ms": f.ts as f64 / 1_000_000.0,
-                "pid": f.proc_id,
-                "op": f.op,
-                "path": f.path,
-                "bytes": f.bytes,
-                "result": f.result,
-            })
-        })
+fn search_files(db: &TraceDb, pattern: &str, page: Page, format: OutputFormat) -> Result<()> {
+    let mut out = stdout_writer(format);
+    let mut matched = 0usize;
+    db.each_file_event(Page::default(), |f| {
+        if !f.path.as_ref().is_some_and(|p| p.contains(pattern)) {
+            return Ok(());
+        }
+        matched += 1;
+        if !in_page(matched - 1, page) {
+            return Ok(());
+        }
+        out.push(&serde_json::json!({
+            "ts_ms": f.ts as f64 / 1_000_000.0,
+            "pid": f.proc_id,
+            "op": f.op,
+            "path": f.path,
+            "bytes": f.bytes,
+            "result": f.result,
+        }))
+    })?;
+    out.finish()
+}
+
+/// Directory tree of file activity, 4 levels deep unless given. JSON
+/// output is the nested tree; the row formats list directories
+/// depth-first with their `depth`. The page applies to rows.
+fn show_files_tree(
+    db: &TraceDb,
+    depth: Option<usize>,
+    page: Page,
+    format: OutputFormat,
+) -> Result<()> {
+    let report = iostats::compute(db)?;
+    let root = filetree::tree(&report.paths, depth.unwrap_or(4));
+    if format == OutputFormat::Json {
+        return write_object(format, &serde_json::to_value(&root)?);
+    }
+    fn walk(node: &filetree::DirNode, depth: usize, rows: &mut Vec<serde_json::Value>) {
+        rows.push(serde_json::json!({
+            "depth": depth,
+            "path": node.stats.path,
+            "files": node.stats.files,
+            "ops": node.stats.ops,
+            "bytes_read": node.stats.bytes_read,
+            "bytes_written": node.stats.bytes_written,
+            "errors": node.stats.errors,
+        }));
+        for child in &node.children {
+            walk(child, depth + 1, rows);
+        }
+    }
+    let mut rows = Vec::new();
+    walk(&root, 0, &mut rows);
+    let rows: Vec<_> = rows
+        .into_iter()
+        .enumerate()
+        .filter(|(i, _)| in_page(*i, page))
+        .map(|(_, r)| r)
         .collect();
-    println!("{}", serde_json::to_string_pretty(&results)?);
-    Ok(())
+    write_rows(format, &rows)
 }
 
-fn search_net(db: &crate::trace::db::TraceDb, pattern: &str) -> Result<()> {
-    let net = db.query_net_events()?;
-    let results: Vec<serde_json::Value> = net
+/// JSON output is the whole history; the row formats list its steps. The
+/// page applies to steps.
+fn show_file_history(db: &TraceDb, path: &str, page: Page, format: OutputFormat) -> Result<()> {
+    let mut history = filehistory::compute(db, path)?;
+    history.steps = history
+        .steps
+        .into_iter()
+        .enumerate()
+        .filter(|(i, _)| in_page(*i, page))
+        .map(|(_, s)| s)
+        .collect();
+    if format == OutputFormat::Json {
+        return write_object(format, &serde_json::to_value(&history)?);
+    }
+    let rows = history
+        .steps
         .iter()
-        .filter(|n| {
-            n.dst.as_ref().map(|d| d.contains(pattern)).unwrap_or(false)
-                || n.src.as_ref().map(|s| s.contains(pattern)).unwrap_or(false)
-        })
-        .map(|n| {
-            serde_json::json!({
-                "ts_ms": n.ts as f64 / 1_000_000.0,
-                "pid": n.proc_id,
-                "op": n.op,
-                "dst": n.dst,
-                "bytes": n.bytes,
-                "result": n.result,
-            })
-        })
+        .map(serde_json::to_value)
+        .collect::<serde_json::Result<Vec<_>>>()?;
+    write_rows(format, &rows)
+}
+
+/// JSON output is one object with `processes` and `paths`; the row formats
+/// flatten both into rows tagged with `scope`. The page applies to paths.
+fn show_io_stats(db: &TraceDb, page: Page, format: OutputFormat) -> Result<()> {
+    let mut report = iostats::compute(db)?;
+    report.paths = report
+        .paths
+        .into_iter()
+        .enumerate()
+        .filter(|(i, _)| in_page(*i, page))
+        .map(|(_, p)| p)
+        .collect();
+
+    if format == OutputFormat::Json {
+        return write_object(format, &serde_json::to_value(&report)?);
+    }
+
+    let mut rows = Vec::new();
+    for p in &report.processes {
+        let mut row = serde_json::json!({
+            "scope": "process",
+            "pid": p.pid,
+            "command": p.command,
+            "path": null,
+        });
+        merge_stats(&mut row, &p.stats)?;
+        rows.push(row);
+    }
+    for p in &report.paths {
+        let mut row = serde_json::json!({
+            "scope": "path",
+            "pid": null,
+            "command": null,
+            "path": p.path,
+        });
+        merge_stats(&mut row, &p.stats)?;
+        rows.push(row);
+    }
+    write_rows(format, &rows)
+}
+
+/// JSON output is the full report with the page applied to `functions`.
+/// The row formats list tree nodes depth-first; `stack` is the
+/// `;`-joined path from the root, as flame graph tools expect.
+fn show_call_tree(db: &TraceDb, page: Page, format: OutputFormat) -> Result<()> {
+    let mut report = calltree::compute(db)?;
+
+    if format == OutputFormat::Json {
+        report.functions = report
+            .functions
+            .into_iter()
+            .enumerate()
+            .filter(|(i, _)| in_page(*i, page))
+            .map(|(_, f)| f)
+            .collect();
+        return write_object(format, &serde_json::to_value(&report)?);
+    }
+
+    fn walk(
+        tid: i64,
+        node: &calltree::CallNode,
+        path: &mut Vec<String>,
+        rows: &mut Vec<serde_json::Value>,
+    ) {
+        path.push(node.func.clone());
+        rows.push(serde_json::json!({
+            "tid": tid,
+            "depth": path.len() - 1,
+            "stack": path.join(";"),
+            "func": node.func,
+            "calls": node.calls,
+            "inclusive_ms": node.inclusive_ms,
+            "exclusive_ms": node.exclusive_ms,
+        }));
+        for child in &node.children {
+            walk(tid, child, path, rows);
+        }
+        path.pop();
+    }
+
+    let mut rows = Vec::new();
+    for thread in &report.threads {
+        for root in &thread.roots {
+            walk(thread.tid, root, &mut Vec::new(), &mut rows);
+        }
+    }
+    let rows: Vec<_> = rows
+        .into_iter()
+        .enumerate()
+        .filter(|(i, _)| in_page(*i, page))
+        .map(|(_, r)| r)
+        .collect();
+    write_rows(format, &rows)
+}
+
+/// One row per make recipe; `failed_ops` is flattened to "op path ERRNO"
+/// entries for the row formats.
+fn show_build_targets(pack: &PackReader, page: Page, format: OutputFormat) -> Result<()> {
+    let stderr = pack.stdio_text("stderr");
+    let mut report = buildsteps::compute(pack.db(), stderr.as_deref())?;
+    report.targets = report
+        .targets
+        .into_iter()
+        .enumerate()
+        .filter(|(i, _)| in_page(*i, page))
+        .map(|(_, t)| t)
+        .collect();
+
+    if format == OutputFormat::Json {
+        return write_object(format, &serde_json::to_value(&report)?);
+    }
+    let mut rows = Vec::new();
+    for target in &report.targets {
+        let mut row = serde_json::to_value(target)?;
+        row["failed_ops"] = target
+            .failed_ops
+            .iter()
+            .map(|f| format!("{} {} {}", f.op, f.path, analyzer::errno_name(f.errno)))
+            .collect::<Vec<_>>()
+            .join("; ")
+            .into();
+        rows.push(row);
+    }
+    write_rows(format, &rows)
+}
+
+/// JSON output is one object with `functions`, `exception_stacks` and the
+/// paged `calls`; the row formats list the calls only.
+fn show_py_calls(
+    db: &TraceDb,
+    include_library: bool,
+    page: Page,
+    format: OutputFormat,
+) -> Result<()> {
+    let mut report = pycalls::compute(db, include_library)?;
+    report.calls = report
+        .calls
+        .into_iter()
+        .enumerate()
+        .filter(|(i, _)| in_page(*i, page))
+        .map(|(_, c)| c)
         .collect();
-    println!("{}", serde_json::to_string_pretty(&results)?);
+
+    if format == OutputFormat::Json {
+        return write_object(format, &serde_json::to_value(&report)?);
+    }
+    let rows = report
+        .calls
+        .iter()
+        .map(serde_json::to_value)
+        .collect::<serde_json::Result<Vec<_>>>()?;
+    write_rows(format, &rows)
+}
+
+fn merge_stats(row: &mut serde_json::Value, stats: &iostats::IoStats) -> Result<()> {
+    if let (Some(row), serde_json::Value::Object(fields)) =
+        (row.as_object_mut(), serde_json::to_value(stats)?)
+    {
+        row.extend(fields);
+    }
     Ok(())
 }
+
+/// Payloads are shown as text when they are valid UTF-8 and as hex
+/// otherwise; `captured` is less than `bytes` when the buffer was capped.
+fn show_payloads(
+    db: &TraceDb,
+    pattern: Option<&str>,
+    page: Page,
+    format: OutputFormat,
+) -> Result<()> {
+    let mut out = stdout_writer(format);
+    db.each_file_payload(pattern, page, |p| {
+        let (encoding, data) = match std::str::from_utf8(&p.data) {
+            Ok(text) => ("utf8", text.to_string()),
+            Err(_) => ("hex", p.data.iter().map(|b| format!("{:02x}", b)).collect()),
+        };
+        out.push(&serde_json::json!({
+            "ts_ms": p.ts as f64 / 1_000_000.0,
+            "pid": p.proc_id,
+            "op": p.op,
+            "path": p.path,
+            "fd": p.fd,
+            "bytes": p.bytes,
+            "captured": p.data.len(),
+            "encoding": encoding,
+            "data": data,
+        }))
+    })?;
+    out.finish()
+}
+
+fn search_net(db: &TraceDb, pattern: &str, page: Page, format: OutputFormat) -> Result<()> {
+    let mut out = stdout_writer(format);
+    let mut matched = 0usize;
+    db.each_net_event(Page::default(), |n| {
+        let hit = n.dst.as_ref().is_some_and(|d| d.contains(pattern))
+            || n.src.as_ref().is_some_and(|s| s.contains(pattern));
+        if !hit {
+            return Ok(());
+        }
+        matched += 1;
+        if !in_page(matched - 1, page) {
+            return Ok(());
+        }
+        out.push(&serde_json::json!({
+            "ts_ms": n.ts as f64 / 1_000_000.0,
+            "pid": n.proc_id,
+            "op": n.op,
+            "dst": n.dst,
+            "bytes": n.bytes,
+            "result": n.result,
+        }))
+    })?;
+    out.finish()
+}
+
+fn in_page(index: usize, page: Page) -> bool {
+    index >= page.offset && page.limit.is_none_or(|l| index < page.offset + l)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(format: OutputFormat, rows: &[serde_json::Value]) -> String {
+        let mut buf = Vec::new();
+        let mut out = RowWriter::new(&mut buf, format);
+        for row in rows {
+            out.push(row).unwrap();
+        }
+        out.finish().unwrap();
+        String::from_utf8(buf).unwrap()
+    }
+
+    #[test]
+    fn row_writer_formats() {
+        let rows = vec![
+            serde_json::json!({"op": "open", "path": "a,b", "fd": 3}),
+            serde_json::json!({"op": "read", "path": null, "fd": 3}),
+        ];
+        assert_eq!(
+            render(OutputFormat::Json, &rows),
+            serde_json::to_string_pretty(&rows).unwrap() + "\n"
+        );
+        assert_eq!(
+            render(OutputFormat::Ndjson, &rows),
+            "{\"fd\":3,\"op\":\"open\",\"path\":\"a,b\"}\n{\"fd\":3,\"op\":\"read\",\"path\":null}\n"
+        );
+        assert_eq!(
+            render(OutputFormat::Csv, &rows),
+            "fd,op,path\n3,open,\"a,b\"\n3,read,\n"
+        );
+        assert_eq!(render(OutputFormat::Json, &[]), "[]\n");
+    }
+}
@@ -0,0 +1,61 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+
+use crate::capture::runner::{self, RunConfig};
+use crate::capture::stdio::StdinMode;
+use crate::cli::run;
+use crate::pack::reader::PackReader;
+use crate::pack::remote;
+
+pub fn execute(packet: PathBuf, output_dir: Option<PathBuf>, no_stdin: bool) -> Result<()> {
+    let (command, working_dir, stdin) = {
+        let reader = PackReader::open(&remote::resolve(&packet)?)?;
+        let summary = reader.summary();
+        (
+            summary.command.clone(),
+            summary.working_dir.clone(),
+            reader.stdin(),
+        )
+    };
+
+    if command.is_empty() {
+        anyhow::bail!("pack has no recorded command");
+    }
+
+    let stdin_mode = match stdin {
+        Some(data) if !no_stdin => {
+            log::info!("replaying {} bytes of recorded stdin", data.len());
+            StdinMode::Replay(data)
+        }
+        _ => StdinMode::Inherit,
+    };
+
+    let output_dir = match output_dir {
+        Some(dir) => dir,
+        None => std::env::current_dir()?,
+    };
+
+    if !working_dir.is_empty() && Path::new(&working_dir).is_dir() {
+        std::env::set_current_dir(&working_dir)?;
+    } else {
+        log::warn!(
+            "original working dir {} not found, replaying in current dir",
+            working_dir
+        );
+    }
+
+    log::info!("replaying {}", command.join(" "));
+
+    let config = RunConfig {
+        command,
+        always_emit: true,
+        output_dir,
+        stdin_mode,
+        ..Default::default()
+    };
+
+    let result = runner::execute_run(config)?;
+    run::report_result(&result, None)?;
+    run::exit_with(&result)
+}
@@ -0,0 +1,37 @@
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+
+use crate::explain::analyzer;
+use crate::explain::patterns::PatternRegistry;
+use crate::explain::report;
+use crate::logging;
+use crate::pack::reader::PackReader;
+use crate::pack::remote;
+
+pub fn execute(
+    pack_path: PathBuf,
+    output: Option<PathBuf>,
+    pattern_files: Vec<PathBuf>,
+) -> Result<()> {
+    let local = remote::resolve(&pack_path)?;
+    let pack = PackReader::open(&local)?;
+    let mut registry = PatternRegistry::load_default();
+    for path in &pattern_files {
+        registry.load_rules_file(path)?;
+    }
+    let explain = analyzer::analyze_with(&pack, &registry)?;
+    let html = report::render(&pack, &explain)?;
+
+    // A downloaded pack's report goes in the working directory, not the cache.
+    let output = output.unwrap_or_else(|| match local.file_name() {
+        Some(name) if local != pack_path => PathBuf::from(name).with_extension("html"),
+        _ => pack_path.with_extension("html"),
+    });
+    std::fs::write(&output, html)
+        .with_context(|| format!("failed to write report: {}", output.display()))?;
+    if !logging::is_quiet() {
+        eprintln!("poe: report written to {}", output.display());
+    }
+    Ok(())
+}
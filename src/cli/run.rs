@@ -1,25 +1,109 @@
-use std::path::PathBuf;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
 use std::process;
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use colored::Colorize;
 
-use crate::capture::runner::{self, RunConfig};
-use crate::events::types::CaptureMode;
+use crate::capture::backend::Backend;
+use crate::capture::runner::{self, FailureWatch, RunConfig, RunResult};
+use crate::capture::stdio::StdinMode;
+use crate::events::types::{CaptureMode, TriggerReason};
 use crate::explain;
+use crate::explain::profile::BaselineProfile;
+use crate::explain::realtime_diff::{self, DiffPolicy, Severity};
+use crate::hooks;
+use crate::logging;
+use crate::pack::baseline;
+use crate::redact::Redactor;
 use crate::util;
 
+#[allow(clippy::too_many_arguments)]
 pub fn execute(
     command: Vec<String>,
     always: bool,
     mode: Option<String>,
     output_dir: Option<PathBuf>,
-    diff_baseline: Option<PathBuf>,
+    diff_baselines: Vec<PathBuf>,
+    diff_quorum: Option<usize>,
+    profile: Option<PathBuf>,
+    max_pack_size: Option<String>,
+    capture_stdin: bool,
+    pty: bool,
+    hash_writes: Option<String>,
+    watch_files: Vec<PathBuf>,
+    backend: Option<String>,
+    trace_shell: bool,
+    capture_sql: bool,
+    capture_cache: bool,
+    capture_http2: bool,
+    tags: Vec<String>,
+    exit_status: Option<String>,
+    fail_on_divergence: bool,
+    diff_severity: Vec<String>,
+    diff_halt: Option<String>,
+    on_failure_of: Vec<String>,
+    exclude_children: Vec<String>,
+    no_follow_vfork: bool,
+    stream: Option<String>,
+    redact: bool,
+    redact_key: Option<PathBuf>,
 ) -> Result<()> {
     if command.is_empty() {
         anyhow::bail!("no command specified");
     }
 
+    let tags = parse_tags(&tags)?;
+    let exit_status = match exit_status {
+        Some(s) => ExitStatus::parse(&s)?,
+        None => ExitStatus::Propagate,
+    };
+    let diff_halt = diff_halt.map(|s| Severity::parse(&s)).transpose()?;
+    let mut diff_policy = DiffPolicy::with_overrides(&diff_severity, diff_halt)?;
+    if let Some(n) = diff_quorum {
+        if n == 0 || n > diff_baselines.len() {
+            anyhow::bail!(
+                "--diff-quorum {} needs between 1 and {} (the number of --diff baselines)",
+                n,
+                diff_baselines.len()
+            );
+        }
+        diff_policy.quorum = n;
+    }
+
+    let max_pack_size = match max_pack_size {
+        Some(s) => Some(
+            util::parse_size(&s)
+                .ok_or_else(|| anyhow::anyhow!("invalid --max-pack-size: {}", s))?,
+        ),
+        None => None,
+    };
+
+    let hash_writes = match hash_writes {
+        Some(s) => Some(
+            util::parse_size(&s)
+                .ok_or_else(|| anyhow::anyhow!("invalid --hash-writes size: {}", s))?,
+        ),
+        None => None,
+    };
+
+    let watch_files = watch_files
+        .iter()
+        .map(|p| resolve_watch_path(p))
+        .collect::<Result<Vec<_>>>()?;
+
+    let backend = match backend {
+        Some(s) => Backend::parse(&s)?,
+        None => Backend::Ptrace,
+    };
+
+    if trace_shell && !hooks::shell::is_bash_command(&command) {
+        log::warn!(
+            "--trace-shell: {} is not a bash script; running without step tracking",
+            command[0]
+        );
+    }
+
     let capture_mode = match mode.as_deref() {
         Some("full") => CaptureMode::Full,
         _ => CaptureMode::Lite,
@@ -27,23 +111,182 @@ pub fn execute(
 
     let output_dir = output_dir.unwrap_or_else(|| PathBuf::from("."));
 
-    let force_always = always || diff_baseline.is_some();
+    let diff_baselines = diff_baselines
+        .iter()
+        .map(|b| baseline::resolve(b))
+        .collect::<Result<Vec<_>>>()?;
+    let redact = match redact_key {
+        Some(path) => Some(Redactor::with_key(&load_redact_key(&path)?)),
+        None => redact.then(Redactor::new),
+    };
+    let diff_profile = profile.map(|p| BaselineProfile::load(&p)).transpose()?;
+    let force_always = always || !diff_baselines.is_empty() || diff_profile.is_some();
 
     let config = RunConfig {
         command: command.clone(),
         capture_mode,
         always_emit: force_always,
         output_dir,
-        diff_baseline: diff_baseline.clone(),
+        diff_baselines: diff_baselines.clone(),
+        diff_profile,
+        diff_policy,
+        max_pack_size,
+        stdin_mode: if capture_stdin {
+            StdinMode::Capture
+        } else {
+            StdinMode::Inherit
+        },
+        pty,
+        hash_writes,
+        watch_files,
+        backend,
+        trace_shell,
+        capture_sql,
+        capture_cache,
+        capture_http2,
+        tags,
+        on_failure_of: on_failure_of
+            .iter()
+            .map(|s| FailureWatch::parse(s))
+            .collect(),
+        exclude_children: exclude_children
+            .iter()
+            .flat_map(|s| s.split(','))
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(String::from)
+            .collect(),
+        follow_vfork: !no_follow_vfork,
+        stream,
+        redact,
         ..Default::default()
     };
 
     let result = runner::execute_run(config)?;
+    // The post-run diff is against the first baseline; the others only
+    // widen what realtime diff accepts.
+    report_result(&result, diff_baselines.first())?;
+    process::exit(exit_status.code(&result, fail_on_divergence))
+}
+
+/// `poe` exit code for a run that failed, under `--exit-status poe`.
+const POE_EXIT_FAILED: i32 = 1;
+/// Exit code when `--fail-on-divergence` trips or `--diff-halt` stopped the
+/// command. 2 is left to usage errors.
+const POE_EXIT_DIVERGED: i32 = 3;
+
+/// What `poe run` exits with.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ExitStatus {
+    /// The command's own exit code, or 128+signal.
+    Propagate,
+    /// Always 0, so capturing never fails a pipeline.
+    Zero,
+    /// poe's verdict: 0 on success, 1 on failure, whatever the command's code.
+    Poe,
+}
+
+impl ExitStatus {
+    fn parse(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "propagate" => Ok(Self::Propagate),
+            "zero" => Ok(Self::Zero),
+            "poe" => Ok(Self::Poe),
+            _ => anyhow::bail!(
+                "unknown --exit-status '{}' (expected propagate, zero or poe)",
+                s
+            ),
+        }
+    }
+
+    fn code(self, result: &RunResult, fail_on_divergence: bool) -> i32 {
+        let code = match self {
+            Self::Zero => 0,
+            _ if result.diff_halt.is_some() => return POE_EXIT_DIVERGED,
+            Self::Propagate => command_exit_code(result),
+            Self::Poe if matches!(result.trigger, Some(TriggerReason::ChildFailure(_))) => {
+                POE_EXIT_FAILED
+            }
+            Self::Poe if command_exit_code(result) == 0 => 0,
+            Self::Poe => POE_EXIT_FAILED,
+        };
+        if code == 0 && fail_on_divergence && !result.realtime_divergences.is_empty() {
+            return POE_EXIT_DIVERGED;
+        }
+        code
+    }
+}
+
+/// Parses `--tag key=value` labels; the value may itself contain `=`.
+fn parse_tags(tags: &[String]) -> Result<BTreeMap<String, String>> {
+    let mut parsed = BTreeMap::new();
+    for tag in tags {
+        let (key, value) = tag
+            .split_once('=')
+            .ok_or_else(|| anyhow::anyhow!("invalid --tag {}: expected key=value", tag))?;
+        let key = key.trim();
+        if key.is_empty() {
+            anyhow::bail!("invalid --tag {}: empty key", tag);
+        }
+        parsed.insert(key.to_string(), value.to_string());
+    }
+    Ok(parsed)
+}
 
+/// Makes a watch path absolute and symlink-free so it compares equal to
+/// what /proc/<pid>/fd reports; the file itself need not exist yet.
+/// The pseudonymization key in `path`, without a trailing newline.
+fn load_redact_key(path: &Path) -> Result<Vec<u8>> {
+    let mut key = std::fs::read(path)
+        .with_context(|| format!("failed to read --redact-key {}", path.display()))?;
+    while key.last().is_some_and(|b| b.is_ascii_whitespace()) {
+        key.pop();
+    }
+    if key.is_empty() {
+        anyhow::bail!("--redact-key {} is empty", path.display());
+    }
+    Ok(key)
+}
+
+fn resolve_watch_path(path: &Path) -> Result<String> {
+    let absolute = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        std::env::current_dir()?.join(path)
+    };
+    let resolved = match std::fs::canonicalize(&absolute) {
+        Ok(p) => p,
+        Err(_) => match (absolute.parent(), absolute.file_name()) {
+            (Some(dir), Some(name)) => std::fs::canonicalize(dir)
+                .map(|d| d.join(name))
+                .unwrap_or(absolute.clone()),
+            _ => absolute.clone(),
+        },
+    };
+    Ok(resolved.to_string_lossy().into_owned())
+}
+
+pub fn report_result(result: &RunResult, diff_baseline: Option<&PathBuf>) -> Result<()> {
+    if logging::is_quiet() {
+        return Ok(());
+    }
     if let Some(ref pack_path) = result.pack_path {
         eprintln!();
         eprintln!("{}", "--- poe debug packet ---".yellow().bold());
 
+        if result.trigger == Some(TriggerReason::Explicit) {
+            eprintln!(
+                "  {} run interrupted; pack written from what was captured",
+                "INTERRUPTED".yellow().bold()
+            );
+        }
+        if let Some(TriggerReason::ChildFailure(pid)) = result.trigger {
+            eprintln!(
+                "  {} child process {} failed",
+                "FAIL".red().bold(),
+                pid.to_string().red()
+            );
+        }
         if let Some(sig) = result.signal {
             eprintln!(
                 "  {} process killed by {} ({})",
@@ -73,8 +316,23 @@ pub fn execute(
         if !result.realtime_divergences.is_empty() {
             eprintln!();
             eprintln!("{}", "--- realtime divergence detected ---".red().bold());
+            if let Some(ref halt) = result.diff_halt {
+                eprintln!(
+                    "  {} stopped at {:.2}ms on a {} divergence: {}",
+                    "HALTED".red().bold(),
+                    halt.ts_ms,
+                    halt.severity.as_str(),
+                    halt.description
+                );
+            }
             for (i, div) in result.realtime_divergences.iter().enumerate().take(10) {
-                eprintln!("  {:>8.2}ms {:?}: {}", div.ts_ms, div.kind, div.description,);
+                eprintln!(
+                    "  {:>8.2}ms [{}] {:?}: {}",
+                    div.ts_ms,
+                    severity_label(div.severity),
+                    div.kind,
+                    div.description,
+                );
                 if i == 0 {
                     eprintln!(
                         "  {} this is the first behavioral divergence from baseline",
@@ -88,10 +346,21 @@ pub fn execute(
                     result.realtime_divergences.len() - 10
                 );
             }
+            eprintln!("  {}", "by kind:".dimmed());
+            for group in realtime_diff::summarize(&result.realtime_divergences) {
+                eprintln!(
+                    "  {:>5}x [{}] {} (first at {:.2}ms: {})",
+                    group.count,
+                    severity_label(group.severity),
+                    group.kind.as_str(),
+                    group.first_ts_ms,
+                    group.example
+                );
+            }
             eprintln!("{}", "------------------------------------".red().bold());
         }
 
-        if let Some(ref baseline_path) = diff_baseline {
+        if let Some(baseline_path) = diff_baseline {
             if baseline_path.exists() {
                 eprintln!();
                 let diff_result = explain::diff::diff_packs(baseline_path, pack_path)?;
@@ -105,10 +374,25 @@ pub fn execute(
         }
     }
 
-    let exit_code = result.exit_code.unwrap_or(if result.signal.is_some() {
+    Ok(())
+}
+
+fn severity_label(severity: Severity) -> colored::ColoredString {
+    match severity {
+        Severity::Critical => severity.as_str().red().bold(),
+        Severity::Warning => severity.as_str().yellow(),
+        Severity::Info => severity.as_str().dimmed(),
+    }
+}
+
+pub fn exit_with(result: &RunResult) -> ! {
+    process::exit(command_exit_code(result));
+}
+
+fn command_exit_code(result: &RunResult) -> i32 {
+    result.exit_code.unwrap_or(if result.signal.is_some() {
         128 + result.signal.unwrap_or(0)
     } else {
         1
-    });
-    process::exit(exit_code);
+    })
 }
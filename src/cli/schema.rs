@@ -0,0 +1,24 @@
+use anyhow::{bail, Result};
+
+use crate::explain::analyzer::ExplainOutput;
+use crate::explain::diff::DiffOutput;
+use crate::pack::summary::PackSummary;
+
+pub const KINDS: [&str; 3] = ["explain", "diff", "pack-summary"];
+
+/// Prints the JSON Schema of `poe explain --json`, `poe diff --json` or a
+/// pack's `summary.json`, generated from the types that serialize them.
+pub fn execute(kind: &str) -> Result<()> {
+    let schema = match kind {
+        "explain" => schemars::schema_for!(ExplainOutput),
+        "diff" => schemars::schema_for!(DiffOutput),
+        "pack-summary" => schemars::schema_for!(PackSummary),
+        _ => bail!(
+            "unknown schema {:?} (expected one of: {})",
+            kind,
+            KINDS.join(", ")
+        ),
+    };
+    println!("{}", serde_json::to_string_pretty(&schema)?);
+    Ok(())
+}
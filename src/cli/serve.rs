@@ -0,0 +1,63 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::{bail, Result};
+
+use crate::serve::http::Limits;
+use crate::serve::retention::ServeConfig;
+use crate::serve::server::{self, TlsOptions};
+use crate::serve::storage;
+use crate::util;
+
+const DEFAULT_CACHE_SIZE: u64 = 10 * 1024 * 1024 * 1024;
+
+#[allow(clippy::too_many_arguments)]
+pub fn execute(
+    bind: String,
+    store: String,
+    config: Option<PathBuf>,
+    cache_dir: Option<PathBuf>,
+    cache_size: Option<String>,
+    tls_cert: Option<PathBuf>,
+    tls_key: Option<PathBuf>,
+    http_redirect: Option<String>,
+    max_body: Option<String>,
+    max_concurrent: Option<usize>,
+    timeout: Option<u64>,
+) -> Result<()> {
+    let cache_size = match cache_size {
+        Some(s) => {
+            util::parse_size(&s).ok_or_else(|| anyhow::anyhow!("invalid --cache-size: {}", s))?
+        }
+        None => DEFAULT_CACHE_SIZE,
+    };
+    let mut limits = Limits::default();
+    if let Some(s) = max_body {
+        limits.max_body =
+            util::parse_size(&s).ok_or_else(|| anyhow::anyhow!("invalid --max-body: {}", s))?;
+    }
+    if let Some(n) = max_concurrent {
+        if n == 0 {
+            bail!("--max-concurrent must be at least 1");
+        }
+        limits.max_concurrent = n;
+    }
+    if let Some(secs) = timeout {
+        if secs == 0 {
+            bail!("--timeout must be at least 1 second");
+        }
+        limits.idle_timeout = Duration::from_secs(secs);
+    }
+
+    let tls = tls_cert.zip(tls_key).map(|(cert, key)| TlsOptions {
+        cert,
+        key,
+        redirect_from: http_redirect,
+    });
+    let config = match config {
+        Some(path) => ServeConfig::load(&path)?,
+        None => ServeConfig::default(),
+    };
+    let storage = storage::open(&store, cache_dir.as_deref(), cache_size)?;
+    server::start(&bind, storage, config.retention, tls.as_ref(), limits)
+}
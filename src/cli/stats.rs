@@ -0,0 +1,128 @@
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use colored::Colorize;
+
+use crate::explain::analyzer;
+use crate::explain::trends::{self, RunPoint};
+use crate::pack::reader::PackReader;
+
+pub fn execute(dir: PathBuf, recent: Option<usize>, json: bool) -> Result<()> {
+    let mut points = Vec::new();
+    for entry in
+        std::fs::read_dir(&dir).with_context(|| format!("failed to read {}", dir.display()))?
+    {
+        let path = entry?.path();
+        if !path.extension().map(|x| x == "poepack").unwrap_or(false) {
+            continue;
+        }
+        let pack = match PackReader::open(&path) {
+            Ok(pack) => pack,
+            Err(e) => {
+                log::warn!("skipping {}: {:#}", path.display(), e);
+                continue;
+            }
+        };
+        let output = analyzer::analyze(&pack)?;
+        points.push(RunPoint::from_pack(pack.summary(), &output));
+    }
+    let packs = points.len();
+    let trends = trends::by_command(points);
+
+    if json {
+        let report: Vec<_> = trends
+            .iter()
+            .map(|t| {
+                serde_json::json!({
+                    "command_hash": t.command_hash,
+                    "command": t.command,
+                    "runs": t.runs,
+                    "failures": t.failures,
+                    "failure_rate": t.failure_rate,
+                    "first_seen": t.first_seen,
+                    "last_seen": t.last_seen,
+                    "duration": t.duration,
+                    "comparison": trends::compare(t, recent),
+                })
+            })
+            .collect();
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        return Ok(());
+    }
+
+    println!();
+    println!("{}", "=== poe stats ===".cyan().bold());
+    println!();
+    println!("  {} packs, {} commands", packs, trends.len());
+    println!();
+
+    let mut regressions = 0;
+    for t in &trends {
+        println!(
+            "  {} {}",
+            t.command.join(" ").bold(),
+            format!("({})", t.command_hash).dimmed()
+        );
+        println!(
+            "      {} runs, {} failed ({:.0}%), p50 {}ms p90 {}ms",
+            t.runs,
+            t.failures,
+            t.failure_rate * 100.0,
+            t.duration.p50_ms,
+            t.duration.p90_ms
+        );
+        if let Some(c) = trends::compare(t, recent) {
+            let change = if c.baseline_p50_ms > 0 {
+                format!(
+                    " ({:+.0}%)",
+                    (c.recent_p50_ms as f64 / c.baseline_p50_ms as f64 - 1.0) * 100.0
+                )
+            } else {
+                String::new()
+            };
+            let line = format!(
+                "last {} vs previous {}: p50 {}ms -> {}ms{}",
+                c.recent_runs, c.baseline_runs, c.baseline_p50_ms, c.recent_p50_ms, change
+            );
+            if c.slower {
+                println!("      {} {}", line.red(), "SLOWER".red().bold());
+            } else {
+                println!("      {}", line.dimmed());
+            }
+            if c.recent_failure_rate != c.baseline_failure_rate {
+                let line = format!(
+                    "failure rate {:.0}% -> {:.0}%",
+                    c.baseline_failure_rate * 100.0,
+                    c.recent_failure_rate * 100.0
+                );
+                if c.recent_failure_rate > c.baseline_failure_rate {
+                    println!("      {}", line.red());
+                } else {
+                    println!("      {}", line.green());
+                }
+            }
+            if !c.new_patterns.is_empty() {
+                println!(
+                    "      {} {}",
+                    "new recurring errors:".red(),
+                    c.new_patterns.join(", ")
+                );
+            }
+            if c.is_regression() {
+                regressions += 1;
+            }
+        }
+        println!();
+    }
+
+    if regressions > 0 {
+        println!(
+            "  {}",
+            format!("{} command(s) regressed", regressions).red().bold()
+        );
+    } else {
+        println!("  {}", "no regressions".green());
+    }
+    println!();
+    Ok(())
+}
@@ -0,0 +1,209 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use colored::Colorize;
+
+use crate::trace::db::TraceDb;
+
+const REFRESH: Duration = Duration::from_secs(1);
+const MAX_ROWS: usize = 10;
+
+pub fn execute(target: Option<String>) -> Result<()> {
+    let db_path = resolve_target(target.as_deref())?;
+    let db = TraceDb::open(&db_path)?;
+
+    let run = db.query_run()?;
+    let (run_id, command) = match run {
+        Some(ref r) => (
+            r.run_id.clone(),
+            serde_json::from_str::<Vec<String>>(&r.command)
+                .map(|v| v.join(" "))
+                .unwrap_or_else(|_| r.command.clone()),
+        ),
+        None => (String::from("unknown"), String::new()),
+    };
+
+    let mut totals: HashMap<i32, i64> = HashMap::new();
+    let (mut file_id, mut net_id) = (0, 0);
+    let mut last_tick = Instant::now();
+    let started = Instant::now();
+
+    loop {
+        // The work dir disappears once the pack has been written.
+        if !db_path.exists() {
+            println!("poe: run {} finished", run_id);
+            return Ok(());
+        }
+
+        let (next_file_id, next_net_id) = db.max_activity_ids()?;
+        let rates = db.query_proc_activity_since(file_id, net_id)?;
+        let hot_paths = db.query_hot_paths_since(file_id, MAX_ROWS)?;
+        let connections = db.query_open_connections(MAX_ROWS)?;
+        let processes = db.query_processes()?;
+        let finished = db.query_run()?.and_then(|r| r.end_time).is_some();
+
+        let elapsed = last_tick.elapsed().as_secs_f64().max(0.001);
+        last_tick = Instant::now();
+        file_id = next_file_id;
+        net_id = next_net_id;
+
+        for (pid, count) in &rates {
+            *totals.entry(*pid).or_insert(0) += count;
+        }
+
+        let argv: HashMap<i32, String> = processes
+            .iter()
+            .map(|p| {
+                let cmd = p
+                    .argv
+                    .as_ref()
+                    .and_then(|a| serde_json::from_str::<Vec<String>>(a).ok())
+                    .map(|v| v.join(" "))
+                    .unwrap_or_default();
+                (p.proc_id, cmd)
+            })
+            .collect();
+        let alive: Vec<i32> = processes
+            .iter()
+            .filter(|p| p.end_ts.is_none())
+            .map(|p| p.proc_id)
+            .collect();
+
+        print!("\x1b[2J\x1b[H");
+        println!("{}", "=== poe top ===".cyan().bold());
+        println!("{} {}", "run_id:".dimmed(), run_id);
+        println!("{} {}", "command:".dimmed(), command);
+        println!(
+            "{} {:.0}s  {} {}/{}",
+            "watching:".dimmed(),
+            started.elapsed().as_secs_f64(),
+            "live processes:".dimmed(),
+            alive.len(),
+            processes.len()
+        );
+        println!();
+
+        println!("{}", "--- processes ---".yellow().bold());
+        println!(
+            "  {:>8} {:>10} {:>10}  COMMAND",
+            "PID", "SYSCALL/s", "TOTAL"
+        );
+        let mut rows: Vec<(i32, i64)> = alive
+            .iter()
+            .map(|pid| {
+                let rate = rates
+                    .iter()
+                    .find(|(p, _)| p == pid)
+                    .map(|(_, c)| *c)
+                    .unwrap_or(0);
+                (*pid, rate)
+            })
+            .collect();
+        rows.sort_by_key(|(_, rate)| std::cmp::Reverse(*rate));
+        for (pid, rate) in rows.iter().take(MAX_ROWS) {
+            let cmd = argv.get(pid).map(|s| s.as_str()).unwrap_or("");
+            println!(
+                "  {:>8} {:>10.0} {:>10}  {}",
+                pid,
+                *rate as f64 / elapsed,
+                totals.get(pid).copied().unwrap_or(0),
+                truncate(cmd, 60)
+            );
+        }
+        println!();
+
+        println!("{}", "--- hottest files ---".yellow().bold());
+        if hot_paths.is_empty() {
+            println!("  {}", "(idle)".dimmed());
+        }
+        for (path, count) in &hot_paths {
+            println!("  {:>8.0}/s  {}", *count as f64 / elapsed, path);
+        }
+        println!();
+
+        println!("{}", "--- open connections ---".yellow().bold());
+        if connections.is_empty() {
+            println!("  {}", "(none)".dimmed());
+        }
+        for conn in &connections {
+            let addr = match conn.op.as_str() {
+                "accept" => conn.src.as_deref().unwrap_or("?"),
+                _ => conn.dst.as_deref().unwrap_or("?"),
+            };
+            println!(
+                "  [{}] {:<7} {:<5} {}",
+                conn.proc_id,
+                conn.op,
+                conn.proto.as_deref().unwrap_or("-"),
+                addr
+            );
+        }
+        println!();
+
+        if finished {
+            println!("poe: run {} finished", run_id);
+            return Ok(());
+        }
+
+        std::thread::sleep(REFRESH);
+    }
+}
+
+fn resolve_target(target: Option<&str>) -> Result<PathBuf> {
+    let Some(target) = target else {
+        return latest_work_dir()
+            .map(|d| d.join("trace.sqlite"))
+            .ok_or_else(|| anyhow::anyhow!("no in-progress poe run found"));
+    };
+
+    let path = Path::new(target);
+    if path.is_file() {
+        return Ok(path.to_path_buf());
+    }
+    if path.join("trace.sqlite").is_file() {
+        return Ok(path.join("trace.sqlite"));
+    }
+
+    // Run ids are hex, so anything else can't name a work dir (and can't
+    // be sliced mid-character).
+    let prefix = target.trim_start_matches("poe-");
+    if !prefix.is_empty() && prefix.bytes().all(|b| b.is_ascii_hexdigit()) {
+        let by_id = std::env::temp_dir().join(format!("poe-{}", &prefix[..prefix.len().min(8)]));
+        if by_id.join("trace.sqlite").is_file() {
+            return Ok(by_id.join("trace.sqlite"));
+        }
+    }
+
+    anyhow::bail!("no in-progress run found at {}", target)
+}
+
+fn latest_work_dir() -> Option<PathBuf> {
+    std::fs::read_dir(std::env::temp_dir())
+        .ok()?
+        .flatten()
+        .filter(|e| e.file_name().to_string_lossy().starts_with("poe-"))
+        .filter(|e| e.path().join("trace.sqlite").is_file())
+        .max_by_key(|e| e.metadata().and_then(|m| m.modified()).ok())
+        .map(|e| e.path())
+}
+
+fn truncate(s: &str, max: usize) -> String {
+    if s.chars().count() <= max {
+        s.to_string()
+    } else {
+        format!("{}...", s.chars().take(max - 3).collect::<String>())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn non_hex_run_ids_are_rejected_without_slicing() {
+        assert!(resolve_target(Some("aéééé")).is_err());
+        assert!(resolve_target(Some("poe-../../etc")).is_err());
+    }
+}
@@ -1,9 +1,12 @@
+use std::io::Read;
+use std::os::unix::process::CommandExt;
 use std::path::PathBuf;
 
-use anyhow::Result;
+use anyhow::{bail, Context, Result};
 use colored::Colorize;
 
 use crate::distributed::trace_context;
+use crate::util;
 
 pub fn execute(packs: Vec<PathBuf>, json: bool) -> Result<()> {
     let traces = trace_context::correlate_packs(&packs)?;
@@ -41,12 +44,20 @@ pub fn execute(packs: Vec<PathBuf>, json: bool) -> Result<()> {
                 "?".dimmed().to_string()
             };
 
+            let mut at = match span.timeline_ms {
+                Some(ms) => format!(" +{}ms", ms),
+                None => String::new(),
+            };
+            if span.clock_shift_ms != 0 {
+                at.push_str(&format!(" (clock shifted {:+}ms)", span.clock_shift_ms));
+            }
             println!(
-                "{}[{}] {} @ {} ({}ms) -> {}",
+                "{}[{}] {} @ {}{} ({}ms) -> {}",
                 indent,
                 prefix.cyan(),
                 span.command.join(" "),
                 span.hostname.dimmed(),
+                at.dimmed(),
                 span.duration_ms,
                 status,
             );
@@ -56,9 +67,111 @@ pub fn execute(packs: Vec<PathBuf>, json: bool) -> Result<()> {
             }
         }
 
+        for warning in &trace.clock_warnings {
+            println!();
+            println!("  {} {}", "clock:".red().bold(), warning);
+        }
+
+        for gap in &trace.gaps {
+            let Some(span) = trace.spans.iter().find(|s| s.span_id == gap.span_id) else {
+                continue;
+            };
+            println!();
+            println!(
+                "  {} {} spent {}ms of {}ms outside its child spans",
+                "gap:".yellow().bold(),
+                span.command.join(" "),
+                gap.gap_ms,
+                gap.duration_ms,
+            );
+            for window in &gap.windows {
+                println!(
+                    "    {}",
+                    format!("+{}ms..+{}ms:", window.start_ms, window.end_ms).dimmed()
+                );
+                for line in &window.activity {
+                    println!("      {}", line);
+                }
+            }
+        }
+
         println!();
         println!("{}", "=========================".cyan().bold());
     }
 
     Ok(())
 }
+
+pub fn inject(json: bool) -> Result<()> {
+    let Some(headers) = trace_context::headers_from_env() else {
+        bail!("not running under `poe run`: there is no trace to propagate");
+    };
+    if json {
+        let object: serde_json::Map<String, serde_json::Value> = headers
+            .into_iter()
+            .map(|(name, value)| (name.to_string(), value.into()))
+            .collect();
+        println!("{}", serde_json::to_string(&object)?);
+    } else {
+        for (name, value) in headers {
+            println!("{}: {}", name, value);
+        }
+    }
+    Ok(())
+}
+
+pub fn extract(
+    headers: Vec<String>,
+    headers_json: Option<String>,
+    command: Vec<String>,
+) -> Result<()> {
+    let mut pairs: Vec<(String, String)> = Vec::new();
+    for header in &headers {
+        let Some((name, value)) = header.split_once(['=', ':']) else {
+            bail!("invalid header {:?}: expected NAME=VALUE", header);
+        };
+        pairs.push((name.to_string(), value.to_string()));
+    }
+    if let Some(json) = headers_json {
+        let json = if json == "-" {
+            let mut input = String::new();
+            std::io::stdin().read_to_string(&mut input)?;
+            input
+        } else {
+            json
+        };
+        let object: serde_json::Map<String, serde_json::Value> =
+            serde_json::from_str(&json).context("--headers-json is not a JSON object")?;
+        for (name, value) in object {
+            // Some clients hand header values over as byte arrays.
+            let value = match value {
+                serde_json::Value::String(s) => s,
+                serde_json::Value::Array(bytes) => bytes
+                    .iter()
+                    .filter_map(|b| b.as_u64().map(|b| b as u8 as char))
+                    .collect(),
+                other => other.to_string(),
+            };
+            pairs.push((name, value));
+        }
+    }
+    let Some(env) =
+        trace_context::env_from_headers(pairs.iter().map(|(n, v)| (n.as_str(), v.as_str())))
+    else {
+        bail!("the headers carry no poe trace (poe-trace-id and poe-parent-span-id)");
+    };
+
+    let Some((program, args)) = command.split_first() else {
+        let mut vars: Vec<_> = env.into_iter().collect();
+        vars.sort();
+        for (var, value) in vars {
+            println!("export {}={}", var, util::shell_quote(&value));
+        }
+        return Ok(());
+    };
+    let err = std::process::Command::new(program)
+        .args(args)
+        .envs(env)
+        .exec();
+    Err(err).with_context(|| format!("failed to run {}", program))
+}
@@ -0,0 +1,21 @@
+use std::io::IsTerminal;
+use std::path::PathBuf;
+
+use anyhow::{bail, Result};
+
+use crate::explain::patterns::PatternRegistry;
+use crate::pack::reader::PackReader;
+use crate::pack::remote;
+use crate::view;
+
+pub fn execute(pack_path: PathBuf, pattern_files: Vec<PathBuf>) -> Result<()> {
+    if !std::io::stdout().is_terminal() {
+        bail!("poe view needs a terminal; use poe explain or poe query for scripts");
+    }
+    let pack = PackReader::open(&remote::resolve(&pack_path)?)?;
+    let mut registry = PatternRegistry::load_default();
+    for path in &pattern_files {
+        registry.load_rules_file(path)?;
+    }
+    view::run(&pack, &registry)
+}
@@ -0,0 +1,198 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::distributed::trace_context::TraceSpan;
+
+/// Error assumed for a host whose clock isn't known to be synchronized.
+const UNSYNCED_ERROR_MS: u64 = 1000;
+
+/// The host clock's NTP state when the run started, as the kernel reports
+/// it: recorded in `environment.json` so spans from different hosts can
+/// be placed on one timeline.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ClockStatus {
+    /// An NTP daemon is disciplining the clock.
+    pub synchronized: bool,
+    /// Correction the kernel still has to apply: the reference time is
+    /// the clock plus this.
+    pub offset_us: i64,
+    /// Bound on the clock's error.
+    pub max_error_us: i64,
+    pub est_error_us: i64,
+}
+
+impl ClockStatus {
+    pub fn read() -> Option<Self> {
+        // SAFETY: timex is plain data, and modes 0 only reads the state.
+        let mut tx: libc::timex = unsafe { std::mem::zeroed() };
+        let state = unsafe { libc::adjtimex(&mut tx) };
+        if state < 0 {
+            return None;
+        }
+        let offset_us = if tx.status & libc::STA_NANO != 0 {
+            tx.offset / 1000
+        } else {
+            tx.offset
+        };
+        Some(Self {
+            synchronized: tx.status & libc::STA_UNSYNC == 0 && state != libc::TIME_ERROR,
+            offset_us: offset_us as i64,
+            max_error_us: tx.maxerror as i64,
+            est_error_us: tx.esterror as i64,
+        })
+    }
+
+    /// How far off the clock may be, in ms.
+    pub fn error_ms(&self) -> u64 {
+        if self.synchronized {
+            (self.max_error_us.max(0) as u64).div_ceil(1000)
+        } else {
+            UNSYNCED_ERROR_MS
+        }
+    }
+}
+
+/// Places spans on one timeline, in ms from the earliest start: each
+/// start is corrected by its host's NTP offset, then hosts whose spans
+/// start before the parent that launched them are shifted forward, since a
+/// child can't start first. Returns a warning for each such negative gap
+/// too large for the clocks' error bounds to explain.
+pub fn align(spans: &mut [TraceSpan]) -> Vec<String> {
+    let corrected: Vec<Option<i64>> = spans.iter().map(corrected_start).collect();
+    let by_span: HashMap<&str, usize> = spans
+        .iter()
+        .enumerate()
+        .map(|(i, s)| (s.span_id.as_str(), i))
+        .collect();
+
+    // Parents before children, so a host shifted for one hop carries the
+    // shift to the next.
+    let mut order: Vec<usize> = Vec::new();
+    let mut queue: Vec<usize> = (0..spans.len())
+        .filter(|&i| {
+            spans[i]
+                .parent_span_id
+                .as_deref()
+                .is_none_or(|p| !by_span.contains_key(p))
+        })
+        .collect();
+    while let Some(i) = queue.pop() {
+        order.push(i);
+        queue.extend((0..spans.len()).filter(|&c| {
+            spans[c].parent_span_id.as_deref() == Some(spans[i].span_id.as_str())
+                && !order.contains(&c)
+        }));
+    }
+
+    let mut shifts: HashMap<String, i64> = HashMap::new();
+    let mut warnings = Vec::new();
+    for &i in &order {
+        let span = &spans[i];
+        let parent = span
+            .parent_span_id
+            .as_deref()
+            .and_then(|p| by_span.get(p).copied());
+        let (Some(p), Some(start)) = (parent, corrected[i]) else {
+            continue;
+        };
+        let Some(parent_start) = corrected[p] else {
+            continue;
+        };
+        let parent_shift = shifts.get(&spans[p].hostname).copied().unwrap_or(0);
+        let shift = shifts.get(&span.hostname).copied().unwrap_or(0);
+        let lead = (parent_start + parent_shift) - (start + shift);
+        if lead <= 0 {
+            continue;
+        }
+        let bound = spans[p].clock_error_ms.unwrap_or(UNSYNCED_ERROR_MS)
+            + span.clock_error_ms.unwrap_or(UNSYNCED_ERROR_MS);
+        let same_clock = span.hostname == spans[p].hostname;
+        if same_clock || lead as u64 > bound {
+            warnings.push(format!(
+                "{} on {} starts {}ms before its parent {} on {}{}",
+                span.command.join(" "),
+                span.hostname,
+                lead,
+                spans[p].command.join(" "),
+                spans[p].hostname,
+                if same_clock {
+                    " on the same clock".to_string()
+                } else {
+                    format!(", beyond the clocks' {}ms error bound", bound)
+                }
+            ));
+        }
+        if !same_clock {
+            shifts.insert(span.hostname.clone(), shift + lead);
+        }
+    }
+
+    let aligned: Vec<Option<i64>> = corrected
+        .iter()
+        .zip(spans.iter())
+        .map(|(start, s)| Some(start.as_ref()? + shifts.get(&s.hostname).unwrap_or(&0)))
+        .collect();
+    let origin = aligned.iter().flatten().min().copied().unwrap_or(0);
+    for (span, start) in spans.iter_mut().zip(aligned) {
+        span.clock_shift_ms = shifts.get(&span.hostname).copied().unwrap_or(0);
+        span.timeline_ms = start.map(|s| s - origin);
+    }
+    warnings
+}
+
+fn corrected_start(span: &TraceSpan) -> Option<i64> {
+    let start = chrono::DateTime::parse_from_rfc3339(&span.start_time)
+        .ok()?
+        .timestamp_millis();
+    Some(start + span.clock_offset_ms)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn span(id: &str, parent: Option<&str>, host: &str, start: &str, error: u64) -> TraceSpan {
+        TraceSpan {
+            span_id: id.into(),
+            parent_span_id: parent.map(Into::into),
+            run_id: id.into(),
+            command: vec![id.into()],
+            hostname: host.into(),
+            exit_code: Some(0),
+            signal: None,
+            duration_ms: 1000,
+            pack_path: None,
+            start_time: format!("2026-01-01T00:00:{}+00:00", start),
+            clock_offset_ms: 0,
+            clock_error_ms: Some(error),
+            clock_shift_ms: 0,
+            timeline_ms: None,
+        }
+    }
+
+    #[test]
+    fn shifts_hosts_whose_children_start_before_their_parent() {
+        let mut spans = vec![
+            span("root", None, "a", "10.000", 5),
+            // b's clock runs 2s behind: its span "starts" before the root.
+            span("api", Some("root"), "b", "08.300", 5),
+            span("db", Some("api"), "b", "08.500", 5),
+            span("cache", Some("root"), "c", "10.100", 5),
+        ];
+        let warnings = align(&mut spans);
+        let timeline: Vec<Option<i64>> = spans.iter().map(|s| s.timeline_ms).collect();
+        assert_eq!(timeline, [Some(0), Some(0), Some(200), Some(100)]);
+        assert_eq!(spans[1].clock_shift_ms, 1700);
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].starts_with("api on b starts 1700ms before its parent root on a"));
+
+        // Within the error bounds it is only aligned, not flagged.
+        let mut spans = vec![
+            span("root", None, "a", "10.000", 500),
+            span("api", Some("root"), "b", "09.800", 500),
+        ];
+        assert!(align(&mut spans).is_empty());
+        assert_eq!(spans[1].timeline_ms, Some(0));
+    }
+}
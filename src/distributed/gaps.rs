@@ -0,0 +1,203 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::distributed::trace_context::TraceSpan;
+use crate::pack::reader::PackReader;
+
+/// A parent is reported when its children leave at least this share of
+/// its duration, and `MIN_GAP_MS`, unaccounted for.
+const MIN_GAP_SHARE: f64 = 0.5;
+const MIN_GAP_MS: u64 = 100;
+const MAX_WINDOWS: usize = 3;
+const MAX_ACTIVITY: usize = 5;
+const MAX_STARTED: usize = 3;
+
+/// Time a parent span spent outside all of its child spans.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpanGap {
+    pub span_id: String,
+    pub run_id: String,
+    pub duration_ms: u64,
+    /// Covered by at least one child span.
+    pub children_ms: u64,
+    pub gap_ms: u64,
+    /// The longest stretches no child covered, longest first.
+    pub windows: Vec<GapWindow>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GapWindow {
+    /// Offsets from the parent's start.
+    pub start_ms: u64,
+    pub end_ms: u64,
+    /// What the parent was doing meanwhile, longest blocked first, e.g.
+    /// `connect 10.0.0.7:5432 x3, 1840ms blocked`.
+    pub activity: Vec<String>,
+}
+
+/// Parents whose duration greatly exceeds what their children account
+/// for, with the parent's own file and network activity in each gap.
+/// Children are placed by their start on the aligned timeline.
+pub fn find_gaps(spans: &[TraceSpan]) -> Vec<SpanGap> {
+    let mut gaps = Vec::new();
+    for parent in spans {
+        let Some(start) = parent.timeline_ms else {
+            continue;
+        };
+        let duration = parent.duration_ms as i64;
+        let children: Vec<(u64, u64)> = spans
+            .iter()
+            .filter(|s| s.parent_span_id.as_deref() == Some(parent.span_id.as_str()))
+            .filter_map(|child| {
+                let offset = child.timeline_ms? - start;
+                let from = offset.clamp(0, duration);
+                let to = (offset + child.duration_ms as i64).clamp(0, duration);
+                Some((from as u64, to as u64))
+            })
+            .collect();
+        if children.is_empty() {
+            continue;
+        }
+        let mut windows = uncovered(parent.duration_ms, &children);
+        let gap_ms: u64 = windows.iter().map(|(from, to)| to - from).sum();
+        if gap_ms < MIN_GAP_MS || (gap_ms as f64) < parent.duration_ms as f64 * MIN_GAP_SHARE {
+            continue;
+        }
+        windows.sort_by_key(|(from, to)| std::cmp::Reverse(to - from));
+        windows.truncate(MAX_WINDOWS);
+        let pack = parent
+            .pack_path
+            .as_deref()
+            .and_then(|p| PackReader::open(Path::new(p)).ok());
+        gaps.push(SpanGap {
+            span_id: parent.span_id.clone(),
+            run_id: parent.run_id.clone(),
+            duration_ms: parent.duration_ms,
+            children_ms: parent.duration_ms - gap_ms,
+            gap_ms,
+            windows: windows
+                .into_iter()
+                .map(|(from, to)| GapWindow {
+                    start_ms: from,
+                    end_ms: to,
+                    activity: pack
+                        .as_ref()
+                        .and_then(|p| activity(p, from, to).ok())
+                        .unwrap_or_default(),
+                })
+                .collect(),
+        });
+    }
+    gaps
+}
+
+/// The parts of `[0, duration)` no interval covers, in order.
+fn uncovered(duration: u64, intervals: &[(u64, u64)]) -> Vec<(u64, u64)> {
+    let mut sorted = intervals.to_vec();
+    sorted.sort();
+    let mut windows = Vec::new();
+    let mut at = 0;
+    for (from, to) in sorted {
+        if from > at {
+            windows.push((at, from));
+        }
+        at = at.max(to);
+    }
+    if duration > at {
+        windows.push((at, duration));
+    }
+    windows
+}
+
+/// File and network operations of the run overlapping `[from, to)` ms,
+/// grouped by operation and target.
+fn activity(pack: &PackReader, from: u64, to: u64) -> Result<Vec<String>> {
+    let (from, to) = (from as i64 * 1_000_000, to as i64 * 1_000_000);
+    let overlaps = |ts: i64, duration: Option<i64>| ts < to && ts + duration.unwrap_or(0) >= from;
+    let db = pack.db();
+
+    // (count, ns blocked) per "op target".
+    let mut ops: HashMap<String, (u64, i64)> = HashMap::new();
+    for e in db.query_net_events()? {
+        if overlaps(e.ts, e.duration_ns) {
+            let key = format!("{} {}", e.op, e.dst.as_deref().unwrap_or("?"));
+            let entry = ops.entry(key).or_default();
+            entry.0 += 1;
+            entry.1 += e.duration_ns.unwrap_or(0);
+        }
+    }
+    for e in db.query_file_events()? {
+        if overlaps(e.ts, e.duration_ns) {
+            let target = match (e.path.as_deref(), e.fd) {
+                (Some(path), _) if !path.is_empty() => path.to_string(),
+                (_, Some(fd)) => format!("fd {}", fd),
+                _ => "?".into(),
+            };
+            let entry = ops.entry(format!("{} {}", e.op, target)).or_default();
+            entry.0 += 1;
+            entry.1 += e.duration_ns.unwrap_or(0);
+        }
+    }
+    let mut ops: Vec<(String, (u64, i64))> = ops.into_iter().collect();
+    ops.sort_by(|a, b| {
+        b.1 .1
+            .cmp(&a.1 .1)
+            .then(b.1 .0.cmp(&a.1 .0))
+            .then(a.0.cmp(&b.0))
+    });
+
+    let mut lines: Vec<String> = ops
+        .into_iter()
+        .take(MAX_ACTIVITY)
+        .map(|(op, (count, blocked_ns))| {
+            let mut line = op;
+            if count > 1 {
+                line.push_str(&format!(" x{}", count));
+            }
+            if blocked_ns >= 1_000_000 {
+                line.push_str(&format!(", {}ms blocked", blocked_ns / 1_000_000));
+            }
+            line
+        })
+        .collect();
+    let started: Vec<_> = db
+        .query_processes()?
+        .into_iter()
+        .filter(|p| p.start_ts >= from && p.start_ts < to)
+        .collect();
+    for p in started.iter().take(MAX_STARTED) {
+        let argv: Vec<String> = p
+            .argv
+            .as_deref()
+            .and_then(|a| serde_json::from_str(a).ok())
+            .unwrap_or_default();
+        lines.push(format!("started pid {} ({})", p.proc_id, argv.join(" ")));
+    }
+    if started.len() > MAX_STARTED {
+        lines.push(format!(
+            "started {} more processes",
+            started.len() - MAX_STARTED
+        ));
+    }
+    if lines.is_empty() {
+        lines.push("no file or network activity recorded".into());
+    }
+    Ok(lines)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_the_time_no_child_covers() {
+        assert_eq!(
+            uncovered(1000, &[(600, 700), (100, 300), (250, 400)]),
+            [(0, 100), (400, 600), (700, 1000)]
+        );
+        assert_eq!(uncovered(500, &[(0, 500)]), []);
+    }
+}
@@ -0,0 +1,124 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::thread::JoinHandle;
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::distributed::clock::ClockStatus;
+use crate::distributed::trace_context::TraceSpan;
+use crate::events::types::RunInfo;
+
+/// How long a report may take before the run goes on without it.
+const POST_TIMEOUT_SECS: &str = "5";
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SpanStatus {
+    Running,
+    Done,
+}
+
+/// A span as `poe run --stream` reports it to the server, once when the
+/// command starts and again when it ends.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpanReport {
+    #[serde(flatten)]
+    pub span: TraceSpan,
+    pub status: SpanStatus,
+}
+
+/// Reports this run's span to a `poe serve` instance.
+pub struct LiveReporter {
+    url: String,
+    report: SpanReport,
+    started: Option<JoinHandle<()>>,
+}
+
+impl LiveReporter {
+    /// Sends the running span in the background, so a slow server doesn't
+    /// hold up the command.
+    pub fn start(server: &str, run_info: &RunInfo) -> Option<Self> {
+        let ctx = run_info.trace_context.as_ref()?;
+        let clock = ClockStatus::read();
+        let report = SpanReport {
+            span: TraceSpan {
+                span_id: ctx.span_id.clone(),
+                parent_span_id: ctx.parent_span_id.clone(),
+                run_id: run_info.run_id.clone(),
+                command: run_info.command.clone(),
+                hostname: run_info.hostname.clone(),
+                exit_code: None,
+                signal: None,
+                duration_ms: 0,
+                pack_path: None,
+                start_time: run_info.start_time.to_rfc3339(),
+                clock_offset_ms: clock.as_ref().map_or(0, |c| c.offset_us / 1000),
+                clock_error_ms: clock.as_ref().map(ClockStatus::error_ms),
+                clock_shift_ms: 0,
+                timeline_ms: None,
+            },
+            status: SpanStatus::Running,
+        };
+        let url = format!(
+            "{}/api/traces/{}/spans",
+            server.trim_end_matches('/'),
+            ctx.trace_id
+        );
+        let body = serde_json::to_vec(&report).ok()?;
+        let started = {
+            let url = url.clone();
+            std::thread::spawn(move || {
+                if let Err(e) = post(&url, &body) {
+                    log::warn!("failed to stream span start to {}: {:#}", url, e);
+                }
+            })
+        };
+        Some(Self {
+            url,
+            report,
+            started: Some(started),
+        })
+    }
+
+    pub fn finish(mut self, exit_code: Option<i32>, signal: Option<i32>, duration_ms: u64) {
+        // The start must land first or the server sees the span end, then
+        // start again.
+        if let Some(started) = self.started.take() {
+            let _ = started.join();
+        }
+        self.report.status = SpanStatus::Done;
+        self.report.span.exit_code = exit_code;
+        self.report.span.signal = signal;
+        self.report.span.duration_ms = duration_ms;
+        let result = serde_json::to_vec(&self.report)
+            .map_err(Into::into)
+            .and_then(|body| post(&self.url, &body));
+        if let Err(e) = result {
+            log::warn!("failed to stream span end to {}: {:#}", self.url, e);
+        }
+    }
+}
+
+fn post(url: &str, body: &[u8]) -> Result<()> {
+    let mut child = Command::new("curl")
+        .args(["-sS", "-f", "--max-time", POST_TIMEOUT_SECS])
+        .args([
+            "-H",
+            "Content-Type: application/json",
+            "--data-binary",
+            "@-",
+        ])
+        .arg(url)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("failed to run curl")?;
+    child.stdin.take().unwrap().write_all(body)?;
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        bail!("{}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(())
+}
@@ -1 +1,4 @@
+pub mod clock;
+pub mod gaps;
+pub mod live;
 pub mod trace_context;
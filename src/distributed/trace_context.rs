@@ -2,10 +2,21 @@ use std::collections::HashMap;
 
 use serde::{Deserialize, Serialize};
 
+use crate::distributed::clock::{self, ClockStatus};
+use crate::distributed::gaps::{self, SpanGap};
+
 const POE_TRACE_ID_ENV: &str = "POE_TRACE_ID";
 const POE_PARENT_SPAN_ENV: &str = "POE_PARENT_SPAN_ID";
 const POE_TRACE_ORIGIN_ENV: &str = "POE_TRACE_ORIGIN";
 
+/// Message headers carrying a trace across a queue, each standing in for
+/// the environment variable beside it.
+pub const TRACE_HEADERS: [(&str, &str); 3] = [
+    ("poe-trace-id", POE_TRACE_ID_ENV),
+    ("poe-parent-span-id", POE_PARENT_SPAN_ENV),
+    ("poe-trace-origin", POE_TRACE_ORIGIN_ENV),
+];
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TraceContext {
     pub trace_id: String,
@@ -58,12 +69,60 @@ impl TraceContext {
     pub fn is_distributed(&self) -> bool {
         self.parent_span_id.is_some()
     }
+
+    /// Headers that make the consumer of a message a child of this span.
+    pub fn to_headers(&self) -> Vec<(&'static str, String)> {
+        let mut env = HashMap::new();
+        self.inject_env(&mut env);
+        TRACE_HEADERS
+            .iter()
+            .filter_map(|(header, var)| Some((*header, env.remove(*var)?)))
+            .collect()
+    }
+}
+
+/// Headers for a message sent by the running program. Under `poe run` the
+/// environment names the capture's span, which becomes the consumer's
+/// parent; elsewhere there is no trace to join.
+pub fn headers_from_env() -> Option<Vec<(&'static str, String)>> {
+    let var = |name| std::env::var(name).ok().filter(|v: &String| !v.is_empty());
+    let mut headers = vec![
+        (TRACE_HEADERS[0].0, var(POE_TRACE_ID_ENV)?),
+        (TRACE_HEADERS[1].0, var(POE_PARENT_SPAN_ENV)?),
+    ];
+    if let Some(origin) = var(POE_TRACE_ORIGIN_ENV) {
+        headers.push((TRACE_HEADERS[2].0, origin));
+    }
+    Some(headers)
+}
+
+/// The environment a consumer runs under so its capture joins the trace
+/// of the message's producer. Header names match case-insensitively, with
+/// `_` for `-`, as brokers and client libraries differ.
+pub fn env_from_headers<'a>(
+    headers: impl IntoIterator<Item = (&'a str, &'a str)>,
+) -> Option<HashMap<String, String>> {
+    let mut env = HashMap::new();
+    for (name, value) in headers {
+        let name = name.trim().to_ascii_lowercase().replace('_', "-");
+        if let Some((_, var)) = TRACE_HEADERS.iter().find(|(h, _)| *h == name) {
+            env.insert(var.to_string(), value.trim().to_string());
+        }
+    }
+    (env.contains_key(POE_TRACE_ID_ENV) && env.contains_key(POE_PARENT_SPAN_ENV)).then_some(env)
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DistributedTrace {
     pub trace_id: String,
     pub spans: Vec<TraceSpan>,
+    /// Parents that spent much of their time outside their children.
+    #[serde(default)]
+    pub gaps: Vec<SpanGap>,
+    /// Children starting before their parents by more than clock error
+    /// explains.
+    #[serde(default)]
+    pub clock_warnings: Vec<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -77,6 +136,21 @@ pub struct TraceSpan {
     pub signal: Option<i32>,
     pub duration_ms: u64,
     pub pack_path: Option<String>,
+    /// RFC 3339, from the capturing host's clock.
+    #[serde(default)]
+    pub start_time: String,
+    /// The host's NTP correction at the start.
+    #[serde(default)]
+    pub clock_offset_ms: i64,
+    /// Bound on the host clock's error, when the pack recorded it.
+    #[serde(default)]
+    pub clock_error_ms: Option<u64>,
+    /// Added to the host's times to keep children after their parents.
+    #[serde(default)]
+    pub clock_shift_ms: i64,
+    /// Start on the trace's common timeline, ms after its earliest span.
+    #[serde(default)]
+    pub timeline_ms: Option<i64>,
 }
 
 pub fn correlate_packs(pack_paths: &[std::path::PathBuf]) -> anyhow::Result<Vec<DistributedTrace>> {
@@ -92,20 +166,7 @@ pub fn correlate_packs(pack_paths: &[std::path::PathBuf]) -> anyhow::Result<Vec<
 
         let trace_id = meta_val
             .as_ref()
-            .and_then(|v| {
-                v.get("trace_context")?
-                    .get("trace_id")?
-                    .as_str()
-                    .map(|s| s.to_string())
-            })
-            .or_else(|| {
-                meta_val.as_ref().and_then(|v| {
-                    v.get("environment")?
-                        .get(POE_TRACE_ID_ENV)?
-                        .as_str()
-                        .map(|s| s.to_string())
-                })
-            })
+            .and_then(trace_id_in)
             .unwrap_or_else(|| summary.run_id.clone());
 
         let span_id = meta_val
@@ -125,6 +186,10 @@ pub fn correlate_packs(pack_paths: &[std::path::PathBuf]) -> anyhow::Result<Vec<
                 .map(|s| s.to_string())
         });
 
+        let clock = meta_val
+            .as_ref()
+            .and_then(|v| serde_json::from_value::<ClockStatus>(v.get("clock")?.clone()).ok());
+
         let span = TraceSpan {
             span_id,
             parent_span_id: parent_span,
@@ -135,6 +200,11 @@ pub fn correlate_packs(pack_paths: &[std::path::PathBuf]) -> anyhow::Result<Vec<
             signal: summary.signal,
             duration_ms: summary.duration_ms,
             pack_path: Some(path.to_string_lossy().into_owned()),
+            start_time: summary.timestamp.clone(),
+            clock_offset_ms: clock.as_ref().map_or(0, |c| c.offset_us / 1000),
+            clock_error_ms: clock.as_ref().map(ClockStatus::error_ms),
+            clock_shift_ms: 0,
+            timeline_ms: None,
         };
 
         traces.entry(trace_id).or_default().push(span);
@@ -144,11 +214,32 @@ pub fn correlate_packs(pack_paths: &[std::path::PathBuf]) -> anyhow::Result<Vec<
         .into_iter()
         .map(|(trace_id, mut spans)| {
             spans.sort_by_key(|s| s.parent_span_id.is_some());
-            DistributedTrace { trace_id, spans }
+            let clock_warnings = clock::align(&mut spans);
+            let gaps = gaps::find_gaps(&spans);
+            DistributedTrace {
+                trace_id,
+                spans,
+                gaps,
+                clock_warnings,
+            }
         })
         .collect())
 }
 
+/// The trace a pack's run belongs to, from its recorded trace context or
+/// an inherited `POE_TRACE_ID`.
+pub fn trace_id(pack: &crate::pack::reader::PackReader) -> Option<String> {
+    let meta = pack.read_meta("environment.json").ok()?;
+    trace_id_in(&serde_json::from_str(&meta).ok()?)
+}
+
+fn trace_id_in(meta: &serde_json::Value) -> Option<String> {
+    meta.get("trace_context")
+        .and_then(|c| c.get("trace_id")?.as_str())
+        .or_else(|| meta.get("environment")?.get(POE_TRACE_ID_ENV)?.as_str())
+        .map(|s| s.to_string())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -182,6 +273,20 @@ mod tests {
         assert_eq!(env.get("POE_PARENT_SPAN_ID").unwrap(), &ctx.span_id);
     }
 
+    #[test]
+    fn headers_carry_the_span_to_the_consumer() {
+        let producer = TraceContext::new_root();
+        let headers = producer.to_headers();
+        let renamed: Vec<(String, String)> = headers
+            .iter()
+            .map(|(h, v)| (h.to_uppercase().replace('-', "_"), v.clone()))
+            .collect();
+        let env = env_from_headers(renamed.iter().map(|(h, v)| (h.as_str(), v.as_str()))).unwrap();
+        assert_eq!(env["POE_TRACE_ID"], producer.trace_id);
+        assert_eq!(env["POE_PARENT_SPAN_ID"], producer.span_id);
+        assert!(env_from_headers([("poe-trace-id", "abc")]).is_none());
+    }
+
     #[test]
     fn from_env_returns_none_without_vars() {
         std::env::remove_var("POE_TRACE_ID");
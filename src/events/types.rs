@@ -1,3 +1,4 @@
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -9,6 +10,13 @@ pub struct RunInfo {
     pub start_time: chrono::DateTime<chrono::Utc>,
     pub git_sha: Option<String>,
     pub hostname: String,
+    /// Labels from `poe run --tag key=value`.
+    #[serde(default)]
+    pub tags: std::collections::BTreeMap<String, String>,
+    /// This run's span, recorded in the pack and handed to the program so
+    /// captures it starts name it as their parent.
+    #[serde(default)]
+    pub trace_context: Option<crate::distributed::trace_context::TraceContext>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -18,6 +26,10 @@ pub struct ProcessInfo {
     pub argv: Vec<String>,
     pub cwd: String,
     pub start_ts: u64,
+    /// Thread group the task belongs to; threads are reported as tasks of
+    /// their own. Not stored in the pack.
+    #[serde(default)]
+    pub tgid: Option<i32>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -28,6 +40,21 @@ pub struct ProcessExit {
     pub signal: Option<i32>,
 }
 
+/// A task's kernel I/O counters at exit, stored on its process row.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ProcessIo {
+    pub proc_id: i32,
+    pub rchar: u64,
+    pub wchar: u64,
+    pub read_bytes: u64,
+    pub write_bytes: u64,
+    pub cancelled_write_bytes: u64,
+    /// Received and sent on the network namespace's non-loopback
+    /// interfaces while the task ran, by any process in the namespace.
+    pub net_rx_bytes: Option<u64>,
+    pub net_tx_bytes: Option<u64>,
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum EventKind {
     ProcessStart,
@@ -36,6 +63,7 @@ pub enum EventKind {
     SyscallEntry,
     SyscallExit,
     Signal,
+    SignalHandler,
     FileOp,
     NetOp,
     StackSample,
@@ -47,6 +75,18 @@ pub enum EventKind {
     PythonUnhandledException,
     NativeTraceEnter,
     NativeTraceExit,
+    Log,
+    ShellStep,
+    DbQuery,
+    CacheOp,
+    H2Stream,
+    PortHolders,
+    FailureStack,
+    CaptureControl,
+    ProcessExcluded,
+    SchedStats,
+    KernelLog,
+    ForeignAbi,
 }
 
 impl EventKind {
@@ -58,6 +98,7 @@ impl EventKind {
             Self::SyscallEntry => "syscall_entry",
             Self::SyscallExit => "syscall_exit",
             Self::Signal => "signal",
+            Self::SignalHandler => "signal_handler",
             Self::FileOp => "file_op",
             Self::NetOp => "net_op",
             Self::StackSample => "stack_sample",
@@ -69,6 +110,18 @@ impl EventKind {
             Self::PythonUnhandledException => "python_unhandled_exception",
             Self::NativeTraceEnter => "native_trace_enter",
             Self::NativeTraceExit => "native_trace_exit",
+            Self::Log => "log",
+            Self::ShellStep => "shell_step",
+            Self::DbQuery => "db_query",
+            Self::CacheOp => "cache_op",
+            Self::H2Stream => "h2_stream",
+            Self::PortHolders => "port_holders",
+            Self::FailureStack => "failure_stack",
+            Self::CaptureControl => "capture_control",
+            Self::ProcessExcluded => "process_excluded",
+            Self::SchedStats => "sched_stats",
+            Self::KernelLog => "kernel_log",
+            Self::ForeignAbi => "foreign_abi",
         }
     }
 }
@@ -98,6 +151,11 @@ pub enum FileOpKind {
     Readlink,
     Truncate,
     Access,
+    CopyFileRange,
+    Sendfile,
+    Splice,
+    Mmap,
+    Munmap,
 }
 
 impl FileOpKind {
@@ -118,8 +176,22 @@ impl FileOpKind {
             Self::Readlink => "readlink",
             Self::Truncate => "truncate",
             Self::Access => "access",
+            Self::CopyFileRange => "copy_file_range",
+            Self::Sendfile => "sendfile",
+            Self::Splice => "splice",
+            Self::Mmap => "mmap",
+            Self::Munmap => "munmap",
         }
     }
+
+    /// Transfers move bytes between two fds, so they count as both read and written.
+    pub fn is_transfer(&self) -> bool {
+        matches!(self, Self::CopyFileRange | Self::Sendfile | Self::Splice)
+    }
+
+    pub fn is_transfer_str(op: &str) -> bool {
+        matches!(op, "copy_file_range" | "sendfile" | "splice")
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -132,6 +204,53 @@ pub struct FileEvent {
     pub bytes: Option<u64>,
     pub flags: Option<i32>,
     pub result: Option<i64>,
+    /// Time between syscall entry and exit.
+    #[serde(default)]
+    pub duration_ns: Option<u64>,
+}
+
+/// Content hash of a file the traced program wrote, taken when the writing
+/// fd was closed. `hash` is None when the file exceeded the size cap.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WrittenFile {
+    pub ts: u64,
+    pub proc_id: i32,
+    pub path: String,
+    pub size: u64,
+    pub hash: Option<String>,
+}
+
+/// A path's state when the pack was written, recorded for the paths the run
+/// failed on or wrote, so explain can tell a missing file from an empty one
+/// or one owned by someone else.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct FileStat {
+    /// As the run named it.
+    pub path: String,
+    /// `file`, `dir`, `symlink`, `other`, `missing` or `inaccessible`.
+    pub file_type: String,
+    pub size: Option<u64>,
+    /// Permission bits.
+    pub mode: Option<u32>,
+    pub uid: Option<u32>,
+    pub gid: Option<u32>,
+    /// User name for `uid`.
+    pub owner: Option<String>,
+    pub mtime_ns: Option<i64>,
+}
+
+/// Bytes moved by a read or write on a file named with `--watch-file`.
+/// `bytes` is the syscall's full transfer size; `data` may be capped, and
+/// under `--redact` it is whatever lines the fd's redactor has completed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FilePayload {
+    pub ts: u64,
+    pub proc_id: i32,
+    pub op: FileOpKind,
+    pub path: String,
+    pub fd: i32,
+    pub bytes: u64,
+    pub data: Vec<u8>,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
@@ -176,6 +295,11 @@ pub struct NetEvent {
     pub bytes: Option<u64>,
     pub fd: Option<i32>,
     pub result: Option<i64>,
+    #[serde(default)]
+    pub duration_ns: Option<u64>,
+    /// Listening socket an accept was called on; `fd` is the accepted one.
+    #[serde(default)]
+    pub listen_fd: Option<i32>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -197,17 +321,38 @@ pub struct StdioChunk {
 pub enum StdioStream {
     Stdout,
     Stderr,
+    Stdin,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum TraceEvent {
     Process(ProcessInfo),
     ProcessExit(ProcessExit),
+    ProcessIo(ProcessIo),
     File(FileEvent),
     Net(NetEvent),
     Stack(StackSample),
     Stdio(StdioChunk),
     Generic(Event),
+    WrittenFile(WrittenFile),
+    FilePayload(FilePayload),
+}
+
+impl TraceEvent {
+    pub fn proc_id(&self) -> i32 {
+        match self {
+            Self::Process(e) => e.proc_id,
+            Self::ProcessExit(e) => e.proc_id,
+            Self::ProcessIo(e) => e.proc_id,
+            Self::File(e) => e.proc_id,
+            Self::Net(e) => e.proc_id,
+            Self::Stack(e) => e.proc_id,
+            Self::Stdio(e) => e.proc_id,
+            Self::Generic(e) => e.proc_id,
+            Self::WrittenFile(e) => e.proc_id,
+            Self::FilePayload(e) => e.proc_id,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
@@ -217,6 +362,8 @@ pub enum TriggerReason {
     Crash,
     Explicit,
     Always,
+    /// A process picked by `--on-failure-of` failed; holds its pid.
+    ChildFailure(i32),
 }
 
 impl TriggerReason {
@@ -227,6 +374,7 @@ impl TriggerReason {
             Self::Crash => "crash",
             Self::Explicit => "explicit",
             Self::Always => "always",
+            Self::ChildFailure(_) => "child_failure",
         }
     }
 }
@@ -250,6 +398,7 @@ mod tests {
             EventKind::SyscallEntry,
             EventKind::SyscallExit,
             EventKind::Signal,
+            EventKind::SignalHandler,
             EventKind::FileOp,
             EventKind::NetOp,
             EventKind::StackSample,
@@ -261,6 +410,11 @@ mod tests {
             EventKind::PythonUnhandledException,
             EventKind::NativeTraceEnter,
             EventKind::NativeTraceExit,
+            EventKind::Log,
+            EventKind::ShellStep,
+            EventKind::DbQuery,
+            EventKind::CacheOp,
+            EventKind::H2Stream,
         ];
 
         for kind in &kinds {
@@ -275,6 +429,9 @@ mod tests {
         assert_eq!(FileOpKind::Read.as_str(), "read");
         assert_eq!(FileOpKind::Write.as_str(), "write");
         assert_eq!(FileOpKind::Close.as_str(), "close");
+        assert_eq!(FileOpKind::CopyFileRange.as_str(), "copy_file_range");
+        assert!(FileOpKind::Sendfile.is_transfer());
+        assert!(!FileOpKind::Mmap.is_transfer());
     }
 
     #[test]
@@ -290,6 +447,7 @@ mod tests {
         assert_eq!(TriggerReason::Signal.as_str(), "signal");
         assert_eq!(TriggerReason::NonZeroExit.as_str(), "non_zero_exit");
         assert_eq!(TriggerReason::Always.as_str(), "always");
+        assert_eq!(TriggerReason::ChildFailure(42).as_str(), "child_failure");
     }
 
     #[test]
@@ -300,6 +458,7 @@ mod tests {
             argv: vec!["test".into(), "--flag".into()],
             cwd: "/tmp".into(),
             start_ts: 1000,
+            tgid: Some(1234),
         };
         assert_eq!(pi.proc_id, 1234);
         assert_eq!(pi.argv.len(), 2);
@@ -1,39 +1,143 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use anyhow::Result;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
+use crate::capture::failstacks::FailureStack;
+use crate::capture::h2wire;
+use crate::capture::metrics;
+use crate::events::types::{FileOpKind, FileStat};
+use crate::explain::anomaly::HistoryAnomalies;
+use crate::explain::causality;
+use crate::explain::codes::PatternCode;
+use crate::explain::kernellog::{self, KernelLogEntry};
+use crate::explain::patterns::{PatternContext, PatternRegistry};
+use crate::explain::portconflicts::{self, PortConflict};
+use crate::explain::races::{self, FileRace};
+use crate::explain::suggest::{self, Suggestion};
+use crate::explain::zoom::TimelineZoom;
 use crate::hooks::rust as rust_hooks;
+use crate::pack::mounts::{self, MountInfo};
 use crate::pack::reader::PackReader;
-use crate::pack::summary::PackSummary;
+use crate::pack::summary::{CaptureMetrics, PackSummary, TruncationSummary};
+use crate::redact::secrets::SecretFinding;
+use crate::redact::Redactor;
+use crate::trace::buildsteps::{self, BuildTarget};
+use crate::trace::calltree::{self, FunctionTiming};
 use crate::trace::db::*;
+use crate::trace::dbqueries::{self, DbStatement};
+use crate::trace::filetree::{self, DirStats};
+use crate::trace::h2streams::{self, H2Stream};
+use crate::trace::iostats::{self, FdPaths};
+use crate::trace::kernelio::{self, KernelIoReport};
+use crate::trace::pycalls::{self, PyExceptionStack, PyFunctionTiming};
+use crate::trace::sched::{self, SchedSummary};
 use crate::util;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+const MAX_SLOW_OPS: usize = 10;
+const MAX_SLOW_FUNCTIONS: usize = 10;
+const MAX_SHELL_STEPS: usize = 8;
+const MAX_BUILD_TARGETS: usize = 10;
+const MAX_DB_STATEMENTS: usize = 10;
+const MAX_KERNEL_IO_PROCESSES: usize = 5;
+const MAX_FAILED_RPCS: usize = 10;
+/// Below this, tracer overhead dominates the measurement.
+const MIN_SLOW_OP_NS: i64 = 10_000_000;
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct ExplainOutput {
     pub failure: Option<FailureExplanation>,
     pub timeline: TimelineExplanation,
+    /// The timeline zoomed with `--timeline-window` or `--pid`.
+    #[serde(default)]
+    pub timeline_window: Option<TimelineZoom>,
     pub hotspots: Vec<Hotspot>,
     pub file_activity: FileActivitySummary,
+    /// Failed opens of paths another process created later or had deleted.
+    #[serde(default)]
+    pub file_races: Vec<FileRace>,
     pub net_activity: NetActivitySummary,
+    #[serde(default)]
+    pub server_activity: ServerActivitySummary,
+    /// EADDRINUSE binds paired with whatever held the port.
+    #[serde(default)]
+    pub port_conflicts: Vec<PortConflict>,
+    #[serde(default)]
+    pub signals: SignalSummary,
+    #[serde(default)]
+    pub logs: LogSummary,
+    #[serde(default)]
+    pub slow_ops: Vec<SlowOp>,
+    /// Native functions with the most inclusive time, from instrumented builds.
+    #[serde(default)]
+    pub slow_functions: Vec<FunctionTiming>,
     pub process_tree: Vec<ProcessNode>,
     pub error_patterns: Vec<ErrorPattern>,
+    #[serde(default)]
+    pub suggestions: Vec<Suggestion>,
     pub python_exceptions: Vec<PythonExceptionInfo>,
+    /// User-code Python functions with the most total time.
+    #[serde(default)]
+    pub python_slow_functions: Vec<PyFunctionTiming>,
+    /// Open call stack where the unhandled exception was first raised.
+    #[serde(default)]
+    pub python_exception_stack: Option<PyExceptionStack>,
     pub rust_panic: Option<rust_hooks::RustPanicInfo>,
+    /// Last traced bash commands, ending at `shell_failed_step` when there
+    /// is one.
+    #[serde(default)]
+    pub shell_steps: Vec<ShellStepInfo>,
+    #[serde(default)]
+    pub shell_failed_step: Option<ShellStepInfo>,
+    /// Per-recipe activity when the run was a make build.
+    #[serde(default)]
+    pub build: Option<BuildSummary>,
+    /// SQL statements decoded with `--capture-sql`.
+    #[serde(default)]
+    pub database: Option<DbSummary>,
     pub stderr_tail: Option<String>,
     pub stdout_tail: Option<String>,
+    pub truncation: Option<TruncationSummary>,
+    #[serde(default)]
+    pub capture_metrics: Option<CaptureMetrics>,
+    /// Signs poe's overhead perturbed the run.
+    #[serde(default)]
+    pub capture_warnings: Vec<String>,
+    /// Likely credentials found when the pack was written.
+    #[serde(default)]
+    pub secrets: Vec<SecretFinding>,
+    /// How unusual the run is next to earlier ones, with `--history`.
+    #[serde(default)]
+    pub history_anomalies: Option<HistoryAnomalies>,
+    /// Time spent running vs waiting for a CPU, in full mode.
+    #[serde(default)]
+    pub scheduling: Option<SchedSummary>,
+    /// Kernel I/O counters reconciled with the traced syscalls, for the
+    /// processes that moved the most untraced bytes.
+    #[serde(default)]
+    pub kernel_io: Option<KernelIoReport>,
+    /// Kernel log lines from the run: segfaults, OOM kills, I/O errors and
+    /// anything at error level.
+    #[serde(default)]
+    pub kernel_log: Vec<KernelLogEntry>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct FailureExplanation {
     pub kind: String,
     pub primary_location: Option<LocationInfo>,
     pub description: String,
     pub exit_code: Option<i32>,
     pub signal: Option<String>,
+    /// The failing process, when it is not the root (`--on-failure-of`).
+    #[serde(default)]
+    pub primary_pid: Option<i32>,
+    #[serde(default)]
+    pub process: Option<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct LocationInfo {
     pub file: Option<String>,
     pub line: Option<u32>,
@@ -41,7 +145,7 @@ pub struct LocationInfo {
     pub module: Option<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct TimelineExplanation {
     pub merged: Vec<TimelineEntry>,
     pub last_file_ops: Vec<TimelineEntry>,
@@ -49,7 +153,7 @@ pub struct TimelineExplanation {
     pub duration_ms: u64,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct TimelineEntry {
     pub ts_ms: f64,
     pub proc_id: i32,
@@ -57,14 +161,14 @@ pub struct TimelineEntry {
     pub description: String,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct Hotspot {
     pub location: String,
     pub count: u64,
     pub percentage: f64,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct FileActivitySummary {
     pub total_ops: i64,
     pub unique_paths: usize,
@@ -73,9 +177,31 @@ pub struct FileActivitySummary {
     pub total_bytes_written: u64,
     pub failed_opens: Vec<FailedFileOp>,
     pub permission_errors: Vec<FailedFileOp>,
+    /// O_CREAT|O_EXCL opens that found the file already there.
+    #[serde(default)]
+    pub exclusive_creates: Vec<FailedFileOp>,
+    /// Activity per directory, `POE_DIR_DEPTH` components deep (default 3).
+    #[serde(default)]
+    pub directories: Vec<DirStats>,
+    /// State of the failed and written paths when the pack was written.
+    #[serde(default)]
+    pub file_stats: Vec<FileStat>,
+    /// ENOSPC, EDQUOT and EROFS failures; a failed write carries the path
+    /// its fd was opened with.
+    #[serde(default)]
+    pub space_errors: Vec<FailedFileOp>,
+    /// Filesystems the run touched, from the pack's environment.json.
+    #[serde(default)]
+    pub mounts: Vec<MountInfo>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+impl FileActivitySummary {
+    pub fn stat_of(&self, path: &str) -> Option<&FileStat> {
+        self.file_stats.iter().find(|s| s.path == path)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct FailedFileOp {
     pub path: String,
     pub op: String,
@@ -83,33 +209,171 @@ pub struct FailedFileOp {
     pub errno_name: String,
     pub ts_ms: f64,
     pub pid: i32,
+    /// Open flags, e.g. `O_WRONLY|O_CREAT|O_EXCL`.
+    #[serde(default)]
+    pub flags: Option<String>,
+    /// Call stack at the failure, innermost first (full mode only).
+    #[serde(default)]
+    pub stack: Vec<String>,
+}
+
+impl FailedFileOp {
+    /// The op with its open flags, e.g. `open(O_WRONLY|O_CREAT)`.
+    pub fn op_label(&self) -> String {
+        match self.flags {
+            Some(ref flags) => format!("{}({})", self.op, flags),
+            None => self.op.clone(),
+        }
+    }
+
+    /// An ENOENT from a creating open means a directory on the way is
+    /// missing, not the file.
+    pub fn creating(&self) -> bool {
+        self.flags
+            .as_deref()
+            .is_some_and(|f| f.split('|').any(|f| f == "O_CREAT"))
+    }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct NetActivitySummary {
     pub total_ops: i64,
     pub connections: Vec<ConnectionInfo>,
     pub total_bytes_sent: u64,
     pub total_bytes_received: u64,
     pub failed_connections: Vec<FailedConnection>,
+    #[serde(default)]
+    pub bytes_by_proto: Vec<ProtoBytes>,
+    /// HTTP/2 requests decoded with `--capture-http2`.
+    #[serde(default)]
+    pub http2_requests: usize,
+    #[serde(default)]
+    pub http2_failed: usize,
+    /// Requests answered with a gRPC error status or HTTP error, or reset;
+    /// the last few.
+    #[serde(default)]
+    pub failed_rpcs: Vec<H2Stream>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ProtoBytes {
+    pub proto: String,
+    pub sent: u64,
+    pub received: u64,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct ConnectionInfo {
     pub addr: String,
     pub result: String,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct FailedConnection {
     pub addr: String,
     pub errno: i64,
     pub errno_name: String,
     pub ts_ms: f64,
     pub pid: i32,
+    /// Call stack at the failure, innermost first (full mode only).
+    #[serde(default)]
+    pub stack: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct ServerActivitySummary {
+    pub listeners: Vec<ListenerInfo>,
+    pub bind_errors: Vec<FailedConnection>,
+    pub peers: Vec<PeerBytes>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ListenerInfo {
+    pub addr: String,
+    pub pid: i32,
+    pub proto: Option<String>,
+    pub listening: bool,
+    pub accepts: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct PeerBytes {
+    pub peer: String,
+    pub local: String,
+    pub connections: u64,
+    pub sent: u64,
+    pub received: u64,
+}
+
+/// A file or network syscall that took long between entry and exit.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct SlowOp {
+    pub ts_ms: f64,
+    pub pid: i32,
+    pub kind: String,
+    pub op: String,
+    pub target: String,
+    pub duration_ms: f64,
+    pub errno_name: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct SignalSummary {
+    pub deliveries: Vec<SignalDelivery>,
+    pub handlers: Vec<SignalHandlerInfo>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct SignalDelivery {
+    pub ts_ms: f64,
+    pub pid: i32,
+    pub signal: String,
+    pub signo: i32,
+    pub fatal: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct SignalHandlerInfo {
+    pub ts_ms: f64,
+    pub pid: i32,
+    pub signal: String,
+    /// `default`, `ignore` or `handler=0x...`.
+    pub disposition: String,
+    /// `SA_*` flags the handler was installed with, e.g. `SA_RESTART`.
+    #[serde(default)]
+    pub flags: Vec<String>,
+    /// Signals blocked while the handler runs.
+    #[serde(default)]
+    pub mask: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct LogSummary {
+    pub total: usize,
+    pub errors: usize,
+    pub warnings: usize,
+    pub by_logger: Vec<LoggerCounts>,
+    pub first_error: Option<LogLine>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct LoggerCounts {
+    pub logger: String,
+    pub total: usize,
+    pub errors: usize,
+    pub warnings: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct LogLine {
+    pub ts_ms: f64,
+    pub pid: i32,
+    pub level: Option<String>,
+    pub logger: Option<String>,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct ProcessNode {
     pub pid: i32,
     pub parent_pid: Option<i32>,
@@ -117,9 +381,41 @@ pub struct ProcessNode {
     pub exit_code: Option<i32>,
     pub signal: Option<i32>,
     pub duration_ms: Option<f64>,
+    /// Matched `--exclude-children`: only its exit status was recorded.
+    #[serde(default)]
+    pub excluded: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct BuildSummary {
+    pub recipes: usize,
+    pub failed: usize,
+    /// Failed recipes first (innermost before recursive makes), then the
+    /// slowest of the rest.
+    pub targets: Vec<BuildTarget>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct DbSummary {
+    pub statements: usize,
+    pub failed: usize,
+    /// The last statement the server rejected.
+    pub failed_statement: Option<DbStatement>,
+    /// Statements leading up to `failed_statement`, or the last ones sent.
+    pub recent: Vec<DbStatement>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct ShellStepInfo {
+    pub ts_ms: f64,
+    pub pid: i32,
+    pub file: String,
+    pub line: u32,
+    pub func: Option<String>,
+    pub command: String,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct PythonExceptionInfo {
     pub exc_type: String,
     pub exc_msg: String,
@@ -129,7 +425,7 @@ pub struct PythonExceptionInfo {
     pub formatted: Vec<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct PythonTraceFrame {
     pub file: String,
     pub line: u32,
@@ -137,7 +433,7 @@ pub struct PythonTraceFrame {
     pub locals: Option<std::collections::HashMap<String, String>>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct PythonChainEntry {
     #[serde(rename = "type")]
     pub exc_type: String,
@@ -145,28 +441,60 @@ pub struct PythonChainEntry {
     pub cause: Option<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
 pub struct ErrorPattern {
+    /// Stable code of a built-in pattern; None for user-defined rules.
+    #[serde(default)]
+    pub code: Option<PatternCode>,
     pub category: String,
     pub severity: String,
     pub description: String,
     pub count: usize,
     pub examples: Vec<String>,
+    /// Run-relative time of the earliest event supporting the pattern.
+    #[serde(default)]
+    pub first_seen_ms: Option<f64>,
+    /// The earliest pattern, when later ones were linked to it.
+    #[serde(default)]
+    pub root_cause: bool,
+    /// Category of the probable root cause this pattern followed.
+    #[serde(default)]
+    pub caused_by: Option<String>,
 }
 
 pub fn analyze(pack: &PackReader) -> Result<ExplainOutput> {
+    analyze_with(pack, &PatternRegistry::load_default())
+}
+
+pub fn analyze_with(pack: &PackReader, patterns: &PatternRegistry) -> Result<ExplainOutput> {
     let summary = pack.summary();
     let db = pack.db();
 
-    let failure = build_failure_explanation(summary);
+    let mut failure = build_failure_explanation(summary);
     let process_tree = build_process_tree(db)?;
-    let timeline = build_timeline(db, summary.duration_ms)?;
+    // A failed child reports its own status rather than the root's.
+    if let Some(f) = failure.as_mut() {
+        if let Some(node) = process_tree.iter().find(|n| Some(n.pid) == f.primary_pid) {
+            f.exit_code = node.exit_code;
+            f.signal = node.signal.map(|s| util::signal_name(s).to_string());
+            f.process = Some(node.command.clone());
+        }
+    }
+    let mut timeline = build_timeline(db, summary.duration_ms)?;
     let hotspots = build_hotspots(db)?;
-    let file_activity = build_file_activity(db)?;
+    let mut file_activity = build_file_activity(db)?;
+    file_activity.mounts = recorded_mounts(pack);
+    let file_races = races::find_file_races(db)?;
     let net_activity = build_net_activity(db)?;
-
-    let stderr_tail = pack.stderr().ok().and_then(|data| {
-        let s = String::from_utf8_lossy(&data);
+    let server_activity = build_server_activity(db)?;
+    let signals = build_signals(db)?;
+    let port_conflicts = portconflicts::find_port_conflicts(db, &signals)?;
+    let logs = build_logs(db)?;
+    let slow_ops = build_slow_ops(db)?;
+    let mut slow_functions = calltree::compute(db)?.functions;
+    slow_functions.truncate(MAX_SLOW_FUNCTIONS);
+
+    let stderr_tail = pack.stdio_text("stderr").and_then(|s| {
         let lines: Vec<&str> = s.lines().collect();
         let tail: Vec<&str> = lines.iter().rev().take(50).rev().copied().collect();
         if tail.is_empty() {
@@ -176,8 +504,7 @@ pub fn analyze(pack: &PackReader) -> Result<ExplainOutput> {
         }
     });
 
-    let stdout_tail = pack.stdout().ok().and_then(|data| {
-        let s = String::from_utf8_lossy(&data);
+    let stdout_tail = pack.stdio_text("stdout").and_then(|s| {
         let lines: Vec<&str> = s.lines().collect();
         let tail: Vec<&str> = lines.iter().rev().take(20).rev().copied().collect();
         if tail.is_empty() {
@@ -187,39 +514,128 @@ pub fn analyze(pack: &PackReader) -> Result<ExplainOutput> {
         }
     });
 
+    if let Some(ref first) = logs.first_error {
+        let desc = format_log_line(first);
+        if !timeline.merged.iter().any(|e| e.description == desc) {
+            let pos = timeline.merged.partition_point(|e| e.ts_ms <= first.ts_ms);
+            timeline.merged.insert(
+                pos,
+                TimelineEntry {
+                    ts_ms: first.ts_ms,
+                    proc_id: first.pid,
+                    kind: "log".into(),
+                    description: desc,
+                },
+            );
+        }
+    }
+
     let python_exceptions = build_python_exceptions(db);
+    if let (Some(failure), Some(exc)) = (failure.as_mut(), python_exceptions.last()) {
+        failure.primary_location = python_exception_location(exc);
+    }
+    let (python_slow_functions, python_exception_stack) =
+        build_python_calls(db, python_exceptions.last())?;
+
+    let (shell_steps, shell_failed_step) = build_shell_steps(db, failure.is_some())?;
+    if let (Some(failure), Some(step)) = (failure.as_mut(), shell_failed_step.as_ref()) {
+        if failure.primary_location.is_none() {
+            failure.primary_location = Some(LocationInfo {
+                file: Some(step.file.clone()),
+                line: Some(step.line),
+                function: Some(step.func.clone().unwrap_or_else(|| "main".into())),
+                module: None,
+            });
+        }
+    }
 
     let full_stderr = pack
         .stderr()
         .ok()
         .map(|d| String::from_utf8_lossy(&d).into_owned());
+
+    let build = build_summary(db, pack.stdio_text("stderr").as_deref())?;
+    if let (Some(failure), Some(target)) = (
+        failure.as_mut(),
+        build
+            .as_ref()
+            .and_then(|b| b.targets.iter().find(|t| t.makefile.is_some())),
+    ) {
+        if failure.primary_location.is_none() {
+            failure.primary_location = Some(LocationInfo {
+                file: target.makefile.clone(),
+                line: target.line,
+                function: target.target.clone(),
+                module: None,
+            });
+        }
+    }
+    let database = build_db_summary(db)?;
     let rust_panic = full_stderr
         .as_ref()
         .and_then(|s| rust_hooks::parse_rust_panic(s));
 
-    let error_patterns = detect_error_patterns(
-        &failure,
-        &file_activity,
-        &net_activity,
-        &process_tree,
-        &stderr_tail,
-        &full_stderr,
-        &python_exceptions,
-    );
-
-    Ok(ExplainOutput {
+    let mut output = ExplainOutput {
         failure,
         timeline,
+        timeline_window: None,
+        history_anomalies: None,
         hotspots,
         file_activity,
+        file_races,
         net_activity,
+        server_activity,
+        port_conflicts,
+        signals,
+        logs,
+        slow_ops,
+        slow_functions,
         process_tree,
-        error_patterns,
+        error_patterns: Vec::new(),
+        suggestions: Vec::new(),
         python_exceptions,
+        python_slow_functions,
+        python_exception_stack,
         rust_panic,
+        shell_steps,
+        shell_failed_step,
+        build,
+        database,
         stderr_tail,
         stdout_tail,
-    })
+        truncation: summary.truncation.clone(),
+        capture_metrics: summary.capture_metrics.clone(),
+        capture_warnings: summary
+            .capture_metrics
+            .as_ref()
+            .map(|m| metrics::overhead_warnings(m, summary.duration_ms))
+            .unwrap_or_default()
+            .into_iter()
+            .chain(foreign_abi_warnings(db)?)
+            .collect(),
+        secrets: summary.secrets.clone(),
+        scheduling: sched::summarize(&sched::compute(db)?),
+        kernel_io: build_kernel_io(db)?,
+        kernel_log: kernellog::load(db)?,
+    };
+
+    let clean_stdout = pack.stdio_text("stdout");
+    let clean_stderr = pack.stdio_text("stderr");
+    let mut error_patterns = patterns.detect(&PatternContext {
+        output: &output,
+        stdout: clean_stdout.as_deref(),
+        stderr: full_stderr.as_deref(),
+        clean_stderr: clean_stderr.as_deref(),
+    });
+    let streams = [
+        db.query_stdio_chunks("stderr")?,
+        db.query_stdio_chunks("stdout")?,
+    ];
+    causality::order(&mut error_patterns, &output, &streams);
+    output.error_patterns = error_patterns;
+    output.suggestions = suggest::build_suggestions(db, &output)?;
+
+    Ok(output)
 }
 
 fn build_failure_explanation(summary: &PackSummary) -> Option<FailureExplanation> {
@@ -231,11 +647,18 @@ fn build_failure_explanation(summary: &PackSummary) -> Option<FailureExplanation
         description: failure_info.description.clone(),
         exit_code: summary.exit_code,
         signal: summary.signal_name.clone(),
+        primary_pid: failure_info.primary_pid,
+        process: None,
     })
 }
 
 fn build_process_tree(db: &TraceDb) -> Result<Vec<ProcessNode>> {
     let processes = db.query_processes()?;
+    let excluded: HashSet<i32> = db
+        .query_events_by_kind("process_excluded")?
+        .iter()
+        .map(|e| e.proc_id)
+        .collect();
 
     Ok(processes
         .iter()
@@ -259,6 +682,7 @@ fn build_process_tree(db: &TraceDb) -> Result<Vec<ProcessNode>> {
                 exit_code: p.exit_code,
                 signal: p.signal,
                 duration_ms,
+                excluded: excluded.contains(&p.proc_id),
             }
         })
         .collect())
@@ -272,14 +696,8 @@ fn build_timeline(db: &TraceDb, duration_ms: u64) -> Result<TimelineExplanation>
     let mut merged: Vec<TimelineEntry> = Vec::new();
 
     for e in last_events.iter().rev() {
-        let desc = format_event_description(&e.kind, e.detail.as_deref().unwrap_or(""));
-        if !desc.is_empty() {
-            merged.push(TimelineEntry {
-                ts_ms: e.ts as f64 / 1_000_000.0,
-                proc_id: e.proc_id,
-                kind: "event".into(),
-                description: desc,
-            });
+        if let Some(entry) = event_entry(e) {
+            merged.push(entry);
         }
     }
 
@@ -288,61 +706,12 @@ fn build_timeline(db: &TraceDb, duration_ms: u64) -> Result<TimelineExplanation>
         if is_noise_path(f.path.as_deref()) {
             continue;
         }
-        let result_str = match f.result {
-            Some(r) if r < 0 => format!(" err={}", errno_name(-r)),
-            Some(r) => format!(" -> {}", r),
-            None => String::new(),
-        };
-        let bytes_str = f
-            .bytes
-            .map(|b| format!(" ({} bytes)", b))
-            .unwrap_or_default();
-        let desc = format!(
-            "{}{}{}{}",
-            f.op,
-            f.path
-                .as_ref()
-                .map(|p| format!(" {}", p))
-                .unwrap_or_default(),
-            bytes_str,
-            result_str,
-        );
-        merged.push(TimelineEntry {
-            ts_ms: f.ts as f64 / 1_000_000.0,
-            proc_id: f.proc_id,
-            kind: "file".into(),
-            description: desc,
-        });
+        merged.push(file_entry(f));
     }
 
     let net_tail: Vec<&NetQueryResult> = net_events.iter().rev().take(20).collect();
     for n in net_tail.iter().rev() {
-        let result_str = match n.result {
-            Some(r) if r < 0 && r != -115 => format!(" err={}", errno_name(-r)),
-            Some(-115) => " (in progress)".into(),
-            Some(r) => format!(" -> {}", r),
-            None => String::new(),
-        };
-        let bytes_str = n
-            .bytes
-            .map(|b| format!(" ({} bytes)", b))
-            .unwrap_or_default();
-        let desc = format!(
-            "{}{}{}{}",
-            n.op,
-            n.dst
-                .as_ref()
-                .map(|d| format!(" {}", d))
-                .unwrap_or_default(),
-            bytes_str,
-            result_str,
-        );
-        merged.push(TimelineEntry {
-            ts_ms: n.ts as f64 / 1_000_000.0,
-            proc_id: n.proc_id,
-            kind: "net".into(),
-            description: desc,
-        });
+        merged.push(net_entry(n));
     }
 
     merged.sort_by(|a, b| {
@@ -358,32 +727,7 @@ fn build_timeline(db: &TraceDb, duration_ms: u64) -> Result<TimelineExplanation>
         .take(20)
         .rev()
         .filter(|f| !is_noise_path(f.path.as_deref()))
-        .map(|f| {
-            let result_str = match f.result {
-                Some(r) if r < 0 => format!(" err={}", errno_name(-r)),
-                Some(r) => format!(" -> {}", r),
-                None => String::new(),
-            };
-            let bytes_str = f
-                .bytes
-                .map(|b| format!(" ({} bytes)", b))
-                .unwrap_or_default();
-            TimelineEntry {
-                ts_ms: f.ts as f64 / 1_000_000.0,
-                proc_id: f.proc_id,
-                kind: "file".into(),
-                description: format!(
-                    "{}{}{}{}",
-                    f.op,
-                    f.path
-                        .as_ref()
-                        .map(|p| format!(" {}", p))
-                        .unwrap_or_default(),
-                    bytes_str,
-                    result_str,
-                ),
-            }
-        })
+        .map(file_entry)
         .collect();
 
     let net_entries: Vec<TimelineEntry> = net_events
@@ -391,33 +735,7 @@ fn build_timeline(db: &TraceDb, duration_ms: u64) -> Result<TimelineExplanation>
         .rev()
         .take(20)
         .rev()
-        .map(|n| {
-            let result_str = match n.result {
-                Some(r) if r < 0 && r != -115 => format!(" err={}", errno_name(-r)),
-                Some(-115) => " (in progress)".into(),
-                Some(r) => format!(" -> {}", r),
-                None => String::new(),
-            };
-            let bytes_str = n
-                .bytes
-                .map(|b| format!(" ({} bytes)", b))
-                .unwrap_or_default();
-            TimelineEntry {
-                ts_ms: n.ts as f64 / 1_000_000.0,
-                proc_id: n.proc_id,
-                kind: "net".into(),
-                description: format!(
-                    "{}{}{}{}",
-                    n.op,
-                    n.dst
-                        .as_ref()
-                        .map(|d| format!(" {}", d))
-                        .unwrap_or_default(),
-                    bytes_str,
-                    result_str,
-                ),
-            }
-        })
+        .map(net_entry)
         .collect();
 
     Ok(TimelineExplanation {
@@ -428,6 +746,72 @@ fn build_timeline(db: &TraceDb, duration_ms: u64) -> Result<TimelineExplanation>
     })
 }
 
+/// None for event kinds the timeline doesn't show.
+pub(crate) fn event_entry(e: &EventQueryResult) -> Option<TimelineEntry> {
+    let desc = format_event_description(&e.kind, e.detail.as_deref().unwrap_or(""));
+    (!desc.is_empty()).then(|| TimelineEntry {
+        ts_ms: e.ts as f64 / 1_000_000.0,
+        proc_id: e.proc_id,
+        kind: "event".into(),
+        description: desc,
+    })
+}
+
+pub(crate) fn file_entry(f: &FileQueryResult) -> TimelineEntry {
+    let result_str = match f.result {
+        Some(r) if r < 0 => format!(" err={}", errno_name(-r)),
+        Some(r) => format!(" -> {}", r),
+        None => String::new(),
+    };
+    let bytes_str = f
+        .bytes
+        .map(|b| format!(" ({} bytes)", b))
+        .unwrap_or_default();
+    TimelineEntry {
+        ts_ms: f.ts as f64 / 1_000_000.0,
+        proc_id: f.proc_id,
+        kind: "file".into(),
+        description: format!(
+            "{}{}{}{}",
+            f.op,
+            f.path
+                .as_ref()
+                .map(|p| format!(" {}", p))
+                .unwrap_or_default(),
+            bytes_str,
+            result_str,
+        ),
+    }
+}
+
+pub(crate) fn net_entry(n: &NetQueryResult) -> TimelineEntry {
+    let result_str = match n.result {
+        Some(r) if r < 0 && r != -115 => format!(" err={}", errno_name(-r)),
+        Some(-115) => " (in progress)".into(),
+        Some(r) => format!(" -> {}", r),
+        None => String::new(),
+    };
+    let bytes_str = n
+        .bytes
+        .map(|b| format!(" ({} bytes)", b))
+        .unwrap_or_default();
+    TimelineEntry {
+        ts_ms: n.ts as f64 / 1_000_000.0,
+        proc_id: n.proc_id,
+        kind: "net".into(),
+        description: format!(
+            "{}{}{}{}",
+            n.op,
+            n.dst
+                .as_ref()
+                .map(|d| format!(" {}", d))
+                .unwrap_or_default(),
+            bytes_str,
+            result_str,
+        ),
+    }
+}
+
 fn build_hotspots(db: &TraceDb) -> Result<Vec<Hotspot>> {
     let stacks = db.query_stacks()?;
 
@@ -462,22 +846,55 @@ fn build_hotspots(db: &TraceDb) -> Result<Vec<Hotspot>> {
         })
         .collect();
 
-    hotspots.sort_by(|a, b| b.count.cmp(&a.count));
+    hotspots.sort_by_key(|h| std::cmp::Reverse(h.count));
     hotspots.truncate(20);
 
     Ok(hotspots)
 }
 
+/// Stacks recorded at failing syscalls in full mode, by (pid, ts) of the
+/// failed event.
+fn failure_stacks(db: &TraceDb) -> Result<HashMap<(i32, i64), Vec<String>>> {
+    Ok(db
+        .query_events_by_kind("failure_stack")?
+        .into_iter()
+        .filter_map(|e| {
+            let stack: FailureStack = serde_json::from_str(e.detail.as_deref()?).ok()?;
+            Some(((e.proc_id, e.ts), stack.frames))
+        })
+        .collect())
+}
+
+fn recorded_mounts(pack: &PackReader) -> Vec<MountInfo> {
+    let Ok(meta) = pack.read_meta("environment.json") else {
+        return Vec::new();
+    };
+    serde_json::from_str::<serde_json::Value>(&meta)
+        .ok()
+        .and_then(|meta| serde_json::from_value(meta.get("mounts")?.clone()).ok())
+        .unwrap_or_default()
+}
+
 fn build_file_activity(db: &TraceDb) -> Result<FileActivitySummary> {
     let events = db.query_file_events()?;
+    let stacks = failure_stacks(db)?;
 
     let mut path_counts: HashMap<String, u64> = HashMap::new();
     let mut total_read = 0u64;
     let mut total_written = 0u64;
     let mut failed_opens = Vec::new();
     let mut permission_errors = Vec::new();
+    let mut exclusive_creates = Vec::new();
+    let mut space_errors = Vec::new();
+    let mut fds = FdPaths::new(&db.query_processes()?);
 
     for ev in &events {
+        let written = match (ev.op.as_str(), ev.fd) {
+            ("write", Some(fd)) => fds.resolve(ev.proc_id, fd),
+            _ => None,
+        };
+        fds.observe(ev);
+
         if let Some(path) = &ev.path {
             if !is_noise_path(Some(path.as_str())) {
                 *path_counts.entry(path.clone()).or_insert(0) += 1;
@@ -489,46 +906,74 @@ fn build_file_activity(db: &TraceDb) -> Result<FileActivitySummary> {
             match ev.op.as_str() {
                 "read" => total_read += bytes,
                 "write" => total_written += bytes,
+                op if FileOpKind::is_transfer_str(op) => {
+                    total_read += bytes;
+                    total_written += bytes;
+                }
                 _ => {}
             }
         }
 
-        if let (Some(result), Some(path)) = (ev.result, &ev.path) {
+        if let (Some(result), Some(path)) = (ev.result, ev.path.as_ref().or(written.as_ref())) {
             if is_noise_path(Some(path.as_str())) {
                 continue;
             }
 
             let neg = -result;
+            let failed = || FailedFileOp {
+                path: path.clone(),
+                op: ev.op.clone(),
+                errno: neg,
+                errno_name: errno_name(neg),
+                ts_ms: ev.ts as f64 / 1_000_000.0,
+                pid: ev.proc_id,
+                flags: ev
+                    .flags
+                    .filter(|_| ev.op == "open")
+                    .map(crate::util::open_flags),
+                stack: stacks
+                    .get(&(ev.proc_id, ev.ts))
+                    .cloned()
+                    .unwrap_or_default(),
+            };
             if neg == libc::ENOENT as i64 && (ev.op == "open" || ev.op == "stat") {
-                failed_opens.push(FailedFileOp {
-                    path: path.clone(),
-                    op: ev.op.clone(),
-                    errno: neg,
-                    errno_name: errno_name(neg),
-                    ts_ms: ev.ts as f64 / 1_000_000.0,
-                    pid: ev.proc_id,
-                });
+                failed_opens.push(failed());
             } else if neg == libc::EACCES as i64 || neg == libc::EPERM as i64 {
-                permission_errors.push(FailedFileOp {
-                    path: path.clone(),
-                    op: ev.op.clone(),
-                    errno: neg,
-                    errno_name: errno_name(neg),
-                    ts_ms: ev.ts as f64 / 1_000_000.0,
-                    pid: ev.proc_id,
-                });
+                permission_errors.push(failed());
+            } else if neg == libc::EEXIST as i64
+                && ev.op == "open"
+                && ev.flags.is_some_and(|f| {
+                    f & (libc::O_CREAT | libc::O_EXCL) == libc::O_CREAT | libc::O_EXCL
+                })
+            {
+                exclusive_creates.push(failed());
+            } else if [libc::ENOSPC, libc::EDQUOT, libc::EROFS].contains(&(neg as i32)) {
+                space_errors.push(failed());
             }
         }
     }
 
     failed_opens.dedup_by(|a, b| a.path == b.path);
+    space_errors.dedup_by(|a, b| a.path == b.path && a.errno == b.errno);
     permission_errors.dedup_by(|a, b| a.path == b.path);
+    exclusive_creates.dedup_by(|a, b| a.path == b.path);
 
     let unique_paths = path_counts.len();
     let mut most_accessed: Vec<(String, u64)> = path_counts.into_iter().collect();
-    most_accessed.sort_by(|a, b| b.1.cmp(&a.1));
+    most_accessed.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
     most_accessed.truncate(10);
 
+    let depth = std::env::var("POE_DIR_DEPTH")
+        .ok()
+        .and_then(|d| d.parse().ok())
+        .unwrap_or(filetree::DEFAULT_DEPTH);
+    let io = iostats::compute(db)?;
+    let mut directories = filetree::rollup(
+        io.paths.iter().filter(|p| !is_noise_path(Some(&p.path))),
+        depth,
+    );
+    directories.truncate(10);
+
     Ok(FileActivitySummary {
         total_ops: events.len() as i64,
         unique_paths,
@@ -537,16 +982,23 @@ fn build_file_activity(db: &TraceDb) -> Result<FileActivitySummary> {
         total_bytes_written: total_written,
         failed_opens,
         permission_errors,
+        exclusive_creates,
+        space_errors,
+        directories,
+        file_stats: db.query_file_stats()?,
+        mounts: Vec::new(),
     })
 }
 
 fn build_net_activity(db: &TraceDb) -> Result<NetActivitySummary> {
     let events = db.query_net_events()?;
+    let stacks = failure_stacks(db)?;
 
     let mut connections = Vec::new();
     let mut failed_connections = Vec::new();
     let mut total_sent = 0u64;
     let mut total_received = 0u64;
+    let mut proto_bytes: HashMap<String, (u64, u64)> = HashMap::new();
 
     for ev in &events {
         match ev.op.as_str() {
@@ -572,6 +1024,10 @@ fn build_net_activity(db: &TraceDb) -> Result<NetActivitySummary> {
                             errno_name: errno_name(-result),
                             ts_ms: ev.ts as f64 / 1_000_000.0,
                             pid: ev.proc_id,
+                            stack: stacks
+                                .get(&(ev.proc_id, ev.ts))
+                                .cloned()
+                                .unwrap_or_default(),
                         });
                     }
                 }
@@ -579,11 +1035,15 @@ fn build_net_activity(db: &TraceDb) -> Result<NetActivitySummary> {
             "send" | "sendto" | "sendmsg" => {
                 if let Some(bytes) = ev.bytes {
                     total_sent += bytes as u64;
+                    let proto = ev.proto.clone().unwrap_or_else(|| "unknown".into());
+                    proto_bytes.entry(proto).or_insert((0, 0)).0 += bytes as u64;
                 }
             }
             "recv" | "recvfrom" | "recvmsg" => {
                 if let Some(bytes) = ev.bytes {
                     total_received += bytes as u64;
+                    let proto = ev.proto.clone().unwrap_or_else(|| "unknown".into());
+                    proto_bytes.entry(proto).or_insert((0, 0)).1 += bytes as u64;
                 }
             }
             _ => {}
@@ -592,16 +1052,427 @@ fn build_net_activity(db: &TraceDb) -> Result<NetActivitySummary> {
 
     connections.dedup_by(|a, b| a.addr == b.addr);
 
+    let mut bytes_by_proto: Vec<ProtoBytes> = proto_bytes
+        .into_iter()
+        .map(|(proto, (sent, received))| ProtoBytes {
+            proto,
+            sent,
+            received,
+        })
+        .collect();
+    bytes_by_proto.sort_by_key(|p| std::cmp::Reverse(p.sent + p.received));
+
+    let streams = h2streams::compute(db)?;
+    // Paths can carry tokens in their query string.
+    let redactor = Redactor::new();
+    let mut failed_rpcs: Vec<H2Stream> = streams.iter().filter(|s| s.failed()).cloned().collect();
+    failed_rpcs.drain(..failed_rpcs.len().saturating_sub(MAX_FAILED_RPCS));
+    for rpc in &mut failed_rpcs {
+        rpc.path = rpc.path.as_deref().map(|p| redact_query(&redactor, p));
+    }
+
     Ok(NetActivitySummary {
         total_ops: events.len() as i64,
         connections,
         total_bytes_sent: total_sent,
         total_bytes_received: total_received,
         failed_connections,
+        bytes_by_proto,
+        http2_requests: streams.len(),
+        http2_failed: streams.iter().filter(|s| s.failed()).count(),
+        failed_rpcs,
+    })
+}
+
+/// Masks query parameters whose names look like credentials.
+fn redact_query(redactor: &Redactor, path: &str) -> String {
+    let Some((base, query)) = path.split_once('?') else {
+        return path.to_string();
+    };
+    let params: Vec<String> = query
+        .split('&')
+        .map(|param| match param.split_once('=') {
+            Some((key, _)) if redactor.should_redact_env_key(key) => {
+                format!("{}=[REDACTED]", key)
+            }
+            _ => param.to_string(),
+        })
+        .collect();
+    format!("{}?{}", base, params.join("&"))
+}
+
+fn build_server_activity(db: &TraceDb) -> Result<ServerActivitySummary> {
+    let events = db.query_net_events()?;
+
+    let mut listeners: Vec<ListenerInfo> = Vec::new();
+    let mut bind_errors = Vec::new();
+    // (pid, fd) -> index into listeners
+    let mut bound: HashMap<(i32, i32), usize> = HashMap::new();
+    // (pid, fd) -> index into peers
+    let mut accepted: HashMap<(i32, i32), usize> = HashMap::new();
+    let mut peers: Vec<PeerBytes> = Vec::new();
+
+    for ev in &events {
+        let Some(fd) = ev.fd else {
+            continue;
+        };
+        let result = ev.result.unwrap_or(0);
+
+        match ev.op.as_str() {
+            "bind" => {
+                let addr = ev.dst.clone().unwrap_or_else(|| "?".into());
+                if result < 0 {
+                    bind_errors.push(FailedConnection {
+                        addr,
+                        errno: -result,
+                        errno_name: errno_name(-result),
+                        ts_ms: ev.ts as f64 / 1_000_000.0,
+                        pid: ev.proc_id,
+                        stack: Vec::new(),
+                    });
+                    continue;
+                }
+                if is_noise_addr(&addr) {
+                    continue;
+                }
+                bound.insert((ev.proc_id, fd), listeners.len());
+                listeners.push(ListenerInfo {
+                    addr,
+                    pid: ev.proc_id,
+                    proto: ev.proto.clone(),
+                    listening: false,
+                    accepts: 0,
+                });
+            }
+            "listen" if result >= 0 => {
+                let idx = *bound.entry((ev.proc_id, fd)).or_insert_with(|| {
+                    listeners.push(ListenerInfo {
+                        addr: format!("fd {} (unbound)", fd),
+                        pid: ev.proc_id,
+                        proto: ev.proto.clone(),
+                        listening: false,
+                        accepts: 0,
+                    });
+                    listeners.len() - 1
+                });
+                listeners[idx].listening = true;
+            }
+            "accept" if result >= 0 => {
+                // Packs from before listen_fd was recorded can't name the listener.
+                let listener = ev.listen_fd.map(|lfd| (lfd, bound.get(&(ev.proc_id, lfd))));
+                let local = match listener {
+                    Some((_, Some(&idx))) => {
+                        listeners[idx].accepts += 1;
+                        listeners[idx].addr.clone()
+                    }
+                    Some((lfd, None)) => format!("fd {}", lfd),
+                    None => "unknown".into(),
+                };
+                let peer = ev.src.clone().unwrap_or_else(|| "unknown".into());
+                let idx = match peers
+                    .iter()
+                    .position(|p| p.peer == peer && p.local == local)
+                {
+                    Some(idx) => idx,
+                    None => {
+                        peers.push(PeerBytes {
+                            peer,
+                            local,
+                            connections: 0,
+                            sent: 0,
+                            received: 0,
+                        });
+                        peers.len() - 1
+                    }
+                };
+                peers[idx].connections += 1;
+                accepted.insert((ev.proc_id, fd), idx);
+            }
+            "send" | "recv" => {
+                if let (Some(&idx), Some(bytes)) = (accepted.get(&(ev.proc_id, fd)), ev.bytes) {
+                    if ev.op == "send" {
+                        peers[idx].sent += bytes as u64;
+                    } else {
+                        peers[idx].received += bytes as u64;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // Stream sockets only count as servers once listened on; bound UDP
+    // sockets are kept since datagram servers never call listen().
+    listeners.retain(|l| l.listening || l.accepts > 0 || l.proto.as_deref() == Some("udp"));
+    peers.sort_by_key(|p| std::cmp::Reverse(p.sent + p.received));
+
+    Ok(ServerActivitySummary {
+        listeners,
+        bind_errors,
+        peers,
+    })
+}
+
+const SUPERVISOR_KILL_WINDOW_MS: f64 = 60_000.0;
+const SIGPIPE_STORM_THRESHOLD: usize = 5;
+
+/// Slowest file and network syscalls. File I/O only counts when its fd
+/// resolves to a path, which leaves out pipes and terminals whose reads
+/// block by design; accept is skipped for the same reason.
+fn build_slow_ops(db: &TraceDb) -> Result<Vec<SlowOp>> {
+    let procs = db.query_processes()?;
+    let mut fds = FdPaths::new(&procs);
+    let mut slow = Vec::new();
+
+    db.each_file_event(Page::default(), |ev| {
+        let target = match (ev.op.as_str(), ev.fd) {
+            ("read" | "write" | "close", Some(fd)) => fds.resolve(ev.proc_id, fd),
+            _ => ev.path.clone(),
+        };
+        fds.observe(&ev);
+        let (Some(duration), Some(target)) = (ev.duration_ns, target) else {
+            return Ok(());
+        };
+        if duration >= MIN_SLOW_OP_NS
+            && !target.is_empty()
+            && !target.starts_with("/dev/")
+            && !is_noise_path(Some(&target))
+        {
+            slow.push(SlowOp {
+                ts_ms: ev.ts as f64 / 1_000_000.0,
+                pid: ev.proc_id,
+                kind: "file".into(),
+                op: ev.op,
+                target,
+                duration_ms: duration as f64 / 1_000_000.0,
+                errno_name: ev.result.filter(|r| *r < 0).map(|r| errno_name(-r)),
+            });
+        }
+        Ok(())
+    })?;
+
+    for ev in db.query_net_events()? {
+        let Some(duration) = ev.duration_ns else {
+            continue;
+        };
+        if duration < MIN_SLOW_OP_NS || ev.op == "accept" {
+            continue;
+        }
+        slow.push(SlowOp {
+            ts_ms: ev.ts as f64 / 1_000_000.0,
+            pid: ev.proc_id,
+            kind: "net".into(),
+            target: ev
+                .dst
+                .clone()
+                .unwrap_or_else(|| format!("fd {}", ev.fd.unwrap_or(-1))),
+            op: ev.op,
+            duration_ms: duration as f64 / 1_000_000.0,
+            errno_name: ev.result.filter(|r| *r < 0).map(|r| errno_name(-r)),
+        });
+    }
+
+    slow.sort_by(|a, b| b.duration_ms.total_cmp(&a.duration_ms));
+    slow.truncate(MAX_SLOW_OPS);
+    Ok(slow)
+}
+
+fn build_logs(db: &TraceDb) -> Result<LogSummary> {
+    let mut summary = LogSummary::default();
+    let mut by_logger: HashMap<String, LoggerCounts> = HashMap::new();
+
+    for ev in db.query_events_by_kind("log")? {
+        let Some(line) = parse_log_event(&ev) else {
+            continue;
+        };
+        let level = line.level.as_deref();
+        let is_error = crate::trace::logs::is_error_level(level);
+        let is_warning = level == Some("warn");
+
+        summary.total += 1;
+        let logger = line.logger.clone().unwrap_or_else(|| "(root)".into());
+        let counts = by_logger.entry(logger.clone()).or_insert(LoggerCounts {
+            logger,
+            total: 0,
+            errors: 0,
+            warnings: 0,
+        });
+        counts.total += 1;
+        if is_error {
+            summary.errors += 1;
+            counts.errors += 1;
+            if summary.first_error.is_none() {
+                summary.first_error = Some(line);
+            }
+        } else if is_warning {
+            summary.warnings += 1;
+            counts.warnings += 1;
+        }
+    }
+
+    summary.by_logger = by_logger.into_values().collect();
+    summary.by_logger.sort_by(|a, b| {
+        (b.errors, b.warnings, b.total)
+            .cmp(&(a.errors, a.warnings, a.total))
+            .then_with(|| a.logger.cmp(&b.logger))
+    });
+    Ok(summary)
+}
+
+fn parse_log_event(ev: &EventQueryResult) -> Option<LogLine> {
+    let v: serde_json::Value = serde_json::from_str(ev.detail.as_deref()?).ok()?;
+    let text = |key: &str| v.get(key).and_then(|s| s.as_str()).map(String::from);
+    Some(LogLine {
+        ts_ms: ev.ts as f64 / 1_000_000.0,
+        pid: ev.proc_id,
+        level: text("level"),
+        logger: text("logger"),
+        message: text("message").unwrap_or_default(),
+    })
+}
+
+fn format_log_line(line: &LogLine) -> String {
+    let level = line.level.as_deref().unwrap_or("log").to_uppercase();
+    match line.logger {
+        Some(ref logger) => format!("[{} {}] {}", level, logger, line.message),
+        None => format!("[{}] {}", level, line.message),
+    }
+}
+
+fn build_signals(db: &TraceDb) -> Result<SignalSummary> {
+    let mut deliveries = Vec::new();
+    for ev in db.query_events_by_kind("signal")? {
+        let detail = ev.detail.as_deref().unwrap_or("");
+        let fatal = detail.starts_with("killed by");
+        let Some(signo) = parse_signo(detail) else {
+            continue;
+        };
+        if signo == libc::SIGCHLD && !fatal {
+            continue;
+        }
+        deliveries.push(SignalDelivery {
+            ts_ms: ev.ts as f64 / 1_000_000.0,
+            pid: ev.proc_id,
+            signal: util::signal_name(signo).to_string(),
+            signo,
+            fatal,
+        });
+    }
+
+    let handlers = db
+        .query_events_by_kind("signal_handler")?
+        .iter()
+        .filter_map(|ev| parse_signal_handler(ev.ts, ev.proc_id, ev.detail.as_deref()?))
+        .collect();
+
+    Ok(SignalSummary {
+        deliveries,
+        handlers,
     })
 }
 
-fn detect_error_patterns(
+/// Reads details like "SIGINT (2) handler=0x4011d6 flags=SA_RESTART
+/// mask=SIGTERM"; traces from before flags were recorded end at the
+/// disposition.
+fn parse_signal_handler(ts: i64, pid: i32, detail: &str) -> Option<SignalHandlerInfo> {
+    let signo = parse_signo(detail)?;
+    let mut fields = detail[detail.find(')')? + 1..].split_whitespace();
+    let mut handler = SignalHandlerInfo {
+        ts_ms: ts as f64 / 1_000_000.0,
+        pid,
+        signal: util::signal_name(signo).to_string(),
+        disposition: fields.next()?.to_string(),
+        flags: Vec::new(),
+        mask: Vec::new(),
+    };
+    let list = |v: &str| v.split('|').map(String::from).collect();
+    for field in fields {
+        match field.split_once('=') {
+            Some(("flags", v)) => handler.flags = list(v),
+            Some(("mask", v)) => handler.mask = list(v),
+            _ => {}
+        }
+    }
+    Some(handler)
+}
+
+/// Extracts the signal number from details like "received SIGTERM (15)".
+fn parse_signo(detail: &str) -> Option<i32> {
+    let start = detail.find('(')? + 1;
+    let end = start + detail[start..].find(')')?;
+    detail[start..end].parse().ok()
+}
+
+pub(crate) fn detect_signal_patterns(signals: &SignalSummary) -> Vec<ErrorPattern> {
+    let mut patterns = Vec::new();
+
+    let mut supervisor_kills = Vec::new();
+    for kill in signals
+        .deliveries
+        .iter()
+        .filter(|d| d.signo == libc::SIGKILL && d.fatal)
+    {
+        let term = signals.deliveries.iter().find(|d| {
+            d.pid == kill.pid
+                && d.signo == libc::SIGTERM
+                && d.ts_ms <= kill.ts_ms
+                && kill.ts_ms - d.ts_ms <= SUPERVISOR_KILL_WINDOW_MS
+        });
+        if let Some(term) = term {
+            supervisor_kills.push(format!(
+                "pid {} got SIGTERM, then SIGKILL {:.0}ms later",
+                kill.pid,
+                kill.ts_ms - term.ts_ms
+            ));
+        }
+    }
+    if !supervisor_kills.is_empty() {
+        patterns.push(ErrorPattern {
+            code: Some(PatternCode::SignalSupervisorKill),
+            category: "supervisor_kill".into(),
+            severity: "error".into(),
+            description: format!(
+                "{} process(es) ignored SIGTERM and were SIGKILLed (timeout or supervisor escalation)",
+                supervisor_kills.len()
+            ),
+            count: supervisor_kills.len(),
+            examples: supervisor_kills.into_iter().take(5).collect(),
+            ..Default::default()
+        });
+    }
+
+    let sigpipes: Vec<&SignalDelivery> = signals
+        .deliveries
+        .iter()
+        .filter(|d| d.signo == libc::SIGPIPE)
+        .collect();
+    if sigpipes.len() >= SIGPIPE_STORM_THRESHOLD {
+        let mut pids: Vec<i32> = sigpipes.iter().map(|d| d.pid).collect();
+        pids.sort_unstable();
+        pids.dedup();
+        patterns.push(ErrorPattern {
+            code: Some(PatternCode::SignalSigpipeStorm),
+            category: "sigpipe_storm".into(),
+            severity: "warning".into(),
+            description: format!(
+                "{} SIGPIPE deliveries across {} process(es) -- writers outliving their readers",
+                sigpipes.len(),
+                pids.len()
+            ),
+            count: sigpipes.len(),
+            examples: pids
+                .iter()
+                .take(5)
+                .map(|pid| format!("pid {}", pid))
+                .collect(),
+            ..Default::default()
+        });
+    }
+
+    patterns
+}
+
+pub(crate) fn detect_error_patterns(
     failure: &Option<FailureExplanation>,
     file_activity: &FileActivitySummary,
     net_activity: &NetActivitySummary,
@@ -615,35 +1486,43 @@ fn detect_error_patterns(
     if let Some(f) = failure {
         if f.signal.as_deref() == Some("SIGSEGV") {
             patterns.push(ErrorPattern {
+                code: Some(PatternCode::CrashSegv),
                 category: "crash".into(),
                 severity: "critical".into(),
                 description: "Segmentation fault - the process accessed invalid memory".into(),
                 count: 1,
                 examples: vec!["Process received SIGSEGV".into()],
+                ..Default::default()
             });
         } else if f.signal.as_deref() == Some("SIGABRT") {
             patterns.push(ErrorPattern {
+                code: Some(PatternCode::CrashAbort),
                 category: "crash".into(),
                 severity: "critical".into(),
                 description: "Process aborted - likely an assertion failure or double-free".into(),
                 count: 1,
                 examples: vec!["Process received SIGABRT".into()],
+                ..Default::default()
             });
         } else if f.signal.as_deref() == Some("SIGBUS") {
             patterns.push(ErrorPattern {
+                code: Some(PatternCode::CrashBus),
                 category: "crash".into(),
                 severity: "critical".into(),
                 description: "Bus error - misaligned memory access or mmap beyond file".into(),
                 count: 1,
                 examples: vec!["Process received SIGBUS".into()],
+                ..Default::default()
             });
         } else if f.signal.as_deref() == Some("SIGFPE") {
             patterns.push(ErrorPattern {
+                code: Some(PatternCode::CrashFpe),
                 category: "crash".into(),
                 severity: "critical".into(),
                 description: "Floating point exception - likely division by zero".into(),
                 count: 1,
                 examples: vec!["Process received SIGFPE".into()],
+                ..Default::default()
             });
         }
     }
@@ -653,9 +1532,10 @@ fn detect_error_patterns(
             .permission_errors
             .iter()
             .take(5)
-            .map(|e| format!("{} {} -> {}", e.op, e.path, e.errno_name))
+            .map(|e| format!("{} {} -> {}", e.op_label(), e.path, e.errno_name))
             .collect();
         patterns.push(ErrorPattern {
+            code: Some(PatternCode::FilePermission),
             category: "permission".into(),
             severity: "error".into(),
             description: format!(
@@ -664,21 +1544,31 @@ fn detect_error_patterns(
             ),
             count: file_activity.permission_errors.len(),
             examples,
+            ..Default::default()
         });
     }
 
-    let significant_missing: Vec<&FailedFileOp> = file_activity
+    let mut significant_missing: Vec<&FailedFileOp> = file_activity
         .failed_opens
         .iter()
         .filter(|f| is_significant_missing_file(&f.path))
         .collect();
+    // Probing for optional files is normal; failing to create one is not.
+    significant_missing.sort_by_key(|f| !f.creating());
     if !significant_missing.is_empty() {
         let examples: Vec<String> = significant_missing
             .iter()
             .take(5)
-            .map(|e| format!("{} {}", e.op, e.path))
+            .map(|e| {
+                if e.creating() {
+                    format!("{} {} (parent directory missing)", e.op_label(), e.path)
+                } else {
+                    format!("{} {}", e.op, e.path)
+                }
+            })
             .collect();
         patterns.push(ErrorPattern {
+            code: Some(PatternCode::FileMissing),
             category: "missing_file".into(),
             severity: "warning".into(),
             description: format!(
@@ -687,6 +1577,74 @@ fn detect_error_patterns(
             ),
             count: significant_missing.len(),
             examples,
+            ..Default::default()
+        });
+    }
+
+    if !file_activity.exclusive_creates.is_empty() {
+        let creates = &file_activity.exclusive_creates;
+        patterns.push(ErrorPattern {
+            code: Some(PatternCode::FileExclusiveCreate),
+            category: "exclusive_create".into(),
+            severity: "warning".into(),
+            description: format!(
+                "{} exclusive create(s) found the file already there -- a lock or pid file held by another process or left over from an earlier run",
+                creates.len()
+            ),
+            count: creates.len(),
+            examples: creates
+                .iter()
+                .take(5)
+                .map(|e| format!("pid {} {} {} -> EEXIST", e.pid, e.op_label(), e.path))
+                .collect(),
+            ..Default::default()
+        });
+    }
+
+    let space_kinds: [(PatternCode, &str, &[i32], &str); 2] = [
+        (
+            PatternCode::FileDiskFull,
+            "disk_full",
+            &[libc::ENOSPC, libc::EDQUOT],
+            "file operation(s) failed because the filesystem or quota is full",
+        ),
+        (
+            PatternCode::FileReadOnly,
+            "read_only_fs",
+            &[libc::EROFS],
+            "file operation(s) failed on a read-only filesystem",
+        ),
+    ];
+    for (code, category, errnos, description) in space_kinds {
+        let failed: Vec<&FailedFileOp> = file_activity
+            .space_errors
+            .iter()
+            .filter(|f| errnos.contains(&(f.errno as i32)))
+            .collect();
+        if failed.is_empty() {
+            continue;
+        }
+        // The mount as it was at pack time: space freed by cleanup after
+        // the failure shows up as free here.
+        let examples = failed
+            .iter()
+            .take(5)
+            .map(|e| {
+                let mut line = format!("{} {} -> {}", e.op_label(), e.path, e.errno_name);
+                if let Some(mount) = mounts::mount_of(&file_activity.mounts, &e.path) {
+                    line.push_str(&format!(" [{}]", mounts::describe(mount)));
+                }
+                line
+            })
+            .collect();
+        patterns.push(ErrorPattern {
+            code: Some(code),
+            category: category.into(),
+            severity: "error".into(),
+            description: format!("{} {}", failed.len(), description),
+            count: failed.len(),
+            examples,
+            ..Default::default()
         });
     }
 
@@ -697,7 +1655,16 @@ fn detect_error_patterns(
             .take(5)
             .map(|e| format!("connect {} -> {}", e.addr, e.errno_name))
             .collect();
+        let mut errnos: HashMap<i64, usize> = HashMap::new();
+        for c in &net_activity.failed_connections {
+            *errnos.entry(c.errno).or_default() += 1;
+        }
+        let errno = errnos
+            .into_iter()
+            .max_by_key(|&(errno, n)| (n, std::cmp::Reverse(errno)))
+            .map_or(0, |(errno, _)| errno);
         patterns.push(ErrorPattern {
+            code: Some(PatternCode::for_connect_errno(errno as i32)),
             category: "network".into(),
             severity: "error".into(),
             description: format!(
@@ -706,6 +1673,26 @@ fn detect_error_patterns(
             ),
             count: net_activity.failed_connections.len(),
             examples,
+            ..Default::default()
+        });
+    }
+
+    if !net_activity.failed_rpcs.is_empty() {
+        let examples: Vec<String> = net_activity
+            .failed_rpcs
+            .iter()
+            .rev()
+            .take(5)
+            .map(|r| format!("{} -> {}", r.path.as_deref().unwrap_or("?"), r.outcome()))
+            .collect();
+        patterns.push(ErrorPattern {
+            code: Some(PatternCode::NetRpcFailed),
+            category: "network".into(),
+            severity: "error".into(),
+            description: format!("{} RPC(s) failed", net_activity.http2_failed),
+            count: net_activity.http2_failed,
+            examples,
+            ..Default::default()
         });
     }
 
@@ -725,11 +1712,13 @@ fn detect_error_patterns(
             })
             .collect();
         patterns.push(ErrorPattern {
+            code: Some(PatternCode::CrashMultiple),
             category: "multi_crash".into(),
             severity: "critical".into(),
             description: format!("{} processes were killed by signals", killed_procs.len()),
             count: killed_procs.len(),
             examples,
+            ..Default::default()
         });
     }
 
@@ -757,11 +1746,13 @@ fn detect_error_patterns(
             })
             .collect();
         patterns.push(ErrorPattern {
+            code: Some(PatternCode::PythonException),
             category: "python_exception".into(),
             severity: "critical".into(),
             description: format!("{} unhandled Python exception(s)", python_exceptions.len()),
             count: python_exceptions.len(),
             examples,
+            ..Default::default()
         });
     }
 
@@ -790,11 +1781,13 @@ fn detect_stderr_patterns(stderr: &str, patterns: &mut Vec<ErrorPattern>) {
             })
             .unwrap_or("out of memory");
         patterns.push(ErrorPattern {
+            code: Some(PatternCode::OutputOom),
             category: "oom".into(),
             severity: "critical".into(),
             description: "Out of memory condition detected in stderr".into(),
             count: 1,
             examples: vec![example_line.to_string()],
+            ..Default::default()
         });
     }
 
@@ -808,11 +1801,13 @@ fn detect_stderr_patterns(stderr: &str, patterns: &mut Vec<ErrorPattern>) {
             })
             .unwrap_or("timeout");
         patterns.push(ErrorPattern {
+            code: Some(PatternCode::OutputTimeout),
             category: "timeout".into(),
             severity: "error".into(),
             description: "Timeout detected in stderr".into(),
             count: 1,
             examples: vec![example_line.to_string()],
+            ..Default::default()
         });
     }
 
@@ -836,11 +1831,13 @@ fn detect_stderr_patterns(stderr: &str, patterns: &mut Vec<ErrorPattern>) {
             .collect();
         if !example_lines.is_empty() {
             patterns.push(ErrorPattern {
+                code: Some(PatternCode::OutputException),
                 category: "exception".into(),
                 severity: "critical".into(),
                 description: "Exception or panic detected in stderr".into(),
                 count: 1,
                 examples: example_lines,
+                ..Default::default()
             });
         }
     }
@@ -848,6 +1845,7 @@ fn detect_stderr_patterns(stderr: &str, patterns: &mut Vec<ErrorPattern>) {
 
 fn build_python_exceptions(db: &crate::trace::TraceDb) -> Vec<PythonExceptionInfo> {
     let events = db.query_python_unhandled_exceptions().unwrap_or_default();
+    let redactor = Redactor::new();
 
     events
         .iter()
@@ -855,10 +1853,15 @@ fn build_python_exceptions(db: &crate::trace::TraceDb) -> Vec<PythonExceptionInf
             let detail = e.detail.as_ref()?;
             let parsed: serde_json::Value = serde_json::from_str(detail).ok()?;
 
-            let traceback: Vec<PythonTraceFrame> = parsed
+            let mut traceback: Vec<PythonTraceFrame> = parsed
                 .get("traceback")
                 .and_then(|t| serde_json::from_value(t.clone()).ok())
                 .unwrap_or_default();
+            for frame in &mut traceback {
+                if let Some(ref locals) = frame.locals {
+                    frame.locals = Some(redactor.redact_vars(locals));
+                }
+            }
 
             let chain: Vec<PythonChainEntry> = parsed
                 .get("chain")
@@ -884,6 +1887,200 @@ fn build_python_exceptions(db: &crate::trace::TraceDb) -> Vec<PythonExceptionInf
         .collect()
 }
 
+/// The innermost user-code frame of the traceback, or the innermost frame
+/// when the exception was raised entirely inside library code.
+pub(crate) fn python_exception_location(exc: &PythonExceptionInfo) -> Option<LocationInfo> {
+    let frame = exc
+        .traceback
+        .iter()
+        .rev()
+        .find(|f| !pycalls::is_library_file(&f.file))
+        .or(exc.traceback.last())?;
+    let module = std::path::Path::new(&frame.file)
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned());
+    Some(LocationInfo {
+        file: Some(frame.file.clone()),
+        line: Some(frame.line),
+        function: Some(frame.func.clone()),
+        module,
+    })
+}
+
+/// Slowest user functions, and the stack at the last raise of the
+/// unhandled exception's type before it escaped.
+fn build_python_calls(
+    db: &TraceDb,
+    unhandled: Option<&PythonExceptionInfo>,
+) -> Result<(Vec<PyFunctionTiming>, Option<PyExceptionStack>)> {
+    if !db.has_python_events() {
+        return Ok((Vec::new(), None));
+    }
+    let report = pycalls::compute(db, false)?;
+    let mut functions = report.functions;
+    functions.truncate(MAX_SLOW_FUNCTIONS);
+    let stack = unhandled.and_then(|exc| {
+        report
+            .exception_stacks
+            .into_iter()
+            .rev()
+            .find(|s| s.exc_type == exc.exc_type)
+    });
+    Ok((functions, stack))
+}
+
+/// The step a failing run was on: the last command a shell traced before
+/// starting the last child that failed, or the root shell's last command
+/// when the failure came from a builtin. Returns the steps leading up to it.
+fn build_shell_steps(
+    db: &TraceDb,
+    failed: bool,
+) -> Result<(Vec<ShellStepInfo>, Option<ShellStepInfo>)> {
+    let steps: Vec<ShellStepInfo> = db
+        .query_events_by_kind("shell_step")?
+        .into_iter()
+        .filter_map(|e| {
+            let v: serde_json::Value = serde_json::from_str(e.detail.as_deref()?).ok()?;
+            let func = v["func"].as_str().unwrap_or("");
+            Some(ShellStepInfo {
+                ts_ms: e.ts as f64 / 1_000_000.0,
+                pid: e.proc_id,
+                // `bash -c` commands have no BASH_SOURCE.
+                file: match v["file"].as_str().unwrap_or("") {
+                    "" => "bash -c".to_string(),
+                    f => f.to_string(),
+                },
+                line: v["line"].as_u64().unwrap_or(0) as u32,
+                func: (!func.is_empty()).then(|| func.to_string()),
+                command: v["command"].as_str().unwrap_or("").to_string(),
+            })
+        })
+        .collect();
+    if steps.is_empty() {
+        return Ok((Vec::new(), None));
+    }
+
+    let failed_step = if failed {
+        let processes = db.query_processes()?;
+        let last_before = |pid: i32, ts_ms: Option<f64>| {
+            steps
+                .iter()
+                .rev()
+                .find(|s| s.pid == pid && ts_ms.is_none_or(|t| s.ts_ms <= t))
+                .cloned()
+        };
+        processes
+            .iter()
+            .filter(|p| p.exit_code.is_some_and(|c| c != 0) || p.signal.is_some())
+            .rev()
+            .find_map(|p| last_before(p.parent_proc_id?, Some(p.start_ts as f64 / 1_000_000.0)))
+            .or_else(|| {
+                let root = processes.iter().find(|p| p.parent_proc_id.is_none())?;
+                last_before(root.proc_id, None)
+            })
+    } else {
+        None
+    };
+
+    let end = failed_step
+        .as_ref()
+        .and_then(|f| steps.iter().rposition(|s| s == f))
+        .map(|i| i + 1)
+        .unwrap_or(steps.len());
+    let start = end.saturating_sub(MAX_SHELL_STEPS);
+    Ok((steps[start..end].to_vec(), failed_step))
+}
+
+fn build_summary(db: &TraceDb, stderr: Option<&str>) -> Result<Option<BuildSummary>> {
+    let report = buildsteps::compute(db, stderr)?;
+    if report.targets.is_empty() {
+        return Ok(None);
+    }
+    let recipes = report.targets.len();
+    let (mut targets, mut rest): (Vec<BuildTarget>, Vec<BuildTarget>) =
+        report.targets.into_iter().partition(|t| t.failed);
+    // A failed recursive make only relays its child's failure.
+    targets.sort_by_key(|t| report.makes.contains(&t.pid));
+    let failed = targets.len();
+    rest.sort_by(|a, b| {
+        b.duration_ms
+            .unwrap_or(0.0)
+            .total_cmp(&a.duration_ms.unwrap_or(0.0))
+    });
+    targets.extend(rest);
+    targets.truncate(MAX_BUILD_TARGETS.max(failed));
+    Ok(Some(BuildSummary {
+        recipes,
+        failed,
+        targets,
+    }))
+}
+
+/// Processes that ran 32-bit or x32 code, whose syscalls were decoded
+/// through a translation table or not at all.
+fn foreign_abi_warnings(db: &TraceDb) -> Result<Vec<String>> {
+    let mut warnings = Vec::new();
+    for e in db.query_events_by_kind("foreign_abi")? {
+        let Some(v) = e
+            .detail
+            .as_deref()
+            .and_then(|d| serde_json::from_str::<serde_json::Value>(d).ok())
+        else {
+            continue;
+        };
+        let abi = v["abi"].as_str().unwrap_or("non-native");
+        let skipped = v["skipped_syscalls"].as_u64().unwrap_or(0);
+        warnings.push(if skipped > 0 {
+            format!(
+                "pid {} ran {} code; {} of its syscalls have no decoder and are missing from the trace",
+                e.proc_id, abi, skipped
+            )
+        } else {
+            format!(
+                "pid {} ran {} code; its syscalls were mapped onto {} ones",
+                e.proc_id,
+                abi,
+                std::env::consts::ARCH
+            )
+        });
+    }
+    Ok(warnings)
+}
+
+fn build_kernel_io(db: &TraceDb) -> Result<Option<KernelIoReport>> {
+    let mut report = kernelio::compute(db)?;
+    if report.processes.is_empty() {
+        return Ok(None);
+    }
+    report
+        .processes
+        .sort_by_key(|p| std::cmp::Reverse(p.untraced_read_bytes + p.untraced_write_bytes));
+    report.processes.truncate(MAX_KERNEL_IO_PROCESSES);
+    Ok(Some(report))
+}
+
+fn build_db_summary(db: &TraceDb) -> Result<Option<DbSummary>> {
+    let mut statements = dbqueries::compute(db)?;
+    if statements.is_empty() {
+        return Ok(None);
+    }
+    // Statements can carry credentials (CREATE USER ... PASSWORD '...').
+    let redactor = Redactor::new();
+    for s in &mut statements {
+        s.statement = redactor.redact_string(&s.statement);
+    }
+    let failed = statements.iter().filter(|s| s.failed()).count();
+    let failed_index = statements.iter().rposition(|s| s.failed());
+    let end = failed_index.map(|i| i + 1).unwrap_or(statements.len());
+    let start = end.saturating_sub(MAX_DB_STATEMENTS);
+    Ok(Some(DbSummary {
+        statements: statements.len(),
+        failed,
+        failed_statement: failed_index.map(|i| statements[i].clone()),
+        recent: statements[start..end].to_vec(),
+    }))
+}
+
 pub fn is_noise_path_pub(path: Option<&str>) -> bool {
     is_noise_path(path)
 }
@@ -947,7 +2144,7 @@ fn is_noise_addr(addr: &str) -> bool {
     addr.starts_with("family=") || addr.contains("nscd")
 }
 
-fn is_significant_missing_file(path: &str) -> bool {
+pub(crate) fn is_significant_missing_file(path: &str) -> bool {
     if is_noise_path(Some(path)) {
         return false;
     }
@@ -1004,7 +2201,7 @@ fn is_significant_missing_file(path: &str) -> bool {
     true
 }
 
-fn errno_name(errno: i64) -> String {
+pub fn errno_name(errno: i64) -> String {
     match errno {
         1 => "EPERM".into(),
         2 => "ENOENT".into(),
@@ -1041,11 +2238,12 @@ fn errno_name(errno: i64) -> String {
         112 => "EHOSTDOWN".into(),
         113 => "EHOSTUNREACH".into(),
         115 => "EINPROGRESS".into(),
+        122 => "EDQUOT".into(),
         _ => format!("errno({})", errno),
     }
 }
 
-fn format_event_description(kind: &str, detail: &str) -> String {
+pub(crate) fn format_event_description(kind: &str, detail: &str) -> String {
     if let Ok(v) = serde_json::from_str::<serde_json::Value>(detail) {
         match kind {
             "python_call" => {
@@ -1078,6 +2276,78 @@ fn format_event_description(kind: &str, detail: &str) -> String {
                 let func = v.get("func").and_then(|f| f.as_str()).unwrap_or("");
                 format!("!! {}: {} in {}()", exc_type, exc_msg, func)
             }
+            "log" => {
+                let text = |key: &str| v.get(key).and_then(|s| s.as_str()).map(String::from);
+                format_log_line(&LogLine {
+                    ts_ms: 0.0,
+                    pid: 0,
+                    level: text("level"),
+                    logger: text("logger"),
+                    message: text("message").unwrap_or_default(),
+                })
+            }
+            "shell_step" => {
+                let file = v.get("file").and_then(|f| f.as_str()).unwrap_or("");
+                let line = v.get("line").and_then(|l| l.as_u64()).unwrap_or(0);
+                let command = v.get("command").and_then(|c| c.as_str()).unwrap_or("");
+                let short_file = file.rsplit('/').next().unwrap_or(file);
+                format!("+ {}:{} {}", short_file, line, command)
+            }
+            "db_query" => {
+                let statement = v.get("statement").and_then(|s| s.as_str()).unwrap_or("");
+                let first_line = statement.lines().next().unwrap_or("");
+                let short: String = first_line.chars().take(80).collect();
+                let outcome = match v.get("error").filter(|e| !e.is_null()) {
+                    Some(e) => format!(
+                        "ERROR {} {}",
+                        e.get("code").and_then(|c| c.as_str()).unwrap_or(""),
+                        e.get("message").and_then(|m| m.as_str()).unwrap_or("")
+                    ),
+                    None => v
+                        .get("tag")
+                        .and_then(|t| t.as_str())
+                        .unwrap_or("")
+                        .to_string(),
+                };
+                format!("sql {} -> {}", short, outcome)
+            }
+            "cache_op" => {
+                let text = |key: &str| v.get(key).and_then(|s| s.as_str()).unwrap_or("");
+                let mut desc = format!("{} {}", text("proto"), text("command"));
+                if !text("key").is_empty() {
+                    let key: String = text("key").chars().take(60).collect();
+                    desc.push(' ');
+                    desc.push_str(&key);
+                }
+                if let Some(bytes) = v.get("value_bytes").and_then(|b| b.as_u64()) {
+                    desc.push_str(&format!(" ({} B)", bytes));
+                }
+                let outcome = match text("status") {
+                    "error" => format!("ERROR {}", text("error")),
+                    "miss" => "miss".to_string(),
+                    "no_response" => "no response".to_string(),
+                    _ => text("reply").to_string(),
+                };
+                format!("{} -> {}", desc, outcome)
+            }
+            "h2_stream" => {
+                let text = |key: &str| v.get(key).and_then(|s| s.as_str()).unwrap_or("");
+                let num = |key: &str| v.get(key).and_then(|n| n.as_u64());
+                let path: String = text("path").chars().take(80).collect();
+                let outcome = match (num("grpc_status"), num("reset"), num("http_status")) {
+                    (Some(code), _, _) if code != 0 => {
+                        let name = h2wire::grpc_code_name(code as u32);
+                        match text("grpc_message") {
+                            "" => format!("grpc {}", name),
+                            msg => format!("grpc {}: {}", name, msg),
+                        }
+                    }
+                    (_, Some(code), _) => format!("reset {}", h2wire::h2_error_name(code as u32)),
+                    (_, _, Some(status)) => status.to_string(),
+                    _ => "no response".into(),
+                };
+                format!("h2 {} {} -> {}", text("method"), path, outcome)
+            }
             "native_trace_enter" => {
                 let func = v.get("func").and_then(|f| f.as_str()).unwrap_or("?");
                 let depth = v.get("depth").and_then(|d| d.as_u64()).unwrap_or(0);
@@ -1090,6 +2360,54 @@ fn format_event_description(kind: &str, detail: &str) -> String {
                 let indent = "  ".repeat(depth as usize);
                 format!("{}<- {}()", indent, func)
             }
+            "port_holders" => {
+                let holders: Vec<String> = v
+                    .get("holders")
+                    .and_then(|h| h.as_array())
+                    .into_iter()
+                    .flatten()
+                    .map(|h| {
+                        let state = h.get("state").and_then(|s| s.as_str()).unwrap_or("?");
+                        match h.get("pid").and_then(|p| p.as_i64()) {
+                            Some(pid) => format!("pid {} {}", pid, state),
+                            None => state.to_string(),
+                        }
+                    })
+                    .collect();
+                format!(
+                    "port {} in use: {}",
+                    v.get("port").and_then(|p| p.as_u64()).unwrap_or(0),
+                    if holders.is_empty() {
+                        "no holder found".to_string()
+                    } else {
+                        holders.join(", ")
+                    }
+                )
+            }
+            "process_excluded" => format!(
+                "tracing stopped: matches --exclude-children {}",
+                v.get("pattern").and_then(|p| p.as_str()).unwrap_or("?")
+            ),
+            "capture_control" => match v.get("capture").and_then(|c| c.as_str()) {
+                Some("off") => "capture turned off by the program".into(),
+                _ => "capture turned back on by the program".into(),
+            },
+            "failure_stack" => {
+                let frames: Vec<&str> = v
+                    .get("frames")
+                    .and_then(|f| f.as_array())
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|f| f.as_str())
+                    .take(3)
+                    .collect();
+                format!(
+                    "{} failed {} in {}",
+                    v.get("op").and_then(|o| o.as_str()).unwrap_or("?"),
+                    errno_name(v.get("errno").and_then(|e| e.as_i64()).unwrap_or(0)),
+                    frames.join(" <- ")
+                )
+            }
             "process_exec" => {
                 if let Some(arr) = v.as_array() {
                     let cmd: Vec<&str> = arr.iter().filter_map(|a| a.as_str()).collect();
@@ -1104,3 +2422,179 @@ fn format_event_description(kind: &str, detail: &str) -> String {
         format!("[{}] {}", kind, detail)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::types::{Event, EventKind, NetEvent, NetOpKind, ProcessInfo, TraceEvent};
+
+    fn delivery(ts_ms: f64, pid: i32, signo: i32, fatal: bool) -> SignalDelivery {
+        SignalDelivery {
+            ts_ms,
+            pid,
+            signal: util::signal_name(signo).to_string(),
+            signo,
+            fatal,
+        }
+    }
+
+    #[test]
+    fn signal_details_are_parsed() {
+        assert_eq!(parse_signo("received SIGTERM (15)"), Some(15));
+        assert_eq!(parse_signo("killed by SIGKILL (9)"), Some(9));
+        assert_eq!(parse_signo("received SIGTERM"), None);
+        assert_eq!(parse_signo("SIGTERM (x)"), None);
+
+        let h = parse_signal_handler(
+            2_000_000,
+            7,
+            "SIGINT (2) handler=0x4011d6 flags=SA_SIGINFO|SA_RESTART mask=SIGTERM",
+        )
+        .unwrap();
+        assert_eq!((h.ts_ms, h.pid, h.signal.as_str()), (2.0, 7, "SIGINT"));
+        assert_eq!(h.disposition, "handler=0x4011d6");
+        assert_eq!(h.flags, ["SA_SIGINFO", "SA_RESTART"]);
+        assert_eq!(h.mask, ["SIGTERM"]);
+
+        let old = parse_signal_handler(0, 7, "SIGPIPE (13) ignore").unwrap();
+        assert_eq!(old.disposition, "ignore");
+        assert!(old.flags.is_empty() && old.mask.is_empty());
+    }
+
+    #[test]
+    fn build_signals_skips_child_exits_but_keeps_fatal_ones() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = TraceDb::create(&dir.path().join("t.sqlite")).unwrap();
+        let event = |ts: u64, kind: EventKind, detail: &str| {
+            TraceEvent::Generic(Event {
+                ts,
+                proc_id: 10,
+                kind,
+                detail: detail.to_string(),
+            })
+        };
+        db.batch_insert_events(&[
+            event(1, EventKind::Signal, "received SIGCHLD (17)"),
+            event(2, EventKind::Signal, "received SIGTERM (15)"),
+            event(3, EventKind::Signal, "killed by SIGKILL (9)"),
+            event(4, EventKind::SignalHandler, "SIGTERM (15) ignore"),
+        ])
+        .unwrap();
+
+        let signals = build_signals(&db).unwrap();
+        let seen: Vec<(&str, bool)> = signals
+            .deliveries
+            .iter()
+            .map(|d| (d.signal.as_str(), d.fatal))
+            .collect();
+        assert_eq!(seen, [("SIGTERM", false), ("SIGKILL", true)]);
+        assert_eq!(signals.handlers.len(), 1);
+        assert_eq!(signals.handlers[0].disposition, "ignore");
+    }
+
+    #[test]
+    fn signal_patterns_flag_escalation_and_sigpipe_storms() {
+        let mut signals = SignalSummary {
+            deliveries: vec![
+                delivery(100.0, 10, libc::SIGTERM, false),
+                delivery(5_100.0, 10, libc::SIGKILL, true),
+                // Too long after its SIGTERM to be an escalation.
+                delivery(0.0, 11, libc::SIGTERM, false),
+                delivery(SUPERVISOR_KILL_WINDOW_MS + 1.0, 11, libc::SIGKILL, true),
+            ],
+            handlers: Vec::new(),
+        };
+        let patterns = detect_signal_patterns(&signals);
+        assert_eq!(patterns.len(), 1);
+        assert_eq!(patterns[0].code, Some(PatternCode::SignalSupervisorKill));
+        assert_eq!(
+            patterns[0].examples,
+            ["pid 10 got SIGTERM, then SIGKILL 5000ms later"]
+        );
+
+        signals.deliveries = (0..SIGPIPE_STORM_THRESHOLD)
+            .map(|i| delivery(i as f64, 20 + (i % 2) as i32, libc::SIGPIPE, false))
+            .collect();
+        let patterns = detect_signal_patterns(&signals);
+        assert_eq!(patterns.len(), 1);
+        assert_eq!(patterns[0].code, Some(PatternCode::SignalSigpipeStorm));
+        assert_eq!(patterns[0].examples, ["pid 20", "pid 21"]);
+
+        signals.deliveries.pop();
+        assert!(detect_signal_patterns(&signals).is_empty());
+    }
+
+    #[test]
+    fn server_activity_follows_listeners_to_accepted_peers() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = TraceDb::create(&dir.path().join("t.sqlite")).unwrap();
+        let net = |op: NetOpKind, proto: &str, addr: Option<&str>, fd: i32, result: i64| NetEvent {
+            ts: 0,
+            proc_id: 10,
+            op,
+            proto: Some(proto.to_string()),
+            src: None,
+            dst: addr.map(str::to_string),
+            bytes: matches!(op, NetOpKind::Send | NetOpKind::Recv).then_some(result as u64),
+            fd: Some(fd),
+            result: Some(result),
+            duration_ns: None,
+            listen_fd: None,
+        };
+        let accept = NetEvent {
+            src: Some("10.0.0.2:5000".into()),
+            listen_fd: Some(3),
+            ..net(NetOpKind::Accept, "tcp", None, 5, 5)
+        };
+        let process = TraceEvent::Process(ProcessInfo {
+            proc_id: 10,
+            parent_proc_id: None,
+            argv: vec!["server".into()],
+            cwd: "/".into(),
+            start_ts: 0,
+            tgid: None,
+        });
+        let events: Vec<TraceEvent> = vec![
+            net(NetOpKind::Bind, "tcp", Some("0.0.0.0:8080"), 3, 0),
+            net(NetOpKind::Listen, "tcp", None, 3, 0),
+            accept,
+            net(NetOpKind::Recv, "tcp", None, 5, 100),
+            net(NetOpKind::Send, "tcp", None, 5, 40),
+            net(
+                NetOpKind::Bind,
+                "tcp",
+                Some("0.0.0.0:8080"),
+                4,
+                -libc::EADDRINUSE as i64,
+            ),
+            net(NetOpKind::Bind, "udp", Some("0.0.0.0:5353"), 6, 0),
+            // Bound but never listened on, e.g. a client pinning its port.
+            net(NetOpKind::Bind, "tcp", Some("127.0.0.1:9000"), 7, 0),
+        ]
+        .into_iter()
+        .map(TraceEvent::Net)
+        .collect();
+        db.batch_insert_events(&[process]).unwrap();
+        db.batch_insert_events(&events).unwrap();
+
+        let server = build_server_activity(&db).unwrap();
+        let listeners: Vec<(&str, bool, u64)> = server
+            .listeners
+            .iter()
+            .map(|l| (l.addr.as_str(), l.listening, l.accepts))
+            .collect();
+        assert_eq!(
+            listeners,
+            [("0.0.0.0:8080", true, 1), ("0.0.0.0:5353", false, 0)]
+        );
+        assert_eq!(server.bind_errors.len(), 1);
+        assert_eq!(server.bind_errors[0].errno_name, "EADDRINUSE");
+        assert_eq!(server.peers.len(), 1);
+        let peer = &server.peers[0];
+        assert_eq!(
+            (peer.peer.as_str(), peer.local.as_str()),
+            ("10.0.0.2:5000", "0.0.0.0:8080")
+        );
+        assert_eq!((peer.connections, peer.received, peer.sent), (1, 100, 40));
+    }
+}
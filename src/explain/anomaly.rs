@@ -0,0 +1,272 @@
+use std::collections::HashSet;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::explain::analyzer::{self, ErrorPattern};
+use crate::explain::patterns::PatternRegistry;
+use crate::explain::trends;
+use crate::pack::reader::PackReader;
+use crate::trace::db::Page;
+
+/// Something seen in at most this share of earlier runs is rare.
+const RARE_FRACTION: f64 = 0.1;
+/// A duration this many standard deviations from the mean is anomalous.
+const DURATION_Z: f64 = 3.0;
+/// Fewer earlier runs than this give no meaningful spread.
+const MIN_RUNS_FOR_Z: usize = 3;
+/// Errors that mean "try again", not that something went wrong.
+const RETRY_ERRNOS: [&str; 3] = ["EAGAIN", "EINPROGRESS", "EINTR"];
+
+/// How unusual a run is next to earlier runs of the same command
+/// (`poe explain --history <dir>`).
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct HistoryAnomalies {
+    pub history_dir: String,
+    /// Earlier runs of the same command found there.
+    pub runs: usize,
+    pub duration: Option<DurationScore>,
+    /// Most anomalous first.
+    pub anomalies: Vec<Anomaly>,
+    /// The highest anomaly score, 0 when nothing stood out.
+    pub score: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct DurationScore {
+    pub duration_ms: u64,
+    pub mean_ms: f64,
+    pub stddev_ms: f64,
+    /// Standard deviations from the mean; None below three earlier runs or
+    /// when they all took the same time.
+    pub z: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct Anomaly {
+    /// new_connection, rare_connection, new_error, rare_error or duration.
+    pub kind: String,
+    pub description: String,
+    /// Earlier runs that showed the same thing.
+    pub seen_in: usize,
+    /// 0 to 1: 1 for something no earlier run did.
+    pub score: f64,
+}
+
+/// What a run is compared on.
+#[derive(Debug, Default)]
+struct RunFacts {
+    duration_ms: u64,
+    /// Addresses connected to.
+    connections: HashSet<String>,
+    /// Pattern codes (`POE2003 net.conn_refused`) and failed syscalls by
+    /// errno (`open ENOENT`).
+    errors: HashSet<String>,
+}
+
+fn facts(pack: &PackReader, patterns: &[ErrorPattern]) -> Result<RunFacts> {
+    let mut facts = RunFacts {
+        duration_ms: pack.summary().duration_ms,
+        errors: patterns
+            .iter()
+            .filter_map(|p| p.code)
+            .map(|c| c.to_string())
+            .collect(),
+        ..Default::default()
+    };
+    let mut failed = |op: &str, result: Option<i64>| {
+        if let Some(r) = result.filter(|r| *r < 0) {
+            let errno = analyzer::errno_name(-r);
+            if !RETRY_ERRNOS.contains(&errno.as_str()) {
+                facts.errors.insert(format!("{} {}", op, errno));
+            }
+        }
+    };
+    let db = pack.db();
+    db.each_file_event(Page::new(None, 0), |f| {
+        if !analyzer::is_noise_path_pub(f.path.as_deref()) {
+            failed(&f.op, f.result);
+        }
+        Ok(())
+    })?;
+    let mut connections = HashSet::new();
+    db.each_net_event(Page::new(None, 0), |n| {
+        if n.op == "connect" {
+            connections.extend(n.dst);
+        }
+        failed(&n.op, n.result);
+        Ok(())
+    })?;
+    facts.connections = connections;
+    Ok(facts)
+}
+
+/// Scores `pack`, explained as `output`, against the packs in `dir` of the
+/// same command line.
+pub fn score_against(
+    pack: &PackReader,
+    output: &analyzer::ExplainOutput,
+    dir: &Path,
+) -> Result<HistoryAnomalies> {
+    let summary = pack.summary();
+    let command = trends::command_hash(&summary.command);
+    let registry = PatternRegistry::builtin();
+    let mut history = Vec::new();
+    for entry in
+        std::fs::read_dir(dir).with_context(|| format!("failed to read {}", dir.display()))?
+    {
+        let path = entry?.path();
+        if !path.extension().map(|x| x == "poepack").unwrap_or(false) {
+            continue;
+        }
+        let earlier = match PackReader::open(&path) {
+            Ok(p) => p,
+            Err(e) => {
+                log::warn!("skipping {}: {:#}", path.display(), e);
+                continue;
+            }
+        };
+        let s = earlier.summary();
+        if s.run_id == summary.run_id || trends::command_hash(&s.command) != command {
+            continue;
+        }
+        let explained = analyzer::analyze_with(&earlier, &registry)?;
+        history.push(facts(&earlier, &explained.error_patterns)?);
+    }
+    let current = facts(pack, &output.error_patterns)?;
+    Ok(score(dir, &current, &history))
+}
+
+fn score(dir: &Path, current: &RunFacts, history: &[RunFacts]) -> HistoryAnomalies {
+    let runs = history.len();
+    let mut anomalies = Vec::new();
+    if runs > 0 {
+        let count = |key: &str, of: fn(&RunFacts) -> &HashSet<String>| {
+            history.iter().filter(|h| of(h).contains(key)).count()
+        };
+        let mut rare = |kind: &str, key: &String, seen_in: usize| {
+            let share = seen_in as f64 / runs as f64;
+            if share > RARE_FRACTION {
+                return;
+            }
+            let (kind, description) = if seen_in == 0 {
+                (
+                    format!("new_{}", kind),
+                    format!("{}: in none of {} earlier runs", key, runs),
+                )
+            } else {
+                (
+                    format!("rare_{}", kind),
+                    format!("{}: in {} of {} earlier runs", key, seen_in, runs),
+                )
+            };
+            anomalies.push(Anomaly {
+                kind,
+                description,
+                seen_in,
+                score: 1.0 - share,
+            });
+        };
+        let mut connections: Vec<&String> = current.connections.iter().collect();
+        connections.sort();
+        for addr in connections {
+            rare("connection", addr, count(addr, |h| &h.connections));
+        }
+        let mut errors: Vec<&String> = current.errors.iter().collect();
+        errors.sort();
+        for error in errors {
+            rare("error", error, count(error, |h| &h.errors));
+        }
+    }
+
+    let duration = (runs > 0).then(|| {
+        let mean_ms = history.iter().map(|h| h.duration_ms as f64).sum::<f64>() / runs as f64;
+        let variance = history
+            .iter()
+            .map(|h| (h.duration_ms as f64 - mean_ms).powi(2))
+            .sum::<f64>()
+            / runs as f64;
+        let stddev_ms = variance.sqrt();
+        let z = (runs >= MIN_RUNS_FOR_Z && stddev_ms > 0.0)
+            .then(|| (current.duration_ms as f64 - mean_ms) / stddev_ms);
+        DurationScore {
+            duration_ms: current.duration_ms,
+            mean_ms,
+            stddev_ms,
+            z,
+        }
+    });
+    if let Some(d) = &duration {
+        if let Some(z) = d.z.filter(|z| z.abs() >= DURATION_Z) {
+            anomalies.push(Anomaly {
+                kind: "duration".into(),
+                description: format!(
+                    "took {}ms, {:.1} standard deviations {} the mean of {:.0}ms",
+                    d.duration_ms,
+                    z.abs(),
+                    if z > 0.0 { "above" } else { "below" },
+                    d.mean_ms
+                ),
+                seen_in: history
+                    .iter()
+                    .filter(|h| {
+                        (h.duration_ms as f64 - d.mean_ms).abs()
+                            >= (d.duration_ms as f64 - d.mean_ms).abs()
+                    })
+                    .count(),
+                score: (z.abs() / (2.0 * DURATION_Z)).min(1.0),
+            });
+        }
+    }
+
+    anomalies.sort_by(|a, b| b.score.total_cmp(&a.score));
+    HistoryAnomalies {
+        history_dir: dir.display().to_string(),
+        runs,
+        duration,
+        score: anomalies.first().map_or(0.0, |a| a.score),
+        anomalies,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(duration_ms: u64, connections: &[&str], errors: &[&str]) -> RunFacts {
+        let set = |keys: &[&str]| keys.iter().map(|k| k.to_string()).collect();
+        RunFacts {
+            duration_ms,
+            connections: set(connections),
+            errors: set(errors),
+        }
+    }
+
+    #[test]
+    fn scores_new_and_rare_behavior_and_slow_runs() {
+        let mut history: Vec<RunFacts> = (0..10)
+            .map(|i| run(1000 + i * 10, &["10.0.0.1:5432"], &["open ENOENT"]))
+            .collect();
+        history[0].errors.insert("POE2003 net.conn_refused".into());
+        let current = run(
+            2000,
+            &["10.0.0.1:5432", "10.0.0.9:6379"],
+            &["open ENOENT", "POE2003 net.conn_refused"],
+        );
+
+        let report = score(Path::new("runs"), &current, &history);
+        let kinds: Vec<(&str, usize)> = report
+            .anomalies
+            .iter()
+            .map(|a| (a.kind.as_str(), a.seen_in))
+            .collect();
+        assert_eq!(
+            kinds,
+            [("new_connection", 0), ("duration", 0), ("rare_error", 1)]
+        );
+        assert_eq!(report.score, 1.0);
+        assert!(report.duration.unwrap().z.unwrap() > DURATION_Z);
+    }
+}
@@ -0,0 +1,214 @@
+use std::cmp::Ordering;
+
+use crate::explain::analyzer::{ErrorPattern, ExplainOutput};
+use crate::trace::db::StdioQueryResult;
+
+/// Shorter example text matches too much unrelated output.
+const MIN_KEY_LEN: usize = 4;
+
+/// Puts the diagnosis in causal rather than detection order: each pattern is
+/// timed by the earliest event that supports it, the earliest becomes the
+/// probable root cause and the later ones are linked to it as consequences.
+/// Patterns with no timed evidence keep their detection order at the end.
+/// `streams` holds the chunks of each captured output stream.
+pub fn order(
+    patterns: &mut [ErrorPattern],
+    output: &ExplainOutput,
+    streams: &[Vec<StdioQueryResult>],
+) {
+    let streams: Vec<StreamText> = streams.iter().map(|s| StreamText::new(s)).collect();
+    for p in patterns.iter_mut() {
+        p.first_seen_ms = first_seen(p, output, &streams);
+    }
+    patterns.sort_by(|a, b| match (a.first_seen_ms, b.first_seen_ms) {
+        (Some(x), Some(y)) => x.total_cmp(&y),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    });
+
+    // Warnings (a probed-for config file that isn't there) often precede
+    // the real failure without causing it, so they only count when nothing
+    // worse was found.
+    let timed = |severities: &[&str]| {
+        patterns
+            .iter()
+            .position(|p| p.first_seen_ms.is_some() && severities.contains(&p.severity.as_str()))
+    };
+    let Some(root) = timed(&["critical", "error"]).or_else(|| timed(&["warning"])) else {
+        return;
+    };
+    let category = patterns[root].category.clone();
+    let mut consequences = 0;
+    for p in patterns[root + 1..]
+        .iter_mut()
+        .filter(|p| p.first_seen_ms.is_some())
+    {
+        p.caused_by = Some(category.clone());
+        consequences += 1;
+    }
+    // A lone pattern is trivially first; only call it a root cause when
+    // something followed from it.
+    patterns[root].root_cause = consequences > 0;
+}
+
+/// A stream's text with the offset each chunk ends at, since programs
+/// often write a line in several pieces.
+struct StreamText {
+    text: String,
+    ends: Vec<(usize, i64)>,
+}
+
+impl StreamText {
+    fn new(chunks: &[StdioQueryResult]) -> Self {
+        let mut text = String::new();
+        let mut ends = Vec::with_capacity(chunks.len());
+        for c in chunks {
+            text.push_str(&c.text);
+            ends.push((text.len(), c.ts));
+        }
+        Self { text, ends }
+    }
+
+    /// Timestamp of the chunk that completed the first occurrence of `key`.
+    fn find(&self, key: &str) -> Option<i64> {
+        let end = self.text.find(key)? + key.len();
+        let i = self.ends.partition_point(|(e, _)| *e < end);
+        self.ends.get(i).map(|(_, ts)| *ts)
+    }
+}
+
+/// Run-relative ms of the earliest failed file op, failed connection or
+/// signal the pattern mentions, or of the output that printed one of its
+/// examples.
+fn first_seen(p: &ErrorPattern, output: &ExplainOutput, streams: &[StreamText]) -> Option<f64> {
+    let mentions = |needle: &str| {
+        needle.len() > 1
+            && (p.description.contains(needle) || p.examples.iter().any(|e| e.contains(needle)))
+    };
+
+    let files = output
+        .file_activity
+        .failed_opens
+        .iter()
+        .chain(&output.file_activity.permission_errors)
+        .chain(&output.file_activity.exclusive_creates)
+        .chain(&output.file_activity.space_errors)
+        .filter(|f| mentions(&f.path))
+        .map(|f| f.ts_ms);
+    let connections = output
+        .net_activity
+        .failed_connections
+        .iter()
+        .chain(&output.server_activity.bind_errors)
+        .filter(|c| mentions(&c.addr))
+        .map(|c| c.ts_ms);
+    let signals = output
+        .signals
+        .deliveries
+        .iter()
+        .filter(|d| mentions(&d.signal))
+        .map(|d| d.ts_ms);
+    let lines = p
+        .examples
+        .iter()
+        .filter_map(|e| output_key(e))
+        .flat_map(|key| streams.iter().filter_map(move |s| s.find(key)))
+        .map(|ts| ts as f64 / 1_000_000.0);
+
+    files
+        .chain(connections)
+        .chain(signals)
+        .chain(lines)
+        .min_by(f64::total_cmp)
+}
+
+/// The part of an example that would appear verbatim in the command's
+/// output: examples are often the offending line with a label in front or
+/// a location appended.
+fn output_key(example: &str) -> Option<&str> {
+    let mut key = example.trim();
+    key = key.strip_prefix("panic: ").unwrap_or(key);
+    for sep in [" at ", " -> ", " in thread "] {
+        if let Some(pos) = key.find(sep) {
+            key = &key[..pos];
+        }
+    }
+    let key = key.trim();
+    (key.len() >= MIN_KEY_LEN).then_some(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn output_keys_drop_labels_and_locations() {
+        assert_eq!(
+            output_key("panic: connection lost at src/main.rs:4:5 in thread 'main'"),
+            Some("connection lost")
+        );
+        assert_eq!(
+            output_key("ValueError: bad port at app.py:3 in main"),
+            Some("ValueError: bad port")
+        );
+        assert_eq!(output_key("  oom "), None);
+    }
+
+    #[test]
+    fn refused_connection_precedes_the_panic_it_caused() {
+        let output: ExplainOutput = serde_json::from_value(serde_json::json!({
+            "failure": null,
+            "timeline": {"merged": [], "last_file_ops": [], "last_net_ops": [], "duration_ms": 0},
+            "hotspots": [],
+            "file_activity": {"total_ops": 0, "unique_paths": 0, "most_accessed": [],
+                "total_bytes_read": 0, "total_bytes_written": 0, "failed_opens": [],
+                "permission_errors": []},
+            "net_activity": {"total_ops": 1, "connections": [], "total_bytes_sent": 0,
+                "total_bytes_received": 0, "failed_connections": [{"addr": "127.0.0.1:5432",
+                "errno": 111, "errno_name": "ECONNREFUSED", "ts_ms": 12.0, "pid": 7}]},
+            "process_tree": [],
+            "error_patterns": [],
+            "python_exceptions": [],
+            "rust_panic": null,
+            "stderr_tail": null,
+            "stdout_tail": null,
+            "truncation": null
+        }))
+        .unwrap();
+        let pattern = |category: &str, example: &str| ErrorPattern {
+            category: category.into(),
+            severity: "error".into(),
+            description: String::new(),
+            count: 1,
+            examples: vec![example.into()],
+            ..Default::default()
+        };
+        let mut patterns = vec![
+            pattern("rust_panic", "panic: db unavailable at src/main.rs:9:5"),
+            pattern("oom", "nothing like this was printed"),
+            pattern("network", "connect 127.0.0.1:5432 -> ECONNREFUSED"),
+        ];
+        let chunk = |ts: i64, text: &str| StdioQueryResult {
+            ts,
+            proc_id: 7,
+            text: text.into(),
+        };
+        let stderr = vec![
+            chunk(
+                30_000_000,
+                "thread 'main' panicked at src/main.rs:9:5:\ndb ",
+            ),
+            chunk(40_000_000, "unavailable\n"),
+        ];
+
+        order(&mut patterns, &output, &[stderr]);
+        let categories: Vec<&str> = patterns.iter().map(|p| p.category.as_str()).collect();
+        assert_eq!(categories, ["network", "rust_panic", "oom"]);
+        assert!(patterns[0].root_cause);
+        assert_eq!(patterns[0].first_seen_ms, Some(12.0));
+        assert_eq!(patterns[1].caused_by.as_deref(), Some("network"));
+        assert_eq!(patterns[1].first_seen_ms, Some(40.0));
+        assert_eq!(patterns[2].caused_by, None);
+    }
+}
@@ -0,0 +1,202 @@
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::explain::analyzer::{self, ExplainOutput, LocationInfo};
+use crate::pack::summary::PackSummary;
+use crate::util;
+
+/// What makes two failures "the same bug": how the run ended, the most
+/// severe error pattern, and where it failed. Counts, messages and
+/// timestamps are left out since they vary between occurrences.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Fingerprint {
+    /// Signal name, or `exit N` when the run exited on its own.
+    pub status: Option<String>,
+    /// Category of the most severe error pattern.
+    pub pattern: Option<String>,
+    /// `file:line` of the panic, exception or failure location; relative
+    /// to the run's working directory when under it, so checkouts in
+    /// different places still match.
+    pub location: Option<String>,
+}
+
+/// A pack as seen by the clustering, independent of where it is stored.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClusterMember {
+    pub run_id: String,
+    pub pack: String,
+    pub timestamp: String,
+    pub command: Vec<String>,
+    pub description: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Cluster {
+    pub id: String,
+    pub fingerprint: Fingerprint,
+    pub count: usize,
+    pub first_seen: String,
+    pub last_seen: String,
+    /// The most recent occurrence.
+    pub latest: ClusterMember,
+    /// Oldest first.
+    pub packs: Vec<String>,
+}
+
+impl Fingerprint {
+    /// `None` when the run did not fail.
+    pub fn compute(summary: &PackSummary, output: &ExplainOutput) -> Option<Self> {
+        let failure = output.failure.as_ref()?;
+        let status = match (&summary.signal_name, summary.exit_code) {
+            (Some(sig), _) => Some(sig.clone()),
+            (None, Some(code)) => Some(format!("exit {}", code)),
+            (None, None) => failure.signal.clone(),
+        };
+        let pattern = output
+            .error_patterns
+            .iter()
+            .min_by_key(|p| severity_rank(&p.severity))
+            .map(|p| p.category.clone());
+
+        let root = Path::new(&summary.working_dir);
+        let location = match output.rust_panic.as_ref().and_then(|p| p.location.as_ref()) {
+            Some(loc) => Some(format!("{}:{}", relative(&loc.file, root), loc.line)),
+            None => output
+                .python_exceptions
+                .last()
+                .and_then(analyzer::python_exception_location)
+                .or_else(|| failure.primary_location.clone())
+                .and_then(|loc| location_text(&loc, root)),
+        };
+
+        Some(Self {
+            status,
+            pattern,
+            location,
+        })
+    }
+
+    /// Short stable id for the fingerprint.
+    pub fn id(&self) -> String {
+        let key = format!(
+            "{}\0{}\0{}",
+            self.status.as_deref().unwrap_or(""),
+            self.pattern.as_deref().unwrap_or(""),
+            self.location.as_deref().unwrap_or("")
+        );
+        util::hash_bytes(key.as_bytes())[..12].to_string()
+    }
+
+    pub fn label(&self) -> String {
+        [&self.status, &self.pattern, &self.location]
+            .iter()
+            .filter_map(|p| p.as_deref())
+            .collect::<Vec<_>>()
+            .join(" / ")
+    }
+}
+
+fn severity_rank(severity: &str) -> u8 {
+    match severity {
+        "critical" => 0,
+        "error" => 1,
+        "warning" => 2,
+        _ => 3,
+    }
+}
+
+fn location_text(loc: &LocationInfo, root: &Path) -> Option<String> {
+    let file = relative(loc.file.as_deref()?, root);
+    Some(match loc.line {
+        Some(line) => format!("{}:{}", file, line),
+        None => file,
+    })
+}
+
+fn relative(file: &str, root: &Path) -> String {
+    match Path::new(file).strip_prefix(root) {
+        Ok(rel) if !root.as_os_str().is_empty() => rel.to_string_lossy().into_owned(),
+        _ => file.to_string(),
+    }
+}
+
+/// Groups failing packs by fingerprint, largest clusters first and, among
+/// equal sizes, the most recently seen.
+pub fn group(mut members: Vec<(Fingerprint, ClusterMember)>) -> Vec<Cluster> {
+    members.sort_by(|a, b| a.1.timestamp.cmp(&b.1.timestamp));
+    let mut clusters: Vec<Cluster> = Vec::new();
+    for (fingerprint, member) in members {
+        match clusters.iter_mut().find(|c| c.fingerprint == fingerprint) {
+            Some(c) => {
+                c.count += 1;
+                c.packs.push(member.pack.clone());
+                c.last_seen = member.timestamp.clone();
+                c.latest = member;
+            }
+            None => clusters.push(Cluster {
+                id: fingerprint.id(),
+                fingerprint,
+                count: 1,
+                first_seen: member.timestamp.clone(),
+                last_seen: member.timestamp.clone(),
+                packs: vec![member.pack.clone()],
+                latest: member,
+            }),
+        }
+    }
+    clusters.sort_by(|a, b| b.count.cmp(&a.count).then(b.last_seen.cmp(&a.last_seen)));
+    clusters
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn member(run_id: &str, timestamp: &str) -> ClusterMember {
+        ClusterMember {
+            run_id: run_id.into(),
+            pack: format!("{}.poepack", run_id),
+            timestamp: timestamp.into(),
+            command: vec!["./app".into()],
+            description: String::new(),
+        }
+    }
+
+    #[test]
+    fn groups_by_fingerprint_and_tracks_first_and_last_seen() {
+        let segv = Fingerprint {
+            status: Some("SIGSEGV".into()),
+            pattern: Some("crash".into()),
+            location: Some("src/main.c:12".into()),
+        };
+        let exit = Fingerprint {
+            status: Some("exit 1".into()),
+            pattern: None,
+            location: None,
+        };
+        let clusters = group(vec![
+            (segv.clone(), member("b", "2024-01-02T00:00:00Z")),
+            (exit.clone(), member("c", "2024-01-05T00:00:00Z")),
+            (segv.clone(), member("a", "2024-01-01T00:00:00Z")),
+            (segv.clone(), member("d", "2024-01-03T00:00:00Z")),
+        ]);
+        assert_eq!(clusters.len(), 2);
+        assert_eq!(clusters[0].fingerprint, segv);
+        assert_eq!(clusters[0].count, 3);
+        assert_eq!(clusters[0].first_seen, "2024-01-01T00:00:00Z");
+        assert_eq!(clusters[0].last_seen, "2024-01-03T00:00:00Z");
+        assert_eq!(clusters[0].latest.run_id, "d");
+        assert_eq!(clusters[0].id, segv.id());
+        assert_ne!(segv.id(), exit.id());
+        assert_eq!(segv.label(), "SIGSEGV / crash / src/main.c:12");
+    }
+
+    #[test]
+    fn locations_are_relative_to_the_working_dir() {
+        let root = Path::new("/home/runner/work/app");
+        assert_eq!(relative("/home/runner/work/app/src/x.py", root), "src/x.py");
+        assert_eq!(relative("/usr/lib/x.py", root), "/usr/lib/x.py");
+        assert_eq!(relative("src/lib.rs", root), "src/lib.rs");
+    }
+}
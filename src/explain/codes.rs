@@ -0,0 +1,187 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// Stable identifiers of the built-in error patterns, for automation to
+/// branch on instead of categories and descriptions. Codes are never reused
+/// or renumbered; the thousands digit groups them by area.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize, JsonSchema,
+)]
+pub enum PatternCode {
+    #[serde(rename = "POE1001")]
+    CrashSegv,
+    #[serde(rename = "POE1002")]
+    CrashAbort,
+    #[serde(rename = "POE1003")]
+    CrashBus,
+    #[serde(rename = "POE1004")]
+    CrashFpe,
+    #[serde(rename = "POE1005")]
+    CrashMultiple,
+    #[serde(rename = "POE1006")]
+    SignalSupervisorKill,
+    #[serde(rename = "POE1007")]
+    SignalSigpipeStorm,
+    #[serde(rename = "POE2001")]
+    NetConnectFailed,
+    #[serde(rename = "POE2002")]
+    NetRpcFailed,
+    #[serde(rename = "POE2003")]
+    NetConnRefused,
+    #[serde(rename = "POE2004")]
+    NetConnTimedOut,
+    #[serde(rename = "POE2005")]
+    NetUnreachable,
+    #[serde(rename = "POE2006")]
+    NetPortConflict,
+    #[serde(rename = "POE2007")]
+    NetRestartRace,
+    #[serde(rename = "POE3001")]
+    FilePermission,
+    #[serde(rename = "POE3002")]
+    FileMissing,
+    #[serde(rename = "POE3003")]
+    FileExclusiveCreate,
+    #[serde(rename = "POE3004")]
+    FileDiskFull,
+    #[serde(rename = "POE3005")]
+    FileReadOnly,
+    #[serde(rename = "POE3006")]
+    FileRace,
+    #[serde(rename = "POE4001")]
+    PythonException,
+    #[serde(rename = "POE4002")]
+    RustPanic,
+    #[serde(rename = "POE4003")]
+    RustOom,
+    #[serde(rename = "POE4004")]
+    RustStackOverflow,
+    #[serde(rename = "POE5001")]
+    OutputOom,
+    #[serde(rename = "POE5002")]
+    OutputTimeout,
+    #[serde(rename = "POE5003")]
+    OutputException,
+    #[serde(rename = "POE6001")]
+    KernelOomKill,
+    #[serde(rename = "POE6002")]
+    KernelIoError,
+    #[serde(rename = "POE6003")]
+    KernelSynFlood,
+}
+
+impl PatternCode {
+    pub const ALL: [PatternCode; 30] = [
+        PatternCode::CrashSegv,
+        PatternCode::CrashAbort,
+        PatternCode::CrashBus,
+        PatternCode::CrashFpe,
+        PatternCode::CrashMultiple,
+        PatternCode::SignalSupervisorKill,
+        PatternCode::SignalSigpipeStorm,
+        PatternCode::NetConnectFailed,
+        PatternCode::NetRpcFailed,
+        PatternCode::NetConnRefused,
+        PatternCode::NetConnTimedOut,
+        PatternCode::NetUnreachable,
+        PatternCode::NetPortConflict,
+        PatternCode::NetRestartRace,
+        PatternCode::FilePermission,
+        PatternCode::FileMissing,
+        PatternCode::FileExclusiveCreate,
+        PatternCode::FileDiskFull,
+        PatternCode::FileReadOnly,
+        PatternCode::FileRace,
+        PatternCode::PythonException,
+        PatternCode::RustPanic,
+        PatternCode::RustOom,
+        PatternCode::RustStackOverflow,
+        PatternCode::OutputOom,
+        PatternCode::OutputTimeout,
+        PatternCode::OutputException,
+        PatternCode::KernelOomKill,
+        PatternCode::KernelIoError,
+        PatternCode::KernelSynFlood,
+    ];
+
+    /// `(code, name)`, e.g. `("POE1001", "crash.segv")`.
+    fn entry(self) -> (&'static str, &'static str) {
+        match self {
+            PatternCode::CrashSegv => ("POE1001", "crash.segv"),
+            PatternCode::CrashAbort => ("POE1002", "crash.abort"),
+            PatternCode::CrashBus => ("POE1003", "crash.bus"),
+            PatternCode::CrashFpe => ("POE1004", "crash.fpe"),
+            PatternCode::CrashMultiple => ("POE1005", "crash.multiple"),
+            PatternCode::SignalSupervisorKill => ("POE1006", "signal.supervisor_kill"),
+            PatternCode::SignalSigpipeStorm => ("POE1007", "signal.sigpipe_storm"),
+            PatternCode::NetConnectFailed => ("POE2001", "net.connect_failed"),
+            PatternCode::NetRpcFailed => ("POE2002", "net.rpc_failed"),
+            PatternCode::NetConnRefused => ("POE2003", "net.conn_refused"),
+            PatternCode::NetConnTimedOut => ("POE2004", "net.conn_timed_out"),
+            PatternCode::NetUnreachable => ("POE2005", "net.unreachable"),
+            PatternCode::NetPortConflict => ("POE2006", "net.port_conflict"),
+            PatternCode::NetRestartRace => ("POE2007", "net.restart_race"),
+            PatternCode::FilePermission => ("POE3001", "file.permission"),
+            PatternCode::FileMissing => ("POE3002", "file.missing"),
+            PatternCode::FileExclusiveCreate => ("POE3003", "file.exclusive_create"),
+            PatternCode::FileDiskFull => ("POE3004", "file.disk_full"),
+            PatternCode::FileReadOnly => ("POE3005", "file.read_only_fs"),
+            PatternCode::FileRace => ("POE3006", "file.race"),
+            PatternCode::PythonException => ("POE4001", "python.exception"),
+            PatternCode::RustPanic => ("POE4002", "rust.panic"),
+            PatternCode::RustOom => ("POE4003", "rust.oom"),
+            PatternCode::RustStackOverflow => ("POE4004", "rust.stack_overflow"),
+            PatternCode::OutputOom => ("POE5001", "output.oom"),
+            PatternCode::OutputTimeout => ("POE5002", "output.timeout"),
+            PatternCode::OutputException => ("POE5003", "output.exception"),
+            PatternCode::KernelOomKill => ("POE6001", "kernel.oom_kill"),
+            PatternCode::KernelIoError => ("POE6002", "kernel.io_error"),
+            PatternCode::KernelSynFlood => ("POE6003", "kernel.syn_flood"),
+        }
+    }
+
+    pub fn code(self) -> &'static str {
+        self.entry().0
+    }
+
+    pub fn name(self) -> &'static str {
+        self.entry().1
+    }
+
+    /// The code for failed connects, by their most common errno.
+    pub fn for_connect_errno(errno: i32) -> Self {
+        match errno {
+            libc::ECONNREFUSED => PatternCode::NetConnRefused,
+            libc::ETIMEDOUT => PatternCode::NetConnTimedOut,
+            libc::ENETUNREACH | libc::EHOSTUNREACH => PatternCode::NetUnreachable,
+            _ => PatternCode::NetConnectFailed,
+        }
+    }
+}
+
+impl std::fmt::Display for PatternCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.code(), self.name())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn codes_are_unique_and_serialize_as_themselves() {
+        let mut seen = std::collections::HashSet::new();
+        for code in PatternCode::ALL {
+            assert!(seen.insert(code.code()), "{} reused", code.code());
+            assert_eq!(
+                serde_json::to_value(code).unwrap(),
+                serde_json::json!(code.code())
+            );
+        }
+        assert_eq!(
+            PatternCode::for_connect_errno(libc::ECONNREFUSED).to_string(),
+            "POE2003 net.conn_refused"
+        );
+    }
+}
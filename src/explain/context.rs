@@ -0,0 +1,351 @@
+use std::collections::HashSet;
+
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::explain::analyzer::{self, ExplainOutput};
+use crate::pack::summary::PackSummary;
+
+pub const DEFAULT_BUDGET_TOKENS: usize = 8000;
+const MAX_TIMELINE: usize = 25;
+const MAX_FRAMES: usize = 10;
+const MAX_STDERR_LINES: usize = 30;
+const MAX_FAILED_OPS: usize = 10;
+const MAX_LINE_CHARS: usize = 300;
+
+/// A prioritized, size-bounded digest of a pack meant to be pasted into an
+/// LLM prompt or returned from an agent tool call.
+#[derive(Debug, Clone, Serialize)]
+pub struct ContextBlock {
+    pub budget_tokens: usize,
+    pub used_tokens: usize,
+    pub sections: Vec<ContextSection>,
+    pub omitted: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ContextSection {
+    pub title: String,
+    pub lines: Vec<String>,
+    #[serde(skip)]
+    code: bool,
+}
+
+struct Candidate {
+    title: &'static str,
+    lines: Vec<String>,
+    code: bool,
+    /// Keep the last lines rather than the first when trimming.
+    keep_tail: bool,
+}
+
+/// Accepts `8000`, `8000tokens`, `8k` or `8ktokens`.
+pub fn parse_budget(s: &str) -> Result<usize> {
+    let lower = s.trim().to_ascii_lowercase();
+    let num = lower
+        .strip_suffix("tokens")
+        .or_else(|| lower.strip_suffix("tok"))
+        .unwrap_or(&lower)
+        .trim();
+    let (digits, scale) = match num.strip_suffix('k') {
+        Some(d) => (d, 1000),
+        None => (num, 1),
+    };
+    let value: usize = digits
+        .parse()
+        .map_err(|_| anyhow::anyhow!("invalid token budget: {}", s))?;
+    if value == 0 {
+        anyhow::bail!("token budget must be positive");
+    }
+    Ok(value * scale)
+}
+
+/// Rough token count; ~4 bytes per token holds for English text and code.
+pub fn estimate_tokens(text: &str) -> usize {
+    text.len().div_ceil(4)
+}
+
+pub fn build_context(
+    summary: &PackSummary,
+    output: &ExplainOutput,
+    budget_tokens: usize,
+) -> ContextBlock {
+    let mut block = ContextBlock {
+        budget_tokens,
+        used_tokens: 0,
+        sections: Vec::new(),
+        omitted: Vec::new(),
+    };
+    let mut seen: HashSet<String> = HashSet::new();
+
+    for candidate in candidates(summary, output) {
+        let title_cost = estimate_tokens(candidate.title) + 2;
+        let mut lines: Vec<String> = candidate
+            .lines
+            .into_iter()
+            .map(|l| clip(&l))
+            .filter(|l| !l.trim().is_empty() && seen.insert(l.trim().to_string()))
+            .collect();
+        if lines.is_empty() {
+            continue;
+        }
+        if candidate.keep_tail {
+            lines.reverse();
+        }
+
+        let mut used = title_cost;
+        let mut kept = Vec::new();
+        for line in lines {
+            let cost = estimate_tokens(&line) + 1;
+            if block.used_tokens + used + cost > budget_tokens {
+                break;
+            }
+            used += cost;
+            kept.push(line);
+        }
+        if candidate.keep_tail {
+            kept.reverse();
+        }
+
+        if kept.is_empty() {
+            block.omitted.push(candidate.title.to_string());
+            continue;
+        }
+        block.used_tokens += used;
+        block.sections.push(ContextSection {
+            title: candidate.title.to_string(),
+            lines: kept,
+            code: candidate.code,
+        });
+    }
+
+    block
+}
+
+fn candidates(summary: &PackSummary, output: &ExplainOutput) -> Vec<Candidate> {
+    let mut out = Vec::new();
+
+    let mut run = vec![format!("command: {}", summary.command.join(" "))];
+    match (&summary.signal_name, summary.exit_code) {
+        (Some(sig), _) => run.push(format!("killed by {}", sig)),
+        (None, Some(code)) => run.push(format!("exit code: {}", code)),
+        _ => {}
+    }
+    run.push(format!("duration: {}ms", summary.duration_ms));
+    run.push(format!("working dir: {}", summary.working_dir));
+    if let Some(ref sha) = summary.git_sha {
+        run.push(format!("git: {}", sha));
+    }
+    out.push(section("Run", run));
+
+    if let Some(ref f) = output.failure {
+        let mut lines = vec![format!("{}: {}", f.kind, f.description)];
+        if let Some(ref loc) = f.primary_location {
+            let mut at = String::new();
+            if let Some(ref func) = loc.function {
+                at.push_str(func);
+            }
+            if let Some(ref file) = loc.file {
+                at.push_str(&format!(" at {}", file));
+                if let Some(line) = loc.line {
+                    at.push_str(&format!(":{}", line));
+                }
+            }
+            if !at.is_empty() {
+                lines.push(format!("location: {}", at.trim()));
+            }
+        }
+        out.push(section("Failure", lines));
+    }
+
+    let mut diagnosis = Vec::new();
+    for p in &output.error_patterns {
+        let mut line = format!("[{}] {}: {}", p.severity, p.category, p.description);
+        if p.root_cause {
+            line.push_str(" [probable root cause]");
+        } else if let Some(ref cause) = p.caused_by {
+            line.push_str(&format!(" [after {}]", cause));
+        }
+        if let Some(example) = p.examples.first() {
+            line.push_str(&format!(" (e.g. {})", example));
+        }
+        diagnosis.push(line);
+    }
+    out.push(section("Diagnosis", diagnosis));
+
+    out.push(section(
+        "Suggestions",
+        output
+            .suggestions
+            .iter()
+            .map(|s| match s.action {
+                Some(ref a) => format!("{} (try: {})", s.message, a),
+                None => s.message.clone(),
+            })
+            .collect(),
+    ));
+
+    if let Some(exc) = output.python_exceptions.last() {
+        let mut lines = vec![format!("{}: {}", exc.exc_type, exc.exc_msg)];
+        for entry in exc.chain.iter().rev().skip(1) {
+            lines.push(format!("caused by {}: {}", entry.exc_type, entry.msg));
+        }
+        let skip = exc.traceback.len().saturating_sub(MAX_FRAMES);
+        for frame in exc.traceback.iter().skip(skip) {
+            lines.push(format!("{}:{} in {}", frame.file, frame.line, frame.func));
+        }
+        if let Some(ref locals) = exc.locals_at_crash {
+            let mut names: Vec<&String> = locals.keys().collect();
+            names.sort();
+            for name in names {
+                lines.push(format!("local {} = {}", name, locals[name]));
+            }
+        }
+        out.push(Candidate {
+            title: "Python exception",
+            lines,
+            code: true,
+            keep_tail: false,
+        });
+    }
+
+    if let Some(ref panic) = output.rust_panic {
+        let mut lines = vec![format!("panic: {}", panic.message)];
+        if let Some(ref loc) = panic.location {
+            lines.push(format!("at {}:{}", loc.file, loc.line));
+        }
+        for frame in panic.backtrace.iter().filter(|f| {
+            let sym = f.symbol.as_deref().unwrap_or("");
+            !sym.is_empty()
+                && !sym.contains("std::")
+                && !sym.contains("core::")
+                && !sym.contains("__rust_")
+        }) {
+            let sym = frame.symbol.as_deref().unwrap_or("???");
+            match (&frame.file, frame.line) {
+                (Some(f), Some(l)) => lines.push(format!("{} at {}:{}", sym, f, l)),
+                _ => lines.push(sym.to_string()),
+            }
+        }
+        out.push(Candidate {
+            title: "Rust panic",
+            lines: lines.into_iter().take(MAX_FRAMES + 2).collect(),
+            code: true,
+            keep_tail: false,
+        });
+    }
+
+    if let Some(ref stderr) = output.stderr_tail {
+        let lines: Vec<&str> = stderr.lines().collect();
+        let skip = lines.len().saturating_sub(MAX_STDERR_LINES);
+        out.push(Candidate {
+            title: "stderr (tail)",
+            lines: lines.iter().skip(skip).map(|l| l.to_string()).collect(),
+            code: true,
+            keep_tail: true,
+        });
+    }
+
+    let mut failed = Vec::new();
+    for f in output
+        .file_activity
+        .failed_opens
+        .iter()
+        .filter(|f| analyzer::is_significant_missing_file(&f.path))
+        .chain(&output.file_activity.permission_errors)
+    {
+        failed.push(format!(
+            "[{}] {} {} -> {}",
+            f.pid, f.op, f.path, f.errno_name
+        ));
+    }
+    for c in &output.net_activity.failed_connections {
+        failed.push(format!(
+            "[{}] connect {} -> {}",
+            c.pid, c.addr, c.errno_name
+        ));
+    }
+    for b in &output.server_activity.bind_errors {
+        failed.push(format!("[{}] bind {} -> {}", b.pid, b.addr, b.errno_name));
+    }
+    failed.truncate(MAX_FAILED_OPS);
+    out.push(section("Failed operations", failed));
+
+    let merged = &output.timeline.merged;
+    let skip = merged.len().saturating_sub(MAX_TIMELINE);
+    out.push(Candidate {
+        title: "Last events",
+        lines: merged
+            .iter()
+            .skip(skip)
+            .map(|e| {
+                format!(
+                    "{:.1}ms [{}] {} {}",
+                    e.ts_ms, e.proc_id, e.kind, e.description
+                )
+            })
+            .collect(),
+        code: false,
+        keep_tail: true,
+    });
+
+    out
+}
+
+fn section(title: &'static str, lines: Vec<String>) -> Candidate {
+    Candidate {
+        title,
+        lines,
+        code: false,
+        keep_tail: false,
+    }
+}
+
+fn clip(line: &str) -> String {
+    if line.chars().count() <= MAX_LINE_CHARS {
+        return line.to_string();
+    }
+    let mut clipped: String = line.chars().take(MAX_LINE_CHARS).collect();
+    clipped.push('…');
+    clipped
+}
+
+pub fn render_markdown(block: &ContextBlock) -> String {
+    let mut md = String::from("# poe failure context\n");
+    for section in &block.sections {
+        md.push_str(&format!("\n## {}\n", section.title));
+        if section.code {
+            md.push_str("```\n");
+            for line in &section.lines {
+                md.push_str(line);
+                md.push('\n');
+            }
+            md.push_str("```\n");
+        } else {
+            for line in &section.lines {
+                md.push_str(&format!("- {}\n", line));
+            }
+        }
+    }
+    if !block.omitted.is_empty() {
+        md.push_str(&format!(
+            "\n_omitted for budget: {}_\n",
+            block.omitted.join(", ")
+        ));
+    }
+    md
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_budget_forms() {
+        assert_eq!(parse_budget("8000").unwrap(), 8000);
+        assert_eq!(parse_budget("8000tokens").unwrap(), 8000);
+        assert_eq!(parse_budget("4k").unwrap(), 4000);
+        assert!(parse_budget("lots").is_err());
+        assert!(parse_budget("0").is_err());
+    }
+}
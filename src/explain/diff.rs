@@ -1,13 +1,24 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::path::Path;
+use std::sync::OnceLock;
 
 use anyhow::Result;
+use regex::Regex;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
+use crate::capture::control;
+use crate::events::types::FileOpKind;
+use crate::explain::analyzer::{self, ErrorPattern};
+use crate::explain::codes::PatternCode;
+use crate::explain::patterns::PatternRegistry;
 use crate::pack::reader::PackReader;
+use crate::redact::Redactor;
+use crate::trace::cacheops::{self, CacheCommand};
 use crate::trace::db::*;
+use crate::util::sandbox::Sandbox;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct DiffOutput {
     pub baseline_id: String,
     pub candidate_id: String,
@@ -17,22 +28,37 @@ pub struct DiffOutput {
     pub process_diff: ProcessDiff,
     pub file_diff: FileDiff,
     pub net_diff: NetDiff,
+    /// Present when either run was captured with `--capture-cache`.
+    #[serde(default)]
+    pub cache_diff: Option<CacheDiff>,
     pub stderr_diff: Option<StderrDiff>,
+    /// Environment variables that differ between the runs.
+    #[serde(default)]
+    pub env_diff: Option<EnvDiff>,
+    /// Capabilities, namespaces, seccomp, cgroup and ulimits that differ.
+    #[serde(default)]
+    pub sandbox_diff: Vec<SandboxChange>,
+    /// Built-in error patterns found in the candidate but not the baseline,
+    /// and the other way round, matched by code.
+    #[serde(default)]
+    pub new_patterns: Vec<PatternChange>,
+    #[serde(default)]
+    pub resolved_patterns: Vec<PatternChange>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct ExitCodeDiff {
     pub baseline: Option<i32>,
     pub candidate: Option<i32>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct SignalDiff {
     pub baseline: Option<String>,
     pub candidate: Option<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct DurationDiff {
     pub baseline_ms: u64,
     pub candidate_ms: u64,
@@ -40,7 +66,7 @@ pub struct DurationDiff {
     pub delta_pct: f64,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct ProcessDiff {
     pub baseline_count: usize,
     pub candidate_count: usize,
@@ -48,7 +74,7 @@ pub struct ProcessDiff {
     pub missing_processes: Vec<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct FileDiff {
     pub baseline_ops: i64,
     pub candidate_ops: i64,
@@ -59,16 +85,29 @@ pub struct FileDiff {
     pub baseline_bytes_written: u64,
     pub candidate_bytes_written: u64,
     pub new_errors: Vec<FileErrorDiff>,
+    #[serde(default)]
+    pub content_changes: Vec<ContentDiff>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// A file both runs wrote (captured with `--hash-writes`) whose final
+/// contents differ.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ContentDiff {
+    pub path: String,
+    pub baseline_hash: Option<String>,
+    pub candidate_hash: Option<String>,
+    pub baseline_size: u64,
+    pub candidate_size: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct FileErrorDiff {
     pub path: String,
     pub op: String,
     pub result: i64,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct NetDiff {
     pub baseline_ops: i64,
     pub candidate_ops: i64,
@@ -81,14 +120,79 @@ pub struct NetDiff {
     pub new_errors: Vec<NetErrorDiff>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct NetErrorDiff {
     pub addr: String,
     pub op: String,
     pub result: i64,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct CacheDiff {
+    pub baseline_ops: usize,
+    pub candidate_ops: usize,
+    pub baseline_misses: usize,
+    pub candidate_misses: usize,
+    /// Keys the baseline found that the candidate missed.
+    pub new_misses: Vec<String>,
+    /// `COMMAND key` pairs issued by only one of the runs.
+    pub new_commands: Vec<String>,
+    pub missing_commands: Vec<String>,
+    pub new_errors: Vec<String>,
+    pub size_changes: Vec<CacheSizeDiff>,
+}
+
+/// A command on the same key that sent or got back a different number of
+/// bytes; the last occurrence in each run is compared.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct CacheSizeDiff {
+    pub command: String,
+    pub key: String,
+    pub baseline_bytes: u64,
+    pub candidate_bytes: u64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct EnvDiff {
+    pub added: Vec<EnvVar>,
+    pub removed: Vec<EnvVar>,
+    pub changed: Vec<EnvChange>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct EnvVar {
+    pub name: String,
+    pub value: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct EnvChange {
+    pub name: String,
+    pub baseline: String,
+    pub candidate: String,
+    /// For PATH-like lists, the entries only the candidate has...
+    #[serde(default)]
+    pub added_entries: Vec<String>,
+    /// ...and the entries only the baseline has.
+    #[serde(default)]
+    pub removed_entries: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct PatternChange {
+    pub code: PatternCode,
+    pub category: String,
+    pub description: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct SandboxChange {
+    pub name: String,
+    pub baseline: String,
+    pub candidate: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct StderrDiff {
     pub baseline_lines: Vec<String>,
     pub candidate_lines: Vec<String>,
@@ -138,9 +242,20 @@ pub fn diff_packs(baseline_path: &Path, candidate_path: &Path) -> Result<DiffOut
     };
 
     let process_diff = diff_processes(bdb, cdb)?;
-    let file_diff = diff_files(bdb, cdb)?;
+    let mut file_diff = diff_files(bdb, cdb)?;
+    file_diff.content_changes = diff_written_contents(bdb, &bs.working_dir, cdb, &cs.working_dir)?;
     let net_diff = diff_net(bdb, cdb)?;
+    let cache_diff = diff_cache(bdb, cdb)?;
     let stderr_diff = diff_stderr(&baseline, &candidate);
+    let env_diff = match (pack_env(&baseline), pack_env(&candidate)) {
+        (Some(b), Some(c)) => diff_env(&b, &c),
+        _ => None,
+    };
+    let sandbox_diff = match (pack_sandbox(&baseline), pack_sandbox(&candidate)) {
+        (Some(b), Some(c)) => diff_sandbox(&b, &c),
+        _ => Vec::new(),
+    };
+    let (new_patterns, resolved_patterns) = diff_patterns(&baseline, &candidate);
 
     Ok(DiffOutput {
         baseline_id: bs.run_id.clone(),
@@ -151,38 +266,54 @@ pub fn diff_packs(baseline_path: &Path, candidate_path: &Path) -> Result<DiffOut
         process_diff,
         file_diff,
         net_diff,
+        cache_diff,
         stderr_diff,
+        env_diff,
+        sandbox_diff,
+        new_patterns,
+        resolved_patterns,
     })
 }
 
 fn diff_processes(bdb: &TraceDb, cdb: &TraceDb) -> Result<ProcessDiff> {
-    let bp = bdb.query_processes()?;
-    let cp = cdb.query_processes()?;
-
-    let b_cmds: HashSet<String> = bp
-        .iter()
-        .filter_map(|p| {
-            p.argv.as_ref().and_then(|a| {
+    let bp = captured_processes(bdb)?;
+    let cp = captured_processes(cdb)?;
+
+    // Normalized command -> the first raw command line seen for it, which
+    // is what gets shown.
+    let commands = |processes: &[ProcessQueryResult]| {
+        let mut commands: Vec<(String, String)> = Vec::new();
+        let mut seen = HashSet::new();
+        for p in processes {
+            let Some(cmd) = p.argv.as_ref().and_then(|a| {
                 serde_json::from_str::<Vec<String>>(a)
                     .ok()
                     .map(|v| v.join(" "))
-            })
-        })
-        .collect();
+            }) else {
+                continue;
+            };
+            let key = normalize_command(&cmd);
+            if seen.insert(key.clone()) {
+                commands.push((key, cmd));
+            }
+        }
+        commands
+    };
+    let b_cmds = commands(&bp);
+    let c_cmds = commands(&cp);
+    let b_keys: HashSet<&str> = b_cmds.iter().map(|(k, _)| k.as_str()).collect();
+    let c_keys: HashSet<&str> = c_cmds.iter().map(|(k, _)| k.as_str()).collect();
 
-    let c_cmds: HashSet<String> = cp
+    let new_processes: Vec<String> = c_cmds
         .iter()
-        .filter_map(|p| {
-            p.argv.as_ref().and_then(|a| {
-                serde_json::from_str::<Vec<String>>(a)
-                    .ok()
-                    .map(|v| v.join(" "))
-            })
-        })
+        .filter(|(k, _)| !b_keys.contains(k.as_str()))
+        .map(|(_, raw)| raw.clone())
+        .collect();
+    let missing_processes: Vec<String> = b_cmds
+        .iter()
+        .filter(|(k, _)| !c_keys.contains(k.as_str()))
+        .map(|(_, raw)| raw.clone())
         .collect();
-
-    let new_processes: Vec<String> = c_cmds.difference(&b_cmds).cloned().collect();
-    let missing_processes: Vec<String> = b_cmds.difference(&c_cmds).cloned().collect();
 
     Ok(ProcessDiff {
         baseline_count: bp.len(),
@@ -192,6 +323,49 @@ fn diff_processes(bdb: &TraceDb, cdb: &TraceDb) -> Result<ProcessDiff> {
     })
 }
 
+/// Processes, less those started while the program had capture turned
+/// off: setup steps it asked to leave out of comparisons.
+fn captured_processes(db: &TraceDb) -> Result<Vec<ProcessQueryResult>> {
+    let paused = control::paused_windows(db)?;
+    let mut processes = db.query_processes()?;
+    processes.retain(|p| {
+        !paused
+            .iter()
+            .any(|&(start, end)| p.start_ts >= start && p.start_ts < end)
+    });
+    Ok(processes)
+}
+
+/// Replaces the parts of a command line that change from run to run --
+/// temp paths, uuids and hex ids, ports, pids in /proc paths -- with
+/// placeholders, so the same step in two runs compares equal.
+pub fn normalize_command(cmd: &str) -> String {
+    static RULES: OnceLock<Vec<(Regex, &'static str)>> = OnceLock::new();
+    let rules = RULES.get_or_init(|| {
+        [
+            (r"(/tmp|/var/tmp|/dev/shm|/var/folders)/[^\s:=,]+", "$1/<tmp>"),
+            (r"\btmp[a-zA-Z0-9_]{6,}\b", "<tmp>"),
+            (
+                r"\b[0-9a-fA-F]{8}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{12}\b",
+                "<id>",
+            ),
+            (r"\b[0-9a-fA-F]{8,}\b", "<id>"),
+            (r"/proc/\d+/", "/proc/<pid>/"),
+            (
+                r"\b(localhost|\d{1,3}(?:\.\d{1,3}){3}|[a-zA-Z0-9-]+(?:\.[a-zA-Z0-9-]+)+|\[[0-9a-fA-F:]*\]):\d{1,5}\b",
+                "$1:<port>",
+            ),
+            (r"(--port[= ])\d{1,5}\b", "${1}<port>"),
+        ]
+        .into_iter()
+        .map(|(re, with)| (Regex::new(re).unwrap(), with))
+        .collect()
+    });
+    rules.iter().fold(cmd.to_string(), |cmd, (re, with)| {
+        re.replace_all(&cmd, *with).into_owned()
+    })
+}
+
 fn diff_files(bdb: &TraceDb, cdb: &TraceDb) -> Result<FileDiff> {
     let bf = bdb.query_file_events()?;
     let cf = cdb.query_file_events()?;
@@ -246,9 +420,58 @@ fn diff_files(bdb: &TraceDb, cdb: &TraceDb) -> Result<FileDiff> {
         baseline_bytes_written: b_written,
         candidate_bytes_written: c_written,
         new_errors,
+        content_changes: Vec::new(),
     })
 }
 
+/// Paths are compared relative to each run's working directory so runs from
+/// different checkouts still line up.
+fn diff_written_contents(
+    bdb: &TraceDb,
+    b_cwd: &str,
+    cdb: &TraceDb,
+    c_cwd: &str,
+) -> Result<Vec<ContentDiff>> {
+    let relative = |path: &str, cwd: &str| -> String {
+        match path.strip_prefix(cwd) {
+            Some(rest) if !cwd.is_empty() && rest.starts_with('/') => {
+                rest.trim_start_matches('/').to_string()
+            }
+            _ => path.to_string(),
+        }
+    };
+
+    let baseline: HashMap<String, WrittenFileQueryResult> = bdb
+        .query_written_files()?
+        .into_iter()
+        .map(|w| (relative(&w.path, b_cwd), w))
+        .collect();
+
+    let mut changes: Vec<ContentDiff> = cdb
+        .query_written_files()?
+        .into_iter()
+        .filter_map(|c| {
+            let key = relative(&c.path, c_cwd);
+            let b = baseline.get(&key)?;
+            let b_size = b.size.unwrap_or(0) as u64;
+            let c_size = c.size.unwrap_or(0) as u64;
+            let differs = match (&b.hash, &c.hash) {
+                (Some(bh), Some(ch)) => bh != ch,
+                _ => b_size != c_size,
+            };
+            differs.then(|| ContentDiff {
+                path: key,
+                baseline_hash: b.hash.clone(),
+                candidate_hash: c.hash.clone(),
+                baseline_size: b_size,
+                candidate_size: c_size,
+            })
+        })
+        .collect();
+    changes.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(changes)
+}
+
 fn sum_file_bytes(events: &[FileQueryResult]) -> (u64, u64) {
     let mut read = 0u64;
     let mut written = 0u64;
@@ -257,6 +480,10 @@ fn sum_file_bytes(events: &[FileQueryResult]) -> (u64, u64) {
             match ev.op.as_str() {
                 "read" => read += bytes as u64,
                 "write" => written += bytes as u64,
+                op if FileOpKind::is_transfer_str(op) => {
+                    read += bytes as u64;
+                    written += bytes as u64;
+                }
                 _ => {}
             }
         }
@@ -335,12 +562,89 @@ fn sum_net_bytes(events: &[NetQueryResult]) -> (u64, u64) {
     (sent, recv)
 }
 
-fn diff_stderr(baseline: &PackReader, candidate: &PackReader) -> Option<StderrDiff> {
-    let b_stderr = baseline.stderr().ok()?;
-    let c_stderr = candidate.stderr().ok()?;
+fn diff_cache(bdb: &TraceDb, cdb: &TraceDb) -> Result<Option<CacheDiff>> {
+    let bc = cacheops::compute(bdb)?;
+    let cc = cacheops::compute(cdb)?;
+    if bc.is_empty() && cc.is_empty() {
+        return Ok(None);
+    }
+
+    let label = |c: &CacheCommand| match c.key {
+        Some(ref key) => format!("{} {}", c.command, key),
+        None => c.command.clone(),
+    };
+    let b_labels: HashSet<String> = bc.iter().map(label).collect();
+    let c_labels: HashSet<String> = cc.iter().map(label).collect();
+    let mut new_commands: Vec<String> = c_labels.difference(&b_labels).cloned().collect();
+    let mut missing_commands: Vec<String> = b_labels.difference(&c_labels).cloned().collect();
+    new_commands.sort();
+    missing_commands.sort();
+
+    let b_found: HashSet<&str> = bc
+        .iter()
+        .filter(|c| c.status == "ok")
+        .filter_map(|c| c.key.as_deref())
+        .collect();
+    let mut new_misses: Vec<String> = Vec::new();
+    for c in cc.iter().filter(|c| c.status == "miss") {
+        if let Some(key) = c.key.as_deref().filter(|k| b_found.contains(k)) {
+            if !new_misses.iter().any(|m| m == key) {
+                new_misses.push(key.to_string());
+            }
+        }
+    }
+
+    let b_errors: HashSet<&str> = bc.iter().filter_map(|c| c.error.as_deref()).collect();
+    let mut new_errors: Vec<String> = Vec::new();
+    for c in &cc {
+        if let Some(err) = c.error.as_deref().filter(|e| !b_errors.contains(e)) {
+            let line = format!("{}: {}", label(c), err);
+            if !new_errors.contains(&line) {
+                new_errors.push(line);
+            }
+        }
+    }
+
+    let sizes = |cmds: &[CacheCommand]| -> HashMap<(String, String), u64> {
+        cmds.iter()
+            .filter_map(|c| {
+                let bytes = c.value_bytes.or(c.reply_bytes)?;
+                Some(((c.command.clone(), c.key.clone()?), bytes))
+            })
+            .collect()
+    };
+    let b_sizes = sizes(&bc);
+    let mut size_changes: Vec<CacheSizeDiff> = sizes(&cc)
+        .into_iter()
+        .filter_map(|((command, key), candidate_bytes)| {
+            let baseline_bytes = *b_sizes.get(&(command.clone(), key.clone()))?;
+            (baseline_bytes != candidate_bytes).then_some(CacheSizeDiff {
+                command,
+                key,
+                baseline_bytes,
+                candidate_bytes,
+            })
+        })
+        .collect();
+    size_changes.sort_by(|a, b| (&a.key, &a.command).cmp(&(&b.key, &b.command)));
+
+    let misses = |cmds: &[CacheCommand]| cmds.iter().filter(|c| c.status == "miss").count();
+    Ok(Some(CacheDiff {
+        baseline_ops: bc.len(),
+        candidate_ops: cc.len(),
+        baseline_misses: misses(&bc),
+        candidate_misses: misses(&cc),
+        new_misses,
+        new_commands,
+        missing_commands,
+        new_errors,
+        size_changes,
+    }))
+}
 
-    let b_text = String::from_utf8_lossy(&b_stderr);
-    let c_text = String::from_utf8_lossy(&c_stderr);
+fn diff_stderr(baseline: &PackReader, candidate: &PackReader) -> Option<StderrDiff> {
+    let b_text = baseline.stdio_text("stderr")?;
+    let c_text = candidate.stdio_text("stderr")?;
 
     let b_lines: Vec<String> = b_text.lines().map(|s| s.to_string()).collect();
     let c_lines: Vec<String> = c_text.lines().map(|s| s.to_string()).collect();
@@ -362,3 +666,210 @@ fn diff_stderr(baseline: &PackReader, candidate: &PackReader) -> Option<StderrDi
         new_lines,
     })
 }
+
+/// Set by the shell per invocation, so they differ between any two runs.
+const VOLATILE_ENV: &[&str] = &["_", "OLDPWD", "SHLVL"];
+
+/// The environment poe recorded for the run, None for packs without one.
+fn pack_env(pack: &PackReader) -> Option<HashMap<String, String>> {
+    let meta: serde_json::Value =
+        serde_json::from_str(&pack.read_meta("environment.json").ok()?).ok()?;
+    serde_json::from_value(meta.get("environment")?.clone()).ok()
+}
+
+/// Values of sensitive variables were redacted when the packs were
+/// written, so those only show up as added or removed, unless both packs
+/// were written with the same `--redact-key` and their pseudonyms differ;
+/// the rest are scrubbed of embedded credentials before being shown.
+fn diff_env(
+    baseline: &HashMap<String, String>,
+    candidate: &HashMap<String, String>,
+) -> Option<EnvDiff> {
+    let redactor = Redactor::new();
+    let shown = |name: &str, value: &str| {
+        if Redactor::is_replacement(value) {
+            value.to_string()
+        } else if redactor.should_redact_env_key(name) {
+            "[REDACTED]".to_string()
+        } else {
+            redactor.redact_string(value)
+        }
+    };
+    let relevant = |name: &&String| !VOLATILE_ENV.contains(&name.as_str());
+
+    let mut diff = EnvDiff::default();
+    for (name, value) in candidate.iter().filter(|(k, _)| relevant(k)) {
+        match baseline.get(name) {
+            None => diff.added.push(EnvVar {
+                name: name.clone(),
+                value: shown(name, value),
+            }),
+            Some(old) if old != value => {
+                let (added_entries, removed_entries) = if is_path_list(name) {
+                    let old_entries: Vec<&str> = old.split(':').collect();
+                    let new_entries: Vec<&str> = value.split(':').collect();
+                    (
+                        new_entries
+                            .iter()
+                            .filter(|e| !old_entries.contains(e))
+                            .map(|e| e.to_string())
+                            .collect(),
+                        old_entries
+                            .iter()
+                            .filter(|e| !new_entries.contains(e))
+                            .map(|e| e.to_string())
+                            .collect(),
+                    )
+                } else {
+                    (Vec::new(), Vec::new())
+                };
+                diff.changed.push(EnvChange {
+                    name: name.clone(),
+                    baseline: shown(name, old),
+                    candidate: shown(name, value),
+                    added_entries,
+                    removed_entries,
+                });
+            }
+            _ => {}
+        }
+    }
+    for (name, value) in baseline.iter().filter(|(k, _)| relevant(k)) {
+        if !candidate.contains_key(name) {
+            diff.removed.push(EnvVar {
+                name: name.clone(),
+                value: shown(name, value),
+            });
+        }
+    }
+
+    if diff.added.is_empty() && diff.removed.is_empty() && diff.changed.is_empty() {
+        return None;
+    }
+    diff.added.sort_by(|a, b| a.name.cmp(&b.name));
+    diff.removed.sort_by(|a, b| a.name.cmp(&b.name));
+    diff.changed.sort_by(|a, b| a.name.cmp(&b.name));
+    Some(diff)
+}
+
+/// The sandbox poe recorded for the run, None for packs from before it
+/// did.
+fn pack_sandbox(pack: &PackReader) -> Option<Sandbox> {
+    let meta: serde_json::Value =
+        serde_json::from_str(&pack.read_meta("environment.json").ok()?).ok()?;
+    serde_json::from_value(meta.get("sandbox")?.clone()).ok()
+}
+
+fn diff_sandbox(baseline: &Sandbox, candidate: &Sandbox) -> Vec<SandboxChange> {
+    let baseline = baseline.summary();
+    candidate
+        .summary()
+        .into_iter()
+        .filter_map(|(name, value)| {
+            let old = baseline.get(&name)?;
+            (*old != value).then(|| SandboxChange {
+                name,
+                baseline: old.clone(),
+                candidate: value,
+            })
+        })
+        .collect()
+}
+
+/// Only built-in patterns are compared, so rule files in the working
+/// directory don't change what diff reports.
+fn diff_patterns(
+    baseline: &PackReader,
+    candidate: &PackReader,
+) -> (Vec<PatternChange>, Vec<PatternChange>) {
+    let registry = PatternRegistry::builtin();
+    let coded = |pack: &PackReader| -> Vec<ErrorPattern> {
+        analyzer::analyze_with(pack, &registry)
+            .map(|o| o.error_patterns)
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|p| p.code.is_some())
+            .collect()
+    };
+    let (before, after) = (coded(baseline), coded(candidate));
+    (only_in(&after, &before), only_in(&before, &after))
+}
+
+fn only_in(patterns: &[ErrorPattern], other: &[ErrorPattern]) -> Vec<PatternChange> {
+    let mut changes: Vec<PatternChange> = Vec::new();
+    for p in patterns {
+        let Some(code) = p.code else { continue };
+        if other.iter().any(|o| o.code == p.code) || changes.iter().any(|c| c.code == code) {
+            continue;
+        }
+        changes.push(PatternChange {
+            code,
+            category: p.category.clone(),
+            description: p.description.clone(),
+        });
+    }
+    changes.sort_by_key(|c| c.code);
+    changes
+}
+
+/// PATH, PYTHONPATH, LD_LIBRARY_PATH and the like.
+fn is_path_list(name: &str) -> bool {
+    name.ends_with("PATH")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env(vars: &[(&str, &str)]) -> HashMap<String, String> {
+        vars.iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn commands_normalize_run_specific_parts() {
+        assert_eq!(
+            normalize_command("python3 -m pytest --basetemp=/tmp/pytest-of-ci/pytest-41 -k fast"),
+            "python3 -m pytest --basetemp=/tmp/<tmp> -k fast"
+        );
+        assert_eq!(
+            normalize_command("server --port 43817 --peer 127.0.0.1:50211 --id 9f86d081884c7d65"),
+            "server --port <port> --peer 127.0.0.1:<port> --id <id>"
+        );
+        assert_eq!(
+            normalize_command("cat /proc/4242/status"),
+            "cat /proc/<pid>/status"
+        );
+        assert_eq!(normalize_command("make -j8 all"), "make -j8 all");
+    }
+
+    #[test]
+    fn env_diff_lists_changes_and_hides_secrets() {
+        let baseline = env(&[
+            ("PATH", "/usr/local/bin:/usr/bin"),
+            ("LANG", "en_US.UTF-8"),
+            ("SHLVL", "1"),
+            ("OLD_FLAG", "1"),
+        ]);
+        let candidate = env(&[
+            ("PATH", "/opt/ci/bin:/usr/bin"),
+            ("LANG", "C"),
+            ("SHLVL", "3"),
+            ("API_TOKEN", "hunter2"),
+        ]);
+
+        let diff = diff_env(&baseline, &candidate).unwrap();
+        assert_eq!(diff.added.len(), 1);
+        assert_eq!(diff.added[0].name, "API_TOKEN");
+        assert_eq!(diff.added[0].value, "[REDACTED]");
+        assert_eq!(diff.removed[0].name, "OLD_FLAG");
+
+        let names: Vec<&str> = diff.changed.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, ["LANG", "PATH"]);
+        assert_eq!(diff.changed[1].added_entries, ["/opt/ci/bin"]);
+        assert_eq!(diff.changed[1].removed_entries, ["/usr/local/bin"]);
+
+        assert!(diff_env(&baseline, &baseline).is_none());
+    }
+}
@@ -0,0 +1,117 @@
+use anyhow::{bail, Result};
+
+/// A slice of explain output: `poe explain --focus` renders only the
+/// sections of the chosen aspects, and the JSON only their fields.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Focus {
+    Failure,
+    Files,
+    Net,
+    Timeline,
+    Hotspots,
+    Stderr,
+}
+
+pub const ASPECTS: [&str; 6] = ["failure", "files", "net", "timeline", "hotspots", "stderr"];
+
+impl Focus {
+    pub fn parse(s: &str) -> Result<Self> {
+        Ok(match s.to_ascii_lowercase().as_str() {
+            "failure" => Focus::Failure,
+            "files" => Focus::Files,
+            "net" => Focus::Net,
+            "timeline" => Focus::Timeline,
+            "hotspots" => Focus::Hotspots,
+            "stderr" => Focus::Stderr,
+            other => bail!(
+                "unknown focus '{}' (expected one of: {})",
+                other,
+                ASPECTS.join(", ")
+            ),
+        })
+    }
+
+    /// The `ExplainOutput` fields the aspect covers.
+    pub fn fields(self) -> &'static [&'static str] {
+        match self {
+            Focus::Failure => &[
+                "failure",
+                "error_patterns",
+                "history_anomalies",
+                "suggestions",
+                "process_tree",
+                "python_exceptions",
+                "python_exception_stack",
+                "rust_panic",
+                "shell_steps",
+                "shell_failed_step",
+                "build",
+                "database",
+            ],
+            Focus::Files => &["file_activity", "file_races", "kernel_io"],
+            Focus::Net => &["net_activity", "server_activity", "port_conflicts"],
+            Focus::Timeline => &[
+                "timeline",
+                "timeline_window",
+                "signals",
+                "logs",
+                "kernel_log",
+            ],
+            Focus::Hotspots => &[
+                "hotspots",
+                "slow_ops",
+                "slow_functions",
+                "python_slow_functions",
+                "scheduling",
+            ],
+            Focus::Stderr => &["stderr_tail", "stdout_tail"],
+        }
+    }
+}
+
+/// Keeps only the named fields of an explain JSON object. Each name is an
+/// aspect standing for its fields, or a field; truncation and capture
+/// warnings are always kept, as they qualify everything else.
+pub fn select_fields(explain: &mut serde_json::Value, names: &[&str]) -> Result<()> {
+    let Some(object) = explain.as_object_mut() else {
+        return Ok(());
+    };
+    let mut keep: Vec<&str> = vec!["truncation", "capture_warnings"];
+    for name in names {
+        if ASPECTS.contains(name) {
+            keep.extend(Focus::parse(name)?.fields());
+        } else if object.contains_key(*name) {
+            keep.push(name);
+        } else {
+            bail!(
+                "unknown field '{}' (expected an explain field or one of: {})",
+                name,
+                ASPECTS.join(", ")
+            );
+        }
+    }
+    object.retain(|key, _| keep.contains(&key.as_str()));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn selects_fields_and_aspects() {
+        let mut explain = serde_json::json!({
+            "failure": null,
+            "error_patterns": [],
+            "file_activity": {},
+            "net_activity": {},
+            "stderr_tail": "boom",
+            "truncation": null,
+        });
+        select_fields(&mut explain, &["stderr", "file_activity"]).unwrap();
+        let mut keys: Vec<&String> = explain.as_object().unwrap().keys().collect();
+        keys.sort();
+        assert_eq!(keys, ["file_activity", "stderr_tail", "truncation"]);
+        assert!(select_fields(&mut explain, &["files_activity"]).is_err());
+    }
+}
@@ -0,0 +1,165 @@
+use anyhow::Result;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::capture::kmsg::KernelRecord;
+use crate::explain::analyzer::ErrorPattern;
+use crate::explain::codes::PatternCode;
+use crate::trace::db::TraceDb;
+
+const MAX_ENTRIES: usize = 20;
+/// Uncategorized records at these levels are kept too.
+const ERROR_LEVELS: &[&str] = &["emerg", "alert", "crit", "err"];
+
+/// A kernel log line logged during the run.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct KernelLogEntry {
+    pub ts_ms: f64,
+    /// The process the line names, else the root process.
+    pub pid: i32,
+    pub level: String,
+    pub category: Option<String>,
+    pub message: String,
+}
+
+/// The kernel log lines explain shows: the ones it has a category for,
+/// plus errors, in time order.
+pub fn load(db: &TraceDb) -> Result<Vec<KernelLogEntry>> {
+    let mut entries: Vec<KernelLogEntry> = db
+        .query_events_by_kind("kernel_log")?
+        .into_iter()
+        .filter_map(|e| {
+            let r: KernelRecord = serde_json::from_str(e.detail.as_deref()?).ok()?;
+            (r.category.is_some() || ERROR_LEVELS.contains(&r.level.as_str())).then(|| {
+                KernelLogEntry {
+                    ts_ms: e.ts as f64 / 1_000_000.0,
+                    pid: e.proc_id,
+                    level: r.level,
+                    category: r.category,
+                    message: r.message,
+                }
+            })
+        })
+        .collect();
+    entries.sort_by(|a, b| a.ts_ms.total_cmp(&b.ts_ms));
+    entries.truncate(MAX_ENTRIES);
+    Ok(entries)
+}
+
+/// Adds the kernel's own report of a crash ("segfault at ... ip ... in
+/// lib.so") to the crash pattern, preferring the line about the failing
+/// process.
+pub fn attach_to_crash(
+    patterns: &mut [ErrorPattern],
+    entries: &[KernelLogEntry],
+    primary_pid: Option<i32>,
+) {
+    let Some(crash) = patterns.iter_mut().find(|p| p.category == "crash") else {
+        return;
+    };
+    let faults = || {
+        entries
+            .iter()
+            .filter(|e| e.category.as_deref() == Some("segfault"))
+    };
+    let line = faults()
+        .find(|e| Some(e.pid) == primary_pid)
+        .or_else(|| faults().next());
+    if let Some(line) = line {
+        crash.examples.push(format!("kernel: {}", line.message));
+    }
+}
+
+/// OOM kills, storage errors and SYN floods the kernel logged during the
+/// run.
+pub fn detect_kernel_patterns(entries: &[KernelLogEntry]) -> Vec<ErrorPattern> {
+    let kinds = [
+        (
+            "oom_kill",
+            PatternCode::KernelOomKill,
+            "critical",
+            "the kernel OOM killer ended a process",
+        ),
+        (
+            "io_error",
+            PatternCode::KernelIoError,
+            "error",
+            "the kernel logged storage I/O errors",
+        ),
+        (
+            "syn_flood",
+            PatternCode::KernelSynFlood,
+            "warning",
+            "a listen queue overflowed and the kernel suspected a SYN flood",
+        ),
+    ];
+    let mut patterns = Vec::new();
+    for (category, code, severity, description) in kinds {
+        let lines: Vec<&KernelLogEntry> = entries
+            .iter()
+            .filter(|e| e.category.as_deref() == Some(category))
+            .collect();
+        let Some(first) = lines.first() else {
+            continue;
+        };
+        patterns.push(ErrorPattern {
+            code: Some(code),
+            category: format!("kernel_{}", category),
+            severity: severity.into(),
+            description: format!("{} ({} kernel log line(s))", description, lines.len()),
+            count: lines.len(),
+            examples: lines
+                .iter()
+                .take(5)
+                .map(|e| format!("{:.1}ms: {}", e.ts_ms, e.message))
+                .collect(),
+            first_seen_ms: Some(first.ts_ms),
+            ..Default::default()
+        });
+    }
+    patterns
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(pid: i32, category: &str, message: &str) -> KernelLogEntry {
+        KernelLogEntry {
+            ts_ms: 12.5,
+            pid,
+            level: "info".into(),
+            category: Some(category.into()),
+            message: message.into(),
+        }
+    }
+
+    #[test]
+    fn kernel_lines_join_the_crash_and_raise_their_own_patterns() {
+        let entries = [
+            entry(5, "segfault", "helper[5]: segfault at 8 ip 1 sp 2 error 4"),
+            entry(
+                9,
+                "segfault",
+                "app[9]: segfault at 0 ip 1 sp 2 error 6 in libx.so",
+            ),
+            entry(9, "oom_kill", "Killed process 9 (app)"),
+        ];
+        let mut patterns = vec![ErrorPattern {
+            code: Some(PatternCode::CrashSegv),
+            category: "crash".into(),
+            examples: vec!["Process received SIGSEGV".into()],
+            ..Default::default()
+        }];
+        attach_to_crash(&mut patterns, &entries, Some(9));
+        assert_eq!(
+            patterns[0].examples[1],
+            "kernel: app[9]: segfault at 0 ip 1 sp 2 error 6 in libx.so"
+        );
+
+        let kernel = detect_kernel_patterns(&entries);
+        assert_eq!(kernel.len(), 1);
+        assert_eq!(kernel[0].code, Some(PatternCode::KernelOomKill));
+        assert_eq!(kernel[0].first_seen_ms, Some(12.5));
+    }
+}
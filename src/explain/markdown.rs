@@ -0,0 +1,558 @@
+use crate::explain::analyzer::{ExplainOutput, FailedFileOp, PythonExceptionInfo};
+use crate::pack::summary::PackSummary;
+use crate::util;
+
+/// Rows kept per table; an issue body should stay readable.
+const MAX_ROWS: usize = 15;
+/// Frames kept per failure stack.
+const MAX_FRAMES: usize = 12;
+/// Lines kept from the end of stderr/stdout.
+const MAX_TAIL_LINES: usize = 60;
+
+/// Renders the explain output as GitHub-flavored markdown for pasting into
+/// an issue or PR description: tables for the diagnosis and failed
+/// operations, with long output folded into `<details>` blocks.
+pub fn render(summary: &PackSummary, output: &ExplainOutput) -> String {
+    let mut md = String::new();
+
+    let status = match (&output.failure, summary.exit_code) {
+        (Some(f), _) => f.kind.clone(),
+        (None, Some(0)) => "succeeded".into(),
+        (None, Some(code)) => format!("exit {}", code),
+        (None, None) => "no exit status".into(),
+    };
+    md.push_str(&format!(
+        "### poe: {} ({})\n\n",
+        code_span(&clip(&summary.command.join(" "), 120)),
+        escape(&status)
+    ));
+
+    md.push_str("| | |\n|---|---|\n");
+    row(
+        &mut md,
+        &["command", &code_span(&summary.command.join(" "))],
+    );
+    let exit = match (summary.exit_code, &summary.signal_name, summary.signal) {
+        (_, Some(name), _) => format!("killed by {}", name),
+        (_, None, Some(sig)) => format!("killed by {}", util::signal_name(sig)),
+        (Some(code), _, _) => code.to_string(),
+        (None, _, _) => "-".into(),
+    };
+    row(&mut md, &["exit", &exit]);
+    row(
+        &mut md,
+        &["duration", &format!("{} ms", summary.duration_ms)],
+    );
+    row(&mut md, &["run id", &code_span(&summary.run_id)]);
+    row(&mut md, &["time", &summary.timestamp]);
+    if let Some(ref sha) = summary.git_sha {
+        row(&mut md, &["git", &code_span(sha)]);
+    }
+    if !summary.tags.is_empty() {
+        let tags: Vec<String> = summary
+            .tags
+            .iter()
+            .map(|(k, v)| code_span(&format!("{}={}", k, v)))
+            .collect();
+        row(&mut md, &["tags", &tags.join(" ")]);
+    }
+    md.push('\n');
+
+    if let Some(ref failure) = output.failure {
+        md.push_str("#### Failure\n\n");
+        md.push_str(&format!(
+            "**{}**: {}\n",
+            escape(&failure.kind),
+            escape(&failure.description)
+        ));
+        if let Some(ref loc) = failure.primary_location {
+            let mut at = String::new();
+            if let Some(ref file) = loc.file {
+                at.push_str(file);
+                if let Some(line) = loc.line {
+                    at.push_str(&format!(":{}", line));
+                }
+            }
+            match (&loc.function, at.is_empty()) {
+                (Some(func), false) => {
+                    md.push_str(&format!("\nin {} at {}\n", code_span(func), code_span(&at)))
+                }
+                (Some(func), true) => md.push_str(&format!("\nin {}\n", code_span(func))),
+                (None, false) => md.push_str(&format!("\nat {}\n", code_span(&at))),
+                (None, true) => {}
+            }
+        }
+        if let Some(ref step) = output.shell_failed_step {
+            md.push_str(&format!(
+                "\nshell step {}:{}: {}\n",
+                escape(&step.file),
+                step.line,
+                code_span(step.command.lines().next().unwrap_or(""))
+            ));
+        }
+        md.push('\n');
+    }
+
+    if let Some(ref panic) = output.rust_panic {
+        md.push_str("#### Rust panic\n\n");
+        let mut text = panic.message.clone();
+        if let Some(ref loc) = panic.location {
+            text.push_str(&format!("\n  at {}:{}", loc.file, loc.line));
+        }
+        for frame in &panic.backtrace {
+            let sym = frame.symbol.as_deref().unwrap_or("???");
+            text.push_str(&format!("\n  #{}: {}", frame.index, sym));
+        }
+        md.push_str(&code_block(&text));
+        md.push('\n');
+    }
+
+    if let Some(exc) = output.python_exceptions.last() {
+        md.push_str("#### Python exception\n\n");
+        md.push_str(&code_block(&python_traceback(exc)));
+        md.push('\n');
+    }
+
+    if !output.error_patterns.is_empty() {
+        md.push_str("#### Diagnosis\n\n");
+        md.push_str("| severity | category | description | count | example |\n");
+        md.push_str("|---|---|---|---:|---|\n");
+        for p in output.error_patterns.iter().take(MAX_ROWS) {
+            let example = p
+                .examples
+                .first()
+                .map(|e| code_span(&clip(e, 100)))
+                .unwrap_or_default();
+            let description = if p.root_cause {
+                format!("**probable root cause:** {}", p.description)
+            } else if let Some(ref cause) = p.caused_by {
+                format!("{} (after {})", p.description, cause)
+            } else {
+                p.description.clone()
+            };
+            row(
+                &mut md,
+                &[
+                    &p.severity,
+                    &p.category,
+                    &description,
+                    &p.count.to_string(),
+                    &example,
+                ],
+            );
+        }
+        md.push('\n');
+    }
+
+    let files = &output.file_activity;
+    let mut failed_files: Vec<&FailedFileOp> = files.permission_errors.iter().collect();
+    for f in files.failed_opens.iter().chain(&files.exclusive_creates) {
+        if !failed_files
+            .iter()
+            .any(|p| p.path == f.path && p.op == f.op && p.errno == f.errno)
+        {
+            failed_files.push(f);
+        }
+    }
+    let net = &output.net_activity;
+    let failed_sql = output
+        .database
+        .as_ref()
+        .and_then(|d| d.failed_statement.as_ref());
+    let failed_targets: Vec<_> = output
+        .build
+        .iter()
+        .flat_map(|b| b.targets.iter().filter(|t| t.failed))
+        .collect();
+    if !failed_files.is_empty()
+        || !net.failed_connections.is_empty()
+        || !net.failed_rpcs.is_empty()
+        || !output.server_activity.bind_errors.is_empty()
+        || failed_sql.is_some()
+        || !failed_targets.is_empty()
+    {
+        md.push_str("#### Failed operations\n\n");
+        md.push_str("| time | pid | operation | target | error |\n");
+        md.push_str("|---:|---:|---|---|---|\n");
+        // The last failures are the ones closest to whatever ended the run.
+        for f in &failed_files[failed_files.len().saturating_sub(MAX_ROWS)..] {
+            row(
+                &mut md,
+                &[
+                    &format!("{:.1} ms", f.ts_ms),
+                    &f.pid.to_string(),
+                    &f.op_label(),
+                    &code_span(&f.path),
+                    &f.errno_name,
+                ],
+            );
+        }
+        for c in net.failed_connections.iter().take(MAX_ROWS) {
+            row(
+                &mut md,
+                &[
+                    &format!("{:.1} ms", c.ts_ms),
+                    &c.pid.to_string(),
+                    "connect",
+                    &code_span(&c.addr),
+                    &c.errno_name,
+                ],
+            );
+        }
+        for b in output.server_activity.bind_errors.iter().take(MAX_ROWS) {
+            row(
+                &mut md,
+                &[
+                    &format!("{:.1} ms", b.ts_ms),
+                    &b.pid.to_string(),
+                    "bind",
+                    &code_span(&b.addr),
+                    &b.errno_name,
+                ],
+            );
+        }
+        for r in net.failed_rpcs.iter().rev().take(MAX_ROWS) {
+            let target = format!(
+                "{}{}",
+                r.authority.as_deref().unwrap_or(""),
+                r.path.as_deref().unwrap_or("?")
+            );
+            row(
+                &mut md,
+                &[
+                    &format!("{:.1} ms", r.ts_ms),
+                    &r.pid.to_string(),
+                    r.method.as_deref().unwrap_or("rpc"),
+                    &code_span(&target),
+                    &r.outcome(),
+                ],
+            );
+        }
+        if let Some(stmt) = failed_sql {
+            let mut error = stmt.error_code.clone().unwrap_or_default();
+            if let Some(ref msg) = stmt.error_message {
+                if !error.is_empty() {
+                    error.push(' ');
+                }
+                error.push_str(msg);
+            }
+            row(
+                &mut md,
+                &[
+                    &format!("{:.1} ms", stmt.ts_ms),
+                    &stmt.pid.to_string(),
+                    "sql",
+                    &code_span(&clip(&one_line(&stmt.statement), 100)),
+                    &error,
+                ],
+            );
+        }
+        for t in failed_targets.iter().take(MAX_ROWS) {
+            let error = match (t.signal, t.exit_code) {
+                (Some(sig), _) => util::signal_name(sig).to_string(),
+                (None, Some(code)) => format!("exit {}", code),
+                (None, None) => "failed".into(),
+            };
+            row(
+                &mut md,
+                &[
+                    &format!("{:.1} ms", t.start_ms),
+                    &t.pid.to_string(),
+                    "make",
+                    &code_span(t.target.as_deref().unwrap_or("?")),
+                    &error,
+                ],
+            );
+        }
+        md.push('\n');
+    }
+
+    let stacks: Vec<(String, &[String])> = failed_files
+        .iter()
+        .filter(|f| !f.stack.is_empty())
+        .map(|f| (format!("{} {}", f.op_label(), f.path), f.stack.as_slice()))
+        .chain(
+            net.failed_connections
+                .iter()
+                .filter(|c| !c.stack.is_empty())
+                .map(|c| (format!("connect {}", c.addr), c.stack.as_slice())),
+        )
+        .collect();
+    if !stacks.is_empty() {
+        md.push_str(
+            "#### Failing call sites
+
+",
+        );
+        for (what, stack) in stacks.iter().take(5) {
+            md.push_str(&format!(
+                "{}:
+
+```
+",
+                code_span(what)
+            ));
+            for frame in stack.iter().take(MAX_FRAMES) {
+                md.push_str(frame);
+                md.push('\n');
+            }
+            md.push_str("```\n\n");
+        }
+    }
+
+    if let Some(history) = output
+        .history_anomalies
+        .as_ref()
+        .filter(|h| !h.anomalies.is_empty())
+    {
+        md.push_str(&format!(
+            "#### Anomalies vs. history ({} earlier runs)\n\n",
+            history.runs
+        ));
+        md.push_str("| score | kind | description |\n");
+        md.push_str("|---:|---|---|\n");
+        for a in history.anomalies.iter().take(MAX_ROWS) {
+            row(
+                &mut md,
+                &[&format!("{:.2}", a.score), &a.kind, &a.description],
+            );
+        }
+        md.push('\n');
+    }
+
+    if !output.suggestions.is_empty() {
+        md.push_str("#### Suggestions\n\n");
+        for s in &output.suggestions {
+            md.push_str(&format!(
+                "- **{}**: {}",
+                escape(&s.category),
+                escape(&s.message)
+            ));
+            if let Some(ref action) = s.action {
+                md.push_str(&format!(" (try {})", code_span(action)));
+            }
+            md.push('\n');
+        }
+        md.push('\n');
+    }
+
+    if !output.process_tree.is_empty() {
+        md.push_str(&details_open(&format!(
+            "Process tree ({} processes)",
+            output.process_tree.len()
+        )));
+        md.push_str("| pid | parent | command | duration | status |\n");
+        md.push_str("|---:|---:|---|---:|---|\n");
+        for p in output.process_tree.iter().take(MAX_ROWS * 2) {
+            let status = match (p.signal, p.exit_code) {
+                (Some(sig), _) => format!("killed by {}", util::signal_name(sig)),
+                (None, Some(0)) => "ok".into(),
+                (None, Some(code)) => format!("exit {}", code),
+                (None, None) => "?".into(),
+            };
+            let status = if p.excluded {
+                format!("{} (not traced)", status)
+            } else {
+                status
+            };
+            row(
+                &mut md,
+                &[
+                    &p.pid.to_string(),
+                    &p.parent_pid.map(|p| p.to_string()).unwrap_or_default(),
+                    &code_span(&clip(&p.command, 100)),
+                    &p.duration_ms
+                        .map(|d| format!("{:.1} ms", d))
+                        .unwrap_or_default(),
+                    &status,
+                ],
+            );
+        }
+        md.push_str("\n</details>\n\n");
+    }
+
+    for (name, tail) in [
+        ("stderr", &output.stderr_tail),
+        ("stdout", &output.stdout_tail),
+    ] {
+        let Some(tail) = tail.as_deref().filter(|t| !t.trim().is_empty()) else {
+            continue;
+        };
+        let lines: Vec<&str> = tail.lines().collect();
+        let kept = &lines[lines.len().saturating_sub(MAX_TAIL_LINES)..];
+        md.push_str(&details_open(&format!(
+            "{} (last {} lines)",
+            name,
+            kept.len()
+        )));
+        md.push_str(&code_block(&kept.join("\n")));
+        md.push_str("\n</details>\n\n");
+    }
+
+    if let Some(ref truncation) = output.truncation {
+        md.push_str(&format!(
+            "_pack truncated to a {} byte budget; some rows were dropped_\n\n",
+            truncation.budget_bytes
+        ));
+    }
+
+    if !output.secrets.is_empty() {
+        md.push_str(&format!(
+            "_{} likely secret(s) in the captured data: {}_\n\n",
+            output.secrets.len(),
+            escape(
+                &output
+                    .secrets
+                    .iter()
+                    .map(|s| format!("{} in {}", s.kind, s.source))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )
+        ));
+    }
+
+    for warning in &output.capture_warnings {
+        md.push_str(&format!("_capture overhead: {}_\n\n", escape(warning)));
+    }
+
+    if let Some(sched) = output.scheduling.as_ref().filter(|s| s.oversubscribed) {
+        md.push_str(&format!(
+            "_oversubscribed machine: processes waited {:.0}ms for a CPU ({:.0}% of their runnable time)_\n\n",
+            sched.wait_ms,
+            sched.wait_share * 100.0
+        ));
+    }
+
+    md.push_str(&format!(
+        "<sub>generated by <code>poe explain --format markdown</code> from run {}</sub>\n",
+        escape(&summary.run_id)
+    ));
+    md
+}
+
+fn python_traceback(exc: &PythonExceptionInfo) -> String {
+    if !exc.formatted.is_empty() {
+        return exc.formatted.concat().trim_end().to_string();
+    }
+    let mut text = String::from("Traceback (most recent call last):");
+    for frame in &exc.traceback {
+        text.push_str(&format!(
+            "\n  File \"{}\", line {}, in {}",
+            frame.file, frame.line, frame.func
+        ));
+    }
+    text.push_str(&format!("\n{}: {}", exc.exc_type, exc.exc_msg));
+    text
+}
+
+fn details_open(summary: &str) -> String {
+    // The blank line after <summary> lets GitHub render the markdown inside.
+    format!("<details><summary>{}</summary>\n\n", escape_html(summary))
+}
+
+fn row(md: &mut String, cells: &[&str]) {
+    md.push('|');
+    for cell in cells {
+        md.push(' ');
+        md.push_str(&cell_text(cell));
+        md.push_str(" |");
+    }
+    md.push('\n');
+}
+
+/// Makes text safe inside a table cell: pipes would split the cell and
+/// newlines would end the row.
+fn cell_text(s: &str) -> String {
+    s.replace('|', "\\|").replace(['\r', '\n'], " ")
+}
+
+/// Escapes characters GitHub would otherwise read as markup in prose.
+fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        if matches!(
+            c,
+            '\\' | '`' | '*' | '_' | '[' | ']' | '<' | '>' | '|' | '#'
+        ) {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// Inline code using a backtick run longer than any in the text, padded
+/// when the text starts or ends with a backtick.
+fn code_span(s: &str) -> String {
+    let s = one_line(s);
+    if s.is_empty() {
+        return String::new();
+    }
+    let ticks = "`".repeat(longest_backtick_run(&s) + 1);
+    if s.starts_with('`') || s.ends_with('`') {
+        format!("{} {} {}", ticks, s, ticks)
+    } else {
+        format!("{}{}{}", ticks, s, ticks)
+    }
+}
+
+/// Fenced block with a fence longer than any backtick run in the content,
+/// so captured output cannot close it early.
+fn code_block(s: &str) -> String {
+    let fence = "`".repeat((longest_backtick_run(s) + 1).max(3));
+    format!("{}\n{}\n{}\n", fence, s.trim_end_matches('\n'), fence)
+}
+
+fn longest_backtick_run(s: &str) -> usize {
+    let mut longest = 0;
+    let mut run = 0;
+    for c in s.chars() {
+        if c == '`' {
+            run += 1;
+            longest = longest.max(run);
+        } else {
+            run = 0;
+        }
+    }
+    longest
+}
+
+fn one_line(s: &str) -> String {
+    s.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn clip(s: &str, max: usize) -> String {
+    match s.char_indices().nth(max) {
+        Some((i, _)) => format!("{}...", &s[..i]),
+        None => s.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn table_cells_escape_pipes_and_newlines() {
+        let mut md = String::new();
+        row(&mut md, &["a|b", "line1\nline2"]);
+        assert_eq!(md, "| a\\|b | line1 line2 |\n");
+    }
+
+    #[test]
+    fn fences_outlast_backticks_in_content() {
+        assert_eq!(code_block("plain"), "```\nplain\n```\n");
+        assert_eq!(
+            code_block("```rust\nfn main() {}\n```"),
+            "````\n```rust\nfn main() {}\n```\n````\n"
+        );
+        assert_eq!(code_span("a `b` c"), "``a `b` c``");
+        assert_eq!(code_span("`x"), "`` `x ``");
+    }
+}
@@ -1,4 +1,22 @@
 pub mod analyzer;
+pub mod anomaly;
+pub mod causality;
+pub mod cluster;
+pub mod codes;
+pub mod context;
 pub mod diff;
+pub mod focus;
+pub mod kernellog;
+pub mod markdown;
+pub mod patterns;
+pub mod portconflicts;
+pub mod profile;
+pub mod races;
+pub mod report;
+pub mod sarif;
+pub mod suggest;
+pub mod trends;
+pub mod unified;
+pub mod zoom;
 
 pub mod realtime_diff;
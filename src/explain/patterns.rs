@@ -0,0 +1,353 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::explain::analyzer::{self, ErrorPattern, ExplainOutput};
+use crate::explain::kernellog;
+use crate::explain::portconflicts;
+use crate::explain::races;
+
+const MAX_EXAMPLES: usize = 5;
+const SEVERITIES: &[&str] = &["critical", "error", "warning", "info"];
+
+/// What a matcher gets to look at: the assembled analysis plus the full
+/// output streams. `stdout` and `clean_stderr` are escape-stripped, `stderr`
+/// is the raw capture.
+pub struct PatternContext<'a> {
+    pub output: &'a ExplainOutput,
+    pub stdout: Option<&'a str>,
+    pub stderr: Option<&'a str>,
+    pub clean_stderr: Option<&'a str>,
+}
+
+pub trait PatternMatcher: Send + Sync {
+    fn name(&self) -> &str;
+    fn detect(&self, ctx: &PatternContext) -> Vec<ErrorPattern>;
+}
+
+/// The hardcoded crash/file/network/runtime detectors.
+struct BuiltinMatcher;
+
+impl PatternMatcher for BuiltinMatcher {
+    fn name(&self) -> &str {
+        "builtin"
+    }
+
+    fn detect(&self, ctx: &PatternContext) -> Vec<ErrorPattern> {
+        let out = ctx.output;
+        let full_stderr = ctx.stderr.map(String::from);
+        let mut patterns = analyzer::detect_error_patterns(
+            &out.failure,
+            &out.file_activity,
+            &out.net_activity,
+            &out.process_tree,
+            &out.stderr_tail,
+            &full_stderr,
+            &out.python_exceptions,
+        );
+        let primary_pid = out.failure.as_ref().and_then(|f| f.primary_pid);
+        kernellog::attach_to_crash(&mut patterns, &out.kernel_log, primary_pid);
+        patterns.extend(analyzer::detect_signal_patterns(&out.signals));
+        patterns.extend(races::detect_race_patterns(&out.file_races));
+        patterns.extend(portconflicts::detect_port_patterns(&out.port_conflicts));
+        patterns.extend(kernellog::detect_kernel_patterns(&out.kernel_log));
+        patterns
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum RuleSource {
+    #[default]
+    Stderr,
+    Stdout,
+    /// Failed file operations, as "op path ERRNO".
+    Files,
+    /// Failed connections, as "addr ERRNO".
+    Net,
+}
+
+#[derive(Debug, Deserialize)]
+struct RuleFile {
+    #[serde(default, rename = "pattern")]
+    patterns: Vec<RuleSpec>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RuleSpec {
+    name: String,
+    category: String,
+    #[serde(default = "default_severity")]
+    severity: String,
+    description: String,
+    regex: String,
+    #[serde(default)]
+    source: RuleSource,
+    #[serde(default = "default_min_count")]
+    min_count: usize,
+}
+
+fn default_severity() -> String {
+    "error".into()
+}
+
+fn default_min_count() -> usize {
+    1
+}
+
+/// A user-defined regex rule. The description may reference `{count}`,
+/// `{match}` (first matching line) and `{1}`..`{9}` (its capture groups).
+pub struct RegexRule {
+    name: String,
+    category: String,
+    severity: String,
+    description: String,
+    regex: Regex,
+    source: RuleSource,
+    min_count: usize,
+}
+
+impl RegexRule {
+    fn from_spec(spec: RuleSpec) -> Result<Self> {
+        if !SEVERITIES.contains(&spec.severity.as_str()) {
+            anyhow::bail!(
+                "pattern {}: severity must be one of {}",
+                spec.name,
+                SEVERITIES.join(", ")
+            );
+        }
+        let regex = Regex::new(&spec.regex)
+            .with_context(|| format!("pattern {}: invalid regex", spec.name))?;
+        Ok(Self {
+            name: spec.name,
+            category: spec.category,
+            severity: spec.severity,
+            description: spec.description,
+            regex,
+            source: spec.source,
+            min_count: spec.min_count.max(1),
+        })
+    }
+
+    fn lines(&self, ctx: &PatternContext) -> Vec<String> {
+        let out = ctx.output;
+        match self.source {
+            RuleSource::Stderr => split_lines(
+                ctx.clean_stderr
+                    .or(ctx.stderr)
+                    .or(out.stderr_tail.as_deref()),
+            ),
+            RuleSource::Stdout => split_lines(ctx.stdout.or(out.stdout_tail.as_deref())),
+            RuleSource::Files => out
+                .file_activity
+                .failed_opens
+                .iter()
+                .chain(&out.file_activity.permission_errors)
+                .chain(&out.file_activity.exclusive_creates)
+                .map(|f| format!("{} {} {}", f.op, f.path, f.errno_name))
+                .collect(),
+            RuleSource::Net => out
+                .net_activity
+                .failed_connections
+                .iter()
+                .map(|c| format!("{} {}", c.addr, c.errno_name))
+                .collect(),
+        }
+    }
+}
+
+fn split_lines(text: Option<&str>) -> Vec<String> {
+    text.map(|t| t.lines().map(String::from).collect())
+        .unwrap_or_default()
+}
+
+impl PatternMatcher for RegexRule {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn detect(&self, ctx: &PatternContext) -> Vec<ErrorPattern> {
+        let matches: Vec<String> = self
+            .lines(ctx)
+            .into_iter()
+            .filter(|l| self.regex.is_match(l))
+            .collect();
+        if matches.len() < self.min_count {
+            return Vec::new();
+        }
+
+        let first = &matches[0];
+        let mut description = self
+            .description
+            .replace("{count}", &matches.len().to_string())
+            .replace("{match}", first.trim());
+        if let Some(caps) = self.regex.captures(first) {
+            for i in 1..caps.len().min(10) {
+                let group = caps.get(i).map(|m| m.as_str()).unwrap_or("");
+                description = description.replace(&format!("{{{}}}", i), group);
+            }
+        }
+
+        vec![ErrorPattern {
+            category: self.category.clone(),
+            severity: self.severity.clone(),
+            description,
+            count: matches.len(),
+            examples: matches
+                .iter()
+                .take(MAX_EXAMPLES)
+                .map(|l| l.trim().to_string())
+                .collect(),
+            ..Default::default()
+        }]
+    }
+}
+
+/// Ordered set of matchers run by the analyzer: the built-in detectors
+/// followed by any rules loaded from pattern files.
+pub struct PatternRegistry {
+    matchers: Vec<Box<dyn PatternMatcher>>,
+}
+
+impl PatternRegistry {
+    pub fn builtin() -> Self {
+        Self {
+            matchers: vec![Box::new(BuiltinMatcher)],
+        }
+    }
+
+    /// Built-ins, patterns contributed by configured adapters, and rules
+    /// from `$POE_PATTERNS` (colon separated), `./.poe/patterns.toml` and
+    /// `~/.config/poe/patterns.toml`. Broken rule files are reported and
+    /// skipped rather than failing the analysis.
+    pub fn load_default() -> Self {
+        let mut registry = Self::builtin();
+        for matcher in crate::hooks::adapter::AdapterManager::load_default().patterns() {
+            registry.register(matcher);
+        }
+        for path in default_rule_paths() {
+            if let Err(e) = registry.load_rules_file(&path) {
+                log::warn!("skipping pattern file {}: {:#}", path.display(), e);
+            }
+        }
+        registry
+    }
+
+    pub fn register(&mut self, matcher: Box<dyn PatternMatcher>) {
+        self.matchers.push(matcher);
+    }
+
+    pub fn load_rules_file(&mut self, path: &Path) -> Result<usize> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        let rules = parse_rules(&content)?;
+        let count = rules.len();
+        for rule in rules {
+            self.register(Box::new(rule));
+        }
+        Ok(count)
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.matchers.iter().map(|m| m.name()).collect()
+    }
+
+    pub fn detect(&self, ctx: &PatternContext) -> Vec<ErrorPattern> {
+        self.matchers.iter().flat_map(|m| m.detect(ctx)).collect()
+    }
+}
+
+pub fn parse_rules(content: &str) -> Result<Vec<RegexRule>> {
+    let file: RuleFile = toml::from_str(content).context("invalid pattern file")?;
+    file.patterns
+        .into_iter()
+        .map(RegexRule::from_spec)
+        .collect()
+}
+
+fn default_rule_paths() -> Vec<PathBuf> {
+    let mut paths: Vec<PathBuf> = std::env::var("POE_PATTERNS")
+        .map(|v| {
+            v.split(':')
+                .filter(|p| !p.is_empty())
+                .map(PathBuf::from)
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let local = PathBuf::from(".poe/patterns.toml");
+    if local.is_file() {
+        paths.push(local);
+    }
+    if let Ok(home) = std::env::var("HOME") {
+        let user = Path::new(&home).join(".config/poe/patterns.toml");
+        if user.is_file() {
+            paths.push(user);
+        }
+    }
+    paths
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RULES: &str = r#"
+[[pattern]]
+name = "django-db"
+category = "database"
+severity = "critical"
+regex = 'OperationalError: (.*)'
+description = "database unavailable: {1} ({count}x)"
+"#;
+
+    #[test]
+    fn parses_and_validates_rules() {
+        let rules = parse_rules(RULES).unwrap();
+        assert_eq!(rules.len(), 1);
+        assert_eq!(rules[0].source, RuleSource::Stderr);
+
+        let bad = RULES.replace("critical", "catastrophic");
+        assert!(parse_rules(&bad).is_err());
+    }
+
+    #[test]
+    fn regex_rule_fills_template() {
+        let rule = parse_rules(RULES).unwrap().remove(0);
+        let output: ExplainOutput = serde_json::from_value(serde_json::json!({
+            "failure": null,
+            "timeline": {"merged": [], "last_file_ops": [], "last_net_ops": [], "duration_ms": 0},
+            "hotspots": [],
+            "file_activity": {"total_ops": 0, "unique_paths": 0, "most_accessed": [],
+                "total_bytes_read": 0, "total_bytes_written": 0, "failed_opens": [],
+                "permission_errors": []},
+            "net_activity": {"total_ops": 0, "connections": [], "total_bytes_sent": 0,
+                "total_bytes_received": 0, "failed_connections": []},
+            "process_tree": [],
+            "error_patterns": [],
+            "python_exceptions": [],
+            "rust_panic": null,
+            "stderr_tail": null,
+            "stdout_tail": null,
+            "truncation": null
+        }))
+        .unwrap();
+        let stderr = "boot\nOperationalError: could not connect\nOperationalError: retry\n";
+        let ctx = PatternContext {
+            output: &output,
+            stdout: None,
+            stderr: None,
+            clean_stderr: Some(stderr),
+        };
+
+        let found = rule.detect(&ctx);
+        assert_eq!(found.len(), 1);
+        assert_eq!(
+            found[0].description,
+            "database unavailable: could not connect (2x)"
+        );
+        assert_eq!(found[0].count, 2);
+    }
+}
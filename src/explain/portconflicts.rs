@@ -0,0 +1,255 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::capture::ports::{PortHolder, PortSnapshot};
+use crate::explain::analyzer::{ErrorPattern, SignalSummary};
+use crate::explain::codes::PatternCode;
+use crate::trace::db::TraceDb;
+
+const MAX_CONFLICTS: usize = 10;
+
+/// A holder told to stop before the failed bind that exits this soon after
+/// it was on its way out: the new instance started before the old one let
+/// go of the port.
+const RESTART_WINDOW_NS: i64 = 2_000_000_000;
+
+const STOP_SIGNALS: [i32; 3] = [libc::SIGTERM, libc::SIGINT, libc::SIGQUIT];
+
+/// A bind that failed with EADDRINUSE, and who had the port.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct PortConflict {
+    pub addr: String,
+    pub pid: i32,
+    pub ts_ms: f64,
+    pub holder_pid: Option<i32>,
+    pub holder_command: Option<String>,
+    /// `trace` when the holder's bind is in the run, `snapshot` when it
+    /// was found in the host's socket table at the failure, else `unknown`.
+    pub holder_source: String,
+    /// Socket state from the snapshot, e.g. `LISTEN` or `TIME_WAIT`.
+    pub holder_state: Option<String>,
+    pub holder_bound_ms: Option<f64>,
+    /// The holder was a previous instance still releasing the port: the
+    /// same command, a process that had exited or was stopping, or only
+    /// closing connections left on the port.
+    pub restart_race: bool,
+}
+
+/// Pairs each EADDRINUSE bind with the last successful bind of the same
+/// port earlier in the run, falling back to the socket table snapshot the
+/// capture took when the bind failed.
+pub fn find_port_conflicts(db: &TraceDb, signals: &SignalSummary) -> Result<Vec<PortConflict>> {
+    let net = db.query_net_events()?;
+    let processes = db.query_processes()?;
+    let command_keys: HashMap<i32, String> = processes
+        .iter()
+        .filter_map(|p| {
+            let argv: Vec<String> = serde_json::from_str(p.argv.as_deref()?).ok()?;
+            Some((p.proc_id, same_command_key(&argv.join(" "))))
+        })
+        .collect();
+    let ended: HashMap<i32, i64> = processes
+        .iter()
+        .filter_map(|p| Some((p.proc_id, p.end_ts?)))
+        .collect();
+    let snapshots: HashMap<u16, PortSnapshot> = db
+        .query_events_by_kind("port_holders")?
+        .iter()
+        .filter_map(|e| serde_json::from_str::<PortSnapshot>(e.detail.as_deref()?).ok())
+        .map(|s| (s.port, s))
+        .collect();
+
+    let mut conflicts: Vec<PortConflict> = Vec::new();
+    for ev in net.iter().filter(|e| {
+        e.op == "bind" && e.result == Some(-(libc::EADDRINUSE as i64)) && e.dst.is_some()
+    }) {
+        let addr = ev.dst.clone().unwrap_or_default();
+        if conflicts
+            .iter()
+            .any(|c| c.pid == ev.proc_id && c.addr == addr)
+        {
+            continue;
+        }
+        let port = port_of(&addr);
+        let bound = net.iter().rev().find(|b| {
+            b.op == "bind"
+                && b.ts < ev.ts
+                && b.result.is_some_and(|r| r >= 0)
+                && b.dst.as_deref().map(port_of) == Some(port)
+        });
+        let snapshot = port
+            .parse()
+            .ok()
+            .and_then(|p: u16| snapshots.get(&p))
+            .map(|s| s.holders.as_slice())
+            .unwrap_or_default();
+        let live = snapshot.iter().find(|h| is_live(h) && h.pid.is_some());
+
+        let mut conflict = PortConflict {
+            addr: addr.clone(),
+            pid: ev.proc_id,
+            ts_ms: ev.ts as f64 / 1_000_000.0,
+            holder_pid: None,
+            holder_command: None,
+            holder_source: "unknown".into(),
+            holder_state: None,
+            holder_bound_ms: None,
+            restart_race: false,
+        };
+        if let Some(b) = bound {
+            conflict.holder_pid = Some(b.proc_id);
+            conflict.holder_command = processes
+                .iter()
+                .find(|p| p.proc_id == b.proc_id)
+                .and_then(|p| p.argv.as_deref())
+                .and_then(|a| serde_json::from_str::<Vec<String>>(a).ok())
+                .map(|argv| argv.join(" "));
+            conflict.holder_source = "trace".into();
+            conflict.holder_bound_ms = Some(b.ts as f64 / 1_000_000.0);
+            let stopping = signals.deliveries.iter().any(|d| {
+                d.pid == b.proc_id
+                    && STOP_SIGNALS.contains(&d.signo)
+                    && d.ts_ms * 1_000_000.0 <= ev.ts as f64
+            });
+            let exiting = ended
+                .get(&b.proc_id)
+                .is_some_and(|&end| end <= ev.ts || (stopping && end <= ev.ts + RESTART_WINDOW_NS));
+            conflict.restart_race = exiting
+                || same_command(command_keys.get(&b.proc_id), command_keys.get(&ev.proc_id));
+        } else if let Some(h) = live.or_else(|| snapshot.first()) {
+            conflict.holder_pid = h.pid;
+            conflict.holder_command = h.command.clone();
+            conflict.holder_source = "snapshot".into();
+            let holder_key = h.command.as_deref().map(same_command_key);
+            conflict.restart_race =
+                live.is_none() || same_command(holder_key.as_ref(), command_keys.get(&ev.proc_id));
+        }
+        if let Some(h) = live.or_else(|| snapshot.first()) {
+            conflict.holder_state = Some(h.state.clone());
+        }
+        conflicts.push(conflict);
+        if conflicts.len() >= MAX_CONFLICTS {
+            break;
+        }
+    }
+    Ok(conflicts)
+}
+
+/// A listener or bound socket, as opposed to connections winding down.
+fn is_live(holder: &PortHolder) -> bool {
+    matches!(holder.state.as_str(), "LISTEN" | "BOUND" | "ESTABLISHED")
+}
+
+fn same_command(a: Option<&String>, b: Option<&String>) -> bool {
+    matches!((a, b), (Some(a), Some(b)) if a == b)
+}
+
+/// Two processes are instances of the same service when their command
+/// lines match, give or take the directory of the executable; comparing
+/// program names alone would pair any two `python3` scripts.
+fn same_command_key(command: &str) -> String {
+    let (program, args) = command.split_once(' ').unwrap_or((command, ""));
+    format!("{} {}", program.rsplit('/').next().unwrap_or(program), args)
+}
+
+fn port_of(addr: &str) -> &str {
+    addr.rsplit(':').next().unwrap_or(addr)
+}
+
+/// Restart races get their own pattern: the fix (wait for the old
+/// instance, SO_REUSEADDR) differs from finding who else owns the port.
+pub fn detect_port_patterns(conflicts: &[PortConflict]) -> Vec<ErrorPattern> {
+    let mut patterns = Vec::new();
+    let (races, others): (Vec<&PortConflict>, Vec<&PortConflict>) =
+        conflicts.iter().partition(|c| c.restart_race);
+    if !others.is_empty() {
+        patterns.push(ErrorPattern {
+            code: Some(PatternCode::NetPortConflict),
+            category: "port_conflict".into(),
+            severity: "error".into(),
+            description: format!(
+                "{} bind(s) failed because another process holds the port",
+                others.len()
+            ),
+            count: others.len(),
+            examples: others.iter().take(5).map(|c| describe(c)).collect(),
+            ..Default::default()
+        });
+    }
+    if !races.is_empty() {
+        patterns.push(ErrorPattern {
+            code: Some(PatternCode::NetRestartRace),
+            category: "restart_race".into(),
+            severity: "error".into(),
+            description: format!(
+                "{} bind(s) raced a previous instance that had not released the port yet",
+                races.len()
+            ),
+            count: races.len(),
+            examples: races.iter().take(5).map(|c| describe(c)).collect(),
+            ..Default::default()
+        });
+    }
+    patterns
+}
+
+pub fn describe(c: &PortConflict) -> String {
+    let holder = match (c.holder_pid, c.holder_command.as_deref()) {
+        (Some(pid), Some(cmd)) => format!("pid {} ({})", pid, clip(cmd, 40)),
+        (Some(pid), None) => format!("pid {}", pid),
+        (None, _) if c.holder_state.is_some() => "sockets with no visible owner".into(),
+        (None, _) => "an unknown process".into(),
+    };
+    let mut line = format!(
+        "pid {} bind {} at {:.1}ms: held by {}",
+        c.pid, c.addr, c.ts_ms, holder
+    );
+    if let Some(ms) = c.holder_bound_ms {
+        line.push_str(&format!(", bound at {:.1}ms", ms));
+    }
+    if let Some(ref state) = c.holder_state {
+        line.push_str(&format!(" [{}]", state));
+    }
+    line
+}
+
+fn clip(s: &str, max: usize) -> String {
+    if s.chars().count() <= max {
+        s.to_string()
+    } else {
+        format!("{}...", s.chars().take(max).collect::<String>())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn conflict(restart_race: bool) -> PortConflict {
+        PortConflict {
+            addr: "0.0.0.0:8080".into(),
+            pid: 20,
+            ts_ms: 50.0,
+            holder_pid: Some(10),
+            holder_command: Some("python3 -m http.server 8080".into()),
+            holder_source: "trace".into(),
+            holder_state: None,
+            holder_bound_ms: Some(12.5),
+            restart_race,
+        }
+    }
+
+    #[test]
+    fn restart_races_get_their_own_pattern() {
+        let patterns = detect_port_patterns(&[conflict(false), conflict(true)]);
+        let categories: Vec<&str> = patterns.iter().map(|p| p.category.as_str()).collect();
+        assert_eq!(categories, ["port_conflict", "restart_race"]);
+        assert_eq!(
+            patterns[0].examples[0],
+            "pid 20 bind 0.0.0.0:8080 at 50.0ms: held by pid 10 (python3 -m http.server 8080), bound at 12.5ms"
+        );
+    }
+}
@@ -0,0 +1,101 @@
+use std::collections::{BTreeMap, HashSet};
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::explain::diff;
+use crate::explain::realtime_diff::Observed;
+
+const PROFILE_VERSION: u32 = 1;
+
+/// What several runs of a command did, learned by `poe learn` for
+/// `poe run --profile` to diff against in realtime instead of a baseline
+/// pack. Each entry counts the runs that showed it; file paths and stderr
+/// lines are normalized so temp dirs, ids and ports that change from run to
+/// run still match.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BaselineProfile {
+    pub version: u32,
+    pub command: Vec<String>,
+    pub created: String,
+    pub runs: usize,
+    pub file_paths: BTreeMap<String, usize>,
+    pub net_addrs: BTreeMap<String, usize>,
+    pub file_errors: BTreeMap<String, usize>,
+    pub processes: BTreeMap<String, usize>,
+    pub stderr_lines: BTreeMap<String, usize>,
+}
+
+impl BaselineProfile {
+    pub fn new(command: Vec<String>) -> Self {
+        Self {
+            version: PROFILE_VERSION,
+            command,
+            created: chrono::Utc::now().to_rfc3339(),
+            ..Default::default()
+        }
+    }
+
+    pub fn add_run(&mut self, observed: Observed) {
+        let normalized = |keys: HashSet<String>| -> HashSet<String> {
+            keys.iter().map(|k| diff::normalize_command(k)).collect()
+        };
+        let add = |counts: &mut BTreeMap<String, usize>, keys: HashSet<String>| {
+            for key in keys {
+                *counts.entry(key).or_default() += 1;
+            }
+        };
+        add(&mut self.file_paths, normalized(observed.file_paths));
+        add(&mut self.net_addrs, observed.net_addrs);
+        add(&mut self.file_errors, normalized(observed.file_errors));
+        add(&mut self.processes, observed.processes);
+        add(&mut self.stderr_lines, normalized(observed.stderr_lines));
+        self.runs += 1;
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read profile {}", path.display()))?;
+        let profile: Self = serde_json::from_str(&text)
+            .with_context(|| format!("{} is not a poe learn profile", path.display()))?;
+        if profile.version > PROFILE_VERSION {
+            bail!(
+                "profile {} is version {}; this poe reads up to {}",
+                path.display(),
+                profile.version,
+                PROFILE_VERSION
+            );
+        }
+        Ok(profile)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        std::fs::write(path, serde_json::to_string_pretty(self)?)
+            .with_context(|| format!("failed to write profile {}", path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merges_runs_and_normalizes_volatile_parts() {
+        let run = |tmp: &str, line: &str| Observed {
+            file_paths: [format!("/tmp/{}/out.txt", tmp), "/etc/hosts".to_string()].into(),
+            net_addrs: ["127.0.0.1:5432".to_string()].into(),
+            stderr_lines: [line.to_string()].into(),
+            ..Default::default()
+        };
+        let mut profile = BaselineProfile::new(vec!["./app".into()]);
+        profile.add_run(run("tmpa1b2c3d4", "listening on localhost:40123"));
+        profile.add_run(run("tmpz9y8x7w6", "listening on localhost:40777"));
+
+        assert_eq!(profile.runs, 2);
+        assert_eq!(profile.file_paths["/etc/hosts"], 2);
+        assert_eq!(profile.file_paths["/tmp/<tmp>"], 2);
+        assert_eq!(profile.net_addrs["127.0.0.1:5432"], 2);
+        assert_eq!(profile.stderr_lines["listening on localhost:<port>"], 2);
+    }
+}
@@ -0,0 +1,236 @@
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+use anyhow::Result;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::explain::analyzer::ErrorPattern;
+use crate::explain::codes::PatternCode;
+use crate::trace::db::{FileQueryResult, TraceDb};
+
+const MAX_RACES: usize = 20;
+
+/// A path one process failed to find although another process created it
+/// later or had deleted it shortly before: an ordering bug between them.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct FileRace {
+    pub path: String,
+    /// `created_later` or `deleted_earlier`.
+    pub kind: String,
+    pub failed_pid: i32,
+    pub failed_op: String,
+    pub failed_ts_ms: f64,
+    pub other_pid: i32,
+    /// The create/rename/unlink that changed the path, e.g. `open(O_CREAT)`.
+    pub other_op: String,
+    pub other_ts_ms: f64,
+}
+
+struct Change {
+    ts: i64,
+    pid: i32,
+    op: &'static str,
+}
+
+/// Matches each ENOENT open/stat against successful creates, renames and
+/// unlinks of the same path by other processes. Creations by the failing
+/// process's own descendants are left out: probing for a file and then
+/// spawning something to make it (make and its recipes) is the intended
+/// order, not a race.
+pub fn find_file_races(db: &TraceDb) -> Result<Vec<FileRace>> {
+    let processes = db.query_processes()?;
+    let cwd: HashMap<i32, &str> = processes
+        .iter()
+        .filter_map(|p| Some((p.proc_id, p.cwd.as_deref()?)))
+        .collect();
+    let parent: HashMap<i32, i32> = processes
+        .iter()
+        .filter_map(|p| Some((p.proc_id, p.parent_proc_id?)))
+        .collect();
+    let descends_from = |mut pid: i32, ancestor: i32| {
+        let mut seen = HashSet::new();
+        while let Some(&ppid) = parent.get(&pid) {
+            if ppid == ancestor {
+                return true;
+            }
+            if !seen.insert(ppid) {
+                break;
+            }
+            pid = ppid;
+        }
+        false
+    };
+    let absolute = |pid: i32, path: &str| match cwd.get(&pid) {
+        Some(dir) if !path.starts_with('/') => Path::new(dir).join(path).display().to_string(),
+        _ => path.to_string(),
+    };
+
+    let events = db.query_file_events()?;
+    let mut created: HashMap<String, Vec<Change>> = HashMap::new();
+    let mut deleted: HashMap<String, Vec<Change>> = HashMap::new();
+    for ev in events.iter().filter(|e| e.result.is_some_and(|r| r >= 0)) {
+        let Some(path) = ev.path.as_deref() else {
+            continue;
+        };
+        let change = |op| Change {
+            ts: ev.ts,
+            pid: ev.proc_id,
+            op,
+        };
+        for (target, op, into) in changed_paths(ev, path) {
+            let map = if into { &mut created } else { &mut deleted };
+            map.entry(absolute(ev.proc_id, target))
+                .or_default()
+                .push(change(op));
+        }
+    }
+
+    let mut races = Vec::new();
+    let mut reported = HashSet::new();
+    for ev in &events {
+        let (Some(path), Some(result)) = (ev.path.as_deref(), ev.result) else {
+            continue;
+        };
+        if -result != libc::ENOENT as i64 || !matches!(ev.op.as_str(), "open" | "stat") {
+            continue;
+        }
+        let path = absolute(ev.proc_id, path);
+        let later = created.get(&path).and_then(|changes| {
+            changes
+                .iter()
+                .find(|c| c.ts > ev.ts && c.pid != ev.proc_id && !descends_from(c.pid, ev.proc_id))
+        });
+        let earlier = deleted.get(&path).and_then(|changes| {
+            changes
+                .iter()
+                .rev()
+                .find(|c| c.ts < ev.ts && c.pid != ev.proc_id)
+        });
+        for (kind, change) in [("created_later", later), ("deleted_earlier", earlier)] {
+            let Some(change) = change else {
+                continue;
+            };
+            if !reported.insert((path.clone(), kind)) {
+                continue;
+            }
+            races.push(FileRace {
+                path: path.clone(),
+                kind: kind.into(),
+                failed_pid: ev.proc_id,
+                failed_op: ev.op.clone(),
+                failed_ts_ms: ev.ts as f64 / 1_000_000.0,
+                other_pid: change.pid,
+                other_op: change.op.into(),
+                other_ts_ms: change.ts as f64 / 1_000_000.0,
+            });
+        }
+        if races.len() >= MAX_RACES {
+            break;
+        }
+    }
+    Ok(races)
+}
+
+/// Paths a successful file op brought into existence (`true`) or removed
+/// (`false`). Two-path ops are recorded as "from -> to", except symlink,
+/// which is "linkpath -> target".
+fn changed_paths<'a>(ev: &FileQueryResult, path: &'a str) -> Vec<(&'a str, &'static str, bool)> {
+    let pair = path.split_once(" -> ");
+    match ev.op.as_str() {
+        "open" if ev.flags.is_some_and(|f| f & libc::O_CREAT != 0) => {
+            vec![(path, "open(O_CREAT)", true)]
+        }
+        "mkdir" => vec![(path, "mkdir", true)],
+        "rename" => match pair {
+            Some((from, to)) => vec![(from, "rename", false), (to, "rename", true)],
+            None => vec![(path, "rename", false)],
+        },
+        "link" => pair
+            .map(|(_, to)| vec![(to, "link", true)])
+            .unwrap_or_default(),
+        "symlink" => vec![(pair.map_or(path, |(link, _)| link), "symlink", true)],
+        "unlink" => vec![(path, "unlink", false)],
+        _ => Vec::new(),
+    }
+}
+
+pub fn detect_race_patterns(races: &[FileRace]) -> Vec<ErrorPattern> {
+    if races.is_empty() {
+        return Vec::new();
+    }
+    vec![ErrorPattern {
+        code: Some(PatternCode::FileRace),
+        category: "file_race".into(),
+        severity: "error".into(),
+        description: format!(
+            "{} path(s) were missing for one process but created or deleted around it by another -- likely an ordering bug",
+            races.len()
+        ),
+        count: races.len(),
+        examples: races
+            .iter()
+            .take(5)
+            .map(|r| {
+                let verb = if r.kind == "created_later" {
+                    "created it"
+                } else {
+                    "had deleted it"
+                };
+                format!(
+                    "pid {} {} {} -> ENOENT at {:.1}ms; pid {} {} at {:.1}ms ({})",
+                    r.failed_pid,
+                    r.failed_op,
+                    r.path,
+                    r.failed_ts_ms,
+                    r.other_pid,
+                    verb,
+                    r.other_ts_ms,
+                    r.other_op
+                )
+            })
+            .collect(),
+        ..Default::default()
+    }]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file_op(op: &str, path: &str, flags: Option<i32>) -> FileQueryResult {
+        FileQueryResult {
+            ts: 0,
+            proc_id: 1,
+            op: op.into(),
+            path: Some(path.into()),
+            fd: None,
+            bytes: None,
+            flags,
+            result: Some(0),
+            duration_ns: None,
+        }
+    }
+
+    #[test]
+    fn changed_paths_follow_op_formats() {
+        let creat = file_op("open", "/tmp/a", Some(libc::O_WRONLY | libc::O_CREAT));
+        assert_eq!(
+            changed_paths(&creat, "/tmp/a"),
+            [("/tmp/a", "open(O_CREAT)", true)]
+        );
+        let read = file_op("open", "/tmp/a", Some(libc::O_RDONLY));
+        assert!(changed_paths(&read, "/tmp/a").is_empty());
+
+        let rename = file_op("rename", "/tmp/a.tmp -> /tmp/a", None);
+        assert_eq!(
+            changed_paths(&rename, "/tmp/a.tmp -> /tmp/a"),
+            [("/tmp/a.tmp", "rename", false), ("/tmp/a", "rename", true)]
+        );
+        let symlink = file_op("symlink", "/tmp/link -> target", None);
+        assert_eq!(
+            changed_paths(&symlink, "/tmp/link -> target"),
+            [("/tmp/link", "symlink", true)]
+        );
+    }
+}
@@ -1,21 +1,28 @@
-use std::collections::HashSet;
-use std::path::Path;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicI32, Ordering};
 use std::sync::{Arc, Mutex};
 
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
 
+use crate::capture::interrupt;
 use crate::events::types::*;
+use crate::explain::diff;
+use crate::explain::profile::BaselineProfile;
 use crate::pack::reader::PackReader;
+use crate::util::ansi;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Divergence {
     pub ts_ms: f64,
     pub kind: DivergenceKind,
     pub description: String,
+    #[serde(default)]
+    pub severity: Severity,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum DivergenceKind {
     NewFilePath,
     MissingFilePath,
@@ -27,161 +34,411 @@ pub enum DivergenceKind {
     ExtraStderr,
 }
 
-pub struct RealtimeDiffState {
-    baseline_file_paths: HashSet<String>,
-    baseline_net_addrs: HashSet<String>,
-    baseline_file_errors: HashSet<String>,
-    baseline_processes: HashSet<String>,
-    baseline_stderr_lines: HashSet<String>,
-    divergences: Vec<Divergence>,
+impl DivergenceKind {
+    pub const ALL: [DivergenceKind; 8] = [
+        Self::NewFilePath,
+        Self::MissingFilePath,
+        Self::NewFileError,
+        Self::NewNetConnection,
+        Self::FailedNetConnection,
+        Self::NewProcess,
+        Self::UnexpectedSignal,
+        Self::ExtraStderr,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::NewFilePath => "new_file_path",
+            Self::MissingFilePath => "missing_file_path",
+            Self::NewFileError => "new_file_error",
+            Self::NewNetConnection => "new_net_connection",
+            Self::FailedNetConnection => "failed_net_connection",
+            Self::NewProcess => "new_process",
+            Self::UnexpectedSignal => "unexpected_signal",
+            Self::ExtraStderr => "extra_stderr",
+        }
+    }
+
+    pub fn parse(s: &str) -> Result<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|k| k.as_str() == s)
+            .ok_or_else(|| {
+                let names: Vec<&str> = Self::ALL.iter().map(|k| k.as_str()).collect();
+                anyhow::anyhow!(
+                    "unknown divergence kind '{}' (expected one of {})",
+                    s,
+                    names.join(", ")
+                )
+            })
+    }
+
+    /// A new file read or stderr line is usually harmless drift; failures
+    /// the baseline never saw are what break runs.
+    pub fn default_severity(&self) -> Severity {
+        match self {
+            Self::NewFilePath | Self::MissingFilePath | Self::ExtraStderr => Severity::Info,
+            Self::NewNetConnection | Self::NewProcess | Self::NewFileError => Severity::Warning,
+            Self::FailedNetConnection | Self::UnexpectedSignal => Severity::Critical,
+        }
+    }
 }
 
-impl RealtimeDiffState {
-    pub fn from_baseline(baseline_path: &Path) -> Result<Self> {
-        let pack = PackReader::open(baseline_path)?;
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    #[default]
+    Info,
+    Warning,
+    Critical,
+}
+
+impl Severity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Info => "info",
+            Self::Warning => "warning",
+            Self::Critical => "critical",
+        }
+    }
+
+    pub fn parse(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "info" => Ok(Self::Info),
+            "warning" | "warn" => Ok(Self::Warning),
+            "critical" => Ok(Self::Critical),
+            _ => anyhow::bail!(
+                "unknown severity '{}' (expected info, warning or critical)",
+                s
+            ),
+        }
+    }
+}
+
+/// How divergences are scored and when they stop the run.
+#[derive(Debug, Clone, Default)]
+pub struct DiffPolicy {
+    /// Severities replacing [`DivergenceKind::default_severity`].
+    pub severities: HashMap<DivergenceKind, Severity>,
+    /// Stop the command at the first divergence at least this severe.
+    pub halt_at: Option<Severity>,
+    /// Baselines that must show an event for it to be expected; 0 or 1
+    /// means any one of them.
+    pub quorum: usize,
+}
+
+impl DiffPolicy {
+    /// Parses `--diff-severity KIND=SEVERITY` overrides.
+    pub fn with_overrides(overrides: &[String], halt_at: Option<Severity>) -> Result<Self> {
+        let mut severities = HashMap::new();
+        for o in overrides {
+            let (kind, severity) = o.split_once('=').ok_or_else(|| {
+                anyhow::anyhow!("invalid --diff-severity {}: expected kind=severity", o)
+            })?;
+            severities.insert(
+                DivergenceKind::parse(kind.trim())?,
+                Severity::parse(severity.trim())?,
+            );
+        }
+        Ok(Self {
+            severities,
+            halt_at,
+            ..Default::default()
+        })
+    }
+
+    pub fn severity(&self, kind: DivergenceKind) -> Severity {
+        self.severities
+            .get(&kind)
+            .copied()
+            .unwrap_or_else(|| kind.default_severity())
+    }
+}
+
+/// Divergences of one kind, for the end-of-run report.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DivergenceGroup {
+    pub kind: DivergenceKind,
+    pub severity: Severity,
+    pub count: usize,
+    pub first_ts_ms: f64,
+    pub example: String,
+}
+
+/// Groups divergences by kind, most severe first, then most frequent.
+pub fn summarize(divergences: &[Divergence]) -> Vec<DivergenceGroup> {
+    let mut groups: Vec<DivergenceGroup> = Vec::new();
+    for d in divergences {
+        match groups.iter_mut().find(|g| g.kind == d.kind) {
+            Some(g) => {
+                g.count += 1;
+                g.severity = g.severity.max(d.severity);
+            }
+            None => groups.push(DivergenceGroup {
+                kind: d.kind,
+                severity: d.severity,
+                count: 1,
+                first_ts_ms: d.ts_ms,
+                example: d.description.clone(),
+            }),
+        }
+    }
+    groups.sort_by(|a, b| {
+        b.severity
+            .cmp(&a.severity)
+            .then(b.count.cmp(&a.count))
+            .then(a.first_ts_ms.total_cmp(&b.first_ts_ms))
+    });
+    groups
+}
+
+/// How many baselines showed each path, address, command or line.
+#[derive(Default)]
+struct Seen {
+    counts: HashMap<String, usize>,
+    quorum: usize,
+}
+
+impl Seen {
+    fn add(&mut self, keys: HashSet<String>) {
+        for key in keys {
+            *self.counts.entry(key).or_default() += 1;
+        }
+    }
+
+    /// Whether enough baselines did it for it to count as normal behavior.
+    fn contains(&self, key: &str) -> bool {
+        self.counts.get(key).is_some_and(|&n| n >= self.quorum)
+    }
+}
+
+/// What a run did, as realtime diff compares it.
+#[derive(Debug, Default)]
+pub struct Observed {
+    pub file_paths: HashSet<String>,
+    /// Addresses connected to.
+    pub net_addrs: HashSet<String>,
+    /// Paths an operation failed on.
+    pub file_errors: HashSet<String>,
+    /// Normalized command lines.
+    pub processes: HashSet<String>,
+    pub stderr_lines: HashSet<String>,
+}
+
+impl Observed {
+    pub fn from_pack(pack_path: &Path) -> Result<Self> {
+        let pack = PackReader::open(pack_path)?;
         let db = pack.db();
 
         let file_events = db.query_file_events()?;
         let net_events = db.query_net_events()?;
         let processes = db.query_processes()?;
 
-        let baseline_file_paths: HashSet<String> =
-            file_events.iter().filter_map(|f| f.path.clone()).collect();
+        Ok(Self {
+            file_paths: file_events.iter().filter_map(|f| f.path.clone()).collect(),
+            net_addrs: net_events
+                .iter()
+                .filter(|n| n.op == "connect")
+                .filter_map(|n| n.dst.clone())
+                .collect(),
+            file_errors: file_events
+                .iter()
+                .filter(|f| f.result.map(|r| r < 0).unwrap_or(false))
+                .filter_map(|f| f.path.clone())
+                .collect(),
+            processes: processes
+                .iter()
+                .filter_map(|p| {
+                    p.argv.as_ref().and_then(|a| {
+                        serde_json::from_str::<Vec<String>>(a)
+                            .ok()
+                            .map(|v| diff::normalize_command(&v.join(" ")))
+                    })
+                })
+                .collect(),
+            stderr_lines: pack
+                .stdio_text("stderr")
+                .map(|text| {
+                    text.lines()
+                        .filter(|l| !l.is_empty())
+                        .map(|l| l.to_string())
+                        .collect()
+                })
+                .unwrap_or_default(),
+        })
+    }
+}
 
-        let baseline_net_addrs: HashSet<String> = net_events
-            .iter()
-            .filter(|n| n.op == "connect")
-            .filter_map(|n| n.dst.clone())
-            .collect();
+pub struct RealtimeDiffState {
+    baseline_file_paths: Seen,
+    baseline_net_addrs: Seen,
+    baseline_file_errors: Seen,
+    baseline_processes: Seen,
+    baseline_stderr_lines: Seen,
+    divergences: Vec<Divergence>,
+    policy: DiffPolicy,
+    /// Also match events normalized, as a learned profile stores them.
+    normalize: bool,
+    /// Holds a stderr line back until its chunk completes it.
+    stderr: ansi::Cleaner,
+}
 
-        let baseline_file_errors: HashSet<String> = file_events
-            .iter()
-            .filter(|f| f.result.map(|r| r < 0).unwrap_or(false))
-            .filter_map(|f| f.path.clone())
-            .collect();
+impl RealtimeDiffState {
+    /// Loads every baseline, and the runs of a learned profile; an event is
+    /// a divergence unless at least `policy.quorum` of them (all but none,
+    /// by default one) show it.
+    pub fn from_baselines(
+        baseline_paths: &[PathBuf],
+        profile: Option<&BaselineProfile>,
+        policy: DiffPolicy,
+    ) -> Result<Self> {
+        let runs = baseline_paths.len() + profile.map_or(0, |p| p.runs);
+        let quorum = policy.quorum.clamp(1, runs.max(1));
+        let seen = || Seen {
+            quorum,
+            ..Default::default()
+        };
+        let mut state = Self {
+            baseline_file_paths: seen(),
+            baseline_net_addrs: seen(),
+            baseline_file_errors: seen(),
+            baseline_processes: seen(),
+            baseline_stderr_lines: seen(),
+            divergences: Vec::new(),
+            policy,
+            normalize: false,
+            stderr: ansi::Cleaner::default(),
+        };
+        for path in baseline_paths {
+            state.add_baseline(path)?;
+        }
+        if let Some(profile) = profile {
+            state.add_profile(profile);
+        }
+        Ok(state)
+    }
 
-        let baseline_processes: HashSet<String> = processes
-            .iter()
-            .filter_map(|p| {
-                p.argv.as_ref().and_then(|a| {
-                    serde_json::from_str::<Vec<String>>(a)
-                        .ok()
-                        .map(|v| v.join(" "))
-                })
-            })
-            .collect();
-
-        let baseline_stderr_lines: HashSet<String> = pack
-            .stderr()
-            .ok()
-            .map(|d| {
-                String::from_utf8_lossy(&d)
-                    .lines()
-                    .filter(|l| !l.is_empty())
-                    .map(|l| l.to_string())
-                    .collect()
-            })
-            .unwrap_or_default();
+    fn add_baseline(&mut self, baseline_path: &Path) -> Result<()> {
+        let observed = Observed::from_pack(baseline_path)?;
+        self.baseline_file_paths.add(observed.file_paths);
+        self.baseline_net_addrs.add(observed.net_addrs);
+        self.baseline_file_errors.add(observed.file_errors);
+        self.baseline_processes.add(observed.processes);
+        self.baseline_stderr_lines.add(observed.stderr_lines);
+        Ok(())
+    }
 
-        Ok(Self {
-            baseline_file_paths,
-            baseline_net_addrs,
-            baseline_file_errors,
-            baseline_processes,
-            baseline_stderr_lines,
-            divergences: Vec::new(),
-        })
+    /// Counts each of the profile's runs as a baseline. Its paths and
+    /// lines were normalized, so from here on events are looked up both as
+    /// they are and normalized.
+    fn add_profile(&mut self, profile: &BaselineProfile) {
+        let add = |seen: &mut Seen, counts: &BTreeMap<String, usize>| {
+            for (key, n) in counts {
+                *seen.counts.entry(key.clone()).or_default() += n;
+            }
+        };
+        add(&mut self.baseline_file_paths, &profile.file_paths);
+        add(&mut self.baseline_net_addrs, &profile.net_addrs);
+        add(&mut self.baseline_file_errors, &profile.file_errors);
+        add(&mut self.baseline_processes, &profile.processes);
+        add(&mut self.baseline_stderr_lines, &profile.stderr_lines);
+        self.normalize = true;
+    }
+
+    /// Whether the baselines show `key`, also as normalized when a profile
+    /// was loaded.
+    fn expected(&self, seen: &Seen, key: &str) -> bool {
+        seen.contains(key) || (self.normalize && seen.contains(&diff::normalize_command(key)))
+    }
+
+    fn push(&mut self, ts: u64, kind: DivergenceKind, description: String) {
+        self.divergences.push(Divergence {
+            ts_ms: ts as f64 / 1_000_000.0,
+            kind,
+            description,
+            severity: self.policy.severity(kind),
+        });
     }
 
     pub fn check_event(&mut self, event: &TraceEvent) {
         match event {
             TraceEvent::File(f) => {
                 if let Some(ref path) = f.path {
-                    if !self.baseline_file_paths.contains(path)
+                    if !self.expected(&self.baseline_file_paths, path)
                         && !crate::explain::analyzer::is_noise_path_pub(Some(path.as_str()))
                         && !path.contains("poe-pyhook")
                         && !path.contains("poe-rt-")
                         && !path.contains("poe-build-")
                     {
-                        self.divergences.push(Divergence {
-                            ts_ms: f.ts as f64 / 1_000_000.0,
-                            kind: DivergenceKind::NewFilePath,
-                            description: format!("new file access: {} {}", f.op.as_str(), path),
-                        });
+                        self.push(
+                            f.ts,
+                            DivergenceKind::NewFilePath,
+                            format!("new file access: {} {}", f.op.as_str(), path),
+                        );
                     }
 
                     if let Some(result) = f.result {
                         if result < 0
-                            && !self.baseline_file_errors.contains(path)
+                            && !self.expected(&self.baseline_file_errors, path)
                             && !crate::explain::analyzer::is_noise_path_pub(Some(path.as_str()))
                             && !path.contains("poe-pyhook")
                             && !path.contains("poe-rt-")
                             && !path.contains("poe-build-")
                         {
-                            self.divergences.push(Divergence {
-                                ts_ms: f.ts as f64 / 1_000_000.0,
-                                kind: DivergenceKind::NewFileError,
-                                description: format!(
-                                    "new file error: {} {} -> {}",
-                                    f.op.as_str(),
-                                    path,
-                                    result
-                                ),
-                            });
+                            self.push(
+                                f.ts,
+                                DivergenceKind::NewFileError,
+                                format!("new file error: {} {} -> {}", f.op.as_str(), path, result),
+                            );
                         }
                     }
                 }
             }
-            TraceEvent::Net(n) => {
-                if n.op == NetOpKind::Connect {
-                    if let Some(ref dst) = n.dst {
-                        if !self.baseline_net_addrs.contains(dst) {
-                            self.divergences.push(Divergence {
-                                ts_ms: n.ts as f64 / 1_000_000.0,
-                                kind: DivergenceKind::NewNetConnection,
-                                description: format!("new network connection: {}", dst),
-                            });
-                        }
+            TraceEvent::Net(n) if n.op == NetOpKind::Connect => {
+                if let Some(ref dst) = n.dst {
+                    if !self.expected(&self.baseline_net_addrs, dst) {
+                        self.push(
+                            n.ts,
+                            DivergenceKind::NewNetConnection,
+                            format!("new network connection: {}", dst),
+                        );
+                    }
 
-                        if let Some(result) = n.result {
-                            if result < 0 && result != -115 {
-                                self.divergences.push(Divergence {
-                                    ts_ms: n.ts as f64 / 1_000_000.0,
-                                    kind: DivergenceKind::FailedNetConnection,
-                                    description: format!(
-                                        "failed connection: {} -> {}",
-                                        dst, result
-                                    ),
-                                });
-                            }
+                    if let Some(result) = n.result {
+                        if result < 0 && result != -115 {
+                            self.push(
+                                n.ts,
+                                DivergenceKind::FailedNetConnection,
+                                format!("failed connection: {} -> {}", dst, result),
+                            );
                         }
                     }
                 }
             }
             TraceEvent::Process(p) => {
                 let cmd = p.argv.join(" ");
-                if !self.baseline_processes.contains(&cmd) {
-                    self.divergences.push(Divergence {
-                        ts_ms: p.start_ts as f64 / 1_000_000.0,
-                        kind: DivergenceKind::NewProcess,
-                        description: format!("new process: {}", cmd),
-                    });
+                if !self
+                    .baseline_processes
+                    .contains(&diff::normalize_command(&cmd))
+                {
+                    self.push(
+                        p.start_ts,
+                        DivergenceKind::NewProcess,
+                        format!("new process: {}", cmd),
+                    );
                 }
             }
-            TraceEvent::Stdio(chunk) => {
-                if chunk.stream == StdioStream::Stderr {
-                    let text = String::from_utf8_lossy(&chunk.data);
-                    for line in text.lines() {
-                        if !line.is_empty() && !self.baseline_stderr_lines.contains(line) {
-                            self.divergences.push(Divergence {
-                                ts_ms: chunk.ts as f64 / 1_000_000.0,
-                                kind: DivergenceKind::ExtraStderr,
-                                description: format!(
-                                    "new stderr: {}",
-                                    &line[..line.len().min(120)]
-                                ),
-                            });
-                        }
+            TraceEvent::Stdio(chunk) if chunk.stream == StdioStream::Stderr => {
+                let text = self.stderr.push(&chunk.data);
+                for line in text.lines() {
+                    if !line.is_empty() && !self.expected(&self.baseline_stderr_lines, line) {
+                        self.push(
+                            chunk.ts,
+                            DivergenceKind::ExtraStderr,
+                            format!("new stderr: {}", &line[..line.len().min(120)]),
+                        );
                     }
                 }
             }
@@ -204,19 +461,67 @@ impl RealtimeDiffState {
 
 pub struct RealtimeDiffMonitor {
     state: Arc<Mutex<RealtimeDiffState>>,
+    halt_at: Option<Severity>,
+    /// Root of the traced command once it is running, 0 before.
+    target: AtomicI32,
+    halted: Mutex<Option<Divergence>>,
 }
 
 impl RealtimeDiffMonitor {
-    pub fn new(baseline_path: &Path) -> Result<Self> {
-        let state = RealtimeDiffState::from_baseline(baseline_path)?;
+    pub fn new(
+        baseline_paths: &[PathBuf],
+        profile: Option<&BaselineProfile>,
+        policy: DiffPolicy,
+    ) -> Result<Self> {
+        let halt_at = policy.halt_at;
+        let state = RealtimeDiffState::from_baselines(baseline_paths, profile, policy)?;
         Ok(Self {
             state: Arc::new(Mutex::new(state)),
+            halt_at,
+            target: AtomicI32::new(0),
+            halted: Mutex::new(None),
         })
     }
 
+    /// Lets `--diff-halt` stop `root_pid`. A halting divergence seen before
+    /// the command was known stops it now.
+    pub fn arm(&self, root_pid: i32) {
+        self.target.store(root_pid, Ordering::SeqCst);
+        if self.halted().is_some() {
+            interrupt::terminate(root_pid);
+        }
+    }
+
     pub fn check(&self, event: &TraceEvent) {
-        if let Ok(mut state) = self.state.lock() {
-            state.check_event(event);
+        let Ok(mut state) = self.state.lock() else {
+            return;
+        };
+        let seen = state.divergences.len();
+        state.check_event(event);
+        let Some(halt_at) = self.halt_at else {
+            return;
+        };
+        let Some(first) = state.divergences[seen..]
+            .iter()
+            .find(|d| d.severity >= halt_at)
+        else {
+            return;
+        };
+        let Ok(mut halted) = self.halted.lock() else {
+            return;
+        };
+        if halted.is_some() {
+            return;
+        }
+        log::warn!(
+            "stopping the command: {} divergence from baseline: {}",
+            first.severity.as_str(),
+            first.description
+        );
+        *halted = Some(first.clone());
+        match self.target.load(Ordering::SeqCst) {
+            0 => {}
+            pid => interrupt::terminate(pid),
         }
     }
 
@@ -231,4 +536,81 @@ impl RealtimeDiffMonitor {
     pub fn has_diverged(&self) -> bool {
         self.state.lock().map(|s| s.has_diverged()).unwrap_or(false)
     }
+
+    /// The divergence that stopped the run under `--diff-halt`.
+    pub fn halted(&self) -> Option<Divergence> {
+        self.halted.lock().ok().and_then(|h| h.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn divergence(kind: DivergenceKind, ts_ms: f64, policy: &DiffPolicy) -> Divergence {
+        Divergence {
+            ts_ms,
+            kind,
+            description: format!("{} at {}", kind.as_str(), ts_ms),
+            severity: policy.severity(kind),
+        }
+    }
+
+    #[test]
+    fn overrides_rescore_kinds_and_summary_leads_with_the_worst() {
+        let policy = DiffPolicy::with_overrides(
+            &[
+                "new_process=critical".into(),
+                "extra_stderr = warning".into(),
+            ],
+            Some(Severity::Critical),
+        )
+        .unwrap();
+        assert_eq!(
+            policy.severity(DivergenceKind::NewProcess),
+            Severity::Critical
+        );
+        assert_eq!(policy.severity(DivergenceKind::NewFilePath), Severity::Info);
+        assert!(DiffPolicy::with_overrides(&["new_process".into()], None).is_err());
+        assert!(DiffPolicy::with_overrides(&["bogus=info".into()], None).is_err());
+
+        let divergences = [
+            divergence(DivergenceKind::NewFilePath, 1.0, &policy),
+            divergence(DivergenceKind::NewFilePath, 2.0, &policy),
+            divergence(DivergenceKind::ExtraStderr, 3.0, &policy),
+            divergence(DivergenceKind::NewProcess, 4.0, &policy),
+        ];
+        let groups = summarize(&divergences);
+        let order: Vec<(&str, usize)> = groups.iter().map(|g| (g.kind.as_str(), g.count)).collect();
+        assert_eq!(
+            order,
+            [
+                ("new_process", 1),
+                ("extra_stderr", 1),
+                ("new_file_path", 2)
+            ]
+        );
+        assert_eq!(groups[2].first_ts_ms, 1.0);
+    }
+
+    #[test]
+    fn quorum_sets_how_many_baselines_must_agree() {
+        let runs = [["/etc/hosts", "/tmp/a1"], ["/etc/hosts", "/tmp/b2"]];
+        let seen = |quorum| {
+            let mut seen = Seen {
+                quorum,
+                ..Default::default()
+            };
+            for run in runs {
+                seen.add(run.iter().map(|p| p.to_string()).collect());
+            }
+            seen
+        };
+        let any = seen(1);
+        assert!(any.contains("/etc/hosts") && any.contains("/tmp/a1"));
+        assert!(!any.contains("/tmp/c3"));
+        let all = seen(2);
+        assert!(all.contains("/etc/hosts"));
+        assert!(!all.contains("/tmp/a1"));
+    }
 }
@@ -0,0 +1,300 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::explain::analyzer::{self, ExplainOutput, TimelineEntry};
+use crate::pack::reader::PackReader;
+use crate::pack::summary::PackSummary;
+use crate::trace::db::TraceDb;
+use crate::trace::{calltree, pycalls};
+
+/// Page layout, styles and viewer scripts; the report data is spliced in
+/// where the template holds `POE_REPORT_DATA`.
+const TEMPLATE: &str = include_str!("report.html");
+const DATA_PLACEHOLDER: &str = "POE_REPORT_DATA";
+
+/// The most recent timeline entries kept; bounds the page size for long runs.
+const MAX_TIMELINE_ENTRIES: usize = 20_000;
+/// Tail of stdout/stderr kept.
+const MAX_STDIO_BYTES: usize = 2 * 1024 * 1024;
+
+#[derive(Debug, Serialize)]
+struct ReportData<'a> {
+    summary: &'a PackSummary,
+    explain: &'a ExplainOutput,
+    processes: Vec<ReportProcess>,
+    timeline: Vec<TimelineEntry>,
+    flamegraphs: Vec<Flamegraph>,
+    stderr: Option<String>,
+    stdout: Option<String>,
+    generated_at: String,
+}
+
+#[derive(Debug, Serialize)]
+struct ReportProcess {
+    pid: i32,
+    parent_pid: Option<i32>,
+    command: String,
+    start_ms: f64,
+    end_ms: Option<f64>,
+    exit_code: Option<i32>,
+    signal: Option<i32>,
+}
+
+#[derive(Debug, Default, Serialize)]
+struct FlameNode {
+    name: String,
+    value: f64,
+    children: Vec<FlameNode>,
+}
+
+impl FlameNode {
+    fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            ..Default::default()
+        }
+    }
+
+    fn child(&mut self, name: &str) -> &mut FlameNode {
+        let i = match self.children.iter().position(|c| c.name == name) {
+            Some(i) => i,
+            None => {
+                self.children.push(FlameNode::new(name));
+                self.children.len() - 1
+            }
+        };
+        &mut self.children[i]
+    }
+
+    /// Raises each node to at least the sum of its children, so grouping
+    /// nodes (threads, the root) span what they contain.
+    fn fill(&mut self) -> f64 {
+        let sum: f64 = self.children.iter_mut().map(|c| c.fill()).sum();
+        if self.value < sum {
+            self.value = sum;
+        }
+        self.value
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct Flamegraph {
+    title: String,
+    /// What node values measure: `ms` or `samples`.
+    unit: &'static str,
+    root: FlameNode,
+}
+
+/// Renders a self-contained HTML page for a pack: everything the viewer
+/// needs is inlined, so the file opens without poe or network access.
+pub fn render(pack: &PackReader, output: &ExplainOutput) -> Result<String> {
+    let db = pack.db();
+    let data = ReportData {
+        summary: pack.summary(),
+        explain: output,
+        processes: processes(db)?,
+        timeline: timeline(db)?,
+        flamegraphs: flamegraphs(db)?,
+        stderr: pack.stdio_text("stderr").map(|s| tail(&s)),
+        stdout: pack.stdio_text("stdout").map(|s| tail(&s)),
+        generated_at: chrono::Utc::now().to_rfc3339(),
+    };
+    // `<` only occurs inside JSON strings, where the escape is equivalent;
+    // this keeps `</script>` in captured output from ending the block.
+    let json = serde_json::to_string(&data)?.replace('<', "\\u003c");
+    Ok(TEMPLATE.replacen(DATA_PLACEHOLDER, &json, 1))
+}
+
+fn tail(s: &str) -> String {
+    if s.len() <= MAX_STDIO_BYTES {
+        return s.to_string();
+    }
+    let mut start = s.len() - MAX_STDIO_BYTES;
+    while !s.is_char_boundary(start) {
+        start += 1;
+    }
+    s[start..].to_string()
+}
+
+fn processes(db: &TraceDb) -> Result<Vec<ReportProcess>> {
+    Ok(db
+        .query_processes()?
+        .into_iter()
+        .map(|p| ReportProcess {
+            pid: p.proc_id,
+            parent_pid: p.parent_proc_id,
+            command: p
+                .argv
+                .as_deref()
+                .and_then(|a| serde_json::from_str::<Vec<String>>(a).ok())
+                .map(|args| args.join(" "))
+                .unwrap_or_else(|| format!("pid:{}", p.proc_id)),
+            start_ms: p.start_ts as f64 / 1_000_000.0,
+            end_ms: p.end_ts.map(|t| t as f64 / 1_000_000.0),
+            exit_code: p.exit_code,
+            signal: p.signal,
+        })
+        .collect())
+}
+
+fn op_description(
+    op: &str,
+    target: Option<&str>,
+    bytes: Option<i64>,
+    result: Option<i64>,
+) -> String {
+    let mut desc = op.to_string();
+    if let Some(t) = target {
+        desc.push(' ');
+        desc.push_str(t);
+    }
+    if let Some(b) = bytes {
+        desc.push_str(&format!(" ({} bytes)", b));
+    }
+    match result {
+        Some(-115) => desc.push_str(" (in progress)"),
+        Some(r) if r < 0 => desc.push_str(&format!(" err={}", analyzer::errno_name(-r))),
+        Some(r) => desc.push_str(&format!(" -> {}", r)),
+        None => {}
+    }
+    desc
+}
+
+/// Every event, file and network operation, unlike the explain timeline
+/// which keeps only the last few of each.
+fn timeline(db: &TraceDb) -> Result<Vec<TimelineEntry>> {
+    let mut entries: Vec<TimelineEntry> = Vec::new();
+    for e in db.query_last_events(MAX_TIMELINE_ENTRIES)? {
+        let description =
+            analyzer::format_event_description(&e.kind, e.detail.as_deref().unwrap_or(""));
+        if !description.is_empty() {
+            entries.push(TimelineEntry {
+                ts_ms: e.ts as f64 / 1_000_000.0,
+                proc_id: e.proc_id,
+                kind: e.kind,
+                description,
+            });
+        }
+    }
+    for f in db.query_file_events()? {
+        if analyzer::is_noise_path_pub(f.path.as_deref()) {
+            continue;
+        }
+        entries.push(TimelineEntry {
+            ts_ms: f.ts as f64 / 1_000_000.0,
+            proc_id: f.proc_id,
+            kind: "file".into(),
+            description: op_description(&f.op, f.path.as_deref(), f.bytes, f.result),
+        });
+    }
+    for n in db.query_net_events()? {
+        entries.push(TimelineEntry {
+            ts_ms: n.ts as f64 / 1_000_000.0,
+            proc_id: n.proc_id,
+            kind: "net".into(),
+            description: op_description(&n.op, n.dst.as_deref(), n.bytes, n.result),
+        });
+    }
+    entries.sort_by(|a, b| a.ts_ms.total_cmp(&b.ts_ms));
+    let excess = entries.len().saturating_sub(MAX_TIMELINE_ENTRIES);
+    entries.drain(..excess);
+    Ok(entries)
+}
+
+/// One flamegraph per source of call data in the pack: instrumented native
+/// calls, Python calls, and sampled stacks.
+fn flamegraphs(db: &TraceDb) -> Result<Vec<Flamegraph>> {
+    let mut graphs = Vec::new();
+
+    let native = calltree::compute(db)?;
+    if !native.threads.is_empty() {
+        fn convert(node: &calltree::CallNode) -> FlameNode {
+            FlameNode {
+                name: node.func.clone(),
+                value: node.inclusive_ms,
+                children: node.children.iter().map(convert).collect(),
+            }
+        }
+        let mut root = FlameNode::new("all");
+        for t in &native.threads {
+            let mut thread = FlameNode::new(format!("thread {}", t.tid));
+            thread.children = t.roots.iter().map(convert).collect();
+            root.children.push(thread);
+        }
+        root.fill();
+        graphs.push(Flamegraph {
+            title: "native calls".into(),
+            unit: "ms",
+            root,
+        });
+    }
+
+    let py = pycalls::compute(db, false)?;
+    if !py.calls.is_empty() {
+        let mut root = FlameNode::new("all");
+        let mut stacks: HashMap<i64, Vec<String>> = HashMap::new();
+        for call in &py.calls {
+            let stack = stacks.entry(call.tid).or_default();
+            stack.truncate(call.depth);
+            stack.push(call.func.clone());
+            let mut node = root.child(&format!("thread {}", call.tid));
+            for name in stack.iter() {
+                node = node.child(name);
+            }
+            node.value += call.duration_ms.unwrap_or(0.0);
+        }
+        root.fill();
+        graphs.push(Flamegraph {
+            title: "python calls".into(),
+            unit: "ms",
+            root,
+        });
+    }
+
+    let stacks = db.query_stacks()?;
+    if !stacks.is_empty() {
+        let mut root = FlameNode::new("all");
+        for s in &stacks {
+            let frames: Vec<u64> = serde_json::from_str(&s.frames).unwrap_or_default();
+            let weight = s.weight.unwrap_or(1) as f64;
+            root.value += weight;
+            let mut node = root.child(&format!("pid {}", s.proc_id));
+            node.value += weight;
+            // Samples are stored innermost frame first.
+            for addr in frames.iter().rev() {
+                node = node.child(&format!("{:#x}", addr));
+                node.value += weight;
+            }
+        }
+        graphs.push(Flamegraph {
+            title: "stack samples".into(),
+            unit: "samples",
+            root,
+        });
+    }
+
+    Ok(graphs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flame_nodes_merge_paths_and_fill_parents() {
+        let mut root = FlameNode::new("all");
+        root.child("main").child("load").value += 3.0;
+        root.child("main").child("load").value += 2.0;
+        root.child("main").child("save").value += 1.0;
+        assert_eq!(root.fill(), 6.0);
+        assert_eq!(root.children.len(), 1);
+        assert_eq!(root.children[0].children[0].value, 5.0);
+    }
+
+    #[test]
+    fn template_has_one_data_slot() {
+        assert_eq!(TEMPLATE.matches(DATA_PLACEHOLDER).count(), 1);
+    }
+}
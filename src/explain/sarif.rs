@@ -0,0 +1,325 @@
+use std::path::Path;
+
+use serde_json::{json, Value};
+
+use crate::explain::analyzer::{self, ErrorPattern, ExplainOutput, PythonExceptionInfo};
+use crate::hooks::rust::RustPanicInfo;
+use crate::pack::summary::PackSummary;
+use crate::trace::pycalls;
+
+const SCHEMA: &str = "https://json.schemastore.org/sarif-2.1.0.json";
+const SRCROOT: &str = "%SRCROOT%";
+
+/// Rule ids are the error pattern categories; these two are emitted from the
+/// parsed exception or panic, which carry their own locations.
+const RUST_PANIC_RULE: &str = "rust_panic";
+const PYTHON_EXCEPTION_RULE: &str = "python_exception";
+
+/// Converts the explain output into a SARIF 2.1.0 log. Patterns that can be
+/// tied to a source line (Python exceptions, Rust panics, or errors at the
+/// failure's primary location) become results; the rest, which
+/// code-scanning UIs cannot place, are reported as tool notifications.
+pub fn render(summary: &PackSummary, output: &ExplainOutput, source_root: &Path) -> Value {
+    let mut sarif = Sarif {
+        root: source_root,
+        rules: Vec::new(),
+        results: Vec::new(),
+        notifications: Vec::new(),
+    };
+
+    for exc in &output.python_exceptions {
+        sarif.python_exception(exc);
+    }
+    if let Some(ref panic) = output.rust_panic {
+        sarif.rust_panic(panic);
+    }
+
+    let failure_location = output
+        .failure
+        .as_ref()
+        .and_then(|f| f.primary_location.as_ref())
+        .and_then(|loc| {
+            let file = loc.file.as_deref()?;
+            Some(sarif.location(file, loc.line?, loc.function.as_deref()))
+        });
+    // The exceptions and panics above already cover these, with better locations.
+    let has_exception_results = !sarif.results.is_empty();
+    for pattern in &output.error_patterns {
+        if has_exception_results
+            && matches!(
+                pattern.category.as_str(),
+                PYTHON_EXCEPTION_RULE | RUST_PANIC_RULE | "exception"
+            )
+        {
+            continue;
+        }
+        // Only patterns severe enough to explain the failure are pinned to
+        // its location; warnings there would annotate an unrelated line.
+        let location = failure_location
+            .clone()
+            .filter(|_| level(&pattern.severity) == "error");
+        sarif.pattern(pattern, location);
+    }
+
+    let mut properties = json!({
+        "runId": summary.run_id,
+        "command": summary.command,
+        "exitCode": summary.exit_code,
+        "durationMs": summary.duration_ms,
+    });
+    if let Some(ref sig) = summary.signal_name {
+        properties["signal"] = json!(sig);
+    }
+    if let Some(ref sha) = summary.git_sha {
+        properties["gitSha"] = json!(sha);
+    }
+
+    json!({
+        "$schema": SCHEMA,
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": "poe",
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "informationUri": "https://github.com/Jaso1024/poe",
+                    "rules": sarif.rules.iter().map(|r| r.to_json()).collect::<Vec<_>>(),
+                }
+            },
+            "originalUriBaseIds": {
+                SRCROOT: { "uri": dir_uri(source_root) }
+            },
+            "invocations": [{
+                "executionSuccessful": true,
+                "startTimeUtc": summary.timestamp,
+                "workingDirectory": { "uri": dir_uri(Path::new(&summary.working_dir)) },
+                "toolExecutionNotifications": sarif.notifications,
+                "properties": properties,
+            }],
+            "results": sarif.results,
+        }]
+    })
+}
+
+struct Rule {
+    id: String,
+    description: String,
+    level: &'static str,
+}
+
+impl Rule {
+    fn to_json(&self) -> Value {
+        json!({
+            "id": self.id,
+            "name": self.id,
+            "shortDescription": { "text": self.description },
+            "defaultConfiguration": { "level": self.level },
+            "properties": { "tags": ["poe"] },
+        })
+    }
+}
+
+struct Sarif<'a> {
+    root: &'a Path,
+    rules: Vec<Rule>,
+    results: Vec<Value>,
+    notifications: Vec<Value>,
+}
+
+impl Sarif<'_> {
+    fn rule(&mut self, id: &str, description: &str, level: &'static str) -> usize {
+        if let Some(i) = self.rules.iter().position(|r| r.id == id) {
+            return i;
+        }
+        self.rules.push(Rule {
+            id: id.to_string(),
+            description: description.to_string(),
+            level,
+        });
+        self.rules.len() - 1
+    }
+
+    fn result(&self, rule: usize, level: &str, message: String, location: Value) -> Value {
+        json!({
+            "ruleId": self.rules[rule].id,
+            "ruleIndex": rule,
+            "level": level,
+            "message": { "text": message },
+            "locations": [location],
+        })
+    }
+
+    fn python_exception(&mut self, exc: &PythonExceptionInfo) {
+        let Some(loc) = analyzer::python_exception_location(exc) else {
+            return;
+        };
+        let (Some(file), Some(line)) = (loc.file.as_deref(), loc.line) else {
+            return;
+        };
+        let rule = self.rule(PYTHON_EXCEPTION_RULE, "Unhandled Python exception", "error");
+        let location = self.location(file, line, loc.function.as_deref());
+        let mut result = self.result(
+            rule,
+            "error",
+            format!("{}: {}", exc.exc_type, exc.exc_msg),
+            location,
+        );
+        // SARIF stacks list the innermost frame first.
+        let frames: Vec<Value> = exc
+            .traceback
+            .iter()
+            .rev()
+            .filter(|f| !f.file.starts_with('<'))
+            .map(|f| {
+                let mut frame = json!({
+                    "location": self.location(&f.file, f.line, Some(&f.func)),
+                });
+                if pycalls::is_library_file(&f.file) {
+                    frame["properties"] = json!({ "library": true });
+                }
+                frame
+            })
+            .collect();
+        if !frames.is_empty() {
+            result["stacks"] = json!([{
+                "message": { "text": "traceback" },
+                "frames": frames,
+            }]);
+        }
+        self.results.push(result);
+    }
+
+    fn rust_panic(&mut self, panic: &RustPanicInfo) {
+        let Some(ref loc) = panic.location else {
+            return;
+        };
+        let rule = self.rule(RUST_PANIC_RULE, "Rust panic", "error");
+        let mut location = self.location(&loc.file, loc.line, None);
+        if let Some(column) = loc.column {
+            location["physicalLocation"]["region"]["startColumn"] = json!(column);
+        }
+        let message = match panic.thread {
+            Some(ref thread) => format!("thread '{}' panicked: {}", thread, panic.message),
+            None => format!("panicked: {}", panic.message),
+        };
+        let mut result = self.result(rule, "error", message, location);
+        let frames: Vec<Value> = panic
+            .backtrace
+            .iter()
+            .filter_map(|f| {
+                let file = f.file.as_deref()?;
+                Some(json!({
+                    "location": self.location(file, f.line?, f.symbol.as_deref()),
+                }))
+            })
+            .collect();
+        if !frames.is_empty() {
+            result["stacks"] = json!([{
+                "message": { "text": "backtrace" },
+                "frames": frames,
+            }]);
+        }
+        self.results.push(result);
+    }
+
+    fn pattern(&mut self, pattern: &ErrorPattern, location: Option<Value>) {
+        let level = level(&pattern.severity);
+        let mut message = pattern.description.clone();
+        for example in pattern.examples.iter().take(3) {
+            message.push_str("\n  ");
+            message.push_str(example);
+        }
+        match location {
+            Some(location) => {
+                let rule = self.rule(&pattern.category, &pattern.description, level);
+                let result = self.result(rule, level, message, location);
+                self.results.push(result);
+            }
+            None => self.notifications.push(json!({
+                "descriptor": { "id": pattern.category },
+                "level": level,
+                "message": { "text": message },
+            })),
+        }
+    }
+
+    fn location(&self, file: &str, line: u32, function: Option<&str>) -> Value {
+        let mut location = json!({
+            "physicalLocation": {
+                "artifactLocation": artifact(file, self.root),
+                "region": { "startLine": line.max(1) },
+            }
+        });
+        if let Some(func) = function {
+            location["logicalLocations"] = json!([{ "name": func, "kind": "function" }]);
+        }
+        location
+    }
+}
+
+fn level(severity: &str) -> &'static str {
+    match severity {
+        "critical" | "error" => "error",
+        "warning" => "warning",
+        _ => "note",
+    }
+}
+
+/// Paths under the source root become relative to `%SRCROOT%`, which is what
+/// code-scanning UIs match against the repository; others stay absolute.
+fn artifact(file: &str, root: &Path) -> Value {
+    let path = Path::new(file);
+    if path.is_relative() {
+        return json!({ "uri": encode_uri(file), "uriBaseId": SRCROOT });
+    }
+    match path.strip_prefix(root) {
+        Ok(rel) => json!({
+            "uri": encode_uri(&rel.to_string_lossy()),
+            "uriBaseId": SRCROOT,
+        }),
+        Err(_) => json!({ "uri": format!("file://{}", encode_uri(file)) }),
+    }
+}
+
+fn dir_uri(dir: &Path) -> String {
+    let mut uri = format!("file://{}", encode_uri(&dir.to_string_lossy()));
+    if !uri.ends_with('/') {
+        uri.push('/');
+    }
+    uri
+}
+
+fn encode_uri(path: &str) -> String {
+    let mut out = String::with_capacity(path.len());
+    for b in path.bytes() {
+        if b.is_ascii_alphanumeric() || b"/-._~".contains(&b) {
+            out.push(b as char);
+        } else {
+            out.push_str(&format!("%{:02X}", b));
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn artifacts_are_relative_to_source_root() {
+        let root = Path::new("/work/repo");
+        assert_eq!(
+            artifact("/work/repo/src/app.py", root),
+            json!({ "uri": "src/app.py", "uriBaseId": SRCROOT })
+        );
+        assert_eq!(
+            artifact("src/main.rs", root),
+            json!({ "uri": "src/main.rs", "uriBaseId": SRCROOT })
+        );
+        assert_eq!(
+            artifact("/usr/lib/python3/my file.py", root),
+            json!({ "uri": "file:///usr/lib/python3/my%20file.py" })
+        );
+        assert_eq!(dir_uri(root), "file:///work/repo/");
+    }
+}
@@ -0,0 +1,421 @@
+use anyhow::Result;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::explain::analyzer::{self, ExplainOutput, FailedConnection, FailedFileOp};
+use crate::pack::filestat;
+use crate::trace::db::{FileQueryResult, NetQueryResult, TraceDb};
+
+const MAX_PER_KIND: usize = 3;
+
+/// A suggested next step derived from a diagnosed pattern and the evidence
+/// around it in the trace.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct Suggestion {
+    pub category: String,
+    pub message: String,
+    pub action: Option<String>,
+}
+
+pub fn build_suggestions(db: &TraceDb, output: &ExplainOutput) -> Result<Vec<Suggestion>> {
+    let files = db.query_file_events()?;
+    let net = db.query_net_events()?;
+    let mut suggestions = Vec::new();
+
+    for missing in output
+        .file_activity
+        .failed_opens
+        .iter()
+        .filter(|f| f.op == "open" && analyzer::is_significant_missing_file(&f.path))
+        .take(MAX_PER_KIND)
+    {
+        suggestions.push(suggest_missing_file(missing, &files, output));
+    }
+
+    for denied in output
+        .file_activity
+        .permission_errors
+        .iter()
+        .take(MAX_PER_KIND)
+    {
+        let state = match output.file_activity.stat_of(&denied.path) {
+            Some(stat) if stat.uid.is_some() => format!("it is {}", filestat::describe(stat)),
+            _ => "its owner or mode does not allow it".into(),
+        };
+        suggestions.push(Suggestion {
+            category: "permission".into(),
+            message: format!(
+                "{} could not {} {} ({}); {}",
+                describe_pid(denied.pid, output),
+                denied.op,
+                denied.path,
+                denied.errno_name,
+                state
+            ),
+            action: Some(format!("ls -ld {}", denied.path)),
+        });
+    }
+
+    for err in output
+        .server_activity
+        .bind_errors
+        .iter()
+        .filter(|e| e.errno == libc::EADDRINUSE as i64)
+        .take(MAX_PER_KIND)
+    {
+        suggestions.push(suggest_addr_in_use(err, &net, output));
+    }
+
+    let mut seen_addrs: Vec<&str> = Vec::new();
+    for conn in &output.net_activity.failed_connections {
+        if seen_addrs.len() >= MAX_PER_KIND || seen_addrs.contains(&conn.addr.as_str()) {
+            continue;
+        }
+        seen_addrs.push(&conn.addr);
+        suggestions.push(suggest_failed_connect(conn, &net, output));
+    }
+
+    for exc in &output.python_exceptions {
+        if let Some(module) = missing_python_module(&exc.exc_msg) {
+            suggestions.push(Suggestion {
+                category: "python_exception".into(),
+                message: format!(
+                    "Python module '{}' is not importable from the interpreter that ran",
+                    module
+                ),
+                action: Some(format!(
+                    "pip install {} (or activate the environment that has it)",
+                    module.split('.').next().unwrap_or(module)
+                )),
+            });
+        }
+    }
+
+    Ok(suggestions)
+}
+
+fn suggest_missing_file(
+    missing: &FailedFileOp,
+    files: &[FileQueryResult],
+    output: &ExplainOutput,
+) -> Suggestion {
+    let failed_ns = (missing.ts_ms * 1_000_000.0) as i64;
+    let who = describe_pid(missing.pid, output);
+
+    let touches: Vec<(&FileQueryResult, bool)> = files
+        .iter()
+        .filter(|ev| ev.result.is_some_and(|r| r >= 0))
+        .filter_map(|ev| {
+            let path = ev.path.as_deref()?;
+            if creates_path(ev, path, &missing.path) {
+                Some((ev, true))
+            } else if removes_path(ev, path, &missing.path) {
+                Some((ev, false))
+            } else {
+                None
+            }
+        })
+        .collect();
+
+    let before = touches.iter().rev().find(|(ev, _)| ev.ts < failed_ns);
+    let after = touches
+        .iter()
+        .find(|(ev, created)| ev.ts > failed_ns && *created);
+
+    let (message, action) = match (before, after) {
+        (Some((ev, false)), _) => (
+            format!(
+                "{} was removed by {} at {:.1}ms, before {} tried to {} it at {:.1}ms",
+                missing.path,
+                describe_pid(ev.proc_id, output),
+                ev.ts as f64 / 1_000_000.0,
+                who,
+                missing.op,
+                missing.ts_ms
+            ),
+            Some("keep the file until its readers are done, or recreate it first".into()),
+        ),
+        (Some((ev, true)), _) => (
+            format!(
+                "{} was last written by {} at {:.1}ms but {} could not find it; check that both ran in the same working directory",
+                missing.path,
+                describe_pid(ev.proc_id, output),
+                ev.ts as f64 / 1_000_000.0,
+                who
+            ),
+            None,
+        ),
+        (None, Some((ev, _))) => (
+            format!(
+                "{} was only created by {} at {:.1}ms, after {} needed it at {:.1}ms",
+                missing.path,
+                describe_pid(ev.proc_id, output),
+                ev.ts as f64 / 1_000_000.0,
+                who,
+                missing.ts_ms
+            ),
+            Some("make the step that produces the file finish before its consumer starts".into()),
+        ),
+        (None, None) => {
+            let files = &output.file_activity;
+            let parent = std::path::Path::new(&missing.path)
+                .parent()
+                .and_then(|p| files.stat_of(&p.to_string_lossy()));
+            match files.stat_of(&missing.path) {
+                // Something outside the traced processes created it.
+                Some(stat) if stat.file_type != "missing" && stat.file_type != "inaccessible" => (
+                    format!(
+                        "{} was missing when {} needed it but existed when the pack was written ({}), so it was created outside the run",
+                        missing.path,
+                        who,
+                        filestat::describe(stat)
+                    ),
+                    Some("wait for whatever creates the file before starting".into()),
+                ),
+                Some(_) if parent.is_some_and(|p| p.file_type == "missing") => (
+                    format!(
+                        "file expected at {} by {} was never created, and its directory {} does not exist either",
+                        missing.path,
+                        who,
+                        parent.unwrap().path
+                    ),
+                    Some(format!("mkdir -p {}", parent.unwrap().path)),
+                ),
+                _ => (
+                    format!(
+                        "file expected at {} by {} was never created during the run",
+                        missing.path, who
+                    ),
+                    Some(format!(
+                        "check the path, or run the step that should produce {}",
+                        file_name(&missing.path)
+                    )),
+                ),
+            }
+        }
+    };
+
+    Suggestion {
+        category: "missing_file".into(),
+        message,
+        action,
+    }
+}
+
+fn suggest_addr_in_use(
+    err: &FailedConnection,
+    net: &[NetQueryResult],
+    output: &ExplainOutput,
+) -> Suggestion {
+    let port = port_of(&err.addr);
+    let failed_ns = (err.ts_ms * 1_000_000.0) as i64;
+
+    let holder = net.iter().rev().find(|ev| {
+        ev.op == "bind"
+            && ev.ts < failed_ns
+            && ev.result.is_some_and(|r| r >= 0)
+            && ev.dst.as_deref().map(port_of) == Some(port)
+    });
+
+    // A bind to port 0 gets an ephemeral port we never see, so it is the
+    // best remaining candidate.
+    let ephemeral = net.iter().rev().find(|ev| {
+        ev.op == "bind"
+            && ev.ts < failed_ns
+            && ev.result.is_some_and(|r| r >= 0)
+            && ev.dst.as_deref().map(port_of) == Some("0")
+    });
+
+    match (holder, ephemeral) {
+        (Some(ev), _) => Suggestion {
+            category: "address_in_use".into(),
+            message: format!(
+                "port {} is held by {}, which bound {} at {:.1}ms earlier in the run",
+                port,
+                describe_pid(ev.proc_id, output),
+                ev.dst.as_deref().unwrap_or("?"),
+                ev.ts as f64 / 1_000_000.0
+            ),
+            action: Some(format!(
+                "stop PID {} before starting the next server, or use a different port",
+                ev.proc_id
+            )),
+        },
+        (None, Some(ev)) => Suggestion {
+            category: "address_in_use".into(),
+            message: format!(
+                "port {} was never bound explicitly in this run; {} bound an ephemeral port at {:.1}ms and may hold it",
+                port,
+                describe_pid(ev.proc_id, output),
+                ev.ts as f64 / 1_000_000.0
+            ),
+            action: Some(format!("ss -ltnup 'sport = :{}'", port)),
+        },
+        (None, None) => {
+            let outside = output
+                .port_conflicts
+                .iter()
+                .find(|c| c.pid == err.pid && c.addr == err.addr && c.holder_pid.is_some());
+            match outside {
+                Some(c) => Suggestion {
+                    category: "address_in_use".into(),
+                    message: format!(
+                        "port {} is held by PID {}{}, which is outside this run",
+                        port,
+                        c.holder_pid.unwrap_or_default(),
+                        c.holder_command
+                            .as_deref()
+                            .map(|cmd| format!(" ({})", cmd))
+                            .unwrap_or_default()
+                    ),
+                    action: Some(format!(
+                        "stop PID {} or use a different port",
+                        c.holder_pid.unwrap_or_default()
+                    )),
+                },
+                None => Suggestion {
+                    category: "address_in_use".into(),
+                    message: format!(
+                        "port {} was not bound by anything in this run, so a process outside it holds {}",
+                        port, err.addr
+                    ),
+                    action: Some(format!("ss -ltnup 'sport = :{}'", port)),
+                },
+            }
+        }
+    }
+}
+
+fn suggest_failed_connect(
+    conn: &FailedConnection,
+    net: &[NetQueryResult],
+    output: &ExplainOutput,
+) -> Suggestion {
+    let port = port_of(&conn.addr);
+    let failed_ns = (conn.ts_ms * 1_000_000.0) as i64;
+
+    if conn.errno == libc::ECONNREFUSED as i64 {
+        let late_server = net.iter().find(|ev| {
+            (ev.op == "bind" || ev.op == "listen")
+                && ev.ts > failed_ns
+                && ev.result.is_some_and(|r| r >= 0)
+                && ev.dst.as_deref().map(port_of) == Some(port)
+        });
+        if let Some(ev) = late_server {
+            return Suggestion {
+                category: "network".into(),
+                message: format!(
+                    "{} started serving port {} at {:.1}ms, after the connect to {} at {:.1}ms was refused",
+                    describe_pid(ev.proc_id, output),
+                    port,
+                    ev.ts as f64 / 1_000_000.0,
+                    conn.addr,
+                    conn.ts_ms
+                ),
+                action: Some("wait for the server to accept connections before starting the client".into()),
+            };
+        }
+        return Suggestion {
+            category: "network".into(),
+            message: format!("nothing was listening on {}", conn.addr),
+            action: Some(format!(
+                "start the service on port {} or point the client at the right address",
+                port
+            )),
+        };
+    }
+
+    Suggestion {
+        category: "network".into(),
+        message: format!(
+            "{} could not reach {} ({})",
+            describe_pid(conn.pid, output),
+            conn.addr,
+            conn.errno_name
+        ),
+        action: Some("check DNS, routing and firewall rules for that address".into()),
+    }
+}
+
+fn creates_path(ev: &FileQueryResult, path: &str, target: &str) -> bool {
+    match ev.op.as_str() {
+        "open" => {
+            let flags = ev.flags.unwrap_or(0);
+            let writes = flags & (libc::O_WRONLY | libc::O_RDWR | libc::O_CREAT) != 0;
+            writes && same_path(path, target)
+        }
+        "rename" | "link" | "symlink" | "mkdir" => {
+            let dest = path.rsplit(" -> ").next().unwrap_or(path);
+            same_path(dest, target)
+        }
+        _ => false,
+    }
+}
+
+fn removes_path(ev: &FileQueryResult, path: &str, target: &str) -> bool {
+    match ev.op.as_str() {
+        "unlink" => same_path(path, target),
+        "rename" => path
+            .split_once(" -> ")
+            .is_some_and(|(src, _)| same_path(src, target)),
+        _ => false,
+    }
+}
+
+/// Paths are recorded as the program passed them, so a relative path matches
+/// an absolute one that ends with it.
+fn same_path(a: &str, b: &str) -> bool {
+    if a == b {
+        return true;
+    }
+    let (long, short) = if a.len() > b.len() { (a, b) } else { (b, a) };
+    let short = short.trim_start_matches("./");
+    !short.starts_with('/') && long.ends_with(&format!("/{}", short))
+}
+
+fn port_of(addr: &str) -> &str {
+    addr.rsplit(':').next().unwrap_or(addr)
+}
+
+fn file_name(path: &str) -> &str {
+    path.rsplit('/').next().unwrap_or(path)
+}
+
+fn missing_python_module(msg: &str) -> Option<&str> {
+    let rest = msg.strip_prefix("No module named ")?;
+    Some(rest.trim_matches(|c| c == '\'' || c == '"'))
+}
+
+fn describe_pid(pid: i32, output: &ExplainOutput) -> String {
+    match output.process_tree.iter().find(|p| p.pid == pid) {
+        Some(p) => {
+            let first_line = p.command.lines().next().unwrap_or("");
+            let cmd: String = first_line.chars().take(60).collect();
+            format!("PID {} ({})", pid, cmd)
+        }
+        None => format!("PID {}", pid),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn relative_paths_match_absolute() {
+        assert!(same_path("config.yaml", "/srv/app/config.yaml"));
+        assert!(same_path("./out/a.txt", "/tmp/out/a.txt"));
+        assert!(!same_path("/etc/config.yaml", "/srv/app/config.yaml"));
+        assert!(!same_path("g.yaml", "/srv/app/config.yaml"));
+    }
+
+    #[test]
+    fn extracts_missing_module() {
+        assert_eq!(
+            missing_python_module("No module named 'yaml'"),
+            Some("yaml")
+        );
+        assert_eq!(missing_python_module("bad input"), None);
+        assert_eq!(port_of("[::1]:8080"), "8080");
+    }
+}
@@ -0,0 +1,306 @@
+use serde::{Deserialize, Serialize};
+
+use crate::explain::analyzer::ExplainOutput;
+use crate::pack::summary::PackSummary;
+use crate::util;
+
+/// A recent p50 this much slower than the baseline's is a regression...
+const P50_REGRESSION_RATIO: f64 = 1.2;
+/// ...provided it also moved by at least this much, so millisecond jitter
+/// on fast commands doesn't count.
+const P50_REGRESSION_MIN_MS: u64 = 100;
+/// An error pattern is recurring once it shows up in this many recent runs.
+const RECURRING_RUNS: usize = 2;
+
+/// The per-run numbers trends are built from.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RunPoint {
+    pub run_id: String,
+    pub timestamp: String,
+    pub command: Vec<String>,
+    pub duration_ms: u64,
+    pub failed: bool,
+    pub bytes_read: u64,
+    pub bytes_written: u64,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    /// Error pattern categories diagnosed in the run.
+    pub patterns: Vec<String>,
+}
+
+impl RunPoint {
+    pub fn from_pack(summary: &PackSummary, output: &ExplainOutput) -> Self {
+        let mut patterns: Vec<String> = output
+            .error_patterns
+            .iter()
+            .map(|p| p.category.clone())
+            .collect();
+        patterns.sort();
+        patterns.dedup();
+        Self {
+            run_id: summary.run_id.clone(),
+            timestamp: summary.timestamp.clone(),
+            command: summary.command.clone(),
+            duration_ms: summary.duration_ms,
+            failed: output.failure.is_some(),
+            bytes_read: output.file_activity.total_bytes_read,
+            bytes_written: output.file_activity.total_bytes_written,
+            bytes_sent: output.net_activity.total_bytes_sent,
+            bytes_received: output.net_activity.total_bytes_received,
+            patterns,
+        }
+    }
+}
+
+/// Identifies runs of the same command line.
+pub fn command_hash(command: &[String]) -> String {
+    util::hash_bytes(command.join("\0").as_bytes())[..12].to_string()
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct DurationStats {
+    pub min_ms: u64,
+    pub p50_ms: u64,
+    pub p90_ms: u64,
+    pub max_ms: u64,
+}
+
+impl DurationStats {
+    fn compute(points: &[&RunPoint]) -> Self {
+        let mut durations: Vec<u64> = points.iter().map(|p| p.duration_ms).collect();
+        durations.sort_unstable();
+        let Some(&max_ms) = durations.last() else {
+            return Self::default();
+        };
+        Self {
+            min_ms: durations[0],
+            p50_ms: percentile(&durations, 50),
+            p90_ms: percentile(&durations, 90),
+            max_ms,
+        }
+    }
+}
+
+/// Nearest-rank percentile of sorted values.
+fn percentile(sorted: &[u64], pct: usize) -> u64 {
+    let idx = (sorted.len() * pct).div_ceil(100).saturating_sub(1);
+    sorted[idx]
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DayBucket {
+    /// `YYYY-MM-DD`, from the run timestamps.
+    pub day: String,
+    pub runs: usize,
+    pub failures: usize,
+    pub failure_rate: f64,
+    pub duration_p50_ms: u64,
+    pub bytes_read: u64,
+    pub bytes_written: u64,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Trend {
+    pub command_hash: String,
+    pub command: Vec<String>,
+    pub runs: usize,
+    pub failures: usize,
+    pub failure_rate: f64,
+    pub first_seen: String,
+    pub last_seen: String,
+    pub duration: DurationStats,
+    pub daily: Vec<DayBucket>,
+    /// Every run, oldest first.
+    pub points: Vec<RunPoint>,
+}
+
+/// Builds the trend for runs of one command; `None` for no runs.
+pub fn trend(mut points: Vec<RunPoint>) -> Option<Trend> {
+    points.sort_by(|a, b| a.timestamp.cmp(&b.timestamp));
+    let first = points.first()?;
+    let all: Vec<&RunPoint> = points.iter().collect();
+    let failures = points.iter().filter(|p| p.failed).count();
+
+    let mut daily: Vec<DayBucket> = Vec::new();
+    let mut start = 0;
+    while start < points.len() {
+        let day = day_of(&points[start].timestamp);
+        let end = start
+            + points[start..]
+                .iter()
+                .take_while(|p| day_of(&p.timestamp) == day)
+                .count();
+        let runs = &all[start..end];
+        let failures = runs.iter().filter(|p| p.failed).count();
+        let n = runs.len() as u64;
+        daily.push(DayBucket {
+            day: day.to_string(),
+            runs: runs.len(),
+            failures,
+            failure_rate: failures as f64 / runs.len() as f64,
+            duration_p50_ms: DurationStats::compute(runs).p50_ms,
+            bytes_read: runs.iter().map(|p| p.bytes_read).sum::<u64>() / n,
+            bytes_written: runs.iter().map(|p| p.bytes_written).sum::<u64>() / n,
+            bytes_sent: runs.iter().map(|p| p.bytes_sent).sum::<u64>() / n,
+            bytes_received: runs.iter().map(|p| p.bytes_received).sum::<u64>() / n,
+        });
+        start = end;
+    }
+
+    Some(Trend {
+        command_hash: command_hash(&first.command),
+        command: first.command.clone(),
+        runs: points.len(),
+        failures,
+        failure_rate: failures as f64 / points.len() as f64,
+        first_seen: first.timestamp.clone(),
+        last_seen: points[points.len() - 1].timestamp.clone(),
+        duration: DurationStats::compute(&all),
+        daily,
+        points,
+    })
+}
+
+fn day_of(timestamp: &str) -> &str {
+    timestamp.get(..10).unwrap_or(timestamp)
+}
+
+/// How the most recent runs of a command compare with the ones before.
+#[derive(Debug, Clone, Serialize)]
+pub struct Regression {
+    pub command_hash: String,
+    pub command: Vec<String>,
+    pub baseline_runs: usize,
+    pub recent_runs: usize,
+    pub baseline_p50_ms: u64,
+    pub recent_p50_ms: u64,
+    pub slower: bool,
+    pub baseline_failure_rate: f64,
+    pub recent_failure_rate: f64,
+    /// Error patterns in at least two recent runs and no baseline run.
+    pub new_patterns: Vec<String>,
+}
+
+impl Regression {
+    pub fn is_regression(&self) -> bool {
+        self.slower || !self.new_patterns.is_empty()
+    }
+}
+
+/// Compares the last `recent` runs (by default the newer half) with the
+/// runs before them; `None` when there aren't runs on both sides.
+pub fn compare(trend: &Trend, recent: Option<usize>) -> Option<Regression> {
+    let n = trend.points.len();
+    let recent = recent.unwrap_or(n / 2).min(n);
+    if recent == 0 || recent == n {
+        return None;
+    }
+    let (baseline, latest) = trend.points.split_at(n - recent);
+    let baseline: Vec<&RunPoint> = baseline.iter().collect();
+    let latest: Vec<&RunPoint> = latest.iter().collect();
+
+    let baseline_p50_ms = DurationStats::compute(&baseline).p50_ms;
+    let recent_p50_ms = DurationStats::compute(&latest).p50_ms;
+    let slower = recent_p50_ms as f64 > baseline_p50_ms as f64 * P50_REGRESSION_RATIO
+        && recent_p50_ms - baseline_p50_ms >= P50_REGRESSION_MIN_MS;
+
+    let mut new_patterns: Vec<String> = Vec::new();
+    for p in latest.iter().flat_map(|p| &p.patterns) {
+        if new_patterns.contains(p) || baseline.iter().any(|b| b.patterns.contains(p)) {
+            continue;
+        }
+        if latest.iter().filter(|r| r.patterns.contains(p)).count() >= RECURRING_RUNS {
+            new_patterns.push(p.clone());
+        }
+    }
+
+    let failure_rate =
+        |runs: &[&RunPoint]| runs.iter().filter(|p| p.failed).count() as f64 / runs.len() as f64;
+    Some(Regression {
+        command_hash: trend.command_hash.clone(),
+        command: trend.command.clone(),
+        baseline_runs: baseline.len(),
+        recent_runs: latest.len(),
+        baseline_p50_ms,
+        recent_p50_ms,
+        slower,
+        baseline_failure_rate: failure_rate(&baseline),
+        recent_failure_rate: failure_rate(&latest),
+        new_patterns,
+    })
+}
+
+/// Splits runs into one trend per command, most runs first.
+pub fn by_command(points: Vec<RunPoint>) -> Vec<Trend> {
+    let mut groups: Vec<(String, Vec<RunPoint>)> = Vec::new();
+    for p in points {
+        let hash = command_hash(&p.command);
+        match groups.iter_mut().find(|(h, _)| *h == hash) {
+            Some((_, runs)) => runs.push(p),
+            None => groups.push((hash, vec![p])),
+        }
+    }
+    let mut trends: Vec<Trend> = groups.into_iter().filter_map(|(_, p)| trend(p)).collect();
+    trends.sort_by(|a, b| b.runs.cmp(&a.runs).then(b.last_seen.cmp(&a.last_seen)));
+    trends
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(day: u32, duration_ms: u64, failed: bool, patterns: &[&str]) -> RunPoint {
+        RunPoint {
+            run_id: format!("run-{}", day),
+            timestamp: format!("2024-03-{:02}T12:00:00+00:00", day),
+            command: vec!["make".into(), "test".into()],
+            duration_ms,
+            failed,
+            patterns: patterns.iter().map(|p| p.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn trend_buckets_runs_by_day() {
+        let t = trend(vec![
+            run(2, 300, true, &[]),
+            run(1, 100, false, &[]),
+            run(1, 200, false, &[]),
+        ])
+        .unwrap();
+        assert_eq!(t.runs, 3);
+        assert_eq!(t.failures, 1);
+        assert_eq!(t.duration.p50_ms, 200);
+        assert_eq!(t.daily.len(), 2);
+        assert_eq!(t.daily[0].day, "2024-03-01");
+        assert_eq!(t.daily[0].runs, 2);
+        assert_eq!(t.daily[1].failure_rate, 1.0);
+        assert_eq!(t.points[0].run_id, "run-1");
+    }
+
+    #[test]
+    fn flags_slower_p50_and_new_recurring_patterns() {
+        let t = trend(vec![
+            run(1, 1000, false, &["network"]),
+            run(2, 1100, false, &[]),
+            run(3, 1050, false, &[]),
+            run(4, 1600, true, &["network", "timeout"]),
+            run(5, 1700, true, &["timeout", "oom"]),
+            run(6, 1500, false, &[]),
+        ])
+        .unwrap();
+        let r = compare(&t, None).unwrap();
+        assert_eq!((r.baseline_runs, r.recent_runs), (3, 3));
+        assert_eq!((r.baseline_p50_ms, r.recent_p50_ms), (1050, 1600));
+        assert!(r.slower);
+        assert_eq!(r.new_patterns, vec!["timeout".to_string()]);
+        assert!(r.is_regression());
+
+        assert!(compare(&t, Some(6)).is_none());
+        let jitter = trend(vec![run(1, 10, false, &[]), run(2, 30, false, &[])]).unwrap();
+        assert!(!compare(&jitter, None).unwrap().slower);
+    }
+}
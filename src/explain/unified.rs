@@ -0,0 +1,222 @@
+use std::collections::BTreeSet;
+use std::path::Path;
+
+use anyhow::Result;
+
+use crate::explain::analyzer;
+use crate::pack::reader::PackReader;
+use crate::trace::db::{Page, TraceDb};
+
+/// Unchanged lines shown around each change.
+pub const CONTEXT: usize = 3;
+/// Above this many cells the line diff isn't computed; the differing middle
+/// of the two sides is shown as removed and then added instead.
+const MAX_CELLS: usize = 4_000_000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Edit {
+    Same(usize, usize),
+    Removed(usize),
+    Added(usize),
+}
+
+/// `poe diff --format unified`: the candidate's stderr and the set of file
+/// paths it touched, against the baseline's, as unified diffs. Empty when
+/// neither differs.
+pub fn render(baseline_path: &Path, candidate_path: &Path) -> Result<String> {
+    let baseline = PackReader::open(baseline_path)?;
+    let candidate = PackReader::open(candidate_path)?;
+    let b_id = short_id(&baseline.summary().run_id);
+    let c_id = short_id(&candidate.summary().run_id);
+
+    let mut out = String::new();
+    let b_stderr = baseline.stdio_text("stderr").unwrap_or_default();
+    let c_stderr = candidate.stdio_text("stderr").unwrap_or_default();
+    out.push_str(&unified_diff(
+        &format!("{}/stderr", b_id),
+        &format!("{}/stderr", c_id),
+        &b_stderr.lines().collect::<Vec<_>>(),
+        &c_stderr.lines().collect::<Vec<_>>(),
+        CONTEXT,
+    ));
+    let b_paths = file_paths(baseline.db())?;
+    let c_paths = file_paths(candidate.db())?;
+    out.push_str(&unified_diff(
+        &format!("{}/files", b_id),
+        &format!("{}/files", c_id),
+        &b_paths.iter().map(String::as_str).collect::<Vec<_>>(),
+        &c_paths.iter().map(String::as_str).collect::<Vec<_>>(),
+        CONTEXT,
+    ));
+    Ok(out)
+}
+
+fn short_id(run_id: &str) -> &str {
+    &run_id[..run_id.len().min(8)]
+}
+
+/// Paths the run touched, sorted, leaving out the same noise (shared
+/// libraries, /proc, ...) as the sectioned diff.
+fn file_paths(db: &TraceDb) -> Result<BTreeSet<String>> {
+    let mut paths = BTreeSet::new();
+    db.each_file_event(Page::new(None, 0), |f| {
+        if let Some(path) = f.path {
+            if !path.is_empty() && !analyzer::is_noise_path_pub(Some(&path)) {
+                paths.insert(path);
+            }
+        }
+        Ok(())
+    })?;
+    Ok(paths)
+}
+
+/// A unified diff of two line lists with `context` lines around changes,
+/// in the layout of `diff -u`. Empty when the lists are equal.
+pub fn unified_diff(from: &str, to: &str, a: &[&str], b: &[&str], context: usize) -> String {
+    let edits = edits(a, b);
+    let changed: Vec<usize> = (0..edits.len())
+        .filter(|&i| !matches!(edits[i], Edit::Same(..)))
+        .collect();
+    if changed.is_empty() {
+        return String::new();
+    }
+
+    // Group changes whose context would overlap into one hunk.
+    let mut hunks: Vec<(usize, usize)> = Vec::new();
+    for &i in &changed {
+        let start = i.saturating_sub(context);
+        let end = (i + context + 1).min(edits.len());
+        match hunks.last_mut() {
+            Some(last) if start <= last.1 => last.1 = end,
+            _ => hunks.push((start, end)),
+        }
+    }
+
+    let mut out = format!("--- {}\n+++ {}\n", from, to);
+    for (start, end) in hunks {
+        let hunk = &edits[start..end];
+        // Lines of each side before the hunk.
+        let a_at = edits[..start]
+            .iter()
+            .filter(|e| !matches!(e, Edit::Added(_)))
+            .count();
+        let b_at = edits[..start]
+            .iter()
+            .filter(|e| !matches!(e, Edit::Removed(_)))
+            .count();
+        let a_len = hunk.iter().filter(|e| !matches!(e, Edit::Added(_))).count();
+        let b_len = hunk
+            .iter()
+            .filter(|e| !matches!(e, Edit::Removed(_)))
+            .count();
+        out.push_str(&format!(
+            "@@ -{} +{} @@\n",
+            range(a_at, a_len),
+            range(b_at, b_len)
+        ));
+        for edit in hunk {
+            match *edit {
+                Edit::Same(i, _) => out.push_str(&format!(" {}\n", a[i])),
+                Edit::Removed(i) => out.push_str(&format!("-{}\n", a[i])),
+                Edit::Added(j) => out.push_str(&format!("+{}\n", b[j])),
+            }
+        }
+    }
+    out
+}
+
+/// `start,len` with a 1-based start; an empty range names the line before
+/// it, as `diff -u` does.
+fn range(before: usize, len: usize) -> String {
+    match len {
+        0 => format!("{},0", before),
+        1 => format!("{}", before + 1),
+        _ => format!("{},{}", before + 1, len),
+    }
+}
+
+/// A shortest edit script from `a` to `b`, by longest common subsequence
+/// after trimming the common prefix and suffix.
+fn edits(a: &[&str], b: &[&str]) -> Vec<Edit> {
+    let prefix = a.iter().zip(b).take_while(|(x, y)| x == y).count();
+    let suffix = a[prefix..]
+        .iter()
+        .rev()
+        .zip(b[prefix..].iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count();
+    let (a_mid, b_mid) = (&a[prefix..a.len() - suffix], &b[prefix..b.len() - suffix]);
+    let (n, m) = (a_mid.len(), b_mid.len());
+
+    let mut edits: Vec<Edit> = (0..prefix).map(|i| Edit::Same(i, i)).collect();
+    if (n + 1) * (m + 1) > MAX_CELLS {
+        edits.extend((0..n).map(|i| Edit::Removed(prefix + i)));
+        edits.extend((0..m).map(|j| Edit::Added(prefix + j)));
+    } else {
+        // lcs[i][j]: common subsequence length of a_mid[i..] and b_mid[j..].
+        let width = m + 1;
+        let mut lcs = vec![0u32; (n + 1) * width];
+        for i in (0..n).rev() {
+            for j in (0..m).rev() {
+                lcs[i * width + j] = if a_mid[i] == b_mid[j] {
+                    lcs[(i + 1) * width + j + 1] + 1
+                } else {
+                    lcs[(i + 1) * width + j].max(lcs[i * width + j + 1])
+                };
+            }
+        }
+        let (mut i, mut j) = (0, 0);
+        while i < n || j < m {
+            if i < n && j < m && a_mid[i] == b_mid[j] {
+                edits.push(Edit::Same(prefix + i, prefix + j));
+                i += 1;
+                j += 1;
+            } else if j == m || (i < n && lcs[(i + 1) * width + j] >= lcs[i * width + j + 1]) {
+                edits.push(Edit::Removed(prefix + i));
+                i += 1;
+            } else {
+                edits.push(Edit::Added(prefix + j));
+                j += 1;
+            }
+        }
+    }
+    let (a_end, b_end) = (prefix + n, prefix + m);
+    edits.extend((0..suffix).map(|k| Edit::Same(a_end + k, b_end + k)));
+    edits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_hunks_with_context() {
+        let a: Vec<String> = (1..=12).map(|i| format!("line {}", i)).collect();
+        let mut b = a.clone();
+        b[1] = "line 2 changed".into();
+        b.remove(10);
+        b.push("line 13".into());
+        let a: Vec<&str> = a.iter().map(String::as_str).collect();
+        let b: Vec<&str> = b.iter().map(String::as_str).collect();
+
+        assert_eq!(
+            unified_diff("old", "new", &a, &b, 2),
+            "--- old\n+++ new\n\
+             @@ -1,4 +1,4 @@\n line 1\n-line 2\n+line 2 changed\n line 3\n line 4\n\
+             @@ -9,4 +9,4 @@\n line 9\n line 10\n-line 11\n line 12\n+line 13\n"
+        );
+        assert_eq!(unified_diff("old", "new", &a, &a, 3), "");
+    }
+
+    #[test]
+    fn empty_sides_name_the_line_before() {
+        assert_eq!(
+            unified_diff("a", "b", &[], &["new"], 3),
+            "--- a\n+++ b\n@@ -0,0 +1 @@\n+new\n"
+        );
+        assert_eq!(
+            unified_diff("a", "b", &["x", "gone"], &["x"], 0),
+            "--- a\n+++ b\n@@ -2 +1,0 @@\n-gone\n"
+        );
+    }
+}
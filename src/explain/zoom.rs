@@ -0,0 +1,285 @@
+use std::collections::HashMap;
+
+use anyhow::{bail, Context, Result};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::explain::analyzer::{self, TimelineEntry};
+use crate::trace::db::{TimeWindow, TraceDb};
+
+/// Entries explain shows after collapsing; a narrower window shows the
+/// rest.
+pub const MAX_ENTRIES: usize = 200;
+/// How many of a process's latest distinct operations an operation can be
+/// folded into, so a read/write loop collapses as well as a run of reads.
+const RECENT_OPS: usize = 4;
+
+/// The merged timeline of part of a run (`--timeline-window`) and/or one
+/// process (`--pid`), with runs of the same operation collapsed.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct TimelineZoom {
+    pub start_ms: Option<f64>,
+    pub end_ms: Option<f64>,
+    pub pid: Option<i32>,
+    /// Events and operations in the window, before collapsing.
+    pub total: usize,
+    pub entries: Vec<ZoomEntry>,
+    /// Entries past the limit.
+    pub omitted: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ZoomEntry {
+    pub ts_ms: f64,
+    /// Time of the last operation collapsed into the entry.
+    pub end_ms: f64,
+    pub proc_id: i32,
+    pub kind: String,
+    pub description: String,
+    pub count: usize,
+}
+
+/// An entry with what makes it the same operation as another: the op, its
+/// target and how it failed. Events have none and are never folded.
+struct Row {
+    entry: TimelineEntry,
+    key: Option<String>,
+    bytes: Option<i64>,
+}
+
+/// `1200..1450ms`, `1.2..1.45s`, `1200..` or `..1450`, in ms. A side
+/// without a unit takes the other side's, and ms when neither has one.
+pub fn parse_window(s: &str) -> Result<(Option<f64>, Option<f64>)> {
+    let (start, end) = s
+        .split_once("..")
+        .with_context(|| format!("invalid window '{}': expected e.g. 1200..1450ms", s))?;
+    let split = |side: &str| -> Result<Option<(f64, Option<f64>)>> {
+        let side = side.trim();
+        if side.is_empty() {
+            return Ok(None);
+        }
+        let digits = side.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+        let scale = match &side[digits.len()..] {
+            "" => None,
+            "us" => Some(0.001),
+            "ms" => Some(1.0),
+            "s" => Some(1000.0),
+            unit => bail!(
+                "invalid unit '{}' in window '{}' (use us, ms or s)",
+                unit,
+                s
+            ),
+        };
+        let value: f64 = digits
+            .parse()
+            .with_context(|| format!("invalid time '{}' in window '{}'", side, s))?;
+        Ok(Some((value, scale)))
+    };
+    let (start, end) = (split(start)?, split(end)?);
+    let unit = end
+        .and_then(|(_, scale)| scale)
+        .or(start.and_then(|(_, scale)| scale))
+        .unwrap_or(1.0);
+    let ms = |side: Option<(f64, Option<f64>)>| side.map(|(v, scale)| v * scale.unwrap_or(unit));
+    let (start, end) = (ms(start), ms(end));
+    if let (Some(a), Some(b)) = (start, end) {
+        if a > b {
+            bail!("window '{}' ends before it starts", s);
+        }
+    }
+    Ok((start, end))
+}
+
+pub fn build(
+    db: &TraceDb,
+    start_ms: Option<f64>,
+    end_ms: Option<f64>,
+    pid: Option<i32>,
+    limit: usize,
+) -> Result<TimelineZoom> {
+    let window = TimeWindow {
+        start_ns: start_ms.map(|ms| (ms * 1_000_000.0) as i64),
+        end_ns: end_ms.map(|ms| (ms * 1_000_000.0) as i64),
+        pid,
+    };
+
+    let mut rows: Vec<Row> = Vec::new();
+    for e in db.query_events_in(window)? {
+        if let Some(entry) = analyzer::event_entry(&e) {
+            rows.push(Row {
+                entry,
+                key: None,
+                bytes: None,
+            });
+        }
+    }
+    db.each_file_event_in(window, |f| {
+        if analyzer::is_noise_path_pub(f.path.as_deref()) {
+            return Ok(());
+        }
+        let target = match (&f.path, f.fd) {
+            (Some(path), _) if !path.is_empty() => path.clone(),
+            (_, Some(fd)) => format!("fd {}", fd),
+            _ => String::new(),
+        };
+        rows.push(Row {
+            key: Some(same_op(&f.op, &target, f.result)),
+            bytes: f.bytes,
+            entry: analyzer::file_entry(&f),
+        });
+        Ok(())
+    })?;
+    db.each_net_event_in(window, |n| {
+        let target = n
+            .dst
+            .clone()
+            .or(n.fd.map(|fd| format!("fd {}", fd)))
+            .unwrap_or_default();
+        rows.push(Row {
+            key: Some(same_op(&n.op, &target, n.result)),
+            bytes: n.bytes,
+            entry: analyzer::net_entry(&n),
+        });
+        Ok(())
+    })?;
+    rows.sort_by(|a, b| a.entry.ts_ms.total_cmp(&b.entry.ts_ms));
+
+    let total = rows.len();
+    let mut entries = collapse(rows);
+    let omitted = entries.len().saturating_sub(limit);
+    entries.truncate(limit);
+    Ok(TimelineZoom {
+        start_ms,
+        end_ms,
+        pid,
+        total,
+        entries,
+        omitted,
+    })
+}
+
+fn same_op(op: &str, target: &str, result: Option<i64>) -> String {
+    match result {
+        // EINPROGRESS is how a non-blocking connect starts, not a failure.
+        Some(r) if r < 0 && r != -libc::EINPROGRESS as i64 => {
+            format!("{} {} err={}", op, target, analyzer::errno_name(-r))
+        }
+        _ if target.is_empty() => op.to_string(),
+        _ => format!("{} {}", op, target),
+    }
+}
+
+/// Folds each operation into one of the process's latest entries when
+/// that is the same operation, however other processes interleave with it.
+/// An event ends the run.
+fn collapse(rows: Vec<Row>) -> Vec<ZoomEntry> {
+    let mut entries: Vec<ZoomEntry> = Vec::new();
+    let mut keys: Vec<Option<String>> = Vec::new();
+    let mut bytes: Vec<Option<i64>> = Vec::new();
+    let mut recent: HashMap<i32, Vec<usize>> = HashMap::new();
+    for row in rows {
+        let pid = row.entry.proc_id;
+        let latest = recent.entry(pid).or_default();
+        if row.key.is_some() {
+            if let Some(&i) = latest.iter().find(|&&i| keys[i] == row.key) {
+                entries[i].count += 1;
+                entries[i].end_ms = row.entry.ts_ms;
+                if let Some(b) = row.bytes.filter(|b| *b > 0) {
+                    bytes[i] = Some(bytes[i].unwrap_or(0) + b);
+                }
+                continue;
+            }
+        } else {
+            latest.clear();
+        }
+        if latest.len() == RECENT_OPS {
+            latest.remove(0);
+        }
+        latest.push(entries.len());
+        entries.push(ZoomEntry {
+            ts_ms: row.entry.ts_ms,
+            end_ms: row.entry.ts_ms,
+            proc_id: pid,
+            kind: row.entry.kind,
+            description: row.entry.description,
+            count: 1,
+        });
+        keys.push(row.key);
+        bytes.push(row.bytes.filter(|b| *b > 0));
+    }
+    for ((entry, key), bytes) in entries.iter_mut().zip(keys).zip(bytes) {
+        if entry.count > 1 {
+            let bytes = bytes.map(|b| format!(" ({} bytes)", b)).unwrap_or_default();
+            entry.description = format!(
+                "{} x{}{} over {:.1}ms",
+                key.unwrap_or_default(),
+                entry.count,
+                bytes,
+                entry.end_ms - entry.ts_ms
+            );
+        }
+    }
+    entries
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_windows() {
+        assert_eq!(
+            parse_window("1200..1450ms").unwrap(),
+            (Some(1200.0), Some(1450.0))
+        );
+        assert_eq!(
+            parse_window("1.2..1.5s").unwrap(),
+            (Some(1200.0), Some(1500.0))
+        );
+        assert_eq!(
+            parse_window("500ms..2s").unwrap(),
+            (Some(500.0), Some(2000.0))
+        );
+        assert_eq!(parse_window("..30").unwrap(), (None, Some(30.0)));
+        assert!(parse_window("1450..1200").is_err());
+        assert!(parse_window("1200ms").is_err());
+        assert!(parse_window("1..2h").is_err());
+    }
+
+    #[test]
+    fn collapses_repeated_ops_per_process() {
+        let row = |ts: f64, pid: i32, op: &str, bytes: Option<i64>| Row {
+            entry: TimelineEntry {
+                ts_ms: ts,
+                proc_id: pid,
+                kind: if op.is_empty() { "event" } else { "file" }.into(),
+                description: format!("{} (single)", op),
+            },
+            key: (!op.is_empty()).then(|| op.to_string()),
+            bytes,
+        };
+        let entries = collapse(vec![
+            row(1.0, 7, "read /data", Some(4096)),
+            row(1.5, 8, "write /log", Some(10)),
+            row(2.0, 7, "write /out", Some(100)),
+            row(3.0, 7, "read /data", Some(4096)),
+            row(4.0, 7, "write /out", Some(100)),
+            row(5.0, 7, "", None),
+            row(6.0, 7, "read /data", Some(0)),
+        ]);
+        let lines: Vec<(i32, &str)> = entries
+            .iter()
+            .map(|e| (e.proc_id, e.description.as_str()))
+            .collect();
+        assert_eq!(
+            lines,
+            [
+                (7, "read /data x2 (8192 bytes) over 2.0ms"),
+                (8, "write /log (single)"),
+                (7, "write /out x2 (200 bytes) over 2.0ms"),
+                (7, " (single)"),
+                (7, "read /data (single)"),
+            ]
+        );
+    }
+}
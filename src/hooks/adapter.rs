@@ -1,27 +1,132 @@
 use std::collections::HashMap;
 use std::os::unix::io::RawFd;
+use std::path::{Path, PathBuf};
 use std::sync::mpsc;
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 
-use crate::events::types::TraceEvent;
+use crate::events::types::{CaptureMode, TraceEvent};
+use crate::explain::patterns::{self, PatternMatcher};
 
-pub trait LanguageAdapter: Send {
+/// What an adapter gets to change before the child is spawned.
+pub struct LoadContext<'a> {
+    pub argv: &'a [String],
+    pub mode: CaptureMode,
+    pub env: &'a mut HashMap<String, String>,
+    /// Fds the child must inherit (e.g. a hook's write end).
+    pub clear_cloexec_fds: &'a mut Vec<RawFd>,
+}
+
+pub struct StartContext {
+    pub event_tx: mpsc::Sender<TraceEvent>,
+    pub root_pid: i32,
+    /// Monotonic time event timestamps are relative to.
+    pub base_ts: u64,
+}
+
+/// A language or runtime integration. Adapters are registered with an
+/// [`AdapterManager`]; the ones whose `detect` accepts the command take part
+/// in the run, and every registered adapter can contribute explain patterns.
+pub trait Adapter: Send {
     fn name(&self) -> &str;
 
-    fn on_load(
-        &mut self,
-        env: &mut HashMap<String, String>,
-        clear_cloexec_fds: &mut Vec<RawFd>,
-    ) -> Result<()>;
+    fn detect(&self, argv: &[String]) -> bool;
+
+    fn on_load(&mut self, ctx: &mut LoadContext) -> Result<()>;
+
+    fn on_start(&mut self, _ctx: &StartContext) -> Result<()> {
+        Ok(())
+    }
+
+    /// Called from the event writer for every captured event.
+    fn on_event(&mut self, _event: &TraceEvent) {}
+
+    fn on_exit(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn contribute_patterns(&self) -> Vec<Box<dyn PatternMatcher>> {
+        Vec::new()
+    }
+}
+
+/// Per-adapter sections from `adapters.toml`, keyed by adapter name.
+#[derive(Debug, Default, Clone)]
+pub struct AdapterConfig {
+    sections: toml::Table,
+}
+
+impl AdapterConfig {
+    pub fn parse(content: &str) -> Result<Self> {
+        let sections: toml::Table = toml::from_str(content).context("invalid adapter config")?;
+        for (name, value) in &sections {
+            if !value.is_table() {
+                anyhow::bail!("adapter config: `{}` must be a section", name);
+            }
+        }
+        Ok(Self { sections })
+    }
+
+    /// `./.poe/adapters.toml` over `~/.config/poe/adapters.toml`, merged per
+    /// section. Broken files are reported and skipped.
+    pub fn load_default() -> Self {
+        let mut config = Self::default();
+        for path in default_config_paths().iter().rev() {
+            match std::fs::read_to_string(path)
+                .with_context(|| format!("failed to read {}", path.display()))
+                .and_then(|c| Self::parse(&c))
+            {
+                Ok(file) => config.merge(file),
+                Err(e) => log::warn!("skipping adapter config {}: {:#}", path.display(), e),
+            }
+        }
+        config
+    }
+
+    fn merge(&mut self, other: Self) {
+        for (name, value) in other.sections {
+            let Some(incoming) = value.as_table() else {
+                continue;
+            };
+            match self.sections.get_mut(&name).and_then(|v| v.as_table_mut()) {
+                Some(existing) => existing.extend(incoming.clone()),
+                None => {
+                    self.sections.insert(name, value);
+                }
+            }
+        }
+    }
+
+    pub fn section(&self, name: &str) -> Option<&toml::Table> {
+        self.sections.get(name).and_then(|v| v.as_table())
+    }
 
-    fn on_start(&mut self, event_tx: mpsc::Sender<TraceEvent>, root_pid: i32) -> Result<()>;
+    pub fn enabled(&self, name: &str) -> bool {
+        self.section(name)
+            .and_then(|s| s.get("enabled"))
+            .and_then(|v| v.as_bool())
+            .unwrap_or(true)
+    }
+}
 
-    fn on_exit(&mut self) -> Result<()>;
+fn default_config_paths() -> Vec<PathBuf> {
+    let mut paths = Vec::new();
+    let local = PathBuf::from(".poe/adapters.toml");
+    if local.is_file() {
+        paths.push(local);
+    }
+    if let Ok(home) = std::env::var("HOME") {
+        let user = Path::new(&home).join(".config/poe/adapters.toml");
+        if user.is_file() {
+            paths.push(user);
+        }
+    }
+    paths
 }
 
 pub struct AdapterManager {
-    adapters: Vec<Box<dyn LanguageAdapter>>,
+    adapters: Vec<Box<dyn Adapter>>,
+    active: Vec<usize>,
 }
 
 impl Default for AdapterManager {
@@ -34,86 +139,183 @@ impl AdapterManager {
     pub fn new() -> Self {
         Self {
             adapters: Vec::new(),
+            active: Vec::new(),
         }
     }
 
-    pub fn detect_and_register(&mut self, argv: &[String]) {
-        if super::python::is_python_command(argv) {
-            if let Ok(adapter) = PythonAdapter::new() {
-                self.adapters.push(Box::new(adapter));
+    /// The built-in adapters plus one [`CommandAdapter`] per config section
+    /// that lists `commands`, honouring `enabled = false`.
+    pub fn from_config(config: &AdapterConfig) -> Self {
+        let mut manager = Self::new();
+        if config.enabled("python") {
+            let section = config.section("python").cloned().unwrap_or_default();
+            manager.register(Box::new(PythonAdapter::new(section)));
+        }
+        for (name, _) in config.sections.iter() {
+            if name == "python" || !config.enabled(name) {
+                continue;
+            }
+            let section = config.section(name).cloned().unwrap_or_default();
+            match CommandAdapter::from_section(name, &section) {
+                Ok(Some(adapter)) => manager.register(Box::new(adapter)),
+                Ok(None) => {}
+                Err(e) => log::warn!("skipping adapter {}: {:#}", name, e),
             }
         }
+        manager
+    }
+
+    pub fn load_default() -> Self {
+        Self::from_config(&AdapterConfig::load_default())
     }
 
-    pub fn on_load(
-        &mut self,
-        env: &mut HashMap<String, String>,
-        clear_cloexec_fds: &mut Vec<RawFd>,
-    ) -> Result<()> {
-        for adapter in &mut self.adapters {
-            adapter.on_load(env, clear_cloexec_fds)?;
+    pub fn register(&mut self, adapter: Box<dyn Adapter>) {
+        self.adapters.push(adapter);
+    }
+
+    /// Selects the adapters that apply to `argv`; only those receive the
+    /// lifecycle calls below.
+    pub fn activate(&mut self, argv: &[String]) {
+        self.active = (0..self.adapters.len())
+            .filter(|&i| self.adapters[i].detect(argv))
+            .collect();
+    }
+
+    fn active_mut(&mut self) -> impl Iterator<Item = &mut Box<dyn Adapter>> {
+        self.adapters
+            .iter_mut()
+            .enumerate()
+            .filter(|(i, _)| self.active.contains(i))
+            .map(|(_, a)| a)
+    }
+
+    pub fn on_load(&mut self, ctx: &mut LoadContext) -> Result<()> {
+        for adapter in self.active_mut() {
+            adapter.on_load(ctx)?;
         }
         Ok(())
     }
 
-    pub fn on_start(&mut self, event_tx: mpsc::Sender<TraceEvent>, root_pid: i32) -> Result<()> {
-        for adapter in &mut self.adapters {
-            adapter.on_start(event_tx.clone(), root_pid)?;
+    pub fn on_start(&mut self, ctx: &StartContext) -> Result<()> {
+        for adapter in self.active_mut() {
+            adapter.on_start(ctx)?;
         }
         Ok(())
     }
 
+    pub fn on_event(&mut self, event: &TraceEvent) {
+        for adapter in self.active_mut() {
+            adapter.on_event(event);
+        }
+    }
+
     pub fn on_exit(&mut self) -> Result<()> {
-        for adapter in &mut self.adapters {
+        for adapter in self.active_mut() {
             adapter.on_exit()?;
         }
         Ok(())
     }
 
+    /// Patterns from every registered adapter, active or not: explain runs
+    /// long after capture and cannot tell which adapters took part.
+    pub fn patterns(&self) -> Vec<Box<dyn PatternMatcher>> {
+        self.adapters
+            .iter()
+            .flat_map(|a| a.contribute_patterns())
+            .collect()
+    }
+
     pub fn adapter_names(&self) -> Vec<&str> {
-        self.adapters.iter().map(|a| a.name()).collect()
+        self.active
+            .iter()
+            .map(|&i| self.adapters[i].name())
+            .collect()
     }
 
     pub fn has_adapters(&self) -> bool {
-        !self.adapters.is_empty()
+        !self.active.is_empty()
     }
 }
 
 struct PythonAdapter {
+    config: toml::Table,
     hook: Option<super::python::PythonHookSetup>,
     reader: Option<super::python::PythonHookReader>,
 }
 
 impl PythonAdapter {
-    fn new() -> Result<Self> {
-        Ok(Self {
+    fn new(config: toml::Table) -> Self {
+        Self {
+            config,
             hook: None,
             reader: None,
-        })
+        }
     }
 }
 
-impl LanguageAdapter for PythonAdapter {
+impl Adapter for PythonAdapter {
     fn name(&self) -> &str {
         "python"
     }
 
-    fn on_load(
-        &mut self,
-        env: &mut HashMap<String, String>,
-        clear_cloexec_fds: &mut Vec<RawFd>,
-    ) -> Result<()> {
+    fn detect(&self, argv: &[String]) -> bool {
+        super::python::is_python_command(argv)
+    }
+
+    fn on_load(&mut self, ctx: &mut LoadContext) -> Result<()> {
+        let settings = super::python::hook_settings(ctx.mode, &self.config)?;
         let run_id = uuid::Uuid::new_v4().to_string();
         let hook = super::python::PythonHookSetup::prepare(&run_id)?;
-        hook.apply_env(env);
-        clear_cloexec_fds.push(hook.write_fd());
+        hook.apply_env(ctx.env, &settings);
+        ctx.clear_cloexec_fds.push(hook.write_fd());
         self.hook = Some(hook);
         Ok(())
     }
 
-    fn on_start(&mut self, event_tx: mpsc::Sender<TraceEvent>, root_pid: i32) -> Result<()> {
+    fn on_start(&mut self, ctx: &StartContext) -> Result<()> {
         if let Some(hook) = self.hook.take() {
-            self.reader = Some(hook.start_reader(event_tx, root_pid));
+            self.reader = Some(hook.start_reader(ctx.event_tx.clone(), ctx.root_pid));
+        }
+        Ok(())
+    }
+
+    fn on_exit(&mut self) -> Result<()> {
+        if let Some(reader) = self.reader.take() {
+            reader.finish();
+        }
+        Ok(())
+    }
+}
+
+/// Runs bash scripts with xtrace routed to a dedicated fd and records each
+/// traced command as a `shell_step` event. Registered by `run --trace-shell`.
+#[derive(Default)]
+pub struct ShellAdapter {
+    setup: Option<super::shell::ShellTraceSetup>,
+    reader: Option<super::shell::ShellTraceReader>,
+}
+
+impl Adapter for ShellAdapter {
+    fn name(&self) -> &str {
+        "bash"
+    }
+
+    fn detect(&self, argv: &[String]) -> bool {
+        super::shell::is_bash_command(argv)
+    }
+
+    fn on_load(&mut self, ctx: &mut LoadContext) -> Result<()> {
+        let run_id = uuid::Uuid::new_v4().to_string();
+        let setup = super::shell::ShellTraceSetup::prepare(&run_id)?;
+        setup.apply_env(ctx.env);
+        ctx.clear_cloexec_fds.push(setup.write_fd());
+        self.setup = Some(setup);
+        Ok(())
+    }
+
+    fn on_start(&mut self, ctx: &StartContext) -> Result<()> {
+        if let Some(setup) = self.setup.take() {
+            self.reader = Some(setup.start_reader(ctx.event_tx.clone(), ctx.base_ts));
         }
         Ok(())
     }
@@ -125,3 +327,172 @@ impl LanguageAdapter for PythonAdapter {
         Ok(())
     }
 }
+
+/// A config-only adapter: matches commands by name, sets environment
+/// variables (typically to preload a runtime's own hook) and contributes
+/// the rules from a pattern file.
+///
+/// ```toml
+/// [ruby]
+/// commands = ["ruby", "bundle", "rake"]
+/// env = { RUBYOPT = "-r/opt/poe/ruby_hook.rb" }
+/// patterns = "/opt/poe/ruby-patterns.toml"
+/// ```
+pub struct CommandAdapter {
+    name: String,
+    commands: Vec<String>,
+    env: Vec<(String, String)>,
+    patterns: Option<PathBuf>,
+}
+
+impl CommandAdapter {
+    /// None for sections without `commands`.
+    pub fn from_section(name: &str, section: &toml::Table) -> Result<Option<Self>> {
+        let Some(commands) = section.get("commands") else {
+            return Ok(None);
+        };
+        let commands = commands
+            .as_array()
+            .map(|a| {
+                a.iter()
+                    .filter_map(|v| v.as_str().map(String::from))
+                    .collect::<Vec<_>>()
+            })
+            .filter(|c| !c.is_empty())
+            .context("`commands` must be a non-empty list of strings")?;
+
+        let mut env = Vec::new();
+        if let Some(table) = section.get("env") {
+            let table = table.as_table().context("`env` must be a table")?;
+            for (key, value) in table {
+                let value = value
+                    .as_str()
+                    .with_context(|| format!("env `{}` must be a string", key))?;
+                env.push((key.clone(), value.to_string()));
+            }
+        }
+
+        let patterns = match section.get("patterns") {
+            Some(v) => Some(PathBuf::from(
+                v.as_str().context("`patterns` must be a path")?,
+            )),
+            None => None,
+        };
+
+        Ok(Some(Self {
+            name: name.to_string(),
+            commands,
+            env,
+            patterns,
+        }))
+    }
+}
+
+impl Adapter for CommandAdapter {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn detect(&self, argv: &[String]) -> bool {
+        let Some(cmd) = argv.first().and_then(|a| Path::new(a).file_name()) else {
+            return false;
+        };
+        let cmd = cmd.to_string_lossy();
+        self.commands.iter().any(|c| *c == cmd)
+    }
+
+    fn on_load(&mut self, ctx: &mut LoadContext) -> Result<()> {
+        for (key, value) in &self.env {
+            ctx.env.insert(key.clone(), value.clone());
+        }
+        Ok(())
+    }
+
+    fn contribute_patterns(&self) -> Vec<Box<dyn PatternMatcher>> {
+        let Some(path) = &self.patterns else {
+            return Vec::new();
+        };
+        match std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))
+            .and_then(|c| patterns::parse_rules(&c))
+        {
+            Ok(rules) => rules
+                .into_iter()
+                .map(|r| Box::new(r) as Box<dyn PatternMatcher>)
+                .collect(),
+            Err(e) => {
+                log::warn!(
+                    "adapter {}: skipping patterns {}: {:#}",
+                    self.name,
+                    path.display(),
+                    e
+                );
+                Vec::new()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"
+[python]
+max_depth = 8
+trace_calls = false
+
+[ruby]
+commands = ["ruby", "bundle"]
+env = { RUBYOPT = "-rpoe_hook" }
+
+[php]
+enabled = false
+commands = ["php"]
+"#;
+
+    #[test]
+    fn config_sections_build_adapters() {
+        let config = AdapterConfig::parse(CONFIG).unwrap();
+        let mut manager = AdapterManager::from_config(&config);
+        assert_eq!(manager.adapters.len(), 2);
+
+        manager.activate(&["/usr/bin/ruby".into(), "app.rb".into()]);
+        assert_eq!(manager.adapter_names(), vec!["ruby"]);
+
+        let mut env = HashMap::new();
+        let mut fds = Vec::new();
+        manager
+            .on_load(&mut LoadContext {
+                argv: &[],
+                mode: CaptureMode::Lite,
+                env: &mut env,
+                clear_cloexec_fds: &mut fds,
+            })
+            .unwrap();
+        assert_eq!(env.get("RUBYOPT").map(String::as_str), Some("-rpoe_hook"));
+
+        manager.activate(&["php".into()]);
+        assert!(!manager.has_adapters());
+    }
+
+    #[test]
+    fn python_section_overrides_hook_settings() {
+        let config = AdapterConfig::parse(CONFIG).unwrap();
+        let section = config.section("python").unwrap();
+        let settings = super::super::python::hook_settings(CaptureMode::Full, section).unwrap();
+        let get = |k: &str| {
+            settings
+                .iter()
+                .find(|(n, _)| n == k)
+                .map(|(_, v)| v.as_str())
+        };
+        assert_eq!(get("_POE_MAX_DEPTH"), Some("8"));
+        assert_eq!(get("_POE_TRACE_CALLS"), Some("0"));
+        assert_eq!(get("_POE_LOCALS"), Some("all"));
+
+        let mut bad = section.clone();
+        bad.insert("colour".into(), toml::Value::Boolean(true));
+        assert!(super::super::python::hook_settings(CaptureMode::Lite, &bad).is_err());
+    }
+}
@@ -1,3 +1,4 @@
 pub mod adapter;
 pub mod python;
 pub mod rust;
+pub mod shell;
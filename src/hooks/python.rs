@@ -30,6 +30,73 @@ pub fn is_python_command(argv: &[String]) -> bool {
         || cmd.starts_with("python2.")
 }
 
+/// How much the hook serializes of frame locals. Lite keeps handled
+/// exceptions cheap and only captures locals for the unhandled one; full
+/// captures them at every raise, rate-limited so exception-heavy code is
+/// not dominated by repr().
+fn locals_limits(mode: CaptureMode) -> [(&'static str, &'static str); 5] {
+    match mode {
+        CaptureMode::Lite => [
+            ("_POE_LOCALS", "unhandled"),
+            ("_POE_LOCALS_PER_SEC", "0"),
+            ("_POE_LOCALS_MAX_BYTES", "4096"),
+            ("_POE_VAR_MAX_LEN", "256"),
+            ("_POE_VAR_MAX_ITEMS", "16"),
+        ],
+        CaptureMode::Full => [
+            ("_POE_LOCALS", "all"),
+            ("_POE_LOCALS_PER_SEC", "100"),
+            ("_POE_LOCALS_MAX_BYTES", "16384"),
+            ("_POE_VAR_MAX_LEN", "1024"),
+            ("_POE_VAR_MAX_ITEMS", "32"),
+        ],
+    }
+}
+
+/// `[python]` keys in adapters.toml and the hook variables they set.
+const CONFIG_KEYS: [(&str, &str); 7] = [
+    ("trace_calls", "_POE_TRACE_CALLS"),
+    ("max_depth", "_POE_MAX_DEPTH"),
+    ("locals", "_POE_LOCALS"),
+    ("locals_per_sec", "_POE_LOCALS_PER_SEC"),
+    ("locals_max_bytes", "_POE_LOCALS_MAX_BYTES"),
+    ("var_max_len", "_POE_VAR_MAX_LEN"),
+    ("var_max_items", "_POE_VAR_MAX_ITEMS"),
+];
+
+/// Hook variables for a run: the capture mode's defaults overlaid with the
+/// `[python]` config section.
+pub fn hook_settings(mode: CaptureMode, config: &toml::Table) -> Result<Vec<(String, String)>> {
+    let mut settings: Vec<(String, String)> = vec![("_POE_TRACE_CALLS".into(), "1".into())];
+    settings.extend(
+        locals_limits(mode)
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string())),
+    );
+
+    for (key, value) in config {
+        if key == "enabled" {
+            continue;
+        }
+        let Some((_, var)) = CONFIG_KEYS.iter().find(|(k, _)| k == key) else {
+            anyhow::bail!("unknown python adapter setting `{}`", key);
+        };
+        let value = match value {
+            toml::Value::Boolean(b) => if *b { "1" } else { "0" }.to_string(),
+            toml::Value::Integer(i) => i.to_string(),
+            toml::Value::String(s) => s.clone(),
+            other => anyhow::bail!(
+                "python adapter setting `{}`: unsupported value {}",
+                key,
+                other
+            ),
+        };
+        settings.retain(|(k, _)| k != var);
+        settings.push((var.to_string(), value));
+    }
+    Ok(settings)
+}
+
 pub struct PythonHookSetup {
     hook_dir: PathBuf,
     read_fd: RawFd,
@@ -64,7 +131,7 @@ impl PythonHookSetup {
         self.write_fd
     }
 
-    pub fn apply_env(&self, env: &mut HashMap<String, String>) {
+    pub fn apply_env(&self, env: &mut HashMap<String, String>, settings: &[(String, String)]) {
         let hook_dir_str = self.hook_dir.to_string_lossy().into_owned();
 
         let existing = env.get("PYTHONPATH").cloned().unwrap_or_default();
@@ -78,7 +145,14 @@ impl PythonHookSetup {
         }
 
         env.insert("_POE_HOOK_FD".into(), self.write_fd.to_string());
-        env.insert("_POE_TRACE_CALLS".into(), "1".into());
+
+        // Values already in poe's own environment win, so the caps can be
+        // tuned per run.
+        for (key, value) in settings {
+            if std::env::var_os(key).is_none() {
+                env.insert(key.clone(), value.clone());
+            }
+        }
     }
 
     pub fn clear_cloexec_on_write_fd(&self) {
@@ -209,7 +283,7 @@ fn convert_python_event(event: PythonEvent, root_pid: i32) -> TraceEvent {
     match event {
         PythonEvent::Call {
             ts,
-            tid: _,
+            tid,
             func,
             file,
             line,
@@ -222,13 +296,14 @@ fn convert_python_event(event: PythonEvent, root_pid: i32) -> TraceEvent {
                 "func": func,
                 "file": file,
                 "line": line,
+                "tid": tid,
                 "depth": depth,
             })
             .to_string(),
         }),
         PythonEvent::Return {
             ts,
-            tid: _,
+            tid,
             func,
             file,
             line,
@@ -242,6 +317,7 @@ fn convert_python_event(event: PythonEvent, root_pid: i32) -> TraceEvent {
                 "func": func,
                 "file": file,
                 "line": line,
+                "tid": tid,
                 "depth": depth,
                 "retval": retval,
             })
@@ -249,7 +325,7 @@ fn convert_python_event(event: PythonEvent, root_pid: i32) -> TraceEvent {
         }),
         PythonEvent::Exception {
             ts,
-            tid: _,
+            tid,
             func,
             file,
             line,
@@ -264,6 +340,7 @@ fn convert_python_event(event: PythonEvent, root_pid: i32) -> TraceEvent {
                 "func": func,
                 "file": file,
                 "line": line,
+                "tid": tid,
                 "exc_type": exc_type,
                 "exc_msg": exc_msg,
                 "locals": locals,
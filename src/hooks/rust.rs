@@ -1,8 +1,10 @@
 use std::collections::HashMap;
 
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 use crate::explain::analyzer::ErrorPattern;
+use crate::explain::codes::PatternCode;
 
 pub fn is_rust_command(argv: &[String]) -> bool {
     if argv.is_empty() {
@@ -60,7 +62,7 @@ pub fn apply_rust_env(env: &mut HashMap<String, String>) {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct RustPanicInfo {
     pub message: String,
     pub location: Option<PanicLocation>,
@@ -68,14 +70,14 @@ pub struct RustPanicInfo {
     pub backtrace: Vec<RustBacktraceFrame>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct PanicLocation {
     pub file: String,
     pub line: u32,
     pub column: Option<u32>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct RustBacktraceFrame {
     pub index: u32,
     pub symbol: Option<String>,
@@ -321,6 +323,7 @@ pub fn detect_rust_patterns(stderr: &str) -> Vec<ErrorPattern> {
         }
 
         patterns.push(ErrorPattern {
+            code: Some(PatternCode::RustPanic),
             category: "rust_panic".into(),
             severity: "critical".into(),
             description: format!(
@@ -330,11 +333,13 @@ pub fn detect_rust_patterns(stderr: &str) -> Vec<ErrorPattern> {
             ),
             count: 1,
             examples,
+            ..Default::default()
         });
     }
 
     if stderr.contains("memory allocation of") && stderr.contains("failed") {
         patterns.push(ErrorPattern {
+            code: Some(PatternCode::RustOom),
             category: "rust_oom".into(),
             severity: "critical".into(),
             description: "Rust memory allocation failure".into(),
@@ -344,16 +349,19 @@ pub fn detect_rust_patterns(stderr: &str) -> Vec<ErrorPattern> {
                 .find(|l| l.contains("memory allocation"))
                 .unwrap_or("allocation failed")
                 .to_string()],
+            ..Default::default()
         });
     }
 
     if stderr.contains("stack overflow") {
         patterns.push(ErrorPattern {
+            code: Some(PatternCode::RustStackOverflow),
             category: "rust_stack_overflow".into(),
             severity: "critical".into(),
             description: "Rust stack overflow detected".into(),
             count: 1,
             examples: vec!["thread caused a stack overflow".into()],
+            ..Default::default()
         });
     }
 
@@ -0,0 +1,287 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::{BufRead, BufReader, Read};
+use std::os::unix::io::{FromRawFd, RawFd};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread;
+
+use anyhow::Result;
+
+use crate::events::types::*;
+use crate::util;
+
+/// Fields in PS4 are separated by this so commands containing tabs or
+/// spaces still split cleanly.
+const SEP: char = '\x1f';
+
+/// Whether `argv` runs bash: `bash ...` directly or a script whose shebang
+/// names bash. `sh` is not included: bash in POSIX mode ignores BASH_ENV.
+pub fn is_bash_command(argv: &[String]) -> bool {
+    let Some(first) = argv.first() else {
+        return false;
+    };
+    let cmd = Path::new(first)
+        .file_name()
+        .map(|f| f.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    if cmd == "bash" {
+        return true;
+    }
+    if cmd == "env" {
+        return is_bash_command(&argv[1..]);
+    }
+
+    let path = if first.contains('/') {
+        PathBuf::from(first)
+    } else {
+        match which(first) {
+            Some(p) => p,
+            None => return false,
+        }
+    };
+    shebang_is_bash(&path)
+}
+
+fn which(cmd: &str) -> Option<PathBuf> {
+    std::env::var("PATH")
+        .ok()?
+        .split(':')
+        .map(|dir| Path::new(dir).join(cmd))
+        .find(|p| p.is_file())
+}
+
+fn shebang_is_bash(path: &Path) -> bool {
+    let Ok(file) = fs::File::open(path) else {
+        return false;
+    };
+    // Binaries have no newline to stop at; only look at the first bytes.
+    let mut head = Vec::new();
+    if file.take(256).read_to_end(&mut head).is_err() {
+        return false;
+    }
+    let head = String::from_utf8_lossy(&head);
+    let Some(interp) = head.lines().next().and_then(|l| l.strip_prefix("#!")) else {
+        return false;
+    };
+    let mut parts = interp.split_whitespace();
+    let Some(prog) = parts.next() else {
+        return false;
+    };
+    let name = Path::new(prog)
+        .file_name()
+        .map(|f| f.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    name == "bash" || (name == "env" && parts.next() == Some("bash"))
+}
+
+pub struct ShellTraceSetup {
+    dir: PathBuf,
+    read_fd: RawFd,
+    write_fd: RawFd,
+}
+
+impl ShellTraceSetup {
+    pub fn prepare(run_id: &str) -> Result<Self> {
+        let dir = std::env::temp_dir().join(format!("poe-shtrace-{}", &run_id[..8]));
+        fs::create_dir_all(&dir)?;
+
+        let mut fds = [0i32; 2];
+        let ret = unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) };
+        if ret != 0 {
+            anyhow::bail!(
+                "pipe2 for shell trace fd failed: {}",
+                std::io::Error::last_os_error()
+            );
+        }
+
+        let rc = format!(
+            "if [ -n \"${{_POE_USER_BASH_ENV-}}\" ]; then . \"$_POE_USER_BASH_ENV\"; fi\n\
+             PS4=$'+{sep}poe{sep}${{BASHPID}}{sep}${{EPOCHREALTIME-}}{sep}${{BASH_SOURCE[0]-}}{sep}${{LINENO}}{sep}${{FUNCNAME[0]-}}{sep}'\n\
+             BASH_XTRACEFD={fd}\n\
+             set -x\n",
+            sep = "\\x1f",
+            fd = fds[1],
+        );
+        fs::write(dir.join("bash_env"), rc)?;
+
+        Ok(Self {
+            dir,
+            read_fd: fds[0],
+            write_fd: fds[1],
+        })
+    }
+
+    pub fn write_fd(&self) -> RawFd {
+        self.write_fd
+    }
+
+    pub fn apply_env(&self, env: &mut HashMap<String, String>) {
+        // A BASH_ENV the user already relies on is sourced before tracing is
+        // switched on.
+        if let Ok(existing) = std::env::var("BASH_ENV") {
+            env.insert("_POE_USER_BASH_ENV".into(), existing);
+        }
+        env.insert(
+            "BASH_ENV".into(),
+            self.dir.join("bash_env").to_string_lossy().into_owned(),
+        );
+    }
+
+    pub fn start_reader(
+        self,
+        event_tx: mpsc::Sender<TraceEvent>,
+        base_ts: u64,
+    ) -> ShellTraceReader {
+        nix::unistd::close(self.write_fd).ok();
+
+        let read_fd = self.read_fd;
+        let dir = self.dir.clone();
+        // EPOCHREALTIME is wall clock; events are monotonic since base_ts.
+        let wall_offset = util::wall_timestamp_ns() as i128 - util::timestamp_ns() as i128;
+
+        let handle = thread::Builder::new()
+            .name("poe-shell-trace".into())
+            .spawn(move || {
+                let file = unsafe { std::fs::File::from_raw_fd(read_fd) };
+                let reader = BufReader::new(file);
+                let mut pending: Option<(u64, ShellStep)> = None;
+
+                let flush = |pending: &mut Option<(u64, ShellStep)>| {
+                    if let Some((arrival, step)) = pending.take() {
+                        let ts = step
+                            .epoch_ns
+                            .map(|wall| (wall as i128 - wall_offset).max(0) as u64)
+                            .unwrap_or(arrival)
+                            .saturating_sub(base_ts);
+                        let _ = event_tx.send(TraceEvent::Generic(step.into_event(ts)));
+                    }
+                };
+
+                for line in reader.split(b'\n') {
+                    let Ok(line) = line else {
+                        break;
+                    };
+                    let line = String::from_utf8_lossy(&line);
+                    match parse_step(&line) {
+                        Some(step) => {
+                            flush(&mut pending);
+                            pending = Some((util::timestamp_ns(), step));
+                        }
+                        // Multi-line commands continue on following lines.
+                        None => {
+                            if let Some((_, ref mut step)) = pending {
+                                step.command.push('\n');
+                                step.command.push_str(&line);
+                            }
+                        }
+                    }
+                }
+                flush(&mut pending);
+
+                let _ = fs::remove_dir_all(&dir);
+            })
+            .expect("failed to spawn shell trace reader thread");
+
+        ShellTraceReader {
+            handle: Some(handle),
+        }
+    }
+}
+
+pub struct ShellTraceReader {
+    handle: Option<thread::JoinHandle<()>>,
+}
+
+impl ShellTraceReader {
+    pub fn finish(mut self) {
+        if let Some(h) = self.handle.take() {
+            let _ = h.join();
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct ShellStep {
+    pid: i32,
+    epoch_ns: Option<u64>,
+    file: String,
+    line: u32,
+    func: String,
+    /// Number of PS4 prefix repeats: 1 for plain commands, more inside
+    /// command substitutions.
+    depth: usize,
+    command: String,
+}
+
+impl ShellStep {
+    fn into_event(self, ts: u64) -> Event {
+        Event {
+            ts,
+            proc_id: self.pid,
+            kind: EventKind::ShellStep,
+            detail: serde_json::json!({
+                "file": self.file,
+                "line": self.line,
+                "func": self.func,
+                "depth": self.depth,
+                "command": self.command,
+            })
+            .to_string(),
+        }
+    }
+}
+
+fn parse_step(line: &str) -> Option<ShellStep> {
+    let mut fields = line.splitn(8, SEP);
+    let prefix = fields.next()?;
+    if prefix.is_empty() || !prefix.chars().all(|c| c == '+') || fields.next()? != "poe" {
+        return None;
+    }
+    let pid = fields.next()?.parse().ok()?;
+    let epoch = fields.next()?;
+    let file = fields.next()?.to_string();
+    let line_no = fields.next()?.parse().unwrap_or(0);
+    let func = fields.next()?.to_string();
+    let command = fields.next().unwrap_or("").to_string();
+
+    Some(ShellStep {
+        pid,
+        epoch_ns: parse_epoch(epoch),
+        file,
+        line: line_no,
+        func,
+        depth: prefix.len(),
+        command,
+    })
+}
+
+/// `EPOCHREALTIME` is seconds with a locale-dependent decimal separator and
+/// microsecond precision.
+fn parse_epoch(s: &str) -> Option<u64> {
+    let (secs, micros) = s.split_once(['.', ','])?;
+    let secs: u64 = secs.parse().ok()?;
+    let micros: u64 = format!("{:0<6}", micros).get(..6)?.parse().ok()?;
+    Some(secs * 1_000_000_000 + micros * 1_000)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_ps4_lines() {
+        let line = "++\x1fpoe\x1f4242\x1f1760000000.123456\x1fdeploy.sh\x1f17\x1fbuild\x1fmake -C src install";
+        let step = parse_step(line).unwrap();
+        assert_eq!(step.pid, 4242);
+        assert_eq!(step.epoch_ns, Some(1_760_000_000_123_456_000));
+        assert_eq!(step.file, "deploy.sh");
+        assert_eq!(step.line, 17);
+        assert_eq!(step.func, "build");
+        assert_eq!(step.depth, 2);
+        assert_eq!(step.command, "make -C src install");
+
+        assert!(parse_step("+ plain xtrace").is_none());
+        assert_eq!(parse_epoch("12,5"), Some(12_500_000_000));
+    }
+}
@@ -1,6 +1,7 @@
 import sys
 import os
 import json
+import reprlib
 import time
 import threading
 import traceback
@@ -11,6 +12,10 @@ _POE_TRACE_CALLS = os.environ.get("_POE_TRACE_CALLS", "1") == "1"
 _POE_MAX_DEPTH = int(os.environ.get("_POE_MAX_DEPTH", "64"))
 _POE_VAR_MAX_LEN = int(os.environ.get("_POE_VAR_MAX_LEN", "256"))
 _POE_VAR_MAX_ITEMS = int(os.environ.get("_POE_VAR_MAX_ITEMS", "16"))
+# "all": locals at every raise site; "unhandled": only in the excepthook.
+_POE_LOCALS = os.environ.get("_POE_LOCALS", "all")
+_POE_LOCALS_PER_SEC = int(os.environ.get("_POE_LOCALS_PER_SEC", "0"))
+_POE_LOCALS_MAX_BYTES = int(os.environ.get("_POE_LOCALS_MAX_BYTES", "4096"))
 
 if _POE_FD < 0:
     _POE_FD = None
@@ -18,6 +23,14 @@ if _POE_FD < 0:
 _lock = threading.Lock()
 _start_ns = time.monotonic_ns()
 _depth = {}
+_locals_window = [0.0, 0]
+
+# Bounds the work repr() does on large containers before truncation.
+_repr = reprlib.Repr()
+_repr.maxlevel = 3
+_repr.maxlist = _repr.maxtuple = _repr.maxset = _repr.maxfrozenset = _POE_VAR_MAX_ITEMS
+_repr.maxdict = _repr.maxdeque = _repr.maxarray = _POE_VAR_MAX_ITEMS
+_repr.maxstring = _repr.maxlong = _repr.maxother = _POE_VAR_MAX_LEN
 
 
 def _ts_ns():
@@ -26,7 +39,7 @@ def _ts_ns():
 
 def _safe_repr(obj, max_len=_POE_VAR_MAX_LEN):
     try:
-        r = repr(obj)
+        r = _repr.repr(obj) if max_len <= _POE_VAR_MAX_LEN else repr(obj)
         if len(r) > max_len:
             return r[:max_len - 3] + "..."
         return r
@@ -34,16 +47,44 @@ def _safe_repr(obj, max_len=_POE_VAR_MAX_LEN):
         return "<repr failed>"
 
 
-def _capture_locals(frame, max_items=_POE_VAR_MAX_ITEMS):
+def _capture_locals(frame, max_items=_POE_VAR_MAX_ITEMS, max_bytes=_POE_LOCALS_MAX_BYTES):
     result = {}
-    items = list(frame.f_locals.items())
-    for k, v in items[:max_items]:
+    budget = max_bytes
+    f_locals = frame.f_locals
+    skipped = 0
+    for k in f_locals:
         if k.startswith("__"):
             continue
-        result[k] = _safe_repr(v)
+        if len(result) >= max_items or budget <= 0:
+            skipped += 1
+            continue
+        r = _safe_repr(f_locals[k], min(_POE_VAR_MAX_LEN, max(budget, 16)))
+        budget -= len(k) + len(r)
+        result[k] = r
+    if skipped:
+        result["<more>"] = "%d more" % skipped
     return result
 
 
+def _locals_allowed(exc_tb):
+    if _POE_LOCALS != "all":
+        return False
+    # The trace function sees the exception again in every frame it unwinds
+    # through; only the raise site gets its locals.
+    if exc_tb is not None and exc_tb.tb_next is not None:
+        return False
+    if _POE_LOCALS_PER_SEC <= 0:
+        return True
+    now = time.monotonic()
+    if now - _locals_window[0] >= 1.0:
+        _locals_window[0] = now
+        _locals_window[1] = 0
+    if _locals_window[1] >= _POE_LOCALS_PER_SEC:
+        return False
+    _locals_window[1] += 1
+    return True
+
+
 def _emit(record):
     if _POE_FD is None:
         return
@@ -123,7 +164,7 @@ def _trace_fn(frame, event, arg):
             "line": frame.f_lineno,
             "exc_type": exc_type.__name__ if exc_type else "Unknown",
             "exc_msg": _safe_repr(exc_value, 512),
-            "locals": _capture_locals(frame),
+            "locals": _capture_locals(frame) if _locals_allowed(exc_tb) else None,
         })
 
     return _trace_fn
@@ -175,7 +216,10 @@ def _init():
 
     if _POE_TRACE_CALLS and _POE_FD is not None:
         sys.settrace(_trace_fn)
-        threading.settrace_all_threads(_trace_fn)
+        if hasattr(threading, "settrace_all_threads"):
+            threading.settrace_all_threads(_trace_fn)
+        else:
+            threading.settrace(_trace_fn)
 
 
 _init()
@@ -7,9 +7,11 @@ pub mod distributed;
 pub mod events;
 pub mod explain;
 pub mod hooks;
+pub mod logging;
 pub mod pack;
 pub mod redact;
 pub mod serve;
 pub mod symbols;
 pub mod trace;
 pub mod util;
+pub mod view;
@@ -0,0 +1,122 @@
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+
+use anyhow::Result;
+use log::{Level, LevelFilter, Log, Metadata, Record};
+
+/// Level recorded in the run log, whatever reaches stderr.
+const FILE_LEVEL: LevelFilter = LevelFilter::Debug;
+
+/// poe's own diagnostics. Only what passes the verbosity level goes to
+/// stderr, where it would otherwise interleave with the traced command's
+/// output; during a run everything down to debug is also written to a log
+/// file in the run's work dir, which is stored in the pack.
+struct Logger {
+    stderr: LevelFilter,
+    file: Mutex<Option<(PathBuf, File)>>,
+}
+
+static LOGGER: OnceLock<Logger> = OnceLock::new();
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.stderr.max(FILE_LEVEL)
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        if record.level() <= self.stderr {
+            match record.level() {
+                Level::Error | Level::Warn | Level::Info => eprintln!("poe: {}", record.args()),
+                level => eprintln!("poe: [{}] {}", level.as_str().to_lowercase(), record.args()),
+            }
+        }
+        if record.level() <= FILE_LEVEL {
+            if let Some((_, file)) = self.file.lock().unwrap().as_mut() {
+                let _ = writeln!(
+                    file,
+                    "{} {:<5} {}: {}",
+                    chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+                    record.level(),
+                    record.target(),
+                    record.args()
+                );
+            }
+        }
+    }
+
+    fn flush(&self) {
+        if let Some((_, file)) = self.file.lock().unwrap().as_mut() {
+            let _ = file.flush();
+        }
+    }
+}
+
+/// Stderr level for `--quiet` / `-v` / `-vv`, falling back to `$POE_LOG`
+/// (`off`, `error`, `warn`, `info`, `debug` or `trace`) when neither is given.
+pub fn stderr_level(quiet: bool, verbose: u8, env: Option<&str>) -> LevelFilter {
+    if quiet {
+        return LevelFilter::Error;
+    }
+    match verbose {
+        0 => env
+            .and_then(|s| s.trim().parse().ok())
+            .unwrap_or(LevelFilter::Warn),
+        1 => LevelFilter::Info,
+        2 => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
+    }
+}
+
+pub fn init(quiet: bool, verbose: u8) {
+    let env = std::env::var("POE_LOG").ok();
+    let stderr = stderr_level(quiet, verbose, env.as_deref());
+    let logger = LOGGER.get_or_init(|| Logger {
+        stderr,
+        file: Mutex::new(None),
+    });
+    if log::set_logger(logger).is_ok() {
+        log::set_max_level(stderr.max(FILE_LEVEL));
+    }
+}
+
+/// True when stderr is limited to errors, so commands should skip their
+/// own reports too.
+pub fn is_quiet() -> bool {
+    LOGGER.get().is_some_and(|l| l.stderr <= LevelFilter::Error)
+}
+
+/// Starts recording diagnostics to `path`, replacing any earlier log file.
+pub fn start_file(path: &Path) -> Result<()> {
+    if let Some(logger) = LOGGER.get() {
+        let file = File::create(path)?;
+        *logger.file.lock().unwrap() = Some((path.to_path_buf(), file));
+    }
+    Ok(())
+}
+
+/// Stops recording to the log file and returns what was written.
+pub fn finish_file() -> Option<Vec<u8>> {
+    let (path, mut file) = LOGGER.get()?.file.lock().unwrap().take()?;
+    let _ = file.flush();
+    std::fs::read(path).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flags_override_poe_log() {
+        assert_eq!(stderr_level(false, 0, None), LevelFilter::Warn);
+        assert_eq!(stderr_level(false, 0, Some("debug")), LevelFilter::Debug);
+        assert_eq!(stderr_level(false, 0, Some("bogus")), LevelFilter::Warn);
+        assert_eq!(stderr_level(false, 1, Some("off")), LevelFilter::Info);
+        assert_eq!(stderr_level(false, 2, None), LevelFilter::Debug);
+        assert_eq!(stderr_level(true, 2, Some("trace")), LevelFilter::Error);
+    }
+}
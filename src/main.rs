@@ -11,12 +11,14 @@ mod distributed;
 mod events;
 mod explain;
 mod hooks;
+mod logging;
 mod pack;
 mod redact;
 mod serve;
 mod symbols;
 mod trace;
 mod util;
+mod view;
 
 #[derive(Parser)]
 #[command(
@@ -28,11 +30,22 @@ mod util;
 struct Cli {
     #[command(subcommand)]
     command: Commands,
+
+    /// Only print errors from poe itself
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+
+    /// Print more of poe's own diagnostics (-v info, -vv debug); default from POE_LOG
+    #[arg(short, long, global = true, action = clap::ArgAction::Count)]
+    verbose: u8,
 }
 
+// Parsed once at startup; boxing `Run`'s options would only clutter dispatch.
+#[allow(clippy::large_enum_variant)]
 #[derive(Subcommand)]
 enum Commands {
     /// Run a command with debug capture
+    #[command(group(clap::ArgGroup::new("baseline").args(["diff", "profile"]).multiple(true)))]
     Run {
         /// Always emit a debug packet, even on success
         #[arg(long)]
@@ -46,9 +59,101 @@ enum Commands {
         #[arg(short, long)]
         output: Option<PathBuf>,
 
-        /// Baseline .poepack to diff against after run
+        /// Baseline .poepack to diff against after run, or @name for a named baseline; repeat for several baselines
+        #[arg(long)]
+        diff: Vec<PathBuf>,
+
+        /// With several --diff baselines, how many must show an event for realtime diff to treat it as expected (default 1)
+        #[arg(long, value_name = "N", requires = "diff")]
+        diff_quorum: Option<usize>,
+
+        /// Profile written by poe learn to diff against in realtime, alone or with --diff
+        #[arg(long, value_name = "FILE")]
+        profile: Option<PathBuf>,
+
+        /// Size budget for the emitted pack (e.g. 100MB); low-priority data is dropped to fit
+        #[arg(long)]
+        max_pack_size: Option<String>,
+
+        /// Record stdin fed to the command so `poe replay` can feed it back
         #[arg(long)]
-        diff: Option<PathBuf>,
+        capture_stdin: bool,
+
+        /// Run the command on a pseudo terminal so it behaves as if interactive
+        #[arg(long)]
+        pty: bool,
+
+        /// Hash files the command writes when they are closed, for `poe diff`; optional size cap (default 16MB)
+        #[arg(long, value_name = "MAX_SIZE", num_args = 0..=1, default_missing_value = "16MB")]
+        hash_writes: Option<String>,
+
+        /// Record the full read/write buffers for this file (repeatable)
+        #[arg(long, value_name = "PATH")]
+        watch_file: Vec<PathBuf>,
+
+        /// Syscall capture backend: ptrace (default) or seccomp (lower overhead, no syscall results)
+        #[arg(long)]
+        backend: Option<String>,
+
+        /// Run bash scripts with xtrace and record each executed line
+        #[arg(long)]
+        trace_shell: bool,
+
+        /// Decode Postgres and MySQL client traffic to record SQL statements and their errors
+        #[arg(long)]
+        capture_sql: bool,
+
+        /// Decode Redis and memcached client traffic to record cache commands, keys and misses
+        #[arg(long)]
+        capture_cache: bool,
+
+        /// Decode cleartext HTTP/2 client traffic to record each request's path, status and gRPC status
+        #[arg(long)]
+        capture_http2: bool,
+
+        /// Label the run, e.g. --tag ci=nightly (repeatable); stored in the pack summary
+        #[arg(long, value_name = "KEY=VALUE")]
+        tag: Vec<String>,
+
+        /// Exit code of poe run: propagate (the command's, default), zero, or poe (0 success, 1 failure)
+        #[arg(long)]
+        exit_status: Option<String>,
+
+        /// Exit 3 when the command succeeded but behavior diverged from the --diff baseline or --profile
+        #[arg(long, requires = "baseline")]
+        fail_on_divergence: bool,
+
+        /// Score a kind of realtime divergence, e.g. --diff-severity new_process=critical (repeatable); levels are info, warning, critical
+        #[arg(long, value_name = "KIND=SEVERITY", requires = "baseline")]
+        diff_severity: Vec<String>,
+
+        /// Stop the command at the first realtime divergence of at least this severity (info, warning, critical)
+        #[arg(long, value_name = "SEVERITY", requires = "baseline")]
+        diff_halt: Option<String>,
+
+        /// Also emit a pack when this process fails (non-zero exit or crash), not just the root: a pid, a program name, or any (repeatable)
+        #[arg(long, value_name = "PID|NAME|any")]
+        on_failure_of: Vec<String>,
+
+        /// Stop tracing children whose program matches these patterns once they exec, e.g. 'cc1*,rustc'; their exit status is still recorded (repeatable)
+        #[arg(long, value_name = "PATTERNS")]
+        exclude_children: Vec<String>,
+
+        /// Don't follow children started with vfork or posix_spawn; they run untraced and only their parent's side is recorded
+        #[arg(long)]
+        no_follow_vfork: bool,
+
+        /// Report this run's span to a poe serve instance as it starts and ends, for live trace views
+        #[arg(long, value_name = "URL")]
+        stream: Option<String>,
+
+        /// Redact secrets (bearer tokens, JWTs, AWS keys, private keys) from captured stdio and --watch-file payloads before they are stored; the terminal still sees them
+        #[arg(long)]
+        redact: bool,
+
+        /// Like --redact, but replace each redacted value with a pseudonym derived from it with HMAC-SHA256 under the key in this file, so the same value matches across packs redacted with the same key
+        #[arg(long, value_name = "FILE")]
+        redact_key: Option<PathBuf>,
 
         /// The command to run (after --)
         #[arg(trailing_var_arg = true, required = true)]
@@ -61,14 +166,96 @@ enum Commands {
         #[arg(required = true)]
         packet: PathBuf,
 
-        /// Output as JSON
+        /// Output as JSON (same as --format json)
         #[arg(long)]
         json: bool,
+
+        /// Output format: text, json, or markdown (GitHub-flavored, for issues and PRs)
+        #[arg(long, conflicts_with = "json")]
+        format: Option<String>,
+
+        /// Extra error pattern rules file (TOML), may be repeated
+        #[arg(long = "patterns")]
+        patterns: Vec<PathBuf>,
+
+        /// Print suggested next steps after the diagnosis
+        #[arg(long)]
+        suggest: bool,
+
+        /// Emit a compact, prioritized context block for LLMs (markdown, or JSON with --json)
+        #[arg(long)]
+        context: bool,
+
+        /// Token budget for --context, e.g. 8000, 8000tokens or 8k
+        #[arg(long, requires = "context")]
+        budget: Option<String>,
+
+        /// Only show these sections: failure, files, net, timeline, hotspots, stderr (repeatable)
+        #[arg(long, conflicts_with = "context")]
+        focus: Vec<String>,
+
+        /// Show the whole timeline between two run-relative times, e.g. 1200..1450ms or 2..3s
+        #[arg(long, value_name = "RANGE", conflicts_with = "context")]
+        timeline_window: Option<String>,
+
+        /// Restrict the timeline to one process
+        #[arg(long, conflicts_with = "context")]
+        pid: Option<i32>,
+
+        /// Directory of earlier packs: score how unusual this run is next to those of the same command
+        #[arg(long, value_name = "DIR", conflicts_with = "context")]
+        history: Option<PathBuf>,
+    },
+
+    /// Write a standalone HTML report for a debug packet
+    Report {
+        /// Path to the .poepack file
+        #[arg(required = true)]
+        packet: PathBuf,
+
+        /// Output file (default: the pack path with an .html extension)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+
+        /// Extra error pattern rules file (TOML), may be repeated
+        #[arg(long = "patterns")]
+        patterns: Vec<PathBuf>,
+    },
+
+    /// Export a debug packet's diagnosis for other tools
+    Export {
+        /// What to export: sarif (for code-scanning annotations) or table (a trace table for pandas or duckdb)
+        #[arg(required = true)]
+        kind: String,
+
+        /// Path to the .poepack file
+        #[arg(required = true)]
+        packet: PathBuf,
+
+        /// Output file (default: stdout)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+
+        /// Directory result paths are made relative to (default: the run's working directory)
+        #[arg(long)]
+        source_root: Option<PathBuf>,
+
+        /// Extra error pattern rules file (TOML), may be repeated
+        #[arg(long = "patterns")]
+        patterns: Vec<PathBuf>,
+
+        /// With table: the table to export (files, net or events)
+        #[arg(long, default_value = "files")]
+        table: String,
+
+        /// With table: csv or parquet (default: parquet for a .parquet output, else csv)
+        #[arg(long)]
+        format: Option<String>,
     },
 
     /// Compare two debug packets to find divergences
     Diff {
-        /// Baseline .poepack file
+        /// Baseline .poepack file, or @name for a named baseline
         #[arg(required = true)]
         baseline: PathBuf,
 
@@ -76,9 +263,13 @@ enum Commands {
         #[arg(required = true)]
         candidate: PathBuf,
 
-        /// Output as JSON
+        /// Output as JSON (same as --format json)
         #[arg(long)]
         json: bool,
+
+        /// Output format: text, json, or unified (stderr and file paths as +/- unified diffs)
+        #[arg(long, conflicts_with = "json")]
+        format: Option<String>,
     },
 
     /// Query a debug packet for specific data
@@ -87,9 +278,42 @@ enum Commands {
         #[arg(required = true)]
         packet: PathBuf,
 
-        /// Query to run (summary, processes, events, files, net, stacks, stdout, stderr, stats, files:<pattern>, net:<pattern>, sql:<query>)
+        /// Query to run (summary, processes, proctree, events, files, net, stacks, stdout, stderr, stdin, stats, io-counters, sched, build, statements, cache, streams, files:<pattern>, net:<pattern>, sql:<query>)
         #[arg(required = true)]
         query: String,
+
+        /// Maximum number of rows to return
+        #[arg(long)]
+        limit: Option<usize>,
+
+        /// Number of rows to skip
+        #[arg(long, default_value_t = 0)]
+        offset: usize,
+
+        /// Output format: json (default), ndjson or csv; dot (Graphviz) for proctree
+        #[arg(long)]
+        format: Option<String>,
+    },
+
+    /// Re-run a packet's command under capture, feeding back recorded stdin
+    Replay {
+        /// Path to the .poepack file
+        #[arg(required = true)]
+        packet: PathBuf,
+
+        /// Output directory for the new .poepack file
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+
+        /// Don't feed recorded stdin; inherit the terminal's instead
+        #[arg(long)]
+        no_stdin: bool,
+    },
+
+    /// Live view of an in-progress capture
+    Top {
+        /// Run work dir, trace.sqlite path, or run id prefix (default: most recent run)
+        target: Option<String>,
     },
 
     /// Build a project with instrumentation for poe capture
@@ -98,8 +322,12 @@ enum Commands {
         #[arg(short, long)]
         output: Option<std::path::PathBuf>,
 
+        /// Write a toolchain file (cmake or meson) pointing at the instrumenting wrappers
+        #[arg(long, value_name = "KIND")]
+        emit_toolchain: Option<String>,
+
         /// The build command to run (after --)
-        #[arg(trailing_var_arg = true, required = true)]
+        #[arg(trailing_var_arg = true, required_unless_present = "emit_toolchain")]
         command: Vec<String>,
     },
 
@@ -109,9 +337,155 @@ enum Commands {
         #[arg(long, default_value = "127.0.0.1:3000")]
         bind: String,
 
-        /// Directory to store uploaded packs
+        /// Directory to store uploaded packs, or s3://bucket/prefix or gs://bucket/prefix
         #[arg(long, default_value = "./poe-store")]
-        store: std::path::PathBuf,
+        store: String,
+
+        /// TOML file with server settings, e.g. [retention]
+        #[arg(long, value_name = "FILE")]
+        config: Option<std::path::PathBuf>,
+
+        /// Local cache of packs read from an s3:// or gs:// store (default: ~/.cache/poe/packs/serve)
+        #[arg(long, value_name = "DIR")]
+        cache_dir: Option<std::path::PathBuf>,
+
+        /// Size the pack cache is kept under, e.g. 2GB (default: 10GB)
+        #[arg(long, value_name = "SIZE")]
+        cache_size: Option<String>,
+
+        /// PEM certificate chain; serve HTTPS instead of HTTP
+        #[arg(long, requires = "tls_key")]
+        tls_cert: Option<std::path::PathBuf>,
+
+        /// PEM private key for --tls-cert
+        #[arg(long, requires = "tls_cert")]
+        tls_key: Option<std::path::PathBuf>,
+
+        /// Also listen for plain HTTP here and redirect it to HTTPS
+        #[arg(long, value_name = "ADDR", requires = "tls_cert")]
+        http_redirect: Option<String>,
+
+        /// Largest pack upload accepted, e.g. 500MB (default: 1GB)
+        #[arg(long, value_name = "SIZE")]
+        max_body: Option<String>,
+
+        /// Requests handled at once; a few times as many queue, the rest get 503 (default: 16)
+        #[arg(long, value_name = "N")]
+        max_concurrent: Option<usize>,
+
+        /// Drop connections that send or take nothing for this many seconds (default: 60)
+        #[arg(long, value_name = "SECS")]
+        timeout: Option<u64>,
+    },
+
+    /// Group failing packs in a directory by failure fingerprint
+    Cluster {
+        /// Directory of .poepack files
+        #[arg(required = true)]
+        dir: PathBuf,
+
+        /// Output as JSON
+        #[arg(long)]
+        json: bool,
+
+        /// Extra error pattern rules file (TOML), may be repeated
+        #[arg(long = "patterns")]
+        patterns: Vec<PathBuf>,
+    },
+
+    /// Duration and failure trends per command across a directory of packs
+    Stats {
+        /// Directory of .poepack files
+        #[arg(required = true)]
+        dir: PathBuf,
+
+        /// How many of the latest runs of each command to compare with the earlier ones (default: half)
+        #[arg(long)]
+        recent: Option<usize>,
+
+        /// Output as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Time a command with and without capture, per backend
+    Bench {
+        /// Timed runs per mode (default: 5)
+        #[arg(short = 'n', long)]
+        runs: Option<usize>,
+
+        /// Capture backend to measure (repeatable; default: every available one)
+        #[arg(long = "backend")]
+        backends: Vec<String>,
+
+        /// Capture mode passed to poe run: lite (default) or full
+        #[arg(long)]
+        mode: Option<String>,
+
+        /// Exit non-zero when a backend's median overhead is above this percentage
+        #[arg(long, value_name = "PCT")]
+        max_overhead: Option<f64>,
+
+        /// Output as JSON
+        #[arg(long)]
+        json: bool,
+
+        /// The command to benchmark (after --)
+        #[arg(trailing_var_arg = true, required = true)]
+        command: Vec<String>,
+    },
+
+    /// Browse a debug packet interactively
+    View {
+        /// Path to the .poepack file
+        #[arg(required = true)]
+        packet: PathBuf,
+
+        /// Extra error pattern rules file (TOML), may be repeated
+        #[arg(long = "patterns")]
+        patterns: Vec<PathBuf>,
+    },
+
+    /// Run a command several times and learn its normal behavior into a profile for poe run --profile
+    Learn {
+        /// How many runs to learn from (default: 3)
+        #[arg(short = 'n', long)]
+        runs: Option<usize>,
+
+        /// Profile file to write (default: poe-profile.json)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+
+        /// Capture mode passed to poe run: lite (default) or full
+        #[arg(long)]
+        mode: Option<String>,
+
+        /// Learn from runs that exit non-zero too, instead of leaving them out
+        #[arg(long)]
+        ignore_exit_code: bool,
+
+        /// The command to learn (after --)
+        #[arg(trailing_var_arg = true, required = true)]
+        command: Vec<String>,
+    },
+
+    /// Print the JSON Schema of explain, diff or pack summary JSON output
+    Schema {
+        /// Which output: explain, diff or pack-summary
+        #[arg(value_parser = cli::schema::KINDS)]
+        kind: String,
+    },
+
+    /// Manage named baseline packs for `poe run --diff @name`
+    Baseline {
+        #[command(subcommand)]
+        action: BaselineAction,
+    },
+
+    /// List or extract the files inside a pack, such as its trace.sqlite
+    Pack {
+        #[command(subcommand)]
+        action: PackAction,
     },
 
     /// Correlate distributed poe captures across multiple packs
@@ -125,15 +499,108 @@ enum Commands {
         json: bool,
     },
 
+    /// Print message headers that carry the current capture's trace to a consumer
+    Inject {
+        /// Print a JSON object instead of `name: value` lines
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Run a consumer command in the trace named by a message's headers
+    Extract {
+        /// A header as NAME=VALUE or "NAME: VALUE" (repeatable)
+        #[arg(long = "header", value_name = "HEADER")]
+        headers: Vec<String>,
+
+        /// Headers as a JSON object, or - to read it from stdin
+        #[arg(long, value_name = "JSON")]
+        headers_json: Option<String>,
+
+        /// The command to run (after --); without one, print shell exports
+        #[arg(trailing_var_arg = true)]
+        command: Vec<String>,
+    },
+
     /// Check system capabilities for poe
-    Doctor,
+    Doctor {
+        /// Output as JSON; exits 1 when a check fails
+        #[arg(long, conflicts_with = "fix")]
+        json: bool,
+
+        /// Print the sysctl commands that fix failed or degraded checks, and offer to run them with sudo
+        #[arg(long)]
+        fix: bool,
+    },
 
     /// Update poe to the latest version
     Update,
 }
 
+#[derive(Subcommand)]
+enum BaselineAction {
+    /// Save a pack as a named baseline
+    Set {
+        /// .poepack file
+        pack: PathBuf,
+
+        /// Baseline name, used as @name
+        #[arg(long)]
+        name: String,
+
+        /// Publish to a poe serve instance instead of the local store
+        #[arg(long)]
+        server: Option<String>,
+
+        /// Upload even if likely secrets were found in the pack
+        #[arg(long, requires = "server")]
+        allow_secrets: bool,
+    },
+
+    /// List named baselines
+    List {
+        /// List a poe serve instance's baselines instead of the local store
+        #[arg(long)]
+        server: Option<String>,
+
+        /// Output as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Remove a baseline from the local store
+    Remove { name: String },
+}
+
+#[derive(Subcommand)]
+enum PackAction {
+    /// List the files in a pack with their sizes
+    List {
+        /// .poepack file
+        pack: PathBuf,
+
+        /// Output as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Copy one file out of a pack
+    Extract {
+        /// .poepack file
+        pack: PathBuf,
+
+        /// Member to extract, as shown by `poe pack list`
+        #[arg(long, default_value = "trace.sqlite")]
+        member: String,
+
+        /// Output file, or - for stdout (default: the member's file name)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+}
+
 fn main() {
     let cli = Cli::parse();
+    logging::init(cli.quiet, cli.verbose);
 
     let result = match cli.command {
         Commands::Run {
@@ -141,26 +608,219 @@ fn main() {
             mode,
             output,
             diff,
+            diff_quorum,
+            profile,
+            max_pack_size,
+            capture_stdin,
+            pty,
+            hash_writes,
+            watch_file,
+            backend,
+            trace_shell,
+            capture_sql,
+            capture_cache,
+            capture_http2,
+            tag,
+            exit_status,
+            fail_on_divergence,
+            diff_severity,
+            diff_halt,
+            on_failure_of,
+            exclude_children,
+            no_follow_vfork,
+            stream,
+            redact,
+            redact_key,
+            command,
+        } => cli::run::execute(
             command,
-        } => cli::run::execute(command, always, mode, output, diff),
+            always,
+            mode,
+            output,
+            diff,
+            diff_quorum,
+            profile,
+            max_pack_size,
+            capture_stdin,
+            pty,
+            hash_writes,
+            watch_file,
+            backend,
+            trace_shell,
+            capture_sql,
+            capture_cache,
+            capture_http2,
+            tag,
+            exit_status,
+            fail_on_divergence,
+            diff_severity,
+            diff_halt,
+            on_failure_of,
+            exclude_children,
+            no_follow_vfork,
+            stream,
+            redact,
+            redact_key,
+        ),
+
+        Commands::Explain {
+            packet,
+            json,
+            format,
+            patterns,
+            suggest,
+            context,
+            budget,
+            focus,
+            timeline_window,
+            pid,
+            history,
+        } => cli::explain::execute(
+            packet,
+            json,
+            format,
+            patterns,
+            suggest,
+            context,
+            budget,
+            focus,
+            timeline_window,
+            pid,
+            history,
+        ),
+
+        Commands::Report {
+            packet,
+            output,
+            patterns,
+        } => cli::report::execute(packet, output, patterns),
+
+        Commands::Export {
+            kind,
+            packet,
+            output,
+            source_root,
+            patterns,
+            table,
+            format,
+        } => match kind.as_str() {
+            "table" => cli::export::table(packet, &table, format, output),
+            _ => cli::export::execute(kind, packet, output, source_root, patterns),
+        },
+
+        Commands::Cluster {
+            dir,
+            json,
+            patterns,
+        } => cli::cluster::execute(dir, json, patterns),
 
-        Commands::Explain { packet, json } => cli::explain::execute(packet, json),
+        Commands::Stats { dir, recent, json } => cli::stats::execute(dir, recent, json),
 
         Commands::Diff {
             baseline,
             candidate,
             json,
-        } => cli::diff::execute(baseline, candidate, json),
+            format,
+        } => cli::diff::execute(baseline, candidate, json, format),
+
+        Commands::Query {
+            packet,
+            query,
+            limit,
+            offset,
+            format,
+        } => cli::query::execute(packet, query, limit, offset, format),
+
+        Commands::Replay {
+            packet,
+            output,
+            no_stdin,
+        } => cli::replay::execute(packet, output, no_stdin),
 
-        Commands::Query { packet, query } => cli::query::execute(packet, query),
+        Commands::Top { target } => cli::top::execute(target),
 
-        Commands::Build { output, command } => cli::build::execute(command, output),
+        Commands::Build {
+            output,
+            emit_toolchain,
+            command,
+        } => cli::build::execute(command, output, emit_toolchain),
 
-        Commands::Trace { packs, json } => cli::trace::execute(packs, json),
+        Commands::Bench {
+            runs,
+            backends,
+            mode,
+            max_overhead,
+            json,
+            command,
+        } => cli::bench::execute(command, runs, backends, mode, max_overhead, json),
 
-        Commands::Serve { bind, store } => serve::server::start(&bind, &store),
+        Commands::Learn {
+            runs,
+            output,
+            mode,
+            ignore_exit_code,
+            command,
+        } => cli::learn::execute(command, runs, output, mode, ignore_exit_code),
+
+        Commands::View { packet, patterns } => cli::view::execute(packet, patterns),
+
+        Commands::Schema { kind } => cli::schema::execute(&kind),
+
+        Commands::Baseline { action } => match action {
+            BaselineAction::Set {
+                pack,
+                name,
+                server,
+                allow_secrets,
+            } => cli::baseline::set(&pack, &name, server, allow_secrets),
+            BaselineAction::List { server, json } => cli::baseline::list(server, json),
+            BaselineAction::Remove { name } => cli::baseline::remove(&name),
+        },
+
+        Commands::Pack { action } => match action {
+            PackAction::List { pack, json } => cli::pack::list(pack, json),
+            PackAction::Extract {
+                pack,
+                member,
+                output,
+            } => cli::pack::extract(pack, &member, output),
+        },
 
-        Commands::Doctor => cli::doctor::execute(),
+        Commands::Trace { packs, json } => cli::trace::execute(packs, json),
+        Commands::Inject { json } => cli::trace::inject(json),
+        Commands::Extract {
+            headers,
+            headers_json,
+            command,
+        } => cli::trace::extract(headers, headers_json, command),
+
+        Commands::Serve {
+            bind,
+            store,
+            config,
+            cache_dir,
+            cache_size,
+            tls_cert,
+            tls_key,
+            http_redirect,
+            max_body,
+            max_concurrent,
+            timeout,
+        } => cli::serve::execute(
+            bind,
+            store,
+            config,
+            cache_dir,
+            cache_size,
+            tls_cert,
+            tls_key,
+            http_redirect,
+            max_body,
+            max_concurrent,
+            timeout,
+        ),
+
+        Commands::Doctor { json, fix } => cli::doctor::execute(json, fix),
 
         Commands::Update => cli::update::execute(),
     };
@@ -0,0 +1,219 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::pack::reader::PackReader;
+
+/// Named baseline packs kept in a local directory: `<name>.poepack` with a
+/// `<name>.json` alongside describing where it came from.
+pub struct BaselineStore {
+    dir: PathBuf,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BaselineInfo {
+    pub name: String,
+    pub run_id: String,
+    pub command: Vec<String>,
+    /// When the baseline run happened.
+    pub timestamp: String,
+    /// When it was made the named baseline.
+    pub set_at: String,
+    /// The pack it was copied from, or the server it was fetched from.
+    pub source: String,
+}
+
+impl BaselineStore {
+    /// `$POE_BASELINE_DIR`, else `$XDG_DATA_HOME/poe/baselines`, else
+    /// `~/.local/share/poe/baselines`.
+    pub fn default_dir() -> Result<PathBuf> {
+        if let Ok(dir) = std::env::var("POE_BASELINE_DIR") {
+            return Ok(PathBuf::from(dir));
+        }
+        if let Ok(data) = std::env::var("XDG_DATA_HOME") {
+            if !data.is_empty() {
+                return Ok(Path::new(&data).join("poe/baselines"));
+            }
+        }
+        let home = std::env::var("HOME").context("HOME is not set; set POE_BASELINE_DIR")?;
+        Ok(Path::new(&home).join(".local/share/poe/baselines"))
+    }
+
+    pub fn open_default() -> Result<Self> {
+        Ok(Self::new(Self::default_dir()?))
+    }
+
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    pub fn pack_path(&self, name: &str) -> PathBuf {
+        self.dir.join(format!("{}.poepack", name))
+    }
+
+    fn info_path(&self, name: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", name))
+    }
+
+    /// Copies `pack` into the store under `name`, replacing any baseline
+    /// already there.
+    pub fn set(&self, name: &str, pack: &Path, source: &str) -> Result<BaselineInfo> {
+        validate_name(name)?;
+        let reader = PackReader::open(pack)?;
+        let summary = reader.summary();
+        let info = BaselineInfo {
+            name: name.to_string(),
+            run_id: summary.run_id.clone(),
+            command: summary.command.clone(),
+            timestamp: summary.timestamp.clone(),
+            set_at: chrono::Utc::now().to_rfc3339(),
+            source: source.to_string(),
+        };
+        drop(reader);
+
+        std::fs::create_dir_all(&self.dir)
+            .with_context(|| format!("failed to create {}", self.dir.display()))?;
+        // Copy then rename so a concurrent `poe run --diff @name` never
+        // reads a half-written pack.
+        let tmp = self.dir.join(format!(".{}.poepack.tmp", name));
+        std::fs::copy(pack, &tmp).with_context(|| format!("failed to copy {}", pack.display()))?;
+        std::fs::rename(&tmp, self.pack_path(name))?;
+        std::fs::write(self.info_path(name), serde_json::to_vec_pretty(&info)?)?;
+        Ok(info)
+    }
+
+    pub fn get(&self, name: &str) -> Option<BaselineInfo> {
+        if !self.pack_path(name).is_file() {
+            return None;
+        }
+        let data = std::fs::read(self.info_path(name)).ok()?;
+        serde_json::from_slice(&data).ok()
+    }
+
+    /// All baselines, by name.
+    pub fn list(&self) -> Result<Vec<BaselineInfo>> {
+        let entries = match std::fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut infos: Vec<BaselineInfo> = entries
+            .filter_map(|e| e.ok())
+            .filter_map(|e| {
+                let path = e.path();
+                if path.extension()? != "poepack" {
+                    return None;
+                }
+                self.get(&path.file_stem()?.to_string_lossy())
+            })
+            .collect();
+        infos.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(infos)
+    }
+
+    pub fn remove(&self, name: &str) -> Result<()> {
+        validate_name(name)?;
+        if self.get(name).is_none() {
+            bail!("no baseline named '{}'", name);
+        }
+        std::fs::remove_file(self.pack_path(name))?;
+        let _ = std::fs::remove_file(self.info_path(name));
+        Ok(())
+    }
+}
+
+/// Names become file names here and URL segments on a server.
+pub fn validate_name(name: &str) -> Result<()> {
+    let ok = !name.is_empty()
+        && !name.starts_with('.')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    if !ok {
+        bail!(
+            "invalid baseline name '{}': use letters, digits, '-', '_' and '.'",
+            name
+        );
+    }
+    Ok(())
+}
+
+/// Resolves a `--diff` argument: `@name` is a named baseline from the
+/// local store, or failing that from the poe server in `$POE_SERVER`
+/// (fetched into the local store); anything else is a pack path.
+pub fn resolve(arg: &Path) -> Result<PathBuf> {
+    let Some(name) = arg.to_str().and_then(|s| s.strip_prefix('@')) else {
+        return super::remote::resolve(arg);
+    };
+    validate_name(name)?;
+    let store = BaselineStore::open_default()?;
+    let server = std::env::var("POE_SERVER").ok().filter(|s| !s.is_empty());
+    match (store.get(name), server) {
+        // Set locally: the server's baseline of the same name doesn't apply.
+        (Some(info), server) if server.as_deref() != Some(info.source.as_str()) => {
+            Ok(store.pack_path(name))
+        }
+        // Fetched from this server before: refresh, since it may have
+        // moved on, but keep using the copy if the server is unreachable.
+        (Some(_), Some(server)) => {
+            if let Err(e) = fetch(&store, &server, name) {
+                log::warn!("using cached baseline '{}': {:#}", name, e);
+            }
+            Ok(store.pack_path(name))
+        }
+        (None, Some(server)) => {
+            fetch(&store, &server, name)?;
+            Ok(store.pack_path(name))
+        }
+        _ => bail!(
+            "no baseline named '{}' in {} (set one with `poe baseline set`, or point POE_SERVER at a poe serve instance)",
+            name,
+            store.dir.display()
+        ),
+    }
+}
+
+/// Downloads the server's baseline `name` into the store.
+pub fn fetch(store: &BaselineStore, server: &str, name: &str) -> Result<BaselineInfo> {
+    let url = format!(
+        "{}/api/baselines/{}/pack",
+        server.trim_end_matches('/'),
+        name
+    );
+    std::fs::create_dir_all(&store.dir)?;
+    let tmp = store.dir.join(format!(".{}.download.tmp", name));
+    let status = std::process::Command::new("curl")
+        .args(["-sSfL", &url, "-o"])
+        .arg(&tmp)
+        .status()
+        .context("failed to run curl")?;
+    if !status.success() {
+        let _ = std::fs::remove_file(&tmp);
+        bail!("failed to fetch baseline '{}' from {}", name, url);
+    }
+    let info = store.set(name, &tmp, server);
+    let _ = std::fs::remove_file(&tmp);
+    info
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn baseline_names_are_file_safe() {
+        assert!(validate_name("nightly").is_ok());
+        assert!(validate_name("release-1.2_x86").is_ok());
+        assert!(validate_name("").is_err());
+        assert!(validate_name("../etc").is_err());
+        assert!(validate_name(".hidden").is_err());
+        assert!(validate_name("a/b").is_err());
+    }
+
+    #[test]
+    fn plain_paths_resolve_to_themselves() {
+        let path = Path::new("./base.poepack");
+        assert_eq!(resolve(path).unwrap(), path);
+    }
+}
@@ -0,0 +1,101 @@
+use anyhow::Result;
+
+use crate::pack::summary::TruncationStep;
+use crate::trace::db::TraceDb;
+
+const BULK_IO_KEEP_TAIL: usize = 2000;
+const STDIO_KEEP_HEAD_ROWS: usize = 64;
+const STDIO_KEEP_TAIL_ROWS: usize = 256;
+const STDIO_KEEP_HEAD_BYTES: usize = 64 * 1024;
+const STDIO_KEEP_TAIL_BYTES: usize = 256 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TruncationStage {
+    StackSamples,
+    BulkIo,
+    StdioMiddle,
+}
+
+impl TruncationStage {
+    pub const ORDER: [TruncationStage; 3] = [
+        TruncationStage::StackSamples,
+        TruncationStage::BulkIo,
+        TruncationStage::StdioMiddle,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::StackSamples => "stack_samples",
+            Self::BulkIo => "bulk_io",
+            Self::StdioMiddle => "stdio_middle",
+        }
+    }
+}
+
+pub fn apply_stage(
+    stage: TruncationStage,
+    db: &TraceDb,
+    stdout: &mut Vec<u8>,
+    stderr: &mut Vec<u8>,
+) -> Result<TruncationStep> {
+    let (rows_dropped, bytes_dropped) = match stage {
+        TruncationStage::StackSamples => (db.delete_stacks()?, 0),
+        TruncationStage::BulkIo => (db.delete_bulk_io(BULK_IO_KEEP_TAIL)?, 0),
+        TruncationStage::StdioMiddle => {
+            let (rows, mut bytes) =
+                db.delete_stdio_middle(STDIO_KEEP_HEAD_ROWS, STDIO_KEEP_TAIL_ROWS)?;
+            for data in [stdout, stderr] {
+                if let Some(trimmed) =
+                    trim_middle(data, STDIO_KEEP_HEAD_BYTES, STDIO_KEEP_TAIL_BYTES)
+                {
+                    bytes += (data.len() - STDIO_KEEP_HEAD_BYTES - STDIO_KEEP_TAIL_BYTES) as u64;
+                    *data = trimmed;
+                }
+            }
+            (rows, bytes)
+        }
+    };
+
+    db.vacuum()?;
+
+    Ok(TruncationStep {
+        stage: stage.as_str().to_string(),
+        rows_dropped,
+        bytes_dropped,
+    })
+}
+
+pub fn trim_middle(data: &[u8], keep_head: usize, keep_tail: usize) -> Option<Vec<u8>> {
+    if data.len() <= keep_head + keep_tail {
+        return None;
+    }
+
+    let dropped = data.len() - keep_head - keep_tail;
+    let marker = format!("\n[poe: {} bytes truncated]\n", dropped);
+
+    let mut result = Vec::with_capacity(keep_head + marker.len() + keep_tail);
+    result.extend_from_slice(&data[..keep_head]);
+    result.extend_from_slice(marker.as_bytes());
+    result.extend_from_slice(&data[data.len() - keep_tail..]);
+    Some(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trim_middle_keeps_head_and_tail() {
+        let data = b"aaaaXXXXXXXXbbbb";
+        let trimmed = trim_middle(data, 4, 4).unwrap();
+        let text = String::from_utf8(trimmed).unwrap();
+        assert!(text.starts_with("aaaa"));
+        assert!(text.ends_with("bbbb"));
+        assert!(text.contains("[poe: 8 bytes truncated]"));
+    }
+
+    #[test]
+    fn trim_middle_leaves_small_data() {
+        assert!(trim_middle(b"short", 4, 4).is_none());
+    }
+}
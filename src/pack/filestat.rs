@@ -0,0 +1,185 @@
+use std::collections::{HashMap, HashSet};
+use std::ffi::CStr;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+
+use crate::events::types::FileStat;
+use crate::explain::analyzer;
+use crate::trace::db::{Page, TraceDb};
+
+/// Caps the stats taken for a run that failed on thousands of paths.
+const MAX_PATHS: usize = 200;
+
+/// Records the state of each path the run failed on or wrote as a
+/// `file_stat` artifact, and of the parent directory of each that is
+/// missing. Lookups of optional files that weren't there are left out, as
+/// explain leaves them out.
+pub fn record(db: &TraceDb) -> Result<usize> {
+    let cwds: HashMap<i32, String> = db
+        .query_processes()?
+        .into_iter()
+        .filter_map(|p| Some((p.proc_id, p.cwd?)))
+        .collect();
+
+    let mut paths: Vec<(String, PathBuf)> = Vec::new();
+    let mut seen: HashSet<String> = HashSet::new();
+    db.each_file_event(Page::default(), |ev| {
+        let (Some(path), Some(result)) = (ev.path, ev.result) else {
+            return Ok(());
+        };
+        if result >= 0 || path.is_empty() || analyzer::is_noise_path_pub(Some(&path)) {
+            return Ok(());
+        }
+        if -result == libc::ENOENT as i64 && !analyzer::is_significant_missing_file(&path) {
+            return Ok(());
+        }
+        if paths.len() < MAX_PATHS && seen.insert(path.clone()) {
+            let resolved = resolve(&path, cwds.get(&ev.proc_id).map(String::as_str));
+            paths.push((path, resolved));
+        }
+        Ok(())
+    })?;
+    for written in db.query_written_files()? {
+        if paths.len() < MAX_PATHS && seen.insert(written.path.clone()) {
+            let resolved = PathBuf::from(&written.path);
+            paths.push((written.path, resolved));
+        }
+    }
+
+    let mut stats = Vec::new();
+    for (path, resolved) in paths {
+        let stat = stat(&path, &resolved);
+        if stat.file_type == "missing" {
+            if let (Some(parent), Some(resolved_parent)) =
+                (Path::new(&path).parent(), resolved.parent())
+            {
+                let parent = parent.to_string_lossy().into_owned();
+                if !parent.is_empty() && seen.insert(parent.clone()) {
+                    stats.push(self::stat(&parent, resolved_parent));
+                }
+            }
+        }
+        stats.push(stat);
+    }
+    db.insert_file_stats(&stats)?;
+    Ok(stats.len())
+}
+
+/// Relative paths are taken from the process's working directory.
+fn resolve(path: &str, cwd: Option<&str>) -> PathBuf {
+    match cwd {
+        Some(cwd) if !path.starts_with('/') => Path::new(cwd).join(path),
+        _ => PathBuf::from(path),
+    }
+}
+
+/// The path's state, without following a final symlink: a dangling link
+/// is reported as the link.
+pub fn stat(path: &str, resolved: &Path) -> FileStat {
+    let mut stat = FileStat {
+        path: path.to_string(),
+        file_type: String::new(),
+        size: None,
+        mode: None,
+        uid: None,
+        gid: None,
+        owner: None,
+        mtime_ns: None,
+    };
+    let meta = match std::fs::symlink_metadata(resolved) {
+        Ok(meta) => meta,
+        Err(e) => {
+            stat.file_type = match e.raw_os_error() {
+                Some(libc::ENOENT) | Some(libc::ENOTDIR) => "missing",
+                _ => "inaccessible",
+            }
+            .into();
+            return stat;
+        }
+    };
+    let kind = meta.file_type();
+    stat.file_type = if kind.is_file() {
+        "file"
+    } else if kind.is_dir() {
+        "dir"
+    } else if kind.is_symlink() {
+        "symlink"
+    } else {
+        "other"
+    }
+    .into();
+    stat.size = Some(meta.len());
+    stat.mode = Some(meta.mode() & 0o7777);
+    stat.uid = Some(meta.uid());
+    stat.gid = Some(meta.gid());
+    stat.owner = user_name(meta.uid());
+    stat.mtime_ns = Some(meta.mtime() * 1_000_000_000 + meta.mtime_nsec());
+    stat
+}
+
+fn user_name(uid: u32) -> Option<String> {
+    let mut buf = vec![0 as libc::c_char; 1024];
+    // SAFETY: passwd and buf outlive the call; getpwuid_r only writes
+    // within buf's length.
+    let mut pwd: libc::passwd = unsafe { std::mem::zeroed() };
+    let mut result: *mut libc::passwd = std::ptr::null_mut();
+    let rc = unsafe { libc::getpwuid_r(uid, &mut pwd, buf.as_mut_ptr(), buf.len(), &mut result) };
+    if rc != 0 || result.is_null() {
+        return None;
+    }
+    let name = unsafe { CStr::from_ptr(pwd.pw_name) };
+    Some(name.to_string_lossy().into_owned())
+}
+
+/// How explain shows a stat: `missing`, or e.g. `file, 0 bytes, mode
+/// 0600, owner root`.
+pub fn describe(stat: &FileStat) -> String {
+    match stat.file_type.as_str() {
+        "missing" => "missing".into(),
+        "inaccessible" => "not accessible to poe".into(),
+        kind => {
+            let mut text = kind.to_string();
+            if kind == "file" {
+                if let Some(size) = stat.size {
+                    text.push_str(&format!(", {} bytes", size));
+                }
+            }
+            if let Some(mode) = stat.mode {
+                text.push_str(&format!(", mode {:04o}", mode));
+            }
+            match (&stat.owner, stat.uid) {
+                (Some(owner), _) => text.push_str(&format!(", owner {}", owner)),
+                (None, Some(uid)) => text.push_str(&format!(", owner uid {}", uid)),
+                _ => {}
+            }
+            text
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tells_missing_from_empty_and_dangling() {
+        let dir = tempfile::tempdir().unwrap();
+        let empty = dir.path().join("empty");
+        std::fs::write(&empty, b"").unwrap();
+        let link = dir.path().join("link");
+        std::os::unix::fs::symlink(dir.path().join("gone"), &link).unwrap();
+
+        let s = stat("empty", &empty);
+        assert_eq!((s.file_type.as_str(), s.size), ("file", Some(0)));
+        assert!(describe(&s).starts_with("file, 0 bytes, mode 0"));
+        assert_eq!(stat("link", &link).file_type, "symlink");
+        let missing = stat("x", &dir.path().join("gone/x"));
+        assert_eq!(describe(&missing), "missing");
+        assert_eq!(
+            resolve("out/a.txt", Some("/work")),
+            Path::new("/work/out/a.txt")
+        );
+    }
+}
@@ -1,3 +1,8 @@
+pub mod baseline;
+pub mod budget;
+pub mod filestat;
+pub mod mounts;
 pub mod reader;
+pub mod remote;
 pub mod summary;
 pub mod writer;
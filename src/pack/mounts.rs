@@ -0,0 +1,227 @@
+use std::collections::{BTreeSet, HashMap};
+use std::ffi::CString;
+use std::path::Path;
+
+use anyhow::Result;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::trace::db::{Page, TraceDb};
+
+/// A filesystem the run touched, with its space as of pack time.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct MountInfo {
+    pub mount_point: String,
+    pub source: String,
+    pub fs_type: String,
+    pub read_only: bool,
+    #[serde(default)]
+    pub total_bytes: Option<u64>,
+    /// Space available to unprivileged users, which is what a failing
+    /// write ran into.
+    #[serde(default)]
+    pub avail_bytes: Option<u64>,
+    #[serde(default)]
+    pub total_inodes: Option<u64>,
+    #[serde(default)]
+    pub free_inodes: Option<u64>,
+}
+
+/// The mounts holding any path the run opened or stat'ed, or any working
+/// directory it ran in, from poe's own view of `/proc/self/mounts`.
+pub fn snapshot(db: &TraceDb) -> Result<Vec<MountInfo>> {
+    let table = std::fs::read_to_string("/proc/self/mounts").unwrap_or_default();
+    let mounts = parse_mounts(&table);
+    if mounts.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let cwds: HashMap<i32, String> = db
+        .query_processes()?
+        .into_iter()
+        .filter_map(|p| Some((p.proc_id, p.cwd?)))
+        .collect();
+    let mut touched: BTreeSet<usize> = BTreeSet::new();
+    for cwd in cwds.values() {
+        touched.extend(find(&mounts, cwd));
+    }
+    let mut dirs: HashMap<String, Option<usize>> = HashMap::new();
+    db.each_file_event(Page::default(), |ev| {
+        let Some(path) = ev.path else {
+            return Ok(());
+        };
+        let absolute = match cwds.get(&ev.proc_id) {
+            Some(cwd) if !path.starts_with('/') => format!("{}/{}", cwd, path),
+            _ => path,
+        };
+        let dir = match absolute.rfind('/') {
+            Some(0) => "/".to_string(),
+            Some(end) => absolute[..end].to_string(),
+            None => absolute,
+        };
+        let found = *dirs.entry(dir).or_insert_with_key(|dir| find(&mounts, dir));
+        touched.extend(found);
+        Ok(())
+    })?;
+
+    Ok(touched
+        .into_iter()
+        .map(|i| {
+            let mut mount = mounts[i].clone();
+            statvfs(&mut mount);
+            mount
+        })
+        .collect())
+}
+
+/// Entries of a mounts table. Later mounts over the same point hide
+/// earlier ones, so only the last is kept.
+fn parse_mounts(table: &str) -> Vec<MountInfo> {
+    let mut mounts: Vec<MountInfo> = Vec::new();
+    for line in table.lines() {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let [source, mount_point, fs_type, options, ..] = fields[..] else {
+            continue;
+        };
+        let mount_point = unescape(mount_point);
+        mounts.retain(|m| m.mount_point != mount_point);
+        mounts.push(MountInfo {
+            mount_point,
+            source: unescape(source),
+            fs_type: fs_type.to_string(),
+            read_only: options.split(',').any(|o| o == "ro"),
+            total_bytes: None,
+            avail_bytes: None,
+            total_inodes: None,
+            free_inodes: None,
+        });
+    }
+    mounts
+}
+
+/// Spaces, tabs, newlines and backslashes are written as octal escapes.
+fn unescape(field: &str) -> String {
+    let mut out = String::with_capacity(field.len());
+    let mut rest = field;
+    while let Some(at) = rest.find('\\') {
+        out.push_str(&rest[..at]);
+        match rest
+            .get(at + 1..at + 4)
+            .and_then(|digits| u8::from_str_radix(digits, 8).ok())
+        {
+            Some(byte) => {
+                out.push(byte as char);
+                rest = &rest[at + 4..];
+            }
+            None => {
+                out.push('\\');
+                rest = &rest[at + 1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Index of the mount holding `path`: the one with the longest mount
+/// point that is a prefix of it.
+fn find(mounts: &[MountInfo], path: &str) -> Option<usize> {
+    let path = Path::new(path);
+    mounts
+        .iter()
+        .enumerate()
+        .filter(|(_, m)| path.starts_with(&m.mount_point))
+        .max_by_key(|(_, m)| m.mount_point.len())
+        .map(|(i, _)| i)
+}
+
+/// The mount holding `path`, among those recorded in a pack.
+pub fn mount_of<'a>(mounts: &'a [MountInfo], path: &str) -> Option<&'a MountInfo> {
+    find(mounts, path).map(|i| &mounts[i])
+}
+
+fn statvfs(mount: &mut MountInfo) {
+    let Ok(path) = CString::new(mount.mount_point.as_str()) else {
+        return;
+    };
+    // SAFETY: path is NUL-terminated and st is a plain struct statvfs
+    // fills in.
+    let mut st: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(path.as_ptr(), &mut st) } != 0 {
+        return;
+    }
+    let block = st.f_frsize as u64;
+    mount.total_bytes = Some(st.f_blocks as u64 * block);
+    mount.avail_bytes = Some(st.f_bavail as u64 * block);
+    mount.total_inodes = Some(st.f_files as u64);
+    mount.free_inodes = Some(st.f_ffree as u64);
+    // A bind mount can be read-only without the table saying so.
+    mount.read_only |= st.f_flag & libc::ST_RDONLY != 0;
+}
+
+/// How explain shows a mount: `/data (ext4 on /dev/sdb1): 0 bytes of
+/// 20.0 GB free, 1200 of 1310720 inodes free, read-only`.
+pub fn describe(mount: &MountInfo) -> String {
+    let mut text = format!(
+        "{} ({} on {})",
+        mount.mount_point, mount.fs_type, mount.source
+    );
+    if let (Some(avail), Some(total)) = (mount.avail_bytes, mount.total_bytes) {
+        text.push_str(&format!(
+            ": {} of {} free",
+            format_bytes(avail),
+            format_bytes(total)
+        ));
+    }
+    if let (Some(free), Some(total)) = (mount.free_inodes, mount.total_inodes) {
+        // Running out of inodes gives ENOSPC with bytes to spare.
+        if total > 0 && free * 100 < total {
+            text.push_str(&format!(", {} of {} inodes free", free, total));
+        }
+    }
+    if mount.read_only {
+        text.push_str(", read-only");
+    }
+    text
+}
+
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: [(u64, &str); 3] = [(1 << 30, "GB"), (1 << 20, "MB"), (1 << 10, "KB")];
+    match UNITS.iter().find(|(size, _)| bytes >= *size) {
+        Some((size, unit)) => format!("{:.1} {}", bytes as f64 / *size as f64, unit),
+        None => format!("{} bytes", bytes),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_the_innermost_mount() {
+        let mounts = parse_mounts(
+            "overlay / overlay rw,relatime 0 0\n\
+             proc /proc proc rw,nosuid 0 0\n\
+             tmpfs /mnt tmpfs rw 0 0\n\
+             tmpfs /mnt tmpfs rw,size=10k 0 0\n\
+             /dev/sdb1 /mnt/my\\040data ext4 ro,relatime 0 0\n",
+        );
+        assert_eq!(mounts.len(), 4);
+        let data = mount_of(&mounts, "/mnt/my data/out.bin").unwrap();
+        assert_eq!((data.source.as_str(), data.read_only), ("/dev/sdb1", true));
+        assert_eq!(mount_of(&mounts, "/mnt/other").unwrap().fs_type, "tmpfs");
+        assert_eq!(mount_of(&mounts, "/proc2/x").unwrap().mount_point, "/");
+
+        let full = MountInfo {
+            total_bytes: Some(20 << 30),
+            avail_bytes: Some(0),
+            total_inodes: Some(1000),
+            free_inodes: Some(3),
+            ..data.clone()
+        };
+        assert_eq!(
+            describe(&full),
+            "/mnt/my data (ext4 on /dev/sdb1): 0 bytes of 20.0 GB free, 3 of 1000 inodes free, read-only"
+        );
+    }
+}
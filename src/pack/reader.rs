@@ -1,12 +1,59 @@
 use std::fs::{self, File};
-use std::io::Read;
+use std::io::{Read, Write};
 use std::path::Path;
 
 use anyhow::{Context, Result};
+use serde::Serialize;
 use zip::ZipArchive;
 
 use crate::pack::summary::PackSummary;
 use crate::trace::db::TraceDb;
+use crate::util::ansi;
+
+/// A file stored in a pack's zip container.
+#[derive(Debug, Clone, Serialize)]
+pub struct PackMember {
+    pub name: String,
+    /// Uncompressed size in bytes.
+    pub size: u64,
+    pub compressed_size: u64,
+}
+
+fn open_archive(path: &Path) -> Result<ZipArchive<File>> {
+    let file =
+        File::open(path).with_context(|| format!("failed to open pack: {}", path.display()))?;
+    ZipArchive::new(file).with_context(|| format!("not a pack: {}", path.display()))
+}
+
+/// The files in a pack, in the order they were written.
+pub fn members(path: &Path) -> Result<Vec<PackMember>> {
+    let mut archive = open_archive(path)?;
+    (0..archive.len())
+        .map(|i| {
+            let entry = archive.by_index_raw(i)?;
+            Ok(PackMember {
+                name: entry.name().to_string(),
+                size: entry.size(),
+                compressed_size: entry.compressed_size(),
+            })
+        })
+        .collect()
+}
+
+/// Decompresses one member of a pack into `out`, returning its size.
+pub fn extract_member(path: &Path, name: &str, out: &mut impl Write) -> Result<u64> {
+    let mut archive = open_archive(path)?;
+    let mut entry = archive.by_name(name).with_context(|| {
+        format!(
+            "pack has no member '{}' (see `poe pack list {}`)",
+            name,
+            path.display()
+        )
+    })?;
+    let size = std::io::copy(&mut entry, out)?;
+    out.flush()?;
+    Ok(size)
+}
 
 pub struct PackReader {
     work_dir: std::path::PathBuf,
@@ -16,10 +63,7 @@ pub struct PackReader {
 
 impl PackReader {
     pub fn open(path: &Path) -> Result<Self> {
-        let file =
-            File::open(path).with_context(|| format!("failed to open pack: {}", path.display()))?;
-
-        let mut archive = ZipArchive::new(file)?;
+        let mut archive = open_archive(path)?;
 
         let work_dir = std::env::temp_dir().join(format!(
             "poe-read-{}",
@@ -50,6 +94,7 @@ impl PackReader {
         for name in [
             "artifacts/stdout.log",
             "artifacts/stderr.log",
+            "artifacts/stdin.log",
             "meta/environment.json",
         ] {
             if let Ok(mut entry) = archive.by_name(name) {
@@ -94,6 +139,23 @@ impl PackReader {
     pub fn stderr(&self) -> Result<Vec<u8>> {
         self.read_artifact("stderr.log")
     }
+
+    /// Escape-stripped text of "stdout" or "stderr" for display and diffing;
+    /// the raw bytes stay available through `stdout()`/`stderr()`.
+    pub fn stdio_text(&self, stream: &str) -> Option<String> {
+        match self.db.query_stdio_clean(stream) {
+            Ok(Some(text)) if !text.is_empty() => Some(text),
+            _ => self
+                .read_artifact(&format!("{}.log", stream))
+                .ok()
+                .map(|data| ansi::clean_text(&data)),
+        }
+    }
+
+    /// Recorded stdin, if the run was captured with --capture-stdin.
+    pub fn stdin(&self) -> Option<Vec<u8>> {
+        self.read_artifact("stdin.log").ok()
+    }
 }
 
 impl Drop for PackReader {
@@ -0,0 +1,197 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+
+use crate::util;
+
+/// Response header `poe serve` sends with pack downloads.
+pub const CHECKSUM_HEADER: &str = "X-Poe-Sha256";
+
+/// Where downloaded packs are kept: `$POE_CACHE_DIR`, else the XDG cache
+/// directory.
+pub fn cache_dir() -> Result<PathBuf> {
+    if let Ok(dir) = std::env::var("POE_CACHE_DIR") {
+        return Ok(PathBuf::from(dir));
+    }
+    if let Ok(cache) = std::env::var("XDG_CACHE_HOME") {
+        if !cache.is_empty() {
+            return Ok(Path::new(&cache).join("poe/packs"));
+        }
+    }
+    let home = std::env::var("HOME").context("HOME is not set; set POE_CACHE_DIR")?;
+    Ok(Path::new(&home).join(".cache/poe/packs"))
+}
+
+/// Turns an http(s) pack argument into a local file, downloading it into
+/// the cache. Anything else is returned as is. The download is checked
+/// against a `#sha256=<hex>` suffix on the URL, or the checksum header
+/// the server sent; a pinned pack already in the cache isn't fetched
+/// again, and an unpinned one is revalidated with a conditional request.
+pub fn resolve(arg: &Path) -> Result<PathBuf> {
+    let Some(url) = arg
+        .to_str()
+        .filter(|s| s.starts_with("http://") || s.starts_with("https://"))
+    else {
+        return Ok(arg.to_path_buf());
+    };
+    let (url, pinned) = split_pin(url)?;
+    let dir = cache_dir()?;
+    let path = dir.join(cache_name(url));
+
+    if let Some(ref sum) = pinned {
+        if path.exists() && util::hash_file(&path).ok().as_ref() == Some(sum) {
+            return Ok(path);
+        }
+    }
+    match download(url, &dir, &path, pinned.as_deref()) {
+        Ok(()) => Ok(path),
+        Err(e) if pinned.is_none() && path.exists() => {
+            log::warn!("using cached copy of {}: {:#}", url, e);
+            Ok(path)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+fn download(url: &str, dir: &Path, path: &Path, pinned: Option<&str>) -> Result<()> {
+    std::fs::create_dir_all(dir)?;
+    // Unique names, so concurrent downloads of one URL don't collide.
+    let tmp = tempfile::NamedTempFile::new_in(dir)?;
+    let headers = tempfile::NamedTempFile::new_in(dir)?;
+    // Request headers that revalidate the cached copy, saved beside it.
+    let validators = path.with_extension("validators");
+    let cached = pinned.is_none() && path.exists();
+
+    let mut curl = std::process::Command::new("curl");
+    curl.args(["-sSfL", url, "-D"])
+        .arg(headers.path())
+        .arg("-o")
+        .arg(tmp.path());
+    if cached {
+        for line in std::fs::read_to_string(&validators)
+            .unwrap_or_default()
+            .lines()
+        {
+            curl.arg("-H").arg(line);
+        }
+    }
+    let status = curl.status().context("failed to run curl")?;
+    if !status.success() {
+        bail!("failed to download pack from {}", url);
+    }
+    let dump = std::fs::read_to_string(headers.path()).unwrap_or_default();
+    let response = last_response(&dump);
+    if cached && status_code(response) == Some(304) {
+        return Ok(());
+    }
+
+    let actual = util::hash_file(tmp.path())?;
+    let sent = header(response, CHECKSUM_HEADER).map(str::to_ascii_lowercase);
+    if let Some(expected) = pinned.map(str::to_string).or(sent) {
+        if actual != expected {
+            bail!(
+                "pack downloaded from {} has sha256 {}, expected {}",
+                url,
+                actual,
+                expected
+            );
+        }
+    }
+    tmp.persist(path)?;
+    let conditional = conditional_headers(response);
+    if conditional.is_empty() {
+        let _ = std::fs::remove_file(&validators);
+    } else if let Err(e) = std::fs::write(&validators, conditional) {
+        log::warn!("failed to save {}: {}", validators.display(), e);
+    }
+    Ok(())
+}
+
+/// Splits `url#sha256=<hex>` into the URL to fetch and the checksum.
+fn split_pin(url: &str) -> Result<(&str, Option<String>)> {
+    let Some((base, fragment)) = url.split_once('#') else {
+        return Ok((url, None));
+    };
+    let Some(sum) = fragment.strip_prefix("sha256=") else {
+        return Ok((base, None));
+    };
+    if sum.len() != 64 || !sum.chars().all(|c| c.is_ascii_hexdigit()) {
+        bail!("invalid sha256 in pack URL: {}", sum);
+    }
+    Ok((base, Some(sum.to_ascii_lowercase())))
+}
+
+fn cache_name(url: &str) -> String {
+    format!("{}.poepack", &util::hash_bytes(url.as_bytes())[..16])
+}
+
+/// The last response in a `curl -D` dump; earlier ones belong to
+/// redirects.
+fn last_response(dump: &str) -> &str {
+    dump.trim_end().rsplit("\r\n\r\n").next().unwrap_or("")
+}
+
+fn status_code(response: &str) -> Option<u16> {
+    response
+        .lines()
+        .next()?
+        .split_whitespace()
+        .nth(1)?
+        .parse()
+        .ok()
+}
+
+fn header<'a>(response: &'a str, name: &str) -> Option<&'a str> {
+    response.lines().skip(1).find_map(|line| {
+        let (key, value) = line.split_once(':')?;
+        key.trim().eq_ignore_ascii_case(name).then(|| value.trim())
+    })
+}
+
+/// `If-None-Match` and `If-Modified-Since` lines for the validators the
+/// response carried, one per line.
+fn conditional_headers(response: &str) -> String {
+    [
+        ("ETag", "If-None-Match"),
+        ("Last-Modified", "If-Modified-Since"),
+    ]
+    .iter()
+    .filter_map(|(sent, ask)| Some(format!("{}: {}\n", ask, header(response, sent)?)))
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_pins_and_reads_checksum_headers() {
+        let sum = "ab".repeat(32);
+        let url = format!("https://poe.internal/api/packs/abc/download#sha256={}", sum);
+        let (base, pin) = split_pin(&url).unwrap();
+        assert_eq!(base, "https://poe.internal/api/packs/abc/download");
+        assert_eq!(pin.as_deref(), Some(sum.as_str()));
+        assert!(split_pin("https://x/p#sha256=zz").is_err());
+        assert_eq!(split_pin("https://x/p").unwrap(), ("https://x/p", None));
+
+        let dump = "HTTP/1.1 302 Found\r\nLocation: /p\r\nETag: \"old\"\r\n\r\n\
+                    HTTP/1.1 200 OK\r\nx-poe-sha256: ABCD\r\nETag: \"v2\"\r\n\
+                    Last-Modified: Tue, 13 Oct 2026 08:00:00 GMT\r\n\r\n";
+        let response = last_response(dump);
+        assert_eq!(status_code(response), Some(200));
+        assert_eq!(header(response, CHECKSUM_HEADER), Some("ABCD"));
+        assert_eq!(
+            conditional_headers(response),
+            "If-None-Match: \"v2\"\nIf-Modified-Since: Tue, 13 Oct 2026 08:00:00 GMT\n"
+        );
+        let not_modified = last_response("HTTP/1.1 304 Not Modified\r\n\r\n");
+        assert_eq!(status_code(not_modified), Some(304));
+        assert_eq!(header(not_modified, CHECKSUM_HEADER), None);
+        assert_eq!(conditional_headers(not_modified), "");
+
+        assert_eq!(
+            resolve(Path::new("run.poepack")).unwrap(),
+            PathBuf::from("run.poepack")
+        );
+    }
+}
@@ -1,12 +1,16 @@
+use std::collections::BTreeMap;
+
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 use crate::events::types::*;
+use crate::redact::secrets::SecretFinding;
 use crate::trace::db::TraceDb;
 use crate::util;
 
 use anyhow::Result;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct PackSummary {
     pub version: String,
     pub run_id: String,
@@ -22,16 +26,27 @@ pub struct PackSummary {
     pub duration_ms: u64,
     pub failure: Option<FailureSummary>,
     pub stats: StatsSummary,
+    #[serde(default)]
+    pub truncation: Option<TruncationSummary>,
+    /// Labels from `poe run --tag key=value`.
+    #[serde(default)]
+    pub tags: BTreeMap<String, String>,
+    /// poe's own overhead while capturing.
+    #[serde(default)]
+    pub capture_metrics: Option<CaptureMetrics>,
+    /// Likely credentials in the captured stdio, environment or file paths.
+    #[serde(default)]
+    pub secrets: Vec<SecretFinding>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct FailureSummary {
     pub kind: String,
     pub description: String,
     pub primary_pid: Option<i32>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct StatsSummary {
     pub process_count: i64,
     pub event_count: i64,
@@ -42,6 +57,44 @@ pub struct StatsSummary {
     pub stderr_bytes: u64,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct TruncationSummary {
+    pub budget_bytes: u64,
+    pub original_bytes: u64,
+    pub within_budget: bool,
+    pub steps: Vec<TruncationStep>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct TruncationStep {
+    pub stage: String,
+    pub rows_dropped: u64,
+    pub bytes_dropped: u64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct CaptureMetrics {
+    /// Events stored by the db writer.
+    pub events: u64,
+    pub events_per_sec: f64,
+    /// Most events waiting in the writer's queue at once.
+    pub backlog_peak: u64,
+    pub db_batches: u64,
+    pub db_write_ms: u64,
+    pub db_write_max_ms: u64,
+    /// Events lost because the trace db failed to store them.
+    pub dropped_events: u64,
+    /// Events left out while the program had capture turned off.
+    #[serde(default)]
+    pub paused_events: u64,
+    /// CPU time of the thread running the tracer's event loop.
+    pub tracer_cpu_ms: u64,
+    /// CPU time of poe as a whole (tracer, db writer, stdio relays).
+    pub poe_cpu_ms: u64,
+    /// CPU time of the traced processes that were waited for.
+    pub workload_cpu_ms: u64,
+}
+
 #[allow(clippy::too_many_arguments)]
 pub fn generate_summary(
     db: &TraceDb,
@@ -76,6 +129,27 @@ pub fn generate_summary(
                 primary_pid: None,
             })
         }
+        Some(TriggerReason::ChildFailure(pid)) => {
+            let proc = db.query_processes()?.into_iter().find(|p| p.proc_id == pid);
+            let command = proc
+                .as_ref()
+                .and_then(|p| p.argv.as_deref())
+                .and_then(|a| serde_json::from_str::<Vec<String>>(a).ok())
+                .map(|argv| argv.join(" "))
+                .unwrap_or_default();
+            let how = match proc.as_ref().and_then(|p| p.signal) {
+                Some(sig) => format!("was killed by {}", util::signal_name(sig)),
+                None => format!(
+                    "exited with code {}",
+                    proc.and_then(|p| p.exit_code).unwrap_or(-1)
+                ),
+            };
+            Some(FailureSummary {
+                kind: "child_failure".into(),
+                description: format!("Child process {} ({}) {}", pid, command, how),
+                primary_pid: Some(pid),
+            })
+        }
         Some(TriggerReason::NonZeroExit) => Some(FailureSummary {
             kind: "non_zero_exit".into(),
             description: format!("Process exited with code {}", exit_code.unwrap_or(-1)),
@@ -120,5 +194,9 @@ pub fn generate_summary(
         duration_ms,
         failure,
         stats,
+        truncation: None,
+        tags: run_info.tags.clone(),
+        capture_metrics: None,
+        secrets: Vec::new(),
     })
 }
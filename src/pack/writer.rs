@@ -1,3 +1,4 @@
+use std::collections::{HashMap, HashSet};
 use std::fs::{self, File};
 use std::io::Write;
 use std::path::Path;
@@ -7,8 +8,11 @@ use zip::write::SimpleFileOptions;
 use zip::ZipWriter;
 
 use crate::events::types::*;
-use crate::pack::summary;
-use crate::trace::db::TraceDb;
+use crate::pack::budget::{self, TruncationStage};
+use crate::pack::summary::{self, CaptureMetrics, PackSummary, TruncationSummary};
+use crate::redact::secrets::{SecretFinding, SecretScanner};
+use crate::redact::Redactor;
+use crate::trace::db::{Page, TraceDb};
 use crate::util::ringbuf::ByteRing;
 
 #[allow(clippy::too_many_arguments)]
@@ -22,6 +26,125 @@ pub fn write_pack(
     duration_ms: u64,
     stdout_ring: &ByteRing,
     stderr_ring: &ByteRing,
+    stdin_data: Option<&[u8]>,
+    poe_log: Option<&[u8]>,
+    capture_metrics: Option<&CaptureMetrics>,
+    max_pack_size: Option<u64>,
+    redactor: &Redactor,
+) -> Result<()> {
+    let env = environment(redactor);
+    let meta_json = build_meta_json(run_info, db, &env)?;
+    let mut stdout_data = stdout_ring.contents();
+    let mut stderr_data = stderr_ring.contents();
+    let secrets = scan_secrets(db, &stdout_data, &stderr_data, &env)?;
+    if !secrets.is_empty() {
+        log::warn!(
+            "{} likely secret{} in the captured data (see `poe explain`)",
+            secrets.len(),
+            if secrets.len() == 1 { "" } else { "s" }
+        );
+    }
+
+    let summarize = || {
+        let mut summary = summary::generate_summary(
+            db,
+            run_info,
+            exit_code,
+            signal,
+            trigger,
+            duration_ms,
+            stdout_ring.total_written(),
+            stderr_ring.total_written(),
+        )?;
+        summary.capture_metrics = capture_metrics.cloned();
+        summary.secrets = secrets.clone();
+        anyhow::Ok(summary)
+    };
+
+    let pack_summary = summarize()?;
+    write_archive(
+        output_path,
+        db,
+        &pack_summary,
+        &stdout_data,
+        &stderr_data,
+        stdin_data,
+        poe_log,
+        &meta_json,
+    )?;
+
+    let Some(budget) = max_pack_size else {
+        return Ok(());
+    };
+
+    let mut size = fs::metadata(output_path)?.len();
+    if size <= budget {
+        return Ok(());
+    }
+
+    let mut truncation = TruncationSummary {
+        budget_bytes: budget,
+        original_bytes: size,
+        within_budget: false,
+        steps: Vec::new(),
+    };
+
+    for stage in TruncationStage::ORDER {
+        let step = budget::apply_stage(stage, db, &mut stdout_data, &mut stderr_data)?;
+        truncation.steps.push(step);
+
+        let mut pack_summary = summarize()?;
+        pack_summary.truncation = Some(truncation.clone());
+        write_archive(
+            output_path,
+            db,
+            &pack_summary,
+            &stdout_data,
+            &stderr_data,
+            stdin_data,
+            poe_log,
+            &meta_json,
+        )?;
+
+        size = fs::metadata(output_path)?.len();
+        if size <= budget {
+            break;
+        }
+    }
+
+    truncation.within_budget = size <= budget;
+    if !truncation.within_budget {
+        log::warn!(
+            "pack is {} bytes after truncation, still over the {} byte budget",
+            size,
+            budget
+        );
+    }
+
+    let mut pack_summary = summarize()?;
+    pack_summary.truncation = Some(truncation);
+    write_archive(
+        output_path,
+        db,
+        &pack_summary,
+        &stdout_data,
+        &stderr_data,
+        stdin_data,
+        poe_log,
+        &meta_json,
+    )
+}
+
+#[allow(clippy::too_many_arguments)]
+fn write_archive(
+    output_path: &Path,
+    db: &TraceDb,
+    pack_summary: &PackSummary,
+    stdout_data: &[u8],
+    stderr_data: &[u8],
+    stdin_data: Option<&[u8]>,
+    poe_log: Option<&[u8]>,
+    meta_json: &str,
 ) -> Result<()> {
     let file = File::create(output_path)
         .with_context(|| format!("failed to create pack file: {}", output_path.display()))?;
@@ -29,18 +152,7 @@ pub fn write_pack(
     let mut zip = ZipWriter::new(file);
     let options = SimpleFileOptions::default().compression_method(zip::CompressionMethod::Deflated);
 
-    let pack_summary = summary::generate_summary(
-        db,
-        run_info,
-        exit_code,
-        signal,
-        trigger,
-        duration_ms,
-        stdout_ring.total_written(),
-        stderr_ring.total_written(),
-    )?;
-
-    let summary_json = serde_json::to_string_pretty(&pack_summary)?;
+    let summary_json = serde_json::to_string_pretty(pack_summary)?;
     zip.start_file("summary.json", options)?;
     zip.write_all(summary_json.as_bytes())?;
 
@@ -52,23 +164,84 @@ pub fn write_pack(
         zip.write_all(&db_bytes)?;
     }
 
-    let stdout_data = stdout_ring.contents();
     if !stdout_data.is_empty() {
         zip.start_file("artifacts/stdout.log", options)?;
-        zip.write_all(&stdout_data)?;
+        zip.write_all(stdout_data)?;
     }
 
-    let stderr_data = stderr_ring.contents();
     if !stderr_data.is_empty() {
         zip.start_file("artifacts/stderr.log", options)?;
-        zip.write_all(&stderr_data)?;
+        zip.write_all(stderr_data)?;
+    }
+
+    // Written even when empty so replay can tell "no input" from "not captured".
+    if let Some(stdin_data) = stdin_data {
+        zip.start_file("artifacts/stdin.log", options)?;
+        zip.write_all(stdin_data)?;
     }
 
-    let env: std::collections::HashMap<String, String> = std::env::vars().collect();
-    let redactor = crate::redact::Redactor::new();
-    let redacted_env = redactor.redact_env(&env);
+    zip.start_file("meta/environment.json", options)?;
+    zip.write_all(meta_json.as_bytes())?;
+
+    // poe's own diagnostics for the run, kept out of the command's stderr.
+    if let Some(poe_log) = poe_log.filter(|l| !l.is_empty()) {
+        zip.start_file("meta/poe.log", options)?;
+        zip.write_all(poe_log)?;
+    }
+
+    zip.finish()?;
+
+    Ok(())
+}
 
-    let trace_ctx = crate::distributed::trace_context::TraceContext::from_env_or_new();
+/// The environment as stored in the pack, with sensitive variables redacted.
+fn environment(redactor: &Redactor) -> HashMap<String, String> {
+    let env: HashMap<String, String> = std::env::vars().collect();
+    redactor.redact_env(&env)
+}
+
+/// Looks for credentials in what the pack is about to store.
+fn scan_secrets(
+    db: &TraceDb,
+    stdout_data: &[u8],
+    stderr_data: &[u8],
+    env: &HashMap<String, String>,
+) -> Result<Vec<SecretFinding>> {
+    let mut scanner = SecretScanner::new();
+    scanner.scan("stdout", &String::from_utf8_lossy(stdout_data));
+    scanner.scan("stderr", &String::from_utf8_lossy(stderr_data));
+    let mut names: Vec<&String> = env.keys().collect();
+    names.sort();
+    for name in names {
+        scanner.scan(&format!("env:{}", name), &env[name]);
+    }
+    let mut paths = HashSet::new();
+    db.each_file_event(Page::new(None, 0), |f| {
+        if let Some(path) = f.path {
+            paths.insert(path);
+        }
+        Ok(())
+    })?;
+    for path in &paths {
+        scanner.scan("file_path", path);
+    }
+    Ok(scanner.finish())
+}
+
+fn build_meta_json(
+    run_info: &RunInfo,
+    db: &TraceDb,
+    redacted_env: &HashMap<String, String>,
+) -> Result<String> {
+    let trace_ctx = run_info
+        .trace_context
+        .clone()
+        .unwrap_or_else(crate::distributed::trace_context::TraceContext::from_env_or_new);
+
+    let mounts = crate::pack::mounts::snapshot(db).unwrap_or_else(|e| {
+        log::warn!("failed to record mounts: {:#}", e);
+        Vec::new()
+    });
 
     let meta = serde_json::json!({
         "run_id": run_info.run_id,
@@ -78,6 +251,9 @@ pub fn write_pack(
         "kernel": get_kernel_version(),
         "arch": std::env::consts::ARCH,
         "environment": redacted_env,
+        "clock": crate::distributed::clock::ClockStatus::read(),
+        "mounts": mounts,
+        "sandbox": crate::util::sandbox::Sandbox::read(),
         "trace_context": {
             "trace_id": trace_ctx.trace_id,
             "span_id": trace_ctx.span_id,
@@ -86,13 +262,7 @@ pub fn write_pack(
         },
     });
 
-    let meta_json = serde_json::to_string_pretty(&meta)?;
-    zip.start_file("meta/environment.json", options)?;
-    zip.write_all(meta_json.as_bytes())?;
-
-    zip.finish()?;
-
-    Ok(())
+    Ok(serde_json::to_string_pretty(&meta)?)
 }
 
 fn get_kernel_version() -> String {
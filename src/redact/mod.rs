@@ -1,3 +1,6 @@
 pub mod patterns;
+pub mod secrets;
+pub mod stream;
 
 pub use patterns::Redactor;
+pub use stream::StreamRedactor;
@@ -1,5 +1,8 @@
 use std::collections::HashSet;
 
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
 const SENSITIVE_ENV_KEYS: &[&str] = &[
     "AWS_ACCESS_KEY_ID",
     "AWS_SECRET_ACCESS_KEY",
@@ -51,10 +54,17 @@ const SENSITIVE_SUBSTRINGS: &[&str] = &[
     "auth",
 ];
 
+/// Hex digits of the HMAC kept in a pseudonym.
+const PSEUDONYM_LEN: usize = 12;
+
+#[derive(Clone)]
 pub struct Redactor {
     sensitive_keys: HashSet<String>,
     allowlist: HashSet<String>,
     denylist: HashSet<String>,
+    /// When set, redacted values become `[REDACTED:<hmac>]` pseudonyms
+    /// instead of `[REDACTED]`.
+    key: Option<Vec<u8>>,
 }
 
 impl Redactor {
@@ -68,9 +78,38 @@ impl Redactor {
             sensitive_keys,
             allowlist: HashSet::new(),
             denylist: HashSet::new(),
+            key: None,
         }
     }
 
+    /// A redactor that replaces each value with a pseudonym derived from it
+    /// with HMAC-SHA256 under `key`: the same value gets the same pseudonym
+    /// in every pack redacted with the same key, so diffs and correlation
+    /// still work without revealing it.
+    pub fn with_key(key: &[u8]) -> Self {
+        Self {
+            key: Some(key.to_vec()),
+            ..Self::new()
+        }
+    }
+
+    /// What `value` is replaced with.
+    pub fn replacement(&self, value: &str) -> String {
+        let Some(ref key) = self.key else {
+            return "[REDACTED]".to_string();
+        };
+        let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC takes any key length");
+        mac.update(value.as_bytes());
+        let digest = mac.finalize().into_bytes();
+        let hex: String = digest.iter().map(|b| format!("{:02x}", b)).collect();
+        format!("[REDACTED:{}]", &hex[..PSEUDONYM_LEN])
+    }
+
+    /// True for a value already replaced by [`replacement`](Self::replacement).
+    pub fn is_replacement(value: &str) -> bool {
+        value.starts_with("[REDACTED") && value.ends_with(']')
+    }
+
     pub fn add_allowlist(&mut self, key: &str) {
         self.allowlist.insert(key.to_uppercase());
     }
@@ -111,7 +150,7 @@ impl Redactor {
         env.iter()
             .map(|(k, v)| {
                 if self.should_redact_env_key(k) {
-                    (k.clone(), "[REDACTED]".to_string())
+                    (k.clone(), self.replacement(v))
                 } else {
                     (k.clone(), v.clone())
                 }
@@ -119,6 +158,23 @@ impl Redactor {
             .collect()
     }
 
+    /// Redacts variables captured from the program, such as Python locals:
+    /// sensitive names are hidden outright, other values have tokens removed.
+    pub fn redact_vars(
+        &self,
+        vars: &std::collections::HashMap<String, String>,
+    ) -> std::collections::HashMap<String, String> {
+        vars.iter()
+            .map(|(k, v)| {
+                if self.should_redact_env_key(k) {
+                    (k.clone(), self.replacement(v))
+                } else {
+                    (k.clone(), self.redact_string(v))
+                }
+            })
+            .collect()
+    }
+
     pub fn redact_string(&self, s: &str) -> String {
         let mut result = s.to_string();
 
@@ -133,8 +189,9 @@ impl Redactor {
                     .map(|p| token_start + p)
                     .unwrap_or(result.len());
                 if token_end > token_start {
-                    result.replace_range(token_start..token_end, "[REDACTED]");
-                    search_from = token_start + "[REDACTED]".len();
+                    let replacement = self.replacement(&result[token_start..token_end]);
+                    result.replace_range(token_start..token_end, &replacement);
+                    search_from = token_start + replacement.len();
                 } else {
                     search_from = token_start;
                 }
@@ -173,6 +230,38 @@ mod tests {
         assert!(!r.should_redact_env_key("AWS_SECRET_ACCESS_KEY"));
     }
 
+    #[test]
+    fn test_vars_redaction() {
+        let r = Redactor::new();
+        let vars = std::collections::HashMap::from([
+            ("db_password".to_string(), "'hunter2'".to_string()),
+            (
+                "headers".to_string(),
+                "{'Authorization': 'Bearer abc'}".to_string(),
+            ),
+            ("count".to_string(), "3".to_string()),
+        ]);
+        let out = r.redact_vars(&vars);
+        assert_eq!(out["db_password"], "[REDACTED]");
+        assert!(!out["headers"].contains("abc"));
+        assert_eq!(out["count"], "3");
+    }
+
+    #[test]
+    fn test_keyed_pseudonyms() {
+        let r = Redactor::with_key(b"team key");
+        let a = r.redact_string("Authorization: Bearer sk-abc123");
+        assert_eq!(a, r.redact_string("Authorization: Bearer sk-abc123"));
+        assert_ne!(a, r.redact_string("Authorization: Bearer sk-other"));
+        assert_ne!(
+            a,
+            Redactor::with_key(b"other key").redact_string("Authorization: Bearer sk-abc123")
+        );
+        assert!(!a.contains("sk-abc123"));
+        assert!(a.starts_with("Authorization: Bearer [REDACTED:"));
+        assert_eq!(Redactor::new().replacement("x"), "[REDACTED]");
+    }
+
     #[test]
     fn test_bearer_redaction() {
         let r = Redactor::new();
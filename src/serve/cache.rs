@@ -0,0 +1,77 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::explain::analyzer;
+use crate::pack::reader::PackReader;
+
+/// Explain output saved next to a pack as `<pack>.explain.json`. It is
+/// reused while the pack's content hash matches and the server is the
+/// same version, since a newer analyzer may find more.
+#[derive(Serialize, Deserialize)]
+struct CachedExplain {
+    sha256: String,
+    version: String,
+    explain: serde_json::Value,
+}
+
+/// The pack's explain JSON, from the cache unless `refresh` is set or the
+/// cache is stale.
+pub fn explain(pack_path: &Path, sha256: &str, refresh: bool) -> Result<serde_json::Value> {
+    let path = cache_path(pack_path);
+    if !refresh {
+        if let Some(explain) = load(&path, sha256) {
+            return Ok(explain);
+        }
+    }
+    let explain = serde_json::to_value(analyzer::analyze(&PackReader::open(pack_path)?)?)?;
+    let cached = CachedExplain {
+        sha256: sha256.to_string(),
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        explain,
+    };
+    // A cache that can't be written only costs the next request time.
+    if let Err(e) = std::fs::write(&path, serde_json::to_vec(&cached)?) {
+        log::warn!(
+            "failed to cache explain output at {}: {}",
+            path.display(),
+            e
+        );
+    }
+    Ok(cached.explain)
+}
+
+pub fn cache_path(pack_path: &Path) -> PathBuf {
+    pack_path.with_extension("explain.json")
+}
+
+fn load(path: &Path, sha256: &str) -> Option<serde_json::Value> {
+    let cached: CachedExplain = serde_json::from_slice(&std::fs::read(path).ok()?).ok()?;
+    (cached.sha256 == sha256 && cached.version == env!("CARGO_PKG_VERSION"))
+        .then_some(cached.explain)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cache_is_used_only_for_the_same_pack_contents() {
+        let dir = tempfile::tempdir().unwrap();
+        let pack = dir.path().join("poe-1234.poepack");
+        let cached = CachedExplain {
+            sha256: "aaaa".into(),
+            version: env!("CARGO_PKG_VERSION").into(),
+            explain: serde_json::json!({"summary": "cached"}),
+        };
+        std::fs::write(cache_path(&pack), serde_json::to_vec(&cached).unwrap()).unwrap();
+
+        let hit = explain(&pack, "aaaa", false).unwrap();
+        assert_eq!(hit["summary"], "cached");
+        // A different hash means the pack was replaced: re-analyze, which
+        // fails here as there is no pack file.
+        assert!(explain(&pack, "bbbb", false).is_err());
+        assert!(explain(&pack, "aaaa", true).is_err());
+    }
+}
@@ -0,0 +1,483 @@
+use std::convert::Infallible;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use http_body_util::combinators::BoxBody;
+use http_body_util::{BodyExt, Full};
+use hyper::body::{Body, Bytes, Frame, Incoming};
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::{Response, StatusCode};
+use hyper_util::rt::{TokioIo, TokioTimer};
+use sha2::{Digest, Sha256};
+use tokio::io::{AsyncRead, AsyncWriteExt, ReadBuf};
+use tokio::net::TcpListener;
+use tokio::sync::Semaphore;
+use tokio_rustls::TlsAcceptor;
+
+use crate::serve::live::LiveTraces;
+use crate::serve::metrics::{self, Metrics};
+use crate::serve::multipart::FilePart;
+use crate::serve::server::{self, PackStore, Reply};
+use crate::serve::tls;
+
+/// Requests allowed to wait per concurrency slot before the server answers
+/// 503.
+const QUEUE_PER_SLOT: usize = 4;
+const FILE_CHUNK: usize = 256 * 1024;
+/// Bodies other than pack uploads are small JSON documents.
+const MAX_API_BODY: u64 = 1024 * 1024;
+
+/// How much of the server one client can take up.
+#[derive(Debug, Clone, Copy)]
+pub struct Limits {
+    /// Largest pack upload accepted, in bytes.
+    pub max_body: u64,
+    /// Requests handled at once, uploads included. Up to `QUEUE_PER_SLOT`
+    /// times as many more wait their turn; beyond that, requests are
+    /// turned away with 503.
+    pub max_concurrent: usize,
+    /// How long a client may take to send request headers, or go quiet in
+    /// the middle of a body, before the request is dropped.
+    pub idle_timeout: Duration,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_body: 1024 * 1024 * 1024,
+            max_concurrent: 16,
+            idle_timeout: Duration::from_secs(60),
+        }
+    }
+}
+
+/// The routes match on these rather than on hyper's method type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Method {
+    Get,
+    Post,
+    Put,
+    Delete,
+    Other,
+}
+
+impl From<&hyper::Method> for Method {
+    fn from(method: &hyper::Method) -> Self {
+        match *method {
+            hyper::Method::GET | hyper::Method::HEAD => Method::Get,
+            hyper::Method::POST => Method::Post,
+            hyper::Method::PUT => Method::Put,
+            hyper::Method::DELETE => Method::Delete,
+            _ => Method::Other,
+        }
+    }
+}
+
+/// A request as the handlers see it, body already read.
+pub struct ApiRequest {
+    pub method: Method,
+    /// Path and query string.
+    pub url: String,
+    pub body: Vec<u8>,
+    /// A pack upload, which goes to disk instead of `body`.
+    pub upload: Option<Upload>,
+}
+
+/// An uploaded pack streamed to a temp file in the store, not yet checked.
+pub struct Upload {
+    pub path: PathBuf,
+    pub sha256: String,
+    pub size: u64,
+}
+
+pub struct Shared {
+    pub store: Mutex<PackStore>,
+    pub live: Mutex<LiveTraces>,
+    pub metrics: Metrics,
+    pub limits: Limits,
+    permits: Semaphore,
+    /// Requests running or waiting for a permit.
+    admitted: AtomicUsize,
+}
+
+impl Shared {
+    pub fn new(store: PackStore, limits: Limits) -> Self {
+        Self {
+            store: Mutex::new(store),
+            live: Mutex::new(LiveTraces::default()),
+            metrics: Metrics::default(),
+            limits,
+            permits: Semaphore::new(limits.max_concurrent),
+            admitted: AtomicUsize::new(0),
+        }
+    }
+}
+
+type ReplyBody = BoxBody<Bytes, io::Error>;
+
+/// Serves the API on `listener` until the process exits; the runtime's
+/// threads only move bytes, handlers run on its blocking pool.
+pub fn run(
+    listener: std::net::TcpListener,
+    tls: Option<TlsAcceptor>,
+    redirect: Option<std::net::TcpListener>,
+    shared: Arc<Shared>,
+) -> Result<()> {
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()?;
+    runtime.block_on(async move {
+        let listener = TcpListener::from_std(listener)?;
+        if let Some(redirect) = redirect {
+            let https_port = listener.local_addr()?.port();
+            let redirect = TcpListener::from_std(redirect)?;
+            tokio::spawn(tls::redirect(
+                redirect,
+                https_port,
+                shared.limits.idle_timeout,
+            ));
+        }
+        loop {
+            let stream = match listener.accept().await {
+                Ok((stream, _)) => stream,
+                Err(e) => {
+                    // Out of fds, usually; give connections time to close.
+                    log::warn!("accept failed: {}", e);
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                    continue;
+                }
+            };
+            let shared = Arc::clone(&shared);
+            let tls = tls.clone();
+            tokio::spawn(async move {
+                let timeout = shared.limits.idle_timeout;
+                let mut conn = http1::Builder::new();
+                conn.timer(TokioTimer::new()).header_read_timeout(timeout);
+                let service = service_fn(move |req| respond(req, Arc::clone(&shared)));
+                match tls {
+                    Some(acceptor) => {
+                        if let Ok(Ok(stream)) =
+                            tokio::time::timeout(timeout, acceptor.accept(stream)).await
+                        {
+                            let _ = conn.serve_connection(TokioIo::new(stream), service).await;
+                        }
+                    }
+                    None => {
+                        let _ = conn.serve_connection(TokioIo::new(stream), service).await;
+                    }
+                }
+            });
+        }
+    })
+}
+
+async fn respond(
+    req: hyper::Request<Incoming>,
+    shared: Arc<Shared>,
+) -> Result<Response<ReplyBody>, Infallible> {
+    let method = Method::from(req.method());
+    let url = req
+        .uri()
+        .path_and_query()
+        .map(|p| p.as_str().to_string())
+        .unwrap_or_else(|| "/".into());
+    let endpoint = metrics::endpoint(&method, url.split('?').next().unwrap_or(""));
+    let started = Instant::now();
+    let limits = shared.limits;
+
+    let admitted = Admitted::new(&shared.admitted);
+    if admitted.count > limits.max_concurrent * (1 + QUEUE_PER_SLOT) {
+        shared.metrics.observe(endpoint, 503, started.elapsed());
+        let mut response = error(503, "server busy, retry later");
+        response
+            .headers_mut()
+            .insert("Retry-After", hyper::header::HeaderValue::from_static("5"));
+        return Ok(response);
+    }
+    let Ok(_permit) = shared.permits.acquire().await else {
+        return Ok(error(503, "server shutting down"));
+    };
+
+    let received = if endpoint == "upload" {
+        let dir = shared.store.lock().unwrap().dir().to_path_buf();
+        receive_upload(req, &dir, &limits)
+            .await
+            .map(|upload| (Vec::new(), Some(upload)))
+    } else {
+        read_body(req, &limits).await.map(|body| (body, None))
+    };
+    let (body, upload) = match received {
+        Ok(received) => received,
+        Err((status, message)) => {
+            shared.metrics.observe(endpoint, status, started.elapsed());
+            return Ok(error(status, &message));
+        }
+    };
+    let request = ApiRequest {
+        method,
+        url,
+        body,
+        upload,
+    };
+    let handler = Arc::clone(&shared);
+    let reply = tokio::task::spawn_blocking(move || server::handle_request(request, &handler))
+        .await
+        .unwrap_or_else(|e| Err(anyhow::anyhow!("handler panicked: {}", e)));
+
+    let response = match reply {
+        Ok(reply) => {
+            let response = into_response(reply).await;
+            shared
+                .metrics
+                .observe(endpoint, response.status().as_u16(), started.elapsed());
+            response
+        }
+        Err(e) => {
+            shared.metrics.failed(endpoint, started.elapsed());
+            log::error!("request error: {:#}", e);
+            error(500, &format!("{:#}", e))
+        }
+    };
+    Ok(response)
+}
+
+type Refusal = (u16, String);
+
+/// Reads a small body into memory.
+async fn read_body(
+    req: hyper::Request<Incoming>,
+    limits: &Limits,
+) -> std::result::Result<Vec<u8>, Refusal> {
+    let max = limits.max_body.min(MAX_API_BODY);
+    check_length(&req, max)?;
+    let mut incoming = req.into_body();
+    let mut body = Vec::new();
+    while let Some(chunk) = next_chunk(&mut incoming, limits).await? {
+        if (body.len() + chunk.len()) as u64 > max {
+            return Err(too_large(max));
+        }
+        body.extend_from_slice(&chunk);
+    }
+    Ok(body)
+}
+
+/// Streams a pack upload, raw or as the file part of a multipart form, to
+/// a temp file in `dir`, hashing it on the way.
+async fn receive_upload(
+    req: hyper::Request<Incoming>,
+    dir: &Path,
+    limits: &Limits,
+) -> std::result::Result<Upload, Refusal> {
+    check_length(&req, limits.max_body)?;
+    let mut part = req
+        .headers()
+        .get(hyper::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(FilePart::boundary)
+        .map(FilePart::new);
+    let path = dir.join(format!("temp-{}.upload", uuid::Uuid::new_v4()));
+    let failed = |e: io::Error| (500, format!("failed to write the upload: {}", e));
+
+    let received = async {
+        let mut file = tokio::fs::File::create(&path).await.map_err(failed)?;
+        let mut incoming = req.into_body();
+        let mut hasher = Sha256::new();
+        let mut received = 0u64;
+        let mut size = 0u64;
+        let mut extracted = Vec::new();
+        while let Some(chunk) = next_chunk(&mut incoming, limits).await? {
+            received += chunk.len() as u64;
+            if received > limits.max_body {
+                return Err(too_large(limits.max_body));
+            }
+            let data = match part.as_mut() {
+                Some(part) => {
+                    extracted.clear();
+                    part.feed(&chunk, &mut extracted)
+                        .map_err(|e| (400, e.to_string()))?;
+                    &extracted[..]
+                }
+                None => &chunk[..],
+            };
+            hasher.update(data);
+            size += data.len() as u64;
+            file.write_all(data).await.map_err(failed)?;
+        }
+        if part.as_ref().is_some_and(|p| !p.complete()) {
+            return Err((400, "multipart upload has no file part".into()));
+        }
+        file.flush().await.map_err(failed)?;
+        Ok(Upload {
+            path: path.clone(),
+            sha256: format!("{:x}", hasher.finalize()),
+            size,
+        })
+    }
+    .await;
+    if received.is_err() {
+        let _ = tokio::fs::remove_file(&path).await;
+    }
+    received
+}
+
+fn check_length(req: &hyper::Request<Incoming>, max: u64) -> std::result::Result<(), Refusal> {
+    let declared = req
+        .headers()
+        .get(hyper::header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok()?.parse::<u64>().ok());
+    match declared {
+        Some(n) if n > max => Err(too_large(max)),
+        _ => Ok(()),
+    }
+}
+
+/// The next piece of the body, giving up when the client stalls for
+/// `idle_timeout`.
+async fn next_chunk(
+    incoming: &mut Incoming,
+    limits: &Limits,
+) -> std::result::Result<Option<Bytes>, Refusal> {
+    loop {
+        let frame = match tokio::time::timeout(limits.idle_timeout, incoming.frame()).await {
+            Err(_) => return Err((408, "timed out reading the request body".into())),
+            Ok(None) => return Ok(None),
+            Ok(Some(Err(e))) => return Err((400, format!("failed to read the body: {}", e))),
+            Ok(Some(Ok(frame))) => frame,
+        };
+        // Trailers carry nothing the API uses.
+        if let Ok(chunk) = frame.into_data() {
+            return Ok(Some(chunk));
+        }
+    }
+}
+
+fn too_large(max: u64) -> Refusal {
+    (413, format!("request body exceeds the {} byte limit", max))
+}
+
+async fn into_response(reply: Reply) -> Response<ReplyBody> {
+    match reply {
+        Reply::Text {
+            status,
+            content_type,
+            body,
+            headers,
+        } => {
+            let mut builder = Response::builder()
+                .status(status)
+                .header("Content-Type", content_type);
+            for (name, value) in headers {
+                builder = builder.header(name, value);
+            }
+            builder
+                .body(full(body))
+                .unwrap_or_else(|_| error(500, "invalid response header"))
+        }
+        Reply::File { path, headers } => {
+            let file = match tokio::fs::File::open(&path).await {
+                Ok(file) => file,
+                Err(e) => return error(500, &format!("failed to open {}: {}", path.display(), e)),
+            };
+            let len = file.metadata().await.map(|m| m.len()).unwrap_or(0);
+            let mut builder = Response::builder()
+                .header("Content-Type", "application/octet-stream")
+                .header("Content-Length", len);
+            for (name, value) in headers {
+                builder = builder.header(name, value);
+            }
+            builder
+                .body(BoxBody::new(FileBody { file }))
+                .unwrap_or_else(|_| error(500, "invalid response header"))
+        }
+    }
+}
+
+fn full(body: String) -> ReplyBody {
+    BoxBody::new(Full::new(Bytes::from(body)).map_err(|never| match never {}))
+}
+
+fn error(status: u16, message: &str) -> Response<ReplyBody> {
+    let mut response = Response::new(full(serde_json::json!({ "error": message }).to_string()));
+    *response.status_mut() =
+        StatusCode::from_u16(status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+    response.headers_mut().insert(
+        hyper::header::CONTENT_TYPE,
+        hyper::header::HeaderValue::from_static("application/json"),
+    );
+    response
+}
+
+/// Counts a request as admitted for as long as it's alive.
+struct Admitted<'a> {
+    counter: &'a AtomicUsize,
+    /// Admitted requests including this one.
+    count: usize,
+}
+
+impl<'a> Admitted<'a> {
+    fn new(counter: &'a AtomicUsize) -> Self {
+        let count = counter.fetch_add(1, Ordering::SeqCst) + 1;
+        Self { counter, count }
+    }
+}
+
+impl Drop for Admitted<'_> {
+    fn drop(&mut self) {
+        self.counter.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Streams a pack file out in chunks rather than loading it whole.
+struct FileBody {
+    file: tokio::fs::File,
+}
+
+impl Body for FileBody {
+    type Data = Bytes;
+    type Error = io::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<std::result::Result<Frame<Bytes>, io::Error>>> {
+        let mut chunk = vec![0u8; FILE_CHUNK];
+        let mut buf = ReadBuf::new(&mut chunk);
+        match Pin::new(&mut self.file).poll_read(cx, &mut buf) {
+            Poll::Pending => Poll::Pending,
+            Poll::Ready(Err(e)) => Poll::Ready(Some(Err(e))),
+            Poll::Ready(Ok(())) => {
+                let n = buf.filled().len();
+                if n == 0 {
+                    return Poll::Ready(None);
+                }
+                chunk.truncate(n);
+                Poll::Ready(Some(Ok(Frame::data(Bytes::from(chunk)))))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn admitted_requests_are_counted_while_alive() {
+        let counter = AtomicUsize::new(0);
+        let first = Admitted::new(&counter);
+        {
+            let second = Admitted::new(&counter);
+            assert_eq!((first.count, second.count), (1, 2));
+        }
+        assert_eq!(Admitted::new(&counter).count, 2);
+        drop(first);
+        assert_eq!(counter.load(Ordering::SeqCst), 0);
+    }
+}
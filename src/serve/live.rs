@@ -0,0 +1,184 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+use crate::distributed::clock;
+use crate::distributed::live::{SpanReport, SpanStatus};
+use crate::distributed::trace_context::TraceSpan;
+
+/// Traces nobody has reported to for this long are dropped.
+const IDLE_EXPIRY: Duration = Duration::from_secs(3600);
+const MAX_SPANS: usize = 10_000;
+
+/// Spans reported by `poe run --stream` sessions, by trace id. Kept in
+/// memory only: a finished trace lives on in its uploaded packs.
+#[derive(Default)]
+pub struct LiveTraces {
+    traces: HashMap<String, LiveTrace>,
+}
+
+struct LiveTrace {
+    spans: Vec<SpanReport>,
+    updated: Instant,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LiveView {
+    pub trace_id: String,
+    pub running: usize,
+    pub done: usize,
+    /// Roots first, each followed by its children, on the aligned
+    /// timeline.
+    pub spans: Vec<LiveSpan>,
+    pub clock_warnings: Vec<String>,
+    /// Seconds since the last report.
+    pub idle_secs: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LiveSpan {
+    #[serde(flatten)]
+    pub span: TraceSpan,
+    pub status: SpanStatus,
+    /// The run's pack has been uploaded; its id is `run_id`.
+    pub stored: bool,
+}
+
+impl LiveTraces {
+    /// Records a span starting or ending; a later report for the same span
+    /// replaces the earlier one.
+    pub fn report(&mut self, trace_id: &str, report: SpanReport) -> Result<(), String> {
+        self.traces.retain(|_, t| t.updated.elapsed() < IDLE_EXPIRY);
+        let trace = self
+            .traces
+            .entry(trace_id.to_string())
+            .or_insert_with(|| LiveTrace {
+                spans: Vec::new(),
+                updated: Instant::now(),
+            });
+        trace.updated = Instant::now();
+        let count = trace.spans.len();
+        match trace
+            .spans
+            .iter_mut()
+            .find(|s| s.span.span_id == report.span.span_id)
+        {
+            // A late start report must not undo the end.
+            Some(known) if known.status == SpanStatus::Done => {}
+            Some(known) => *known = report,
+            None if count >= MAX_SPANS => {
+                return Err(format!("trace has more than {} spans", MAX_SPANS));
+            }
+            None => trace.spans.push(report),
+        }
+        Ok(())
+    }
+
+    pub fn view(&self, trace_id: &str, stored: impl Fn(&str) -> bool) -> Option<LiveView> {
+        let trace = self.traces.get(trace_id)?;
+        let mut spans: Vec<TraceSpan> = trace.spans.iter().map(|r| r.span.clone()).collect();
+        let clock_warnings = clock::align(&mut spans);
+
+        let status: HashMap<&str, SpanStatus> = trace
+            .spans
+            .iter()
+            .map(|r| (r.span.span_id.as_str(), r.status))
+            .collect();
+        let mut ordered: Vec<LiveSpan> = Vec::new();
+        let known = |id: &str| spans.iter().any(|s| s.span_id == id);
+        let mut pending: Vec<&TraceSpan> = spans
+            .iter()
+            .filter(|s| s.parent_span_id.as_deref().is_none_or(|p| !known(p)))
+            .collect();
+        pending.sort_by_key(|s| std::cmp::Reverse(s.timeline_ms));
+        while let Some(span) = pending.pop() {
+            ordered.push(LiveSpan {
+                span: span.clone(),
+                status: status[span.span_id.as_str()],
+                stored: stored(&span.run_id),
+            });
+            let mut children: Vec<&TraceSpan> = spans
+                .iter()
+                .filter(|s| s.parent_span_id.as_deref() == Some(span.span_id.as_str()))
+                .filter(|s| !ordered.iter().any(|o| o.span.span_id == s.span_id))
+                .collect();
+            children.sort_by_key(|s| std::cmp::Reverse(s.timeline_ms));
+            pending.extend(children);
+        }
+
+        Some(LiveView {
+            trace_id: trace_id.to_string(),
+            running: trace
+                .spans
+                .iter()
+                .filter(|r| r.status == SpanStatus::Running)
+                .count(),
+            done: trace
+                .spans
+                .iter()
+                .filter(|r| r.status == SpanStatus::Done)
+                .count(),
+            spans: ordered,
+            clock_warnings,
+            idle_secs: trace.updated.elapsed().as_secs(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report(id: &str, parent: Option<&str>, status: SpanStatus) -> SpanReport {
+        SpanReport {
+            span: TraceSpan {
+                span_id: id.into(),
+                parent_span_id: parent.map(Into::into),
+                run_id: format!("run-{}", id),
+                command: vec![id.into()],
+                hostname: "host".into(),
+                exit_code: None,
+                signal: None,
+                duration_ms: 0,
+                pack_path: None,
+                start_time: "2026-01-01T00:00:00+00:00".into(),
+                clock_offset_ms: 0,
+                clock_error_ms: Some(1),
+                clock_shift_ms: 0,
+                timeline_ms: None,
+            },
+            status,
+        }
+    }
+
+    #[test]
+    fn assembles_spans_as_they_start_and_end() {
+        let mut live = LiveTraces::default();
+        live.report("t", report("child", Some("root"), SpanStatus::Running))
+            .unwrap();
+        live.report("t", report("root", None, SpanStatus::Running))
+            .unwrap();
+        live.report("t", report("child", Some("root"), SpanStatus::Done))
+            .unwrap();
+        // A start report arriving after the end is ignored.
+        live.report("t", report("child", Some("root"), SpanStatus::Running))
+            .unwrap();
+
+        let view = live.view("t", |run| run == "run-child").unwrap();
+        assert_eq!((view.running, view.done), (1, 1));
+        let order: Vec<(&str, SpanStatus, bool)> = view
+            .spans
+            .iter()
+            .map(|s| (s.span.span_id.as_str(), s.status, s.stored))
+            .collect();
+        assert_eq!(
+            order,
+            [
+                ("root", SpanStatus::Running, false),
+                ("child", SpanStatus::Done, true)
+            ]
+        );
+        assert!(live.view("other", |_| false).is_none());
+    }
+}
@@ -0,0 +1,275 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::serve::http::Method;
+
+/// Upper bounds, in seconds, of the request latency histogram buckets.
+const BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// Request counters and latencies for `GET /metrics`, labeled by endpoint.
+#[derive(Default)]
+pub struct Metrics {
+    inner: Mutex<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+    uploads: u64,
+    upload_bytes: u64,
+    duplicates: u64,
+    duplicate_bytes: u64,
+    expired: u64,
+    expired_bytes: u64,
+    responses: BTreeMap<(&'static str, u16), u64>,
+    errors: BTreeMap<&'static str, u64>,
+    latency: BTreeMap<&'static str, Histogram>,
+}
+
+#[derive(Default)]
+struct Histogram {
+    buckets: [u64; BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, secs: f64) {
+        for (bucket, le) in self.buckets.iter_mut().zip(BUCKETS) {
+            if secs <= le {
+                *bucket += 1;
+            }
+        }
+        self.sum += secs;
+        self.count += 1;
+    }
+}
+
+impl Metrics {
+    /// A request answered with `status`. Server errors count as errors.
+    pub fn observe(&self, endpoint: &'static str, status: u16, elapsed: Duration) {
+        let mut inner = self.inner.lock().unwrap();
+        *inner.responses.entry((endpoint, status)).or_default() += 1;
+        if status >= 500 {
+            *inner.errors.entry(endpoint).or_default() += 1;
+        }
+        inner
+            .latency
+            .entry(endpoint)
+            .or_default()
+            .observe(elapsed.as_secs_f64());
+    }
+
+    /// A request whose handler failed before it could answer.
+    pub fn failed(&self, endpoint: &'static str, elapsed: Duration) {
+        let mut inner = self.inner.lock().unwrap();
+        *inner.errors.entry(endpoint).or_default() += 1;
+        inner
+            .latency
+            .entry(endpoint)
+            .or_default()
+            .observe(elapsed.as_secs_f64());
+    }
+
+    pub fn uploaded(&self, bytes: u64) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.uploads += 1;
+        inner.upload_bytes += bytes;
+    }
+
+    /// An upload that was already in the store and wasn't kept again.
+    pub fn deduplicated(&self, bytes: u64) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.duplicates += 1;
+        inner.duplicate_bytes += bytes;
+    }
+
+    /// A pack deleted by the retention policy.
+    pub fn expired(&self, bytes: u64) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.expired += 1;
+        inner.expired_bytes += bytes;
+    }
+
+    /// The Prometheus text exposition of everything counted so far, plus
+    /// the store gauges.
+    pub fn render(&self, packs: usize, store_bytes: u64) -> String {
+        let inner = self.inner.lock().unwrap();
+        let mut out = String::new();
+        let name = family(&mut out, "poe_packs", "gauge", "Packs in the store.");
+        let _ = writeln!(out, "{} {}", name, packs);
+        let name = family(
+            &mut out,
+            "poe_store_bytes",
+            "gauge",
+            "Total size of the packs in the store.",
+        );
+        let _ = writeln!(out, "{} {}", name, store_bytes);
+        let name = family(
+            &mut out,
+            "poe_uploads_total",
+            "counter",
+            "Packs uploaded and stored.",
+        );
+        let _ = writeln!(out, "{} {}", name, inner.uploads);
+        let name = family(
+            &mut out,
+            "poe_upload_bytes_total",
+            "counter",
+            "Bytes of packs uploaded.",
+        );
+        let _ = writeln!(out, "{} {}", name, inner.upload_bytes);
+        let name = family(
+            &mut out,
+            "poe_upload_duplicates_total",
+            "counter",
+            "Uploads of packs already in the store, answered with the existing id.",
+        );
+        let _ = writeln!(out, "{} {}", name, inner.duplicates);
+        let name = family(
+            &mut out,
+            "poe_upload_duplicate_bytes_total",
+            "counter",
+            "Bytes not stored again because the upload was a duplicate.",
+        );
+        let _ = writeln!(out, "{} {}", name, inner.duplicate_bytes);
+        let name = family(
+            &mut out,
+            "poe_retention_deleted_total",
+            "counter",
+            "Packs deleted by the retention policy.",
+        );
+        let _ = writeln!(out, "{} {}", name, inner.expired);
+        let name = family(
+            &mut out,
+            "poe_retention_deleted_bytes_total",
+            "counter",
+            "Bytes of packs deleted by the retention policy.",
+        );
+        let _ = writeln!(out, "{} {}", name, inner.expired_bytes);
+
+        let name = family(
+            &mut out,
+            "poe_http_responses_total",
+            "counter",
+            "Responses by endpoint and status code.",
+        );
+        for ((endpoint, status), n) in &inner.responses {
+            let _ = writeln!(
+                out,
+                "{}{{endpoint=\"{}\",code=\"{}\"}} {}",
+                name, endpoint, status, n
+            );
+        }
+        let name = family(
+            &mut out,
+            "poe_http_errors_total",
+            "counter",
+            "Requests that failed with a server error.",
+        );
+        for (endpoint, n) in &inner.errors {
+            let _ = writeln!(out, "{}{{endpoint=\"{}\"}} {}", name, endpoint, n);
+        }
+
+        let name = family(
+            &mut out,
+            "poe_http_request_duration_seconds",
+            "histogram",
+            "Time to handle a request, by endpoint.",
+        );
+        for (endpoint, h) in &inner.latency {
+            for (le, n) in BUCKETS.iter().zip(h.buckets) {
+                let _ = writeln!(
+                    out,
+                    "{}_bucket{{endpoint=\"{}\",le=\"{}\"}} {}",
+                    name, endpoint, le, n
+                );
+            }
+            let _ = writeln!(
+                out,
+                "{}_bucket{{endpoint=\"{}\",le=\"+Inf\"}} {}",
+                name, endpoint, h.count
+            );
+            let _ = writeln!(out, "{}_sum{{endpoint=\"{}\"}} {}", name, endpoint, h.sum);
+            let _ = writeln!(
+                out,
+                "{}_count{{endpoint=\"{}\"}} {}",
+                name, endpoint, h.count
+            );
+        }
+        out
+    }
+}
+
+/// Writes a metric's HELP and TYPE lines and returns its name.
+fn family(out: &mut String, name: &'static str, kind: &str, help: &str) -> &'static str {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+    name
+}
+
+/// The label a request is counted under: one per route, so pack ids and
+/// query names don't multiply the series.
+pub fn endpoint(method: &Method, path: &str) -> &'static str {
+    let segments: Vec<&str> = path.trim_start_matches('/').split('/').collect();
+    match (method, segments.as_slice()) {
+        (Method::Get, [""]) => "index",
+        (Method::Get, ["metrics"]) => "metrics",
+        (Method::Post, ["api", "packs"]) => "upload",
+        (Method::Get, ["api", "packs"]) => "list",
+        (Method::Get, ["api", "packs", _]) => "summary",
+        (Method::Get, ["api", "packs", _, "explain"]) => "explain",
+        (Method::Get, ["api", "packs", _, "query", _]) => "query",
+        (Method::Get, ["api", "packs", _, "download"]) => "download",
+        (_, ["api", "packs", _, "hold"]) => "hold",
+        (Method::Get, ["api", "search"]) => "search",
+        (Method::Get, ["api", "clusters"]) => "clusters",
+        (Method::Get, ["api", "trends"]) => "trends",
+        (Method::Get, ["api", "traces", ..]) => "traces",
+        (Method::Post, ["api", "traces", _, "spans"]) => "spans",
+        (_, ["api", "baselines", ..]) => "baselines",
+        _ => "other",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_counters_and_cumulative_buckets() {
+        let metrics = Metrics::default();
+        metrics.observe("explain", 200, Duration::from_millis(30));
+        metrics.observe("explain", 200, Duration::from_secs(3));
+        metrics.observe("query", 500, Duration::from_millis(1));
+        metrics.uploaded(2048);
+        metrics.deduplicated(2048);
+        metrics.expired(512);
+
+        let text = metrics.render(4, 10_000);
+        for line in [
+            "poe_packs 4",
+            "poe_store_bytes 10000",
+            "poe_uploads_total 1",
+            "poe_upload_bytes_total 2048",
+            "poe_upload_duplicates_total 1",
+            "poe_retention_deleted_bytes_total 512",
+            "poe_http_responses_total{endpoint=\"explain\",code=\"200\"} 2",
+            "poe_http_errors_total{endpoint=\"query\"} 1",
+            "poe_http_request_duration_seconds_bucket{endpoint=\"explain\",le=\"0.025\"} 0",
+            "poe_http_request_duration_seconds_bucket{endpoint=\"explain\",le=\"0.05\"} 1",
+            "poe_http_request_duration_seconds_bucket{endpoint=\"explain\",le=\"5\"} 2",
+            "poe_http_request_duration_seconds_bucket{endpoint=\"explain\",le=\"+Inf\"} 2",
+            "poe_http_request_duration_seconds_count{endpoint=\"explain\"} 2",
+        ] {
+            assert!(text.lines().any(|l| l == line), "missing {}", line);
+        }
+        assert_eq!(
+            endpoint(&Method::Get, "/api/packs/abc/query/files"),
+            "query"
+        );
+    }
+}
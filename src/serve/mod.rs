@@ -1 +1,10 @@
+pub mod cache;
+pub mod http;
+pub mod live;
+pub mod metrics;
+pub mod multipart;
+pub mod retention;
+pub mod search;
 pub mod server;
+pub mod storage;
+pub mod tls;
@@ -0,0 +1,190 @@
+use anyhow::{bail, Result};
+
+/// Part headers are a line or two; anything much longer is not a form.
+const MAX_HEADER_BYTES: usize = 16 * 1024;
+
+/// Pulls the first file part out of a `multipart/form-data` body as it
+/// streams in, so `curl -F pack=@run.poepack` uploads can go straight to
+/// disk like raw ones.
+pub struct FilePart {
+    /// `\r\n--boundary`; the body is read as if it started with `\r\n` so
+    /// the first boundary looks like the rest.
+    delimiter: Vec<u8>,
+    state: State,
+    buf: Vec<u8>,
+    /// How much of `buf` has been searched for the end of a part's headers.
+    scanned: usize,
+    found: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Preamble,
+    Headers,
+    /// Inside a part: the file when `true`, a form field otherwise.
+    Part(bool),
+    Done,
+}
+
+impl FilePart {
+    pub fn new(boundary: &str) -> Self {
+        Self {
+            delimiter: format!("\r\n--{}", boundary).into_bytes(),
+            state: State::Preamble,
+            buf: b"\r\n".to_vec(),
+            scanned: 0,
+            found: false,
+        }
+    }
+
+    /// The boundary of a `multipart/form-data` content type.
+    pub fn boundary(content_type: &str) -> Option<&str> {
+        let (kind, params) = content_type.split_once(';')?;
+        if !kind.trim().eq_ignore_ascii_case("multipart/form-data") {
+            return None;
+        }
+        params.split(';').find_map(|p| {
+            let (key, value) = p.split_once('=')?;
+            key.trim()
+                .eq_ignore_ascii_case("boundary")
+                .then(|| value.trim().trim_matches('"'))
+        })
+    }
+
+    /// Takes the next chunk of the body and appends whatever it completes
+    /// of the file to `out`. Fails on part headers too long to be real.
+    pub fn feed(&mut self, chunk: &[u8], out: &mut Vec<u8>) -> Result<()> {
+        self.buf.extend_from_slice(chunk);
+        loop {
+            match self.state {
+                State::Done => {
+                    self.buf.clear();
+                    return Ok(());
+                }
+                State::Preamble => match find(&self.buf, &self.delimiter) {
+                    Some(i) => {
+                        self.buf.drain(..i + self.delimiter.len());
+                        self.state = State::Headers;
+                    }
+                    None => {
+                        self.keep_tail(None);
+                        return Ok(());
+                    }
+                },
+                State::Headers => {
+                    if self.buf.starts_with(b"--") {
+                        // The closing boundary, and no file part before it.
+                        self.state = State::Done;
+                        continue;
+                    }
+                    // The terminator may straddle the last chunk.
+                    let from = self.scanned.saturating_sub(3);
+                    let Some(end) = find(&self.buf[from..], b"\r\n\r\n").map(|i| from + i) else {
+                        if self.buf.len() > MAX_HEADER_BYTES {
+                            bail!("multipart part headers exceed {} bytes", MAX_HEADER_BYTES);
+                        }
+                        self.scanned = self.buf.len();
+                        return Ok(());
+                    };
+                    if end > MAX_HEADER_BYTES {
+                        bail!("multipart part headers exceed {} bytes", MAX_HEADER_BYTES);
+                    }
+                    let headers = String::from_utf8_lossy(&self.buf[..end]).to_ascii_lowercase();
+                    self.buf.drain(..end + 4);
+                    self.scanned = 0;
+                    self.state = State::Part(headers.contains("filename="));
+                }
+                State::Part(file) => match find(&self.buf, &self.delimiter) {
+                    Some(i) => {
+                        if file {
+                            out.extend_from_slice(&self.buf[..i]);
+                            self.found = true;
+                            self.state = State::Done;
+                        } else {
+                            self.buf.drain(..i + self.delimiter.len());
+                            self.state = State::Headers;
+                        }
+                    }
+                    None => {
+                        self.keep_tail(file.then_some(&mut *out));
+                        return Ok(());
+                    }
+                },
+            }
+        }
+    }
+
+    /// Whether the whole file part has been seen.
+    pub fn complete(&self) -> bool {
+        self.found
+    }
+
+    /// Hands on (or drops) all but the bytes that could be the start of a
+    /// delimiter split across chunks.
+    fn keep_tail(&mut self, out: Option<&mut Vec<u8>>) {
+        let keep = self.delimiter.len() - 1;
+        if self.buf.len() > keep {
+            let cut = self.buf.len() - keep;
+            if let Some(out) = out {
+                out.extend_from_slice(&self.buf[..cut]);
+            }
+            self.buf.drain(..cut);
+        }
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extracts_the_file_part_across_any_chunking() {
+        let content_type = "multipart/form-data; boundary=\"XyZ\"";
+        let boundary = FilePart::boundary(content_type).unwrap();
+        assert_eq!(boundary, "XyZ");
+        let file = b"PK\x03\x04 pack bytes\r\n--Xy not quite a boundary";
+        let mut body =
+            b"--XyZ\r\nContent-Disposition: form-data; name=\"tag\"\r\n\r\nci\r\n".to_vec();
+        body.extend_from_slice(
+            b"--XyZ\r\nContent-Disposition: form-data; name=\"pack\"; filename=\"run.poepack\"\r\n\r\n",
+        );
+        body.extend_from_slice(file);
+        body.extend_from_slice(b"\r\n--XyZ--\r\n");
+
+        for size in [1, 2, 3, 7, body.len()] {
+            let mut part = FilePart::new(boundary);
+            let mut out = Vec::new();
+            for chunk in body.chunks(size) {
+                part.feed(chunk, &mut out).unwrap();
+            }
+            assert!(part.complete(), "chunk size {}", size);
+            assert_eq!(out, file, "chunk size {}", size);
+        }
+
+        let mut part = FilePart::new(boundary);
+        let mut out = Vec::new();
+        part.feed(
+            b"--XyZ\r\nContent-Disposition: form-data; name=\"a\"\r\n\r\n1\r\n--XyZ--\r\n",
+            &mut out,
+        )
+        .unwrap();
+        assert!(!part.complete());
+        assert!(out.is_empty());
+        assert_eq!(FilePart::boundary("application/octet-stream"), None);
+    }
+
+    #[test]
+    fn endless_part_headers_are_refused() {
+        let mut part = FilePart::new("XyZ");
+        let mut out = Vec::new();
+        part.feed(b"--XyZ\r\nX-Filler: ", &mut out).unwrap();
+        let filler = [b'a'; 1024];
+        let refused = (0..64).any(|_| part.feed(&filler, &mut out).is_err());
+        assert!(refused);
+        assert!(part.buf.len() <= MAX_HEADER_BYTES + filler.len());
+    }
+}
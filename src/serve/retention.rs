@@ -0,0 +1,169 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+
+/// Settings read from `poe serve --config <file>`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ServeConfig {
+    #[serde(default)]
+    pub retention: RetentionPolicy,
+}
+
+impl ServeConfig {
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        toml::from_str(&content).with_context(|| format!("invalid config {}", path.display()))
+    }
+}
+
+/// How long packs are kept, by tag:
+///
+/// ```toml
+/// [retention]
+/// default = "30d"
+/// tags = { "ci=nightly" = "7d", release = "forever" }
+/// ```
+///
+/// A pack matching several tag rules is kept for the longest of them;
+/// one matching none falls back to `default`, and without a default it
+/// is kept forever.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RetentionPolicy {
+    #[serde(default)]
+    pub default: Option<Keep>,
+    /// `key=value` matches that label, a bare `key` any pack carrying it.
+    #[serde(default)]
+    pub tags: BTreeMap<String, Keep>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(try_from = "String")]
+pub enum Keep {
+    For(Duration),
+    Forever,
+}
+
+impl TryFrom<String> for Keep {
+    type Error = String;
+
+    /// `forever`, or a number of minutes, hours, days or weeks: `90m`,
+    /// `12h`, `7d`, `2w`.
+    fn try_from(s: String) -> std::result::Result<Self, String> {
+        let s = s.trim();
+        if s == "forever" {
+            return Ok(Keep::Forever);
+        }
+        let invalid = || {
+            format!(
+                "invalid retention {:?}: expected e.g. 7d, 12h or forever",
+                s
+            )
+        };
+        let split = s.find(|c: char| !c.is_ascii_digit()).ok_or_else(invalid)?;
+        let n: u64 = s[..split].parse().map_err(|_| invalid())?;
+        let unit = match &s[split..] {
+            "m" => 60,
+            "h" => 3600,
+            "d" => 86_400,
+            "w" => 7 * 86_400,
+            _ => return Err(invalid()),
+        };
+        Ok(Keep::For(Duration::from_secs(
+            n.checked_mul(unit).ok_or_else(invalid)?,
+        )))
+    }
+}
+
+impl RetentionPolicy {
+    pub fn is_empty(&self) -> bool {
+        self.default.is_none() && self.tags.is_empty()
+    }
+
+    pub fn keep(&self, tags: &BTreeMap<String, String>) -> Keep {
+        self.tags
+            .iter()
+            .filter(|(rule, _)| match rule.split_once('=') {
+                Some((key, value)) => tags.get(key).is_some_and(|v| v == value),
+                None => tags.contains_key(rule.as_str()),
+            })
+            .map(|(_, keep)| *keep)
+            .max()
+            .or(self.default)
+            .unwrap_or(Keep::Forever)
+    }
+
+    /// Whether a pack with these tags, from `timestamp` (RFC 3339), is
+    /// past its retention at `now`. Packs with unreadable timestamps are
+    /// kept.
+    pub fn expired(
+        &self,
+        tags: &BTreeMap<String, String>,
+        timestamp: &str,
+        now: DateTime<Utc>,
+    ) -> bool {
+        let Keep::For(keep) = self.keep(tags) else {
+            return false;
+        };
+        let Ok(at) = DateTime::parse_from_rfc3339(timestamp) else {
+            return false;
+        };
+        chrono::Duration::from_std(keep).is_ok_and(|keep| now - at.with_timezone(&Utc) > keep)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn longest_matching_rule_wins() {
+        let config: ServeConfig = toml::from_str(
+            r#"
+            [retention]
+            default = "30d"
+            tags = { "ci=nightly" = "7d", release = "forever", ci = "14d" }
+            "#,
+        )
+        .unwrap();
+        let policy = config.retention;
+        let tags = |pairs: &[(&str, &str)]| -> BTreeMap<String, String> {
+            pairs
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect()
+        };
+        let days = |n: u64| Keep::For(Duration::from_secs(n * 86_400));
+        assert_eq!(policy.keep(&tags(&[("ci", "nightly")])), days(14));
+        assert_eq!(
+            policy.keep(&tags(&[("ci", "nightly"), ("release", "1.2")])),
+            Keep::Forever
+        );
+        assert_eq!(policy.keep(&tags(&[("branch", "main")])), days(30));
+
+        let now = DateTime::parse_from_rfc3339("2026-03-20T00:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let nightly = tags(&[("ci", "nightly")]);
+        assert!(policy.expired(&nightly, "2026-03-01T00:00:00+00:00", now));
+        assert!(!policy.expired(&nightly, "2026-03-10T00:00:00+00:00", now));
+        assert!(!policy.expired(&nightly, "not a time", now));
+
+        assert!(toml::from_str::<ServeConfig>("[retention]\ndefault = \"7 days\"").is_err());
+    }
+
+    #[test]
+    fn overflowing_retention_is_rejected() {
+        assert!(Keep::try_from("99999999999999999w".to_string()).is_err());
+        assert_eq!(
+            Keep::try_from("2w".to_string()),
+            Ok(Keep::For(Duration::from_secs(14 * 86_400)))
+        );
+    }
+}
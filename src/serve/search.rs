@@ -0,0 +1,205 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::explain::analyzer;
+use crate::explain::cluster::Fingerprint;
+use crate::explain::trends::RunPoint;
+use crate::pack::reader::PackReader;
+use crate::serve::storage::Storage;
+
+/// Tail of stderr kept in the index; the end is where failures are reported.
+const MAX_STDERR_BYTES: usize = 64 * 1024;
+const MAX_FAILED_PATHS: usize = 200;
+/// Characters of context kept on each side of a match in a snippet.
+const SNIPPET_CONTEXT: usize = 60;
+/// Bumped when the index gains fields, so files written by an older server
+/// are rebuilt rather than read with the new fields empty.
+const INDEX_VERSION: u32 = 3;
+
+/// The searchable text of one pack, its failure fingerprint and trend
+/// numbers, extracted when it is stored so queries don't reopen and
+/// re-analyze every pack. Saved in the store as `<pack>.index.json`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SearchDoc {
+    #[serde(default)]
+    pub version: u32,
+    pub command: String,
+    pub categories: Vec<String>,
+    pub failed_paths: Vec<String>,
+    pub stderr: String,
+    /// `None` for runs that did not fail.
+    #[serde(default)]
+    pub fingerprint: Option<Fingerprint>,
+    #[serde(default)]
+    pub failure: Option<String>,
+    #[serde(default)]
+    pub point: RunPoint,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SearchHit {
+    /// `command`, `category`, `failed_path` or `stderr`.
+    pub field: &'static str,
+    pub snippet: String,
+}
+
+impl SearchDoc {
+    pub fn build(pack: &PackReader) -> Result<Self> {
+        let output = analyzer::analyze(pack)?;
+        let mut categories: Vec<String> = output
+            .error_patterns
+            .iter()
+            .map(|p| p.category.clone())
+            .collect();
+        categories.dedup();
+
+        let files = &output.file_activity;
+        let mut failed_paths: Vec<String> = Vec::new();
+        for f in files.failed_opens.iter().chain(&files.permission_errors) {
+            if !failed_paths.contains(&f.path) {
+                failed_paths.push(f.path.clone());
+            }
+        }
+        failed_paths.truncate(MAX_FAILED_PATHS);
+
+        let mut stderr = pack.stdio_text("stderr").unwrap_or_default();
+        if stderr.len() > MAX_STDERR_BYTES {
+            let mut start = stderr.len() - MAX_STDERR_BYTES;
+            while !stderr.is_char_boundary(start) {
+                start += 1;
+            }
+            stderr.drain(..start);
+        }
+
+        Ok(Self {
+            version: INDEX_VERSION,
+            command: pack.summary().command.join(" "),
+            categories,
+            failed_paths,
+            stderr,
+            fingerprint: Fingerprint::compute(pack.summary(), &output),
+            failure: output.failure.as_ref().map(|f| f.description.clone()),
+            point: RunPoint::from_pack(pack.summary(), &output),
+        })
+    }
+
+    /// Loads the saved index for a stored pack, building and saving it
+    /// when it is missing, unreadable or from an older server.
+    pub fn load_or_build(storage: &dyn Storage, pack_name: &str) -> Result<Self> {
+        if let Some(doc) = storage
+            .read(&index_name(pack_name))
+            .ok()
+            .flatten()
+            .and_then(|data| serde_json::from_slice::<Self>(&data).ok())
+            .filter(|doc| doc.version == INDEX_VERSION)
+        {
+            return Ok(doc);
+        }
+        let doc = Self::build(&PackReader::open(&storage.fetch(pack_name)?)?)?;
+        doc.save(storage, pack_name)?;
+        Ok(doc)
+    }
+
+    pub fn save(&self, storage: &dyn Storage, pack_name: &str) -> Result<()> {
+        storage.write(&index_name(pack_name), &serde_json::to_vec(self)?)
+    }
+
+    /// Every term must occur (case-insensitively) somewhere in the pack;
+    /// returns a snippet from the first match in each field a term was
+    /// found in, or nothing if some term is missing.
+    pub fn search(&self, terms: &[String]) -> Vec<SearchHit> {
+        let mut hits: Vec<SearchHit> = Vec::new();
+        for term in terms {
+            let mut found = false;
+            let mut hit = |field: &'static str, text: &str| {
+                if let Some(snippet) = snippet(text, term) {
+                    found = true;
+                    let hit = SearchHit { field, snippet };
+                    if !hits.contains(&hit) {
+                        hits.push(hit);
+                    }
+                }
+            };
+            hit("command", &self.command);
+            if let Some(c) = self.categories.iter().find(|c| contains(c, term)) {
+                hit("category", c);
+            }
+            if let Some(p) = self.failed_paths.iter().find(|p| contains(p, term)) {
+                hit("failed_path", p);
+            }
+            if let Some(line) = self.stderr.lines().find(|l| contains(l, term)) {
+                hit("stderr", line);
+            }
+            if !found {
+                return Vec::new();
+            }
+        }
+        hits
+    }
+}
+
+/// `poe-1234.poepack` is indexed in `poe-1234.index.json`.
+pub fn index_name(pack_name: &str) -> String {
+    format!("{}.index.json", pack_name.trim_end_matches(".poepack"))
+}
+
+/// Splits a query into lowercase terms.
+pub fn parse_terms(q: &str) -> Vec<String> {
+    q.split_whitespace().map(|t| t.to_lowercase()).collect()
+}
+
+fn contains(text: &str, term: &str) -> bool {
+    text.to_lowercase().contains(term)
+}
+
+/// The text around the first occurrence of `term`, with `...` where it was
+/// cut.
+fn snippet(text: &str, term: &str) -> Option<String> {
+    let lower = text.to_lowercase();
+    let at = lower.find(term)?;
+    // Lowercasing can change byte lengths; map back through char indices.
+    let char_at = lower[..at].chars().count();
+    let chars: Vec<char> = text.chars().collect();
+    let end = (char_at + term.chars().count() + SNIPPET_CONTEXT).min(chars.len());
+    let start = char_at.saturating_sub(SNIPPET_CONTEXT).min(end);
+    let mut out = String::new();
+    if start > 0 {
+        out.push_str("...");
+    }
+    out.extend(&chars[start..end]);
+    if end < chars.len() {
+        out.push_str("...");
+    }
+    Some(out.trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn all_terms_must_match_and_snippets_are_clipped() {
+        let doc = SearchDoc {
+            version: INDEX_VERSION,
+            command: "pytest tests/test_db.py".into(),
+            categories: vec!["missing_file".into(), "network".into()],
+            failed_paths: vec!["/etc/app/config.yaml".into()],
+            stderr: format!(
+                "collecting\n{}ConnectionRefusedError: [Errno 111] Connection refused\n",
+                "x".repeat(100)
+            ),
+            ..Default::default()
+        };
+
+        let hits = doc.search(&parse_terms("CONFIG.yaml connectionrefused"));
+        assert_eq!(hits.len(), 2);
+        assert_eq!(hits[0].field, "failed_path");
+        assert_eq!(hits[0].snippet, "/etc/app/config.yaml");
+        assert_eq!(hits[1].field, "stderr");
+        assert!(hits[1].snippet.starts_with("...x"));
+        assert!(hits[1].snippet.ends_with("refused"));
+
+        assert!(doc.search(&parse_terms("pytest redis")).is_empty());
+        assert_eq!(doc.search(&parse_terms("network"))[0].field, "category");
+    }
+}
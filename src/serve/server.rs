@@ -1,20 +1,144 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::fs;
+use std::net::TcpListener;
 use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
-use anyhow::{Context, Result};
-use tiny_http::{Header, Method, Request, Response, Server, StatusCode};
+use anyhow::{bail, Context, Result};
 
-use crate::explain::analyzer;
+use crate::distributed::live::SpanReport;
+use crate::distributed::trace_context;
+use crate::explain::cluster::{self, ClusterMember};
+use crate::explain::focus;
+use crate::explain::trends;
+use crate::logging;
+use crate::pack::baseline;
 use crate::pack::reader::PackReader;
+use crate::pack::remote;
+use crate::serve::cache;
+use crate::serve::http::{self, ApiRequest, Limits, Method, Shared, Upload};
+use crate::serve::metrics::Metrics;
+use crate::serve::retention::RetentionPolicy;
+use crate::serve::search::{self, SearchDoc, SearchHit};
+use crate::serve::storage::{self, Storage, StoredObject};
+use crate::serve::tls;
+use crate::trace::db::Page;
+use crate::util;
 
-struct PackStore {
-    dir: PathBuf,
+const DEFAULT_ROW_LIMIT: usize = 500;
+const DEFAULT_SEARCH_LIMIT: usize = 50;
+const CATALOG_VERSION: u32 = 2;
+const RETENTION_INTERVAL: Duration = Duration::from_secs(3600);
+
+pub struct PackStore {
+    storage: Arc<dyn Storage>,
     index: HashMap<String, PackMeta>,
+    /// Searchable text per pack id, for `/api/search`.
+    docs: HashMap<String, SearchDoc>,
+    /// Named baselines and the pack id each points at, saved to
+    /// `baselines.json` in the store.
+    baselines: BTreeMap<String, String>,
+    /// Ids of packs on legal hold, exempt from retention; saved to
+    /// `holds.json` in the store.
+    holds: BTreeSet<String>,
+    /// What `index` was built from, by pack file name.
+    catalog: BTreeMap<String, CatalogEntry>,
+}
+
+/// Pack metadata saved as `catalog.json` in the store, so startup only
+/// opens packs added or rewritten since it was written. An entry is reused
+/// while the file's size and modification time, or ETag in a bucket,
+/// still match.
+#[derive(Default, serde::Serialize, serde::Deserialize)]
+struct Catalog {
+    version: u32,
+    packs: BTreeMap<String, CatalogEntry>,
+}
+
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+struct CatalogEntry {
+    size: u64,
+    mtime_ns: u64,
+    #[serde(default)]
+    etag: String,
+    /// Of the file's content, to spot re-uploads of the same pack.
+    #[serde(default)]
+    sha256: String,
+    meta: PackMeta,
+}
+
+/// An upload in a temp file in the store that was checked and indexed,
+/// ready to be moved in. Staging doesn't need the store lock, so large
+/// uploads don't hold up other requests while they're read.
+struct StagedPack {
+    temp_path: PathBuf,
+    sha256: String,
+    doc: SearchDoc,
+    meta: PackMeta,
+}
+
+impl StagedPack {
+    /// Removes the temp file if it isn't a usable pack.
+    fn new(temp_path: PathBuf, sha256: String) -> Result<Self> {
+        let staged = (|| {
+            let pack = PackReader::open(&temp_path).context("invalid .poepack file")?;
+            let summary = pack.summary();
+            let doc = SearchDoc::build(&pack)?;
+            let filename = format!("poe-{}.poepack", &summary.run_id[..8]);
+            Ok((doc, PackMeta::new(&pack, filename)))
+        })();
+        match staged {
+            Ok((doc, meta)) => Ok(Self {
+                temp_path,
+                sha256,
+                doc,
+                meta,
+            }),
+            Err(e) => {
+                let _ = fs::remove_file(&temp_path);
+                Err(e)
+            }
+        }
+    }
+
+    /// Moves the upload into the store, without the store lock: a bucket
+    /// upload takes a while.
+    fn put(&self, storage: &dyn Storage) -> Result<StoredObject> {
+        let stored = storage.put(&self.meta.filename, &self.temp_path);
+        if stored.is_err() {
+            let _ = fs::remove_file(&self.temp_path);
+        }
+        let stored = stored?;
+        self.doc.save(storage, &self.meta.filename)?;
+        Ok(stored)
+    }
 }
 
-#[derive(Clone, serde::Serialize)]
+#[derive(Default, serde::Serialize)]
+struct TraceListing {
+    trace_id: String,
+    /// Ids of the packs captured as spans of the trace.
+    packs: Vec<String>,
+    failures: usize,
+    last_seen: String,
+}
+
+#[derive(serde::Serialize)]
+struct ListedPack<'a> {
+    #[serde(flatten)]
+    pack: &'a PackMeta,
+    hold: bool,
+}
+
+#[derive(serde::Serialize)]
+struct SearchResult<'a> {
+    #[serde(flatten)]
+    pack: &'a PackMeta,
+    matches: Vec<SearchHit>,
+}
+
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
 struct PackMeta {
     id: String,
     filename: String,
@@ -23,168 +147,811 @@ struct PackMeta {
     exit_code: Option<i32>,
     signal: Option<i32>,
     duration_ms: u64,
+    tags: BTreeMap<String, String>,
+    /// Groups runs of the same command for `/api/trends`.
+    command_hash: String,
+    /// The distributed trace the run is part of, for `/api/traces`.
+    #[serde(default)]
+    trace_id: Option<String>,
+}
+
+impl PackMeta {
+    fn new(pack: &PackReader, filename: String) -> Self {
+        let summary = pack.summary();
+        Self {
+            id: summary.run_id.clone(),
+            filename,
+            uploaded_at: summary.timestamp.clone(),
+            command: summary.command.clone(),
+            exit_code: summary.exit_code,
+            signal: summary.signal,
+            duration_ms: summary.duration_ms,
+            tags: summary.tags.clone(),
+            command_hash: trends::command_hash(&summary.command),
+            trace_id: trace_context::trace_id(pack),
+        }
+    }
+}
+
+/// A stored pack, to be fetched to a local file outside the store lock.
+struct PackRef {
+    storage: Arc<dyn Storage>,
+    name: String,
+}
+
+impl PackRef {
+    fn fetch(&self) -> Result<PathBuf> {
+        self.storage.fetch(&self.name)
+    }
 }
 
 impl PackStore {
-    fn new(dir: &Path) -> Result<Self> {
-        fs::create_dir_all(dir)?;
+    pub fn new(dir: &Path) -> Result<Self> {
+        Self::with_storage(Arc::new(storage::LocalStorage::new(dir)?))
+    }
+
+    pub fn with_storage(storage: Arc<dyn Storage>) -> Result<Self> {
         let mut store = Self {
-            dir: dir.to_path_buf(),
+            storage,
             index: HashMap::new(),
+            docs: HashMap::new(),
+            baselines: BTreeMap::new(),
+            holds: BTreeSet::new(),
+            catalog: BTreeMap::new(),
         };
         store.scan_existing()?;
+        if let Some(data) = store.storage.read("baselines.json")? {
+            store.baselines = serde_json::from_slice(&data).context("invalid baselines.json")?;
+        }
+        if let Some(data) = store.storage.read("holds.json")? {
+            store.holds = serde_json::from_slice(&data).context("invalid holds.json")?;
+        }
         Ok(store)
     }
 
+    fn set_hold(&mut self, id: &str, hold: bool) -> Result<()> {
+        if hold {
+            self.holds.insert(id.to_string());
+        } else {
+            self.holds.remove(id);
+        }
+        self.storage
+            .write("holds.json", &serde_json::to_vec_pretty(&self.holds)?)
+    }
+
+    /// Packs past their retention, other than those on hold or named as a
+    /// baseline.
+    fn expired(&self, policy: &RetentionPolicy, now: chrono::DateTime<chrono::Utc>) -> Vec<String> {
+        let baselines: BTreeSet<&String> = self.baselines.values().collect();
+        self.index
+            .values()
+            .filter(|m| !self.holds.contains(&m.id) && !baselines.contains(&m.id))
+            .filter(|m| policy.expired(&m.tags, &m.uploaded_at, now))
+            .map(|m| m.id.clone())
+            .collect()
+    }
+
+    /// Drops packs from the catalog, returning the file name and size of
+    /// each for the caller to delete. Until they are, the next startup
+    /// finds them again.
+    fn forget(&mut self, ids: &[String]) -> Result<Vec<(String, u64)>> {
+        let mut removed = Vec::new();
+        for id in ids {
+            let Some(meta) = self.index.remove(id) else {
+                continue;
+            };
+            self.docs.remove(id);
+            let size = self.catalog.remove(&meta.filename).map_or(0, |e| e.size);
+            removed.push((meta.filename, size));
+        }
+        if !removed.is_empty() {
+            self.save_catalog()?;
+        }
+        Ok(removed)
+    }
+
+    fn set_baseline(&mut self, name: &str, id: &str) -> Result<()> {
+        self.baselines.insert(name.to_string(), id.to_string());
+        self.storage.write(
+            "baselines.json",
+            &serde_json::to_vec_pretty(&self.baselines)?,
+        )
+    }
+
+    fn baseline_list(&self) -> Vec<serde_json::Value> {
+        self.baselines
+            .iter()
+            .map(|(name, id)| {
+                let meta = self.index.get(id);
+                serde_json::json!({
+                    "name": name,
+                    "id": id,
+                    "command": meta.map(|m| &m.command),
+                    "timestamp": meta.map(|m| &m.uploaded_at),
+                })
+            })
+            .collect()
+    }
+
+    fn save_catalog(&self) -> Result<()> {
+        let catalog = Catalog {
+            version: CATALOG_VERSION,
+            packs: self.catalog.clone(),
+        };
+        self.storage
+            .write("catalog.json", &serde_json::to_vec(&catalog)?)
+    }
+
     fn scan_existing(&mut self) -> Result<()> {
-        for entry in fs::read_dir(&self.dir)? {
-            let entry = entry?;
-            let path = entry.path();
-            if path.extension().map(|e| e == "poepack").unwrap_or(false) {
-                if let Ok(pack) = PackReader::open(&path) {
-                    let summary = pack.summary();
-                    let meta = PackMeta {
-                        id: summary.run_id.clone(),
-                        filename: path.file_name().unwrap().to_string_lossy().into_owned(),
-                        uploaded_at: summary.timestamp.clone(),
-                        command: summary.command.clone(),
-                        exit_code: summary.exit_code,
-                        signal: summary.signal,
-                        duration_ms: summary.duration_ms,
+        // Left behind by uploads and downloads cut short when the server
+        // stopped.
+        for entry in fs::read_dir(self.storage.local_dir())? {
+            let path = entry?.path();
+            if path
+                .extension()
+                .is_some_and(|e| e == "upload" || e == "download")
+            {
+                let _ = fs::remove_file(&path);
+            }
+        }
+        let mut cached = self
+            .storage
+            .read("catalog.json")
+            .ok()
+            .flatten()
+            .and_then(|data| serde_json::from_slice::<Catalog>(&data).ok())
+            .filter(|c| c.version == CATALOG_VERSION)
+            .unwrap_or_default()
+            .packs;
+        let mut changed = false;
+        for object in self.storage.list()? {
+            let StoredObject {
+                name: filename,
+                size,
+                mtime_ns,
+                etag,
+            } = object;
+            let (meta, sha256) = match cached
+                .remove(&filename)
+                .filter(|e| e.size == size && e.mtime_ns == mtime_ns && e.etag == etag)
+            {
+                Some(entry) if !entry.sha256.is_empty() => (entry.meta, entry.sha256),
+                // Cataloged before content hashes were kept.
+                Some(entry) => {
+                    let sha256 = match self
+                        .storage
+                        .fetch(&filename)
+                        .and_then(|path| Ok(util::hash_file(&path)?))
+                    {
+                        Ok(sha256) => sha256,
+                        Err(e) => {
+                            log::warn!("failed to hash {}: {:#}", filename, e);
+                            continue;
+                        }
+                    };
+                    changed = true;
+                    (entry.meta, sha256)
+                }
+                None => {
+                    let path = match self.storage.fetch(&filename) {
+                        Ok(path) => path,
+                        Err(e) => {
+                            log::warn!("failed to fetch {}: {:#}", filename, e);
+                            continue;
+                        }
+                    };
+                    let Ok(pack) = PackReader::open(&path) else {
+                        continue;
                     };
-                    self.index.insert(summary.run_id.clone(), meta);
+                    let sha256 = match util::hash_file(&path) {
+                        Ok(sha256) => sha256,
+                        Err(e) => {
+                            log::warn!("failed to hash {}: {:#}", filename, e);
+                            continue;
+                        }
+                    };
+                    changed = true;
+                    (PackMeta::new(&pack, filename.clone()), sha256)
+                }
+            };
+            match SearchDoc::load_or_build(self.storage.as_ref(), &filename) {
+                Ok(doc) => {
+                    self.docs.insert(meta.id.clone(), doc);
                 }
+                Err(e) => log::warn!("failed to index {}: {:#}", filename, e),
             }
+            self.index.insert(meta.id.clone(), meta.clone());
+            self.catalog.insert(
+                filename,
+                CatalogEntry {
+                    size,
+                    mtime_ns,
+                    etag,
+                    sha256,
+                    meta,
+                },
+            );
+        }
+        // Whatever is left was deleted from the store.
+        if changed || !cached.is_empty() {
+            self.save_catalog()?;
         }
         Ok(())
     }
 
-    fn store_pack(&mut self, data: &[u8]) -> Result<String> {
-        let temp_path = self
-            .dir
-            .join(format!("temp-{}.poepack", uuid::Uuid::new_v4()));
-        fs::write(&temp_path, data)?;
-
-        let pack = PackReader::open(&temp_path).context("invalid .poepack file")?;
-        let summary = pack.summary();
-        let id = summary.run_id.clone();
-
-        let final_name = format!("poe-{}.poepack", &id[..8]);
-        let final_path = self.dir.join(&final_name);
-        fs::rename(&temp_path, &final_path)?;
+    /// The id of a stored pack whose content has this hash.
+    fn duplicate_of(&self, sha256: &str) -> Option<String> {
+        self.catalog
+            .values()
+            .find(|e| e.sha256 == sha256)
+            .map(|e| e.meta.id.clone())
+    }
 
-        let meta = PackMeta {
-            id: id.clone(),
-            filename: final_name,
-            uploaded_at: summary.timestamp.clone(),
-            command: summary.command.clone(),
-            exit_code: summary.exit_code,
-            signal: summary.signal,
-            duration_ms: summary.duration_ms,
-        };
+    /// Indexes a pack `StagedPack::put` moved into the store.
+    fn store_pack(&mut self, staged: StagedPack, object: StoredObject) -> Result<String> {
+        let StagedPack {
+            sha256, doc, meta, ..
+        } = staged;
+        let id = meta.id.clone();
+        self.docs.insert(id.clone(), doc);
+        self.catalog.insert(
+            meta.filename.clone(),
+            CatalogEntry {
+                size: object.size,
+                mtime_ns: object.mtime_ns,
+                etag: object.etag,
+                sha256,
+                meta: meta.clone(),
+            },
+        );
+        self.save_catalog()?;
         self.index.insert(id.clone(), meta);
 
         Ok(id)
     }
 
-    fn get_path(&self, id: &str) -> Option<PathBuf> {
-        self.index.get(id).map(|m| self.dir.join(&m.filename))
+    /// Where uploads are received.
+    pub fn dir(&self) -> &Path {
+        self.storage.local_dir()
+    }
+
+    fn store_bytes(&self) -> u64 {
+        self.catalog.values().map(|e| e.size).sum()
+    }
+
+    fn get(&self, id: &str) -> Option<PackRef> {
+        self.index.get(id).map(|m| PackRef {
+            storage: Arc::clone(&self.storage),
+            name: m.filename.clone(),
+        })
+    }
+
+    fn sha256_of(&self, id: &str) -> Option<String> {
+        let filename = &self.index.get(id)?.filename;
+        Some(self.catalog.get(filename)?.sha256.clone())
     }
 
-    fn list(&self) -> Vec<&PackMeta> {
-        let mut metas: Vec<_> = self.index.values().collect();
+    fn clusters(&self) -> Vec<cluster::Cluster> {
+        let members = self
+            .index
+            .values()
+            .filter_map(|meta| {
+                let doc = self.docs.get(&meta.id)?;
+                let member = ClusterMember {
+                    run_id: meta.id.clone(),
+                    pack: meta.filename.clone(),
+                    timestamp: meta.uploaded_at.clone(),
+                    command: meta.command.clone(),
+                    description: doc.failure.clone().unwrap_or_default(),
+                };
+                Some((doc.fingerprint.clone()?, member))
+            })
+            .collect();
+        cluster::group(members)
+    }
+
+    /// Each trace with packs in the store, most recently active first.
+    fn traces(&self) -> Vec<TraceListing> {
+        let mut traces: BTreeMap<&str, TraceListing> = BTreeMap::new();
+        for meta in self.index.values() {
+            let Some(ref trace_id) = meta.trace_id else {
+                continue;
+            };
+            let trace = traces.entry(trace_id).or_insert_with(|| TraceListing {
+                trace_id: trace_id.clone(),
+                ..Default::default()
+            });
+            trace.packs.push(meta.id.clone());
+            if meta.exit_code != Some(0) {
+                trace.failures += 1;
+            }
+            if meta.uploaded_at > trace.last_seen {
+                trace.last_seen = meta.uploaded_at.clone();
+            }
+        }
+        let mut traces: Vec<TraceListing> = traces.into_values().collect();
+        traces.sort_by(|a, b| b.last_seen.cmp(&a.last_seen));
+        traces
+    }
+
+    fn trace_packs(&self, trace_id: &str) -> Vec<PackRef> {
+        self.index
+            .values()
+            .filter(|meta| meta.trace_id.as_deref() == Some(trace_id))
+            .filter_map(|meta| self.get(&meta.id))
+            .collect()
+    }
+
+    /// One trend per command, or just the one for `command_hash`.
+    fn trends(&self, command_hash: Option<&str>) -> Vec<trends::Trend> {
+        let points = self
+            .index
+            .values()
+            .filter(|meta| command_hash.is_none_or(|h| meta.command_hash == h))
+            .filter_map(|meta| Some(self.docs.get(&meta.id)?.point.clone()))
+            .collect();
+        trends::by_command(points)
+    }
+
+    /// Matching packs, most recent first.
+    fn search(&self, terms: &[String]) -> Vec<SearchResult<'_>> {
+        self.list(&[])
+            .into_iter()
+            .filter_map(|pack| {
+                let matches = self.docs.get(&pack.id)?.search(terms);
+                (!matches.is_empty()).then_some(SearchResult { pack, matches })
+            })
+            .collect()
+    }
+
+    fn listing(&self, filters: &[TagFilter]) -> Vec<ListedPack<'_>> {
+        self.list(filters)
+            .into_iter()
+            .map(|pack| ListedPack {
+                pack,
+                hold: self.holds.contains(&pack.id),
+            })
+            .collect()
+    }
+
+    fn list(&self, filters: &[TagFilter]) -> Vec<&PackMeta> {
+        let mut metas: Vec<_> = self
+            .index
+            .values()
+            .filter(|m| filters.iter().all(|f| f.matches(&m.tags)))
+            .collect();
         metas.sort_by(|a, b| b.uploaded_at.cmp(&a.uploaded_at));
         metas
     }
 }
 
-pub fn start(bind: &str, store_dir: &Path) -> Result<()> {
-    let server =
-        Server::http(bind).map_err(|e| anyhow::anyhow!("failed to bind {}: {}", bind, e))?;
-
-    eprintln!("poe serve: listening on http://{}", bind);
-    eprintln!("poe serve: pack store: {}", store_dir.display());
-    eprintln!();
-    eprintln!("  POST   /api/packs           upload a .poepack");
-    eprintln!("  GET    /api/packs           list all packs");
-    eprintln!("  GET    /api/packs/:id       get pack summary");
-    eprintln!("  GET    /api/packs/:id/explain   analyze pack");
-    eprintln!("  GET    /api/packs/:id/query/:q  query pack data");
-    eprintln!();
-
-    let store = Arc::new(Mutex::new(PackStore::new(store_dir)?));
-
-    for request in server.incoming_requests() {
-        let store = Arc::clone(&store);
-        std::thread::spawn(move || {
-            if let Err(e) = handle_request(request, store) {
-                eprintln!("poe serve: request error: {:#}", e);
+/// Certificate and key for serving HTTPS, and an optional plain HTTP
+/// address that redirects to it.
+pub struct TlsOptions {
+    pub cert: PathBuf,
+    pub key: PathBuf,
+    pub redirect_from: Option<String>,
+}
+
+/// What a handler answers with; `http` turns it into the response.
+pub enum Reply {
+    Text {
+        status: u16,
+        content_type: &'static str,
+        body: String,
+        headers: Vec<(&'static str, String)>,
+    },
+    /// A pack, streamed from disk.
+    File {
+        path: PathBuf,
+        headers: Vec<(&'static str, String)>,
+    },
+}
+
+pub fn start(
+    bind: &str,
+    storage: Arc<dyn Storage>,
+    retention: RetentionPolicy,
+    tls: Option<&TlsOptions>,
+    limits: Limits,
+) -> Result<()> {
+    let listener = TcpListener::bind(bind).with_context(|| format!("failed to bind {}", bind))?;
+    listener.set_nonblocking(true)?;
+    let acceptor = match tls {
+        Some(t) => Some(tls::load(&t.cert, &t.key)?),
+        None => None,
+    };
+    let redirect = match tls.and_then(|t| t.redirect_from.as_deref()) {
+        Some(from) => {
+            let redirect =
+                TcpListener::bind(from).with_context(|| format!("failed to bind {}", from))?;
+            redirect.set_nonblocking(true)?;
+            Some(redirect)
+        }
+        None => None,
+    };
+
+    let scheme = if tls.is_some() { "https" } else { "http" };
+    if !logging::is_quiet() {
+        eprintln!("poe serve: listening on {}://{}", scheme, bind);
+        if let Some(from) = tls.and_then(|t| t.redirect_from.as_deref()) {
+            eprintln!("poe serve: redirecting http://{} to https", from);
+        }
+        eprintln!("poe serve: pack store: {}", storage.describe());
+        eprintln!();
+        eprintln!("  POST   /api/packs           upload a .poepack");
+        eprintln!("  GET    /api/packs           list all packs (?tag=key=value)");
+        eprintln!("  GET    /api/packs/:id       get pack summary");
+        eprintln!(
+            "  POST   /api/packs/:id/hold  exempt a pack from retention (DELETE releases it)"
+        );
+        eprintln!("  GET    /api/search?q=       search commands, errors, stderr, failed paths");
+        eprintln!("  GET    /api/clusters        group failing packs by failure fingerprint");
+        eprintln!("  GET    /api/trends          per-command duration, bytes and failure rate (?command_hash=)");
+        eprintln!("  GET    /api/baselines       list named baselines");
+        eprintln!("  PUT    /api/baselines/:name point a baseline at a pack ({{\"id\": ...}})");
+        eprintln!("  GET    /api/baselines/:name/pack  download a baseline's .poepack");
+        eprintln!("  GET    /api/packs/:id/explain   analyze pack (?fields=failure,net,...)");
+        eprintln!(
+            "  GET    /api/packs/:id/download  download the .poepack (sha256 in X-Poe-Sha256)"
+        );
+        eprintln!("  GET    /api/packs/:id/query/:q  query pack data (?limit=&offset=)");
+        eprintln!("  GET    /metrics             Prometheus metrics");
+        eprintln!();
+    }
+
+    let store = PackStore::with_storage(storage)?;
+    let shared = Arc::new(Shared::new(store, limits));
+    if !retention.is_empty() {
+        let shared = Arc::clone(&shared);
+        std::thread::spawn(move || loop {
+            if let Err(e) = sweep(&shared, &retention) {
+                log::error!("retention sweep failed: {:#}", e);
             }
+            std::thread::sleep(RETENTION_INTERVAL);
         });
     }
+    http::run(listener, acceptor, redirect, shared)
+}
 
+/// Deletes the packs `policy` no longer keeps.
+fn sweep(shared: &Shared, policy: &RetentionPolicy) -> Result<()> {
+    let (storage, removed) = {
+        let mut store = shared.store.lock().unwrap();
+        let expired = store.expired(policy, chrono::Utc::now());
+        (Arc::clone(&store.storage), store.forget(&expired)?)
+    };
+    if removed.is_empty() {
+        return Ok(());
+    }
+    let mut bytes = 0;
+    for (name, size) in &removed {
+        storage.delete(name)?;
+        storage.delete(&search::index_name(name))?;
+        shared.metrics.expired(*size);
+        bytes += size;
+    }
+    log::info!("retention removed {} packs, {} bytes", removed.len(), bytes);
     Ok(())
 }
 
-fn handle_request(mut request: Request, store: Arc<Mutex<PackStore>>) -> Result<()> {
-    let url = request.url().to_string();
-    let method = request.method().clone();
+/// Answers one request; runs on the blocking pool.
+pub fn handle_request(request: ApiRequest, shared: &Shared) -> Result<Reply> {
+    let ApiRequest {
+        method,
+        url,
+        body,
+        upload,
+    } = request;
+    let store = &shared.store;
+    let path = url.split('?').next().unwrap_or("");
+    let segments: Vec<&str> = path.trim_start_matches('/').split('/').collect();
+
+    // Pack downloads are the binary responses.
+    if let (Method::Get, ["api", "baselines", name, "pack"]) = (method, segments.as_slice()) {
+        let pack = {
+            let store = store.lock().unwrap();
+            store.baselines.get(*name).and_then(|id| store.get(id))
+        };
+        if let Some(pack) = pack {
+            return Ok(Reply::File {
+                path: pack.fetch()?,
+                headers: Vec::new(),
+            });
+        }
+    }
 
-    let segments: Vec<&str> = url.trim_start_matches('/').split('/').collect();
+    if let (Method::Get, ["api", "packs", id, "download"]) = (method, segments.as_slice()) {
+        let found = {
+            let store = store.lock().unwrap();
+            store.get(id).zip(store.sha256_of(id))
+        };
+        if let Some((pack, checksum)) = found {
+            return Ok(Reply::File {
+                path: pack.fetch()?,
+                headers: vec![(remote::CHECKSUM_HEADER, checksum)],
+            });
+        }
+    }
 
-    let (status, body) = route(&method, &segments, &mut request, &store)?;
+    if let (Method::Get, ["metrics"]) = (method, segments.as_slice()) {
+        let text = {
+            let store = store.lock().unwrap();
+            shared
+                .metrics
+                .render(store.index.len(), store.store_bytes())
+        };
+        return Ok(Reply::Text {
+            status: 200,
+            content_type: "text/plain; version=0.0.4",
+            body: text,
+            headers: Vec::new(),
+        });
+    }
 
-    let response = Response::from_string(&body)
-        .with_status_code(StatusCode(status))
-        .with_header(
-            Header::from_bytes(
-                "Content-Type",
-                if status == 200 && url == "/" {
-                    "text/html"
-                } else {
-                    "application/json"
+    let mut headers = Vec::new();
+    let (status, body) = match (method, segments.as_slice()) {
+        (Method::Post, ["api", "packs"]) => {
+            let Some(upload) = upload else {
+                bail!("upload without a body");
+            };
+            let (status, reply, duplicate) = store_upload(upload, store, &shared.metrics)?;
+            if duplicate {
+                headers.push(("X-Poe-Duplicate", "true".to_string()));
+            }
+            (status, reply)
+        }
+        (Method::Post, ["api", "traces", trace_id, "spans"]) => {
+            match serde_json::from_slice::<SpanReport>(&body) {
+                Ok(report) => match shared.live.lock().unwrap().report(trace_id, report) {
+                    Ok(()) => (200, serde_json::json!({"status": "ok"}).to_string()),
+                    Err(e) => (400, serde_json::json!({ "error": e }).to_string()),
                 },
-            )
-            .unwrap(),
-        );
-    request.respond(response)?;
-    Ok(())
+                Err(e) => {
+                    let error = format!("invalid span report: {}", e);
+                    (400, serde_json::json!({ "error": error }).to_string())
+                }
+            }
+        }
+        (Method::Get, ["api", "traces", trace_id, "live"]) => {
+            let view = {
+                let live = shared.live.lock().unwrap();
+                let store = store.lock().unwrap();
+                live.view(trace_id, |run_id| store.index.contains_key(run_id))
+            };
+            match view {
+                Some(view) => (200, serde_json::to_string_pretty(&view)?),
+                None => (
+                    404,
+                    serde_json::json!({"error": "no live spans for trace"}).to_string(),
+                ),
+            }
+        }
+        _ => route(method, &segments, &url, &body, store)?,
+    };
+    let content_type = if status == 200 && path == "/" {
+        "text/html"
+    } else {
+        "application/json"
+    };
+    Ok(Reply::Text {
+        status,
+        content_type,
+        body,
+        headers,
+    })
+}
+
+/// Stores an uploaded pack. A pack already in the store byte for byte,
+/// as CI retries send, isn't stored again: the reply carries the existing
+/// id and is flagged as a duplicate.
+fn store_upload(
+    upload: Upload,
+    store: &Mutex<PackStore>,
+    metrics: &Metrics,
+) -> Result<(u16, String, bool)> {
+    let Upload { path, sha256, size } = upload;
+    let duplicate = |id: String| {
+        let _ = fs::remove_file(&path);
+        metrics.deduplicated(size);
+        let reply = serde_json::json!({"id": id, "status": "duplicate"});
+        Ok((200, reply.to_string(), true))
+    };
+
+    let known = store.lock().unwrap().duplicate_of(&sha256);
+    if let Some(id) = known {
+        return duplicate(id);
+    }
+    let staged = match StagedPack::new(path.clone(), sha256.clone()) {
+        Ok(staged) => staged,
+        Err(e) => {
+            let error = format!("{:#}", e);
+            return Ok((
+                400,
+                serde_json::json!({ "error": error }).to_string(),
+                false,
+            ));
+        }
+    };
+    let storage = Arc::clone(&store.lock().unwrap().storage);
+    let object = staged.put(storage.as_ref())?;
+    let mut store = store.lock().unwrap();
+    // The same pack may have been stored while this copy was staged; it
+    // was the same file, so storing it again changed nothing.
+    if let Some(id) = store.duplicate_of(&sha256) {
+        return duplicate(id);
+    }
+    let id = store.store_pack(staged, object)?;
+    metrics.uploaded(size);
+    let reply = serde_json::json!({"id": id, "status": "ok"});
+    Ok((200, reply.to_string(), false))
 }
 
 fn route(
-    method: &Method,
+    method: Method,
     segments: &[&str],
-    request: &mut Request,
-    store: &Arc<Mutex<PackStore>>,
+    url: &str,
+    body: &[u8],
+    store: &Mutex<PackStore>,
 ) -> Result<(u16, String)> {
     match (method, segments) {
         (Method::Get, ["api", "packs"]) => {
+            let filters = parse_tag_filters(url);
             let store = store.lock().unwrap();
-            let packs = store.list();
+            let packs = store.listing(&filters);
             Ok((200, serde_json::to_string_pretty(&packs)?))
         }
 
-        (Method::Post, ["api", "packs"]) => {
-            let mut body = Vec::new();
-            request.as_reader().read_to_end(&mut body)?;
+        (Method::Get, ["api", "search"]) => {
+            let terms = search::parse_terms(&query_param(url, "q").unwrap_or_default());
+            if terms.is_empty() {
+                return Ok((
+                    400,
+                    serde_json::json!({"error": "missing search query: ?q="}).to_string(),
+                ));
+            }
+            let page = match parse_page(url) {
+                Ok(page) => page,
+                Err(e) => {
+                    return Ok((400, serde_json::json!({"error": e}).to_string()));
+                }
+            };
+            let store = store.lock().unwrap();
+            let results: Vec<_> = store
+                .search(&terms)
+                .into_iter()
+                .skip(page.offset)
+                .take(page.limit.unwrap_or(DEFAULT_SEARCH_LIMIT))
+                .collect();
+            Ok((200, serde_json::to_string_pretty(&results)?))
+        }
 
-            let mut store = store.lock().unwrap();
-            match store.store_pack(&body) {
-                Ok(id) => Ok((
-                    200,
-                    serde_json::json!({"id": id, "status": "ok"}).to_string(),
-                )),
-                Err(e) => Ok((
+        (Method::Get, ["api", "clusters"]) => {
+            let store = store.lock().unwrap();
+            Ok((200, serde_json::to_string_pretty(&store.clusters())?))
+        }
+
+        (Method::Get, ["api", "traces"]) => {
+            let traces = store.lock().unwrap().traces();
+            Ok((200, serde_json::to_string_pretty(&traces)?))
+        }
+
+        (Method::Get, ["api", "traces", trace_id]) => {
+            let packs = store.lock().unwrap().trace_packs(trace_id);
+            if packs.is_empty() {
+                return Ok((
+                    404,
+                    serde_json::json!({"error": "no packs for trace"}).to_string(),
+                ));
+            }
+            let paths = packs
+                .iter()
+                .map(PackRef::fetch)
+                .collect::<Result<Vec<_>>>()?;
+            let Some(mut trace) = trace_context::correlate_packs(&paths)?.into_iter().next() else {
+                bail!("packs of trace {} have no spans", trace_id);
+            };
+            // Paths in the store mean nothing to clients; run_id is the
+            // pack id.
+            for span in &mut trace.spans {
+                span.pack_path = None;
+            }
+            Ok((200, serde_json::to_string_pretty(&trace)?))
+        }
+
+        (Method::Get, ["api", "trends"]) => {
+            let store = store.lock().unwrap();
+            match query_param(url, "command_hash") {
+                Some(hash) => match store.trends(Some(&hash)).into_iter().next() {
+                    Some(trend) => Ok((200, serde_json::to_string_pretty(&trend)?)),
+                    None => Ok((
+                        404,
+                        serde_json::json!({"error": "no packs for command_hash"}).to_string(),
+                    )),
+                },
+                None => {
+                    // The overview leaves out per-run points; fetch one
+                    // command's trend for those.
+                    let overview: Vec<_> = store
+                        .trends(None)
+                        .iter()
+                        .map(|t| {
+                            serde_json::json!({
+                                "command_hash": t.command_hash,
+                                "command": t.command,
+                                "runs": t.runs,
+                                "failures": t.failures,
+                                "failure_rate": t.failure_rate,
+                                "first_seen": t.first_seen,
+                                "last_seen": t.last_seen,
+                                "duration": t.duration,
+                            })
+                        })
+                        .collect();
+                    Ok((200, serde_json::to_string_pretty(&overview)?))
+                }
+            }
+        }
+
+        (Method::Get, ["api", "baselines"]) => {
+            let store = store.lock().unwrap();
+            Ok((200, serde_json::to_string_pretty(&store.baseline_list())?))
+        }
+
+        (Method::Put, ["api", "baselines", name]) => {
+            if let Err(e) = baseline::validate_name(name) {
+                return Ok((400, serde_json::json!({"error": e.to_string()}).to_string()));
+            }
+            let Some(id) = serde_json::from_slice::<serde_json::Value>(body)
+                .ok()
+                .and_then(|v| v["id"].as_str().map(String::from))
+            else {
+                return Ok((
                     400,
-                    serde_json::json!({"error": format!("{:#}", e)}).to_string(),
-                )),
+                    serde_json::json!({"error": "expected {\"id\": \"<pack id>\"}"}).to_string(),
+                ));
+            };
+            let mut store = store.lock().unwrap();
+            if !store.index.contains_key(&id) {
+                return Ok((
+                    404,
+                    serde_json::json!({"error": "pack not found"}).to_string(),
+                ));
             }
+            store.set_baseline(name, &id)?;
+            Ok((
+                200,
+                serde_json::json!({"name": name, "id": id, "status": "ok"}).to_string(),
+            ))
+        }
+
+        (Method::Get, ["api", "baselines", _, "pack"]) => Ok((
+            404,
+            serde_json::json!({"error": "baseline not found"}).to_string(),
+        )),
+
+        (Method::Get, ["api", "packs", _, "download"]) => Ok((
+            404,
+            serde_json::json!({"error": "pack not found"}).to_string(),
+        )),
+
+        (Method::Post | Method::Delete, ["api", "packs", id, "hold"]) => {
+            let mut store = store.lock().unwrap();
+            if !store.index.contains_key(*id) {
+                return Ok((
+                    404,
+                    serde_json::json!({"error": "pack not found"}).to_string(),
+                ));
+            }
+            let hold = method == Method::Post;
+            store.set_hold(id, hold)?;
+            Ok((200, serde_json::json!({"id": id, "hold": hold}).to_string()))
         }
 
         (Method::Get, ["api", "packs", id]) => {
-            let store = store.lock().unwrap();
-            if let Some(path) = store.get_path(id) {
-                let pack = PackReader::open(&path)?;
+            let pack = store.lock().unwrap().get(id);
+            if let Some(pack) = pack {
+                let pack = PackReader::open(&pack.fetch()?)?;
                 Ok((200, serde_json::to_string_pretty(pack.summary())?))
             } else {
                 Ok((
@@ -195,10 +962,19 @@ fn route(
         }
 
         (Method::Get, ["api", "packs", id, "explain"]) => {
-            let store = store.lock().unwrap();
-            if let Some(path) = store.get_path(id) {
-                let pack = PackReader::open(&path)?;
-                let output = analyzer::analyze(&pack)?;
+            let found = {
+                let store = store.lock().unwrap();
+                store.get(id).zip(store.sha256_of(id))
+            };
+            if let Some((pack, sha256)) = found {
+                let refresh = query_param(url, "refresh").as_deref() == Some("true");
+                let mut output = cache::explain(&pack.fetch()?, &sha256, refresh)?;
+                if let Some(fields) = query_param(url, "fields") {
+                    let names: Vec<&str> = fields.split(',').filter(|f| !f.is_empty()).collect();
+                    if let Err(e) = focus::select_fields(&mut output, &names) {
+                        return Ok((400, serde_json::json!({"error": e.to_string()}).to_string()));
+                    }
+                }
                 Ok((200, serde_json::to_string_pretty(&output)?))
             } else {
                 Ok((
@@ -209,14 +985,25 @@ fn route(
         }
 
         (Method::Get, ["api", "packs", id, "query", query]) => {
-            let store = store.lock().unwrap();
-            if let Some(path) = store.get_path(id) {
-                let pack = PackReader::open(&path)?;
+            let pack = store.lock().unwrap().get(id);
+            if let Some(pack) = pack {
+                let pack = PackReader::open(&pack.fetch()?)?;
                 let db = pack.db();
+                let page = match parse_page(url) {
+                    Ok(page) => page,
+                    Err(e) => {
+                        return Ok((400, serde_json::json!({"error": e}).to_string()));
+                    }
+                };
+                // Row tables are capped unless the caller asks for more.
+                let capped = Page {
+                    limit: Some(page.limit.unwrap_or(DEFAULT_ROW_LIMIT)),
+                    ..page
+                };
 
                 let result: serde_json::Value = match *query {
                     "processes" | "procs" => {
-                        let procs = db.query_processes()?;
+                        let procs = db.query_processes_page(page)?;
                         serde_json::to_value(
                             procs
                                 .iter()
@@ -233,11 +1020,10 @@ fn route(
                         )?
                     }
                     "files" => {
-                        let files = db.query_file_events()?;
+                        let files = db.query_file_events_page(capped)?;
                         serde_json::to_value(
                             files
                                 .iter()
-                                .take(500)
                                 .map(|f| {
                                     serde_json::json!({
                                         "ts_ms": f.ts as f64 / 1_000_000.0,
@@ -251,7 +1037,7 @@ fn route(
                         )?
                     }
                     "net" | "network" => {
-                        let net = db.query_net_events()?;
+                        let net = db.query_net_events_page(capped)?;
                         serde_json::to_value(
                             net.iter()
                                 .map(|n| {
@@ -295,3 +1081,205 @@ fn route(
         _ => Ok((404, serde_json::json!({"error": "not found"}).to_string())),
     }
 }
+
+/// A `?tag=` filter on the pack list: `key=value` matches that label,
+/// a bare `key` matches any pack carrying it.
+#[derive(Debug, PartialEq)]
+struct TagFilter {
+    key: String,
+    value: Option<String>,
+}
+
+impl TagFilter {
+    fn matches(&self, tags: &BTreeMap<String, String>) -> bool {
+        match (tags.get(&self.key), &self.value) {
+            (Some(v), Some(want)) => v == want,
+            (Some(_), None) => true,
+            (None, _) => false,
+        }
+    }
+}
+
+/// Reads every `tag` parameter from the request's query string; a pack
+/// must match all of them.
+fn parse_tag_filters(url: &str) -> Vec<TagFilter> {
+    let Some((_, query)) = url.split_once('?') else {
+        return Vec::new();
+    };
+    query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .filter(|(key, _)| *key == "tag")
+        .map(|(_, value)| {
+            let value = query_decode(value);
+            match value.split_once('=') {
+                Some((k, v)) => TagFilter {
+                    key: k.to_string(),
+                    value: Some(v.to_string()),
+                },
+                None => TagFilter {
+                    key: value,
+                    value: None,
+                },
+            }
+        })
+        .collect()
+}
+
+/// The decoded value of the first `key` parameter in the query string.
+fn query_param(url: &str, key: &str) -> Option<String> {
+    let (_, query) = url.split_once('?')?;
+    query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(k, _)| *k == key)
+        .map(|(_, v)| query_decode(v))
+}
+
+/// Decodes `+` and `%XX` escapes in a query string value.
+fn query_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => out.push(b' '),
+            b'%' => {
+                if let Some(b) = value
+                    .get(i + 1..i + 3)
+                    .and_then(|h| u8::from_str_radix(h, 16).ok())
+                {
+                    out.push(b);
+                    i += 3;
+                    continue;
+                }
+                out.push(b'%');
+            }
+            b => out.push(b),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Reads `limit` and `offset` from the request's query string.
+fn parse_page(url: &str) -> std::result::Result<Page, String> {
+    let mut page = Page::default();
+    let Some((_, query)) = url.split_once('?') else {
+        return Ok(page);
+    };
+    for pair in query.split('&') {
+        let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+        let parse = || {
+            value
+                .parse::<usize>()
+                .map_err(|_| format!("invalid {}: {}", key, value))
+        };
+        match key {
+            "limit" => page.limit = Some(parse()?),
+            "offset" => page.offset = parse()?,
+            _ => {}
+        }
+    }
+    Ok(page)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tag_filters_match_all_labels() {
+        let filters =
+            parse_tag_filters("/api/packs?tag=ci=nightly&limit=5&tag=branch%3Dmain&tag=job");
+        assert_eq!(filters.len(), 3);
+        assert_eq!(filters[1].value.as_deref(), Some("main"));
+
+        let mut tags = BTreeMap::new();
+        tags.insert("ci".to_string(), "nightly".to_string());
+        tags.insert("branch".to_string(), "main".to_string());
+        assert!(!filters.iter().all(|f| f.matches(&tags)));
+        tags.insert("job".to_string(), "unit".to_string());
+        assert!(filters.iter().all(|f| f.matches(&tags)));
+        tags.insert("ci".to_string(), "pr".to_string());
+        assert!(!filters.iter().all(|f| f.matches(&tags)));
+    }
+
+    #[test]
+    fn startup_reuses_catalog_entries_for_unchanged_packs() {
+        let dir = tempfile::tempdir().unwrap();
+        // Not a real pack: it can only be listed from the catalog.
+        let pack = dir.path().join("poe-abc.poepack");
+        fs::write(&pack, b"not a zip").unwrap();
+        let entry = |id: &str, filename: &str, (size, mtime_ns)| CatalogEntry {
+            size,
+            mtime_ns,
+            etag: String::new(),
+            sha256: String::new(),
+            meta: PackMeta {
+                id: id.into(),
+                filename: filename.into(),
+                uploaded_at: String::new(),
+                command: vec!["make".into()],
+                exit_code: Some(2),
+                signal: None,
+                duration_ms: 10,
+                tags: BTreeMap::new(),
+                command_hash: String::new(),
+                trace_id: Some("trace-1".into()),
+            },
+        };
+        let mut packs = BTreeMap::new();
+        packs.insert(
+            "poe-abc.poepack".into(),
+            entry(
+                "abc",
+                "poe-abc.poepack",
+                storage::fingerprint(&pack).unwrap(),
+            ),
+        );
+        packs.insert(
+            "poe-gone.poepack".into(),
+            entry("gone", "poe-gone.poepack", (1, 1)),
+        );
+        let catalog = Catalog {
+            version: CATALOG_VERSION,
+            packs,
+        };
+        fs::write(
+            dir.path().join("catalog.json"),
+            serde_json::to_vec(&catalog).unwrap(),
+        )
+        .unwrap();
+
+        let store = PackStore::new(dir.path()).unwrap();
+        assert_eq!(store.index.len(), 1);
+        assert_eq!(store.index["abc"].exit_code, Some(2));
+        let saved: Catalog =
+            serde_json::from_slice(&fs::read(dir.path().join("catalog.json")).unwrap()).unwrap();
+        assert_eq!(saved.packs.keys().collect::<Vec<_>>(), ["poe-abc.poepack"]);
+        // The content hash is filled in for entries cataloged without one.
+        assert_eq!(
+            saved.packs["poe-abc.poepack"].sha256,
+            util::hash_bytes(b"not a zip")
+        );
+        assert_eq!(
+            store
+                .duplicate_of(&util::hash_bytes(b"not a zip"))
+                .as_deref(),
+            Some("abc")
+        );
+        let traces = store.traces();
+        assert_eq!(traces.len(), 1);
+        assert_eq!(
+            (traces[0].packs.as_slice(), traces[0].failures),
+            (&["abc".to_string()][..], 1)
+        );
+        let paths: Vec<PathBuf> = store
+            .trace_packs("trace-1")
+            .iter()
+            .map(|p| p.fetch().unwrap())
+            .collect();
+        assert_eq!(paths, [dir.path().join("poe-abc.poepack")]);
+    }
+}
@@ -0,0 +1,623 @@
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, bail, Context, Result};
+use regex::Regex;
+
+use crate::pack::remote;
+use crate::serve::cache;
+
+const DEFAULT_REGION: &str = "us-east-1";
+
+/// A pack in the store, with what identifies its current contents.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StoredObject {
+    pub name: String,
+    pub size: u64,
+    /// Modification time (ns since the epoch) on disk; 0 in a bucket.
+    pub mtime_ns: u64,
+    /// The bucket's ETag; empty on disk.
+    pub etag: String,
+}
+
+/// Where the pack store keeps packs and its own JSON files. Packs are
+/// opened from local files, so a remote backend keeps the recently used
+/// ones in a local cache.
+pub trait Storage: Send + Sync {
+    /// For uploads in progress and local copies of packs.
+    fn local_dir(&self) -> &Path;
+    /// The `.poepack` files in the store.
+    fn list(&self) -> Result<Vec<StoredObject>>;
+    /// Moves a finished local file into the store as `name`.
+    fn put(&self, name: &str, local: &Path) -> Result<StoredObject>;
+    /// A local file with the contents of `name`.
+    fn fetch(&self, name: &str) -> Result<PathBuf>;
+    /// `None` if there is no `name` in the store.
+    fn read(&self, name: &str) -> Result<Option<Vec<u8>>>;
+    fn write(&self, name: &str, data: &[u8]) -> Result<()>;
+    /// Removes `name`, and any local copy and cached explain output; a
+    /// missing `name` is not an error.
+    fn delete(&self, name: &str) -> Result<()>;
+    fn describe(&self) -> String;
+}
+
+/// Opens `poe serve --store`: an `s3://bucket/prefix` or
+/// `gs://bucket/prefix` URL, or a directory.
+pub fn open(store: &str, cache_dir: Option<&Path>, cache_size: u64) -> Result<Arc<dyn Storage>> {
+    if store.starts_with("s3://") || store.starts_with("gs://") {
+        let cache_dir = match cache_dir {
+            Some(dir) => dir.to_path_buf(),
+            None => remote::cache_dir()?.join("serve"),
+        };
+        Ok(Arc::new(BucketStorage::new(store, &cache_dir, cache_size)?))
+    } else {
+        Ok(Arc::new(LocalStorage::new(Path::new(store))?))
+    }
+}
+
+/// Size and modification time (ns since the epoch) of a file.
+pub fn fingerprint(path: &Path) -> Option<(u64, u64)> {
+    let meta = fs::metadata(path).ok()?;
+    let mtime = meta.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+    Some((meta.len(), mtime.as_nanos() as u64))
+}
+
+fn is_pack(name: &str) -> bool {
+    name.ends_with(".poepack")
+}
+
+pub struct LocalStorage {
+    dir: PathBuf,
+}
+
+impl LocalStorage {
+    pub fn new(dir: &Path) -> Result<Self> {
+        fs::create_dir_all(dir)?;
+        Ok(Self {
+            dir: dir.to_path_buf(),
+        })
+    }
+
+    fn object(&self, name: &str) -> Option<StoredObject> {
+        let (size, mtime_ns) = fingerprint(&self.dir.join(name))?;
+        Some(StoredObject {
+            name: name.to_string(),
+            size,
+            mtime_ns,
+            etag: String::new(),
+        })
+    }
+}
+
+impl Storage for LocalStorage {
+    fn local_dir(&self) -> &Path {
+        &self.dir
+    }
+
+    fn list(&self) -> Result<Vec<StoredObject>> {
+        let mut objects = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let name = entry?.file_name().to_string_lossy().into_owned();
+            if is_pack(&name) {
+                objects.extend(self.object(&name));
+            }
+        }
+        Ok(objects)
+    }
+
+    fn put(&self, name: &str, local: &Path) -> Result<StoredObject> {
+        fs::rename(local, self.dir.join(name))?;
+        self.object(name)
+            .with_context(|| format!("{} vanished from the store", name))
+    }
+
+    fn fetch(&self, name: &str) -> Result<PathBuf> {
+        Ok(self.dir.join(name))
+    }
+
+    fn read(&self, name: &str) -> Result<Option<Vec<u8>>> {
+        match fs::read(self.dir.join(name)) {
+            Ok(data) => Ok(Some(data)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn write(&self, name: &str, data: &[u8]) -> Result<()> {
+        fs::write(self.dir.join(name), data)?;
+        Ok(())
+    }
+
+    fn delete(&self, name: &str) -> Result<()> {
+        remove_local(&self.dir.join(name))
+    }
+
+    fn describe(&self) -> String {
+        self.dir.display().to_string()
+    }
+}
+
+/// An S3 bucket, or a GCS bucket through its S3-compatible API, reached
+/// with curl's SigV4 signing. Credentials come from `AWS_ACCESS_KEY_ID`,
+/// `AWS_SECRET_ACCESS_KEY` and `AWS_SESSION_TOKEN` (HMAC keys for GCS);
+/// without them requests go unsigned. `POE_S3_ENDPOINT` points at another
+/// S3-compatible service, e.g. MinIO.
+pub struct BucketStorage {
+    url: String,
+    /// Requests go to `<base>/<key>`.
+    base: String,
+    /// Empty, or ends with `/`.
+    prefix: String,
+    region: String,
+    cache: PathBuf,
+    /// Least recently used packs are evicted from `cache` beyond this.
+    cache_size: u64,
+}
+
+struct Response {
+    status: u16,
+    headers: String,
+}
+
+impl BucketStorage {
+    pub fn new(url: &str, cache_dir: &Path, cache_size: u64) -> Result<Self> {
+        let Some((scheme, rest)) = url.split_once("://") else {
+            bail!("invalid store URL: {}", url);
+        };
+        let (bucket, prefix) = rest.split_once('/').unwrap_or((rest, ""));
+        if bucket.is_empty() {
+            bail!("no bucket in store URL: {}", url);
+        }
+        let prefix = prefix.trim_matches('/');
+        let region = std::env::var("AWS_REGION")
+            .or_else(|_| std::env::var("AWS_DEFAULT_REGION"))
+            .ok()
+            .filter(|r| !r.is_empty())
+            .unwrap_or_else(|| {
+                if scheme == "gs" {
+                    "auto"
+                } else {
+                    DEFAULT_REGION
+                }
+                .to_string()
+            });
+        let base = match std::env::var("POE_S3_ENDPOINT") {
+            Ok(endpoint) if !endpoint.is_empty() => {
+                format!("{}/{}", endpoint.trim_end_matches('/'), bucket)
+            }
+            _ if scheme == "gs" => format!("https://storage.googleapis.com/{}", bucket),
+            _ => format!("https://{}.s3.{}.amazonaws.com", bucket, region),
+        };
+        let cache = cache_dir.join(bucket);
+        fs::create_dir_all(&cache)
+            .with_context(|| format!("failed to create {}", cache.display()))?;
+        Ok(Self {
+            url: url.to_string(),
+            base,
+            prefix: if prefix.is_empty() {
+                String::new()
+            } else {
+                format!("{}/", prefix)
+            },
+            region,
+            cache,
+            cache_size,
+        })
+    }
+
+    fn key(&self, name: &str) -> String {
+        format!("{}{}", self.prefix, name)
+    }
+
+    fn temp_path(&self) -> PathBuf {
+        self.cache
+            .join(format!("temp-{}.download", uuid::Uuid::new_v4()))
+    }
+
+    /// Sends one request, writing the response body to `output`.
+    fn request(
+        &self,
+        method: &str,
+        key: &str,
+        query: &str,
+        upload: Option<&Path>,
+        output: &Path,
+    ) -> Result<Response> {
+        let mut url = format!("{}/{}", self.base, encode(key, false));
+        if !query.is_empty() {
+            url.push('?');
+            url.push_str(query);
+        }
+        let mut cmd = Command::new("curl");
+        cmd.args(["-sS", "--retry", "3", "-X", method, "-D", "-"])
+            .args(["-w", "\n%{http_code}", "-o"])
+            .arg(output)
+            // Uploads are streamed from disk rather than hashed first.
+            .args(["-H", "x-amz-content-sha256: UNSIGNED-PAYLOAD"]);
+        if let Some(path) = upload {
+            cmd.arg("-T").arg(path);
+        }
+        // Keys are passed on stdin: the command line is visible to every
+        // user on the host.
+        let config = credentials();
+        if config.is_some() {
+            cmd.arg("--aws-sigv4")
+                .arg(format!("aws:amz:{}:s3", self.region))
+                .args(["-K", "-"]);
+        }
+        let mut child = cmd
+            .arg(&url)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .context("failed to run curl")?;
+        let mut stdin = child.stdin.take().unwrap();
+        if let Some(config) = config {
+            stdin.write_all(config.as_bytes())?;
+        }
+        drop(stdin);
+        let out = child.wait_with_output()?;
+        let stdout = String::from_utf8_lossy(&out.stdout);
+        let (headers, code) = stdout.trim_end().rsplit_once('\n').unwrap_or(("", &stdout));
+        let status: u16 = code.trim().parse().unwrap_or(0);
+        if !out.status.success() || status == 0 {
+            bail!(
+                "{} {}: {}",
+                method,
+                url,
+                String::from_utf8_lossy(&out.stderr).trim()
+            );
+        }
+        Ok(Response {
+            status,
+            headers: headers.to_string(),
+        })
+    }
+
+    /// An error for a failed request, with the bucket's message from the
+    /// response body in `output`, which is removed.
+    fn failure(&self, what: &str, status: u16, output: &Path) -> anyhow::Error {
+        let body = fs::read_to_string(output).unwrap_or_default();
+        let _ = fs::remove_file(output);
+        let message = Regex::new(r"(?s)<Message>(.*?)</Message>")
+            .unwrap()
+            .captures(&body)
+            .map(|c| format!(": {}", unescape(&c[1])))
+            .unwrap_or_default();
+        anyhow!("{} {}: HTTP {}{}", what, self.url, status, message)
+    }
+
+    /// Downloads `name` to a temp file in the cache.
+    fn download(&self, name: &str) -> Result<Option<PathBuf>> {
+        let temp = self.temp_path();
+        let response = self.request("GET", &self.key(name), "", None, &temp);
+        match response {
+            Ok(r) if r.status == 200 => Ok(Some(temp)),
+            Ok(r) if r.status == 404 => {
+                let _ = fs::remove_file(&temp);
+                Ok(None)
+            }
+            Ok(r) => Err(self.failure(&format!("reading {} from", name), r.status, &temp)),
+            Err(e) => {
+                let _ = fs::remove_file(&temp);
+                Err(e)
+            }
+        }
+    }
+
+    /// Deletes the least recently used packs, and their cached explain
+    /// output, until the cache fits in `cache_size`; never `keep`.
+    fn evict(&self, keep: &Path) {
+        let Ok(entries) = fs::read_dir(&self.cache) else {
+            return;
+        };
+        let mut packs: Vec<(SystemTime, u64, PathBuf)> = entries
+            .flatten()
+            .map(|e| e.path())
+            .filter(|p| p.file_name().is_some_and(|n| is_pack(&n.to_string_lossy())))
+            .filter_map(|p| {
+                let meta = fs::metadata(&p).ok()?;
+                Some((meta.modified().ok()?, meta.len(), p))
+            })
+            .collect();
+        let mut total: u64 = packs.iter().map(|(_, size, _)| size).sum();
+        packs.sort();
+        for (_, size, path) in packs {
+            if total <= self.cache_size {
+                break;
+            }
+            if path != keep && fs::remove_file(&path).is_ok() {
+                let _ = fs::remove_file(cache::cache_path(&path));
+                total -= size;
+            }
+        }
+    }
+}
+
+impl Storage for BucketStorage {
+    fn local_dir(&self) -> &Path {
+        &self.cache
+    }
+
+    fn list(&self) -> Result<Vec<StoredObject>> {
+        let mut objects = Vec::new();
+        let mut token: Option<String> = None;
+        loop {
+            // Parameters in sorted order, as SigV4 signs them.
+            let mut query = String::new();
+            if let Some(token) = &token {
+                query.push_str(&format!("continuation-token={}&", encode(token, true)));
+            }
+            query.push_str(&format!(
+                "list-type=2&prefix={}",
+                encode(&self.prefix, true)
+            ));
+            let temp = self.temp_path();
+            let response = self.request("GET", "", &query, None, &temp);
+            let status = match response {
+                Ok(r) => r.status,
+                Err(e) => {
+                    let _ = fs::remove_file(&temp);
+                    return Err(e);
+                }
+            };
+            if status != 200 {
+                return Err(self.failure("listing", status, &temp));
+            }
+            let body = fs::read_to_string(&temp).unwrap_or_default();
+            let _ = fs::remove_file(&temp);
+            let (page, next) = parse_listing(&body, &self.prefix);
+            objects.extend(page);
+            match next {
+                Some(next) => token = Some(next),
+                None => return Ok(objects),
+            }
+        }
+    }
+
+    fn put(&self, name: &str, local: &Path) -> Result<StoredObject> {
+        let size = fs::metadata(local)?.len();
+        let temp = self.temp_path();
+        let response = match self.request("PUT", &self.key(name), "", Some(local), &temp) {
+            Ok(r) => r,
+            Err(e) => {
+                let _ = fs::remove_file(&temp);
+                return Err(e);
+            }
+        };
+        if !(200..300).contains(&response.status) {
+            return Err(self.failure(&format!("uploading {} to", name), response.status, &temp));
+        }
+        let _ = fs::remove_file(&temp);
+        // The upload stays on as the cached copy.
+        let path = self.cache.join(name);
+        fs::rename(local, &path)?;
+        self.evict(&path);
+        Ok(StoredObject {
+            name: name.to_string(),
+            size,
+            mtime_ns: 0,
+            etag: header(&response.headers, "etag")
+                .map(|e| e.trim_matches('"').to_string())
+                .unwrap_or_default(),
+        })
+    }
+
+    fn fetch(&self, name: &str) -> Result<PathBuf> {
+        let path = self.cache.join(name);
+        if path.exists() {
+            // Marks the copy as recently used.
+            let _ = fs::File::open(&path).and_then(|f| f.set_modified(SystemTime::now()));
+            return Ok(path);
+        }
+        let temp = self
+            .download(name)?
+            .with_context(|| format!("{} is not in {}", name, self.url))?;
+        fs::rename(&temp, &path)?;
+        self.evict(&path);
+        Ok(path)
+    }
+
+    fn read(&self, name: &str) -> Result<Option<Vec<u8>>> {
+        let Some(temp) = self.download(name)? else {
+            return Ok(None);
+        };
+        let data = fs::read(&temp);
+        let _ = fs::remove_file(&temp);
+        Ok(Some(data?))
+    }
+
+    fn write(&self, name: &str, data: &[u8]) -> Result<()> {
+        let temp = self.temp_path();
+        fs::write(&temp, data)?;
+        let output = self.temp_path();
+        let response = self.request("PUT", &self.key(name), "", Some(&temp), &output);
+        let _ = fs::remove_file(&temp);
+        let status = match response {
+            Ok(r) => r.status,
+            Err(e) => {
+                let _ = fs::remove_file(&output);
+                return Err(e);
+            }
+        };
+        if !(200..300).contains(&status) {
+            return Err(self.failure(&format!("writing {} to", name), status, &output));
+        }
+        let _ = fs::remove_file(&output);
+        Ok(())
+    }
+
+    fn delete(&self, name: &str) -> Result<()> {
+        let output = self.temp_path();
+        let response = self.request("DELETE", &self.key(name), "", None, &output);
+        let status = match response {
+            Ok(r) => r.status,
+            Err(e) => {
+                let _ = fs::remove_file(&output);
+                return Err(e);
+            }
+        };
+        if !(200..300).contains(&status) && status != 404 {
+            return Err(self.failure(&format!("deleting {} from", name), status, &output));
+        }
+        let _ = fs::remove_file(&output);
+        remove_local(&self.cache.join(name))
+    }
+
+    fn describe(&self) -> String {
+        format!("{} (cached in {})", self.url, self.cache.display())
+    }
+}
+
+/// Removes a file and the explain output cached beside it.
+fn remove_local(path: &Path) -> Result<()> {
+    let _ = fs::remove_file(cache::cache_path(path));
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+/// A curl config setting the SigV4 keys, from the environment.
+fn credentials() -> Option<String> {
+    let key = std::env::var("AWS_ACCESS_KEY_ID")
+        .ok()
+        .filter(|k| !k.is_empty())?;
+    let secret = std::env::var("AWS_SECRET_ACCESS_KEY").ok()?;
+    let quote = |s: &str| s.replace('\\', "\\\\").replace('"', "\\\"");
+    let mut config = format!("user = \"{}\"\n", quote(&format!("{}:{}", key, secret)));
+    if let Ok(token) = std::env::var("AWS_SESSION_TOKEN") {
+        if !token.is_empty() {
+            config.push_str(&format!(
+                "header = \"{}\"\n",
+                quote(&format!("x-amz-security-token: {}", token))
+            ));
+        }
+    }
+    Some(config)
+}
+
+/// Packs directly under `prefix` in a ListObjectsV2 response, and the
+/// token for the next page if there is one.
+fn parse_listing(body: &str, prefix: &str) -> (Vec<StoredObject>, Option<String>) {
+    let field = |xml: &str, tag: &str| {
+        Regex::new(&format!(r"(?s)<{0}>(.*?)</{0}>", tag))
+            .unwrap()
+            .captures(xml)
+            .map(|c| unescape(&c[1]))
+    };
+    let objects = Regex::new(r"(?s)<Contents>(.*?)</Contents>")
+        .unwrap()
+        .captures_iter(body)
+        .filter_map(|c| {
+            let key = field(&c[1], "Key")?;
+            let name = key.strip_prefix(prefix)?;
+            if name.contains('/') || !is_pack(name) {
+                return None;
+            }
+            Some(StoredObject {
+                name: name.to_string(),
+                size: field(&c[1], "Size")?.parse().ok()?,
+                mtime_ns: 0,
+                etag: field(&c[1], "ETag")
+                    .unwrap_or_default()
+                    .trim_matches('"')
+                    .to_string(),
+            })
+        })
+        .collect();
+    let next = (field(body, "IsTruncated").as_deref() == Some("true"))
+        .then(|| field(body, "NextContinuationToken"))
+        .flatten();
+    (objects, next)
+}
+
+fn unescape(s: &str) -> String {
+    s.replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&")
+}
+
+fn header<'a>(headers: &'a str, name: &str) -> Option<&'a str> {
+    headers
+        .lines()
+        .rev()
+        .filter_map(|line| line.split_once(':'))
+        .find(|(key, _)| key.trim().eq_ignore_ascii_case(name))
+        .map(|(_, value)| value.trim())
+}
+
+/// Percent-encodes all but unreserved characters, and `/` unless
+/// `slash` is set.
+fn encode(s: &str, slash: bool) -> String {
+    let mut out = String::new();
+    for b in s.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                out.push(b as char)
+            }
+            b'/' if !slash => out.push('/'),
+            _ => out.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lists_packs_directly_under_the_prefix() {
+        let body = r#"<?xml version="1.0" encoding="UTF-8"?>
+<ListBucketResult><Name>b</Name><Prefix>ci/</Prefix><IsTruncated>true</IsTruncated>
+<Contents><Key>ci/poe-1.poepack</Key><Size>120</Size><ETag>&quot;abc&quot;</ETag></Contents>
+<Contents><Key>ci/catalog.json</Key><Size>9</Size><ETag>&quot;def&quot;</ETag></Contents>
+<Contents><Key>ci/old/poe-2.poepack</Key><Size>7</Size><ETag>&quot;ghi&quot;</ETag></Contents>
+<NextContinuationToken>1/x+y=</NextContinuationToken></ListBucketResult>"#;
+        let (objects, next) = parse_listing(body, "ci/");
+        assert_eq!(
+            objects,
+            [StoredObject {
+                name: "poe-1.poepack".into(),
+                size: 120,
+                mtime_ns: 0,
+                etag: "abc".into(),
+            }]
+        );
+        assert_eq!(next.as_deref(), Some("1/x+y="));
+        assert_eq!(encode(next.as_deref().unwrap(), true), "1%2Fx%2By%3D");
+    }
+
+    #[test]
+    fn evicts_least_recently_used_packs() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = BucketStorage::new("s3://bucket/ci", dir.path(), 10).unwrap();
+        let now = SystemTime::now();
+        for (i, name) in ["old", "mid", "new"].iter().enumerate() {
+            let path = storage.cache.join(format!("{}.poepack", name));
+            fs::write(&path, b"12345").unwrap();
+            let age = std::time::Duration::from_secs(100 - i as u64);
+            fs::File::open(&path)
+                .unwrap()
+                .set_modified(now - age)
+                .unwrap();
+        }
+        fs::write(storage.cache.join("old.explain.json"), b"{}").unwrap();
+        // The oldest is kept while in use.
+        storage.evict(&storage.cache.join("old.poepack"));
+        let mut left: Vec<String> = fs::read_dir(&storage.cache)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        left.sort();
+        assert_eq!(left, ["new.poepack", "old.explain.json", "old.poepack"]);
+    }
+}
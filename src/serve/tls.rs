@@ -0,0 +1,112 @@
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use http_body_util::Empty;
+use hyper::body::Bytes;
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::{Response, StatusCode};
+use hyper_util::rt::{TokioIo, TokioTimer};
+use tokio::net::TcpListener;
+use tokio_rustls::rustls::ServerConfig;
+use tokio_rustls::TlsAcceptor;
+
+/// Builds the TLS acceptor from a PEM certificate chain and private key
+/// (PKCS#8, RSA or EC).
+pub fn load(cert: &Path, key: &Path) -> Result<TlsAcceptor> {
+    let certificate = std::fs::read(cert)
+        .with_context(|| format!("failed to read TLS certificate {}", cert.display()))?;
+    let private_key =
+        std::fs::read(key).with_context(|| format!("failed to read TLS key {}", key.display()))?;
+    let chain = rustls_pemfile::certs(&mut certificate.as_slice())
+        .collect::<std::result::Result<Vec<_>, _>>()
+        .with_context(|| format!("invalid PEM in {}", cert.display()))?;
+    if chain.is_empty() {
+        bail!("{} holds no PEM certificate", cert.display());
+    }
+    let Some(private_key) = rustls_pemfile::private_key(&mut private_key.as_slice())
+        .with_context(|| format!("invalid PEM in {}", key.display()))?
+    else {
+        bail!("{} holds no unencrypted private key", key.display());
+    };
+    let mut config = ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(chain, private_key)
+        .context("certificate and key don't make a usable TLS configuration")?;
+    config.alpn_protocols = vec![b"http/1.1".to_vec()];
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
+/// Answers plain HTTP on `listener` with a redirect to the same path on
+/// the HTTPS port.
+pub async fn redirect(listener: TcpListener, https_port: u16, timeout: Duration) {
+    while let Ok((stream, _)) = listener.accept().await {
+        tokio::spawn(async move {
+            let service = service_fn(move |req: hyper::Request<hyper::body::Incoming>| {
+                let host = req
+                    .headers()
+                    .get(hyper::header::HOST)
+                    .and_then(|h| h.to_str().ok());
+                let url = req
+                    .uri()
+                    .path_and_query()
+                    .map(|p| p.as_str())
+                    .unwrap_or("/");
+                let location = redirect_location(host, https_port, url);
+                let response = Response::builder()
+                    .status(StatusCode::MOVED_PERMANENTLY)
+                    .header(hyper::header::LOCATION, location)
+                    .body(Empty::<Bytes>::new());
+                async move { response }
+            });
+            let _ = http1::Builder::new()
+                .timer(TokioTimer::new())
+                .header_read_timeout(timeout)
+                .serve_connection(TokioIo::new(stream), service)
+                .await;
+        });
+    }
+}
+
+/// `https://<host>[:port]<url>`, keeping the host the client asked for and
+/// swapping in the HTTPS port.
+fn redirect_location(host: Option<&str>, https_port: u16, url: &str) -> String {
+    let host = host.unwrap_or("localhost");
+    let name = match host.rsplit_once(':') {
+        // Leave bracketed IPv6 literals without a port alone.
+        Some((name, port)) if !port.contains(']') => name,
+        _ => host,
+    };
+    if https_port == 443 {
+        format!("https://{}{}", name, url)
+    } else {
+        format!("https://{}:{}{}", name, https_port, url)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redirects_keep_host_and_path() {
+        assert_eq!(
+            redirect_location(Some("poe.internal:8080"), 8443, "/api/packs?tag=ci"),
+            "https://poe.internal:8443/api/packs?tag=ci"
+        );
+        assert_eq!(
+            redirect_location(Some("poe.internal"), 443, "/"),
+            "https://poe.internal/"
+        );
+        assert_eq!(
+            redirect_location(Some("[::1]:80"), 3443, "/x"),
+            "https://[::1]:3443/x"
+        );
+        assert_eq!(
+            redirect_location(Some("[::1]"), 3443, "/x"),
+            "https://[::1]:3443/x"
+        );
+    }
+}
@@ -1,5 +1,6 @@
 use std::collections::HashMap;
-use std::fs;
+use std::fs::File;
+use std::os::unix::fs::FileExt;
 use std::path::Path;
 
 use anyhow::Result;
@@ -9,6 +10,9 @@ use crate::util::procfs::{self, MemoryMapping};
 pub struct SymbolResolver {
     mappings: Vec<MemoryMapping>,
     cache: HashMap<u64, Option<ResolvedSymbol>>,
+    /// Symbol tables by module path, kept across `load_maps` so processes
+    /// sharing libraries don't reread them.
+    tables: HashMap<String, Option<SymbolTable>>,
 }
 
 #[derive(Debug, Clone)]
@@ -31,6 +35,7 @@ impl SymbolResolver {
         Self {
             mappings: Vec::new(),
             cache: HashMap::new(),
+            tables: HashMap::new(),
         }
     }
 
@@ -55,13 +60,14 @@ impl SymbolResolver {
         result
     }
 
-    fn resolve_uncached(&self, addr: u64) -> Option<ResolvedSymbol> {
+    fn resolve_uncached(&mut self, addr: u64) -> Option<ResolvedSymbol> {
         let mapping = self
             .mappings
             .iter()
             .find(|m| addr >= m.start && addr < m.end && m.permissions.contains('x'))?;
 
         let module_path = mapping.path.as_ref()?;
+        let file_offset = addr - mapping.start + mapping.offset;
 
         if module_path.starts_with('[') {
             return Some(ResolvedSymbol {
@@ -73,18 +79,29 @@ impl SymbolResolver {
             });
         }
 
-        let file_offset = addr - mapping.start + mapping.offset;
-
-        let resolved = resolve_from_elf(module_path, file_offset, addr);
-        if resolved.is_some() {
-            return resolved;
+        let module_path = module_path.clone();
+        let table = self
+            .tables
+            .entry(module_path.clone())
+            .or_insert_with(|| SymbolTable::load(Path::new(&module_path)));
+        if let Some(table) = table {
+            let lookup_addr = if table.absolute { addr } else { file_offset };
+            if let Some((offset, function)) = table.lookup(lookup_addr) {
+                return Some(ResolvedSymbol {
+                    function,
+                    file: None,
+                    line: None,
+                    module: module_name(&module_path),
+                    offset,
+                });
+            }
         }
 
         Some(ResolvedSymbol {
             function: format!("{:#x}", addr),
             file: None,
             line: None,
-            module: module_path.clone(),
+            module: module_path,
             offset: file_offset,
         })
     }
@@ -94,169 +111,116 @@ impl SymbolResolver {
     }
 }
 
-fn resolve_from_elf(elf_path: &str, file_offset: u64, addr: u64) -> Option<ResolvedSymbol> {
-    let data = fs::read(elf_path).ok()?;
-
-    let module = Path::new(elf_path)
+fn module_name(elf_path: &str) -> String {
+    Path::new(elf_path)
         .file_name()
         .map(|f| f.to_string_lossy().into_owned())
-        .unwrap_or_else(|| elf_path.to_string());
-
-    resolve_elf_symbol(&data, file_offset, addr, &module)
+        .unwrap_or_else(|| elf_path.to_string())
 }
 
-fn resolve_elf_symbol(
-    elf_data: &[u8],
-    file_offset: u64,
-    addr: u64,
-    module: &str,
-) -> Option<ResolvedSymbol> {
-    if elf_data.len() < 16 || &elf_data[0..4] != b"\x7fELF" {
-        return None;
-    }
-
-    let is_64 = elf_data[4] == 2;
-    let is_le = elf_data[5] == 1;
-
-    if !is_64 || !is_le {
-        return None;
-    }
-
-    let e_shoff = u64::from_le_bytes(elf_data.get(40..48)?.try_into().ok()?);
-    let e_shentsize = u16::from_le_bytes(elf_data.get(58..60)?.try_into().ok()?) as usize;
-    let e_shnum = u16::from_le_bytes(elf_data.get(60..62)?.try_into().ok()?) as usize;
-    let _e_shstrndx = u16::from_le_bytes(elf_data.get(62..64)?.try_into().ok()?) as usize;
-
-    let mut symtab_offset = 0u64;
-    let mut symtab_size = 0u64;
-    let mut symtab_entsize = 0u64;
-    let mut strtab_offset = 0u64;
-    let mut _strtab_size = 0u64;
-    let mut found_symtab = false;
-
-    for i in 0..e_shnum {
-        let sh_start = e_shoff as usize + i * e_shentsize;
-        if sh_start + e_shentsize > elf_data.len() {
-            break;
-        }
-
-        let sh_type =
-            u32::from_le_bytes(elf_data.get(sh_start + 4..sh_start + 8)?.try_into().ok()?);
-
-        if sh_type == 2 || sh_type == 11 {
-            symtab_offset = u64::from_le_bytes(
-                elf_data
-                    .get(sh_start + 24..sh_start + 32)?
-                    .try_into()
-                    .ok()?,
-            );
-            symtab_size = u64::from_le_bytes(
-                elf_data
-                    .get(sh_start + 32..sh_start + 40)?
-                    .try_into()
-                    .ok()?,
-            );
-            symtab_entsize = u64::from_le_bytes(
-                elf_data
-                    .get(sh_start + 56..sh_start + 64)?
-                    .try_into()
-                    .ok()?,
-            );
-
-            let strtab_idx = u32::from_le_bytes(
-                elf_data
-                    .get(sh_start + 40..sh_start + 44)?
-                    .try_into()
-                    .ok()?,
-            ) as usize;
-
-            let str_sh_start = e_shoff as usize + strtab_idx * e_shentsize;
-            strtab_offset = u64::from_le_bytes(
-                elf_data
-                    .get(str_sh_start + 24..str_sh_start + 32)?
-                    .try_into()
-                    .ok()?,
-            );
-            _strtab_size = u64::from_le_bytes(
-                elf_data
-                    .get(str_sh_start + 32..str_sh_start + 40)?
-                    .try_into()
-                    .ok()?,
-            );
+/// Function and object symbols of one ELF module, sorted by address. Only
+/// the symbol and string tables are read, not the whole file.
+struct SymbolTable {
+    /// An `ET_EXEC` module: symbol values are the addresses it runs at
+    /// rather than offsets.
+    absolute: bool,
+    /// (value, size, offset of the name in `names`)
+    symbols: Vec<(u64, u64, usize)>,
+    names: Vec<u8>,
+}
 
-            found_symtab = true;
-            if sh_type == 2 {
-                break;
+impl SymbolTable {
+    fn load(path: &Path) -> Option<Self> {
+        let file = File::open(path).ok()?;
+        let file_len = file.metadata().ok()?.len();
+        let read_at = |offset: u64, len: u64| -> Option<Vec<u8>> {
+            if offset.checked_add(len)? > file_len {
+                return None;
             }
+            let mut buf = vec![0; len as usize];
+            file.read_exact_at(&mut buf, offset).ok()?;
+            Some(buf)
+        };
+        let u16_at =
+            |d: &[u8], at: usize| Some(u16::from_le_bytes(d.get(at..at + 2)?.try_into().ok()?));
+        let u32_at =
+            |d: &[u8], at: usize| Some(u32::from_le_bytes(d.get(at..at + 4)?.try_into().ok()?));
+        let u64_at =
+            |d: &[u8], at: usize| Some(u64::from_le_bytes(d.get(at..at + 8)?.try_into().ok()?));
+
+        let header = read_at(0, 64)?;
+        // 64-bit little-endian only.
+        if &header[0..4] != b"\x7fELF" || header[4] != 2 || header[5] != 1 {
+            return None;
         }
-    }
-
-    if !found_symtab || symtab_entsize == 0 {
-        return None;
-    }
-
-    let e_type = u16::from_le_bytes(elf_data.get(16..18)?.try_into().ok()?);
-    let lookup_addr = if e_type == 2 { addr } else { file_offset };
-
-    let num_syms = (symtab_size / symtab_entsize) as usize;
-    let mut best_match: Option<(u64, String)> = None;
-
-    for i in 0..num_syms {
-        let sym_start = symtab_offset as usize + i * symtab_entsize as usize;
-        if sym_start + symtab_entsize as usize > elf_data.len() {
-            break;
-        }
-
-        let st_name =
-            u32::from_le_bytes(elf_data.get(sym_start..sym_start + 4)?.try_into().ok()?) as usize;
-        let st_info = elf_data.get(sym_start + 4)?;
-        let st_value = u64::from_le_bytes(
-            elf_data
-                .get(sym_start + 8..sym_start + 16)?
-                .try_into()
-                .ok()?,
-        );
-        let st_size = u64::from_le_bytes(
-            elf_data
-                .get(sym_start + 16..sym_start + 24)?
-                .try_into()
-                .ok()?,
-        );
-
-        let sym_type = st_info & 0xf;
-        if sym_type != 1 && sym_type != 2 {
-            continue;
+        let e_type = u16_at(&header, 16)?;
+        let e_shoff = u64_at(&header, 40)?;
+        let e_shentsize = u16_at(&header, 58)? as usize;
+        let e_shnum = u16_at(&header, 60)? as usize;
+        if e_shentsize < 64 {
+            return None;
         }
-
-        if st_value == 0 {
-            continue;
-        }
-
-        if lookup_addr >= st_value && lookup_addr < st_value + st_size.max(1) {
-            let name_start = strtab_offset as usize + st_name;
-            if name_start < elf_data.len() {
-                let name_end = elf_data[name_start..]
-                    .iter()
-                    .position(|&b| b == 0)
-                    .unwrap_or(0);
-                let name = String::from_utf8_lossy(&elf_data[name_start..name_start + name_end])
-                    .into_owned();
-
-                let distance = lookup_addr - st_value;
-                if best_match.as_ref().is_none_or(|(d, _)| distance < *d) {
-                    best_match = Some((distance, name));
+        let sections = read_at(e_shoff, (e_shnum * e_shentsize) as u64)?;
+        let section = |i: usize| sections.get(i * e_shentsize..(i + 1) * e_shentsize);
+
+        // Prefer .symtab; fall back to .dynsym for stripped modules.
+        let mut symtab = None;
+        for i in 0..e_shnum {
+            let sh = section(i)?;
+            match u32_at(sh, 4)? {
+                2 => {
+                    symtab = Some(sh);
+                    break;
                 }
+                11 => symtab = Some(sh),
+                _ => {}
             }
         }
+        let symtab = symtab?;
+        let entsize = u64_at(symtab, 56)?;
+        if entsize < 24 {
+            return None;
+        }
+        let strtab = section(u32_at(symtab, 40)? as usize)?;
+        let raw = read_at(u64_at(symtab, 24)?, u64_at(symtab, 32)?)?;
+        let names = read_at(u64_at(strtab, 24)?, u64_at(strtab, 32)?)?;
+
+        let mut symbols: Vec<(u64, u64, usize)> = raw
+            .chunks_exact(entsize as usize)
+            .filter_map(|sym| {
+                let st_type = sym[4] & 0xf;
+                let value = u64_at(sym, 8)?;
+                // STT_OBJECT and STT_FUNC with an address.
+                if !(st_type == 1 || st_type == 2) || value == 0 {
+                    return None;
+                }
+                Some((value, u64_at(sym, 16)?, u32_at(sym, 0)? as usize))
+            })
+            .collect();
+        symbols.sort_by_key(|&(value, _, _)| value);
+
+        Some(Self {
+            absolute: e_type == 2,
+            symbols,
+            names,
+        })
     }
 
-    best_match.map(|(offset, function)| ResolvedSymbol {
-        function,
-        file: None,
-        line: None,
-        module: module.to_string(),
-        offset,
-    })
+    /// The nearest symbol starting at or before `addr` that covers it, with
+    /// the distance from its start.
+    fn lookup(&self, addr: u64) -> Option<(u64, String)> {
+        let end = self.symbols.partition_point(|&(value, _, _)| value <= addr);
+        let &(value, _, name) = self.symbols[..end]
+            .iter()
+            .rev()
+            .find(|&&(value, size, _)| addr < value.saturating_add(size.max(1)))?;
+        let name = self.names.get(name..)?;
+        let len = name.iter().position(|&b| b == 0).unwrap_or(0);
+        Some((
+            addr - value,
+            String::from_utf8_lossy(&name[..len]).into_owned(),
+        ))
+    }
 }
 
 pub fn format_frame(sym: &Option<ResolvedSymbol>, addr: u64) -> String {
@@ -279,3 +243,25 @@ pub fn format_frame(sym: &Option<ResolvedSymbol>, addr: u64) -> String {
         None => format!("{:#x}: ???", addr),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn symbol_table_finds_the_symbol_covering_an_address() {
+        let exe = std::env::current_exe().unwrap();
+        let table = SymbolTable::load(&exe).expect("test binary has a symbol table");
+        assert!(table.symbols.windows(2).all(|w| w[0].0 <= w[1].0));
+
+        let &(value, size, _) = table
+            .symbols
+            .iter()
+            .find(|&&(_, size, _)| size > 1)
+            .unwrap();
+        let (offset, name) = table.lookup(value + 1).unwrap();
+        assert!(!name.is_empty());
+        assert!(offset >= 1 && offset < size);
+        assert!(table.lookup(0).is_none());
+    }
+}
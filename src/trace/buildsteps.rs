@@ -0,0 +1,313 @@
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+use anyhow::Result;
+use regex::Regex;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::trace::db::{ProcessQueryResult, TraceDb};
+
+const MAX_FAILED_OPS: usize = 5;
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct FailedOp {
+    pub op: String,
+    pub path: String,
+    pub errno: i64,
+}
+
+/// One recipe make ran, with everything its process subtree did. Recipes of
+/// nested makes are their own entries.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct BuildTarget {
+    /// From make's error message when it reported one, else the `-o`
+    /// output, else the first file the recipe wrote.
+    pub target: Option<String>,
+    pub command: String,
+    pub pid: i32,
+    pub make_pid: i32,
+    pub start_ms: f64,
+    pub duration_ms: Option<f64>,
+    pub exit_code: Option<i32>,
+    pub signal: Option<i32>,
+    pub failed: bool,
+    /// Rule location from make's `*** [file:line: target]` message.
+    pub makefile: Option<String>,
+    pub line: Option<u32>,
+    pub processes: u64,
+    pub files_read: u64,
+    pub files_written: u64,
+    pub net_ops: u64,
+    /// Last failed opens, one per file name.
+    pub failed_ops: Vec<FailedOp>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct BuildReport {
+    pub makes: Vec<i32>,
+    /// In start order.
+    pub targets: Vec<BuildTarget>,
+}
+
+fn is_make(argv: &[String]) -> bool {
+    argv.first()
+        .and_then(|a| Path::new(a).file_name())
+        .is_some_and(|n| n == "make" || n == "gmake")
+}
+
+/// The recipe text: the script of `sh -c`, otherwise the command line.
+fn recipe_command(argv: &[String]) -> String {
+    let shell = argv
+        .first()
+        .and_then(|a| Path::new(a).file_name())
+        .is_some_and(|n| n == "sh" || n == "bash");
+    if shell && argv.get(1).map(String::as_str) == Some("-c") {
+        if let Some(script) = argv.get(2) {
+            return script.trim().to_string();
+        }
+    }
+    argv.join(" ")
+}
+
+fn output_arg(command: &str) -> Option<String> {
+    let mut words = command.split_whitespace();
+    while let Some(w) = words.next() {
+        if w == "-o" {
+            return words.next().map(String::from);
+        }
+        if let Some(out) = w.strip_prefix("-o").filter(|o| !o.is_empty()) {
+            return Some(out.to_string());
+        }
+    }
+    None
+}
+
+struct MakeError {
+    makefile: Option<String>,
+    line: Option<u32>,
+    target: String,
+    code: Option<i32>,
+}
+
+fn parse_make_errors(stderr: &str) -> Vec<MakeError> {
+    let re =
+        Regex::new(r"^g?make(?:\[\d+\])?: \*\*\* \[(?:(.+?):(\d+): )?(.+?)\] (?:Error (\d+)|.*)")
+            .unwrap();
+    stderr
+        .lines()
+        .filter_map(|l| re.captures(l))
+        .map(|c| MakeError {
+            makefile: c.get(1).map(|m| m.as_str().to_string()),
+            line: c.get(2).and_then(|m| m.as_str().parse().ok()),
+            target: c[3].to_string(),
+            code: c.get(4).and_then(|m| m.as_str().parse().ok()),
+        })
+        .collect()
+}
+
+/// Groups the run by make recipe. Every child make forks is a recipe; each
+/// process belongs to the recipe of its nearest recipe ancestor.
+pub fn compute(db: &TraceDb, stderr: Option<&str>) -> Result<BuildReport> {
+    let processes = db.query_processes()?;
+
+    let mut exec_argv: HashMap<i32, Vec<String>> = HashMap::new();
+    for ev in db.query_events_by_kind("process_exec")? {
+        if let Some(argv) = ev
+            .detail
+            .as_deref()
+            .and_then(|d| serde_json::from_str::<Vec<String>>(d).ok())
+        {
+            exec_argv.entry(ev.proc_id).or_insert(argv);
+        }
+    }
+    let argv_of = |p: &ProcessQueryResult| -> Vec<String> {
+        exec_argv.get(&p.proc_id).cloned().unwrap_or_else(|| {
+            p.argv
+                .as_deref()
+                .and_then(|a| serde_json::from_str(a).ok())
+                .unwrap_or_default()
+        })
+    };
+
+    let makes: HashSet<i32> = processes
+        .iter()
+        .filter(|p| is_make(&argv_of(p)))
+        .map(|p| p.proc_id)
+        .collect();
+    if makes.is_empty() {
+        return Ok(BuildReport::default());
+    }
+
+    let parents: HashMap<i32, i32> = processes
+        .iter()
+        .filter_map(|p| Some((p.proc_id, p.parent_proc_id?)))
+        .collect();
+    let is_recipe = |pid: i32| parents.get(&pid).is_some_and(|pp| makes.contains(pp));
+    let recipe_of = |mut pid: i32| -> Option<i32> {
+        loop {
+            if is_recipe(pid) {
+                return Some(pid);
+            }
+            pid = *parents.get(&pid)?;
+        }
+    };
+
+    let mut targets: Vec<BuildTarget> = Vec::new();
+    let mut index: HashMap<i32, usize> = HashMap::new();
+    for p in &processes {
+        if !is_recipe(p.proc_id) {
+            continue;
+        }
+        let command = recipe_command(&argv_of(p));
+        index.insert(p.proc_id, targets.len());
+        targets.push(BuildTarget {
+            target: output_arg(&command),
+            command,
+            pid: p.proc_id,
+            make_pid: p.parent_proc_id.unwrap_or(0),
+            start_ms: p.start_ts as f64 / 1_000_000.0,
+            duration_ms: p
+                .end_ts
+                .map(|end| (end - p.start_ts).max(0) as f64 / 1_000_000.0),
+            exit_code: p.exit_code,
+            signal: p.signal,
+            failed: p.exit_code.is_some_and(|c| c != 0) || p.signal.is_some(),
+            ..Default::default()
+        });
+    }
+    for p in &processes {
+        if let Some(&i) = recipe_of(p.proc_id).and_then(|r| index.get(&r)) {
+            targets[i].processes += 1;
+        }
+    }
+
+    let mut read: HashMap<usize, HashSet<String>> = HashMap::new();
+    let mut written: HashMap<usize, Vec<String>> = HashMap::new();
+    for f in db.query_file_events()? {
+        let Some(&i) = recipe_of(f.proc_id).and_then(|r| index.get(&r)) else {
+            continue;
+        };
+        let Some(path) = f.path else {
+            continue;
+        };
+        if let Some(errno) = f.result.filter(|r| *r < 0) {
+            // Compilers probe every include and library directory; one
+            // entry per file name is enough to show what was missing.
+            let name = Path::new(&path).file_name().map(|n| n.to_os_string());
+            let ops = &mut targets[i].failed_ops;
+            if f.op == "open"
+                && !path.contains(".so")
+                && !ops
+                    .iter()
+                    .any(|o| Path::new(&o.path).file_name().map(|n| n.to_os_string()) == name)
+            {
+                ops.push(FailedOp {
+                    op: f.op.clone(),
+                    path,
+                    errno: -errno,
+                });
+            }
+            continue;
+        }
+        if f.op != "open" || path.starts_with("/proc/") || path.starts_with("/dev/") {
+            continue;
+        }
+        let flags = f.flags.unwrap_or(0);
+        if flags & libc::O_ACCMODE != libc::O_RDONLY || flags & libc::O_CREAT != 0 {
+            let paths = written.entry(i).or_default();
+            if !paths.contains(&path) {
+                paths.push(path);
+            }
+        } else {
+            read.entry(i).or_default().insert(path);
+        }
+    }
+    for t in &mut targets {
+        let excess = t.failed_ops.len().saturating_sub(MAX_FAILED_OPS);
+        t.failed_ops.drain(..excess);
+    }
+    for (i, paths) in read {
+        targets[i].files_read = paths.len() as u64;
+    }
+    for (i, paths) in written {
+        targets[i].files_written = paths.len() as u64;
+        if targets[i].target.is_none() {
+            targets[i].target = paths.into_iter().find(|p| !p.starts_with("/tmp/"));
+        }
+    }
+
+    for n in db.query_net_events()? {
+        if let Some(&i) = recipe_of(n.proc_id).and_then(|r| index.get(&r)) {
+            targets[i].net_ops += 1;
+        }
+    }
+
+    // make names the target of each failed recipe; match by inferred target
+    // first, then by exit code among failed recipes still unnamed.
+    let mut named: HashSet<usize> = HashSet::new();
+    for err in parse_make_errors(stderr.unwrap_or("")) {
+        let same_target = |t: &BuildTarget| {
+            t.target.as_deref().is_some_and(|name| {
+                name == err.target
+                    || name.ends_with(&format!("/{}", err.target))
+                    || err.target.ends_with(&format!("/{}", name))
+            })
+        };
+        let found = (0..targets.len())
+            .find(|&i| targets[i].failed && !named.contains(&i) && same_target(&targets[i]))
+            .or_else(|| {
+                (0..targets.len()).find(|&i| {
+                    let t = &targets[i];
+                    t.failed
+                        && !named.contains(&i)
+                        && err.code.is_none_or(|c| t.exit_code == Some(c))
+                })
+            });
+        if let Some(i) = found {
+            named.insert(i);
+            let t = &mut targets[i];
+            t.target = Some(err.target);
+            t.makefile = err.makefile;
+            t.line = err.line;
+        }
+    }
+
+    let mut makes: Vec<i32> = makes.into_iter().collect();
+    makes.sort();
+    Ok(BuildReport { makes, targets })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_make_error_lines() {
+        let errs = parse_make_errors(
+            "cc: error\nmake[2]: *** [src/Makefile:14: obj/b.o] Error 1\nmake: *** [all] Error 2\n",
+        );
+        assert_eq!(errs.len(), 2);
+        assert_eq!(errs[0].makefile.as_deref(), Some("src/Makefile"));
+        assert_eq!(errs[0].line, Some(14));
+        assert_eq!(errs[0].target, "obj/b.o");
+        assert_eq!(errs[0].code, Some(1));
+        assert_eq!(errs[1].makefile, None);
+        assert_eq!(errs[1].target, "all");
+    }
+
+    #[test]
+    fn recipe_command_and_output() {
+        let argv: Vec<String> = ["/bin/sh", "-c", "cc -c b.c -ob.o && strip b.o"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let command = recipe_command(&argv);
+        assert_eq!(command, "cc -c b.c -ob.o && strip b.o");
+        assert_eq!(output_arg(&command).as_deref(), Some("b.o"));
+        assert_eq!(
+            output_arg("ld -o out/app main.o").as_deref(),
+            Some("out/app")
+        );
+    }
+}
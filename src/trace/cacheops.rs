@@ -0,0 +1,48 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::trace::db::TraceDb;
+
+/// One Redis or memcached command decoded from `--capture-cache` traffic.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CacheCommand {
+    pub ts_ms: f64,
+    pub pid: i32,
+    pub proto: String,
+    pub peer: Option<String>,
+    pub command: String,
+    pub key: Option<String>,
+    pub value_bytes: Option<u64>,
+    /// ok, miss, error or no_response.
+    pub status: String,
+    pub duration_ms: Option<f64>,
+    pub reply: Option<String>,
+    pub reply_bytes: Option<u64>,
+    pub error: Option<String>,
+}
+
+/// Commands in the order they were sent.
+pub fn compute(db: &TraceDb) -> Result<Vec<CacheCommand>> {
+    Ok(db
+        .query_events_by_kind("cache_op")?
+        .into_iter()
+        .filter_map(|e| {
+            let v: serde_json::Value = serde_json::from_str(e.detail.as_deref()?).ok()?;
+            let text = |key: &str| v[key].as_str().map(String::from);
+            Some(CacheCommand {
+                ts_ms: e.ts as f64 / 1_000_000.0,
+                pid: e.proc_id,
+                proto: text("proto").unwrap_or_default(),
+                peer: text("peer"),
+                command: text("command").unwrap_or_default(),
+                key: text("key"),
+                value_bytes: v["value_bytes"].as_u64(),
+                status: text("status").unwrap_or_default(),
+                duration_ms: v["duration_ns"].as_u64().map(|d| d as f64 / 1_000_000.0),
+                reply: text("reply"),
+                reply_bytes: v["reply_bytes"].as_u64(),
+                error: text("error"),
+            })
+        })
+        .collect())
+}
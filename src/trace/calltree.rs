@@ -0,0 +1,289 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::trace::db::TraceDb;
+
+/// One node of a per-thread call tree; calls along the same stack path are
+/// merged, so `calls` counts how often this path was entered.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct CallNode {
+    pub func: String,
+    pub calls: u64,
+    pub inclusive_ms: f64,
+    pub exclusive_ms: f64,
+    pub children: Vec<CallNode>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ThreadCallTree {
+    pub tid: i64,
+    pub roots: Vec<CallNode>,
+}
+
+/// Totals per function across all threads. Recursive calls count once
+/// towards inclusive time, from the outermost frame.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct FunctionTiming {
+    pub func: String,
+    pub calls: u64,
+    pub inclusive_ms: f64,
+    pub exclusive_ms: f64,
+    pub max_ms: f64,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct CallTreeReport {
+    pub threads: Vec<ThreadCallTree>,
+    /// Sorted by inclusive time, slowest first.
+    pub functions: Vec<FunctionTiming>,
+}
+
+struct Frame {
+    func: String,
+    addr: String,
+    depth: u64,
+    start: u64,
+    child_ns: u64,
+    node: usize,
+}
+
+#[derive(Default)]
+struct Arena {
+    nodes: Vec<(CallNode, HashMap<String, usize>)>,
+    roots: HashMap<String, usize>,
+    root_order: Vec<usize>,
+    child_order: Vec<Vec<usize>>,
+}
+
+impl Arena {
+    fn child(&mut self, parent: Option<usize>, func: &str) -> usize {
+        let existing = match parent {
+            Some(p) => self.nodes[p].1.get(func).copied(),
+            None => self.roots.get(func).copied(),
+        };
+        if let Some(idx) = existing {
+            return idx;
+        }
+        let idx = self.nodes.len();
+        self.nodes.push((
+            CallNode {
+                func: func.to_string(),
+                ..Default::default()
+            },
+            HashMap::new(),
+        ));
+        self.child_order.push(Vec::new());
+        match parent {
+            Some(p) => {
+                self.nodes[p].1.insert(func.to_string(), idx);
+                self.child_order[p].push(idx);
+            }
+            None => {
+                self.roots.insert(func.to_string(), idx);
+                self.root_order.push(idx);
+            }
+        }
+        idx
+    }
+
+    fn build(&self, idx: usize) -> CallNode {
+        let mut node = self.nodes[idx].0.clone();
+        node.children = self.child_order[idx]
+            .iter()
+            .map(|&c| self.build(c))
+            .collect();
+        node.children
+            .sort_by(|a, b| b.inclusive_ms.total_cmp(&a.inclusive_ms));
+        node
+    }
+}
+
+#[derive(Default)]
+struct ThreadState {
+    stack: Vec<Frame>,
+    arena: Arena,
+    last_ts: u64,
+}
+
+/// Rebuilds call trees from native trace events. Exits are matched to the
+/// innermost open frame of the same function. mcount builds record entries
+/// only, so a new entry at depth d also closes every open frame at depth d
+/// or deeper; frames still open at the end close at the thread's last event.
+pub fn compute(db: &TraceDb) -> Result<CallTreeReport> {
+    let events = db.query_events_by_kinds(&["native_trace_enter", "native_trace_exit"])?;
+
+    let mut threads: HashMap<i64, ThreadState> = HashMap::new();
+    let mut order: Vec<i64> = Vec::new();
+    let mut functions: HashMap<String, FunctionTiming> = HashMap::new();
+
+    for ev in &events {
+        let Some(detail) = ev
+            .detail
+            .as_deref()
+            .and_then(|d| serde_json::from_str::<serde_json::Value>(d).ok())
+        else {
+            continue;
+        };
+        let tid = detail["tid"].as_i64().unwrap_or(ev.proc_id as i64);
+        let func = detail["func"].as_str().unwrap_or("?").to_string();
+        let addr = detail["func_addr"].as_str().unwrap_or("").to_string();
+        let depth = detail["depth"].as_u64().unwrap_or(0);
+        let ts = ev.ts.max(0) as u64;
+
+        let state = threads.entry(tid).or_insert_with(|| {
+            order.push(tid);
+            ThreadState::default()
+        });
+        state.last_ts = state.last_ts.max(ts);
+
+        if ev.kind == "native_trace_enter" {
+            while state.stack.last().is_some_and(|f| f.depth >= depth) {
+                close_frame(state, ts, &mut functions);
+            }
+            let parent = state.stack.last().map(|f| f.node);
+            let node = state.arena.child(parent, &func);
+            state.stack.push(Frame {
+                func,
+                addr,
+                depth,
+                start: ts,
+                child_ns: 0,
+                node,
+            });
+        } else if let Some(pos) = state
+            .stack
+            .iter()
+            .rposition(|f| f.addr == addr && f.func == func)
+        {
+            while state.stack.len() > pos {
+                close_frame(state, ts, &mut functions);
+            }
+        }
+    }
+
+    let mut report = CallTreeReport::default();
+    for tid in order {
+        let mut state = threads.remove(&tid).unwrap_or_default();
+        let end = state.last_ts;
+        while !state.stack.is_empty() {
+            close_frame(&mut state, end, &mut functions);
+        }
+        let mut roots: Vec<CallNode> = state
+            .arena
+            .root_order
+            .iter()
+            .map(|&r| state.arena.build(r))
+            .collect();
+        roots.sort_by(|a, b| b.inclusive_ms.total_cmp(&a.inclusive_ms));
+        report.threads.push(ThreadCallTree { tid, roots });
+    }
+
+    report.functions = functions.into_values().collect();
+    report.functions.sort_by(|a, b| {
+        b.inclusive_ms
+            .total_cmp(&a.inclusive_ms)
+            .then_with(|| a.func.cmp(&b.func))
+    });
+    Ok(report)
+}
+
+fn close_frame(state: &mut ThreadState, ts: u64, functions: &mut HashMap<String, FunctionTiming>) {
+    let Some(frame) = state.stack.pop() else {
+        return;
+    };
+    let inclusive = ts.saturating_sub(frame.start);
+    let exclusive = inclusive.saturating_sub(frame.child_ns);
+    if let Some(parent) = state.stack.last_mut() {
+        parent.child_ns += inclusive;
+    }
+
+    let node = &mut state.arena.nodes[frame.node].0;
+    node.calls += 1;
+    node.inclusive_ms += ms(inclusive);
+    node.exclusive_ms += ms(exclusive);
+
+    let recursive = state.stack.iter().any(|f| f.func == frame.func);
+    let timing = functions
+        .entry(frame.func.clone())
+        .or_insert_with(|| FunctionTiming {
+            func: frame.func.clone(),
+            ..Default::default()
+        });
+    timing.calls += 1;
+    timing.exclusive_ms += ms(exclusive);
+    if !recursive {
+        timing.inclusive_ms += ms(inclusive);
+    }
+    timing.max_ms = timing.max_ms.max(ms(inclusive));
+}
+
+fn ms(ns: u64) -> f64 {
+    ns as f64 / 1_000_000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::types::{Event, EventKind};
+
+    fn native(db: &TraceDb, ts: u64, enter: bool, func: &str, depth: u64) {
+        let detail = serde_json::json!({
+            "func": func,
+            "func_addr": format!("0x{}", func.len()),
+            "depth": depth,
+            "tid": 7,
+        });
+        db.insert_event(&Event {
+            ts,
+            proc_id: 1,
+            kind: if enter {
+                EventKind::NativeTraceEnter
+            } else {
+                EventKind::NativeTraceExit
+            },
+            detail: detail.to_string(),
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn pairs_enter_and_exit_into_tree() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = TraceDb::create(&dir.path().join("t.sqlite")).unwrap();
+        native(&db, 0, true, "main", 0);
+        native(&db, 1_000_000, true, "work", 1);
+        native(&db, 4_000_000, false, "work", 1);
+        native(&db, 5_000_000, true, "work", 1);
+        native(&db, 7_000_000, false, "work", 1);
+        native(&db, 10_000_000, false, "main", 0);
+
+        let report = compute(&db).unwrap();
+        let main = &report.threads[0].roots[0];
+        assert_eq!(main.func, "main");
+        assert_eq!(main.inclusive_ms, 10.0);
+        assert_eq!(main.exclusive_ms, 5.0);
+        assert_eq!(main.children[0].calls, 2);
+        assert_eq!(main.children[0].inclusive_ms, 5.0);
+        assert_eq!(report.functions[0].func, "main");
+        assert_eq!(report.functions[1].max_ms, 3.0);
+    }
+
+    #[test]
+    fn entry_only_traces_close_by_depth() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = TraceDb::create(&dir.path().join("t.sqlite")).unwrap();
+        native(&db, 0, true, "main", 0);
+        native(&db, 2_000_000, true, "a", 1);
+        native(&db, 5_000_000, true, "bb", 1);
+        native(&db, 6_000_000, true, "ccc", 2);
+
+        let report = compute(&db).unwrap();
+        let main = &report.threads[0].roots[0];
+        assert_eq!(main.inclusive_ms, 6.0);
+        let a = main.children.iter().find(|c| c.func == "a").unwrap();
+        assert_eq!(a.inclusive_ms, 3.0);
+    }
+}
@@ -1,9 +1,11 @@
 use anyhow::{Context, Result};
 use rusqlite::{params, Connection};
+use std::collections::HashMap;
 use std::path::Path;
 use std::sync::Mutex;
 
 use crate::events::types::*;
+use crate::util::ansi;
 
 const SCHEMA: &str = r#"
 CREATE TABLE IF NOT EXISTS run (
@@ -17,7 +19,8 @@ CREATE TABLE IF NOT EXISTS run (
     hostname TEXT,
     exit_code INTEGER,
     signal INTEGER,
-    trigger_reason TEXT
+    trigger_reason TEXT,
+    tags TEXT
 );
 
 CREATE TABLE IF NOT EXISTS processes (
@@ -29,6 +32,13 @@ CREATE TABLE IF NOT EXISTS processes (
     end_ts INTEGER,
     exit_code INTEGER,
     signal INTEGER,
+    io_rchar INTEGER,
+    io_wchar INTEGER,
+    io_read_bytes INTEGER,
+    io_write_bytes INTEGER,
+    io_cancelled_write_bytes INTEGER,
+    net_rx_bytes INTEGER,
+    net_tx_bytes INTEGER,
     FOREIGN KEY (parent_proc_id) REFERENCES processes(proc_id)
 );
 
@@ -50,6 +60,7 @@ CREATE TABLE IF NOT EXISTS files (
     bytes INTEGER,
     flags INTEGER,
     result INTEGER,
+    duration_ns INTEGER,
     FOREIGN KEY (proc_id) REFERENCES processes(proc_id)
 );
 
@@ -64,6 +75,8 @@ CREATE TABLE IF NOT EXISTS net (
     bytes INTEGER,
     fd INTEGER,
     result INTEGER,
+    duration_ns INTEGER,
+    listen_fd INTEGER,
     FOREIGN KEY (proc_id) REFERENCES processes(proc_id)
 );
 
@@ -80,7 +93,13 @@ CREATE TABLE IF NOT EXISTS artifacts (
     kind TEXT NOT NULL,
     path TEXT NOT NULL,
     content_hash TEXT,
-    size INTEGER
+    size INTEGER,
+    file_type TEXT,
+    mode INTEGER,
+    uid INTEGER,
+    gid INTEGER,
+    owner TEXT,
+    mtime_ns INTEGER
 );
 
 CREATE TABLE IF NOT EXISTS spans (
@@ -107,6 +126,18 @@ CREATE TABLE IF NOT EXISTS stdio (
     ts INTEGER NOT NULL,
     proc_id INTEGER NOT NULL,
     stream TEXT NOT NULL,
+    data BLOB NOT NULL,
+    clean TEXT
+);
+
+CREATE TABLE IF NOT EXISTS file_payloads (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    ts INTEGER NOT NULL,
+    proc_id INTEGER NOT NULL,
+    op TEXT NOT NULL,
+    path TEXT NOT NULL,
+    fd INTEGER,
+    bytes INTEGER NOT NULL,
     data BLOB NOT NULL
 );
 
@@ -123,8 +154,91 @@ CREATE INDEX IF NOT EXISTS idx_stacks_proc ON stacks(proc_id);
 CREATE INDEX IF NOT EXISTS idx_stdio_proc ON stdio(proc_id);
 "#;
 
+/// Columns added after the first pack format, as (table, column, type).
+const ADDED_COLUMNS: &[(&str, &str, &str)] = &[
+    ("files", "duration_ns", "INTEGER"),
+    ("net", "duration_ns", "INTEGER"),
+    ("net", "listen_fd", "INTEGER"),
+    ("run", "tags", "TEXT"),
+    ("artifacts", "file_type", "TEXT"),
+    ("artifacts", "mode", "INTEGER"),
+    ("artifacts", "uid", "INTEGER"),
+    ("artifacts", "gid", "INTEGER"),
+    ("artifacts", "owner", "TEXT"),
+    ("artifacts", "mtime_ns", "INTEGER"),
+    ("processes", "io_rchar", "INTEGER"),
+    ("processes", "io_wchar", "INTEGER"),
+    ("processes", "io_read_bytes", "INTEGER"),
+    ("processes", "io_write_bytes", "INTEGER"),
+    ("processes", "io_cancelled_write_bytes", "INTEGER"),
+    ("processes", "net_rx_bytes", "INTEGER"),
+    ("processes", "net_tx_bytes", "INTEGER"),
+];
+
+/// Brings a db written by an older poe up to the current schema so queries
+/// can name new tables and columns; they simply read back empty or NULL.
+fn upgrade_schema(conn: &Connection) -> Result<()> {
+    conn.execute_batch(SCHEMA)?;
+    for (table, column, kind) in ADDED_COLUMNS {
+        let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", table))?;
+        let exists = stmt
+            .query_map([], |row| row.get::<_, String>(1))?
+            .filter_map(|c| c.ok())
+            .any(|c| c == *column);
+        if !exists {
+            conn.execute_batch(&format!(
+                "ALTER TABLE {} ADD COLUMN {} {}",
+                table, column, kind
+            ))?;
+        }
+    }
+    Ok(())
+}
+
+/// LIMIT/OFFSET window for the row queries; the default is unbounded.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Page {
+    pub limit: Option<usize>,
+    pub offset: usize,
+}
+
+impl Page {
+    pub fn new(limit: Option<usize>, offset: usize) -> Self {
+        Self { limit, offset }
+    }
+
+    /// SQLite treats a negative LIMIT as no limit.
+    fn sql_limit(&self) -> i64 {
+        self.limit.map_or(-1, |l| l as i64)
+    }
+}
+
+/// Run-relative time range (ns, inclusive) and process to restrict row
+/// queries to; the default matches every row.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TimeWindow {
+    pub start_ns: Option<i64>,
+    pub end_ns: Option<i64>,
+    pub pid: Option<i32>,
+}
+
+impl TimeWindow {
+    const SQL: &'static str = "ts >= ?1 AND ts <= ?2 AND (?3 IS NULL OR proc_id = ?3)";
+
+    fn sql_params(&self) -> (i64, i64, Option<i32>) {
+        (
+            self.start_ns.unwrap_or(i64::MIN),
+            self.end_ns.unwrap_or(i64::MAX),
+            self.pid,
+        )
+    }
+}
+
 pub struct TraceDb {
     conn: Mutex<Connection>,
+    /// Per-stream cleaning state, so a line split across chunks is cleaned
+    /// as one. Each row's `clean` holds the lines its chunk completes.
+    stdio_cleaners: Mutex<HashMap<&'static str, ansi::Cleaner>>,
 }
 
 impl TraceDb {
@@ -140,23 +254,26 @@ impl TraceDb {
 
         Ok(Self {
             conn: Mutex::new(conn),
+            stdio_cleaners: Mutex::default(),
         })
     }
 
     pub fn open(path: &Path) -> Result<Self> {
         let conn = Connection::open(path)
             .with_context(|| format!("failed to open trace db at {}", path.display()))?;
+        upgrade_schema(&conn)?;
 
         Ok(Self {
             conn: Mutex::new(conn),
+            stdio_cleaners: Mutex::default(),
         })
     }
 
     pub fn insert_run(&self, info: &RunInfo) -> Result<()> {
         let conn = self.conn.lock().unwrap();
         conn.execute(
-            "INSERT INTO run (run_id, command, working_dir, env_hash, start_time, git_sha, hostname)
-             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            "INSERT INTO run (run_id, command, working_dir, env_hash, start_time, git_sha, hostname, tags)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
             params![
                 info.run_id,
                 serde_json::to_string(&info.command)?,
@@ -165,6 +282,9 @@ impl TraceDb {
                 info.start_time.to_rfc3339(),
                 info.git_sha,
                 info.hostname,
+                (!info.tags.is_empty())
+                    .then(|| serde_json::to_string(&info.tags))
+                    .transpose()?,
             ],
         )?;
         Ok(())
@@ -238,6 +358,24 @@ impl TraceDb {
         Ok(())
     }
 
+    pub fn insert_events(&self, events: &[Event]) -> Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        for event in events {
+            tx.execute(
+                "INSERT INTO events (ts, proc_id, kind, detail) VALUES (?1, ?2, ?3, ?4)",
+                params![
+                    event.ts as i64,
+                    event.proc_id,
+                    event.kind.as_str(),
+                    event.detail,
+                ],
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
     pub fn insert_file_event(&self, event: &FileEvent) -> Result<()> {
         let conn = self.conn.lock().unwrap();
         conn.execute(
@@ -260,8 +398,8 @@ impl TraceDb {
     pub fn insert_net_event(&self, event: &NetEvent) -> Result<()> {
         let conn = self.conn.lock().unwrap();
         conn.execute(
-            "INSERT INTO net (ts, proc_id, op, proto, src, dst, bytes, fd, result)
-             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            "INSERT INTO net (ts, proc_id, op, proto, src, dst, bytes, fd, result, listen_fd)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
             params![
                 event.ts as i64,
                 event.proc_id,
@@ -272,6 +410,7 @@ impl TraceDb {
                 event.bytes.map(|b| b as i64),
                 event.fd,
                 event.result,
+                event.listen_fd,
             ],
         )?;
         Ok(())
@@ -291,15 +430,49 @@ impl TraceDb {
         let stream_str = match chunk.stream {
             StdioStream::Stdout => "stdout",
             StdioStream::Stderr => "stderr",
+            StdioStream::Stdin => "stdin",
         };
         let conn = self.conn.lock().unwrap();
         conn.execute(
-            "INSERT INTO stdio (ts, proc_id, stream, data) VALUES (?1, ?2, ?3, ?4)",
-            params![chunk.ts as i64, chunk.proc_id, stream_str, chunk.data],
+            "INSERT INTO stdio (ts, proc_id, stream, data, clean) VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                chunk.ts as i64,
+                chunk.proc_id,
+                stream_str,
+                chunk.data,
+                self.clean_stdio(stream_str, &chunk.data)
+            ],
         )?;
         Ok(())
     }
 
+    fn clean_stdio(&self, stream: &'static str, data: &[u8]) -> String {
+        let mut cleaners = self.stdio_cleaners.lock().unwrap();
+        cleaners.entry(stream).or_default().push(data)
+    }
+
+    /// Appends each stream's unfinished last line to its last row. Call once
+    /// the writer has stored every chunk.
+    pub fn finish_stdio(&self) -> Result<()> {
+        let tails: Vec<_> = self
+            .stdio_cleaners
+            .lock()
+            .unwrap()
+            .drain()
+            .map(|(stream, mut cleaner)| (stream, cleaner.finish()))
+            .filter(|(_, tail)| !tail.is_empty())
+            .collect();
+        let conn = self.conn.lock().unwrap();
+        for (stream, tail) in tails {
+            conn.execute(
+                "UPDATE stdio SET clean = coalesce(clean, '') || ?1 WHERE id = \
+                 (SELECT id FROM stdio WHERE stream = ?2 ORDER BY ts DESC, id DESC LIMIT 1)",
+                params![tail, stream],
+            )?;
+        }
+        Ok(())
+    }
+
     pub fn insert_artifact(
         &self,
         id: &str,
@@ -317,6 +490,31 @@ impl TraceDb {
         Ok(())
     }
 
+    pub fn insert_file_stats(&self, stats: &[FileStat]) -> Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        for s in stats {
+            tx.execute(
+                "INSERT OR REPLACE INTO artifacts
+                 (artifact_id, kind, path, size, file_type, mode, uid, gid, owner, mtime_ns)
+                 VALUES (?1, 'file_stat', ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+                params![
+                    format!("stat:{}", s.path),
+                    s.path,
+                    s.size.map(|s| s as i64),
+                    s.file_type,
+                    s.mode,
+                    s.uid,
+                    s.gid,
+                    s.owner,
+                    s.mtime_ns
+                ],
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
     pub fn batch_insert_events(&self, events: &[TraceEvent]) -> Result<()> {
         let mut conn = self.conn.lock().unwrap();
         let tx = conn.transaction()?;
@@ -348,10 +546,27 @@ impl TraceDb {
                         ],
                     )?;
                 }
+                TraceEvent::ProcessIo(io) => {
+                    tx.execute(
+                        "UPDATE processes SET io_rchar = ?1, io_wchar = ?2, io_read_bytes = ?3,
+                         io_write_bytes = ?4, io_cancelled_write_bytes = ?5, net_rx_bytes = ?6,
+                         net_tx_bytes = ?7 WHERE proc_id = ?8",
+                        params![
+                            io.rchar as i64,
+                            io.wchar as i64,
+                            io.read_bytes as i64,
+                            io.write_bytes as i64,
+                            io.cancelled_write_bytes as i64,
+                            io.net_rx_bytes.map(|b| b as i64),
+                            io.net_tx_bytes.map(|b| b as i64),
+                            io.proc_id
+                        ],
+                    )?;
+                }
                 TraceEvent::File(f) => {
                     tx.execute(
-                        "INSERT INTO files (ts, proc_id, op, path, fd, bytes, flags, result)
-                         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                        "INSERT INTO files (ts, proc_id, op, path, fd, bytes, flags, result, duration_ns)
+                         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
                         params![
                             f.ts as i64,
                             f.proc_id,
@@ -361,13 +576,15 @@ impl TraceDb {
                             f.bytes.map(|b| b as i64),
                             f.flags,
                             f.result,
+                            f.duration_ns.map(|d| d as i64),
                         ],
                     )?;
                 }
                 TraceEvent::Net(n) => {
                     tx.execute(
-                        "INSERT INTO net (ts, proc_id, op, proto, src, dst, bytes, fd, result)
-                         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+                        "INSERT INTO net (ts, proc_id, op, proto, src, dst, bytes, fd, result, duration_ns,
+                                          listen_fd)
+                         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
                         params![
                             n.ts as i64,
                             n.proc_id,
@@ -378,6 +595,8 @@ impl TraceDb {
                             n.bytes.map(|b| b as i64),
                             n.fd,
                             n.result,
+                            n.duration_ns.map(|d| d as i64),
+                            n.listen_fd,
                         ],
                     )?;
                 }
@@ -391,10 +610,17 @@ impl TraceDb {
                     let stream_str = match c.stream {
                         StdioStream::Stdout => "stdout",
                         StdioStream::Stderr => "stderr",
+                        StdioStream::Stdin => "stdin",
                     };
                     tx.execute(
-                        "INSERT INTO stdio (ts, proc_id, stream, data) VALUES (?1, ?2, ?3, ?4)",
-                        params![c.ts as i64, c.proc_id, stream_str, c.data],
+                        "INSERT INTO stdio (ts, proc_id, stream, data, clean) VALUES (?1, ?2, ?3, ?4, ?5)",
+                        params![
+                            c.ts as i64,
+                            c.proc_id,
+                            stream_str,
+                            c.data,
+                            self.clean_stdio(stream_str, &c.data)
+                        ],
                     )?;
                 }
                 TraceEvent::Generic(e) => {
@@ -402,6 +628,44 @@ impl TraceDb {
                         "INSERT INTO events (ts, proc_id, kind, detail) VALUES (?1, ?2, ?3, ?4)",
                         params![e.ts as i64, e.proc_id, e.kind.as_str(), e.detail],
                     )?;
+                    // A forked child keeps its parent's argv until it execs.
+                    if e.kind == EventKind::ProcessExec
+                        && e.detail.starts_with('[')
+                        && e.detail != "[]"
+                    {
+                        tx.execute(
+                            "UPDATE processes SET argv = ?1 WHERE proc_id = ?2",
+                            params![e.detail, e.proc_id],
+                        )?;
+                    }
+                }
+                // One row per path; a later close replaces the earlier hash.
+                TraceEvent::FilePayload(p) => {
+                    tx.execute(
+                        "INSERT INTO file_payloads (ts, proc_id, op, path, fd, bytes, data)
+                         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                        params![
+                            p.ts as i64,
+                            p.proc_id,
+                            p.op.as_str(),
+                            p.path,
+                            p.fd,
+                            p.bytes as i64,
+                            p.data,
+                        ],
+                    )?;
+                }
+                TraceEvent::WrittenFile(w) => {
+                    tx.execute(
+                        "INSERT OR REPLACE INTO artifacts (artifact_id, kind, path, content_hash, size)
+                         VALUES (?1, 'written_file', ?2, ?3, ?4)",
+                        params![
+                            format!("written:{}", w.path),
+                            w.path,
+                            w.hash,
+                            w.size as i64
+                        ],
+                    )?;
                 }
             }
         }
@@ -414,7 +678,7 @@ impl TraceDb {
         let conn = self.conn.lock().unwrap();
         let mut stmt = conn.prepare(
             "SELECT run_id, command, working_dir, env_hash, start_time, end_time,
-                    git_sha, hostname, exit_code, signal, trigger_reason
+                    git_sha, hostname, exit_code, signal, trigger_reason, tags
              FROM run LIMIT 1",
         )?;
 
@@ -432,6 +696,7 @@ impl TraceDb {
                     exit_code: row.get(8)?,
                     signal: row.get(9)?,
                     trigger_reason: row.get(10)?,
+                    tags: row.get(11)?,
                 })
             })
             .optional()?;
@@ -440,14 +705,18 @@ impl TraceDb {
     }
 
     pub fn query_processes(&self) -> Result<Vec<ProcessQueryResult>> {
+        self.query_processes_page(Page::default())
+    }
+
+    pub fn query_processes_page(&self, page: Page) -> Result<Vec<ProcessQueryResult>> {
         let conn = self.conn.lock().unwrap();
         let mut stmt = conn.prepare(
             "SELECT proc_id, parent_proc_id, argv, cwd, start_ts, end_ts, exit_code, signal
-             FROM processes ORDER BY start_ts",
+             FROM processes ORDER BY start_ts LIMIT ?1 OFFSET ?2",
         )?;
 
         let results = stmt
-            .query_map([], |row| {
+            .query_map(params![page.sql_limit(), page.offset as i64], |row| {
                 Ok(ProcessQueryResult {
                     proc_id: row.get(0)?,
                     parent_proc_id: row.get(1)?,
@@ -464,13 +733,45 @@ impl TraceDb {
         Ok(results)
     }
 
+    /// Kernel I/O counters of the tasks that had them read at exit.
+    pub fn query_process_io(&self) -> Result<Vec<ProcessIoQueryResult>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT proc_id, parent_proc_id, io_rchar, io_wchar, io_read_bytes, io_write_bytes,
+                    io_cancelled_write_bytes, net_rx_bytes, net_tx_bytes
+             FROM processes WHERE io_rchar IS NOT NULL ORDER BY proc_id",
+        )?;
+        let results = stmt
+            .query_map([], |row| {
+                Ok(ProcessIoQueryResult {
+                    proc_id: row.get(0)?,
+                    parent_proc_id: row.get(1)?,
+                    rchar: row.get(2)?,
+                    wchar: row.get(3)?,
+                    read_bytes: row.get(4)?,
+                    write_bytes: row.get(5)?,
+                    cancelled_write_bytes: row.get(6)?,
+                    net_rx_bytes: row.get(7)?,
+                    net_tx_bytes: row.get(8)?,
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(results)
+    }
+
     pub fn query_last_events(&self, limit: usize) -> Result<Vec<EventQueryResult>> {
+        self.query_last_events_page(Page::new(Some(limit), 0))
+    }
+
+    /// Newest first; `offset` skips that many of the most recent events.
+    pub fn query_last_events_page(&self, page: Page) -> Result<Vec<EventQueryResult>> {
         let conn = self.conn.lock().unwrap();
-        let mut stmt =
-            conn.prepare("SELECT ts, proc_id, kind, detail FROM events ORDER BY ts DESC LIMIT ?1")?;
+        let mut stmt = conn.prepare(
+            "SELECT ts, proc_id, kind, detail FROM events ORDER BY ts DESC LIMIT ?1 OFFSET ?2",
+        )?;
 
         let results = stmt
-            .query_map(params![limit as i64], |row| {
+            .query_map(params![page.sql_limit(), page.offset as i64], |row| {
                 Ok(EventQueryResult {
                     ts: row.get(0)?,
                     proc_id: row.get(1)?,
@@ -483,24 +784,21 @@ impl TraceDb {
         Ok(results)
     }
 
-    pub fn query_file_events(&self) -> Result<Vec<FileQueryResult>> {
+    pub fn query_events_in(&self, window: TimeWindow) -> Result<Vec<EventQueryResult>> {
         let conn = self.conn.lock().unwrap();
-        let mut stmt = conn.prepare(
-            "SELECT ts, proc_id, op, path, fd, bytes, flags, result
-             FROM files ORDER BY ts",
-        )?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT ts, proc_id, kind, detail FROM events WHERE {} ORDER BY ts, id",
+            TimeWindow::SQL
+        ))?;
+        let (start, end, pid) = window.sql_params();
 
         let results = stmt
-            .query_map([], |row| {
-                Ok(FileQueryResult {
+            .query_map(params![start, end, pid], |row| {
+                Ok(EventQueryResult {
                     ts: row.get(0)?,
                     proc_id: row.get(1)?,
-                    op: row.get(2)?,
-                    path: row.get(3)?,
-                    fd: row.get(4)?,
-                    bytes: row.get(5)?,
-                    flags: row.get(6)?,
-                    result: row.get(7)?,
+                    kind: row.get(2)?,
+                    detail: row.get(3)?,
                 })
             })?
             .collect::<rusqlite::Result<Vec<_>>>()?;
@@ -508,25 +806,221 @@ impl TraceDb {
         Ok(results)
     }
 
+    /// Streams events in time order to `f` one row at a time; like
+    /// [`each_file_event`](Self::each_file_event), `f` must not call back
+    /// into the db.
+    pub fn each_event(
+        &self,
+        page: Page,
+        mut f: impl FnMut(EventQueryResult) -> Result<()>,
+    ) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT ts, proc_id, kind, detail FROM events ORDER BY ts, id LIMIT ?1 OFFSET ?2",
+        )?;
+        let mut rows = stmt.query(params![page.sql_limit(), page.offset as i64])?;
+        while let Some(row) = rows.next()? {
+            f(EventQueryResult {
+                ts: row.get(0)?,
+                proc_id: row.get(1)?,
+                kind: row.get(2)?,
+                detail: row.get(3)?,
+            })?;
+        }
+        Ok(())
+    }
+
+    pub fn query_file_events(&self) -> Result<Vec<FileQueryResult>> {
+        self.query_file_events_page(Page::default())
+    }
+
+    pub fn query_file_events_page(&self, page: Page) -> Result<Vec<FileQueryResult>> {
+        let mut results = Vec::new();
+        self.each_file_event(page, |f| {
+            results.push(f);
+            Ok(())
+        })?;
+        Ok(results)
+    }
+
+    /// Streams file events to `f` one row at a time. The connection stays
+    /// locked for the duration, so `f` must not call back into the db.
+    pub fn each_file_event(
+        &self,
+        page: Page,
+        mut f: impl FnMut(FileQueryResult) -> Result<()>,
+    ) -> Result<()> {
+        self.each_file_row(
+            "ORDER BY ts LIMIT ?1 OFFSET ?2",
+            params![page.sql_limit(), page.offset as i64],
+            &mut f,
+        )
+    }
+
+    pub fn each_file_event_in(
+        &self,
+        window: TimeWindow,
+        mut f: impl FnMut(FileQueryResult) -> Result<()>,
+    ) -> Result<()> {
+        let (start, end, pid) = window.sql_params();
+        self.each_file_row(
+            &format!("WHERE {} ORDER BY ts", TimeWindow::SQL),
+            params![start, end, pid],
+            &mut f,
+        )
+    }
+
+    fn each_file_row(
+        &self,
+        clauses: &str,
+        params: impl rusqlite::Params,
+        f: &mut impl FnMut(FileQueryResult) -> Result<()>,
+    ) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT ts, proc_id, op, path, fd, bytes, flags, result, duration_ns
+             FROM files {}",
+            clauses
+        ))?;
+
+        let mut rows = stmt.query(params)?;
+        while let Some(row) = rows.next()? {
+            f(FileQueryResult {
+                ts: row.get(0)?,
+                proc_id: row.get(1)?,
+                op: row.get(2)?,
+                path: row.get(3)?,
+                fd: row.get(4)?,
+                bytes: row.get(5)?,
+                flags: row.get(6)?,
+                result: row.get(7)?,
+                duration_ns: row.get(8)?,
+            })?;
+        }
+
+        Ok(())
+    }
+
     pub fn query_net_events(&self) -> Result<Vec<NetQueryResult>> {
+        self.query_net_events_page(Page::default())
+    }
+
+    pub fn query_net_events_page(&self, page: Page) -> Result<Vec<NetQueryResult>> {
+        let mut results = Vec::new();
+        self.each_net_event(page, |n| {
+            results.push(n);
+            Ok(())
+        })?;
+        Ok(results)
+    }
+
+    pub fn each_net_event(
+        &self,
+        page: Page,
+        mut f: impl FnMut(NetQueryResult) -> Result<()>,
+    ) -> Result<()> {
+        self.each_net_row(
+            "ORDER BY ts LIMIT ?1 OFFSET ?2",
+            params![page.sql_limit(), page.offset as i64],
+            &mut f,
+        )
+    }
+
+    pub fn each_net_event_in(
+        &self,
+        window: TimeWindow,
+        mut f: impl FnMut(NetQueryResult) -> Result<()>,
+    ) -> Result<()> {
+        let (start, end, pid) = window.sql_params();
+        self.each_net_row(
+            &format!("WHERE {} ORDER BY ts", TimeWindow::SQL),
+            params![start, end, pid],
+            &mut f,
+        )
+    }
+
+    fn each_net_row(
+        &self,
+        clauses: &str,
+        params: impl rusqlite::Params,
+        f: &mut impl FnMut(NetQueryResult) -> Result<()>,
+    ) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT ts, proc_id, op, proto, src, dst, bytes, fd, result, duration_ns, listen_fd
+             FROM net {}",
+            clauses
+        ))?;
+
+        let mut rows = stmt.query(params)?;
+        while let Some(row) = rows.next()? {
+            f(NetQueryResult {
+                ts: row.get(0)?,
+                proc_id: row.get(1)?,
+                op: row.get(2)?,
+                proto: row.get(3)?,
+                src: row.get(4)?,
+                dst: row.get(5)?,
+                bytes: row.get(6)?,
+                fd: row.get(7)?,
+                result: row.get(8)?,
+                duration_ns: row.get(9)?,
+                listen_fd: row.get(10)?,
+            })?;
+        }
+
+        Ok(())
+    }
+
+    pub fn query_stacks(&self) -> Result<Vec<StackQueryResult>> {
+        self.query_stacks_page(Page::default())
+    }
+
+    pub fn query_stacks_page(&self, page: Page) -> Result<Vec<StackQueryResult>> {
+        let mut results = Vec::new();
+        self.each_stack(page, |s| {
+            results.push(s);
+            Ok(())
+        })?;
+        Ok(results)
+    }
+
+    pub fn each_stack(
+        &self,
+        page: Page,
+        mut f: impl FnMut(StackQueryResult) -> Result<()>,
+    ) -> Result<()> {
         let conn = self.conn.lock().unwrap();
         let mut stmt = conn.prepare(
-            "SELECT ts, proc_id, op, proto, src, dst, bytes, fd, result
-             FROM net ORDER BY ts",
+            "SELECT ts, proc_id, frames, weight FROM stacks ORDER BY ts LIMIT ?1 OFFSET ?2",
+        )?;
+
+        let mut rows = stmt.query(params![page.sql_limit(), page.offset as i64])?;
+        while let Some(row) = rows.next()? {
+            f(StackQueryResult {
+                ts: row.get(0)?,
+                proc_id: row.get(1)?,
+                frames: row.get(2)?,
+                weight: row.get(3)?,
+            })?;
+        }
+
+        Ok(())
+    }
+
+    pub fn query_written_files(&self) -> Result<Vec<WrittenFileQueryResult>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT path, content_hash, size FROM artifacts
+             WHERE kind = 'written_file' ORDER BY path",
         )?;
 
         let results = stmt
             .query_map([], |row| {
-                Ok(NetQueryResult {
-                    ts: row.get(0)?,
-                    proc_id: row.get(1)?,
-                    op: row.get(2)?,
-                    proto: row.get(3)?,
-                    src: row.get(4)?,
-                    dst: row.get(5)?,
-                    bytes: row.get(6)?,
-                    fd: row.get(7)?,
-                    result: row.get(8)?,
+                Ok(WrittenFileQueryResult {
+                    path: row.get(0)?,
+                    hash: row.get(1)?,
+                    size: row.get(2)?,
                 })
             })?
             .collect::<rusqlite::Result<Vec<_>>>()?;
@@ -534,18 +1028,24 @@ impl TraceDb {
         Ok(results)
     }
 
-    pub fn query_stacks(&self) -> Result<Vec<StackQueryResult>> {
+    pub fn query_file_stats(&self) -> Result<Vec<FileStat>> {
         let conn = self.conn.lock().unwrap();
-        let mut stmt =
-            conn.prepare("SELECT ts, proc_id, frames, weight FROM stacks ORDER BY ts")?;
+        let mut stmt = conn.prepare(
+            "SELECT path, file_type, size, mode, uid, gid, owner, mtime_ns FROM artifacts
+             WHERE kind = 'file_stat' ORDER BY path",
+        )?;
 
         let results = stmt
             .query_map([], |row| {
-                Ok(StackQueryResult {
-                    ts: row.get(0)?,
-                    proc_id: row.get(1)?,
-                    frames: row.get(2)?,
-                    weight: row.get(3)?,
+                Ok(FileStat {
+                    path: row.get(0)?,
+                    file_type: row.get::<_, Option<String>>(1)?.unwrap_or_default(),
+                    size: row.get::<_, Option<i64>>(2)?.map(|s| s as u64),
+                    mode: row.get(3)?,
+                    uid: row.get(4)?,
+                    gid: row.get(5)?,
+                    owner: row.get(6)?,
+                    mtime_ns: row.get(7)?,
                 })
             })?
             .collect::<rusqlite::Result<Vec<_>>>()?;
@@ -553,6 +1053,37 @@ impl TraceDb {
         Ok(results)
     }
 
+    /// Streams captured `--watch-file` payloads, optionally only those whose
+    /// path contains `path_filter`.
+    pub fn each_file_payload(
+        &self,
+        path_filter: Option<&str>,
+        page: Page,
+        mut f: impl FnMut(FilePayloadQueryResult) -> Result<()>,
+    ) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT ts, proc_id, op, path, fd, bytes, data FROM file_payloads
+             WHERE ?1 IS NULL OR path LIKE '%' || ?1 || '%'
+             ORDER BY ts, id LIMIT ?2 OFFSET ?3",
+        )?;
+
+        let mut rows = stmt.query(params![path_filter, page.sql_limit(), page.offset as i64])?;
+        while let Some(row) = rows.next()? {
+            f(FilePayloadQueryResult {
+                ts: row.get(0)?,
+                proc_id: row.get(1)?,
+                op: row.get(2)?,
+                path: row.get(3)?,
+                fd: row.get(4)?,
+                bytes: row.get(5)?,
+                data: row.get(6)?,
+            })?;
+        }
+
+        Ok(())
+    }
+
     pub fn query_stdio(&self, stream: &str) -> Result<Vec<u8>> {
         let conn = self.conn.lock().unwrap();
         let mut stmt = conn.prepare("SELECT data FROM stdio WHERE stream = ?1 ORDER BY ts")?;
@@ -569,6 +1100,61 @@ impl TraceDb {
         Ok(all_data)
     }
 
+    /// Escape-stripped text for a stream. Returns None for traces recorded
+    /// before the `clean` column existed.
+    pub fn query_stdio_clean(&self, stream: &str) -> Result<Option<String>> {
+        let conn = self.conn.lock().unwrap();
+        let Ok(mut stmt) =
+            conn.prepare("SELECT clean FROM stdio WHERE stream = ?1 ORDER BY ts, id")
+        else {
+            return Ok(None);
+        };
+
+        let mut text = String::new();
+        let rows = stmt.query_map(params![stream], |row| row.get::<_, Option<String>>(0))?;
+        for row in rows {
+            match row? {
+                Some(clean) => text.push_str(&clean),
+                None => return Ok(None),
+            }
+        }
+        Ok(Some(text))
+    }
+
+    /// Per-chunk text for a stream, preferring the escape-stripped rendering.
+    pub fn query_stdio_chunks(&self, stream: &str) -> Result<Vec<StdioQueryResult>> {
+        let conn = self.conn.lock().unwrap();
+        let has_clean = conn.prepare("SELECT clean FROM stdio LIMIT 0").is_ok();
+        let sql = if has_clean {
+            "SELECT ts, proc_id, data, clean FROM stdio WHERE stream = ?1 ORDER BY ts, id"
+        } else {
+            "SELECT ts, proc_id, data, NULL FROM stdio WHERE stream = ?1 ORDER BY ts, id"
+        };
+        let mut stmt = conn.prepare(sql)?;
+
+        // Older traces have no `clean`; clean them here, carrying state
+        // across chunks the way the writer does.
+        let mut cleaner = ansi::Cleaner::default();
+        let mut results = stmt
+            .query_map(params![stream], |row| {
+                let data: Vec<u8> = row.get(2)?;
+                let clean: Option<String> = row.get(3)?;
+                Ok(StdioQueryResult {
+                    ts: row.get(0)?,
+                    proc_id: row.get(1)?,
+                    text: clean.unwrap_or_else(|| cleaner.push(&data)),
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        if !has_clean {
+            if let Some(last) = results.last_mut() {
+                last.text.push_str(&cleaner.finish());
+            }
+        }
+
+        Ok(results)
+    }
+
     pub fn event_count(&self) -> Result<i64> {
         let conn = self.conn.lock().unwrap();
         conn.query_row("SELECT COUNT(*) FROM events", [], |row| row.get(0))
@@ -600,11 +1186,25 @@ impl TraceDb {
     }
 
     pub fn raw_query(&self, sql: &str) -> Result<Vec<serde_json::Value>> {
+        let mut results = Vec::new();
+        self.each_raw_row(sql, |row| {
+            results.push(row);
+            Ok(())
+        })?;
+        Ok(results)
+    }
+
+    pub fn each_raw_row(
+        &self,
+        sql: &str,
+        mut f: impl FnMut(serde_json::Value) -> Result<()>,
+    ) -> Result<()> {
         let conn = self.conn.lock().unwrap();
         let mut stmt = conn.prepare(sql)?;
         let column_names: Vec<String> = stmt.column_names().iter().map(|s| s.to_string()).collect();
 
-        let rows = stmt.query_map([], |row| {
+        let mut rows = stmt.query([])?;
+        while let Some(row) = rows.next()? {
             let mut map = serde_json::Map::new();
             for (i, name) in column_names.iter().enumerate() {
                 let val: rusqlite::Result<rusqlite::types::Value> = row.get(i);
@@ -620,17 +1220,16 @@ impl TraceDb {
                 };
                 map.insert(name.clone(), json_val);
             }
-            Ok(serde_json::Value::Object(map))
-        })?;
-
-        let mut results = Vec::new();
-        for row in rows {
-            results.push(row?);
+            f(serde_json::Value::Object(map))?;
         }
-        Ok(results)
+        Ok(())
     }
 
     pub fn query_python_events(&self, kind: &str) -> Result<Vec<EventQueryResult>> {
+        self.query_events_by_kind(kind)
+    }
+
+    pub fn query_events_by_kind(&self, kind: &str) -> Result<Vec<EventQueryResult>> {
         let conn = self.conn.lock().unwrap();
         let mut stmt = conn
             .prepare("SELECT ts, proc_id, kind, detail FROM events WHERE kind = ?1 ORDER BY ts")?;
@@ -649,6 +1248,29 @@ impl TraceDb {
         Ok(results)
     }
 
+    /// Events of any of the given kinds, in recording order.
+    pub fn query_events_by_kinds(&self, kinds: &[&str]) -> Result<Vec<EventQueryResult>> {
+        let conn = self.conn.lock().unwrap();
+        let placeholders = vec!["?"; kinds.len()].join(", ");
+        let mut stmt = conn.prepare(&format!(
+            "SELECT ts, proc_id, kind, detail FROM events WHERE kind IN ({}) ORDER BY ts, id",
+            placeholders
+        ))?;
+
+        let results = stmt
+            .query_map(rusqlite::params_from_iter(kinds), |row| {
+                Ok(EventQueryResult {
+                    ts: row.get(0)?,
+                    proc_id: row.get(1)?,
+                    kind: row.get(2)?,
+                    detail: row.get(3)?,
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        Ok(results)
+    }
+
     pub fn query_python_unhandled_exceptions(&self) -> Result<Vec<EventQueryResult>> {
         self.query_python_events("python_unhandled_exception")
     }
@@ -674,6 +1296,152 @@ impl TraceDb {
         Ok(())
     }
 
+    pub fn vacuum(&self) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute_batch("VACUUM; PRAGMA wal_checkpoint(TRUNCATE);")?;
+        Ok(())
+    }
+
+    pub fn delete_stacks(&self) -> Result<u64> {
+        let conn = self.conn.lock().unwrap();
+        let n = conn.execute("DELETE FROM stacks", [])?;
+        Ok(n as u64)
+    }
+
+    pub fn delete_bulk_io(&self, keep_tail: usize) -> Result<u64> {
+        let conn = self.conn.lock().unwrap();
+        let files = conn.execute(
+            "DELETE FROM files
+             WHERE op IN ('read', 'write') AND (result IS NULL OR result >= 0)
+               AND id NOT IN (
+                 SELECT id FROM files WHERE op IN ('read', 'write')
+                 ORDER BY ts DESC LIMIT ?1
+               )",
+            params![keep_tail as i64],
+        )?;
+        let net = conn.execute(
+            "DELETE FROM net
+             WHERE op IN ('send', 'recv') AND (result IS NULL OR result >= 0)
+               AND id NOT IN (
+                 SELECT id FROM net WHERE op IN ('send', 'recv')
+                 ORDER BY ts DESC LIMIT ?1
+               )",
+            params![keep_tail as i64],
+        )?;
+        Ok((files + net) as u64)
+    }
+
+    pub fn delete_stdio_middle(&self, keep_head: usize, keep_tail: usize) -> Result<(u64, u64)> {
+        let conn = self.conn.lock().unwrap();
+        let mut rows = 0u64;
+        let mut bytes = 0u64;
+
+        for stream in ["stdout", "stderr"] {
+            let filter = "stream = ?1
+               AND id NOT IN (SELECT id FROM stdio WHERE stream = ?1 ORDER BY id ASC LIMIT ?2)
+               AND id NOT IN (SELECT id FROM stdio WHERE stream = ?1 ORDER BY id DESC LIMIT ?3)";
+            let args = params![stream, keep_head as i64, keep_tail as i64];
+
+            let dropped_bytes: i64 = conn.query_row(
+                &format!(
+                    "SELECT COALESCE(SUM(LENGTH(data)), 0) FROM stdio WHERE {}",
+                    filter
+                ),
+                args,
+                |row| row.get(0),
+            )?;
+            let n = conn.execute(&format!("DELETE FROM stdio WHERE {}", filter), args)?;
+
+            rows += n as u64;
+            bytes += dropped_bytes as u64;
+        }
+
+        Ok((rows, bytes))
+    }
+
+    pub fn max_activity_ids(&self) -> Result<(i64, i64)> {
+        let conn = self.conn.lock().unwrap();
+        let files: i64 =
+            conn.query_row("SELECT COALESCE(MAX(id), 0) FROM files", [], |r| r.get(0))?;
+        let net: i64 = conn.query_row("SELECT COALESCE(MAX(id), 0) FROM net", [], |r| r.get(0))?;
+        Ok((files, net))
+    }
+
+    pub fn query_proc_activity_since(&self, file_id: i64, net_id: i64) -> Result<Vec<(i32, i64)>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT proc_id, COUNT(*) FROM (
+                SELECT proc_id FROM files WHERE id > ?1
+                UNION ALL
+                SELECT proc_id FROM net WHERE id > ?2
+             ) GROUP BY proc_id ORDER BY COUNT(*) DESC",
+        )?;
+
+        let results = stmt
+            .query_map(params![file_id, net_id], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        Ok(results)
+    }
+
+    pub fn query_hot_paths_since(&self, file_id: i64, limit: usize) -> Result<Vec<(String, i64)>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT path, COUNT(*) FROM files
+             WHERE id > ?1 AND path IS NOT NULL AND path != ''
+             GROUP BY path ORDER BY COUNT(*) DESC LIMIT ?2",
+        )?;
+
+        let results = stmt
+            .query_map(params![file_id, limit as i64], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        Ok(results)
+    }
+
+    pub fn query_open_connections(&self, limit: usize) -> Result<Vec<NetQueryResult>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT n.ts, n.proc_id, n.op, n.proto, n.src, n.dst, n.bytes, n.fd, n.result,
+                    n.duration_ns, n.listen_fd
+             FROM net n
+             LEFT JOIN processes p ON p.proc_id = n.proc_id
+             WHERE n.op IN ('connect', 'accept')
+               AND (n.result >= 0 OR n.result = -115)
+               AND p.end_ts IS NULL
+               AND NOT EXISTS (
+                   SELECT 1 FROM files f
+                   WHERE f.op = 'close' AND f.proc_id = n.proc_id AND f.ts > n.ts
+                     AND f.fd = CASE WHEN n.op = 'accept' THEN n.result ELSE n.fd END
+               )
+             ORDER BY n.ts DESC LIMIT ?1",
+        )?;
+
+        let results = stmt
+            .query_map(params![limit as i64], |row| {
+                Ok(NetQueryResult {
+                    ts: row.get(0)?,
+                    proc_id: row.get(1)?,
+                    op: row.get(2)?,
+                    proto: row.get(3)?,
+                    src: row.get(4)?,
+                    dst: row.get(5)?,
+                    bytes: row.get(6)?,
+                    fd: row.get(7)?,
+                    result: row.get(8)?,
+                    duration_ns: row.get(9)?,
+                    listen_fd: row.get(10)?,
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        Ok(results)
+    }
+
     pub fn path(&self) -> Result<String> {
         let conn = self.conn.lock().unwrap();
         Ok(conn.path().unwrap_or("").to_string())
@@ -695,6 +1463,8 @@ pub struct RunQueryResult {
     pub exit_code: Option<i32>,
     pub signal: Option<i32>,
     pub trigger_reason: Option<String>,
+    /// JSON object of `--tag` labels.
+    pub tags: Option<String>,
 }
 
 #[derive(Debug, Clone)]
@@ -709,6 +1479,19 @@ pub struct ProcessQueryResult {
     pub signal: Option<i32>,
 }
 
+#[derive(Debug, Clone)]
+pub struct ProcessIoQueryResult {
+    pub proc_id: i32,
+    pub parent_proc_id: Option<i32>,
+    pub rchar: i64,
+    pub wchar: i64,
+    pub read_bytes: i64,
+    pub write_bytes: i64,
+    pub cancelled_write_bytes: i64,
+    pub net_rx_bytes: Option<i64>,
+    pub net_tx_bytes: Option<i64>,
+}
+
 #[derive(Debug, Clone)]
 pub struct EventQueryResult {
     pub ts: i64,
@@ -727,6 +1510,7 @@ pub struct FileQueryResult {
     pub bytes: Option<i64>,
     pub flags: Option<i32>,
     pub result: Option<i64>,
+    pub duration_ns: Option<i64>,
 }
 
 #[derive(Debug, Clone)]
@@ -740,6 +1524,33 @@ pub struct NetQueryResult {
     pub bytes: Option<i64>,
     pub fd: Option<i32>,
     pub result: Option<i64>,
+    pub duration_ns: Option<i64>,
+    pub listen_fd: Option<i32>,
+}
+
+#[derive(Debug, Clone)]
+pub struct StdioQueryResult {
+    pub ts: i64,
+    pub proc_id: i32,
+    pub text: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct FilePayloadQueryResult {
+    pub ts: i64,
+    pub proc_id: i32,
+    pub op: String,
+    pub path: String,
+    pub fd: Option<i32>,
+    pub bytes: i64,
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug, Clone)]
+pub struct WrittenFileQueryResult {
+    pub path: String,
+    pub hash: Option<String>,
+    pub size: Option<i64>,
 }
 
 #[derive(Debug, Clone)]
@@ -0,0 +1,59 @@
+use anyhow::Result;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::trace::db::TraceDb;
+
+/// One SQL statement decoded from `--capture-sql` traffic.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct DbStatement {
+    pub ts_ms: f64,
+    pub pid: i32,
+    pub proto: String,
+    pub peer: Option<String>,
+    /// Empty for errors raised outside a statement, such as failed logins.
+    pub statement: String,
+    /// ok, error or no_response.
+    pub status: String,
+    pub duration_ms: Option<f64>,
+    pub tag: Option<String>,
+    pub error_code: Option<String>,
+    pub vendor_code: Option<u32>,
+    pub error_severity: Option<String>,
+    pub error_message: Option<String>,
+    pub error_detail: Option<String>,
+}
+
+impl DbStatement {
+    pub fn failed(&self) -> bool {
+        self.status == "error"
+    }
+}
+
+/// Statements in the order they were sent.
+pub fn compute(db: &TraceDb) -> Result<Vec<DbStatement>> {
+    Ok(db
+        .query_events_by_kind("db_query")?
+        .into_iter()
+        .filter_map(|e| {
+            let v: serde_json::Value = serde_json::from_str(e.detail.as_deref()?).ok()?;
+            let text = |v: &serde_json::Value| v.as_str().map(String::from);
+            let error = &v["error"];
+            Some(DbStatement {
+                ts_ms: e.ts as f64 / 1_000_000.0,
+                pid: e.proc_id,
+                proto: text(&v["proto"]).unwrap_or_default(),
+                peer: text(&v["peer"]),
+                statement: text(&v["statement"]).unwrap_or_default(),
+                status: text(&v["status"]).unwrap_or_default(),
+                duration_ms: v["duration_ns"].as_u64().map(|d| d as f64 / 1_000_000.0),
+                tag: text(&v["tag"]),
+                error_code: text(&error["code"]),
+                vendor_code: error["vendor_code"].as_u64().map(|c| c as u32),
+                error_severity: text(&error["severity"]),
+                error_message: text(&error["message"]),
+                error_detail: text(&error["detail"]),
+            })
+        })
+        .collect())
+}
@@ -0,0 +1,275 @@
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::trace::db::{FileQueryResult, TraceDb};
+use crate::trace::iostats::FdPaths;
+use crate::util;
+
+/// Everything that happened to one file during the run, under each name it
+/// had.
+#[derive(Debug, Clone, Serialize)]
+pub struct FileHistory {
+    pub path: String,
+    /// The names linked to `path` by renames, in the order they were used.
+    pub names: Vec<String>,
+    /// `exists`, `deleted`, `missing` (only failed lookups) or `unknown`.
+    pub fate: String,
+    /// Where the file is at the end of the run, when it still exists.
+    pub final_path: Option<String>,
+    pub steps: Vec<FileStep>,
+}
+
+/// One operation on the file, or a run of identical reads, writes or
+/// stats by the same process.
+#[derive(Debug, Clone, Serialize)]
+pub struct FileStep {
+    pub ts_ms: f64,
+    pub pid: i32,
+    pub command: Option<String>,
+    pub op: String,
+    pub path: String,
+    pub count: usize,
+    pub bytes: Option<i64>,
+    pub result: Option<i64>,
+    pub description: String,
+}
+
+/// Reconstructs the lifecycle of `path`: which process created, wrote,
+/// renamed and removed it. Renames are followed both ways, so asking about
+/// a temp file finds where it was moved and asking about the destination
+/// finds what it was written as. A relative `path` matches any absolute
+/// path ending in it.
+pub fn compute(db: &TraceDb, path: &str) -> Result<FileHistory> {
+    let procs = db.query_processes()?;
+    let cwd: HashMap<i32, String> = procs
+        .iter()
+        .filter_map(|p| Some((p.proc_id, p.cwd.clone()?)))
+        .collect();
+    let commands: HashMap<i32, String> = procs
+        .iter()
+        .filter_map(|p| {
+            let argv: Vec<String> = serde_json::from_str(p.argv.as_deref()?).ok()?;
+            Some((p.proc_id, argv.join(" ")))
+        })
+        .collect();
+    let absolute = |pid: i32, p: &str| match cwd.get(&pid) {
+        Some(dir) if !p.starts_with('/') => Path::new(dir).join(p).display().to_string(),
+        _ => p.to_string(),
+    };
+    let matches_query =
+        |p: &str| p == path || (!path.starts_with('/') && p.ends_with(&format!("/{}", path)));
+
+    let events = db.query_file_events()?;
+    let mut renames: Vec<(String, String)> = Vec::new();
+    let mut seeds: Vec<String> = Vec::new();
+    for ev in &events {
+        let Some(p) = ev.path.as_deref() else {
+            continue;
+        };
+        let (from, to) = split_pair(p);
+        let from = absolute(ev.proc_id, from);
+        let to = to.map(|t| absolute(ev.proc_id, t));
+        for name in std::iter::once(&from).chain(to.as_ref()) {
+            if matches_query(name) && !seeds.contains(name) {
+                seeds.push(name.clone());
+            }
+        }
+        if ev.op == "rename" && ev.result.is_some_and(|r| r >= 0) {
+            if let Some(to) = to {
+                renames.push((from, to));
+            }
+        }
+    }
+    if seeds.is_empty() {
+        seeds.push(path.to_string());
+    }
+    let names = lineage(&seeds, &renames);
+    let known: HashSet<&str> = names.iter().map(String::as_str).collect();
+
+    let mut fds = FdPaths::new(&procs);
+    let mut steps: Vec<FileStep> = Vec::new();
+    let mut fate = "unknown";
+    let mut current: Option<String> = None;
+    for ev in &events {
+        let ok = ev.result.is_some_and(|r| r >= 0);
+        let target = match (ev.op.as_str(), ev.fd, ev.path.as_deref()) {
+            ("read" | "write", Some(fd), _) => fds
+                .resolve(ev.proc_id, fd)
+                .map(|p| absolute(ev.proc_id, &p)),
+            (_, _, Some(p)) => Some(absolute(ev.proc_id, split_pair(p).0)),
+            _ => None,
+        };
+        fds.observe(ev);
+        let renamed_to = (ev.op == "rename")
+            .then(|| ev.path.as_deref().and_then(|p| split_pair(p).1))
+            .flatten()
+            .map(|t| absolute(ev.proc_id, t));
+        let Some(target) = target else {
+            continue;
+        };
+        let from_known = known.contains(target.as_str());
+        let to_known = renamed_to.as_deref().is_some_and(|t| known.contains(t));
+        if !from_known && !to_known || ev.op == "close" {
+            continue;
+        }
+
+        let description = describe(ev, from_known, renamed_to.as_deref(), &target);
+        if ok {
+            match ev.op.as_str() {
+                "unlink" => {
+                    fate = "deleted";
+                    current = None;
+                }
+                "rename" if to_known => {
+                    fate = "exists";
+                    current = renamed_to.clone();
+                }
+                "rename" => {
+                    fate = "deleted";
+                    current = None;
+                }
+                _ => {
+                    fate = "exists";
+                    current = Some(target.clone());
+                }
+            }
+        } else if fate == "unknown" && ev.result == Some(-(libc::ENOENT as i64)) {
+            fate = "missing";
+        }
+
+        if let Some(last) = steps.last_mut() {
+            let repeat = matches!(ev.op.as_str(), "read" | "write" | "stat")
+                && last.op == ev.op
+                && last.pid == ev.proc_id
+                && last.path == target
+                && last.result.is_some_and(|r| r >= 0) == ok;
+            if repeat {
+                last.count += 1;
+                last.bytes = match (last.bytes, ev.bytes) {
+                    (Some(a), Some(b)) => Some(a + b),
+                    (a, b) => a.or(b),
+                };
+                last.description = repeated(&last.op, last.count, last.bytes, ok);
+                continue;
+            }
+        }
+        steps.push(FileStep {
+            ts_ms: ev.ts as f64 / 1_000_000.0,
+            pid: ev.proc_id,
+            command: commands.get(&ev.proc_id).cloned(),
+            op: ev.op.clone(),
+            path: target,
+            count: 1,
+            bytes: ev.bytes,
+            result: ev.result,
+            description,
+        });
+    }
+
+    Ok(FileHistory {
+        path: path.to_string(),
+        names,
+        fate: fate.into(),
+        final_path: current,
+        steps,
+    })
+}
+
+/// Names connected to `seeds` by renames: forward in time from where the
+/// file went, backward from where it came from.
+fn lineage(seeds: &[String], renames: &[(String, String)]) -> Vec<String> {
+    let mut names: Vec<String> = seeds.to_vec();
+    for (from, to) in renames.iter().rev() {
+        if names.contains(to) && !names.contains(from) {
+            names.insert(0, from.clone());
+        }
+    }
+    for (from, to) in renames {
+        if names.contains(from) && !names.contains(to) {
+            names.push(to.clone());
+        }
+    }
+    names
+}
+
+/// Two-path ops are recorded as "from -> to".
+fn split_pair(path: &str) -> (&str, Option<&str>) {
+    match path.split_once(" -> ") {
+        Some((from, to)) => (from, Some(to)),
+        None => (path, None),
+    }
+}
+
+fn describe(
+    ev: &FileQueryResult,
+    from_known: bool,
+    renamed_to: Option<&str>,
+    path: &str,
+) -> String {
+    let ok = ev.result.is_some_and(|r| r >= 0);
+    if !ok {
+        let errno = ev.result.map(|r| crate::explain::analyzer::errno_name(-r));
+        return format!("{} failed: {}", ev.op, errno.unwrap_or_default());
+    }
+    match ev.op.as_str() {
+        "open" => {
+            let flags = ev.flags.unwrap_or(0);
+            let label = util::open_flags(flags);
+            if flags & libc::O_CREAT != 0 {
+                format!("created or opened for writing ({})", label)
+            } else {
+                format!("opened ({})", label)
+            }
+        }
+        "rename" if from_known => format!("renamed to {}", renamed_to.unwrap_or("?")),
+        "rename" => format!("renamed from {}", path),
+        "unlink" => "deleted".into(),
+        "mkdir" => "created as a directory".into(),
+        "link" | "symlink" => format!("{} created", ev.op),
+        op => repeated(op, 1, ev.bytes, true),
+    }
+}
+
+fn repeated(op: &str, count: usize, bytes: Option<i64>, ok: bool) -> String {
+    let verb = match op {
+        "read" => "read",
+        "write" => "written",
+        "stat" => "checked",
+        other => other,
+    };
+    let times = if count == 1 {
+        "once".to_string()
+    } else {
+        format!("{} times", count)
+    };
+    match bytes {
+        Some(b) if ok => format!("{} {} ({} bytes)", verb, times, b),
+        _ if ok => format!("{} {}", verb, times),
+        _ => format!("{} failed {}", op, times),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lineage_follows_renames_both_ways() {
+        let renames = vec![
+            ("/out/a.tmp".to_string(), "/out/a".to_string()),
+            ("/out/a".to_string(), "/archive/a".to_string()),
+            ("/other.tmp".to_string(), "/other".to_string()),
+        ];
+        assert_eq!(
+            lineage(&["/out/a".into()], &renames),
+            ["/out/a.tmp", "/out/a", "/archive/a"]
+        );
+        assert_eq!(
+            lineage(&["/out/a.tmp".into()], &renames),
+            ["/out/a.tmp", "/out/a", "/archive/a"]
+        );
+    }
+}
@@ -0,0 +1,162 @@
+use std::collections::BTreeMap;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::trace::iostats::PathIoStats;
+
+/// Directory depth `explain` rolls file activity up to, e.g. 3 for
+/// `/home/me/app` or `/app/node_modules/lodash`.
+pub const DEFAULT_DEPTH: usize = 3;
+
+/// File activity summed over everything below one directory.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct DirStats {
+    pub path: String,
+    /// Distinct paths touched below it.
+    pub files: u64,
+    pub ops: u64,
+    pub bytes_read: u64,
+    pub bytes_written: u64,
+    pub errors: u64,
+}
+
+impl DirStats {
+    fn add(&mut self, p: &PathIoStats) {
+        self.files += 1;
+        self.ops += p.stats.ops;
+        self.bytes_read += p.stats.bytes_read;
+        self.bytes_written += p.stats.bytes_written;
+        self.errors += p.stats.errors;
+    }
+}
+
+/// A directory and its subdirectories, for the `files-tree` query.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DirNode {
+    #[serde(flatten)]
+    pub stats: DirStats,
+    pub children: Vec<DirNode>,
+}
+
+/// Directories of the first `depth` components, busiest first. Paths that
+/// aren't absolute (`<stdout>`, unresolved fds) are left out.
+pub fn rollup<'a>(paths: impl IntoIterator<Item = &'a PathIoStats>, depth: usize) -> Vec<DirStats> {
+    let mut dirs: BTreeMap<String, DirStats> = BTreeMap::new();
+    for p in paths {
+        let parents = ancestors(&p.path);
+        let Some(&dir) = parents.get(depth.max(1) - 1).or(parents.last()) else {
+            continue;
+        };
+        let entry = dirs.entry(dir.to_string()).or_insert_with(|| DirStats {
+            path: dir.to_string(),
+            ..Default::default()
+        });
+        entry.add(p);
+    }
+    let mut dirs: Vec<DirStats> = dirs.into_values().collect();
+    dirs.sort_by(|a, b| b.ops.cmp(&a.ops).then(a.path.cmp(&b.path)));
+    dirs
+}
+
+/// The directory tree under `/` down to `max_depth`, children busiest
+/// first.
+pub fn tree<'a>(paths: impl IntoIterator<Item = &'a PathIoStats>, max_depth: usize) -> DirNode {
+    let mut root = DirNode {
+        stats: DirStats {
+            path: "/".into(),
+            ..Default::default()
+        },
+        children: Vec::new(),
+    };
+    for p in paths {
+        if !p.path.starts_with('/') {
+            continue;
+        }
+        root.stats.add(p);
+        let mut node = &mut root;
+        for dir in ancestors(&p.path).into_iter().take(max_depth) {
+            let i = match node.children.iter().position(|c| c.stats.path == dir) {
+                Some(i) => i,
+                None => {
+                    node.children.push(DirNode {
+                        stats: DirStats {
+                            path: dir.to_string(),
+                            ..Default::default()
+                        },
+                        children: Vec::new(),
+                    });
+                    node.children.len() - 1
+                }
+            };
+            node = &mut node.children[i];
+            node.stats.add(p);
+        }
+    }
+    sort(&mut root);
+    root
+}
+
+fn sort(node: &mut DirNode) {
+    node.children.sort_by(|a, b| {
+        b.stats
+            .ops
+            .cmp(&a.stats.ops)
+            .then(a.stats.path.cmp(&b.stats.path))
+    });
+    for child in &mut node.children {
+        sort(child);
+    }
+}
+
+/// The directories containing an absolute path, outermost first:
+/// `/a/b/c.txt` gives `/a` and `/a/b`.
+fn ancestors(path: &str) -> Vec<&str> {
+    if !path.starts_with('/') {
+        return Vec::new();
+    }
+    path.match_indices('/')
+        .skip(1)
+        .map(|(i, _)| &path[..i])
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::trace::iostats::IoStats;
+
+    fn path(path: &str, ops: u64) -> PathIoStats {
+        let mut stats = IoStats::default();
+        stats.ops = ops;
+        PathIoStats {
+            path: path.into(),
+            stats,
+        }
+    }
+
+    #[test]
+    fn rolls_paths_up_to_the_given_depth() {
+        let paths = [
+            path("/app/node_modules/a/index.js", 4),
+            path("/app/node_modules/b/index.js", 2),
+            path("/app/main.js", 1),
+            path("<stdout>", 9),
+        ];
+        let dirs = rollup(&paths, 2);
+        let got: Vec<(&str, u64, u64)> = dirs
+            .iter()
+            .map(|d| (d.path.as_str(), d.files, d.ops))
+            .collect();
+        assert_eq!(got, [("/app/node_modules", 2, 6), ("/app", 1, 1)]);
+
+        let root = tree(&paths, 8);
+        assert_eq!(root.stats.ops, 7);
+        let app = &root.children[0];
+        assert_eq!((app.stats.path.as_str(), app.stats.ops), ("/app", 7));
+        assert_eq!(
+            app.children[0].children[0].stats.path,
+            "/app/node_modules/a"
+        );
+    }
+}
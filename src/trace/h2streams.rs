@@ -0,0 +1,88 @@
+use anyhow::Result;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::capture::h2wire;
+use crate::trace::db::TraceDb;
+
+/// One HTTP/2 request decoded from `--capture-http2` traffic.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct H2Stream {
+    pub ts_ms: f64,
+    pub pid: i32,
+    pub peer: Option<String>,
+    pub stream_id: u32,
+    pub method: Option<String>,
+    pub path: Option<String>,
+    pub authority: Option<String>,
+    pub grpc: bool,
+    /// ok, error, reset or no_response.
+    pub status: String,
+    pub duration_ms: Option<f64>,
+    pub http_status: Option<u16>,
+    pub grpc_status: Option<u32>,
+    /// Name of the gRPC status code, e.g. `UNAVAILABLE`.
+    pub grpc_code: Option<String>,
+    pub grpc_message: Option<String>,
+    /// RST_STREAM error name, e.g. `CANCEL`.
+    pub reset: Option<String>,
+    pub request_bytes: u64,
+    pub response_bytes: u64,
+}
+
+impl H2Stream {
+    pub fn failed(&self) -> bool {
+        self.status == "error" || self.status == "reset"
+    }
+
+    /// `grpc UNAVAILABLE: message`, `HTTP 503` or `reset CANCEL`.
+    pub fn outcome(&self) -> String {
+        if let Some(ref code) = self.grpc_code.as_ref().filter(|c| *c != "OK") {
+            return match self.grpc_message {
+                Some(ref msg) => format!("grpc {}: {}", code, msg),
+                None => format!("grpc {}", code),
+            };
+        }
+        if let Some(ref reset) = self.reset {
+            return format!("reset {}", reset);
+        }
+        match self.http_status {
+            Some(s) => format!("HTTP {}", s),
+            None => "no response".into(),
+        }
+    }
+}
+
+/// Streams in the order their requests were sent.
+pub fn compute(db: &TraceDb) -> Result<Vec<H2Stream>> {
+    Ok(db
+        .query_events_by_kind("h2_stream")?
+        .into_iter()
+        .filter_map(|e| {
+            let v: serde_json::Value = serde_json::from_str(e.detail.as_deref()?).ok()?;
+            let text = |key: &str| v[key].as_str().map(String::from);
+            let grpc_status = v["grpc_status"].as_u64().map(|s| s as u32);
+            Some(H2Stream {
+                ts_ms: e.ts as f64 / 1_000_000.0,
+                pid: e.proc_id,
+                peer: text("peer"),
+                stream_id: v["stream_id"].as_u64().unwrap_or(0) as u32,
+                method: text("method"),
+                path: text("path"),
+                authority: text("authority"),
+                grpc: v["grpc"].as_bool().unwrap_or(false),
+                status: text("status").unwrap_or_default(),
+                duration_ms: v["duration_ns"].as_u64().map(|d| d as f64 / 1_000_000.0),
+                http_status: v["http_status"].as_u64().map(|s| s as u16),
+                grpc_status,
+                grpc_code: grpc_status.map(|s| h2wire::grpc_code_name(s).to_string()),
+                grpc_message: text("grpc_message"),
+                reset: v["reset"]
+                    .as_u64()
+                    .map(|c| h2wire::h2_error_name(c as u32).to_string()),
+                request_bytes: v["request_bytes"].as_u64().unwrap_or(0),
+                response_bytes: v["response_bytes"].as_u64().unwrap_or(0),
+            })
+        })
+        .collect())
+}
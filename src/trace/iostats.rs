@@ -0,0 +1,229 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::events::types::FileOpKind;
+use crate::trace::db::{FileQueryResult, Page, ProcessQueryResult, TraceDb};
+
+/// File I/O aggregated over one process or one path.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct IoStats {
+    pub ops: u64,
+    pub opens: u64,
+    pub reads: u64,
+    pub writes: u64,
+    pub bytes_read: u64,
+    pub bytes_written: u64,
+    pub errors: u64,
+    /// 95th percentile syscall latency, for packs that record durations.
+    pub p95_ms: Option<f64>,
+    #[serde(skip)]
+    durations: Vec<u64>,
+}
+
+impl IoStats {
+    fn add(&mut self, op: &str, bytes: Option<i64>, failed: bool, duration_ns: Option<i64>) {
+        let bytes = bytes.unwrap_or(0).max(0) as u64;
+        self.ops += 1;
+        if let Some(d) = duration_ns {
+            self.durations.push(d.max(0) as u64);
+        }
+        if failed {
+            self.errors += 1;
+        }
+        match op {
+            "open" => self.opens += 1,
+            "read" => {
+                self.reads += 1;
+                self.bytes_read += bytes;
+            }
+            "write" => {
+                self.writes += 1;
+                self.bytes_written += bytes;
+            }
+            op if FileOpKind::is_transfer_str(op) => {
+                self.bytes_read += bytes;
+                self.bytes_written += bytes;
+            }
+            _ => {}
+        }
+    }
+
+    fn total_bytes(&self) -> u64 {
+        self.bytes_read + self.bytes_written
+    }
+
+    fn finish(&mut self) {
+        let mut durations = std::mem::take(&mut self.durations);
+        if durations.is_empty() {
+            return;
+        }
+        durations.sort_unstable();
+        let idx = (durations.len() * 95).div_ceil(100).saturating_sub(1);
+        self.p95_ms = Some(durations[idx] as f64 / 1_000_000.0);
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ProcessIoStats {
+    pub pid: i32,
+    pub command: Option<String>,
+    #[serde(flatten)]
+    pub stats: IoStats,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PathIoStats {
+    pub path: String,
+    #[serde(flatten)]
+    pub stats: IoStats,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct IoReport {
+    pub processes: Vec<ProcessIoStats>,
+    pub paths: Vec<PathIoStats>,
+}
+
+/// Aggregates the files table per process and per path. Reads and writes
+/// only carry an fd, so they are attributed to the path the fd was last
+/// opened as in that process or, for inherited fds, in its ancestors.
+pub fn compute(db: &TraceDb) -> Result<IoReport> {
+    let procs = db.query_processes()?;
+    let mut fds = FdPaths::new(&procs);
+
+    let mut by_proc: HashMap<i32, IoStats> = HashMap::new();
+    let mut by_path: HashMap<String, IoStats> = HashMap::new();
+
+    db.each_file_event(Page::default(), |ev| {
+        let failed = ev.result.is_some_and(|r| r < 0);
+        by_proc
+            .entry(ev.proc_id)
+            .or_default()
+            .add(&ev.op, ev.bytes, failed, ev.duration_ns);
+
+        let path = match (ev.op.as_str(), ev.fd) {
+            ("read" | "write" | "close", Some(fd)) => {
+                Some(fds.resolve(ev.proc_id, fd).unwrap_or_else(|| fd_label(fd)))
+            }
+            _ => ev.path.clone(),
+        };
+        fds.observe(&ev);
+        if let Some(path) = path {
+            by_path
+                .entry(path)
+                .or_default()
+                .add(&ev.op, ev.bytes, failed, ev.duration_ns);
+        }
+        Ok(())
+    })?;
+
+    let commands: HashMap<i32, String> = procs
+        .iter()
+        .filter_map(|p| {
+            let argv: Vec<String> = serde_json::from_str(p.argv.as_deref()?).ok()?;
+            Some((p.proc_id, argv.join(" ")))
+        })
+        .collect();
+
+    let mut processes: Vec<ProcessIoStats> = by_proc
+        .into_iter()
+        .map(|(pid, mut stats)| {
+            stats.finish();
+            ProcessIoStats {
+                pid,
+                command: commands.get(&pid).cloned(),
+                stats,
+            }
+        })
+        .collect();
+    processes.sort_by_key(|p| p.pid);
+
+    let mut paths: Vec<PathIoStats> = by_path
+        .into_iter()
+        .map(|(path, mut stats)| {
+            stats.finish();
+            PathIoStats { path, stats }
+        })
+        .collect();
+    paths.sort_by(|a, b| {
+        b.stats
+            .total_bytes()
+            .cmp(&a.stats.total_bytes())
+            .then(b.stats.ops.cmp(&a.stats.ops))
+            .then(a.path.cmp(&b.path))
+    });
+
+    Ok(IoReport { processes, paths })
+}
+
+/// Maps (pid, fd) to the path the fd was last opened as, falling back to
+/// ancestors for fds inherited across fork.
+pub(crate) struct FdPaths {
+    fds: HashMap<(i32, i32), String>,
+    parents: HashMap<i32, i32>,
+}
+
+impl FdPaths {
+    pub(crate) fn new(procs: &[ProcessQueryResult]) -> Self {
+        Self {
+            fds: HashMap::new(),
+            parents: procs
+                .iter()
+                .filter_map(|p| p.parent_proc_id.map(|pp| (p.proc_id, pp)))
+                .collect(),
+        }
+    }
+
+    /// Feed every file event in timestamp order.
+    pub(crate) fn observe(&mut self, ev: &FileQueryResult) {
+        let ok = ev.result.is_some_and(|r| r >= 0);
+        match (ev.op.as_str(), &ev.path, ev.fd) {
+            ("open", Some(path), Some(fd)) if ok => {
+                self.fds.insert((ev.proc_id, fd), path.clone());
+            }
+            ("close", _, Some(fd)) if ok => {
+                self.fds.remove(&(ev.proc_id, fd));
+            }
+            _ => {}
+        }
+    }
+
+    pub(crate) fn resolve(&self, pid: i32, fd: i32) -> Option<String> {
+        let mut current = pid;
+        for _ in 0..=self.parents.len() {
+            if let Some(path) = self.fds.get(&(current, fd)) {
+                return Some(path.clone());
+            }
+            current = *self.parents.get(&current)?;
+        }
+        None
+    }
+}
+
+fn fd_label(fd: i32) -> String {
+    match fd {
+        0 => "<stdin>".into(),
+        1 => "<stdout>".into(),
+        2 => "<stderr>".into(),
+        _ => format!("<fd {}>", fd),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn inherited_fds_resolve_through_parents() {
+        let fds = FdPaths {
+            fds: HashMap::from([((10, 3), "/tmp/out.log".to_string())]),
+            parents: HashMap::from([(11, 10), (12, 11)]),
+        };
+        assert_eq!(fds.resolve(12, 3).as_deref(), Some("/tmp/out.log"));
+        assert_eq!(fds.resolve(12, 1), None);
+        assert_eq!(fd_label(1), "<stdout>");
+        assert_eq!(fd_label(7), "<fd 7>");
+    }
+}
@@ -0,0 +1,218 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::trace::db::{Page, TraceDb};
+use crate::trace::iostats;
+
+/// A task's kernel I/O counters next to the bytes its traced syscalls moved.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct ProcessKernelIo {
+    pub pid: i32,
+    pub command: Option<String>,
+    /// Bytes through read- and write-family syscalls on any fd.
+    pub rchar: u64,
+    pub wchar: u64,
+    /// Bytes fetched from and dirtied for storage, mapped files included.
+    pub read_bytes: u64,
+    pub write_bytes: u64,
+    pub cancelled_write_bytes: u64,
+    /// Bytes the file syscalls poe traced read and wrote.
+    pub traced_read_bytes: u64,
+    pub traced_write_bytes: u64,
+    /// Storage I/O beyond the traced syscalls, such as page faults of
+    /// mmap'd files and io_uring. A lower bound: reads served from the page
+    /// cache never reach storage.
+    pub untraced_read_bytes: u64,
+    pub untraced_write_bytes: u64,
+}
+
+/// Traffic on the network namespace's non-loopback interfaces while the
+/// root process ran, next to the socket bytes poe traced.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct NamespaceNet {
+    /// Counted by the interfaces, for every process in the namespace.
+    pub rx_bytes: u64,
+    pub tx_bytes: u64,
+    /// Received and sent by traced processes, leaving out loopback and
+    /// unix socket peers where the peer is known.
+    pub traced_rx_bytes: u64,
+    pub traced_tx_bytes: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct KernelIoReport {
+    pub untraced_read_bytes: u64,
+    pub untraced_write_bytes: u64,
+    /// Every task with counters, in pid order.
+    pub processes: Vec<ProcessKernelIo>,
+    pub network: Option<NamespaceNet>,
+}
+
+fn is_local_peer(addr: &str) -> bool {
+    addr.starts_with("127.")
+        || addr.starts_with("[::1]")
+        || addr.starts_with("::1")
+        || addr.starts_with('/')
+        || addr.starts_with('@')
+}
+
+/// Socket bytes received and sent by traced processes to peers that
+/// aren't known to be local; the peer of an fd is where it connected or
+/// what it accepted.
+fn traced_net_bytes(db: &TraceDb) -> Result<(u64, u64)> {
+    let mut local: HashMap<(i32, i32), bool> = HashMap::new();
+    let (mut rx, mut tx) = (0u64, 0u64);
+    db.each_net_event(Page::default(), |ev| {
+        let ok = ev.result.is_some_and(|r| r >= 0);
+        match ev.op.as_str() {
+            "connect" => {
+                if let (Some(fd), Some(dst)) = (ev.fd, &ev.dst) {
+                    local.insert((ev.proc_id, fd), is_local_peer(dst));
+                }
+            }
+            "accept" if ok => {
+                if let (Some(fd), Some(src)) = (ev.result, &ev.src) {
+                    local.insert((ev.proc_id, fd as i32), is_local_peer(src));
+                }
+            }
+            "send" | "recv" if ok => {
+                let peer_local = ev
+                    .dst
+                    .as_deref()
+                    .map(is_local_peer)
+                    .or_else(|| ev.fd.and_then(|fd| local.get(&(ev.proc_id, fd)).copied()))
+                    .unwrap_or(false);
+                if !peer_local {
+                    let bytes = ev.bytes.unwrap_or(0).max(0) as u64;
+                    if ev.op == "recv" {
+                        rx += bytes;
+                    } else {
+                        tx += bytes;
+                    }
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    })?;
+    Ok((rx, tx))
+}
+
+/// Reconciles the counters read as each task exited with what the syscall
+/// trace recorded.
+pub fn compute(db: &TraceDb) -> Result<KernelIoReport> {
+    let rows = db.query_process_io()?;
+    if rows.is_empty() {
+        return Ok(KernelIoReport::default());
+    }
+    let io = iostats::compute(db)?;
+    let traced: HashMap<i32, &iostats::ProcessIoStats> =
+        io.processes.iter().map(|p| (p.pid, p)).collect();
+    let commands: HashMap<i32, String> = db
+        .query_processes()?
+        .iter()
+        .map(|p| (p.proc_id, super::proctree::command(p)))
+        .collect();
+
+    let mut report = KernelIoReport::default();
+    for row in &rows {
+        let (traced_read, traced_write) = traced
+            .get(&row.proc_id)
+            .map(|p| (p.stats.bytes_read, p.stats.bytes_written))
+            .unwrap_or((0, 0));
+        let read_bytes = row.read_bytes.max(0) as u64;
+        let write_bytes = row.write_bytes.max(0) as u64;
+        let cancelled = row.cancelled_write_bytes.max(0) as u64;
+        let p = ProcessKernelIo {
+            pid: row.proc_id,
+            command: commands.get(&row.proc_id).cloned(),
+            rchar: row.rchar.max(0) as u64,
+            wchar: row.wchar.max(0) as u64,
+            read_bytes,
+            write_bytes,
+            cancelled_write_bytes: cancelled,
+            traced_read_bytes: traced_read,
+            traced_write_bytes: traced_write,
+            untraced_read_bytes: read_bytes.saturating_sub(traced_read),
+            untraced_write_bytes: write_bytes
+                .saturating_sub(cancelled)
+                .saturating_sub(traced_write),
+        };
+        report.untraced_read_bytes += p.untraced_read_bytes;
+        report.untraced_write_bytes += p.untraced_write_bytes;
+        report.processes.push(p);
+    }
+
+    // The namespace is shared, so only the root's lifetime is counted.
+    let root = rows
+        .iter()
+        .find(|r| r.parent_proc_id.is_none())
+        .and_then(|r| Some((r.net_rx_bytes?, r.net_tx_bytes?)));
+    if let Some((rx, tx)) = root {
+        let (traced_rx, traced_tx) = traced_net_bytes(db)?;
+        report.network = Some(NamespaceNet {
+            rx_bytes: rx.max(0) as u64,
+            tx_bytes: tx.max(0) as u64,
+            traced_rx_bytes: traced_rx,
+            traced_tx_bytes: traced_tx,
+        });
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::types::{FileEvent, FileOpKind, ProcessInfo, ProcessIo, TraceEvent};
+
+    #[test]
+    fn untraced_io_is_what_the_syscalls_miss() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = TraceDb::create(&dir.path().join("t.sqlite")).unwrap();
+        db.batch_insert_events(&[
+            TraceEvent::Process(ProcessInfo {
+                proc_id: 10,
+                parent_proc_id: None,
+                argv: vec!["app".into()],
+                cwd: "/".into(),
+                start_ts: 0,
+                tgid: None,
+            }),
+            TraceEvent::File(FileEvent {
+                ts: 1,
+                proc_id: 10,
+                op: FileOpKind::Read,
+                path: None,
+                fd: Some(3),
+                bytes: Some(4096),
+                flags: None,
+                result: Some(4096),
+                duration_ns: None,
+            }),
+            TraceEvent::ProcessIo(ProcessIo {
+                proc_id: 10,
+                rchar: 4096,
+                wchar: 0,
+                read_bytes: 1 << 20,
+                write_bytes: 8192,
+                cancelled_write_bytes: 8192,
+                net_rx_bytes: Some(500),
+                net_tx_bytes: Some(20),
+            }),
+        ])
+        .unwrap();
+
+        let report = compute(&db).unwrap();
+        let p = &report.processes[0];
+        assert_eq!(p.command.as_deref(), Some("app"));
+        assert_eq!(p.untraced_read_bytes, (1 << 20) - 4096);
+        assert_eq!(p.untraced_write_bytes, 0);
+        assert_eq!(report.untraced_read_bytes, p.untraced_read_bytes);
+        let net = report.network.unwrap();
+        assert_eq!((net.rx_bytes, net.traced_rx_bytes), (500, 0));
+        assert!(is_local_peer("127.0.0.1:5432") && !is_local_peer("10.0.0.2:443"));
+    }
+}
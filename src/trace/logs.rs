@@ -0,0 +1,239 @@
+use anyhow::Result;
+use serde_json::{Map, Value};
+
+use crate::events::types::{Event, EventKind};
+use crate::trace::db::TraceDb;
+
+const LEVEL_KEYS: &[&str] = &["level", "lvl", "severity", "levelname", "log.level"];
+const MESSAGE_KEYS: &[&str] = &["msg", "message", "event", "text"];
+const LOGGER_KEYS: &[&str] = &[
+    "logger",
+    "logger_name",
+    "name",
+    "target",
+    "module",
+    "component",
+];
+const MAX_LINE: usize = 64 * 1024;
+
+/// A structured log line recognized on stdout/stderr.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogRecord {
+    pub level: Option<String>,
+    pub message: Option<String>,
+    pub logger: Option<String>,
+    pub fields: Map<String, Value>,
+}
+
+impl LogRecord {
+    pub fn to_detail(&self, stream: &str) -> String {
+        serde_json::json!({
+            "stream": stream,
+            "level": self.level,
+            "message": self.message,
+            "logger": self.logger,
+            "fields": self.fields,
+        })
+        .to_string()
+    }
+}
+
+/// Parses a JSON object or logfmt line. Lines with neither a level nor a
+/// message key are not treated as logs.
+pub fn parse_line(line: &str) -> Option<LogRecord> {
+    let line = line.trim();
+    if line.is_empty() || line.len() > MAX_LINE {
+        return None;
+    }
+
+    let fields = if line.starts_with('{') {
+        match serde_json::from_str::<Value>(line).ok()? {
+            Value::Object(map) => map,
+            _ => return None,
+        }
+    } else {
+        parse_logfmt(line)?
+    };
+
+    from_fields(fields)
+}
+
+fn from_fields(mut fields: Map<String, Value>) -> Option<LogRecord> {
+    let level = take_first(&mut fields, LEVEL_KEYS).map(|l| normalize_level(&l));
+    let message = take_first(&mut fields, MESSAGE_KEYS);
+    if level.is_none() && message.is_none() {
+        return None;
+    }
+    let logger = take_first(&mut fields, LOGGER_KEYS);
+
+    Some(LogRecord {
+        level,
+        message,
+        logger,
+        fields,
+    })
+}
+
+fn take_first(fields: &mut Map<String, Value>, keys: &[&str]) -> Option<String> {
+    for key in keys {
+        match fields.remove(*key) {
+            Some(Value::String(s)) => return Some(s),
+            Some(Value::Null) | None => {}
+            Some(other) => return Some(other.to_string()),
+        }
+    }
+    None
+}
+
+/// Maps the many level spellings (`ERR`, `warning`, bunyan numbers) onto
+/// trace/debug/info/warn/error/fatal.
+pub fn normalize_level(level: &str) -> String {
+    if let Ok(n) = level.parse::<u32>() {
+        // bunyan/pino numeric levels
+        return match n {
+            0..=10 => "trace",
+            11..=20 => "debug",
+            21..=30 => "info",
+            31..=40 => "warn",
+            41..=50 => "error",
+            _ => "fatal",
+        }
+        .to_string();
+    }
+    match level.to_ascii_lowercase().as_str() {
+        "trace" | "trc" => "trace",
+        "debug" | "dbg" => "debug",
+        "info" | "inf" | "information" | "notice" => "info",
+        "warn" | "warning" | "wrn" => "warn",
+        "error" | "err" | "eror" => "error",
+        "fatal" | "critical" | "crit" | "panic" | "alert" | "emerg" | "emergency" => "fatal",
+        other => return other.to_string(),
+    }
+    .to_string()
+}
+
+pub fn is_error_level(level: Option<&str>) -> bool {
+    matches!(level, Some("error") | Some("fatal"))
+}
+
+/// key=value pairs separated by spaces; values may be double-quoted.
+fn parse_logfmt(line: &str) -> Option<Map<String, Value>> {
+    let mut fields = Map::new();
+    let mut chars = line.chars().peekable();
+
+    loop {
+        while chars.next_if(|c| *c == ' ').is_some() {}
+        if chars.peek().is_none() {
+            break;
+        }
+
+        let mut key = String::new();
+        while let Some(c) = chars.next_if(|c| *c != '=' && *c != ' ') {
+            key.push(c);
+        }
+        if chars.next_if_eq(&'=').is_none() || key.is_empty() {
+            return None;
+        }
+
+        let mut value = String::new();
+        if chars.next_if_eq(&'"').is_some() {
+            let mut closed = false;
+            while let Some(c) = chars.next() {
+                match c {
+                    '\\' => {
+                        if let Some(escaped) = chars.next() {
+                            value.push(escaped);
+                        }
+                    }
+                    '"' => {
+                        closed = true;
+                        break;
+                    }
+                    c => value.push(c),
+                }
+            }
+            if !closed {
+                return None;
+            }
+        } else {
+            while let Some(c) = chars.next_if(|c| *c != ' ') {
+                value.push(c);
+            }
+        }
+        fields.insert(key, Value::String(value));
+    }
+
+    (fields.len() >= 2).then_some(fields)
+}
+
+/// Scans the captured stdout/stderr chunks and records each structured log
+/// line as a `log` event timestamped at the chunk that completed it.
+pub fn extract_log_events(db: &TraceDb) -> Result<usize> {
+    let mut count = 0;
+
+    for stream in ["stdout", "stderr"] {
+        let mut pending = String::new();
+        let mut events = Vec::new();
+        let mut last = None;
+
+        for chunk in db.query_stdio_chunks(stream)? {
+            last = Some((chunk.ts as u64, chunk.proc_id));
+            pending.push_str(&chunk.text);
+            while let Some(pos) = pending.find('\n') {
+                let line: String = pending.drain(..=pos).collect();
+                if let Some(record) = parse_line(&line) {
+                    events.push(Event {
+                        ts: chunk.ts as u64,
+                        proc_id: chunk.proc_id,
+                        kind: EventKind::Log,
+                        detail: record.to_detail(stream),
+                    });
+                }
+            }
+        }
+
+        if let (Some(record), Some((ts, proc_id))) = (parse_line(&pending), last) {
+            events.push(Event {
+                ts,
+                proc_id,
+                kind: EventKind::Log,
+                detail: record.to_detail(stream),
+            });
+        }
+
+        count += events.len();
+        db.insert_events(&events)?;
+    }
+
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_json_log_line() {
+        let rec =
+            parse_line(r#"{"level":"ERROR","msg":"db down","logger":"api.db","retry":3}"#).unwrap();
+        assert_eq!(rec.level.as_deref(), Some("error"));
+        assert_eq!(rec.message.as_deref(), Some("db down"));
+        assert_eq!(rec.logger.as_deref(), Some("api.db"));
+        assert_eq!(rec.fields.get("retry"), Some(&Value::from(3)));
+    }
+
+    #[test]
+    fn parses_logfmt_line() {
+        let rec = parse_line(r#"ts=2024-01-01 level=warn msg="slow query" dur=3s"#).unwrap();
+        assert_eq!(rec.level.as_deref(), Some("warn"));
+        assert_eq!(rec.message.as_deref(), Some("slow query"));
+        assert_eq!(rec.fields.get("dur"), Some(&Value::from("3s")));
+    }
+
+    #[test]
+    fn ignores_plain_text() {
+        assert!(parse_line("Traceback (most recent call last):").is_none());
+        assert!(parse_line("a=b").is_none());
+        assert!(parse_line(r#"{"user":"x","id":1}"#).is_none());
+    }
+}
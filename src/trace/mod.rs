@@ -1,3 +1,17 @@
+pub mod buildsteps;
+pub mod cacheops;
+pub mod calltree;
 pub mod db;
+pub mod dbqueries;
+pub mod filehistory;
+pub mod filetree;
+pub mod h2streams;
+pub mod iostats;
+pub mod kernelio;
+pub mod logs;
+pub mod proctree;
+pub mod pycalls;
+pub mod sched;
+pub mod tables;
 
 pub use db::TraceDb;
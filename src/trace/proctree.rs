@@ -0,0 +1,161 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::Write;
+
+use crate::trace::db::ProcessQueryResult;
+use crate::util;
+
+/// Longest command shown in a node before it is cut.
+const MAX_LABEL_CHARS: usize = 60;
+
+/// One process in tree order, for the `proctree` query.
+#[derive(Debug, Clone)]
+pub struct TreeProcess<'a> {
+    pub process: &'a ProcessQueryResult,
+    /// 0 for roots.
+    pub depth: usize,
+}
+
+pub fn command(p: &ProcessQueryResult) -> String {
+    p.argv
+        .as_deref()
+        .and_then(|a| serde_json::from_str::<Vec<String>>(a).ok())
+        .map(|argv| argv.join(" "))
+        .unwrap_or_else(|| "?".to_string())
+}
+
+pub fn duration_ms(p: &ProcessQueryResult) -> Option<f64> {
+    p.end_ts
+        .map(|end| end.saturating_sub(p.start_ts) as f64 / 1_000_000.0)
+}
+
+/// The processes depth first, children in start order. Processes whose
+/// parent wasn't traced are roots.
+pub fn walk(procs: &[ProcessQueryResult]) -> Vec<TreeProcess<'_>> {
+    let pids: HashSet<i32> = procs.iter().map(|p| p.proc_id).collect();
+    let mut children: BTreeMap<Option<i32>, Vec<&ProcessQueryResult>> = BTreeMap::new();
+    for p in procs {
+        let parent = p.parent_proc_id.filter(|pp| pids.contains(pp));
+        children.entry(parent).or_default().push(p);
+    }
+    for list in children.values_mut() {
+        list.sort_by_key(|p| (p.start_ts, p.proc_id));
+    }
+
+    let mut out = Vec::with_capacity(procs.len());
+    let mut seen = HashSet::new();
+    let mut stack: Vec<(&ProcessQueryResult, usize)> = children
+        .get(&None)
+        .map(|roots| roots.iter().rev().map(|p| (*p, 0)).collect())
+        .unwrap_or_default();
+    while let Some((p, depth)) = stack.pop() {
+        // A reused pid can't loop the walk.
+        if !seen.insert(p.proc_id) {
+            continue;
+        }
+        out.push(TreeProcess { process: p, depth });
+        if let Some(kids) = children.get(&Some(p.proc_id)) {
+            stack.extend(kids.iter().rev().map(|k| (*k, depth + 1)));
+        }
+    }
+    out
+}
+
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// The process tree as a Graphviz digraph: one box per process with its
+/// command, duration and how it ended. Processes killed by a signal are
+/// red, those that exited non-zero orange.
+pub fn to_dot(procs: &[ProcessQueryResult]) -> String {
+    let mut dot = String::from(
+        "digraph proctree {\n  rankdir=LR;\n  node [shape=box, style=\"rounded,filled\", fillcolor=white, fontname=\"monospace\"];\n",
+    );
+    let tree = walk(procs);
+    for t in &tree {
+        let p = t.process;
+        let mut cmd = command(p);
+        if cmd.chars().count() > MAX_LABEL_CHARS {
+            cmd = cmd.chars().take(MAX_LABEL_CHARS - 3).collect::<String>() + "...";
+        }
+        let ended = match (p.signal, p.exit_code) {
+            (Some(sig), _) => format!("killed by {}", util::signal_name(sig)),
+            (None, Some(code)) => format!("exit {}", code),
+            (None, None) => "still running".to_string(),
+        };
+        let took = duration_ms(p)
+            .map(|ms| format!("{:.1}ms, ", ms))
+            .unwrap_or_default();
+        let color = match (p.signal, p.exit_code) {
+            (Some(_), _) => ", fillcolor=\"#f4a6a6\", color=red",
+            (None, Some(code)) if code != 0 => ", fillcolor=\"#fbd5a5\", color=orange",
+            _ => "",
+        };
+        let _ = writeln!(
+            dot,
+            "  p{} [label=\"{}\\n{}\\n{}{}\"{}];",
+            p.proc_id,
+            p.proc_id,
+            escape(&cmd),
+            took,
+            ended,
+            color
+        );
+    }
+    for t in &tree {
+        if t.depth > 0 {
+            if let Some(parent) = t.process.parent_proc_id {
+                let _ = writeln!(dot, "  p{} -> p{};", parent, t.process.proc_id);
+            }
+        }
+    }
+    dot.push_str("}\n");
+    dot
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn proc(
+        pid: i32,
+        parent: Option<i32>,
+        argv: &str,
+        exit: Option<i32>,
+        sig: Option<i32>,
+    ) -> ProcessQueryResult {
+        ProcessQueryResult {
+            proc_id: pid,
+            parent_proc_id: parent,
+            argv: Some(argv.to_string()),
+            cwd: None,
+            start_ts: pid as i64 * 1_000_000,
+            end_ts: Some(pid as i64 * 1_000_000 + 2_500_000),
+            exit_code: exit,
+            signal: sig,
+        }
+    }
+
+    #[test]
+    fn renders_tree_with_failed_and_killed_processes() {
+        let procs = vec![
+            proc(12, Some(10), r#"["cc","-o","a \"b\""]"#, Some(1), None),
+            proc(10, Some(1), r#"["make"]"#, Some(2), None),
+            proc(11, Some(10), r#"["sh","-c","sleep 9"]"#, None, Some(9)),
+        ];
+        let order: Vec<(i32, usize)> = walk(&procs)
+            .iter()
+            .map(|t| (t.process.proc_id, t.depth))
+            .collect();
+        assert_eq!(order, [(10, 0), (11, 1), (12, 1)]);
+
+        let dot = to_dot(&procs);
+        assert!(dot.starts_with("digraph proctree {"));
+        assert!(dot.contains(
+            "p11 [label=\"11\\nsh -c sleep 9\\n2.5ms, killed by SIGKILL\", fillcolor=\"#f4a6a6\", color=red];"
+        ));
+        assert!(dot.contains("cc -o a \\\"b\\\"\\n2.5ms, exit 1\", fillcolor=\"#fbd5a5\""));
+        assert!(dot.contains("  p10 -> p11;\n  p10 -> p12;\n"));
+        assert!(!dot.contains("p1 ->"));
+    }
+}
@@ -0,0 +1,292 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::trace::db::TraceDb;
+
+/// One Python function call, paired with its return when one was seen.
+#[derive(Debug, Clone, Serialize)]
+pub struct PyCall {
+    pub ts_ms: f64,
+    pub tid: i64,
+    pub func: String,
+    pub file: String,
+    pub line: u32,
+    pub depth: usize,
+    /// None when the call never returned (still running at exit or the
+    /// process died inside it).
+    pub duration_ms: Option<f64>,
+    pub self_ms: Option<f64>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct PyFunctionTiming {
+    pub func: String,
+    pub file: String,
+    pub line: u32,
+    pub calls: u64,
+    /// Recursive calls count once, from the outermost frame.
+    pub total_ms: f64,
+    pub self_ms: f64,
+    pub max_ms: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct PyStackFrame {
+    pub func: String,
+    pub file: String,
+    pub line: u32,
+    /// Time the frame had been running when the exception was raised.
+    pub elapsed_ms: f64,
+}
+
+/// The open call stack, outermost first, where an exception was raised.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct PyExceptionStack {
+    pub ts_ms: f64,
+    pub tid: i64,
+    pub exc_type: String,
+    pub exc_msg: String,
+    pub stack: Vec<PyStackFrame>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PyCallReport {
+    /// Sorted by total time, slowest first.
+    pub functions: Vec<PyFunctionTiming>,
+    pub exception_stacks: Vec<PyExceptionStack>,
+    pub calls: Vec<PyCall>,
+}
+
+/// Frames from the standard library, installed packages and poe's own
+/// hook are library code.
+pub fn is_library_file(file: &str) -> bool {
+    const MARKERS: [&str; 6] = [
+        "<frozen",
+        "/lib/python",
+        "/site-packages/",
+        "/dist-packages/",
+        "sitecustomize.py",
+        "/nix/store/",
+    ];
+    MARKERS.iter().any(|m| file.contains(m))
+}
+
+struct Open {
+    call: usize,
+    start: u64,
+    child_ns: u64,
+}
+
+#[derive(Default)]
+struct ThreadState {
+    stack: Vec<Open>,
+    /// Type of the exception being unwound, while only returns and
+    /// exception events have followed it.
+    propagating: Option<String>,
+    last_was_return: bool,
+}
+
+/// Pairs python_call/python_return events per thread. Library frames are
+/// dropped unless `include_library` is set; their time stays in the
+/// caller's self time.
+pub fn compute(db: &TraceDb, include_library: bool) -> Result<PyCallReport> {
+    let events = db.query_events_by_kinds(&["python_call", "python_return", "python_exception"])?;
+
+    let mut calls: Vec<PyCall> = Vec::new();
+    let mut threads: HashMap<i64, ThreadState> = HashMap::new();
+    let mut exception_stacks = Vec::new();
+    let mut by_func: HashMap<(String, String), PyFunctionTiming> = HashMap::new();
+
+    for ev in &events {
+        let Some(v) = ev
+            .detail
+            .as_deref()
+            .and_then(|d| serde_json::from_str::<serde_json::Value>(d).ok())
+        else {
+            continue;
+        };
+        let text = |key: &str| v[key].as_str().unwrap_or("").to_string();
+        let file = text("file");
+        if !include_library && is_library_file(&file) {
+            continue;
+        }
+        let func = text("func");
+        let tid = v["tid"].as_i64().unwrap_or(ev.proc_id as i64);
+        let ts = ev.ts.max(0) as u64;
+        let state = threads.entry(tid).or_default();
+
+        match ev.kind.as_str() {
+            "python_call" => {
+                state.propagating = None;
+                state.last_was_return = false;
+                let line = v["line"].as_u64().unwrap_or(0) as u32;
+                by_func
+                    .entry((func.clone(), file.clone()))
+                    .or_insert_with(|| PyFunctionTiming {
+                        func: func.clone(),
+                        file: file.clone(),
+                        line,
+                        ..Default::default()
+                    })
+                    .calls += 1;
+                state.stack.push(Open {
+                    call: calls.len(),
+                    start: ts,
+                    child_ns: 0,
+                });
+                calls.push(PyCall {
+                    ts_ms: ms(ts),
+                    tid,
+                    func,
+                    file,
+                    line,
+                    depth: state.stack.len() - 1,
+                    duration_ms: None,
+                    self_ms: None,
+                });
+            }
+            "python_return" => {
+                state.last_was_return = true;
+                let Some(pos) = state
+                    .stack
+                    .iter()
+                    .rposition(|o| calls[o.call].func == func && calls[o.call].file == file)
+                else {
+                    continue;
+                };
+                // Frames above the match returned without an event (the
+                // hook's depth cap); close them at the same time.
+                while state.stack.len() > pos {
+                    let open = state.stack.pop().unwrap();
+                    let duration = ts.saturating_sub(open.start);
+                    if let Some(parent) = state.stack.last_mut() {
+                        parent.child_ns += duration;
+                    }
+                    let self_ns = duration.saturating_sub(open.child_ns);
+                    let call = &mut calls[open.call];
+                    call.duration_ms = Some(ms(duration));
+                    call.self_ms = Some(ms(self_ns));
+
+                    let call = &calls[open.call];
+                    let recursive = state.stack.iter().any(|o| {
+                        calls[o.call].func == call.func && calls[o.call].file == call.file
+                    });
+                    if let Some(timing) = by_func.get_mut(&(call.func.clone(), call.file.clone())) {
+                        if !recursive {
+                            timing.total_ms += ms(duration);
+                        }
+                        timing.self_ms += ms(self_ns);
+                        timing.max_ms = timing.max_ms.max(ms(duration));
+                    }
+                }
+            }
+            "python_exception" => {
+                let exc_type = text("exc_type");
+                let unwinding =
+                    state.last_was_return && state.propagating.as_deref() == Some(&exc_type);
+                state.propagating = Some(exc_type.clone());
+                state.last_was_return = false;
+                if unwinding {
+                    continue;
+                }
+                exception_stacks.push(PyExceptionStack {
+                    ts_ms: ms(ts),
+                    tid,
+                    exc_type,
+                    exc_msg: text("exc_msg"),
+                    stack: state
+                        .stack
+                        .iter()
+                        .map(|o| {
+                            let c = &calls[o.call];
+                            PyStackFrame {
+                                func: c.func.clone(),
+                                file: c.file.clone(),
+                                line: c.line,
+                                elapsed_ms: ms(ts.saturating_sub(o.start)),
+                            }
+                        })
+                        .collect(),
+                });
+            }
+            _ => {}
+        }
+    }
+
+    let mut functions: Vec<PyFunctionTiming> = by_func.into_values().collect();
+    functions.sort_by(|a, b| {
+        b.total_ms
+            .total_cmp(&a.total_ms)
+            .then_with(|| a.func.cmp(&b.func))
+    });
+
+    Ok(PyCallReport {
+        functions,
+        exception_stacks,
+        calls,
+    })
+}
+
+fn ms(ns: u64) -> f64 {
+    ns as f64 / 1_000_000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::types::{Event, EventKind};
+
+    fn py(db: &TraceDb, ts_ms: u64, kind: EventKind, func: &str, file: &str) {
+        let detail = serde_json::json!({
+            "func": func,
+            "file": file,
+            "line": 1,
+            "tid": 1,
+            "exc_type": "ValueError",
+            "exc_msg": "bad",
+        });
+        db.insert_event(&Event {
+            ts: ts_ms * 1_000_000,
+            proc_id: 1,
+            kind,
+            detail: detail.to_string(),
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn pairs_calls_and_captures_raise_stack() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = TraceDb::create(&dir.path().join("t.sqlite")).unwrap();
+        let lib = "/usr/lib/python3.12/json/__init__.py";
+        py(&db, 0, EventKind::PythonCall, "main", "app.py");
+        py(&db, 1, EventKind::PythonCall, "load", "app.py");
+        py(&db, 2, EventKind::PythonCall, "loads", lib);
+        py(&db, 6, EventKind::PythonReturn, "loads", lib);
+        py(&db, 7, EventKind::PythonReturn, "load", "app.py");
+        py(&db, 8, EventKind::PythonCall, "check", "app.py");
+        py(&db, 9, EventKind::PythonException, "check", "app.py");
+        py(&db, 9, EventKind::PythonReturn, "check", "app.py");
+        py(&db, 9, EventKind::PythonException, "main", "app.py");
+        py(&db, 9, EventKind::PythonReturn, "main", "app.py");
+
+        let report = compute(&db, false).unwrap();
+        assert_eq!(report.calls.len(), 3);
+        assert_eq!(report.functions[0].func, "main");
+        assert_eq!(report.functions[0].total_ms, 9.0);
+        let load = report.functions.iter().find(|f| f.func == "load").unwrap();
+        assert_eq!(load.self_ms, 6.0);
+
+        assert_eq!(report.exception_stacks.len(), 1);
+        let stack = &report.exception_stacks[0].stack;
+        assert_eq!(stack.len(), 2);
+        assert_eq!(stack[1].func, "check");
+        assert_eq!(stack[0].elapsed_ms, 9.0);
+
+        let all = compute(&db, true).unwrap();
+        assert_eq!(all.calls.len(), 4);
+    }
+}
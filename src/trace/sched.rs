@@ -0,0 +1,130 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::trace::db::TraceDb;
+use crate::trace::proctree;
+
+/// Processes listed in the summary, those that waited longest first.
+const MAX_PROCESSES: usize = 5;
+/// Share of runnable time spent waiting for a CPU above which the machine
+/// is called oversubscribed.
+const OVERSUBSCRIBED_SHARE: f64 = 0.2;
+/// Less waiting than this is noise, whatever its share.
+const MIN_WAIT_MS: f64 = 50.0;
+
+/// CPU time of one traced task, read from its schedstat as it exited.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct ProcessSched {
+    pub pid: i32,
+    pub command: Option<String>,
+    /// On a CPU.
+    pub run_ms: f64,
+    /// Runnable but waiting for a CPU.
+    pub wait_ms: f64,
+    pub voluntary_switches: u64,
+    pub involuntary_switches: u64,
+}
+
+/// How the traced tasks' time split between running and waiting to run,
+/// recorded in full mode.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct SchedSummary {
+    pub tasks: usize,
+    pub run_ms: f64,
+    pub wait_ms: f64,
+    /// wait_ms / (run_ms + wait_ms).
+    pub wait_share: f64,
+    pub voluntary_switches: u64,
+    pub involuntary_switches: u64,
+    /// The tasks spent a large share of their time waiting for a CPU, so
+    /// the machine, not the code, made the run slow.
+    pub oversubscribed: bool,
+    pub processes: Vec<ProcessSched>,
+}
+
+/// Every task with recorded scheduler stats, in pid order.
+pub fn compute(db: &TraceDb) -> Result<Vec<ProcessSched>> {
+    let commands: HashMap<i32, String> = db
+        .query_processes()?
+        .iter()
+        .map(|p| (p.proc_id, proctree::command(p)))
+        .collect();
+    let mut rows: Vec<ProcessSched> = db
+        .query_events_by_kind("sched_stats")?
+        .into_iter()
+        .filter_map(|e| {
+            let v: serde_json::Value = serde_json::from_str(e.detail.as_deref()?).ok()?;
+            let ms = |key: &str| v[key].as_u64().unwrap_or(0) as f64 / 1_000_000.0;
+            Some(ProcessSched {
+                pid: e.proc_id,
+                command: commands.get(&e.proc_id).cloned(),
+                run_ms: ms("run_ns"),
+                wait_ms: ms("wait_ns"),
+                voluntary_switches: v["voluntary_switches"].as_u64().unwrap_or(0),
+                involuntary_switches: v["involuntary_switches"].as_u64().unwrap_or(0),
+            })
+        })
+        .collect();
+    rows.sort_by_key(|r| r.pid);
+    Ok(rows)
+}
+
+pub fn summarize(rows: &[ProcessSched]) -> Option<SchedSummary> {
+    if rows.is_empty() {
+        return None;
+    }
+    let run_ms: f64 = rows.iter().map(|r| r.run_ms).sum();
+    let wait_ms: f64 = rows.iter().map(|r| r.wait_ms).sum();
+    let wait_share = if run_ms + wait_ms > 0.0 {
+        wait_ms / (run_ms + wait_ms)
+    } else {
+        0.0
+    };
+    let mut processes = rows.to_vec();
+    processes.sort_by(|a, b| b.wait_ms.total_cmp(&a.wait_ms));
+    processes.truncate(MAX_PROCESSES);
+    Some(SchedSummary {
+        tasks: rows.len(),
+        run_ms,
+        wait_ms,
+        wait_share,
+        voluntary_switches: rows.iter().map(|r| r.voluntary_switches).sum(),
+        involuntary_switches: rows.iter().map(|r| r.involuntary_switches).sum(),
+        oversubscribed: wait_share >= OVERSUBSCRIBED_SHARE && wait_ms >= MIN_WAIT_MS,
+        processes,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn task(pid: i32, run_ms: f64, wait_ms: f64) -> ProcessSched {
+        ProcessSched {
+            pid,
+            command: None,
+            run_ms,
+            wait_ms,
+            voluntary_switches: 1,
+            involuntary_switches: 2,
+        }
+    }
+
+    #[test]
+    fn flags_runs_that_mostly_waited_for_a_cpu() {
+        let busy = summarize(&[task(1, 400.0, 20.0), task(2, 300.0, 10.0)]).unwrap();
+        assert!(!busy.oversubscribed);
+        assert_eq!(busy.involuntary_switches, 4);
+
+        let starved = summarize(&[task(1, 100.0, 20.0), task(2, 100.0, 280.0)]).unwrap();
+        assert!(starved.oversubscribed);
+        assert!((starved.wait_share - 0.6).abs() < 1e-9);
+        assert_eq!(starved.processes[0].pid, 2);
+
+        assert!(summarize(&[task(1, 1.0, 9.0)]).is_some_and(|s| !s.oversubscribed));
+        assert!(summarize(&[]).is_none());
+    }
+}
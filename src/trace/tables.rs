@@ -0,0 +1,354 @@
+use std::fs::File;
+use std::io::Write;
+use std::sync::Arc;
+
+use anyhow::{bail, Result};
+use parquet::basic::Compression;
+use parquet::data_type::{ByteArray, ByteArrayType, Int32Type, Int64Type};
+use parquet::file::properties::WriterProperties;
+use parquet::file::writer::SerializedFileWriter;
+use parquet::schema::parser::parse_message_type;
+
+use crate::explain::analyzer;
+use crate::trace::db::{Page, TraceDb};
+use crate::util;
+use ColumnType::{Int32, Int64, Text};
+
+/// Rows buffered per Parquet row group.
+const ROW_GROUP_ROWS: usize = 64 * 1024;
+
+/// A trace table `poe export table` can write out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Table {
+    Files,
+    Net,
+    Events,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColumnType {
+    Int32,
+    Int64,
+    Text,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Column {
+    pub name: &'static str,
+    pub ty: ColumnType,
+    pub nullable: bool,
+}
+
+const fn col(name: &'static str, ty: ColumnType, nullable: bool) -> Column {
+    Column { name, ty, nullable }
+}
+
+/// Timestamps and durations are nanoseconds, from the start of the run.
+const FILE_COLUMNS: &[Column] = &[
+    col("ts_ns", Int64, false),
+    col("pid", Int32, false),
+    col("op", Text, false),
+    col("path", Text, true),
+    col("fd", Int32, true),
+    col("bytes", Int64, true),
+    col("flags", Int32, true),
+    col("result", Int64, true),
+    col("errno", Text, true),
+    col("duration_ns", Int64, true),
+];
+
+const NET_COLUMNS: &[Column] = &[
+    col("ts_ns", Int64, false),
+    col("pid", Int32, false),
+    col("op", Text, false),
+    col("proto", Text, true),
+    col("src", Text, true),
+    col("dst", Text, true),
+    col("fd", Int32, true),
+    col("bytes", Int64, true),
+    col("result", Int64, true),
+    col("errno", Text, true),
+    col("duration_ns", Int64, true),
+    col("listen_fd", Int32, true),
+];
+
+const EVENT_COLUMNS: &[Column] = &[
+    col("ts_ns", Int64, false),
+    col("pid", Int32, false),
+    col("kind", Text, false),
+    col("detail", Text, true),
+];
+
+/// One value of a row; a column's cells all have its type.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Cell {
+    Int(Option<i64>),
+    Text(Option<String>),
+}
+
+fn errno(result: Option<i64>) -> Cell {
+    Cell::Text(result.filter(|r| *r < 0).map(|r| analyzer::errno_name(-r)))
+}
+
+fn int<T: Into<i64>>(v: Option<T>) -> Cell {
+    Cell::Int(v.map(Into::into))
+}
+
+impl Table {
+    pub fn parse(s: &str) -> Result<Self> {
+        match s {
+            "files" => Ok(Self::Files),
+            "net" => Ok(Self::Net),
+            "events" => Ok(Self::Events),
+            other => bail!("unknown table '{}' (expected files, net or events)", other),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Files => "files",
+            Self::Net => "net",
+            Self::Events => "events",
+        }
+    }
+
+    pub fn columns(self) -> &'static [Column] {
+        match self {
+            Self::Files => FILE_COLUMNS,
+            Self::Net => NET_COLUMNS,
+            Self::Events => EVENT_COLUMNS,
+        }
+    }
+
+    /// Streams the table's rows in time order, cells in column order.
+    pub fn each_row(self, db: &TraceDb, mut f: impl FnMut(Vec<Cell>) -> Result<()>) -> Result<()> {
+        let all = Page::new(None, 0);
+        match self {
+            Self::Files => db.each_file_event(all, |e| {
+                f(vec![
+                    int(Some(e.ts)),
+                    int(Some(e.proc_id)),
+                    Cell::Text(Some(e.op)),
+                    Cell::Text(e.path),
+                    int(e.fd),
+                    int(e.bytes),
+                    int(e.flags),
+                    int(e.result),
+                    errno(e.result),
+                    int(e.duration_ns),
+                ])
+            }),
+            Self::Net => db.each_net_event(all, |e| {
+                f(vec![
+                    int(Some(e.ts)),
+                    int(Some(e.proc_id)),
+                    Cell::Text(Some(e.op)),
+                    Cell::Text(e.proto),
+                    Cell::Text(e.src),
+                    Cell::Text(e.dst),
+                    int(e.fd),
+                    int(e.bytes),
+                    int(e.result),
+                    errno(e.result),
+                    int(e.duration_ns),
+                    int(e.listen_fd),
+                ])
+            }),
+            Self::Events => db.each_event(all, |e| {
+                f(vec![
+                    int(Some(e.ts)),
+                    int(Some(e.proc_id)),
+                    Cell::Text(Some(e.kind)),
+                    Cell::Text(e.detail),
+                ])
+            }),
+        }
+    }
+}
+
+/// Writes `table` as CSV with a header row; nulls are empty fields.
+/// Returns the number of rows.
+pub fn write_csv(db: &TraceDb, table: Table, out: &mut impl Write) -> Result<u64> {
+    let header: Vec<&str> = table.columns().iter().map(|c| c.name).collect();
+    writeln!(out, "{}", header.join(","))?;
+    let mut rows = 0;
+    table.each_row(db, |cells| {
+        let fields: Vec<String> = cells
+            .into_iter()
+            .map(|cell| match cell {
+                Cell::Int(v) => v.map(|v| v.to_string()).unwrap_or_default(),
+                Cell::Text(v) => v.map(|v| util::csv_field(&v)).unwrap_or_default(),
+            })
+            .collect();
+        writeln!(out, "{}", fields.join(","))?;
+        rows += 1;
+        Ok(())
+    })?;
+    out.flush()?;
+    Ok(rows)
+}
+
+/// The Parquet schema of a table: 32/64-bit integers and UTF-8 strings,
+/// optional where the column can be null.
+fn parquet_schema(table: Table) -> String {
+    let fields: Vec<String> = table
+        .columns()
+        .iter()
+        .map(|c| {
+            let repetition = if c.nullable { "OPTIONAL" } else { "REQUIRED" };
+            let ty = match c.ty {
+                Int32 => "INT32",
+                Int64 => "INT64",
+                Text => "BINARY",
+            };
+            let annotation = if c.ty == Text { " (UTF8)" } else { "" };
+            format!("  {} {} {}{};", repetition, ty, c.name, annotation)
+        })
+        .collect();
+    format!("message {} {{\n{}\n}}", table.name(), fields.join("\n"))
+}
+
+/// A column's values for the row group being built; nulls only show in
+/// the definition levels.
+#[derive(Default)]
+struct ColumnBuf {
+    ints: Vec<i64>,
+    texts: Vec<ByteArray>,
+    defs: Vec<i16>,
+}
+
+/// Writes `table` as an uncompressed Parquet file, a row group at a time.
+/// Returns the number of rows.
+pub fn write_parquet(db: &TraceDb, table: Table, file: File) -> Result<u64> {
+    let schema = Arc::new(parse_message_type(&parquet_schema(table))?);
+    let props = Arc::new(
+        WriterProperties::builder()
+            .set_compression(Compression::UNCOMPRESSED)
+            .set_created_by(format!("poe {}", env!("CARGO_PKG_VERSION")))
+            .build(),
+    );
+    let mut writer = SerializedFileWriter::new(file, schema, props)?;
+    let columns = table.columns();
+    let mut bufs: Vec<ColumnBuf> = columns.iter().map(|_| ColumnBuf::default()).collect();
+    let mut buffered = 0;
+    let mut rows = 0;
+
+    let flush =
+        |writer: &mut SerializedFileWriter<File>, bufs: &mut Vec<ColumnBuf>| -> Result<()> {
+            let mut group = writer.next_row_group()?;
+            for (column, buf) in columns.iter().zip(bufs.iter_mut()) {
+                let Some(mut out) = group.next_column()? else {
+                    bail!("parquet schema is missing column {}", column.name);
+                };
+                let defs = column.nullable.then_some(buf.defs.as_slice());
+                match column.ty {
+                    Int32 => {
+                        let values: Vec<i32> = buf.ints.iter().map(|&v| v as i32).collect();
+                        out.typed::<Int32Type>().write_batch(&values, defs, None)?;
+                    }
+                    Int64 => {
+                        out.typed::<Int64Type>()
+                            .write_batch(&buf.ints, defs, None)?;
+                    }
+                    Text => {
+                        out.typed::<ByteArrayType>()
+                            .write_batch(&buf.texts, defs, None)?;
+                    }
+                }
+                out.close()?;
+                *buf = ColumnBuf::default();
+            }
+            group.close()?;
+            Ok(())
+        };
+
+    table.each_row(db, |cells| {
+        for (buf, cell) in bufs.iter_mut().zip(cells) {
+            let present = match cell {
+                Cell::Int(Some(v)) => {
+                    buf.ints.push(v);
+                    true
+                }
+                Cell::Text(Some(v)) => {
+                    buf.texts.push(ByteArray::from(v.into_bytes()));
+                    true
+                }
+                Cell::Int(None) | Cell::Text(None) => false,
+            };
+            buf.defs.push(present as i16);
+        }
+        buffered += 1;
+        rows += 1;
+        if buffered == ROW_GROUP_ROWS {
+            flush(&mut writer, &mut bufs)?;
+            buffered = 0;
+        }
+        Ok(())
+    })?;
+    if buffered > 0 {
+        flush(&mut writer, &mut bufs)?;
+    }
+    writer.close()?;
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::types::{Event, EventKind};
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+
+    #[test]
+    fn parquet_round_trips_events() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = TraceDb::create(&dir.path().join("t.sqlite")).unwrap();
+        for (ts, detail) in [(5, "b, \"quoted\""), (2, "a")] {
+            db.insert_event(&Event {
+                ts,
+                proc_id: 7,
+                kind: EventKind::NativeTraceEnter,
+                detail: detail.into(),
+            })
+            .unwrap();
+        }
+
+        let path = dir.path().join("events.parquet");
+        let rows = write_parquet(&db, Table::Events, File::create(&path).unwrap()).unwrap();
+        assert_eq!(rows, 2);
+        let reader = SerializedFileReader::new(File::open(&path).unwrap()).unwrap();
+        let read: Vec<String> = reader
+            .get_row_iter(None)
+            .unwrap()
+            .map(|r| r.unwrap().to_string())
+            .collect();
+        assert_eq!(read.len(), 2);
+        assert!(
+            read[0].starts_with("{ts_ns: 2, pid: 7, kind: "),
+            "{}",
+            read[0]
+        );
+        assert!(
+            read[1].ends_with(r#"detail: "b, "quoted""}"#),
+            "{}",
+            read[1]
+        );
+
+        let mut csv = Vec::new();
+        write_csv(&db, Table::Events, &mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        assert!(csv.starts_with("ts_ns,pid,kind,detail\n2,7,"));
+        assert!(csv.ends_with(",\"b, \"\"quoted\"\"\"\n"));
+    }
+
+    #[test]
+    fn schema_marks_nullable_columns_optional() {
+        let schema = parquet_schema(Table::Events);
+        assert_eq!(
+            schema,
+            "message events {\n  REQUIRED INT64 ts_ns;\n  REQUIRED INT32 pid;\n  \
+             REQUIRED BINARY kind (UTF8);\n  OPTIONAL BINARY detail (UTF8);\n}"
+        );
+        assert!(parse_message_type(&parquet_schema(Table::Files)).is_ok());
+        assert!(parse_message_type(&parquet_schema(Table::Net)).is_ok());
+    }
+}
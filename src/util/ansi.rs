@@ -0,0 +1,203 @@
+use std::str::Chars;
+
+/// An unterminated escape sequence longer than this is dropped rather than
+/// held back waiting for the rest.
+const MAX_PENDING: usize = 4096;
+
+/// Renders captured terminal output as plain text: escape sequences are
+/// stripped, `\r\n` becomes `\n`, a bare `\r` discards the line so far (as a
+/// progress bar redraw would), and backspace erases the previous character.
+pub fn clean_text(data: &[u8]) -> String {
+    let mut cleaner = Cleaner::default();
+    let mut out = cleaner.push(data);
+    out.push_str(&cleaner.finish());
+    out
+}
+
+/// [`clean_text`] for a stream that arrives in pieces. A line, `\r`, escape
+/// sequence or UTF-8 character cut by a chunk boundary is carried over to
+/// the next piece, so the pieces clean to the same text as the whole.
+#[derive(Debug, Default)]
+pub struct Cleaner {
+    /// Bytes not yet rendered: a split UTF-8 character or escape sequence.
+    pending: Vec<u8>,
+    line: String,
+    /// A `\r` whose effect depends on the character after it.
+    cr: bool,
+}
+
+impl Cleaner {
+    /// Feeds the next piece; returns the lines it completes.
+    pub fn push(&mut self, data: &[u8]) -> String {
+        let text = self.decode(data);
+        self.render(&text, false)
+    }
+
+    /// Ends the stream; returns the unfinished last line.
+    pub fn finish(&mut self) -> String {
+        let rest = std::mem::take(&mut self.pending);
+        let mut out = self.render(&String::from_utf8_lossy(&rest), true);
+        out.push_str(&std::mem::take(&mut self.line));
+        self.cr = false;
+        out
+    }
+
+    /// Decodes pending bytes plus `data`, keeping an incomplete trailing
+    /// UTF-8 character back; invalid bytes become U+FFFD.
+    fn decode(&mut self, data: &[u8]) -> String {
+        let mut bytes = std::mem::take(&mut self.pending);
+        bytes.extend_from_slice(data);
+        let mut text = String::with_capacity(bytes.len());
+        let mut rest = &bytes[..];
+        loop {
+            match std::str::from_utf8(rest) {
+                Ok(s) => {
+                    text.push_str(s);
+                    break;
+                }
+                Err(e) => {
+                    let (valid, after) = rest.split_at(e.valid_up_to());
+                    text.push_str(std::str::from_utf8(valid).unwrap_or_default());
+                    match e.error_len() {
+                        Some(n) => {
+                            text.push(char::REPLACEMENT_CHARACTER);
+                            rest = &after[n..];
+                        }
+                        None => {
+                            self.pending = after.to_vec();
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+        text
+    }
+
+    fn render(&mut self, text: &str, last: bool) -> String {
+        let mut out = String::with_capacity(text.len());
+        let mut chars = text.chars();
+
+        loop {
+            let start = text.len() - chars.as_str().len();
+            let Some(c) = chars.next() else { break };
+            if std::mem::take(&mut self.cr) && c != '\n' {
+                self.line.clear();
+            }
+            let complete = match c {
+                '\x1b' => skip_escape(&mut chars),
+                '\u{9b}' => skip_csi(&mut chars),
+                '\r' => {
+                    self.cr = true;
+                    true
+                }
+                '\n' => {
+                    out.push_str(&self.line);
+                    out.push('\n');
+                    self.line.clear();
+                    true
+                }
+                '\x08' => {
+                    self.line.pop();
+                    true
+                }
+                '\t' => {
+                    self.line.push(c);
+                    true
+                }
+                c if c.is_control() => true,
+                c => {
+                    self.line.push(c);
+                    true
+                }
+            };
+            if !complete && !last && text.len() - start <= MAX_PENDING {
+                let mut held = text.as_bytes()[start..].to_vec();
+                held.append(&mut self.pending);
+                self.pending = held;
+                break;
+            }
+        }
+        out
+    }
+}
+
+/// Each skip returns false when the input ran out before the sequence ended.
+fn skip_escape(chars: &mut Chars) -> bool {
+    match chars.next() {
+        None => false,
+        Some('[') => skip_csi(chars),
+        Some(']') | Some('P') | Some('X') | Some('^') | Some('_') => skip_string(chars),
+        // nF sequences: intermediates (0x20-0x2F) followed by one final byte.
+        Some(c) if ('\x20'..='\x2f').contains(&c) => loop {
+            match chars.next() {
+                None => break false,
+                Some(c) if ('\x20'..='\x2f').contains(&c) => {}
+                Some(_) => break true,
+            }
+        },
+        _ => true,
+    }
+}
+
+fn skip_csi(chars: &mut Chars) -> bool {
+    chars.any(|c| ('\x40'..='\x7e').contains(&c))
+}
+
+/// OSC/DCS/etc. run until BEL or the string terminator `ESC \`.
+fn skip_string(chars: &mut Chars) -> bool {
+    while let Some(c) = chars.next() {
+        match c {
+            '\x07' | '\u{9c}' => return true,
+            // A final ESC may be the first half of `ESC \`.
+            '\x1b' => match chars.as_str().chars().next() {
+                None => return false,
+                Some('\\') => {
+                    chars.next();
+                    return true;
+                }
+                Some(_) => return true,
+            },
+            _ => {}
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strips_color_and_osc() {
+        let raw = b"\x1b[1;31merror\x1b[0m: \x1b]8;;http://x\x1b\\link\x1b]8;;\x07 done\n";
+        assert_eq!(clean_text(raw), "error: link done\n");
+    }
+
+    #[test]
+    fn collapses_carriage_returns() {
+        assert_eq!(clean_text(b"10%\r50%\r100%\r\nok\r\n"), "100%\nok\n");
+        assert_eq!(clean_text(b"abx\x08c"), "abc");
+    }
+
+    #[test]
+    fn chunk_boundaries_do_not_change_the_text() {
+        let raw = "10%\r50%\x1b[32mok\x1b[0m \u{e9}t\u{e9}\r\ndone\x1b]0;t\x07\n".as_bytes();
+        let whole = clean_text(raw);
+        assert_eq!(whole, "50%ok \u{e9}t\u{e9}\ndone\n");
+        for at in 1..raw.len() {
+            let mut cleaner = Cleaner::default();
+            let mut out = cleaner.push(&raw[..at]);
+            out.push_str(&cleaner.push(&raw[at..]));
+            out.push_str(&cleaner.finish());
+            assert_eq!(out, whole, "split at byte {at}");
+        }
+
+        // Split right after `\r` and inside a CSI sequence.
+        let mut cleaner = Cleaner::default();
+        assert_eq!(cleaner.push(b"10%\r"), "");
+        assert_eq!(cleaner.push(b"50%\x1b[3"), "");
+        assert_eq!(cleaner.push(b"2mok\x1b[0m\n"), "50%ok\n");
+        assert_eq!(cleaner.finish(), "");
+    }
+}
@@ -1,5 +1,7 @@
+pub mod ansi;
 pub mod procfs;
 pub mod ringbuf;
+pub mod sandbox;
 
 use sha2::{Digest, Sha256};
 use std::time::{SystemTime, UNIX_EPOCH};
@@ -28,6 +30,12 @@ pub fn hash_bytes(data: &[u8]) -> String {
     format!("{:x}", hasher.finalize())
 }
 
+pub fn hash_file(path: &std::path::Path) -> std::io::Result<String> {
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut std::fs::File::open(path)?, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
 pub fn hash_env(env: &std::collections::HashMap<String, String>) -> String {
     let mut sorted: Vec<_> = env.iter().collect();
     sorted.sort_by_key(|(k, _)| k.as_str());
@@ -42,6 +50,80 @@ pub fn hash_env(env: &std::collections::HashMap<String, String>) -> String {
     format!("{:x}", hasher.finalize())[..16].to_string()
 }
 
+/// Quotes a CSV field when it holds a separator, quote or newline.
+pub fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+/// Single-quotes a word for a POSIX shell.
+pub fn shell_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "'\\''"))
+}
+
+pub fn parse_size(s: &str) -> Option<u64> {
+    let s = s.trim();
+    let split = s
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(s.len());
+    let (num, unit) = s.split_at(split);
+    let num: f64 = num.parse().ok()?;
+
+    let multiplier: u64 = match unit.trim().to_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" | "KB" | "KIB" => 1024,
+        "M" | "MB" | "MIB" => 1024 * 1024,
+        "G" | "GB" | "GIB" => 1024 * 1024 * 1024,
+        _ => return None,
+    };
+
+    Some((num * multiplier as f64) as u64)
+}
+
+/// Open flags as `O_WRONLY|O_CREAT|O_TRUNC`, with any bits not named here
+/// left as hex.
+pub fn open_flags(flags: i32) -> String {
+    const NAMED: [(i32, &str); 14] = [
+        (libc::O_CREAT, "O_CREAT"),
+        (libc::O_EXCL, "O_EXCL"),
+        (libc::O_NOCTTY, "O_NOCTTY"),
+        (libc::O_TRUNC, "O_TRUNC"),
+        (libc::O_APPEND, "O_APPEND"),
+        (libc::O_NONBLOCK, "O_NONBLOCK"),
+        // O_SYNC and O_TMPFILE include the bits of O_DSYNC and
+        // O_DIRECTORY, so they are matched first.
+        (libc::O_SYNC, "O_SYNC"),
+        (libc::O_DSYNC, "O_DSYNC"),
+        (libc::O_DIRECT, "O_DIRECT"),
+        (libc::O_TMPFILE, "O_TMPFILE"),
+        (libc::O_DIRECTORY, "O_DIRECTORY"),
+        (libc::O_NOFOLLOW, "O_NOFOLLOW"),
+        (libc::O_NOATIME, "O_NOATIME"),
+        (libc::O_CLOEXEC, "O_CLOEXEC"),
+    ];
+    let mut names = vec![match flags & libc::O_ACCMODE {
+        libc::O_WRONLY => "O_WRONLY",
+        libc::O_RDWR => "O_RDWR",
+        _ if flags & libc::O_PATH != 0 => "O_PATH",
+        _ => "O_RDONLY",
+    }
+    .to_string()];
+    let mut rest = flags & !libc::O_ACCMODE & !libc::O_PATH;
+    for (bits, name) in NAMED {
+        if bits != 0 && rest & bits == bits {
+            names.push(name.to_string());
+            rest &= !bits;
+        }
+    }
+    if rest != 0 {
+        names.push(format!("0x{:x}", rest));
+    }
+    names.join("|")
+}
+
 pub fn signal_name(sig: i32) -> &'static str {
     match sig {
         1 => "SIGHUP",
@@ -59,10 +141,97 @@ pub fn signal_name(sig: i32) -> &'static str {
         13 => "SIGPIPE",
         14 => "SIGALRM",
         15 => "SIGTERM",
+        16 => "SIGSTKFLT",
         17 => "SIGCHLD",
         18 => "SIGCONT",
         19 => "SIGSTOP",
         20 => "SIGTSTP",
+        21 => "SIGTTIN",
+        22 => "SIGTTOU",
+        23 => "SIGURG",
+        24 => "SIGXCPU",
+        25 => "SIGXFSZ",
+        26 => "SIGVTALRM",
+        27 => "SIGPROF",
+        28 => "SIGWINCH",
+        29 => "SIGIO",
+        30 => "SIGPWR",
+        31 => "SIGSYS",
         _ => "UNKNOWN",
     }
 }
+
+/// Shell-style wildcard match: `*` is any run of characters, `?` any one.
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let (p, t): (Vec<char>, Vec<char>) = (pattern.chars().collect(), text.chars().collect());
+    let (mut pi, mut ti) = (0, 0);
+    // Where the last `*` was and the text position it currently covers up to.
+    let mut star: Option<(usize, usize)> = None;
+    while ti < t.len() {
+        if pi < p.len() && (p[pi] == '?' || p[pi] == t[ti]) {
+            pi += 1;
+            ti += 1;
+        } else if pi < p.len() && p[pi] == '*' {
+            star = Some((pi, ti));
+            pi += 1;
+        } else if let Some((sp, st)) = star {
+            pi = sp + 1;
+            ti = st + 1;
+            star = Some((sp, st + 1));
+        } else {
+            return false;
+        }
+    }
+    p[pi..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_size_units() {
+        assert_eq!(parse_size("512"), Some(512));
+        assert_eq!(parse_size("4KB"), Some(4096));
+        assert_eq!(parse_size("100MB"), Some(100 * 1024 * 1024));
+        assert_eq!(parse_size("1.5G"), Some(1536 * 1024 * 1024));
+        assert_eq!(parse_size("10 mb"), Some(10 * 1024 * 1024));
+        assert_eq!(parse_size("lots"), None);
+    }
+
+    #[test]
+    fn open_flags_are_named() {
+        assert_eq!(
+            open_flags(libc::O_RDONLY | libc::O_CLOEXEC),
+            "O_RDONLY|O_CLOEXEC"
+        );
+        assert_eq!(
+            open_flags(libc::O_WRONLY | libc::O_CREAT | libc::O_EXCL),
+            "O_WRONLY|O_CREAT|O_EXCL"
+        );
+        assert_eq!(open_flags(libc::O_RDWR | libc::O_SYNC), "O_RDWR|O_SYNC");
+        assert_eq!(
+            open_flags(libc::O_PATH | libc::O_DIRECTORY),
+            "O_PATH|O_DIRECTORY"
+        );
+    }
+
+    #[test]
+    fn glob_matches_wildcards() {
+        assert!(glob_match("cc1*", "cc1plus"));
+        assert!(glob_match("cc1*", "cc1"));
+        assert!(glob_match("rustc", "rustc"));
+        assert!(!glob_match("rustc", "rustdoc"));
+        assert!(!glob_match("*-gcc-?", "x86_64-linux-gnu-gcc-12"));
+        assert!(glob_match("*-gcc-??", "x86_64-linux-gnu-gcc-12"));
+        assert!(glob_match("*", ""));
+        assert!(!glob_match("a*b", "acd"));
+    }
+
+    #[test]
+    fn shell_quote_survives_quotes_and_expansions() {
+        assert_eq!(shell_quote("a b"), "'a b'");
+        assert_eq!(shell_quote("$(id)"), "'$(id)'");
+        assert_eq!(shell_quote("it's"), "'it'\\''s'");
+    }
+}
@@ -65,6 +65,11 @@ pub fn read_cwd(pid: i32) -> Result<String> {
     Ok(target.to_string_lossy().into_owned())
 }
 
+pub fn read_fd_path(pid: i32, fd: i32) -> Option<String> {
+    let target = fs::read_link(format!("/proc/{}/fd/{}", pid, fd)).ok()?;
+    Some(target.to_string_lossy().into_owned())
+}
+
 pub fn read_environ(pid: i32) -> Result<HashMap<String, String>> {
     let path = format!("/proc/{}/environ", pid);
     let content = fs::read(&path).with_context(|| format!("failed to read {}", path))?;
@@ -102,6 +107,109 @@ pub fn read_status_field(pid: i32, field: &str) -> Result<String> {
     anyhow::bail!("field {} not found in {}", field, path)
 }
 
+/// A task's CPU time and time spent runnable but waiting for a CPU, from
+/// `/proc/<tid>/schedstat` (needs a kernel with schedstats/sched_info).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SchedStat {
+    pub run_ns: u64,
+    pub wait_ns: u64,
+    pub timeslices: u64,
+    pub voluntary_switches: u64,
+    pub involuntary_switches: u64,
+}
+
+pub fn read_schedstat(tid: i32) -> Result<SchedStat> {
+    let path = format!("/proc/{}/schedstat", tid);
+    let content = fs::read_to_string(&path).with_context(|| format!("failed to read {}", path))?;
+    let mut stat = parse_schedstat(&content).with_context(|| format!("malformed {}", path))?;
+    let switches = |field| {
+        read_status_field(tid, field)
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(0)
+    };
+    stat.voluntary_switches = switches("voluntary_ctxt_switches");
+    stat.involuntary_switches = switches("nonvoluntary_ctxt_switches");
+    Ok(stat)
+}
+
+fn parse_schedstat(content: &str) -> Option<SchedStat> {
+    let mut fields = content.split_whitespace().map(|f| f.parse::<u64>().ok());
+    Some(SchedStat {
+        run_ns: fields.next()??,
+        wait_ns: fields.next()??,
+        timeslices: fields.next()??,
+        ..Default::default()
+    })
+}
+
+/// A task's I/O accounting from `/proc/<tgid>/task/<tid>/io`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct IoCounters {
+    /// Bytes passed to read-family syscalls, from any kind of fd.
+    pub rchar: u64,
+    pub wchar: u64,
+    /// Bytes the task caused to be fetched from storage, page faults of
+    /// mapped files included.
+    pub read_bytes: u64,
+    /// Bytes the task dirtied for writeback, through mappings too.
+    pub write_bytes: u64,
+    /// Dirtied bytes that never reached storage, e.g. a file truncated
+    /// before writeback.
+    pub cancelled_write_bytes: u64,
+}
+
+pub fn read_io(tgid: i32, tid: i32) -> Result<IoCounters> {
+    let path = format!("/proc/{}/task/{}/io", tgid, tid);
+    let content = fs::read_to_string(&path).with_context(|| format!("failed to read {}", path))?;
+    let mut io = IoCounters::default();
+    for line in content.lines() {
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        let value = value.trim().parse().unwrap_or(0);
+        match key {
+            "rchar" => io.rchar = value,
+            "wchar" => io.wchar = value,
+            "read_bytes" => io.read_bytes = value,
+            "write_bytes" => io.write_bytes = value,
+            "cancelled_write_bytes" => io.cancelled_write_bytes = value,
+            _ => {}
+        }
+    }
+    Ok(io)
+}
+
+/// Bytes received and sent on the non-loopback interfaces of `pid`'s
+/// network namespace, from `/proc/<pid>/net/dev`.
+pub fn read_net_dev(pid: i32) -> Result<(u64, u64)> {
+    let path = format!("/proc/{}/net/dev", pid);
+    let content = fs::read_to_string(&path).with_context(|| format!("failed to read {}", path))?;
+    Ok(parse_net_dev(&content))
+}
+
+fn parse_net_dev(content: &str) -> (u64, u64) {
+    let (mut rx, mut tx) = (0, 0);
+    // Two header lines, then `iface: rx_bytes packets ... tx_bytes ...`.
+    for line in content.lines().skip(2) {
+        let Some((iface, counters)) = line.split_once(':') else {
+            continue;
+        };
+        if iface.trim() == "lo" {
+            continue;
+        }
+        let fields: Vec<u64> = counters
+            .split_whitespace()
+            .map(|f| f.parse().unwrap_or(0))
+            .collect();
+        if fields.len() >= 9 {
+            rx += fields[0];
+            tx += fields[8];
+        }
+    }
+    (rx, tx)
+}
+
 pub fn git_sha(dir: &Path) -> Option<String> {
     let output = std::process::Command::new("git")
         .args(["rev-parse", "HEAD"])
@@ -0,0 +1,311 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+/// Inode numbers of the initial namespaces, fixed by the kernel.
+const INIT_USER_NS: &str = "user:[4026531837]";
+const INIT_PID_NS: &str = "pid:[4026531836]";
+
+const CAPABILITIES: [&str; 41] = [
+    "CAP_CHOWN",
+    "CAP_DAC_OVERRIDE",
+    "CAP_DAC_READ_SEARCH",
+    "CAP_FOWNER",
+    "CAP_FSETID",
+    "CAP_KILL",
+    "CAP_SETGID",
+    "CAP_SETUID",
+    "CAP_SETPCAP",
+    "CAP_LINUX_IMMUTABLE",
+    "CAP_NET_BIND_SERVICE",
+    "CAP_NET_BROADCAST",
+    "CAP_NET_ADMIN",
+    "CAP_NET_RAW",
+    "CAP_IPC_LOCK",
+    "CAP_IPC_OWNER",
+    "CAP_SYS_MODULE",
+    "CAP_SYS_RAWIO",
+    "CAP_SYS_CHROOT",
+    "CAP_SYS_PTRACE",
+    "CAP_SYS_PACCT",
+    "CAP_SYS_ADMIN",
+    "CAP_SYS_BOOT",
+    "CAP_SYS_NICE",
+    "CAP_SYS_RESOURCE",
+    "CAP_SYS_TIME",
+    "CAP_SYS_TTY_CONFIG",
+    "CAP_MKNOD",
+    "CAP_LEASE",
+    "CAP_AUDIT_WRITE",
+    "CAP_AUDIT_CONTROL",
+    "CAP_SETFCAP",
+    "CAP_MAC_OVERRIDE",
+    "CAP_MAC_ADMIN",
+    "CAP_SYSLOG",
+    "CAP_WAKE_ALARM",
+    "CAP_BLOCK_SUSPEND",
+    "CAP_AUDIT_READ",
+    "CAP_PERFMON",
+    "CAP_BPF",
+    "CAP_CHECKPOINT_RESTORE",
+];
+
+const RLIMITS: [(&str, libc::__rlimit_resource_t); 7] = [
+    ("nofile", libc::RLIMIT_NOFILE),
+    ("nproc", libc::RLIMIT_NPROC),
+    ("stack", libc::RLIMIT_STACK),
+    ("core", libc::RLIMIT_CORE),
+    ("as", libc::RLIMIT_AS),
+    ("fsize", libc::RLIMIT_FSIZE),
+    ("memlock", libc::RLIMIT_MEMLOCK),
+];
+
+/// The restrictions poe runs under, which the traced command inherits:
+/// what explains a run that passes on a laptop and fails in a container.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Sandbox {
+    /// Effective capabilities by name.
+    pub capabilities: Vec<String>,
+    /// `host`, or `nested` for a user namespace, with its uid map.
+    pub user_namespace: String,
+    #[serde(default)]
+    pub uid_map: Option<String>,
+    pub pid_namespace: String,
+    /// `disabled`, `strict` or `filter`.
+    pub seccomp: String,
+    pub no_new_privs: bool,
+    /// The tightest limits of the process's cgroup and its ancestors.
+    #[serde(default)]
+    pub memory_limit: Option<u64>,
+    /// In CPUs, e.g. 1.5.
+    #[serde(default)]
+    pub cpu_limit: Option<f64>,
+    #[serde(default)]
+    pub pids_limit: Option<u64>,
+    /// Soft and hard resource limits; None is unlimited.
+    #[serde(default)]
+    pub rlimits: BTreeMap<String, (Option<u64>, Option<u64>)>,
+}
+
+impl Sandbox {
+    pub fn read() -> Self {
+        let status = std::fs::read_to_string("/proc/self/status").unwrap_or_default();
+        let field = |name: &str| {
+            status
+                .lines()
+                .find_map(|l| l.strip_prefix(name)?.strip_prefix(':'))
+                .map(str::trim)
+        };
+        let namespace =
+            |name: &str, init: &str| match std::fs::read_link(format!("/proc/self/ns/{}", name)) {
+                Ok(link) if link.to_string_lossy() == init => "host".to_string(),
+                Ok(_) => "nested".to_string(),
+                Err(_) => "unknown".to_string(),
+            };
+
+        let user_namespace = namespace("user", INIT_USER_NS);
+        let uid_map = (user_namespace != "host")
+            .then(|| std::fs::read_to_string("/proc/self/uid_map").ok())
+            .flatten()
+            .map(|m| m.split_whitespace().collect::<Vec<_>>().join(" "));
+        let cgroups = std::fs::read_to_string("/proc/self/cgroup").unwrap_or_default();
+
+        Sandbox {
+            capabilities: field("CapEff")
+                .and_then(|hex| u64::from_str_radix(hex, 16).ok())
+                .map(capability_names)
+                .unwrap_or_default(),
+            user_namespace,
+            uid_map,
+            pid_namespace: namespace("pid", INIT_PID_NS),
+            seccomp: match field("Seccomp") {
+                Some("0") => "disabled",
+                Some("1") => "strict",
+                Some("2") => "filter",
+                _ => "unknown",
+            }
+            .into(),
+            no_new_privs: field("NoNewPrivs") == Some("1"),
+            memory_limit: cgroup_limit(&cgroups, "", |d| number(&d.join("memory.max"))).or_else(
+                || {
+                    cgroup_limit(&cgroups, "memory", |d| {
+                        number(&d.join("memory.limit_in_bytes"))
+                    })
+                },
+            ),
+            cpu_limit: cgroup_limit(&cgroups, "", |d| {
+                let content = std::fs::read_to_string(d.join("cpu.max")).ok()?;
+                let (quota, period) = content.trim().split_once(' ')?;
+                cpus(quota.parse().ok()?, period.parse().ok()?)
+            })
+            .or_else(|| {
+                cgroup_limit(&cgroups, "cpu", |d| {
+                    let read = |f: &str| std::fs::read_to_string(d.join(f)).ok();
+                    cpus(
+                        read("cpu.cfs_quota_us")?.trim().parse().ok()?,
+                        read("cpu.cfs_period_us")?.trim().parse().ok()?,
+                    )
+                })
+            }),
+            pids_limit: cgroup_limit(&cgroups, "", |d| number(&d.join("pids.max")))
+                .or_else(|| cgroup_limit(&cgroups, "pids", |d| number(&d.join("pids.max")))),
+            rlimits: RLIMITS
+                .iter()
+                .filter_map(|(name, resource)| Some((name.to_string(), rlimit(*resource)?)))
+                .collect(),
+        }
+    }
+
+    /// One line per setting, for doctor and for diffing two packs.
+    pub fn summary(&self) -> BTreeMap<String, String> {
+        let mut lines = BTreeMap::new();
+        let missing: Vec<&str> = CAPABILITIES
+            .iter()
+            .filter(|c| !self.capabilities.iter().any(|have| have == *c))
+            .copied()
+            .collect();
+        // A root shell has nearly all of them; what it lacks is the news.
+        let caps = if self.capabilities.is_empty() {
+            "none".to_string()
+        } else if missing.is_empty() {
+            "all".to_string()
+        } else if missing.len() < self.capabilities.len() {
+            format!("all except {}", missing.join(", "))
+        } else {
+            self.capabilities.join(", ")
+        };
+        lines.insert("capabilities".to_string(), caps);
+        let user_ns = match &self.uid_map {
+            Some(map) => format!("{} (uid map {})", self.user_namespace, map),
+            None => self.user_namespace.clone(),
+        };
+        lines.insert("user namespace".to_string(), user_ns);
+        lines.insert("pid namespace".to_string(), self.pid_namespace.clone());
+        let seccomp = if self.no_new_privs {
+            format!("{}, no_new_privs", self.seccomp)
+        } else {
+            self.seccomp.clone()
+        };
+        lines.insert("seccomp".to_string(), seccomp);
+        let limit = |v: Option<String>| v.unwrap_or_else(|| "unlimited".into());
+        lines.insert(
+            "cgroup memory".to_string(),
+            limit(self.memory_limit.map(|b| format!("{} MB", b >> 20))),
+        );
+        lines.insert(
+            "cgroup cpu".to_string(),
+            limit(self.cpu_limit.map(|c| format!("{} CPUs", c))),
+        );
+        lines.insert(
+            "cgroup pids".to_string(),
+            limit(self.pids_limit.map(|p| p.to_string())),
+        );
+        for (name, (soft, hard)) in &self.rlimits {
+            let soft = limit(soft.map(|v| v.to_string()));
+            let hard = limit(hard.map(|v| v.to_string()));
+            lines.insert(
+                format!("ulimit {}", name),
+                format!("{} (hard {})", soft, hard),
+            );
+        }
+        lines
+    }
+}
+
+fn capability_names(mask: u64) -> Vec<String> {
+    CAPABILITIES
+        .iter()
+        .enumerate()
+        .filter(|(bit, _)| mask & (1 << bit) != 0)
+        .map(|(_, name)| name.to_string())
+        .collect()
+}
+
+/// The tightest limit `read` finds in the process's cgroup for
+/// `controller` (v1), or in the unified hierarchy for "", or in any of its
+/// ancestors.
+fn cgroup_limit<T: PartialOrd>(
+    cgroups: &str,
+    controller: &str,
+    read: impl Fn(&Path) -> Option<T>,
+) -> Option<T> {
+    let root = cgroup_root(controller);
+    let dir = cgroup_dir(cgroups, controller)?;
+    dir.ancestors()
+        .take_while(|d| d.starts_with(&root))
+        .filter_map(read)
+        .reduce(|a, b| if b < a { b } else { a })
+}
+
+/// A limit file's value; "max" and v1's near-2^63 sentinel mean none.
+fn number(path: &Path) -> Option<u64> {
+    let value: u64 = std::fs::read_to_string(path).ok()?.trim().parse().ok()?;
+    (value < 1 << 62).then_some(value)
+}
+
+/// A CFS quota as CPUs, to two decimals; a negative quota is unlimited.
+fn cpus(quota: i64, period: i64) -> Option<f64> {
+    (quota > 0 && period > 0).then(|| (quota as f64 / period as f64 * 100.0).round() / 100.0)
+}
+
+fn cgroup_root(controller: &str) -> PathBuf {
+    Path::new("/sys/fs/cgroup").join(controller)
+}
+
+fn cgroup_dir(cgroups: &str, controller: &str) -> Option<PathBuf> {
+    cgroups.lines().find_map(|line| {
+        let mut parts = line.splitn(3, ':');
+        let (_, controllers, path) = (parts.next()?, parts.next()?, parts.next()?);
+        let matches = if controller.is_empty() {
+            controllers.is_empty()
+        } else {
+            controllers.split(',').any(|c| c == controller)
+        };
+        let dir = cgroup_root(controller).join(path.trim_start_matches('/'));
+        (matches && dir.is_dir()).then_some(dir)
+    })
+}
+
+fn rlimit(resource: libc::__rlimit_resource_t) -> Option<(Option<u64>, Option<u64>)> {
+    let mut limit = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    // SAFETY: limit is a valid rlimit for getrlimit to fill.
+    if unsafe { libc::getrlimit(resource, &mut limit) } != 0 {
+        return None;
+    }
+    let finite = |v: libc::rlim_t| (v != libc::RLIM_INFINITY).then_some(v);
+    Some((finite(limit.rlim_cur), finite(limit.rlim_max)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn summarizes_capabilities_and_limits() {
+        let sandbox = Sandbox {
+            capabilities: capability_names(1 << 19 | 1 << 21),
+            user_namespace: "nested".into(),
+            uid_map: Some("0 1000 1".into()),
+            pid_namespace: "host".into(),
+            seccomp: "filter".into(),
+            no_new_privs: true,
+            memory_limit: Some(512 << 20),
+            cpu_limit: cpus(150_000, 100_000),
+            pids_limit: None,
+            rlimits: [("nofile".to_string(), (Some(1024), None))].into(),
+        };
+        let summary = sandbox.summary();
+        assert_eq!(summary["capabilities"], "CAP_SYS_PTRACE, CAP_SYS_ADMIN");
+        assert_eq!(summary["user namespace"], "nested (uid map 0 1000 1)");
+        assert_eq!(summary["seccomp"], "filter, no_new_privs");
+        assert_eq!(summary["cgroup memory"], "512 MB");
+        assert_eq!(summary["cgroup cpu"], "1.5 CPUs");
+        assert_eq!(summary["cgroup pids"], "unlimited");
+        assert_eq!(summary["ulimit nofile"], "1024 (hard unlimited)");
+        assert_eq!(cpus(-1, 100_000), None);
+    }
+}
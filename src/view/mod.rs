@@ -0,0 +1,611 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+
+use crate::explain::analyzer::{self, ExplainOutput};
+use crate::explain::patterns::PatternRegistry;
+use crate::explain::zoom;
+use crate::pack::reader::PackReader;
+use crate::trace::db::{Page, TraceDb};
+use crate::util;
+
+mod render;
+
+/// Rows loaded into the file and network tables.
+const MAX_ROWS: usize = 100_000;
+/// Lines the timeline loads for a window, after collapsing.
+const TIMELINE_LINES: usize = 5_000;
+/// How much `+` and `-` narrow and widen the timeline window.
+const ZOOM_FACTOR: f64 = 4.0;
+const PAGE: usize = 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tab {
+    Processes,
+    Timeline,
+    Files,
+    Net,
+    Stderr,
+    Hotspots,
+}
+
+impl Tab {
+    pub const ALL: [Tab; 6] = [
+        Tab::Processes,
+        Tab::Timeline,
+        Tab::Files,
+        Tab::Net,
+        Tab::Stderr,
+        Tab::Hotspots,
+    ];
+
+    pub fn title(self) -> &'static str {
+        match self {
+            Tab::Processes => "processes",
+            Tab::Timeline => "timeline",
+            Tab::Files => "files",
+            Tab::Net => "net",
+            Tab::Stderr => "stderr",
+            Tab::Hotspots => "hotspots",
+        }
+    }
+}
+
+pub struct Row {
+    pub cells: Vec<String>,
+    /// Failed operations, failed processes and error lines.
+    pub alert: bool,
+    pub ts_ms: Option<f64>,
+    pub pid: Option<i32>,
+}
+
+/// One tab's table, with its filter and scroll position.
+#[derive(Default)]
+pub struct Pane {
+    pub header: Vec<&'static str>,
+    pub rows: Vec<Row>,
+    /// Indices of the rows matching `filter`.
+    pub visible: Vec<usize>,
+    /// Position in `visible`.
+    pub selected: usize,
+    pub offset: usize,
+    pub filter: String,
+    /// Shown after the tab's title, e.g. how many rows were left out.
+    pub note: String,
+}
+
+impl Pane {
+    fn new(header: Vec<&'static str>, rows: Vec<Row>) -> Self {
+        let mut pane = Pane {
+            header,
+            rows,
+            ..Default::default()
+        };
+        pane.apply_filter();
+        pane
+    }
+
+    /// Case-insensitive substring match on any cell.
+    fn apply_filter(&mut self) {
+        let needle = self.filter.to_lowercase();
+        self.visible = (0..self.rows.len())
+            .filter(|&i| {
+                needle.is_empty()
+                    || self.rows[i]
+                        .cells
+                        .iter()
+                        .any(|c| c.to_lowercase().contains(&needle))
+            })
+            .collect();
+        self.selected = self.selected.min(self.visible.len().saturating_sub(1));
+    }
+
+    fn move_by(&mut self, delta: isize) {
+        let last = self.visible.len().saturating_sub(1);
+        self.selected = self.selected.saturating_add_signed(delta).min(last);
+    }
+
+    pub fn current(&self) -> Option<&Row> {
+        self.visible.get(self.selected).map(|&i| &self.rows[i])
+    }
+}
+
+pub struct App {
+    pub command: String,
+    pub status: String,
+    pub diagnosis: Option<String>,
+    pub tab: Tab,
+    pub panes: Vec<Pane>,
+    /// Typing into the current pane's filter.
+    pub editing: bool,
+    pub window: (Option<f64>, Option<f64>),
+    pub pid: Option<i32>,
+    duration_ms: f64,
+    pub quit: bool,
+}
+
+/// Opens the viewer on a terminal and runs it until `q`.
+pub fn run(pack: &PackReader, registry: &PatternRegistry) -> Result<()> {
+    let mut app = App::load(pack, registry)?;
+    let mut terminal = ratatui::init();
+    let result = (|| -> Result<()> {
+        while !app.quit {
+            terminal.draw(|frame| render::draw(frame, &mut app))?;
+            if let Event::Key(key) = event::read()? {
+                if key.kind == KeyEventKind::Press {
+                    app.handle_key(key, pack.db())?;
+                }
+            }
+        }
+        Ok(())
+    })();
+    ratatui::restore();
+    result
+}
+
+impl App {
+    pub fn load(pack: &PackReader, registry: &PatternRegistry) -> Result<Self> {
+        let output = analyzer::analyze_with(pack, registry)?;
+        let summary = pack.summary();
+        let db = pack.db();
+
+        let mut status = match (&summary.signal_name, summary.exit_code) {
+            (Some(signal), _) => format!("killed by {}", signal),
+            (None, Some(code)) => format!("exit {}", code),
+            (None, None) => "running".to_string(),
+        };
+        status.push_str(&format!(
+            "  {}ms  run {}",
+            summary.duration_ms,
+            &summary.run_id[..summary.run_id.len().min(8)]
+        ));
+        let diagnosis = output
+            .error_patterns
+            .iter()
+            .find(|p| p.root_cause)
+            .or(output.error_patterns.first())
+            .map(|p| format!("{}: {}", p.category, p.description));
+
+        let stderr = pack.stdio_text("stderr").unwrap_or_default();
+        let mut app = App {
+            command: summary.command.join(" "),
+            status,
+            diagnosis,
+            tab: Tab::Processes,
+            panes: vec![
+                Pane::new(
+                    vec!["pid", "status", "duration", "command"],
+                    process_rows(&output),
+                ),
+                Pane::default(),
+                Pane::new(
+                    vec!["time", "pid", "op", "path", "bytes", "result", "took"],
+                    file_rows(db)?,
+                ),
+                Pane::new(
+                    vec!["time", "pid", "op", "proto", "address", "bytes", "result"],
+                    net_rows(db)?,
+                ),
+                Pane::new(vec!["line"], stderr_rows(&stderr)),
+                Pane::new(vec!["kind", "cost", "where"], hotspot_rows(&output)),
+            ],
+            editing: false,
+            window: (None, None),
+            pid: None,
+            duration_ms: summary.duration_ms as f64,
+            quit: false,
+        };
+        for tab in [Tab::Files, Tab::Net] {
+            let pane = app.pane_mut(tab);
+            if pane.rows.len() == MAX_ROWS {
+                pane.note = format!("first {} rows", MAX_ROWS);
+            }
+        }
+        app.reload_timeline(db)?;
+        Ok(app)
+    }
+
+    pub fn pane(&self) -> &Pane {
+        &self.panes[self.tab as usize]
+    }
+
+    fn pane_mut(&mut self, tab: Tab) -> &mut Pane {
+        &mut self.panes[tab as usize]
+    }
+
+    pub fn handle_key(&mut self, key: KeyEvent, db: &TraceDb) -> Result<()> {
+        let tab = self.tab;
+        if self.editing {
+            let pane = self.pane_mut(tab);
+            match key.code {
+                KeyCode::Enter => self.editing = false,
+                KeyCode::Esc => {
+                    pane.filter.clear();
+                    pane.apply_filter();
+                    self.editing = false;
+                }
+                KeyCode::Backspace => {
+                    pane.filter.pop();
+                    pane.apply_filter();
+                }
+                KeyCode::Char(c) => {
+                    pane.filter.push(c);
+                    pane.apply_filter();
+                }
+                _ => {}
+            }
+            return Ok(());
+        }
+
+        let index = tab as usize;
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => self.quit = true,
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => self.quit = true,
+            KeyCode::Tab | KeyCode::Right | KeyCode::Char('l') => {
+                self.tab = Tab::ALL[(index + 1) % Tab::ALL.len()]
+            }
+            KeyCode::BackTab | KeyCode::Left | KeyCode::Char('h') => {
+                self.tab = Tab::ALL[(index + Tab::ALL.len() - 1) % Tab::ALL.len()]
+            }
+            KeyCode::Char(c @ '1'..='6') => self.tab = Tab::ALL[c as usize - '1' as usize],
+            KeyCode::Down | KeyCode::Char('j') => self.pane_mut(tab).move_by(1),
+            KeyCode::Up | KeyCode::Char('k') => self.pane_mut(tab).move_by(-1),
+            KeyCode::PageDown => self.pane_mut(tab).move_by(PAGE as isize),
+            KeyCode::PageUp => self.pane_mut(tab).move_by(-(PAGE as isize)),
+            KeyCode::Home | KeyCode::Char('g') => self.pane_mut(tab).selected = 0,
+            KeyCode::End | KeyCode::Char('G') => self.pane_mut(tab).move_by(isize::MAX),
+            KeyCode::Char('/') => self.editing = true,
+            KeyCode::Enter if tab == Tab::Processes => {
+                if let Some(pid) = self.pane().current().and_then(|r| r.pid) {
+                    self.pid = Some(pid);
+                    self.tab = Tab::Timeline;
+                    self.reload_timeline(db)?;
+                }
+            }
+            KeyCode::Char('p') if tab == Tab::Timeline => {
+                self.pid = match self.pid {
+                    Some(_) => None,
+                    None => self.pane().current().and_then(|r| r.pid),
+                };
+                self.reload_timeline(db)?;
+            }
+            KeyCode::Char('+') | KeyCode::Char('=') if tab == Tab::Timeline => {
+                self.zoom(1.0 / ZOOM_FACTOR);
+                self.reload_timeline(db)?;
+            }
+            KeyCode::Char('-') if tab == Tab::Timeline => {
+                self.zoom(ZOOM_FACTOR);
+                self.reload_timeline(db)?;
+            }
+            KeyCode::Char('0') if tab == Tab::Timeline => {
+                self.window = (None, None);
+                self.pid = None;
+                self.reload_timeline(db)?;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Scales the timeline window around the selected entry; a window
+    /// covering the whole run is dropped.
+    fn zoom(&mut self, factor: f64) {
+        let (start, end) = self.window;
+        let start = start.unwrap_or(0.0);
+        let end = end.unwrap_or(self.duration_ms);
+        let center = self
+            .pane()
+            .current()
+            .and_then(|r| r.ts_ms)
+            .unwrap_or((start + end) / 2.0);
+        let span = ((end - start) * factor).max(1.0);
+        if span >= self.duration_ms {
+            self.window = (None, None);
+            return;
+        }
+        let start = (center - span / 2.0).max(0.0);
+        self.window = (Some(start), Some(start + span));
+    }
+
+    fn reload_timeline(&mut self, db: &TraceDb) -> Result<()> {
+        let (start, end) = self.window;
+        let timeline = zoom::build(db, start, end, self.pid, TIMELINE_LINES)?;
+        let rows = timeline
+            .entries
+            .iter()
+            .map(|e| Row {
+                cells: vec![
+                    format!("{:.2}ms", e.ts_ms),
+                    e.proc_id.to_string(),
+                    e.kind.clone(),
+                    e.description.clone(),
+                ],
+                alert: e.description.contains(" err="),
+                ts_ms: Some(e.ts_ms),
+                pid: Some(e.proc_id),
+            })
+            .collect();
+        let selected_ts = self.pane().current().and_then(|r| r.ts_ms);
+        let filter = std::mem::take(&mut self.pane_mut(Tab::Timeline).filter);
+        let mut pane = Pane::new(vec!["time", "pid", "kind", "what"], rows);
+        pane.filter = filter;
+        pane.apply_filter();
+        // Keep the entry zoomed around in view.
+        if let Some(ts) = selected_ts {
+            pane.selected = pane
+                .visible
+                .iter()
+                .position(|&i| pane.rows[i].ts_ms.is_some_and(|t| t >= ts))
+                .unwrap_or(0);
+        }
+        let mut note = format!("{} ops in {} lines", timeline.total, pane.rows.len());
+        if timeline.omitted > 0 {
+            note.push_str(&format!(", {} more: zoom in", timeline.omitted));
+        }
+        pane.note = note;
+        *self.pane_mut(Tab::Timeline) = pane;
+        Ok(())
+    }
+}
+
+fn process_rows(output: &ExplainOutput) -> Vec<Row> {
+    let parents: HashMap<i32, i32> = output
+        .process_tree
+        .iter()
+        .filter_map(|p| Some((p.pid, p.parent_pid?)))
+        .collect();
+    output
+        .process_tree
+        .iter()
+        .map(|p| {
+            let mut depth = 0;
+            let mut pid = p.pid;
+            while let Some(&parent) = parents.get(&pid) {
+                depth += 1;
+                pid = parent;
+                if depth > 64 {
+                    break;
+                }
+            }
+            let status = match (p.signal, p.exit_code) {
+                (Some(sig), _) => format!("killed by {}", util::signal_name(sig)),
+                (None, Some(0)) => "ok".to_string(),
+                (None, Some(code)) => format!("exit {}", code),
+                (None, None) if p.excluded => "not traced".to_string(),
+                (None, None) => "?".to_string(),
+            };
+            Row {
+                cells: vec![
+                    p.pid.to_string(),
+                    status,
+                    p.duration_ms
+                        .map(|d| format!("{:.1}ms", d))
+                        .unwrap_or_default(),
+                    format!("{}{}", "  ".repeat(depth), p.command),
+                ],
+                alert: p.signal.is_some() || p.exit_code.is_some_and(|c| c != 0),
+                ts_ms: None,
+                pid: Some(p.pid),
+            }
+        })
+        .collect()
+}
+
+fn result_text(result: Option<i64>) -> String {
+    match result {
+        Some(r) if r < 0 => analyzer::errno_name(-r),
+        Some(r) => r.to_string(),
+        None => String::new(),
+    }
+}
+
+fn file_rows(db: &TraceDb) -> Result<Vec<Row>> {
+    let mut rows = Vec::new();
+    db.each_file_event(Page::new(Some(MAX_ROWS), 0), |f| {
+        let target = match (f.path, f.fd) {
+            (Some(path), _) if !path.is_empty() => path,
+            (_, Some(fd)) => format!("fd {}", fd),
+            _ => String::new(),
+        };
+        let ts_ms = f.ts as f64 / 1_000_000.0;
+        rows.push(Row {
+            cells: vec![
+                format!("{:.2}ms", ts_ms),
+                f.proc_id.to_string(),
+                f.op,
+                target,
+                f.bytes.map(|b| b.to_string()).unwrap_or_default(),
+                result_text(f.result),
+                f.duration_ns
+                    .map(|ns| format!("{:.2}ms", ns as f64 / 1_000_000.0))
+                    .unwrap_or_default(),
+            ],
+            alert: f.result.is_some_and(|r| r < 0),
+            ts_ms: Some(ts_ms),
+            pid: Some(f.proc_id),
+        });
+        Ok(())
+    })?;
+    Ok(rows)
+}
+
+fn net_rows(db: &TraceDb) -> Result<Vec<Row>> {
+    let mut rows = Vec::new();
+    db.each_net_event(Page::new(Some(MAX_ROWS), 0), |n| {
+        let ts_ms = n.ts as f64 / 1_000_000.0;
+        let address = n
+            .dst
+            .or(n.src)
+            .or(n.fd.map(|fd| format!("fd {}", fd)))
+            .unwrap_or_default();
+        rows.push(Row {
+            cells: vec![
+                format!("{:.2}ms", ts_ms),
+                n.proc_id.to_string(),
+                n.op,
+                n.proto.unwrap_or_default(),
+                address,
+                n.bytes.map(|b| b.to_string()).unwrap_or_default(),
+                result_text(n.result),
+            ],
+            // A non-blocking connect in progress hasn't failed yet.
+            alert: n
+                .result
+                .is_some_and(|r| r < 0 && r != -libc::EINPROGRESS as i64),
+            ts_ms: Some(ts_ms),
+            pid: Some(n.proc_id),
+        });
+        Ok(())
+    })?;
+    Ok(rows)
+}
+
+fn stderr_rows(stderr: &str) -> Vec<Row> {
+    stderr
+        .lines()
+        .map(|line| {
+            let lower = line.to_lowercase();
+            Row {
+                cells: vec![line.to_string()],
+                alert: ["error", "panic", "exception", "fatal", "traceback"]
+                    .iter()
+                    .any(|k| lower.contains(k)),
+                ts_ms: None,
+                pid: None,
+            }
+        })
+        .collect()
+}
+
+fn hotspot_rows(output: &ExplainOutput) -> Vec<Row> {
+    let row = |kind: &str, cost: String, place: String, alert: bool, pid: Option<i32>| Row {
+        cells: vec![kind.to_string(), cost, place],
+        alert,
+        ts_ms: None,
+        pid,
+    };
+    let mut rows = Vec::new();
+    for h in &output.hotspots {
+        rows.push(row(
+            "stack",
+            format!("{:.1}% ({})", h.percentage, h.count),
+            h.location.clone(),
+            false,
+            None,
+        ));
+    }
+    for op in &output.slow_ops {
+        let outcome = op
+            .errno_name
+            .as_ref()
+            .map(|e| format!(" -> {}", e))
+            .unwrap_or_default();
+        rows.push(Row {
+            ts_ms: Some(op.ts_ms),
+            ..row(
+                "slow op",
+                format!("{:.1}ms", op.duration_ms),
+                format!("[{}] {} {}{}", op.pid, op.op, op.target, outcome),
+                op.errno_name.is_some(),
+                Some(op.pid),
+            )
+        });
+    }
+    for f in &output.slow_functions {
+        rows.push(row(
+            "native",
+            format!("{:.2}ms x{}", f.inclusive_ms, f.calls),
+            f.func.clone(),
+            false,
+            None,
+        ));
+    }
+    for f in &output.python_slow_functions {
+        rows.push(row(
+            "python",
+            format!("{:.2}ms x{}", f.total_ms, f.calls),
+            format!("{}() at {}:{}", f.func, f.file, f.line),
+            false,
+            None,
+        ));
+    }
+    rows
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(code: KeyCode) -> KeyEvent {
+        KeyEvent::new(code, KeyModifiers::NONE)
+    }
+
+    #[test]
+    fn filters_and_navigates_panes() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = TraceDb::create(&dir.path().join("trace.sqlite")).unwrap();
+        let lines = "starting\nconnecting to db\nError: connection refused\nretrying\n";
+        let mut app = App {
+            command: "app".into(),
+            status: "exit 1".into(),
+            diagnosis: None,
+            tab: Tab::Processes,
+            panes: Tab::ALL.iter().map(|_| Pane::default()).collect(),
+            editing: false,
+            window: (None, None),
+            pid: None,
+            duration_ms: 1000.0,
+            quit: false,
+        };
+        app.panes[Tab::Stderr as usize] = Pane::new(vec!["line"], stderr_rows(lines));
+
+        app.handle_key(key(KeyCode::Char('5')), &db).unwrap();
+        assert_eq!(app.tab, Tab::Stderr);
+        app.handle_key(key(KeyCode::End), &db).unwrap();
+        assert_eq!(app.pane().selected, 3);
+        for code in [KeyCode::Char('/'), KeyCode::Char('C'), KeyCode::Char('o')] {
+            app.handle_key(key(code), &db).unwrap();
+        }
+        app.handle_key(key(KeyCode::Char('n')), &db).unwrap();
+        let shown: Vec<&str> = app
+            .pane()
+            .visible
+            .iter()
+            .map(|&i| app.pane().rows[i].cells[0].as_str())
+            .collect();
+        assert_eq!(shown, ["connecting to db", "Error: connection refused"]);
+        assert_eq!(app.pane().selected, 1);
+        assert!(app.pane().current().unwrap().alert);
+
+        // q goes into the filter while typing, and quits once done.
+        app.handle_key(key(KeyCode::Char('q')), &db).unwrap();
+        assert!(!app.quit);
+        app.handle_key(key(KeyCode::Esc), &db).unwrap();
+        assert_eq!(app.pane().visible.len(), 4);
+        app.handle_key(key(KeyCode::Char('q')), &db).unwrap();
+        assert!(app.quit);
+    }
+
+    #[test]
+    fn zooms_around_the_selected_entry() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = TraceDb::create(&dir.path().join("trace.sqlite")).unwrap();
+        let mut app = App {
+            command: String::new(),
+            status: String::new(),
+            diagnosis: None,
+            tab: Tab::Timeline,
+            panes: Tab::ALL.iter().map(|_| Pane::default()).collect(),
+            editing: false,
+            window: (None, None),
+            pid: None,
+            duration_ms: 1000.0,
+            quit: false,
+        };
+        app.zoom(1.0 / ZOOM_FACTOR);
+        assert_eq!(app.window, (Some(375.0), Some(625.0)));
+        app.handle_key(key(KeyCode::Char('-')), &db).unwrap();
+        assert_eq!(app.window, (None, None));
+    }
+}
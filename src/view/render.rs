@@ -0,0 +1,149 @@
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Borders, Cell, Paragraph, Row, Table, TableState, Tabs};
+use ratatui::Frame;
+
+use super::{App, Tab};
+
+fn widths(tab: Tab) -> Vec<Constraint> {
+    use Constraint::{Length, Min};
+    match tab {
+        Tab::Processes => vec![Length(8), Length(18), Length(10), Min(20)],
+        Tab::Timeline => vec![Length(12), Length(8), Length(6), Min(20)],
+        Tab::Files => vec![
+            Length(12),
+            Length(8),
+            Length(10),
+            Min(20),
+            Length(10),
+            Length(12),
+            Length(10),
+        ],
+        Tab::Net => vec![
+            Length(12),
+            Length(8),
+            Length(10),
+            Length(6),
+            Min(20),
+            Length(10),
+            Length(14),
+        ],
+        Tab::Stderr => vec![Min(20)],
+        Tab::Hotspots => vec![Length(8), Length(16), Min(20)],
+    }
+}
+
+pub fn draw(frame: &mut Frame, app: &mut App) {
+    let [header, tabs, body, footer] = Layout::vertical([
+        Constraint::Length(2),
+        Constraint::Length(1),
+        Constraint::Min(3),
+        Constraint::Length(1),
+    ])
+    .areas(frame.area());
+
+    let diagnosis = match &app.diagnosis {
+        Some(d) => Span::styled(d.as_str(), Style::default().fg(Color::Red)),
+        None => Span::raw("no error patterns"),
+    };
+    frame.render_widget(
+        Paragraph::new(vec![
+            Line::from(vec![
+                Span::styled(
+                    app.command.as_str(),
+                    Style::default().add_modifier(Modifier::BOLD),
+                ),
+                Span::raw("  "),
+                Span::raw(app.status.as_str()),
+            ]),
+            Line::from(diagnosis),
+        ]),
+        header,
+    );
+
+    let titles = Tab::ALL
+        .iter()
+        .enumerate()
+        .map(|(i, tab)| format!("{} {}", i + 1, tab.title()));
+    frame.render_widget(
+        Tabs::new(titles).select(app.tab as usize).highlight_style(
+            Style::default()
+                .fg(Color::Yellow)
+                .add_modifier(Modifier::BOLD),
+        ),
+        tabs,
+    );
+
+    let mut title = app.tab.title().to_string();
+    if app.tab == Tab::Timeline {
+        match app.window {
+            (None, None) => {}
+            (start, end) => title.push_str(&format!(
+                " {:.1}..{:.1}ms",
+                start.unwrap_or(0.0),
+                end.unwrap_or(f64::INFINITY)
+            )),
+        }
+        if let Some(pid) = app.pid {
+            title.push_str(&format!(" pid {}", pid));
+        }
+    }
+    let pane = &mut app.panes[app.tab as usize];
+    if !pane.note.is_empty() {
+        title.push_str(&format!(" ({})", pane.note));
+    }
+    if !pane.filter.is_empty() {
+        title.push_str(&format!(" /{}", pane.filter));
+    }
+    title.push_str(&format!(
+        " {}/{}",
+        (pane.selected + 1).min(pane.visible.len()),
+        pane.visible.len()
+    ));
+
+    // Only the rows on screen are built, so large tables scroll cheaply.
+    let height = body.height.saturating_sub(3) as usize;
+    if pane.selected < pane.offset {
+        pane.offset = pane.selected;
+    } else if height > 0 && pane.selected >= pane.offset + height {
+        pane.offset = pane.selected + 1 - height;
+    }
+    let end = (pane.offset + height).min(pane.visible.len());
+    let rows = pane.visible[pane.offset.min(end)..end].iter().map(|&i| {
+        let row = &pane.rows[i];
+        let style = if row.alert {
+            Style::default().fg(Color::Red)
+        } else {
+            Style::default()
+        };
+        Row::new(row.cells.iter().map(|c| Cell::from(c.as_str()))).style(style)
+    });
+    let table = Table::new(rows, widths(app.tab))
+        .header(
+            Row::new(pane.header.iter().copied())
+                .style(Style::default().add_modifier(Modifier::BOLD)),
+        )
+        .block(Block::default().borders(Borders::ALL).title(title))
+        .row_highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+    let mut state = TableState::default()
+        .with_selected((!pane.visible.is_empty()).then(|| pane.selected - pane.offset));
+    frame.render_stateful_widget(table, body, &mut state);
+
+    let help = if app.editing {
+        Line::from(vec![
+            Span::styled("filter: ", Style::default().fg(Color::Yellow)),
+            Span::raw(pane.filter.as_str()),
+            Span::raw("_  (enter to keep, esc to clear)"),
+        ])
+    } else {
+        let mut keys = "q quit  tab/1-6 pane  j/k scroll  / filter".to_string();
+        match app.tab {
+            Tab::Processes => keys.push_str("  enter timeline of process"),
+            Tab::Timeline => keys.push_str("  +/- zoom  p this pid  0 reset"),
+            _ => {}
+        }
+        Line::from(keys)
+    };
+    frame.render_widget(Paragraph::new(help), footer);
+}